@@ -0,0 +1,16 @@
+import json
+import sys
+
+
+def main():
+    """Print a mix of accented characters and emoji, as text and as JSON, to check they round-trip."""
+    sample = "Ding Lirén, Nepomniachtchi, Rapport Richárd, Duda Jan-Krzysztof, Çağrı ♛ ♞ 🏆 ✅"
+    if "--json" in sys.argv[1:]:
+        print(json.dumps({"sample": sample, "length": len(sample)}, ensure_ascii=False))
+    else:
+        print(sample)
+    print(f"encoding: {sys.stdout.encoding}", file=sys.stderr)
+
+
+if __name__ == "__main__":
+    main()
@@ -1,7 +1,7 @@
 import {AbsoluteFill, staticFile, Sequence, Img} from 'remotion';
 import {interpolate, useCurrentFrame} from 'remotion';
 import ChessOverlay from '../src/components/ChessOverlay'
-import data from './export.json'
+import {data} from './data'
 
 type ChessOverlayAnimationProps = {
   currentFen: string;
@@ -2,7 +2,7 @@ import React from 'react';
 import {Composition} from 'remotion';
 import {ChessComponent} from './ChessComponent';
 import './style.css';
-import data from './export.json'
+import {data} from './data'
  
 export const RemotionRoot: React.FC = () => {
   const framePerMove = data.framePerMove;
@@ -0,0 +1,7 @@
+import {getInputProps} from 'remotion';
+import fallback from './export.json';
+
+// Each export renders with its own props file (--props), so exports running side by side
+// don't share export.json; the bundled one is only used by the studio
+const props = getInputProps();
+export const data = (Object.keys(props).length > 0 ? props : fallback) as typeof fallback;
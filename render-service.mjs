@@ -0,0 +1,111 @@
+// The long-lived renderer behind the "service" render backend (src-tauri/src/renderservice.rs).
+// It bundles the project and opens Chromium once, then renders every request sent to it.
+//
+// Each side sends one JSON object per line. From the app on stdin:
+//   {"type":"render","id":1,"composition":"Chess","props":"...","output":"...","frames":[0,59],"concurrency":null,"scale":null}
+//   {"type":"ping","id":2}  {"type":"cancel","id":1}  {"type":"shutdown"}
+// And back on stdout:
+//   {"type":"ready"}  {"type":"pong","id":2}  {"type":"progress","id":1,"rendered":12,"total":60}
+//   {"type":"done","id":1}  {"type":"error","id":1,"message":"..."}
+// An error without an id is about the service itself, e.g. the bundle failing.
+import {bundle} from '@remotion/bundler';
+import {makeCancelSignal, openBrowser, renderMedia, selectComposition} from '@remotion/renderer';
+import {enableTailwind} from '@remotion/tailwind-v4';
+import {readFile} from 'fs/promises';
+import path from 'path';
+import readline from 'readline';
+
+// Anything else on stdout would break the framing, so Remotion's logging goes to stderr
+console.log = console.error;
+console.info = console.error;
+
+const send = (message) => process.stdout.write(JSON.stringify(message) + '\n');
+
+let serveUrl;
+let browser;
+try {
+  // remotion.config.ts only applies to the CLI, so its webpack override is repeated here
+  serveUrl = await bundle({
+    entryPoint: path.resolve('./remotion/index.ts'),
+    webpackOverride: (config) => enableTailwind(config),
+  });
+  browser = await openBrowser('chrome');
+} catch (e) {
+  send({type: 'error', id: null, message: `The render service failed to start: ${e?.message ?? e}`});
+  process.exit(1);
+}
+
+const cancels = new Map();
+
+async function render({id, composition: compositionId, props, output, frames, concurrency, scale}) {
+  const {cancelSignal, cancel} = makeCancelSignal();
+  cancels.set(id, cancel);
+  try {
+    const inputProps = JSON.parse(await readFile(props, 'utf8'));
+    const composition = await selectComposition({serveUrl, id: compositionId, inputProps, puppeteerInstance: browser});
+    const total = frames ? frames[1] - frames[0] + 1 : composition.durationInFrames;
+    let reported = -1;
+    await renderMedia({
+      composition,
+      serveUrl,
+      codec: 'h264',
+      outputLocation: output,
+      inputProps,
+      puppeteerInstance: browser,
+      cancelSignal,
+      frameRange: frames ?? null,
+      concurrency: concurrency ?? null,
+      scale: scale ?? 1,
+      onProgress: ({renderedFrames}) => {
+        if (renderedFrames !== reported) {
+          reported = renderedFrames;
+          send({type: 'progress', id, rendered: renderedFrames, total});
+        }
+      },
+    });
+    send({type: 'done', id});
+  } catch (e) {
+    send({type: 'error', id, message: e?.message ?? String(e)});
+  } finally {
+    cancels.delete(id);
+  }
+}
+
+async function shutdown() {
+  for (const cancel of cancels.values()) {
+    cancel();
+  }
+  await browser.close({silent: false}).catch(() => {});
+  process.exit(0);
+}
+
+const lines = readline.createInterface({input: process.stdin});
+lines.on('line', (line) => {
+  let request;
+  try {
+    request = JSON.parse(line);
+  } catch {
+    send({type: 'error', id: null, message: `Not a JSON request: ${line}`});
+    return;
+  }
+  switch (request.type) {
+    case 'render':
+      render(request);
+      break;
+    case 'ping':
+      send({type: 'pong', id: request.id});
+      break;
+    case 'cancel':
+      cancels.get(request.id)?.();
+      break;
+    case 'shutdown':
+      shutdown();
+      break;
+    default:
+      send({type: 'error', id: request.id ?? null, message: `Unknown request type ${request.type}`});
+  }
+});
+// stdin closes when the app exits without shutting the service down, e.g. when it crashes
+lines.on('close', shutdown);
+
+send({type: 'ready'});
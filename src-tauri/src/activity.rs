@@ -0,0 +1,176 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Emitter, State};
+
+use crate::warnings;
+
+// Events kept for get_recent_logs; older ones are dropped
+const CAPACITY: usize = 2000;
+const DEFAULT_LIMIT: usize = 200;
+// This crate's own log lines start with its name; anything else is a dependency's
+const CRATE_TARGET: &str = env!("CARGO_CRATE_NAME");
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEvent {
+    // Increases by one per event, so the frontend can tell which ones it has already seen
+    pub seq: u64,
+    pub timestamp_ms: u64,
+    pub level: String,
+    #[serde(skip)]
+    severity: Level,
+    pub target: String,
+    pub message: String,
+    // The export the line was logged from, when it ran inside one
+    pub job_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LogFilter {
+    // The least severe level returned: "error", "warn", "info", "debug" or "trace"
+    pub level: Option<String>,
+    // A module path prefix, e.g. "app::python"
+    pub target: Option<String>,
+    pub job_id: Option<String>,
+}
+
+struct Inner {
+    events: Mutex<VecDeque<LogEvent>>,
+    next_seq: AtomicU64,
+    // Level::Warn or Level::Error as usize while subscribe_logs is on, 0 while it's off
+    emit_level: AtomicUsize,
+    app: OnceLock<AppHandle>,
+}
+
+// The most recent log events, filled by the logger installed in main and read by get_recent_logs.
+// Cloned into managed state; every clone shares the buffer.
+#[derive(Clone)]
+pub struct RecentLogs(Arc<Inner>);
+
+thread_local! {
+    // Set while an event is being emitted, so anything the emit itself logs isn't emitted again
+    static EMITTING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn parse_level(level: &str) -> Result<Level, String> {
+    level.parse().map_err(|_| format!("Unknown log level \"{}\", expected error, warn, info, debug or trace", level))
+}
+
+impl RecentLogs {
+    // Makes this the process's logger: this crate's lines are printed as println! printed them
+    // and kept, dependencies only get through from warn up
+    pub fn install() -> Self {
+        let logs = RecentLogs(Arc::new(Inner {
+            events: Mutex::new(VecDeque::with_capacity(CAPACITY)),
+            next_seq: AtomicU64::new(0),
+            emit_level: AtomicUsize::new(0),
+            app: OnceLock::new(),
+        }));
+        if log::set_boxed_logger(Box::new(logs.clone())).is_ok() {
+            log::set_max_level(LevelFilter::Info);
+        }
+        logs
+    }
+
+    // Needed before subscribe_logs can emit anything
+    pub fn attach(&self, app: &AppHandle) {
+        let _ = self.0.app.set(app.clone());
+    }
+
+    fn push(&self, event: LogEvent) {
+        // Everything is formatted before the lock; holding it is a push and at most a pop
+        let mut events = self.0.events.lock().unwrap();
+        if events.len() == CAPACITY {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    fn emit(&self, event: &LogEvent, level: Level) {
+        let threshold = self.0.emit_level.load(Ordering::Relaxed);
+        if threshold == 0 || level as usize > threshold || EMITTING.with(Cell::get) {
+            return;
+        }
+        if let Some(app) = self.0.app.get() {
+            EMITTING.with(|e| e.set(true));
+            let _ = app.emit("log-event", event);
+            EMITTING.with(|e| e.set(false));
+        }
+    }
+
+    pub fn query(&self, filter: &LogFilter, limit: usize, min_level: Option<Level>) -> Vec<LogEvent> {
+        let events = self.0.events.lock().unwrap();
+        events
+            .iter()
+            .rev()
+            .filter(|e| min_level.map_or(true, |min| e.severity <= min))
+            .filter(|e| filter.target.as_deref().map_or(true, |t| e.target.starts_with(t)))
+            .filter(|e| filter.job_id.as_deref().map_or(true, |id| e.job_id.as_deref() == Some(id)))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Log for RecentLogs {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().starts_with(CRATE_TARGET) || metadata.level() <= Level::Warn
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        if record.target().starts_with(CRATE_TARGET) {
+            match record.level() {
+                Level::Warn => println!("Warning: {}", message),
+                Level::Error => println!("Error: {}", message),
+                _ => println!("{}", message),
+            }
+        }
+        let event = LogEvent {
+            seq: self.0.next_seq.fetch_add(1, Ordering::Relaxed),
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+            level: record.level().as_str().to_lowercase(),
+            severity: record.level(),
+            target: record.target().to_string(),
+            message,
+            job_id: warnings::current_export_id(),
+        };
+        self.emit(&event, record.level());
+        self.push(event);
+    }
+
+    fn flush(&self) {}
+}
+
+// Newest first, at most `limit` (default 200) of the last 2000 events
+#[command]
+pub fn get_recent_logs(logs: State<'_, RecentLogs>, filter: Option<LogFilter>, limit: Option<usize>) -> Result<Vec<LogEvent>, String> {
+    let filter = filter.unwrap_or_default();
+    let min_level = filter.level.as_deref().map(parse_level).transpose()?;
+    Ok(logs.query(&filter, limit.unwrap_or(DEFAULT_LIMIT).min(CAPACITY), min_level))
+}
+
+// Emits every event from `level` up ("warn" by default, or "error") as log-event; info is too
+// chatty to push, so the frontend pulls it with get_recent_logs
+#[command]
+pub fn subscribe_logs(logs: State<'_, RecentLogs>, level: Option<String>) -> Result<(), String> {
+    let level = parse_level(level.as_deref().unwrap_or("warn"))?;
+    if level > Level::Warn {
+        return Err(format!("subscribe_logs only streams warn and error, got {}", level.as_str().to_lowercase()));
+    }
+    logs.0.emit_level.store(level as usize, Ordering::Relaxed);
+    Ok(())
+}
+
+#[command]
+pub fn unsubscribe_logs(logs: State<'_, RecentLogs>) {
+    logs.0.emit_level.store(0, Ordering::Relaxed);
+}
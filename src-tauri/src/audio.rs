@@ -0,0 +1,383 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::{command, AppHandle, Emitter};
+
+use crate::ffmpeg::{probe_audio, probe_video};
+use crate::hello::execute_ffmpeg_command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+    Aac,
+}
+
+impl AudioFormat {
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            AudioFormat::Wav => &["wav"],
+            AudioFormat::Mp3 => &["mp3"],
+            AudioFormat::Aac => &["aac", "m4a"],
+        }
+    }
+
+    fn encoder_args(self) -> &'static [&'static str] {
+        match self {
+            AudioFormat::Wav => &["-c:a", "pcm_s16le"],
+            AudioFormat::Mp3 => &["-c:a", "libmp3lame", "-q:a", "2"],
+            AudioFormat::Aac => &["-c:a", "aac", "-b:a", "192k"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct TimeRange {
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedAudio {
+    pub output_path: String,
+    pub duration_secs: Option<f64>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AudioProgressEvent {
+    input: String,
+    output: String,
+    percent: f64,
+}
+
+fn emit_progress(app: &AppHandle, input: &str, output: &str, done: f64, total: f64) {
+    if total > 0.0 {
+        let _ = app.emit("audio-progress", AudioProgressEvent {
+            input: input.to_string(),
+            output: output.to_string(),
+            percent: ((done / total).clamp(0.0, 1.0) * 100.0).round(),
+        });
+    }
+}
+
+fn refuse_overwrite(output: &Path, overwrite: bool) -> Result<(), String> {
+    if output.exists() && !overwrite {
+        return Err(format!("{} already exists", output.display()));
+    }
+    Ok(())
+}
+
+fn validate_request(output: &Path, format: AudioFormat, time_range: Option<TimeRange>, overwrite: bool) -> Result<(), String> {
+    let extension = output
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if !format.extensions().contains(&extension.as_str()) {
+        return Err(format!(
+            "{} does not match the requested format, expected a .{} file",
+            output.display(),
+            format.extensions().join(" or .")
+        ));
+    }
+    refuse_overwrite(output, overwrite)?;
+    if let Some(range) = time_range {
+        if !range.start.is_finite() || range.start < 0.0 {
+            return Err(format!("time_range start must be 0 or later, got {}", range.start));
+        }
+        if let Some(end) = range.end.filter(|&end| !end.is_finite() || end <= range.start) {
+            return Err(format!("time_range end must be after its start, got {}", end));
+        }
+    }
+    Ok(())
+}
+
+fn extract_args(input: &str, output: &str, format: AudioFormat, time_range: Option<TimeRange>) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    if let Some(range) = time_range {
+        // Input-side seeking is fast and audio has no keyframe concerns
+        args.extend(["-ss".to_string(), range.start.to_string()]);
+        if let Some(end) = range.end {
+            args.extend(["-to".to_string(), end.to_string()]);
+        }
+    }
+    args.extend(["-i".to_string(), input.to_string(), "-vn".to_string(), "-map".to_string(), "0:a:0".to_string()]);
+    args.extend(format.encoder_args().iter().map(|a| a.to_string()));
+    args.extend(["-y".to_string(), output.to_string()]);
+    args
+}
+
+// ffmpeg prints the whole input's duration, so a range needs its own total
+fn extract_total(time_range: Option<TimeRange>, input_duration: f64) -> f64 {
+    match time_range {
+        Some(range) => range.end.unwrap_or(input_duration).min(input_duration) - range.start,
+        None => input_duration,
+    }
+}
+
+// Saves the background's audio track on its own, e.g. for editing commentary in another tool
+#[command]
+pub async fn extract_audio(
+    app: AppHandle,
+    input: String,
+    output: String,
+    format: AudioFormat,
+    time_range: Option<TimeRange>,
+    overwrite: Option<bool>,
+) -> Result<ExtractedAudio, String> {
+    validate_request(Path::new(&output), format, time_range, overwrite.unwrap_or(false))?;
+
+    let probe = probe_audio(&app, Path::new(&input)).await?;
+    if probe.stream.is_none() {
+        return Err(format!("{} has no audio stream", input));
+    }
+
+    let args = extract_args(&input, &output, format, time_range);
+    let report = |done: f64, total: f64| emit_progress(&app, &input, &output, done, extract_total(time_range, total));
+    let result = execute_ffmpeg_command(app.clone(), &args, Some(&report), false).await?;
+    if !result.success {
+        return Err(format!(
+            "Failed to extract audio: {}\nReturn code: {:?}",
+            result.error, result.return_code
+        ));
+    }
+
+    let written = probe_audio(&app, Path::new(&output)).await?;
+    let stream = written.stream.or(probe.stream);
+    log::info!("Extracted audio from {} to {}", input, output);
+    Ok(ExtractedAudio {
+        output_path: output,
+        duration_secs: written.duration_secs,
+        sample_rate: stream.as_ref().and_then(|s| s.sample_rate),
+        channels: stream.and_then(|s| s.channels),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplaceMode {
+    // Drop the video's own audio
+    Replace,
+    // Lay the new audio over the existing track
+    Mix,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplacedAudio {
+    pub output_path: String,
+    pub duration_secs: Option<f64>,
+    pub width: u32,
+    pub height: u32,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<String>,
+    pub warnings: Vec<String>,
+}
+
+// Audio filter for the new track: a positive offset delays it, a negative one is trimmed off the input instead
+fn offset_filter(mode: ReplaceMode, offset_secs: f64) -> String {
+    let mut new_audio = String::from("[1:a]");
+    if offset_secs > 0.0 {
+        new_audio.push_str(&format!("adelay=delays={}:all=1,", (offset_secs * 1000.0).round()));
+    }
+    match mode {
+        // Padding keeps the video whole when the new audio runs out early; -shortest stops at the video's end
+        ReplaceMode::Replace => format!("{}apad[aout]", new_audio),
+        ReplaceMode::Mix => format!(
+            "{}anull[new_audio];[0:a][new_audio]amix=inputs=2:duration=first:dropout_transition=0[aout]",
+            new_audio
+        ),
+    }
+}
+
+// What the listener should know about the result: silence where the new audio is short or delayed
+fn replace_warnings(mode: ReplaceMode, offset_secs: f64, video_len: Option<f64>, audio_len: Option<f64>) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let (ReplaceMode::Replace, Some(video_len), Some(audio_len)) = (mode, video_len, audio_len) {
+        let audio_end = audio_len + offset_secs;
+        if audio_end + 0.05 < video_len {
+            warnings.push(format!(
+                "The audio ends {:.2}s before the video; the rest is padded with silence",
+                video_len - audio_end
+            ));
+        }
+    }
+    // Nothing fills the gap before a delayed track in replace mode
+    if offset_secs > 0.0 && mode == ReplaceMode::Replace {
+        warnings.push(format!("The first {:.2}s are silent because of the offset", offset_secs));
+    }
+    warnings
+}
+
+fn replace_args(video: &str, audio: &str, output: &str, mode: ReplaceMode, offset_secs: f64) -> Vec<String> {
+    let mut args: Vec<String> = vec!["-i".to_string(), video.to_string()];
+    if offset_secs < 0.0 {
+        args.extend(["-ss".to_string(), (-offset_secs).to_string()]);
+    }
+    args.extend(["-i".to_string(), audio.to_string()]);
+    args.extend(["-filter_complex".to_string(), offset_filter(mode, offset_secs)]);
+    args.extend(
+        ["-map", "0:v", "-map", "[aout]", "-c:v", "copy", "-c:a", "aac", "-b:a", "192k", "-shortest", "-y"]
+            .iter()
+            .map(|a| a.to_string()),
+    );
+    args.push(output.to_string());
+    args
+}
+
+// Puts re-edited commentary back onto an exported video; the video stream is copied untouched
+#[command]
+pub async fn replace_audio(
+    app: AppHandle,
+    video: String,
+    audio: String,
+    output: String,
+    mode: ReplaceMode,
+    offset_secs: Option<f64>,
+    overwrite: Option<bool>,
+) -> Result<ReplacedAudio, String> {
+    let offset_secs = offset_secs.unwrap_or(0.0);
+    if !offset_secs.is_finite() {
+        return Err(format!("offset_secs must be a finite number, got {}", offset_secs));
+    }
+    if Path::new(&output) == Path::new(&video) {
+        return Err("The output must not overwrite the input video".to_string());
+    }
+    refuse_overwrite(Path::new(&output), overwrite.unwrap_or(false))?;
+
+    let video_probe = probe_video(&app, Path::new(&video)).await?;
+    let audio_probe = probe_audio(&app, Path::new(&audio)).await?;
+    if audio_probe.stream.is_none() {
+        return Err(format!("{} has no audio stream", audio));
+    }
+    if mode == ReplaceMode::Mix && !video_probe.has_audio {
+        return Err(format!("{} has no audio track to mix with", video));
+    }
+
+    let warnings = replace_warnings(mode, offset_secs, video_probe.duration_secs, audio_probe.duration_secs);
+    for warning in &warnings {
+        log::warn!("{}", warning);
+    }
+
+    let args = replace_args(&video, &audio, &output, mode, offset_secs);
+
+    let report = |done: f64, total: f64| emit_progress(&app, &video, &output, done, total);
+    let result = execute_ffmpeg_command(app.clone(), &args, Some(&report), false).await?;
+    if !result.success {
+        return Err(format!(
+            "Failed to replace audio: {}\nReturn code: {:?}",
+            result.error, result.return_code
+        ));
+    }
+
+    let written = probe_video(&app, Path::new(&output)).await?;
+    let written_audio = probe_audio(&app, Path::new(&output)).await?.stream;
+    log::info!("Wrote {} with audio from {}", output, audio);
+    Ok(ReplacedAudio {
+        output_path: output,
+        duration_secs: written.duration_secs,
+        width: written.width,
+        height: written.height,
+        sample_rate: written_audio.as_ref().and_then(|s| s.sample_rate),
+        channels: written_audio.and_then(|s| s.channels),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn range(start: f64, end: Option<f64>) -> Option<TimeRange> {
+        Some(TimeRange { start, end })
+    }
+
+    #[test]
+    fn the_extension_has_to_match_the_format() {
+        let request = |path: &str, format| validate_request(Path::new(path), format, None, false);
+        assert_eq!(request("/audio/commentary.WAV", AudioFormat::Wav), Ok(()));
+        assert_eq!(request("/audio/commentary.m4a", AudioFormat::Aac), Ok(()));
+        assert_eq!(
+            request("/audio/commentary.wav", AudioFormat::Aac).unwrap_err(),
+            "/audio/commentary.wav does not match the requested format, expected a .aac or .m4a file"
+        );
+        assert!(request("/audio/commentary", AudioFormat::Mp3).is_err());
+    }
+
+    #[test]
+    fn a_range_has_to_start_at_0_or_later_and_end_after_it_starts() {
+        let request = |time_range| validate_request(Path::new("out.mp3"), AudioFormat::Mp3, time_range, false);
+        assert_eq!(request(range(0.0, None)), Ok(()));
+        assert_eq!(request(range(2.0, Some(5.0))), Ok(()));
+        assert_eq!(request(range(-1.0, None)).unwrap_err(), "time_range start must be 0 or later, got -1");
+        assert_eq!(request(range(f64::NAN, None)).unwrap_err(), "time_range start must be 0 or later, got NaN");
+        assert_eq!(request(range(5.0, Some(5.0))).unwrap_err(), "time_range end must be after its start, got 5");
+    }
+
+    #[test]
+    fn an_existing_output_is_kept_unless_overwrite_is_set() {
+        let output = std::env::temp_dir().join(format!("boardcast-audio-{}.wav", std::process::id()));
+        fs::write(&output, b"RIFF").unwrap();
+        assert_eq!(
+            validate_request(&output, AudioFormat::Wav, None, false).unwrap_err(),
+            format!("{} already exists", output.display())
+        );
+        assert_eq!(validate_request(&output, AudioFormat::Wav, None, true), Ok(()));
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn only_the_first_audio_track_is_encoded_from_the_range() {
+        assert_eq!(
+            extract_args("game.mp4", "commentary.wav", AudioFormat::Wav, None),
+            ["-i", "game.mp4", "-vn", "-map", "0:a:0", "-c:a", "pcm_s16le", "-y", "commentary.wav"]
+        );
+        assert_eq!(
+            extract_args("game.mp4", "commentary.mp3", AudioFormat::Mp3, range(1.5, Some(20.0))),
+            ["-ss", "1.5", "-to", "20", "-i", "game.mp4", "-vn", "-map", "0:a:0", "-c:a", "libmp3lame", "-q:a", "2", "-y", "commentary.mp3"]
+        );
+        assert_eq!(
+            extract_args("game.mp4", "commentary.m4a", AudioFormat::Aac, range(3.0, None))[..2],
+            ["-ss", "3"]
+        );
+    }
+
+    #[test]
+    fn a_delayed_track_is_padded_and_an_early_one_is_trimmed() {
+        assert_eq!(offset_filter(ReplaceMode::Replace, 0.0), "[1:a]apad[aout]");
+        assert_eq!(offset_filter(ReplaceMode::Replace, 1.25), "[1:a]adelay=delays=1250:all=1,apad[aout]");
+        assert_eq!(
+            offset_filter(ReplaceMode::Mix, 0.5),
+            "[1:a]adelay=delays=500:all=1,anull[new_audio];[0:a][new_audio]amix=inputs=2:duration=first:dropout_transition=0[aout]"
+        );
+        assert_eq!(
+            replace_args("game.mp4", "voice.wav", "out.mp4", ReplaceMode::Replace, -2.5),
+            [
+                "-i", "game.mp4", "-ss", "2.5", "-i", "voice.wav", "-filter_complex", "[1:a]apad[aout]",
+                "-map", "0:v", "-map", "[aout]", "-c:v", "copy", "-c:a", "aac", "-b:a", "192k", "-shortest", "-y", "out.mp4",
+            ]
+        );
+    }
+
+    #[test]
+    fn silence_from_a_short_or_delayed_replacement_is_reported() {
+        assert!(replace_warnings(ReplaceMode::Replace, 0.0, Some(60.0), Some(60.02)).is_empty());
+        assert_eq!(
+            replace_warnings(ReplaceMode::Replace, 2.0, Some(60.0), Some(50.0)),
+            ["The audio ends 8.00s before the video; the rest is padded with silence", "The first 2.00s are silent because of the offset"]
+        );
+        // Mixed in, the video's own audio covers the gaps
+        assert!(replace_warnings(ReplaceMode::Mix, 2.0, Some(60.0), Some(10.0)).is_empty());
+        assert!(replace_warnings(ReplaceMode::Replace, -1.0, None, Some(10.0)).is_empty());
+    }
+
+    #[test]
+    fn progress_is_measured_against_the_range() {
+        assert_eq!(extract_total(None, 60.0), 60.0);
+        assert_eq!(extract_total(range(10.0, Some(25.0)), 60.0), 15.0);
+        assert_eq!(extract_total(range(10.0, None), 60.0), 50.0);
+        // A range past the end stops with the input
+        assert_eq!(extract_total(range(10.0, Some(90.0)), 60.0), 50.0);
+    }
+}
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::cancel::CancelState;
+use crate::encoders;
+use crate::hello::{execute_ffmpeg_command, render_chess_animation, RenderOptions};
+use crate::pause::PauseState;
+use crate::paths::path_arg;
+use crate::progress::ProgressReporter;
+use crate::warnings::{self, WarningCollector};
+use crate::workdir::{WorkDir, WorkDirs};
+
+// 1080p testsrc composited under the animation for this long at 30 fps
+const COMPOSITE_SECS: u64 = 10;
+const COMPOSITE_FPS: u64 = 30;
+// Render concurrency levels compared
+const PARALLEL_LEVELS: &[u32] = &[1, 2];
+// Cancelled, and reported on, like an export with this id
+const BENCHMARK_ID: &str = "benchmark";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    // "render" or "composite"
+    pub stage: String,
+    // The parallel level for renders, the encoder for composites
+    pub variant: String,
+    pub frames: u64,
+    pub wall_secs: f64,
+    pub fps: f64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkReport {
+    pub finished_at: u64,
+    pub results: Vec<BenchmarkResult>,
+    pub recommended_parallel_render: Option<u32>,
+    pub recommended_encoder: Option<String>,
+}
+
+impl BenchmarkReport {
+    fn fastest(&self, stage: &str) -> Option<&BenchmarkResult> {
+        self.results
+            .iter()
+            .filter(|r| r.stage == stage && r.error.is_none())
+            .max_by(|a, b| a.fps.total_cmp(&b.fps))
+    }
+
+    fn fps(&self, stage: &str, variant: &str) -> Option<f64> {
+        self.results.iter().find(|r| r.stage == stage && r.variant == variant && r.error.is_none()).map(|r| r.fps)
+    }
+}
+
+// The latest report, saved in the app data directory
+pub struct BenchmarkStore {
+    path: Option<PathBuf>,
+    latest: Mutex<Option<BenchmarkReport>>,
+}
+
+impl BenchmarkStore {
+    pub fn load(app: &AppHandle) -> Self {
+        let path = app.path().app_data_dir().ok().map(|dir| dir.join("benchmark.json"));
+        let latest = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|content| serde_json::from_str(&content).ok());
+        BenchmarkStore {
+            path,
+            latest: Mutex::new(latest),
+        }
+    }
+
+    fn store(&self, report: BenchmarkReport) {
+        if let Some(path) = &self.path {
+            let saved = path
+                .parent()
+                .map(|parent| fs::create_dir_all(parent).map_err(|e| e.to_string()))
+                .unwrap_or(Ok(()))
+                .and_then(|_| serde_json::to_string_pretty(&report).map_err(|e| e.to_string()))
+                .and_then(|content| fs::write(path, content).map_err(|e| e.to_string()));
+            if let Err(e) = saved {
+                log::warn!("Failed to save the benchmark results: {}", e);
+            }
+        }
+        *self.latest.lock().unwrap() = Some(report);
+    }
+
+    // Used when the parallel_render setting is unset
+    pub fn recommended_parallel_render(&self) -> Option<u32> {
+        self.latest.lock().unwrap().as_ref().and_then(|r| r.recommended_parallel_render)
+    }
+
+    // The slowest render measured, startup included, so a budget built on it errs long
+    pub fn render_frame_secs(&self) -> Option<f64> {
+        let latest = self.latest.lock().unwrap();
+        let renders = latest.as_ref()?.results.iter().filter(|r| r.stage == "render" && r.error.is_none() && r.fps > 0.0);
+        renders.map(|r| 1.0 / r.fps).max_by(f64::total_cmp)
+    }
+
+    // True when the benchmark measured libx264 faster than `encoder` on this machine
+    pub fn software_is_faster(&self, encoder: &str) -> bool {
+        let latest = self.latest.lock().unwrap();
+        let Some(report) = latest.as_ref() else {
+            return false;
+        };
+        match (report.fps("composite", "libx264"), report.fps("composite", encoder)) {
+            (Some(software), Some(hardware)) => software > hardware,
+            _ => false,
+        }
+    }
+}
+
+// Three moves from the starting position, small enough to render in seconds
+fn benchmark_payload() -> serde_json::Value {
+    serde_json::json!({
+        "boardSize": 480,
+        "framePerMove": 15,
+        "timePerMove": 0.5,
+        "positions": [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        ],
+        "moves": [null, "e4", "e5", "Nf3"],
+        "evaluations": [
+            { "bestMove": "e2e4", "evaluation": 0.3 },
+            { "bestMove": "e7e5", "evaluation": 0.3 },
+            { "bestMove": "g1f3", "evaluation": 0.3 },
+            { "bestMove": "b8c6", "evaluation": 0.3 }
+        ],
+        "timestamps": [0.0, 1.0, 2.0, 3.0],
+        "x_offset": 0,
+        "y_offset": 0
+    })
+}
+
+// Removes the benchmark's working directory, also when it is cancelled
+struct Cleanup {
+    workdir: WorkDir,
+}
+
+impl Drop for Cleanup {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(self.workdir.path()) {
+            log::warn!("Failed to remove the benchmark directory {}: {}", self.workdir.path().display(), e);
+        }
+    }
+}
+
+fn result(stage: &str, variant: &str, frames: u64, started: Instant, outcome: Result<(), String>) -> BenchmarkResult {
+    let wall_secs = started.elapsed().as_secs_f64();
+    log::info!("Benchmark {} {}: {:.2}s {:?}", stage, variant, wall_secs, outcome);
+    BenchmarkResult {
+        stage: stage.to_string(),
+        variant: variant.to_string(),
+        frames,
+        wall_secs,
+        fps: if outcome.is_ok() && wall_secs > 0.0 { frames as f64 / wall_secs } else { 0.0 },
+        error: outcome.err(),
+    }
+}
+
+async fn benchmark(app: &AppHandle) -> Result<BenchmarkReport, String> {
+    let payload = benchmark_payload();
+    let content = serde_json::to_string_pretty(&payload).map_err(|e| e.to_string())?;
+    let cleanup = Cleanup { workdir: app.state::<WorkDirs>().allocate(app, BENCHMARK_ID)? };
+    let props_path = cleanup.workdir.file("export.json");
+    fs::write(&props_path, &content).map_err(|e| format!("Failed to write {}: {}", props_path.display(), e))?;
+
+    let total_frames = 4 * 15;
+    let progress = ProgressReporter::new(app, BENCHMARK_ID, 3);
+    let mut results = Vec::new();
+    for &parallel in PARALLEL_LEVELS {
+        let path = cleanup.workdir.file(&format!("animation-{}.mp4", parallel));
+        let options = RenderOptions { total_frames, frame_range: None, parallel, scale: None };
+        let started = Instant::now();
+        let rendered = render_chess_animation(app, &props_path, &path, options, None, &progress).await.map(|_| ());
+        results.push(result("render", &parallel.to_string(), total_frames, started, rendered));
+    }
+    let animation = PARALLEL_LEVELS
+        .iter()
+        .map(|p| cleanup.workdir.file(&format!("animation-{}.mp4", p)))
+        .find(|p| p.is_file())
+        .ok_or("The benchmark animation failed to render")?;
+
+    let mut candidates = vec!["libx264".to_string()];
+    match encoders::hardware_encoders(app, false).await {
+        Ok(found) => candidates.extend(found.into_iter().find(|e| e.verified && e.codec == "h264").map(|e| e.name)),
+        Err(e) => log::warn!("Hardware encoder detection failed: {}", e),
+    }
+    let frames = COMPOSITE_SECS * COMPOSITE_FPS;
+    for encoder in &candidates {
+        let output = cleanup.workdir.file(&format!("composite-{}.mp4", encoder));
+        let args: Vec<String> = vec![
+            "-y".into(), "-f".into(), "lavfi".into(),
+            "-i".into(), format!("testsrc=size=1920x1080:rate={}", COMPOSITE_FPS),
+            "-stream_loop".into(), "-1".into(), "-i".into(), path_arg(&animation)?,
+            "-filter_complex".into(), "[0:v][1:v]overlay=x=0:y=0[v]".into(),
+            "-map".into(), "[v]".into(), "-t".into(), COMPOSITE_SECS.to_string(),
+            "-c:v".into(), encoder.clone(), path_arg(&output)?,
+        ];
+        let started = Instant::now();
+        let outcome = match execute_ffmpeg_command(app.clone(), &args, None, false).await {
+            Ok(r) if r.success => Ok(()),
+            Ok(r) => Err(r.error.lines().last().unwrap_or("encode failed").trim().to_string()),
+            Err(e) => Err(e),
+        };
+        results.push(result("composite", encoder, frames, started, outcome));
+    }
+
+    let mut report = BenchmarkReport {
+        finished_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        results,
+        recommended_parallel_render: None,
+        recommended_encoder: None,
+    };
+    report.recommended_parallel_render = report.fastest("render").and_then(|r| r.variant.parse().ok());
+    report.recommended_encoder = report.fastest("composite").map(|r| r.variant.clone());
+    drop(cleanup);
+    Ok(report)
+}
+
+#[command]
+pub async fn run_benchmark(app: AppHandle) -> Result<BenchmarkReport, String> {
+    let cancels = app.state::<CancelState>();
+    let job = cancels.start(BENCHMARK_ID).ok_or("A benchmark is already running")?;
+    log::info!("Starting the pipeline benchmark...");
+    // Its processes are tracked like an export's, so a cancel kills them with everything they
+    // started; dropping the benchmark runs the cleanup
+    let benchmark = warnings::scope(WarningCollector::new(&app, BENCHMARK_ID), benchmark(&app));
+    let report = job.run(benchmark).await.unwrap_or_else(|| Err("The benchmark was cancelled".to_string()))?;
+    app.state::<BenchmarkStore>().store(report.clone());
+    Ok(report)
+}
+
+#[command]
+pub fn cancel_benchmark(cancels: State<'_, CancelState>, pauses: State<'_, PauseState>) -> Result<(), String> {
+    if !cancels.cancel(BENCHMARK_ID, &pauses) {
+        return Err("No benchmark is running".to_string());
+    }
+    Ok(())
+}
+
+#[command]
+pub fn get_benchmark(store: State<'_, BenchmarkStore>) -> Option<BenchmarkReport> {
+    store.latest.lock().unwrap().clone()
+}
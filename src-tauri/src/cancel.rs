@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tauri::{command, AppHandle, Manager, State};
+use tokio::sync::Notify;
+
+use crate::exports::ExportRegistry;
+use crate::failure::{ExportFailure, FailureStage};
+use crate::pause::PauseState;
+use crate::process;
+use crate::workdir;
+
+// The cancel signal of every running export, benchmark and self-test, by id
+#[derive(Default)]
+pub struct CancelState {
+    jobs: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+// A running job that cancel can stop; it can't be cancelled any more once this is dropped
+pub struct Cancellable<'a> {
+    state: &'a CancelState,
+    job_id: String,
+    signal: Arc<Notify>,
+}
+
+impl Drop for Cancellable<'_> {
+    fn drop(&mut self) {
+        self.state.jobs.lock().unwrap().remove(&self.job_id);
+    }
+}
+
+impl Cancellable<'_> {
+    // Runs `job` to the end, or returns None once it was cancelled. A cancelled job is dropped, which
+    // runs its cleanup guards.
+    pub async fn run<F: Future>(&self, job: F) -> Option<F::Output> {
+        tokio::select! {
+            // A job whose processes were just killed would otherwise end as an ordinary failure
+            biased;
+            _ = self.signal.notified() => None,
+            output = job => Some(output),
+        }
+    }
+}
+
+impl CancelState {
+    // None while another job runs under the same id
+    pub fn start(&self, job_id: &str) -> Option<Cancellable<'_>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.contains_key(job_id) {
+            return None;
+        }
+        let signal = Arc::new(Notify::new());
+        jobs.insert(job_id.to_string(), signal.clone());
+        Some(Cancellable { state: self, job_id: job_id.to_string(), signal })
+    }
+
+    // Stops the job and kills the processes it has running, along with everything they started.
+    // False when nothing runs under `job_id`.
+    pub fn cancel(&self, job_id: &str, pauses: &PauseState) -> bool {
+        let Some(signal) = self.jobs.lock().unwrap().get(job_id).cloned() else {
+            return false;
+        };
+        // Listed first: the dropped job only kills the processes it started itself, and their
+        // children are orphaned once those are gone
+        let trees: Vec<u32> = pauses
+            .processes(job_id)
+            .into_iter()
+            .flat_map(|pid| process::process_tree(pid).unwrap_or_else(|_| vec![pid]))
+            .collect();
+        signal.notify_one();
+        if let Err(e) = process::kill_all(&trees) {
+            log::warn!("Failed to kill the processes of {}: {}", job_id, e);
+        }
+        true
+    }
+}
+
+// Runs an export, or a crashed one resumed, until it ends or cancel_export stops it. A cancelled
+// export leaves nothing to resume: its working directory is removed.
+pub async fn run_export<F>(app: &AppHandle, export_id: &str, export: F) -> Result<String, ExportFailure>
+where
+    F: Future<Output = Result<String, ExportFailure>>,
+{
+    let workdir = workdir::jobs_dir(app).map(|dir| dir.join(export_id)).ok();
+    let cancels = app.state::<CancelState>();
+    cancellable_export(&cancels, &app.state::<ExportRegistry>(), export_id, workdir.as_deref(), export).await
+}
+
+async fn cancellable_export<F>(
+    cancels: &CancelState,
+    registry: &ExportRegistry,
+    export_id: &str,
+    workdir: Option<&Path>,
+    export: F,
+) -> Result<String, ExportFailure>
+where
+    F: Future<Output = Result<String, ExportFailure>>,
+{
+    let Some(job) = cancels.start(export_id) else {
+        let message = format!("Export {} is already running", export_id);
+        return Err(ExportFailure::new(FailureStage::Validation, "export_running", message));
+    };
+    if let Some(result) = job.run(export).await {
+        return result;
+    }
+    log::info!("Cancelled export {}", export_id);
+    if let Some(dir) = workdir.filter(|dir| dir.exists()) {
+        if let Err(e) = fs::remove_dir_all(dir) {
+            log::warn!("Failed to remove the working directory {} of the cancelled export: {}", dir.display(), e);
+        }
+    }
+    Err(registry.cancel(export_id).unwrap_or_else(|| {
+        ExportFailure::new(FailureStage::WriteProps, "export_cancelled", "The export was cancelled".to_string()).for_export(export_id)
+    }))
+}
+
+// Stops a queued or running export, one resumed with resume_crashed_export included. Its processes
+// are killed along with everything they started, and its working directory is removed.
+#[command]
+pub fn cancel_export(cancels: State<'_, CancelState>, pauses: State<'_, PauseState>, export_id: String) -> Result<(), String> {
+    if !cancels.cancel(&export_id, &pauses) {
+        return Err(format!("Export {} isn't running", export_id));
+    }
+    log::info!("Cancelling export {}", export_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::time::{Duration, Instant};
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("boardcast-cancel-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Gone, or a zombie nobody has reaped yet
+    #[cfg(unix)]
+    fn exited(pid: u32) -> bool {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+        !process::is_process_alive(pid) || stat.rsplit(')').next().is_some_and(|rest| rest.trim_start().starts_with('Z'))
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cancelling_a_resumed_export_kills_its_processes_and_removes_its_workdir() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let (cancels, pauses, registry) = (CancelState::default(), PauseState::default(), ExportRegistry::default());
+        let id = "1700000000000-0";
+        // As resume_crashed_export finds it: rendered, and composited again under its old id
+        registry.resume_export(id);
+        registry.record_progress(id, "composite", 40.0, None);
+        let workdir = scratch_dir("resumed");
+        fs::write(workdir.join("chess-animation.mp4"), b"rendered").unwrap();
+
+        // A composite that started a child of its own, like npx starting node
+        let mut shell = std::process::Command::new("sh")
+            .args(["-c", "sleep 30 & echo $!; wait"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(shell.stdout.take().unwrap()).read_line(&mut line).unwrap();
+        let grandchild: u32 = line.trim().parse().unwrap();
+
+        let export = async {
+            let _tracked = pauses.track(id, shell.id());
+            std::future::pending::<Result<String, ExportFailure>>().await
+        };
+        let cancel = async {
+            assert!(cancels.cancel(id, &pauses));
+        };
+        let (result, ()) = tokio::join!(cancellable_export(&cancels, &registry, id, Some(&workdir), export), cancel);
+
+        let failure = result.unwrap_err();
+        assert_eq!((failure.stage, failure.code), (FailureStage::Composite, "export_cancelled"));
+        assert_eq!(registry.status(Some(id))[0].state, "cancelled");
+        assert!(!workdir.exists());
+        assert_eq!(shell.wait().unwrap().signal(), Some(libc::SIGKILL));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !exited(grandchild) {
+            assert!(Instant::now() < deadline, "process {} outlived the cancel", grandchild);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        // Its processes are no longer the export's, and it can't be cancelled twice
+        assert!(pauses.processes(id).is_empty());
+        assert!(!cancels.cancel(id, &pauses));
+    }
+
+    #[tokio::test]
+    async fn a_finished_export_keeps_its_workdir_and_frees_its_id() {
+        let (cancels, registry) = (CancelState::default(), ExportRegistry::default());
+        let workdir = scratch_dir("finished");
+        let export = async { Ok("{}".to_string()) };
+        assert_eq!(cancellable_export(&cancels, &registry, "e1", Some(&workdir), export).await.unwrap(), "{}");
+        assert!(workdir.exists());
+        assert!(!cancels.cancel("e1", &PauseState::default()));
+        assert!(cancels.start("e1").is_some());
+        fs::remove_dir_all(&workdir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn one_job_runs_under_an_id_at_a_time() {
+        let cancels = CancelState::default();
+        let running = cancels.start("self-test").unwrap();
+        assert!(cancels.start("self-test").is_none());
+        let export = async { Ok(String::new()) };
+        let refused = cancellable_export(&cancels, &ExportRegistry::default(), "self-test", None, export).await.unwrap_err();
+        assert_eq!(refused.code, "export_running");
+        drop(running);
+        assert!(cancels.start("self-test").is_some());
+    }
+}
@@ -0,0 +1,130 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+
+// Containers whose muxers drop or reject ffmetadata chapters
+const UNSUPPORTED_EXTENSIONS: &[&str] = &["webm"];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Chapter {
+    pub title: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+pub fn enabled(data: &Value) -> bool {
+    data.get("chapters").and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+pub fn supports_chapters(output_path: &str) -> bool {
+    let extension = Path::new(output_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    !UNSUPPORTED_EXTENSIONS.contains(&extension.as_str())
+}
+
+// One chapter per move, running until the next move's overlay appears; numbering starts at `first_move`
+pub fn build_chapters(windows: &[[f64; 2]], labels: &[Option<String>], first_move: usize) -> Vec<Chapter> {
+    windows
+        .iter()
+        .enumerate()
+        .map(|(i, window)| {
+            let end = windows.get(i + 1).map(|next| next[0]).unwrap_or(window[1]);
+            let title = match labels.get(i).and_then(|l| l.as_deref()).map(str::trim) {
+                Some(label) if !label.is_empty() => format!("Move {}: {}", i + first_move, label),
+                _ => format!("Move {}", i + first_move),
+            };
+            Chapter { title, start: window[0], end }
+        })
+        .filter(|chapter| chapter.end - chapter.start >= 0.001)
+        .collect()
+}
+
+// ffmetadata treats these as syntax, so they are backslash-escaped; line breaks would end the value
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '=' | ';' | '#' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\r' | '\n' => escaped.push(' '),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn ffmetadata(chapters: &[Chapter]) -> String {
+    let mut content = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        content.push_str(&format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (chapter.start * 1000.0).round() as i64,
+            (chapter.end * 1000.0).round() as i64,
+            escape_ffmetadata(&chapter.title)
+        ));
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(moves: &[&str]) -> Vec<Option<String>> {
+        moves.iter().map(|m| Some(m.to_string())).collect()
+    }
+
+    #[test]
+    fn each_chapter_runs_until_the_next_move_appears() {
+        let chapters = build_chapters(&[[1.0, 2.5], [2.0, 4.0], [3.5, 4.5]], &labels(&["e4", "e5", "Nf3"]), 1);
+        assert_eq!(
+            chapters,
+            [
+                Chapter { title: "Move 1: e4".to_string(), start: 1.0, end: 2.0 },
+                Chapter { title: "Move 2: e5".to_string(), start: 2.0, end: 3.5 },
+                Chapter { title: "Move 3: Nf3".to_string(), start: 3.5, end: 4.5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_move_range_keeps_its_move_numbers_and_blank_labels_are_left_off() {
+        let moves = vec![Some("  ".to_string()), None];
+        let titles: Vec<String> = build_chapters(&[[0.0, 1.0], [1.0, 2.0]], &moves, 12).into_iter().map(|c| c.title).collect();
+        assert_eq!(titles, ["Move 12", "Move 13"]);
+    }
+
+    #[test]
+    fn a_move_sharing_the_next_ones_start_gets_no_chapter() {
+        let chapters = build_chapters(&[[1.0, 2.0], [1.0, 2.0], [3.0, 4.0]], &[], 1);
+        assert_eq!(chapters.iter().map(|c| c.title.as_str()).collect::<Vec<_>>(), ["Move 2", "Move 3"]);
+    }
+
+    #[test]
+    fn ffmetadata_is_in_milliseconds_with_its_syntax_escaped() {
+        let chapters = [
+            Chapter { title: "Move 1: Qxf7#".to_string(), start: 1.0005, end: 2.25 },
+            Chapter { title: "Move 2: a=b; c\\d\nnext".to_string(), start: 2.25, end: 3.0 },
+        ];
+        assert_eq!(
+            ffmetadata(&chapters),
+            ";FFMETADATA1\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=1001\nEND=2250\ntitle=Move 1: Qxf7\\#\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=2250\nEND=3000\ntitle=Move 2: a\\=b\\; c\\\\d next\n"
+        );
+        assert_eq!(ffmetadata(&[]), ";FFMETADATA1\n");
+    }
+
+    #[test]
+    fn chapters_are_off_by_default_and_left_out_of_webm() {
+        assert!(!enabled(&serde_json::json!({})));
+        assert!(enabled(&serde_json::json!({"chapters": true})));
+        assert!(supports_chapters("/exports/game.mp4"));
+        assert!(supports_chapters("/exports/game.MKV"));
+        assert!(!supports_chapters("/exports/game.WebM"));
+    }
+}
@@ -0,0 +1,451 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::jobstate::{fnv1a, FNV_OFFSET};
+use crate::outputname::output_paths_mut;
+use crate::progress::ProgressReporter;
+use crate::timings;
+
+const COPY_CHUNK_BYTES: usize = 1024 * 1024;
+// A dropped share usually comes back within seconds; each attempt restarts the copy
+const COPY_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+// Linux filesystem types that are reached over the network, or over 9P for WSL's Windows drives
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const NETWORK_FS: &[&str] = &["cifs", "smb3", "smbfs", "nfs", "nfs4", "fuse.sshfs", "9p", "drvfs", "afpfs", "webdav"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DestinationKind {
+    Local,
+    // An SMB or NFS share, by UNC path, mapped drive or mount
+    Network,
+    // The other side of the WSL boundary: \\wsl$ from Windows, /mnt/c from inside WSL
+    Wsl,
+}
+
+fn lowercase_prefix(path: &str, prefixes: &[&str]) -> bool {
+    let path = path.replace('/', "\\").to_lowercase();
+    prefixes.iter().any(|prefix| path.starts_with(prefix))
+}
+
+fn is_wsl_share(path: &str) -> bool {
+    lowercase_prefix(path, &[r"\\wsl$\", r"\\wsl.localhost\", r"\\?\unc\wsl$\", r"\\?\unc\wsl.localhost\"])
+}
+
+fn is_unc(path: &str) -> bool {
+    let normalized = path.replace('/', "\\");
+    (normalized.starts_with(r"\\") && !normalized.starts_with(r"\\?\") && !normalized.starts_with(r"\\.\"))
+        || lowercase_prefix(path, &[r"\\?\unc\"])
+}
+
+// C:\... or C:/...
+fn drive_letter(path: &str) -> Option<char> {
+    let bytes = path.as_bytes();
+    (bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && matches!(bytes[2], b'\\' | b'/'))
+        .then(|| bytes[0].to_ascii_lowercase() as char)
+}
+
+#[cfg(windows)]
+fn mounted_kind(path: &str) -> DestinationKind {
+    use windows_sys::Win32::Storage::FileSystem::GetDriveTypeW;
+    use windows_sys::Win32::System::WindowsProgramming::DRIVE_REMOTE;
+
+    let Some(letter) = drive_letter(path) else {
+        return DestinationKind::Local;
+    };
+    let root: Vec<u16> = format!("{}:\\", letter).encode_utf16().chain(std::iter::once(0)).collect();
+    match unsafe { GetDriveTypeW(root.as_ptr()) } {
+        DRIVE_REMOTE => DestinationKind::Network,
+        _ => DestinationKind::Local,
+    }
+}
+
+// The filesystem type of the longest /proc/mounts entry `path` is under
+#[cfg(target_os = "linux")]
+fn mounted_kind(path: &str) -> DestinationKind {
+    let Ok(mounts) = std::fs::read_to_string("/proc/mounts") else {
+        return DestinationKind::Local;
+    };
+    let path = Path::new(path);
+    let fs_type = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            Some((mount_point, fields.next()?.to_string()))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .map(|(_, fs_type)| fs_type);
+    match fs_type.as_deref() {
+        Some("drvfs" | "9p") if path.starts_with("/mnt") => DestinationKind::Wsl,
+        Some(fs_type) if NETWORK_FS.contains(&fs_type) => DestinationKind::Network,
+        _ => DestinationKind::Local,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn mounted_kind(path: &str) -> DestinationKind {
+    use std::ffi::{CStr, CString};
+
+    // statfs needs something that exists; the output itself usually doesn't yet
+    let Some(existing) = Path::new(path).ancestors().find(|p| p.exists()) else {
+        return DestinationKind::Local;
+    };
+    let Ok(c_path) = CString::new(existing.to_string_lossy().as_bytes()) else {
+        return DestinationKind::Local;
+    };
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return DestinationKind::Local;
+    }
+    let fs_type = unsafe { CStr::from_ptr(stats.f_fstypename.as_ptr()) }.to_string_lossy().to_string();
+    match NETWORK_FS.contains(&fs_type.as_str()) {
+        true => DestinationKind::Network,
+        false => DestinationKind::Local,
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+fn mounted_kind(_path: &str) -> DestinationKind {
+    DestinationKind::Local
+}
+
+pub fn classify(path: &str) -> DestinationKind {
+    if is_wsl_share(path) {
+        DestinationKind::Wsl
+    } else if is_unc(path) {
+        DestinationKind::Network
+    } else {
+        mounted_kind(path)
+    }
+}
+
+fn in_wsl() -> bool {
+    std::env::var_os("WSL_DISTRO_NAME").is_some() || Path::new("/proc/sys/fs/binfmt_misc/WSLInterop").exists()
+}
+
+// The host, distribution and Linux path of a \\wsl$ path
+fn share_parts(path: &str) -> (String, String, String) {
+    let normalized = path.replace('\\', "/");
+    let mut parts = normalized.trim_start_matches('/').split('/').filter(|p| !p.is_empty());
+    let host = parts.next().unwrap_or_default();
+    let host = if host == "?" { parts.nth(1).unwrap_or_default() } else { host };
+    let distro = parts.next().unwrap_or_default();
+    (host.to_string(), distro.to_string(), format!("/{}", parts.collect::<Vec<_>>().join("/")))
+}
+
+// `path` as a process inside WSL opens it, for arguments handed to scripts run there. Paths that
+// are already Linux paths, and network shares WSL only sees through a mount, are left alone.
+pub fn wsl_path(path: &str) -> String {
+    if is_wsl_share(path) {
+        return share_parts(path).2;
+    }
+    match drive_letter(path) {
+        Some(letter) => format!("/mnt/{}/{}", letter, path[3..].replace('\\', "/")),
+        None => path.to_string(),
+    }
+}
+
+// An output path in the form this side of the WSL boundary opens: \\wsl$ paths and drive letters
+// become Linux paths inside WSL, /mnt/c paths become drive letters on Windows. Windows paths
+// that can't mean anything here are refused instead of being taken as odd relative names.
+pub fn native_path(path: &str) -> Result<String, String> {
+    if cfg!(windows) {
+        let mut parts = path.split('/').skip(1);
+        if path.starts_with("/mnt/") {
+            if let Some(drive) = parts.nth(1).filter(|d| d.len() == 1 && d.chars().all(|c| c.is_ascii_alphabetic())) {
+                let rest: Vec<&str> = parts.collect();
+                return Ok(format!("{}:\\{}", drive.to_uppercase(), rest.join("\\")));
+            }
+        }
+        return Ok(path.to_string());
+    }
+    if is_wsl_share(path) {
+        let (host, distro, rest) = share_parts(path);
+        let current = std::env::var("WSL_DISTRO_NAME").unwrap_or_default();
+        if !in_wsl() || !current.eq_ignore_ascii_case(&distro) {
+            return Err(format!("{} is inside the WSL distribution {} ({}), which this process can't reach", path, distro, host));
+        }
+        return Ok(rest);
+    }
+    if is_unc(path) {
+        return Err(format!("{} is a Windows network path; mount the share and use its mount point instead", path));
+    }
+    if drive_letter(path).is_some() {
+        if !in_wsl() {
+            return Err(format!("{} is a Windows path, which can't be written from this system", path));
+        }
+        return Ok(wsl_path(path));
+    }
+    Ok(path.to_string())
+}
+
+// Rewrites the output paths to native_path's form
+pub fn normalize_output_paths(mut data: Value) -> Result<Value, String> {
+    for (field, value) in output_paths_mut(&mut data) {
+        let path = value.as_str().unwrap_or_default().to_string();
+        let native = native_path(&path).map_err(|e| format!("{}: {}", field, e))?;
+        if native != path {
+            log::info!("Using {} for {} {}", native, field, path);
+            *value = Value::String(native);
+        }
+    }
+    Ok(data)
+}
+
+// An output encoded into the working directory and copied to its real destination afterwards
+#[derive(Debug, Clone, Serialize)]
+pub struct Staged {
+    pub local: String,
+    pub destination: String,
+    pub kind: DestinationKind,
+}
+
+// Points every output on a network share or across the WSL boundary at a local file in `workdir`,
+// so the encode never waits on the network
+pub fn stage_outputs(data: &Value, workdir: &Path) -> (Value, Vec<Staged>) {
+    let mut data = data.clone();
+    let mut staged = Vec::new();
+    for (index, (_, value)) in output_paths_mut(&mut data).into_iter().enumerate() {
+        let destination = value.as_str().unwrap_or_default().to_string();
+        let kind = classify(&destination);
+        if kind == DestinationKind::Local {
+            continue;
+        }
+        // Split on either separator: Path only knows about backslashes on Windows
+        let name = destination.rsplit(['/', '\\']).next().unwrap_or_default();
+        let local = workdir.join(format!("staged-{}-{}", index, name)).to_string_lossy().to_string();
+        log::info!("{} is on a {:?} destination, encoding into {} first", destination, kind, local);
+        *value = Value::String(local.clone());
+        staged.push(Staged { local, destination, kind });
+    }
+    (data, staged)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivered {
+    pub path: String,
+    pub size_bytes: u64,
+    pub hash: String,
+    pub attempts: u32,
+}
+
+async fn copy_once(staged: &Staged, partial: &Path, progress: &ProgressReporter) -> Result<(u64, u64), String> {
+    let mut source = tokio::fs::File::open(&staged.local)
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", staged.local, e))?;
+    let total = source.metadata().await.map(|m| m.len()).unwrap_or(0);
+    let mut target = tokio::fs::File::create(partial)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", partial.display(), e))?;
+    let mut buffer = vec![0; COPY_CHUNK_BYTES];
+    let (mut copied, mut hash) = (0u64, FNV_OFFSET);
+    loop {
+        let read = source.read(&mut buffer).await.map_err(|e| format!("Failed to read {}: {}", staged.local, e))?;
+        if read == 0 {
+            break;
+        }
+        target
+            .write_all(&buffer[..read])
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        hash = fnv1a(hash, &buffer[..read]);
+        copied += read as u64;
+        progress.report_copy(&staged.destination, copied as f64, total as f64);
+    }
+    target.sync_all().await.map_err(|e| format!("Failed to flush {}: {}", partial.display(), e))?;
+    Ok((copied, hash))
+}
+
+// Reads the copy back, since a share can acknowledge writes it later loses
+async fn verify(partial: &Path, size: u64, hash: u64) -> Result<(), String> {
+    let mut file = tokio::fs::File::open(partial)
+        .await
+        .map_err(|e| format!("Failed to reopen {}: {}", partial.display(), e))?;
+    let mut buffer = vec![0; COPY_CHUNK_BYTES];
+    let (mut read_size, mut read_hash) = (0u64, FNV_OFFSET);
+    loop {
+        let read = file.read(&mut buffer).await.map_err(|e| format!("Failed to read back {}: {}", partial.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        read_hash = fnv1a(read_hash, &buffer[..read]);
+        read_size += read as u64;
+    }
+    if read_size != size {
+        return Err(format!("the copy has {} bytes, the encoded file {}", read_size, size));
+    }
+    if read_hash != hash {
+        return Err("the copy's contents differ from the encoded file".to_string());
+    }
+    Ok(())
+}
+
+pub async fn deliver_one(staged: &Staged, progress: &ProgressReporter) -> Result<Delivered, String> {
+    let destination = PathBuf::from(&staged.destination);
+    let name = destination.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    // Copied under another name and renamed once verified, so a broken copy never looks finished
+    let partial = destination.with_file_name(format!("{}.boardcast-partial", name));
+    let mut last_error = String::new();
+    for attempt in 1..=COPY_ATTEMPTS {
+        let copied = match copy_once(staged, &partial, progress).await {
+            Ok((size, hash)) => verify(&partial, size, hash).await.map(|_| (size, hash)),
+            Err(e) => Err(e),
+        };
+        match copied {
+            Ok((size_bytes, hash)) => {
+                tokio::fs::rename(&partial, &destination)
+                    .await
+                    .map_err(|e| format!("Failed to move {} into place: {}", partial.display(), e))?;
+                return Ok(Delivered { path: staged.destination.clone(), size_bytes, hash: format!("{:016x}", hash), attempts: attempt });
+            }
+            Err(e) => {
+                log::warn!("Copy {} of {} to {} failed: {}", attempt, staged.local, staged.destination, e);
+                let _ = tokio::fs::remove_file(&partial).await;
+                last_error = e;
+                if attempt < COPY_ATTEMPTS {
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+    Err(last_error)
+}
+
+// Copies every staged output that was encoded to its destination and removes the local file. A
+// failed copy keeps the local file and the error says where it is.
+pub async fn deliver(staged: &[Staged], progress: &ProgressReporter) -> Result<Vec<Delivered>, String> {
+    let _copying = timings::span("copy");
+    let mut delivered = Vec::new();
+    for staged in staged.iter().filter(|s| Path::new(&s.local).is_file()) {
+        log::info!("Copying {} to {}", staged.local, staged.destination);
+        match deliver_one(staged, progress).await {
+            Ok(copy) => {
+                if let Err(e) = tokio::fs::remove_file(&staged.local).await {
+                    log::warn!("Failed to remove {}: {}", staged.local, e);
+                }
+                delivered.push(copy);
+            }
+            Err(e) => {
+                return Err(format!(
+                    "The video was encoded but copying it to {} failed: {}. The finished file is kept at {}",
+                    staged.destination, e, staged.local
+                ));
+            }
+        }
+    }
+    Ok(delivered)
+}
+
+// The composite's result with the destinations in place of the local files, plus the copies made
+pub fn with_destinations(result: &str, staged: &[Staged], delivered: &[Delivered]) -> String {
+    let Ok(mut value) = serde_json::from_str::<Value>(result) else {
+        return result.to_string();
+    };
+    for staged in staged {
+        let local = Value::String(staged.local.clone());
+        if value.get("output_path") == Some(&local) {
+            value["output_path"] = Value::String(staged.destination.clone());
+        }
+        for output in value.get_mut("outputs").and_then(|v| v.as_array_mut()).into_iter().flatten() {
+            if output.get("path") == Some(&local) {
+                output["path"] = Value::String(staged.destination.clone());
+            }
+        }
+    }
+    value["delivered"] = serde_json::json!(delivered);
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn shares_are_network_destinations_and_wsl_shares_their_own() {
+        for path in [r"\\NAS\videos\game.mp4", "//nas/videos/game.mp4", r"\\?\UNC\nas\videos\game.mp4"] {
+            assert_eq!(classify(path), DestinationKind::Network, "{}", path);
+        }
+        for path in [r"\\wsl$\Ubuntu\home\me\game.mp4", r"\\wsl.localhost\Ubuntu\home\me\game.mp4", r"\\?\UNC\wsl$\Ubuntu\tmp\a.mp4"] {
+            assert_eq!(classify(path), DestinationKind::Wsl, "{}", path);
+        }
+        // Device paths aren't shares
+        assert!(!is_unc(r"\\?\C:\videos\game.mp4"));
+        assert!(!is_unc(r"\\.\pipe\boardcast"));
+    }
+
+    #[test]
+    fn paths_are_translated_for_scripts_run_inside_wsl() {
+        assert_eq!(wsl_path(r"C:\Users\me\Videos\game.mp4"), "/mnt/c/Users/me/Videos/game.mp4");
+        assert_eq!(wsl_path(r"\\wsl$\Ubuntu\home\me\game.mp4"), "/home/me/game.mp4");
+        assert_eq!(wsl_path(r"\\?\UNC\wsl.localhost\Ubuntu\tmp\a.mp4"), "/tmp/a.mp4");
+        // Linux paths and shares WSL only reaches through a mount are left alone
+        assert_eq!(wsl_path("/home/me/game.mp4"), "/home/me/game.mp4");
+        assert_eq!(wsl_path(r"\\NAS\videos\game.mp4"), r"\\NAS\videos\game.mp4");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn windows_only_paths_are_refused_here() {
+        assert_eq!(native_path("/home/me/game.mp4"), Ok("/home/me/game.mp4".to_string()));
+        assert_eq!(
+            native_path(r"\\NAS\videos\game.mp4"),
+            Err(r"\\NAS\videos\game.mp4 is a Windows network path; mount the share and use its mount point instead".to_string())
+        );
+        assert_eq!(
+            native_path(r"\\wsl$\NoSuchDistro\home\me\game.mp4"),
+            Err(r"\\wsl$\NoSuchDistro\home\me\game.mp4 is inside the WSL distribution NoSuchDistro (wsl$), which this process can't reach".to_string())
+        );
+        let data = normalize_output_paths(json!({"outputPath": "/exports/game.mp4", "outputs": [{"path": r"\\NAS\v.mp4"}]}));
+        assert!(data.unwrap_err().starts_with(r"outputs[0].path: \\NAS\v.mp4 is a Windows network path"));
+    }
+
+    #[test]
+    fn network_outputs_are_encoded_locally_and_reported_at_their_destination() {
+        let workdir = Path::new("/work/1700000000000-0");
+        let local = |name: &str| workdir.join(name).to_string_lossy().to_string();
+        let data = json!({"outputPath": r"\\NAS\videos\game.mp4", "outputs": [{"path": "game-720p.mp4"}, {"path": r"\\wsl$\Ubuntu\tmp\game.webm"}]});
+        let (staged_data, staged) = stage_outputs(&data, workdir);
+        let destinations: Vec<(String, &str, DestinationKind)> =
+            staged.iter().map(|s| (s.local.clone(), s.destination.as_str(), s.kind)).collect();
+        assert_eq!(
+            destinations,
+            [
+                (local("staged-0-game.mp4"), r"\\NAS\videos\game.mp4", DestinationKind::Network),
+                (local("staged-2-game.webm"), r"\\wsl$\Ubuntu\tmp\game.webm", DestinationKind::Wsl),
+            ]
+        );
+        assert_eq!(staged_data["outputPath"], local("staged-0-game.mp4"));
+        assert_eq!(staged_data["outputs"][0]["path"], "game-720p.mp4");
+
+        let delivered = [Delivered { path: r"\\NAS\videos\game.mp4".to_string(), size_bytes: 5, hash: "00".to_string(), attempts: 2 }];
+        let result = json!({
+            "output_path": local("staged-0-game.mp4"),
+            "outputs": [{"path": "game-720p.mp4"}, {"path": local("staged-2-game.webm")}],
+        });
+        let result: Value = serde_json::from_str(&with_destinations(&result.to_string(), &staged, &delivered)).unwrap();
+        assert_eq!(result["output_path"], r"\\NAS\videos\game.mp4");
+        assert_eq!(result["outputs"][1]["path"], r"\\wsl$\Ubuntu\tmp\game.webm");
+        assert_eq!(result["delivered"][0]["attempts"], 2);
+    }
+
+    #[tokio::test]
+    async fn a_copy_is_read_back_before_it_counts() {
+        let partial = std::env::temp_dir().join(format!("boardcast-verify-{}.partial", std::process::id()));
+        std::fs::write(&partial, b"encoded video").unwrap();
+        let hash = fnv1a(FNV_OFFSET, b"encoded video");
+        assert_eq!(verify(&partial, 13, hash).await, Ok(()));
+        assert_eq!(verify(&partial, 20, hash).await, Err("the copy has 13 bytes, the encoded file 20".to_string()));
+        assert_eq!(
+            verify(&partial, 13, fnv1a(FNV_OFFSET, b"encoded vidEo")).await,
+            Err("the copy's contents differ from the encoded file".to_string())
+        );
+        std::fs::remove_file(&partial).unwrap();
+    }
+}
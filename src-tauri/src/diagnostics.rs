@@ -0,0 +1,298 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tauri::{command, AppHandle, Manager};
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::escape::{cmd_script, quote_sh};
+use crate::ffmpeg::{ffmpeg_command, resolve_ffmpeg};
+use crate::paths::ProjectPaths;
+use crate::process;
+use crate::wsl::{detect_wsl, WslStatus};
+use crate::settings::SettingsState;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Configure flags worth knowing about when an export fails to encode
+const FFMPEG_CAPABILITIES: &[&str] = &[
+    "libx264", "libx265", "libvpx", "libaom", "libopus", "libmp3lame",
+    "libfreetype", "libass", "nvenc", "amf", "libvpl", "videotoolbox", "vaapi",
+];
+
+#[derive(Debug, Serialize)]
+pub struct ProbeResult {
+    pub name: String,
+    pub ok: bool,
+    pub value: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiskSpace {
+    pub label: String,
+    pub path: String,
+    pub available_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiagnosticsReport {
+    pub os: String,
+    pub arch: String,
+    pub app_version: String,
+    pub probes: Vec<ProbeResult>,
+    pub disk_space: Vec<DiskSpace>,
+    pub paths: BTreeMap<String, String>,
+    pub text: String,
+}
+
+async fn run_probe<F>(name: &str, probe: F) -> ProbeResult
+where
+    F: Future<Output = Result<String, String>>,
+{
+    let start = Instant::now();
+    let result = match timeout(PROBE_TIMEOUT, probe).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Timed out after {} seconds", PROBE_TIMEOUT.as_secs())),
+    };
+
+    let (value, error) = match result {
+        Ok(value) => (Some(value), None),
+        Err(error) => (None, Some(error)),
+    };
+    ProbeResult {
+        name: name.to_string(),
+        ok: error.is_none(),
+        value,
+        error,
+        duration_ms: start.elapsed().as_millis(),
+    }
+}
+
+// wsl.exe writes UTF-16LE when its output is piped, everything else writes UTF-8
+pub fn decode_output(bytes: &[u8]) -> String {
+    let odd_zero_bytes = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    let looks_utf16 = bytes.len() >= 2 && odd_zero_bytes * 2 >= bytes.len() / 2;
+    if looks_utf16 {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units).trim_start_matches('\u{feff}').to_string()
+    } else {
+        String::from_utf8_lossy(bytes).to_string()
+    }
+}
+
+pub async fn command_output(mut command: Command) -> Result<String, String> {
+    command.kill_on_drop(true);
+    let output = command.output().await.map_err(|e| e.to_string())?;
+
+    let stdout = decode_output(&output.stdout).trim().to_string();
+    let stderr = decode_output(&output.stderr).trim().to_string();
+    if output.status.success() {
+        // Some tools (python 2, older pipenv) print their version on stderr
+        Ok(if stdout.is_empty() { stderr } else { stdout })
+    } else if stderr.is_empty() {
+        Err(format!("Exited with {:?}: {}", output.status.code(), stdout))
+    } else {
+        Err(stderr)
+    }
+}
+
+pub fn shell(command_str: &str) -> Command {
+    if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command_str]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command_str]);
+        cmd
+    }
+}
+
+async fn os_version() -> Result<String, String> {
+    if cfg!(target_os = "windows") {
+        command_output(shell("ver")).await
+    } else if cfg!(target_os = "macos") {
+        command_output(shell("sw_vers -productVersion")).await
+    } else {
+        command_output(shell("uname -sr")).await
+    }
+}
+
+async fn ffmpeg_version(app: &AppHandle) -> Result<String, String> {
+    let resolved = resolve_ffmpeg(app).await.map_err(|e| e.to_string())?;
+    let output = ffmpeg_command(app, &resolved)?
+        .args(["-version"])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute FFmpeg: {}", e))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    let version = stdout.lines().next().unwrap_or("").trim().to_string();
+    let configuration = stdout
+        .lines()
+        .find(|l| l.starts_with("configuration:"))
+        .unwrap_or("");
+    let enabled: Vec<&str> = FFMPEG_CAPABILITIES
+        .iter()
+        .copied()
+        .filter(|cap| configuration.contains(cap))
+        .collect();
+
+    if enabled.is_empty() {
+        Ok(format!("{} via {} (no notable libraries detected)", version, resolved.describe()))
+    } else {
+        Ok(format!("{} via {} (enabled: {})", version, resolved.describe(), enabled.join(", ")))
+    }
+}
+
+async fn pipenv_windows(script_dir: &str) -> Result<String, String> {
+    let script = cmd_script(&[&["cd", "/D", script_dir], &["pipenv", "--version"], &["pipenv", "run", "python", "--version"]]);
+    command_output(process::cmd(&script)).await.map(|v| v.lines().collect::<Vec<_>>().join("; "))
+}
+
+async fn pipenv_wsl(script_dir: &str) -> Result<String, String> {
+    let script = format!("cd {} && pipenv --version && pipenv run python --version", quote_sh(script_dir));
+    let mut cmd = Command::new("wsl");
+    cmd.args(["bash", "-c", &script]);
+    command_output(cmd).await.map(|v| v.lines().collect::<Vec<_>>().join("; "))
+}
+
+async fn wsl_status(app: &AppHandle) -> Result<String, String> {
+    match detect_wsl(app, true).await {
+        status @ WslStatus::Available { .. } => Ok(status.to_string()),
+        status => Err(status.to_string()),
+    }
+}
+
+fn disk_space(label: &str, path: &Path) -> DiskSpace {
+    // Walk up to the nearest existing ancestor so a not-yet-created output folder still reports its volume
+    let existing = path.ancestors().find(|p| p.exists()).unwrap_or(path);
+    let (available_bytes, total_bytes, error) = match (fs4::available_space(existing), fs4::total_space(existing)) {
+        (Ok(available), Ok(total)) => (Some(available), Some(total), None),
+        (Err(e), _) | (_, Err(e)) => (None, None, Some(e.to_string())),
+    };
+
+    DiskSpace {
+        label: label.to_string(),
+        path: path.display().to_string(),
+        available_bytes,
+        total_bytes,
+        error,
+    }
+}
+
+fn project_paths(app: &AppHandle) -> BTreeMap<String, String> {
+    let mut paths = BTreeMap::new();
+    let current_dir = env::current_dir().ok();
+    let project = ProjectPaths::resolve(app);
+
+    let describe = |p: Option<PathBuf>| match p {
+        Some(p) => format!("{}{}", p.display(), if p.exists() { "" } else { " (missing)" }),
+        None => "unresolved".to_string(),
+    };
+    paths.insert("current_dir".to_string(), describe(current_dir.clone()));
+    match &project {
+        Ok(project) => {
+            paths.insert("project_root".to_string(), describe(Some(project.root.clone())));
+            paths.insert("remotion_export_json".to_string(), describe(Some(project.export_json())));
+            paths.insert("sample_exporting".to_string(), describe(Some(project.sample_exporting())));
+        }
+        Err(e) => {
+            paths.insert("project_root".to_string(), e.clone());
+        }
+    }
+    let settings = app.state::<SettingsState>().get();
+    paths.insert("windows_script_dir".to_string(), settings.script_dir());
+    paths.insert("wsl_script_dir".to_string(), settings.wsl_script_dir());
+    paths
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{:.1} GB", bytes as f64 / 1_000_000_000.0)
+}
+
+fn format_report(report: &DiagnosticsReport) -> String {
+    let mut lines = vec![
+        "### boardcast diagnostics".to_string(),
+        format!("OS: {} ({})", report.os, report.arch),
+        format!("App version: {}", report.app_version),
+    ];
+
+    for probe in &report.probes {
+        match (&probe.value, &probe.error) {
+            (Some(value), _) => lines.push(format!("{}: {}", probe.name, value)),
+            (None, Some(error)) => lines.push(format!("{}: ERROR {}", probe.name, error)),
+            (None, None) => lines.push(format!("{}: no output", probe.name)),
+        }
+    }
+
+    for disk in &report.disk_space {
+        match (disk.available_bytes, disk.total_bytes) {
+            (Some(available), Some(total)) => lines.push(format!(
+                "Disk ({}): {} free of {} at {}",
+                disk.label, format_bytes(available), format_bytes(total), disk.path
+            )),
+            _ => lines.push(format!("Disk ({}): ERROR {}", disk.label, disk.error.clone().unwrap_or_default())),
+        }
+    }
+
+    for (name, path) in &report.paths {
+        lines.push(format!("Path {}: {}", name, path));
+    }
+
+    lines.join("\n")
+}
+
+#[command]
+pub async fn system_diagnostics(app: AppHandle, output_path: Option<String>) -> Result<DiagnosticsReport, String> {
+    log::info!("Collecting system diagnostics...");
+    let settings = app.state::<SettingsState>().get();
+    let (script_dir, wsl_script_dir) = (settings.script_dir(), settings.wsl_script_dir());
+
+    let (os_version, ffmpeg, node, npx, pipenv_windows, pipenv_wsl, wsl) = tokio::join!(
+        run_probe("os_version", os_version()),
+        run_probe("ffmpeg", ffmpeg_version(&app)),
+        run_probe("node", command_output(shell("node --version"))),
+        run_probe("npx", command_output(shell("npx --version"))),
+        run_probe("python_windows", pipenv_windows(&script_dir)),
+        run_probe("python_wsl", pipenv_wsl(&wsl_script_dir)),
+        run_probe("wsl", wsl_status(&app)),
+    );
+
+    let working_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let output_dir = output_path
+        .map(PathBuf::from)
+        .or_else(|| app.path().video_dir().ok())
+        .unwrap_or_else(|| working_dir.clone());
+
+    let mut report = DiagnosticsReport {
+        os: env::consts::OS.to_string(),
+        arch: env::consts::ARCH.to_string(),
+        app_version: app.package_info().version.to_string(),
+        probes: vec![os_version, ffmpeg, node, npx, pipenv_windows, pipenv_wsl, wsl],
+        disk_space: vec![
+            disk_space("working", &working_dir),
+            disk_space("output", &output_dir),
+        ],
+        paths: project_paths(&app),
+        text: String::new(),
+    };
+    report.text = format_report(&report);
+
+    log::info!("{}", report.text);
+    Ok(report)
+}
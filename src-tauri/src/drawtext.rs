@@ -0,0 +1,123 @@
+// Builds ffmpeg drawtext filters. Text goes through three parsers before it is drawn: the
+// filtergraph, the filter's key=value options, and drawtext's own %{...} expansion, so each
+// layer gets its own escaping pass, innermost first.
+
+// Fonts tried when the caller doesn't supply one; drawtext falls back to fontconfig without a fontfile
+const FONT_CANDIDATES: &[&str] = &[
+    "C:/Windows/Fonts/arial.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+];
+
+pub fn default_font_file() -> Option<String> {
+    FONT_CANDIDATES
+        .iter()
+        .find(|path| std::path::Path::new(path).is_file())
+        .map(|path| path.to_string())
+}
+
+fn escape_chars(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Literal text for drawtext's expansion pass, where % starts a function
+fn escape_expansion(text: &str) -> String {
+    escape_chars(text, &['%'])
+}
+
+// One option value, safe for both the option parser and the filtergraph around it
+fn escape_value(value: &str) -> String {
+    let option = escape_chars(value, &[':', '\'']);
+    escape_chars(&option, &['\'', '[', ']', ',', ';'])
+}
+
+// A file path as an option value for any filter, e.g. lut3d's file
+pub fn escape_filter_path(path: &str) -> String {
+    // Forward slashes avoid a second round of backslash escaping for Windows paths
+    escape_value(&path.replace('\\', "/"))
+}
+
+#[derive(Debug, Clone)]
+pub struct DrawText {
+    // Already in expansion syntax; use `literal` for user text
+    text: String,
+    options: Vec<(String, String)>,
+}
+
+impl DrawText {
+    pub fn literal(text: &str) -> Self {
+        DrawText { text: escape_expansion(text), options: Vec::new() }
+    }
+
+    // Text using drawtext functions such as %{pts:hms}
+    pub fn expansion(text: &str) -> Self {
+        DrawText { text: text.to_string(), options: Vec::new() }
+    }
+
+    pub fn option(mut self, key: &str, value: impl ToString) -> Self {
+        self.options.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn font_file(self, font_file: Option<&str>) -> Self {
+        match font_file {
+            // Forward slashes avoid a second round of backslash escaping for Windows paths
+            Some(path) => self.option("fontfile", path.replace('\\', "/")),
+            None => self,
+        }
+    }
+
+    pub fn build(&self) -> String {
+        let mut filter = format!("drawtext=text={}", escape_value(&self.text));
+        for (key, value) in &self.options {
+            filter.push_str(&format!(":{}={}", key, escape_value(value)));
+        }
+        filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_text_survives_every_parser() {
+        for (text, built) in [
+            ("plain", "drawtext=text=plain"),
+            ("1:23", r"drawtext=text=1\\:23"),
+            ("it's", r"drawtext=text=it\\\'s"),
+            (r"C:\games", r"drawtext=text=C\\:\\\\\\\\games"),
+            ("100%", r"drawtext=text=100\\\\%"),
+            ("e4, e5; [Nf3]", r"drawtext=text=e4\, e5\; \[Nf3\]"),
+        ] {
+            assert_eq!(DrawText::literal(text).build(), built, "{}", text);
+        }
+    }
+
+    #[test]
+    fn expansions_keep_their_functions() {
+        assert_eq!(DrawText::expansion("%{pts:hms}").build(), r"drawtext=text=%{pts\\:hms}");
+        assert_eq!(DrawText::literal("%{pts:hms}").build(), r"drawtext=text=\\\\%{pts\\:hms}");
+    }
+
+    #[test]
+    fn options_are_escaped_like_the_text() {
+        let built = DrawText::literal("Move 1")
+            .option("fontcolor", "white@0.8")
+            .option("x", "w-tw-10")
+            .font_file(Some(r"C:\Windows\Fonts\arial.ttf"))
+            .build();
+        assert_eq!(built, r"drawtext=text=Move 1:fontcolor=white@0.8:x=w-tw-10:fontfile=C\\:/Windows/Fonts/arial.ttf");
+        assert_eq!(DrawText::literal("a").font_file(None).build(), "drawtext=text=a");
+        assert_eq!(escape_filter_path(r"D:\luts\film (1).cube"), r"D\\:/luts/film (1).cube");
+    }
+}
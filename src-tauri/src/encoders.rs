@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{command, AppHandle, Manager};
+
+use crate::benchmark::BenchmarkStore;
+use crate::ffmpeg::{ffmpeg_command, resolve_ffmpeg};
+use crate::jobstate::hash_content;
+
+// Hardware encoder families we know how to drive
+const HARDWARE_FAMILIES: &[&str] = &["nvenc", "qsv", "amf", "videotoolbox"];
+
+// A test encode that hangs is as good as a failed one
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HardwareEncoder {
+    pub name: String,
+    pub codec: String,
+    pub family: String,
+    // Listed encoders often fail at runtime without the matching GPU or driver
+    pub verified: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedEncoders {
+    // Changes whenever a different ffmpeg build is resolved
+    build_hash: String,
+    encoders: Vec<HardwareEncoder>,
+}
+
+pub struct EncoderCache {
+    path: Option<PathBuf>,
+    cached: Mutex<Option<CachedEncoders>>,
+}
+
+impl EncoderCache {
+    pub fn load(app: &AppHandle) -> Self {
+        let path = app.path().app_data_dir().ok().map(|dir| dir.join("hardware_encoders.json"));
+        let cached = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|content| serde_json::from_str(&content).ok());
+        EncoderCache {
+            path,
+            cached: Mutex::new(cached),
+        }
+    }
+
+    fn get(&self, build_hash: &str) -> Option<Vec<HardwareEncoder>> {
+        self.cached
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|c| c.build_hash == build_hash)
+            .map(|c| c.encoders.clone())
+    }
+
+    fn store(&self, entry: CachedEncoders) {
+        if let Some(path) = &self.path {
+            let saved = path
+                .parent()
+                .map(|parent| fs::create_dir_all(parent).map_err(|e| e.to_string()))
+                .unwrap_or(Ok(()))
+                .and_then(|_| serde_json::to_string_pretty(&entry).map_err(|e| e.to_string()))
+                .and_then(|content| fs::write(path, content).map_err(|e| e.to_string()));
+            if let Err(e) = saved {
+                log::warn!("Failed to save the hardware encoder cache: {}", e);
+            }
+        }
+        *self.cached.lock().unwrap() = Some(entry);
+    }
+}
+
+// Parses lines like " V....D h264_nvenc    NVIDIA NVENC H.264 encoder (codec h264)"
+fn parse_encoder_list(stdout: &str) -> Vec<HardwareEncoder> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let flags = fields.next()?;
+            let name = fields.next()?;
+            if !flags.starts_with('V') || flags.len() != 6 {
+                return None;
+            }
+            let family = HARDWARE_FAMILIES.iter().find(|f| name.ends_with(&format!("_{}", f)))?;
+            let codec = line
+                .rsplit_once("(codec ")
+                .and_then(|(_, rest)| rest.split(')').next())
+                .map(String::from)
+                .unwrap_or_else(|| name.trim_end_matches(&format!("_{}", family)).to_string());
+            Some(HardwareEncoder {
+                name: name.to_string(),
+                codec,
+                family: family.to_string(),
+                verified: false,
+                error: None,
+            })
+        })
+        .collect()
+}
+
+pub async fn ffmpeg_output(app: &AppHandle, args: &[&str]) -> Result<(bool, String, String), String> {
+    let resolved = resolve_ffmpeg(app).await.map_err(|e| e.to_string())?;
+    let command = ffmpeg_command(app, &resolved)?.args(args);
+    let output = tokio::time::timeout(VERIFY_TIMEOUT, command.output())
+        .await
+        .map_err(|_| format!("timed out after {} seconds", VERIFY_TIMEOUT.as_secs()))?
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    Ok((
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        String::from_utf8_lossy(&output.stderr).to_string(),
+    ))
+}
+
+// Encodes ten frames of testsrc to the null muxer, which is enough to hit driver and device errors
+async fn verify_encoder(app: &AppHandle, name: &str) -> Result<(), String> {
+    let args = [
+        "-hide_banner", "-f", "lavfi", "-i", "testsrc=size=256x256:rate=30",
+        "-frames:v", "10", "-c:v", name, "-f", "null", "-",
+    ];
+    match ffmpeg_output(app, &args).await? {
+        (true, _, _) => Ok(()),
+        (false, _, stderr) => Err(stderr.lines().last().unwrap_or("test encode failed").trim().to_string()),
+    }
+}
+
+pub async fn build_hash(app: &AppHandle) -> Result<String, String> {
+    let resolved = resolve_ffmpeg(app).await.map_err(|e| e.to_string())?;
+    let (_, version, _) = ffmpeg_output(app, &["-hide_banner", "-version"]).await?;
+    Ok(hash_content(&format!("{}\n{}", resolved.describe(), version)))
+}
+
+// Detected and verified hardware encoders for the resolved ffmpeg, from the cache when it matches the build
+pub async fn hardware_encoders(app: &AppHandle, refresh: bool) -> Result<Vec<HardwareEncoder>, String> {
+    let build_hash = build_hash(app).await?;
+    let cache = app.state::<EncoderCache>();
+    if !refresh {
+        if let Some(encoders) = cache.get(&build_hash) {
+            return Ok(encoders);
+        }
+    }
+
+    let (_, listing, _) = ffmpeg_output(app, &["-hide_banner", "-encoders"]).await?;
+    let mut encoders = parse_encoder_list(&listing);
+    for encoder in &mut encoders {
+        match verify_encoder(app, &encoder.name).await {
+            Ok(()) => encoder.verified = true,
+            Err(e) => encoder.error = Some(e),
+        }
+        log::info!("Hardware encoder {}: {}", encoder.name, if encoder.verified { "ok" } else { "failed" });
+    }
+
+    cache.store(CachedEncoders { build_hash, encoders: encoders.clone() });
+    Ok(encoders)
+}
+
+// First verified hardware encoder for a codec such as "h264", if any. libx264 is picked
+// instead when the last benchmark found it faster than that encoder on this machine.
+pub async fn auto_encoder(app: &AppHandle, codec: &str) -> Option<String> {
+    match hardware_encoders(app, false).await {
+        Ok(encoders) => {
+            let encoder = encoders.into_iter().find(|e| e.verified && e.codec == codec)?.name;
+            if app.state::<BenchmarkStore>().software_is_faster(&encoder) {
+                log::info!("The benchmark found libx264 faster than {}", encoder);
+                return Some("libx264".to_string());
+            }
+            Some(encoder)
+        }
+        Err(e) => {
+            log::warn!("Hardware encoder detection failed: {}", e);
+            None
+        }
+    }
+}
+
+#[command]
+pub async fn get_hardware_encoders(app: AppHandle, refresh: Option<bool>) -> Result<Vec<HardwareEncoder>, String> {
+    hardware_encoders(&app, refresh.unwrap_or(false)).await
+}
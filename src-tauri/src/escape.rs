@@ -0,0 +1,170 @@
+// Quoting helpers for every place an argument is embedded in a larger string: command lines shown
+// to the user, scripts run through a shell, and ffmpeg list files
+
+fn is_posix_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c)
+}
+
+pub fn quote_posix(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(is_posix_safe) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+// Follows the MSVC runtime rules for splitting a command line, which is what ffmpeg.exe uses
+pub fn quote_cmd(arg: &str) -> String {
+    let needs_quotes = arg.is_empty()
+        || arg.chars().any(|c| c.is_whitespace() || "\"&|<>^()%!,;=".contains(c));
+    if !needs_quotes {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+// One argument of a script run through `bash -c`; always quoted, so nothing in it is expanded
+pub fn quote_sh(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+// A `cmd /C` command line with every argument quoted and the && between the commands left bare.
+// cmd.exe parses this itself, so it has to be passed as one raw argument (process::cmd); %VAR% is
+// still expanded inside the quotes, cmd has no way to escape it there.
+pub fn cmd_script(commands: &[&[&str]]) -> String {
+    commands
+        .iter()
+        .map(|command| command.iter().map(|arg| quote_cmd(arg)).collect::<Vec<String>>().join(" "))
+        .collect::<Vec<String>>()
+        .join(" && ")
+}
+
+// A line of an ffmpeg concat demuxer list, which reads single-quoted strings with a
+// shell-like '\'' for a quote. The list has one entry per line, so a newline can't be in one.
+pub fn concat_entry(path: &str) -> Result<String, String> {
+    if path.contains(['\n', '\r']) {
+        return Err(format!("{:?} can't go in an ffmpeg concat list: it contains a line break", path));
+    }
+    Ok(format!("file '{}'\n", path.replace('\'', "'\\''")))
+}
+
+pub fn render_command_line(program: &str, args: &[String]) -> String {
+    let quote: fn(&str) -> String = if cfg!(target_os = "windows") {
+        quote_cmd
+    } else {
+        quote_posix
+    };
+
+    std::iter::once(program)
+        .chain(args.iter().map(|a| a.as_str()))
+        .map(quote)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn posix_quoting_leaves_plain_words_bare() {
+        assert_eq!(quote_posix("/tmp/output-1.mp4"), "/tmp/output-1.mp4");
+        assert_eq!(quote_posix("scale=1920:1080,fps=30"), "scale=1920:1080,fps=30");
+        assert_eq!(quote_posix("100%"), "100%");
+        assert_eq!(quote_posix("Ränkspiel.mkv"), "'Ränkspiel.mkv'");
+        assert_eq!(quote_posix("Carlsen's game"), "'Carlsen'\\''s game'");
+        assert_eq!(quote_posix("$HOME"), "'$HOME'");
+        assert_eq!(quote_posix(""), "''");
+    }
+
+    #[test]
+    fn sh_quoting_always_quotes() {
+        assert_eq!(quote_sh("output.mp4"), "'output.mp4'");
+        assert_eq!(quote_sh("ブリッツ対局.mp4"), "'ブリッツ対局.mp4'");
+        assert_eq!(quote_sh("it's $5"), "'it'\\''s $5'");
+    }
+
+    // The shell itself decides whether the quoting holds, so every awkward name is echoed back through it
+    #[cfg(unix)]
+    #[test]
+    fn quoted_arguments_come_back_from_the_shell_unchanged() {
+        // Names that have broken a step of the pipeline before, or could
+        const AWKWARD: &[&str] = &[
+            "ブリッツ対局.mp4",
+            "Ränkspiel #3.mkv",
+            "Carlsen's best game.mp4",
+            "100% accuracy.mp4",
+            "C:\\Users\\me\\Videos\\game: round 1.mp4",
+            "$HOME and `date` and $(rm -rf x).mp4",
+            "\"quoted\" name.mp4",
+            "",
+        ];
+        for quote in [quote_posix as fn(&str) -> String, quote_sh] {
+            for &arg in AWKWARD {
+                let output = std::process::Command::new("sh")
+                    .args(["-c", &format!("printf '%s' {}", quote(arg))])
+                    .output()
+                    .unwrap();
+                assert_eq!(String::from_utf8(output.stdout).unwrap(), arg);
+            }
+        }
+    }
+
+    #[test]
+    fn cmd_quoting_follows_the_msvc_rules() {
+        assert_eq!(quote_cmd(r"C:\Videos\output.mp4"), r"C:\Videos\output.mp4");
+        assert_eq!(quote_cmd("ブリッツ対局.mp4"), "ブリッツ対局.mp4");
+        assert_eq!(quote_cmd("game: round 1.mp4"), "\"game: round 1.mp4\"");
+        assert_eq!(quote_cmd("100%"), "\"100%\"");
+        assert_eq!(quote_cmd(""), "\"\"");
+        // Backslashes only double before a quote, including the closing one
+        assert_eq!(quote_cmd(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_cmd(r"C:\My Videos\"), r#""C:\My Videos\\""#);
+        assert_eq!(quote_cmd(r#"a\"b c"#), r#""a\\\"b c""#);
+    }
+
+    #[test]
+    fn cmd_scripts_join_quoted_commands() {
+        let script = cmd_script(&[&["cd", "/D", r"C:\Räume & Co"], &["pipenv", "run", "python", "export.py"]]);
+        assert_eq!(script, r#"cd /D "C:\Räume & Co" && pipenv run python export.py"#);
+    }
+
+    #[test]
+    fn concat_entries_quote_like_the_shell_and_refuse_line_breaks() {
+        assert_eq!(concat_entry("/tmp/ブリッツ対局.mp4").unwrap(), "file '/tmp/ブリッツ対局.mp4'\n");
+        assert_eq!(concat_entry("/tmp/Carlsen's 100%.mp4").unwrap(), "file '/tmp/Carlsen'\\''s 100%.mp4'\n");
+        assert_eq!(
+            concat_entry("/tmp/two\nlines.mp4").unwrap_err(),
+            "\"/tmp/two\\nlines.mp4\" can't go in an ffmpeg concat list: it contains a line break"
+        );
+    }
+
+    #[test]
+    fn command_lines_quote_every_argument_for_the_platform() {
+        let args: Vec<String> = ["-i", "Ränkspiel #3.mkv", "-y", "out.mp4"].iter().map(|a| a.to_string()).collect();
+        let expected = match cfg!(target_os = "windows") {
+            true => "ffmpeg -i \"Ränkspiel #3.mkv\" -y out.mp4",
+            false => "ffmpeg -i 'Ränkspiel #3.mkv' -y out.mp4",
+        };
+        assert_eq!(render_command_line("ffmpeg", &args), expected);
+    }
+}
@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use tauri::{command, AppHandle, Manager};
+
+use crate::export_data::drop_zero_duration_moves;
+use crate::ffmpeg::probe_video;
+use crate::hello::planned_output_duration;
+use crate::history::ExportHistory;
+use crate::timecode;
+
+// Muxing overhead on top of the streams themselves
+pub const CONTAINER_OVERHEAD: f64 = 0.02;
+
+// Assumed for the background's audio, which is usually copied through at whatever rate it has
+const DEFAULT_AUDIO_KBPS: u32 = 128;
+
+// Each codec's encoder default, used when neither a CRF nor a bitrate is asked for
+fn default_crf(family: &str) -> u32 {
+    match family {
+        "hevc" => 28,
+        "vp9" => 31,
+        "av1" => 30,
+        _ => 23,
+    }
+}
+
+// Rough bits per pixel at the codec's default CRF for screen-recorded gameplay
+fn baseline_bits_per_pixel(family: &str) -> f64 {
+    match family {
+        "hevc" => 0.06,
+        "vp9" => 0.07,
+        "av1" => 0.05,
+        _ => 0.10,
+    }
+}
+
+pub fn codec_family(encoder: &str) -> &'static str {
+    let encoder = encoder.to_lowercase();
+    if encoder.contains("265") || encoder.contains("hevc") {
+        "hevc"
+    } else if encoder.contains("vp9") || encoder.contains("vpx") {
+        "vp9"
+    } else if encoder.contains("av1") || encoder.contains("aom") {
+        "av1"
+    } else {
+        "h264"
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EstimateOptions {
+    // Encoder name; defaults to the payload's videoEncoder, then libx264
+    #[serde(default)]
+    pub codec: Option<String>,
+    #[serde(default)]
+    pub bitrate_kbps: Option<u32>,
+    #[serde(default)]
+    pub crf: Option<u32>,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub fps: Option<f64>,
+    #[serde(default)]
+    pub audio_kbps: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SizeEstimate {
+    pub low_bytes: u64,
+    pub expected_bytes: u64,
+    pub high_bytes: u64,
+    pub duration_secs: f64,
+    // How the video bitrate was arrived at: "bitrate", "history" or "heuristic"
+    pub method: String,
+    pub assumptions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeTarget {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    pub duration_secs: f64,
+    pub has_audio: bool,
+}
+
+// Pure size math once the target's shape is known
+pub fn estimate_size(history: &ExportHistory, target: EncodeTarget, codec: &str, options: &EstimateOptions) -> SizeEstimate {
+    let mut assumptions = vec![format!(
+        "{}x{} at {} fps for {:.1} s",
+        target.width, target.height, target.fps, target.duration_secs
+    )];
+    let pixels_per_sec = f64::from(target.width) * f64::from(target.height) * target.fps;
+    let family = codec_family(codec);
+
+    // Each method comes with its own uncertainty band
+    let (video_bps, method, low, high) = match options.bitrate_kbps {
+        Some(kbps) => {
+            assumptions.push(format!("Video at the requested {} kb/s", kbps));
+            (f64::from(kbps) * 1000.0, "bitrate", 0.95, 1.05)
+        }
+        None => match history.measured_bits_per_pixel(codec, target.width, target.height, options.crf) {
+            Some((bpp, samples)) => {
+                assumptions.push(format!("{:.3} bits per pixel measured over {} past export(s) with {}", bpp, samples, codec));
+                (bpp * pixels_per_sec, "history", 0.8, 1.25)
+            }
+            None => {
+                let crf = options.crf.unwrap_or_else(|| default_crf(family));
+                // Every 6 CRF steps roughly halves or doubles the bitrate
+                let bpp = baseline_bits_per_pixel(family) * 2f64.powf((f64::from(default_crf(family)) - f64::from(crf)) / 6.0);
+                assumptions.push(format!("{:.3} bits per pixel estimated for {} at CRF {}", bpp, family, crf));
+                (bpp * pixels_per_sec, "heuristic", 0.6, 1.6)
+            }
+        },
+    };
+
+    let audio_bps = if target.has_audio {
+        let kbps = options.audio_kbps.unwrap_or(DEFAULT_AUDIO_KBPS);
+        assumptions.push(format!("Audio at {} kb/s", kbps));
+        f64::from(kbps) * 1000.0
+    } else {
+        assumptions.push("No audio track".to_string());
+        0.0
+    };
+    assumptions.push(format!("{}% container overhead", CONTAINER_OVERHEAD * 100.0));
+
+    let bytes = |video_factor: f64| {
+        let bits = (video_bps * video_factor + audio_bps) * target.duration_secs;
+        (bits / 8.0 * (1.0 + CONTAINER_OVERHEAD)).round() as u64
+    };
+    SizeEstimate {
+        low_bytes: bytes(low),
+        expected_bytes: bytes(1.0),
+        high_bytes: bytes(high),
+        duration_secs: target.duration_secs,
+        method: method.to_string(),
+        assumptions,
+    }
+}
+
+pub async fn estimate_for_payload(app: &AppHandle, data: &Value, options: &EstimateOptions) -> Result<SizeEstimate, String> {
+    let data = timecode::timecodes_to_seconds(app, data.clone()).await?;
+    let mut data = timecode::resolve_background_fps(app, data).await?;
+    drop_zero_duration_moves(&mut data)?;
+    let data = &data;
+    let video_path = data.get("videoPath").and_then(|v| v.as_str()).ok_or("No videoPath in export data")?;
+    let probe = probe_video(app, Path::new(video_path)).await?;
+    let source_duration = probe
+        .duration_secs
+        .ok_or_else(|| format!("Could not read the duration of {}", video_path))?;
+
+    let target = EncodeTarget {
+        width: options.width.unwrap_or(probe.width),
+        height: options.height.unwrap_or(probe.height),
+        fps: options.fps.or(probe.fps).unwrap_or(30.0),
+        duration_secs: planned_output_duration(data, source_duration)?,
+        has_audio: probe.has_audio,
+    };
+    let codec = options
+        .codec
+        .clone()
+        .or_else(|| data.get("videoEncoder").and_then(|v| v.as_str()).filter(|e| *e != "auto").map(String::from))
+        .unwrap_or_else(|| "libx264".to_string());
+    Ok(estimate_size(&app.state::<ExportHistory>(), target, &codec, options))
+}
+
+#[command]
+pub async fn estimate_export_size(app: AppHandle, data: Value, options: Option<EstimateOptions>) -> Result<SizeEstimate, String> {
+    estimate_for_payload(&app, &data, &options.unwrap_or_default()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{EncodeStats, HistoryEntry};
+
+    const TEN_SECONDS_1080P: EncodeTarget = EncodeTarget { width: 1920, height: 1080, fps: 30.0, duration_secs: 10.0, has_audio: true };
+
+    fn completed(export_id: &str, encode: EncodeStats, preview: bool) -> HistoryEntry {
+        HistoryEntry {
+            export_id: export_id.to_string(),
+            status: "completed".to_string(),
+            finished_at: 0,
+            moves: 40,
+            video_path: None,
+            output_path: None,
+            stage_durations: Default::default(),
+            encode: Some(encode),
+            preview,
+            managed_output: false,
+            pruned: false,
+            hooks_skipped: false,
+            finished_to: Vec::new(),
+            rendered_frames: None,
+        }
+    }
+
+    fn encode(crf: Option<u32>, size_bytes: u64) -> EncodeStats {
+        EncodeStats { codec: "libx264".to_string(), width: 1920, height: 1080, crf, fps: 30.0, duration_secs: 10.0, size_bytes }
+    }
+
+    fn bytes(estimate: &SizeEstimate) -> [u64; 3] {
+        [estimate.low_bytes, estimate.expected_bytes, estimate.high_bytes]
+    }
+
+    #[test]
+    fn encoders_are_grouped_by_codec() {
+        assert_eq!(codec_family("libx265"), "hevc");
+        assert_eq!(codec_family("hevc_nvenc"), "hevc");
+        assert_eq!(codec_family("libvpx-vp9"), "vp9");
+        assert_eq!(codec_family("libaom-av1"), "av1");
+        assert_eq!(codec_family("h264_videotoolbox"), "h264");
+        assert_eq!(codec_family("libx264"), "h264");
+    }
+
+    #[test]
+    fn without_history_the_codec_default_crf_sets_the_bitrate() {
+        let estimate = estimate_size(&ExportHistory::default(), TEN_SECONDS_1080P, "libx264", &EstimateOptions::default());
+        // 0.1 bits per pixel of 1920x1080 at 30 fps and 128 kb/s of audio for 10s, plus 2%
+        assert_eq!(bytes(&estimate), [4_922_112, 8_094_720, 12_853_632]);
+        assert_eq!(estimate.method, "heuristic");
+        assert_eq!(
+            estimate.assumptions,
+            [
+                "1920x1080 at 30 fps for 10.0 s",
+                "0.100 bits per pixel estimated for h264 at CRF 23",
+                "Audio at 128 kb/s",
+                "2% container overhead",
+            ]
+        );
+
+        // Six CRF steps down doubles the video bitrate
+        let options = EstimateOptions { crf: Some(17), audio_kbps: Some(0), ..Default::default() };
+        let sharper = estimate_size(&ExportHistory::default(), TEN_SECONDS_1080P, "libx264", &options);
+        let options = EstimateOptions { audio_kbps: Some(0), ..Default::default() };
+        let default = estimate_size(&ExportHistory::default(), TEN_SECONDS_1080P, "libx264", &options);
+        assert_eq!(sharper.expected_bytes, 2 * default.expected_bytes);
+    }
+
+    #[test]
+    fn a_requested_bitrate_is_trusted_within_5_percent() {
+        let target = EncodeTarget { has_audio: false, duration_secs: 60.0, ..TEN_SECONDS_1080P };
+        let options = EstimateOptions { bitrate_kbps: Some(8000), ..Default::default() };
+        let estimate = estimate_size(&ExportHistory::default(), target, "libx264", &options);
+        assert_eq!(bytes(&estimate), [58_140_000, 61_200_000, 64_260_000]);
+        assert_eq!(estimate.method, "bitrate");
+        assert!(estimate.assumptions.contains(&"No audio track".to_string()));
+    }
+
+    #[test]
+    fn past_full_exports_with_the_same_settings_calibrate_the_estimate() {
+        let history = ExportHistory::default();
+        history.record(completed("1", encode(None, 5_000_000), false));
+        history.record(completed("2", encode(None, 7_000_000), false));
+        // A preview and an export at another CRF don't count
+        history.record(completed("3", encode(None, 50_000_000), true));
+        history.record(completed("4", encode(Some(18), 50_000_000), false));
+
+        let target = EncodeTarget { has_audio: false, ..TEN_SECONDS_1080P };
+        let estimate = estimate_size(&history, target, "libx264", &EstimateOptions::default());
+        assert_eq!(estimate.method, "history");
+        assert_eq!(estimate.assumptions[1], "0.077 bits per pixel measured over 2 past export(s) with libx264");
+        // The past exports averaged 6 MB, so this one is 6 MB plus the container overhead
+        assert_eq!(bytes(&estimate), [4_896_000, 6_120_000, 7_650_000]);
+    }
+}
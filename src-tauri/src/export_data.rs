@@ -0,0 +1,1728 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+use tauri::command;
+
+use crate::filename::FilenameMode;
+use crate::hook::ExportHook;
+use crate::migrate;
+use crate::outputname::check_template;
+
+// Anything bigger than this is not an export.json someone edited by hand
+const MAX_IMPORT_BYTES: u64 = 16 * 1024 * 1024;
+
+// Per-move background speeds outside this range are almost certainly typos
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 10.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Evaluation {
+    #[serde(default)]
+    pub evaluation: Option<Value>,
+    #[serde(default)]
+    pub best_move: Option<String>,
+}
+
+// How overlay segments are cut out of the rendered animation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeekMode {
+    // Trim on timestamps; a boundary frame can land on either side after rounding
+    #[default]
+    Fast,
+    // Trim on frame indices so every segment starts on the move's first frame
+    Accurate,
+}
+
+impl SeekMode {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("seek_mode") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| format!("seek_mode must be \"fast\" or \"accurate\", got {}", value)),
+        }
+    }
+}
+
+// Keeps a background export from starving a stream or game running alongside it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    // Passed to ffmpeg as -threads; 0 lets ffmpeg decide
+    #[serde(default, alias = "ffmpegThreads")]
+    pub ffmpeg_threads: u32,
+    #[serde(default, alias = "lowPriority")]
+    pub low_priority: bool,
+}
+
+impl ResourceLimits {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        match data.get("resource_limits") {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map(Some)
+                .map_err(|e| format!("Invalid resource_limits: {}", e)),
+        }
+    }
+}
+
+// Region of the rendered animation to keep, in its own pixel coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OverlayCrop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OverlayCrop {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        match data.get("overlay_crop") {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map(Some)
+                .map_err(|e| format!("Invalid overlay_crop: {}", e)),
+        }
+    }
+
+    pub fn validate(&self, (width, height): (u32, u32)) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("overlay_crop width and height must be greater than 0".to_string());
+        }
+        if u64::from(self.x) + u64::from(self.width) > u64::from(width)
+            || u64::from(self.y) + u64::from(self.height) > u64::from(height)
+        {
+            return Err(format!(
+                "overlay_crop {}x{} at ({}, {}) does not fit the {}x{} animation",
+                self.width, self.height, self.x, self.y, width, height
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TreatmentMode {
+    Blur,
+    Dim,
+}
+
+// Blurs or darkens the background under the board while it is shown
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BackgroundTreatment {
+    pub mode: TreatmentMode,
+    // Blur radius in pixels, or how much to darken from 0.0 to 1.0
+    pub strength: f64,
+    #[serde(default, alias = "paddingPx")]
+    pub padding_px: u32,
+}
+
+impl BackgroundTreatment {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let treatment: Option<Self> = match data.get("background_treatment") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid background_treatment: {}", e))?,
+        };
+        match treatment {
+            Some(t) if t.mode == TreatmentMode::Blur && (t.strength.is_nan() || t.strength < 1.0) => {
+                Err(format!("background_treatment blur strength must be at least 1, got {}", t.strength))
+            }
+            Some(t) if t.mode == TreatmentMode::Dim && !(0.0..=1.0).contains(&t.strength) => {
+                Err(format!("background_treatment dim strength must be between 0.0 and 1.0, got {}", t.strength))
+            }
+            treatment => Ok(treatment),
+        }
+    }
+}
+
+// Short brightness pulse on the background at each move's timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MoveFlash {
+    #[serde(default = "default_flash_duration_ms")]
+    pub duration_ms: u32,
+    // Peak brightness added, from 0.0 to 1.0
+    #[serde(default = "default_flash_intensity")]
+    pub intensity: f64,
+}
+
+fn default_flash_duration_ms() -> u32 {
+    150
+}
+
+fn default_flash_intensity() -> f64 {
+    0.3
+}
+
+impl MoveFlash {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let flash: Option<Self> = match data.get("move_flash") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid move_flash: {}", e))?,
+        };
+        match flash {
+            Some(f) if f.duration_ms == 0 => Err("move_flash duration_ms must be greater than 0".to_string()),
+            Some(f) if !(0.0..=1.0).contains(&f.intensity) => {
+                Err(format!("move_flash intensity must be between 0.0 and 1.0, got {}", f.intensity))
+            }
+            flash => Ok(flash),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationKind {
+    // Moves in from an edge of the frame and back out again
+    Slide,
+    // Fades in and out in place
+    Pop,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlideEdge {
+    // Whichever edge the board is closest to
+    #[default]
+    Nearest,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+// How the board enters at the start of its window and leaves at the end, instead of cutting
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OverlayAnimation {
+    #[serde(rename = "type")]
+    pub kind: AnimationKind,
+    #[serde(default = "default_animation_duration_ms")]
+    pub duration_ms: u32,
+    #[serde(default)]
+    pub edge: SlideEdge,
+}
+
+fn default_animation_duration_ms() -> u32 {
+    200
+}
+
+impl OverlayAnimation {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let animation: Option<Self> = match data.get("overlay_animation") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid overlay_animation: {}", e))?,
+        };
+        match animation {
+            Some(a) if a.duration_ms == 0 || a.duration_ms > 5000 => Err(format!(
+                "overlay_animation duration_ms must be between 1 and 5000, got {}",
+                a.duration_ms
+            )),
+            animation => Ok(animation),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransitionKind {
+    Crossfade,
+}
+
+// Dissolve from one move's frozen board into the next move's animation when they are back to back
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SegmentTransition {
+    #[serde(rename = "type")]
+    pub kind: TransitionKind,
+    #[serde(default = "default_transition_duration_ms")]
+    pub duration_ms: u32,
+}
+
+fn default_transition_duration_ms() -> u32 {
+    250
+}
+
+impl SegmentTransition {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let transition: Option<Self> = match data.get("segment_transition") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid segment_transition: {}", e))?,
+        };
+        match transition {
+            Some(t) if t.duration_ms == 0 || t.duration_ms > 2000 => Err(format!(
+                "segment_transition duration_ms must be between 1 and 2000, got {}",
+                t.duration_ms
+            )),
+            transition => Ok(transition),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoomMode {
+    // Push in and ease back out over each overlay window
+    DuringOverlay,
+    // One slow push in across the whole video
+    Whole,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BackgroundZoom {
+    // Extra scale at the height of the zoom, e.g. 0.1 for 110%
+    pub amount: f64,
+    pub mode: ZoomMode,
+}
+
+impl BackgroundZoom {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let zoom: Option<Self> = match data.get("background_zoom") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid background_zoom: {}", e))?,
+        };
+        match zoom {
+            Some(z) if !(0.0..=1.0).contains(&z.amount) => {
+                Err(format!("background_zoom amount must be between 0.0 and 1.0, got {}", z.amount))
+            }
+            zoom => Ok(zoom),
+        }
+    }
+}
+
+// Fixes for sideways or mirrored backgrounds, applied before anything else looks at the frame
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BackgroundTransform {
+    // Clockwise degrees: 0, 90, 180 or 270
+    #[serde(default)]
+    pub rotate: u32,
+    #[serde(default)]
+    pub hflip: bool,
+    #[serde(default)]
+    pub vflip: bool,
+    // Bake in the rotation the container asks players to apply, before `rotate`
+    #[serde(default = "default_true")]
+    pub honor_rotation_metadata: bool,
+}
+
+impl BackgroundTransform {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let transform: Option<Self> = match data.get("background_transform") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid background_transform: {}", e))?,
+        };
+        match transform {
+            Some(t) if ![0, 90, 180, 270].contains(&t.rotate) => {
+                Err(format!("background_transform rotate must be 0, 90, 180 or 270, got {}", t.rotate))
+            }
+            transform => Ok(transform),
+        }
+    }
+}
+
+// What to do with an HDR background under the SDR board render
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HdrHandling {
+    // Tonemap when the probe reports an HDR transfer
+    #[default]
+    Auto,
+    // Always tonemap, assuming PQ when the probe can't tell
+    Tonemap,
+    // Keep the HDR video and tag the output as HDR
+    Passthrough,
+}
+
+impl HdrHandling {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("hdr_handling") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| format!("hdr_handling must be \"auto\", \"tonemap\" or \"passthrough\", got {}", value)),
+        }
+    }
+}
+
+// LUT formats ffmpeg's lut3d filter reads
+const LUT_EXTENSIONS: &[&str] = &["cube", "3dl", "dat", "m3d", "csp"];
+
+// Colour correction for the background only; the board is overlaid after it and keeps its colours
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColorGrade {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lut_file: Option<String>,
+    // eq filter adjustments: brightness -1.0 to 1.0 (0 is unchanged), contrast and saturation 0.0 to 3.0 (1 is unchanged)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contrast: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<f64>,
+}
+
+impl ColorGrade {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let grade: Self = match data.get("color_grade") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid color_grade: {}", e))?,
+        };
+        if let Some(lut) = &grade.lut_file {
+            let extension = Path::new(lut)
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if !LUT_EXTENSIONS.contains(&extension.as_str()) {
+                return Err(format!(
+                    "color_grade lut_file must be a .{} file, got {}",
+                    LUT_EXTENSIONS.join(", ."), lut
+                ));
+            }
+        }
+        for (field, value, range) in [
+            ("brightness", grade.brightness, -1.0..=1.0),
+            ("contrast", grade.contrast, 0.0..=3.0),
+            ("saturation", grade.saturation, 0.0..=3.0),
+        ] {
+            if let Some(value) = value.filter(|v| !range.contains(v)) {
+                return Err(format!(
+                    "color_grade {} must be between {} and {}, got {}",
+                    field, range.start(), range.end(), value
+                ));
+            }
+        }
+        Ok(Some(grade))
+    }
+
+    pub fn has_adjustments(&self) -> bool {
+        self.brightness.is_some() || self.contrast.is_some() || self.saturation.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockFormat {
+    // Running time of the output video
+    Elapsed,
+    // The move shown by the current overlay window
+    MoveNumber,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClockStyle {
+    #[serde(default = "default_clock_font_size")]
+    pub font_size: u32,
+    #[serde(default = "default_clock_color")]
+    pub color: String,
+    // Box drawn behind the text, e.g. "black@0.5"; no box when absent
+    #[serde(default)]
+    pub background: Option<String>,
+}
+
+impl Default for ClockStyle {
+    fn default() -> Self {
+        ClockStyle {
+            font_size: default_clock_font_size(),
+            color: default_clock_color(),
+            background: None,
+        }
+    }
+}
+
+fn default_clock_font_size() -> u32 {
+    32
+}
+
+fn default_clock_color() -> String {
+    "white".to_string()
+}
+
+// Small running clock or move counter burned into a corner of the export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClockOverlay {
+    #[serde(default)]
+    pub position: Corner,
+    pub format: ClockFormat,
+    #[serde(default)]
+    pub style: ClockStyle,
+}
+
+impl ClockOverlay {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let clock: Option<Self> = match data.get("clock_overlay") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid clock_overlay: {}", e))?,
+        };
+        match clock {
+            Some(c) if c.style.font_size == 0 => Err("clock_overlay font_size must be greater than 0".to_string()),
+            clock => Ok(clock),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LayerTimeRange {
+    pub start: f64,
+    #[serde(default)]
+    pub end: Option<f64>,
+}
+
+// Another video composited with the board, e.g. a facecam or a watermark
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtraLayer {
+    pub file: String,
+    // Corner placement; ignored when x and y are given
+    #[serde(default)]
+    pub anchor: Option<Corner>,
+    #[serde(default)]
+    pub x: Option<f64>,
+    #[serde(default)]
+    pub y: Option<f64>,
+    // Relative to the layer's own size
+    #[serde(default = "default_layer_scale")]
+    pub scale: f64,
+    // When the layer is shown, in output time; the layer starts playing at `start`
+    #[serde(default)]
+    pub time_range: Option<LayerTimeRange>,
+    // The board sits at 0: lower values go under it, higher ones over it
+    #[serde(default)]
+    pub z_order: i32,
+    #[serde(default)]
+    pub mix_audio: bool,
+}
+
+fn default_layer_scale() -> f64 {
+    1.0
+}
+
+impl ExtraLayer {
+    pub fn from_value(data: &Value) -> Result<Vec<Self>, String> {
+        let layers: Vec<Self> = match data.get("extra_layers") {
+            None | Some(Value::Null) => return Ok(Vec::new()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid extra_layers: {}", e))?,
+        };
+        for (i, layer) in layers.iter().enumerate() {
+            if !layer.scale.is_finite() || layer.scale <= 0.0 {
+                return Err(format!("extra_layers[{}] scale must be greater than 0, got {}", i, layer.scale));
+            }
+            if layer.x.into_iter().chain(layer.y).any(|v| !v.is_finite()) {
+                return Err(format!("extra_layers[{}] x and y must be finite numbers", i));
+            }
+            if let Some(range) = layer.time_range {
+                if !range.start.is_finite() || range.start < 0.0 {
+                    return Err(format!("extra_layers[{}] time_range start must be 0 or later", i));
+                }
+                if range.end.is_some_and(|end| !end.is_finite() || end <= range.start) {
+                    return Err(format!("extra_layers[{}] time_range end must be after its start", i));
+                }
+            }
+        }
+        Ok(layers)
+    }
+}
+
+// Background kept before the first and after the last move of a range, in seconds
+const DEFAULT_RANGE_LEAD_IN: f64 = 2.0;
+const DEFAULT_RANGE_TAIL: f64 = 2.0;
+
+// Exports moves `first` through `last` (1-based, inclusive) with the background trimmed around them
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MoveRange {
+    pub first: usize,
+    pub last: usize,
+    pub lead_in: f64,
+    pub tail: f64,
+}
+
+impl MoveRange {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let (first, last): (usize, usize) = match data.get("move_range") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid move_range, expected [first, last]: {}", e))?,
+        };
+        let moves = move_count(data);
+        if first == 0 || first > last {
+            return Err(format!("move_range must satisfy 1 <= first <= last, got [{}, {}]", first, last));
+        }
+        if last > moves {
+            return Err(format!("move_range ends at move {} but there are only {} moves", last, moves));
+        }
+        let seconds = |field: &str, default: f64| match finite_field(data, field)? {
+            Some(value) if value < 0.0 => Err(format!("{} must be 0 or more, got {}", field, value)),
+            Some(value) => Ok(value),
+            None => Ok(default),
+        };
+        Ok(Some(MoveRange {
+            first,
+            last,
+            lead_in: seconds("move_range_lead_in", DEFAULT_RANGE_LEAD_IN)?,
+            tail: seconds("move_range_tail", DEFAULT_RANGE_TAIL)?,
+        }))
+    }
+}
+
+// "WIDTHxHEIGHT" with both sides positive
+pub fn parse_resolution(resolution: &str) -> Option<(u32, u32)> {
+    let (width, height) = resolution.split_once('x')?;
+    let (width, height) = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+// Encoder options for the final output; anything set here wins over the platform preset
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EncodeSettings {
+    // ffmpeg encoder name, or "auto"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crf: Option<u32>,
+    // Encoder speed preset such as "slow"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_bitrate_kbps: Option<u32>,
+    // Caps the video bitrate on top of the CRF
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bitrate_kbps: Option<u32>,
+    // "WIDTHxHEIGHT" box the composite is scaled to fit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    // Moves the MP4 index to the front so playback can start before the download finishes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub faststart: Option<bool>,
+}
+
+impl EncodeSettings {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        let settings: Self = match data.get("encoding") {
+            None | Some(Value::Null) => Self::default(),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid encoding: {}", e))?,
+        };
+        if let Some(crf) = settings.crf.filter(|&crf| crf > 63) {
+            return Err(format!("encoding crf must be at most 63, got {}", crf));
+        }
+        if let Some(resolution) = &settings.resolution {
+            parse_resolution(resolution)
+                .ok_or_else(|| format!("encoding resolution must look like 1920x1080, got '{}'", resolution))?;
+        }
+        Ok(settings)
+    }
+
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        parse_resolution(self.resolution.as_deref()?)
+    }
+}
+
+// One encoded file produced from the shared composite
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputSpec {
+    pub path: String,
+    // Container name passed to -f; taken from the path's extension when absent
+    #[serde(default)]
+    pub format: Option<String>,
+    // "auto" picks a verified hardware encoder when there is one
+    #[serde(default)]
+    pub codec: Option<String>,
+    // CRF value for the video encoder
+    #[serde(default)]
+    pub quality: Option<u32>,
+    // "WIDTHxHEIGHT"; keeps the composite's size when absent
+    #[serde(default)]
+    pub resolution: Option<String>,
+}
+
+impl OutputSpec {
+    pub fn from_value(data: &Value) -> Result<Vec<Self>, String> {
+        let specs: Vec<Self> = match data.get("outputs") {
+            None | Some(Value::Null) => return Ok(Vec::new()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid outputs: {}", e))?,
+        };
+        for (i, spec) in specs.iter().enumerate() {
+            if spec.path.trim().is_empty() {
+                return Err(format!("outputs[{}] has an empty path", i));
+            }
+            if let Some(resolution) = &spec.resolution {
+                spec.dimensions().ok_or_else(|| {
+                    format!("outputs[{}] resolution must look like 1280x720, got '{}'", i, resolution)
+                })?;
+            }
+        }
+        Ok(specs)
+    }
+
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        parse_resolution(self.resolution.as_deref()?)
+    }
+
+    pub fn container(&self) -> String {
+        self.format.clone().unwrap_or_else(|| {
+            Path::new(&self.path)
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_else(|| "mp4".to_string())
+        })
+    }
+}
+
+// What the background does while a move's animation plays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundBehavior {
+    #[default]
+    Continue,
+    // Hold the frame under each overlay window and resume afterwards, lengthening the video
+    Freeze,
+}
+
+impl BackgroundBehavior {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("background_behavior") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| format!("background_behavior must be \"continue\" or \"freeze\", got {}", value)),
+        }
+    }
+}
+
+// Whether the board covers the background or sits next to it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutMode {
+    #[default]
+    Overlay,
+    SideBySide,
+}
+
+impl LayoutMode {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("layout_mode") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| format!("layout_mode must be \"overlay\" or \"side_by_side\", got {}", value)),
+        }
+    }
+}
+
+// How segment boundaries are rounded: to milliseconds, or to the output's frame times
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimePrecision {
+    #[default]
+    Ms,
+    Frame,
+}
+
+impl TimePrecision {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("time_precision") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| format!("time_precision must be \"ms\" or \"frame\", got {}", value)),
+        }
+    }
+}
+
+// How the composite runs: one filter graph over the whole video, or one small ffmpeg job per
+// stretch of the timeline, joined losslessly at the end
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompositeStrategy {
+    #[default]
+    SinglePass,
+    Segmented,
+}
+
+impl CompositeStrategy {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("composite_strategy") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| format!("composite_strategy must be \"single_pass\" or \"segmented\", got {}", value)),
+        }
+    }
+}
+
+// How ffmpeg decodes the background; the filter graph is software either way
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HwaccelDecode {
+    // The platform's decoder when ffmpeg has it and a test decode of the background works
+    Auto,
+    #[default]
+    Off,
+    D3d11va,
+    Videotoolbox,
+    Vaapi,
+}
+
+impl HwaccelDecode {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("hwaccel_decode") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone()).map_err(|_| {
+                format!("hwaccel_decode must be \"auto\", \"off\", \"d3d11va\", \"videotoolbox\" or \"vaapi\", got {}", value)
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoardSide {
+    Left,
+    #[default]
+    Right,
+}
+
+// Canvas options for the side-by-side layout
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SideBySide {
+    // Share of the canvas width given to the background
+    #[serde(default = "default_side_by_side_split")]
+    pub split: f64,
+    #[serde(default)]
+    pub board_side: BoardSide,
+    // Keep the board on screen between moves instead of only during each move's window
+    #[serde(default = "default_true")]
+    pub persistent: bool,
+}
+
+impl Default for SideBySide {
+    fn default() -> Self {
+        SideBySide {
+            split: default_side_by_side_split(),
+            board_side: BoardSide::default(),
+            persistent: true,
+        }
+    }
+}
+
+fn default_side_by_side_split() -> f64 {
+    0.6
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl SideBySide {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        let options: Self = match data.get("side_by_side") {
+            None | Some(Value::Null) => Self::default(),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid side_by_side: {}", e))?,
+        };
+        if !options.split.is_finite() || !(0.2..=0.8).contains(&options.split) {
+            return Err(format!("side_by_side split must be between 0.2 and 0.8, got {}", options.split));
+        }
+        Ok(options)
+    }
+}
+
+// Board position in pixels: one [x, y] for the whole video, or one per move so the board can move between moves
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum XyOffset {
+    Shared([f64; 2]),
+    PerMove(Vec<[f64; 2]>),
+}
+
+impl XyOffset {
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        let value = match data.get("xy_offset") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => value,
+        };
+        let offset: XyOffset = serde_json::from_value(value.clone())
+            .map_err(|_| format!("xy_offset must be [x, y] or a list with one [x, y] per move, got {}", value))?;
+        if let XyOffset::PerMove(positions) = &offset {
+            let moves = move_count(data);
+            if positions.len() != moves {
+                return Err(format!(
+                    "xy_offset has {} positions but there are {} moves; give one [x, y] per move or a single pair",
+                    positions.len(), moves
+                ));
+            }
+        }
+        Ok(Some(offset))
+    }
+}
+
+// What happens to a move whose timestamp repeats the previous one, leaving it no time on screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZeroDurationPolicy {
+    #[default]
+    Error,
+    Skip,
+}
+
+impl ZeroDurationPolicy {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("zero_duration_policy") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| format!("zero_duration_policy must be \"error\" or \"skip\", got {}", value)),
+        }
+    }
+}
+
+// Lists with one entry per move, which have to lose the same entries
+const PER_MOVE_FIELDS: &[&str] = &["timestamps", "timestamps_frames", "positions", "moves", "evaluations", "speed", "background_clips"];
+
+// Finds moves timestamped within a millisecond of the previous one, or on the same frame when they
+// are given as frames. With the skip policy they are removed from every per-move list, so the
+// render, the plan and everything labelled per move keep the same count; the removed indices are returned.
+pub fn drop_zero_duration_moves(data: &mut Value) -> Result<Vec<usize>, String> {
+    let policy = ZeroDurationPolicy::from_value(data)?;
+    let frames = timestamps_frames(data)?;
+    let timestamps: Vec<Option<f64>> = match &frames {
+        Some(frames) => frames.iter().map(|&f| Some(f as f64)).collect(),
+        None => data.get("timestamps")
+            .and_then(|v| v.as_array())
+            .map(|ts| ts.iter().map(|t| t.as_f64()).collect())
+            .unwrap_or_default(),
+    };
+    let tolerance = if frames.is_some() { 0.5 } else { 0.001 };
+    let duplicates: Vec<usize> = (1..timestamps.len())
+        .filter(|&i| matches!((timestamps[i - 1], timestamps[i]), (Some(a), Some(b)) if (b - a).abs() < tolerance))
+        .collect();
+    if duplicates.is_empty() {
+        return Ok(duplicates);
+    }
+    if policy == ZeroDurationPolicy::Error {
+        let pairs: Vec<String> = duplicates
+            .iter()
+            .map(|&i| {
+                let at = timestamps[i].unwrap_or_default();
+                match frames {
+                    Some(_) => format!("timestamps_frames[{}] and [{}] are both frame {}", i - 1, i, at),
+                    None => format!("timestamps[{}] and [{}] are both {}s", i - 1, i, at),
+                }
+            })
+            .collect();
+        return Err(format!(
+            "{}; remove the duplicates or set zero_duration_policy to \"skip\"",
+            pairs.join(", ")
+        ));
+    }
+
+    let object = data.as_object_mut().ok_or("Export data must be a JSON object")?;
+    let per_move_offsets = matches!(object.get("xy_offset"), Some(Value::Array(items)) if items.iter().all(|v| v.is_array()));
+    let fields = PER_MOVE_FIELDS.iter().copied().chain(per_move_offsets.then_some("xy_offset"));
+    for field in fields {
+        if let Some(Value::Array(items)) = object.get_mut(field) {
+            for &i in duplicates.iter().rev() {
+                if i < items.len() {
+                    items.remove(i);
+                }
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+// What happens when the board would extend past the edges of the frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutOfBounds {
+    #[default]
+    Error,
+    // Move the board just far enough to be fully visible
+    Clamp,
+    Allow,
+}
+
+impl OutOfBounds {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("out_of_bounds") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| format!("out_of_bounds must be \"error\", \"clamp\" or \"allow\", got {}", value)),
+        }
+    }
+}
+
+// What fills the bars when the background is letterboxed: an ffmpeg colour, or "blur"
+pub fn letterbox_fill(data: &Value) -> Result<String, String> {
+    let fill = match data.get("letterbox_fill") {
+        None | Some(Value::Null) => return Ok("black".to_string()),
+        Some(value) => value.as_str().map(str::trim).unwrap_or_default(),
+    };
+    if !is_ffmpeg_colour(fill) {
+        return Err(format!("letterbox_fill must be a colour such as black or #202020, or \"blur\", got '{}'", fill));
+    }
+    Ok(fill.to_string())
+}
+
+// Colour names, #RRGGBB and name@alpha are all ffmpeg needs; anything else could break the graph
+fn is_ffmpeg_colour(colour: &str) -> bool {
+    !colour.is_empty() && colour.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '#' | '@' | '.'))
+}
+
+// Base canvas generated under the board when the background file has audio but no video
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeneratedCanvas {
+    #[serde(default = "default_canvas_color")]
+    pub color: String,
+    #[serde(default = "default_canvas_width")]
+    pub width: u32,
+    #[serde(default = "default_canvas_height")]
+    pub height: u32,
+}
+
+fn default_canvas_color() -> String {
+    "black".to_string()
+}
+
+fn default_canvas_width() -> u32 {
+    1920
+}
+
+fn default_canvas_height() -> u32 {
+    1080
+}
+
+impl GeneratedCanvas {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        let canvas: Self = match data.get("generated_canvas") {
+            None | Some(Value::Null) => serde_json::from_value(Value::Object(Map::new())).map_err(|e| e.to_string())?,
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid generated_canvas: {}", e))?,
+        };
+        if !is_ffmpeg_colour(canvas.color.trim()) {
+            return Err(format!("generated_canvas color must be a colour such as black or #202020, got '{}'", canvas.color));
+        }
+        for (field, side) in [("width", canvas.width), ("height", canvas.height)] {
+            if !(16..=7680).contains(&side) || side % 2 != 0 {
+                return Err(format!("generated_canvas {} must be an even number from 16 to 7680, got {}", field, side));
+            }
+        }
+        Ok(canvas)
+    }
+}
+
+// One clip of the background per move, e.g. a different camera angle or a replay behind each move
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackgroundClip {
+    pub file: String,
+    // Where the clip's part of the background starts, in seconds into the clip
+    #[serde(default)]
+    pub in_point: f64,
+}
+
+impl BackgroundClip {
+    pub fn from_value(data: &Value) -> Result<Option<Vec<Self>>, String> {
+        let clips: Vec<Self> = match data.get("background_clips") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid background_clips: {}", e))?,
+        };
+        let moves = move_count(data);
+        if clips.len() != moves {
+            return Err(format!("background_clips has {} clips but there are {} moves; give one clip per move", clips.len(), moves));
+        }
+        for (i, clip) in clips.iter().enumerate() {
+            if clip.file.trim().is_empty() {
+                return Err(format!("background_clips[{}] has no file", i));
+            }
+            if !clip.in_point.is_finite() || clip.in_point < 0.0 {
+                return Err(format!("background_clips[{}] in_point must be 0 or later, got {}", i, clip.in_point));
+            }
+        }
+        Ok(Some(clips))
+    }
+}
+
+// An exact frame rate, so frame numbers convert to seconds with a single rounding
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRate {
+    pub num: u64,
+    pub den: u64,
+}
+
+impl FrameRate {
+    // Rates within 0.01 of an NTSC rate (29.97 is 30000/1001) or a whole number are taken as exactly that
+    pub fn from_fps(fps: f64) -> Option<Self> {
+        if !fps.is_finite() || fps <= 0.0 {
+            return None;
+        }
+        let whole = fps.round();
+        let ntsc = (fps * 1.001).round();
+        if (fps - ntsc * 1000.0 / 1001.0).abs() < 0.01 && (fps - whole).abs() > 0.01 {
+            return Some(FrameRate { num: ntsc as u64 * 1000, den: 1001 });
+        }
+        if (fps - whole).abs() < 0.01 {
+            return Some(FrameRate { num: whole as u64, den: 1 });
+        }
+        Some(FrameRate { num: (fps * 1000.0).round() as u64, den: 1000 })
+    }
+
+    // A number such as 29.97, or an exact ratio such as "30000/1001"
+    pub fn parse(field: &str, value: &Value) -> Result<Self, String> {
+        let invalid = || format!("{} must be a positive number or a ratio like \"30000/1001\", got {}", field, value);
+        match value {
+            Value::String(ratio) => {
+                let (num, den) = ratio.split_once('/').ok_or_else(invalid)?;
+                match (num.trim().parse::<u64>(), den.trim().parse::<u64>()) {
+                    (Ok(num), Ok(den)) if num > 0 && den > 0 => Ok(FrameRate { num, den }),
+                    _ => Err(invalid()),
+                }
+            }
+            value => value.as_f64().and_then(Self::from_fps).ok_or_else(invalid),
+        }
+    }
+
+    pub fn from_value(data: &Value) -> Result<Option<Self>, String> {
+        match data.get("background_fps") {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => Self::parse("background_fps", value).map(Some),
+        }
+    }
+
+    pub fn fps(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    pub fn seconds(self, frames: u64) -> f64 {
+        (frames as u128 * self.den as u128) as f64 / self.num as f64
+    }
+}
+
+// Number of moves, whether they are timestamped in seconds or in frames
+pub fn move_count(data: &Value) -> usize {
+    ["timestamps", "timestamps_frames"]
+        .iter()
+        .find_map(|field| data.get(*field).and_then(|v| v.as_array()))
+        .map_or(0, |moves| moves.len())
+}
+
+// Move timestamps given as background frames, checked to be whole numbers
+pub fn timestamps_frames(data: &Value) -> Result<Option<Vec<u64>>, String> {
+    match data.get("timestamps_frames") {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Array(frames)) => frames
+            .iter()
+            .enumerate()
+            .map(|(i, v)| v.as_u64().ok_or_else(|| format!("timestamps_frames[{}] must be a whole frame number, got {}", i, v)))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some),
+        Some(value) => Err(format!("timestamps_frames must be a list of frame numbers, got {}", value)),
+    }
+}
+
+// Single audio track laid under stitched background clips instead of the clips' own audio
+pub fn background_audio(data: &Value) -> Result<Option<String>, String> {
+    match data.get("background_audio") {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(path)) if !path.trim().is_empty() => Ok(Some(path.clone())),
+        Some(value) => Err(format!("background_audio must be the path of an audio file, got {}", value)),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportData {
+    // Set to the current version by migrate::migrate before the payload is read
+    #[serde(rename = "schema_version", default = "default_schema_version")]
+    pub schema_version: u32,
+    #[serde(default)]
+    pub video_path: Option<String>,
+    #[serde(default)]
+    pub output_path: Option<String>,
+    #[serde(default = "default_frame_per_move")]
+    pub frame_per_move: u32,
+    #[serde(default = "default_time_per_move")]
+    pub time_per_move: f64,
+    #[serde(default)]
+    pub positions: Vec<String>,
+    #[serde(default)]
+    pub moves: Vec<Option<String>>,
+    #[serde(rename = "x_offset", default, skip_serializing_if = "Option::is_none")]
+    pub x_offset: Option<f64>,
+    #[serde(rename = "y_offset", default, skip_serializing_if = "Option::is_none")]
+    pub y_offset: Option<f64>,
+    // Fractions of the background frame, used when the pixel offsets are absent
+    #[serde(rename = "x_offset_pct", default, skip_serializing_if = "Option::is_none")]
+    pub x_offset_pct: Option<f64>,
+    #[serde(rename = "y_offset_pct", default, skip_serializing_if = "Option::is_none")]
+    pub y_offset_pct: Option<f64>,
+    // Empty when the moves are given as timestamps_frames instead
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timestamps: Vec<Option<f64>>,
+    // Frames of the background at background_fps, kept as frames until the filter values are written
+    #[serde(rename = "timestamps_frames", default, skip_serializing_if = "Option::is_none")]
+    pub timestamps_frames: Option<Vec<u64>>,
+    // e.g. 60, 29.97 or "30000/1001"; probed from videoPath when missing
+    #[serde(rename = "background_fps", default, skip_serializing_if = "Option::is_none")]
+    pub background_fps: Option<Value>,
+    #[serde(default)]
+    pub board_size: Option<f64>,
+    #[serde(default)]
+    pub evaluations: Vec<Evaluation>,
+    #[serde(rename = "seek_mode", default)]
+    pub seek_mode: SeekMode,
+    #[serde(rename = "resource_limits", default)]
+    pub resource_limits: Option<ResourceLimits>,
+    #[serde(rename = "overlay_crop", default)]
+    pub overlay_crop: Option<OverlayCrop>,
+    #[serde(rename = "background_treatment", default)]
+    pub background_treatment: Option<BackgroundTreatment>,
+    #[serde(rename = "background_zoom", default)]
+    pub background_zoom: Option<BackgroundZoom>,
+    #[serde(rename = "background_behavior", default)]
+    pub background_behavior: BackgroundBehavior,
+    // Background playback speed for each move, parallel to timestamps
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub speed: Vec<Option<f64>>,
+    // Embed a chapter per move in the output container
+    #[serde(default)]
+    pub chapters: bool,
+    // Container tags; title, artist, author, comment and date are written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<std::collections::HashMap<String, String>>,
+    // Headers of the game being exported, used to fill in the container tags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pgn_headers: Option<std::collections::HashMap<String, String>>,
+    // Extra encodes of the same composite; outputPath is not written when these are present
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<OutputSpec>,
+    #[serde(rename = "clock_overlay", default, skip_serializing_if = "Option::is_none")]
+    pub clock_overlay: Option<ClockOverlay>,
+    #[serde(rename = "move_flash", default, skip_serializing_if = "Option::is_none")]
+    pub move_flash: Option<MoveFlash>,
+    #[serde(rename = "overlay_animation", default, skip_serializing_if = "Option::is_none")]
+    pub overlay_animation: Option<OverlayAnimation>,
+    #[serde(rename = "segment_transition", default, skip_serializing_if = "Option::is_none")]
+    pub segment_transition: Option<SegmentTransition>,
+    #[serde(rename = "background_transform", default, skip_serializing_if = "Option::is_none")]
+    pub background_transform: Option<BackgroundTransform>,
+    #[serde(rename = "generated_canvas", default, skip_serializing_if = "Option::is_none")]
+    pub generated_canvas: Option<GeneratedCanvas>,
+    // Replaces videoPath with the clips stitched together, one per move
+    #[serde(rename = "background_clips", default, skip_serializing_if = "Option::is_none")]
+    pub background_clips: Option<Vec<BackgroundClip>>,
+    // Clips too short for their move are looped instead of failing the export
+    #[serde(rename = "loop_background_clips", default, skip_serializing_if = "Option::is_none")]
+    pub loop_background_clips: Option<bool>,
+    #[serde(rename = "background_audio", default, skip_serializing_if = "Option::is_none")]
+    pub background_audio: Option<String>,
+    #[serde(rename = "hdr_handling", default)]
+    pub hdr_handling: HdrHandling,
+    // Interlaced sources are only deinterlaced when this is true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deinterlace: Option<bool>,
+    // Variable frame rate backgrounds are normalised when detected; true always normalises, false never
+    #[serde(rename = "force_cfr", default, skip_serializing_if = "Option::is_none")]
+    pub force_cfr: Option<bool>,
+    #[serde(rename = "color_grade", default, skip_serializing_if = "Option::is_none")]
+    pub color_grade: Option<ColorGrade>,
+    #[serde(rename = "extra_layers", default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_layers: Vec<ExtraLayer>,
+    // ffmpeg encoder name, or "auto" for the first verified hardware H.264 encoder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_encoder: Option<String>,
+    // Named set of encoder options for an upload target, e.g. "youtube"
+    #[serde(rename = "platform_preset", default, skip_serializing_if = "Option::is_none")]
+    pub platform_preset: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<EncodeSettings>,
+    // 1-based inclusive [first, last] moves to export
+    #[serde(rename = "move_range", default, skip_serializing_if = "Option::is_none")]
+    pub move_range: Option<(usize, usize)>,
+    #[serde(rename = "move_range_lead_in", default, skip_serializing_if = "Option::is_none")]
+    pub move_range_lead_in: Option<f64>,
+    #[serde(rename = "move_range_tail", default, skip_serializing_if = "Option::is_none")]
+    pub move_range_tail: Option<f64>,
+    // Takes the place of x_offset/y_offset when present
+    #[serde(rename = "xy_offset", default, skip_serializing_if = "Option::is_none")]
+    pub xy_offset: Option<XyOffset>,
+    #[serde(rename = "out_of_bounds", default)]
+    pub out_of_bounds: OutOfBounds,
+    #[serde(rename = "letterbox_fill", default, skip_serializing_if = "Option::is_none")]
+    pub letterbox_fill: Option<String>,
+    #[serde(rename = "layout_mode", default)]
+    pub layout_mode: LayoutMode,
+    #[serde(rename = "side_by_side", default, skip_serializing_if = "Option::is_none")]
+    pub side_by_side: Option<SideBySide>,
+    // Renders only the first N moves, quickly and at low quality, into the preview file
+    #[serde(rename = "preview_moves", default, skip_serializing_if = "Option::is_none")]
+    pub preview_moves: Option<usize>,
+    // Fits the output under this many MB with a two-pass encode
+    #[serde(rename = "target_size_mb", default, skip_serializing_if = "Option::is_none")]
+    pub target_size_mb: Option<f64>,
+    // "skip" drops moves whose timestamp repeats the previous one instead of failing the export
+    #[serde(rename = "zero_duration_policy", default)]
+    pub zero_duration_policy: ZeroDurationPolicy,
+    // Moves whose windows are closer than this are composited as one continuous window
+    #[serde(rename = "merge_gap_ms", default, skip_serializing_if = "Option::is_none")]
+    pub merge_gap_ms: Option<u64>,
+    // Shortens moves that come closer together than timePerMove instead of failing the export
+    #[serde(rename = "auto_shrink", default)]
+    pub auto_shrink: bool,
+    // Only warns when the rendered animation's length doesn't match the moves instead of failing the export
+    #[serde(rename = "ignore_duration_mismatch", default)]
+    pub ignore_duration_mismatch: bool,
+    // Set when boardcast named outputPath itself, which lets the output retention delete it
+    #[serde(rename = "managed_output", default)]
+    pub managed_output: bool,
+    // Names outputPath from placeholders such as {white} and {date}; see outputname.rs
+    #[serde(rename = "output_template", default, skip_serializing_if = "Option::is_none")]
+    pub output_template: Option<String>,
+    // "strict" fails the export over an output name the target filesystem can't hold instead of renaming it
+    #[serde(rename = "filename_mode", default)]
+    pub filename_mode: FilenameMode,
+    // Replace the hook settings for this export; null runs no hook
+    #[serde(rename = "pre_export_hook", default, skip_serializing_if = "Option::is_none")]
+    pub pre_export_hook: Option<ExportHook>,
+    #[serde(rename = "post_export_hook", default, skip_serializing_if = "Option::is_none")]
+    pub post_export_hook: Option<ExportHook>,
+    // Runs neither hook, whatever the settings say
+    #[serde(rename = "skip_hooks", default)]
+    pub skip_hooks: bool,
+    // "frame" snaps every segment boundary to the output's frame times
+    #[serde(rename = "time_precision", default)]
+    pub time_precision: TimePrecision,
+    // "segmented" composites the video in pieces, which keeps long games' memory use down
+    #[serde(rename = "composite_strategy", default)]
+    pub composite_strategy: CompositeStrategy,
+    #[serde(rename = "hwaccel_decode", default)]
+    pub hwaccel_decode: HwaccelDecode,
+    // Replace the timeouts worked out from the export's size
+    #[serde(rename = "render_timeout_secs", default)]
+    pub render_timeout_secs: Option<u64>,
+    #[serde(rename = "ffmpeg_timeout_secs", default)]
+    pub ffmpeg_timeout_secs: Option<u64>,
+    // Collects unknown fields so they can be reported instead of silently vanishing
+    #[serde(flatten, skip_serializing)]
+    pub unknown: Map<String, Value>,
+}
+
+fn default_schema_version() -> u32 {
+    migrate::CURRENT_SCHEMA_VERSION
+}
+
+fn default_frame_per_move() -> u32 {
+    5
+}
+
+fn default_time_per_move() -> f64 {
+    0.2
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportedExport {
+    pub data: ExportData,
+    pub warnings: Vec<String>,
+}
+
+// Numbers sometimes arrive as strings after a round trip through a spreadsheet or text editor
+fn coerce_number(field: &str, value: &mut Value, warnings: &mut Vec<String>) {
+    if let Value::String(s) = value {
+        if let Ok(n) = s.trim().parse::<f64>() {
+            if let Some(number) = serde_json::Number::from_f64(n) {
+                warnings.push(format!("Converted {} from string \"{}\" to a number", field, s));
+                *value = Value::Number(number);
+            }
+        }
+    }
+}
+
+fn coerce_fields(object: &mut Map<String, Value>, warnings: &mut Vec<String>) {
+    for field in ["timePerMove", "x_offset", "y_offset", "boardSize"] {
+        if let Some(value) = object.get_mut(field) {
+            coerce_number(field, value, warnings);
+        }
+    }
+
+    if let Some(Value::Number(n)) = object.get("framePerMove") {
+        if n.as_u64().is_none() {
+            if let Some(f) = n.as_f64().filter(|f| *f >= 0.0) {
+                let rounded = f.round() as u64;
+                warnings.push(format!("Rounded framePerMove from {} to {}", f, rounded));
+                object.insert("framePerMove".to_string(), Value::from(rounded));
+            }
+        }
+    }
+
+    if let Some(Value::Array(timestamps)) = object.get_mut("timestamps") {
+        for (i, value) in timestamps.iter_mut().enumerate() {
+            coerce_number(&format!("timestamps[{}]", i), value, warnings);
+        }
+        let missing: Vec<String> = timestamps
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_null())
+            .map(|(i, _)| i.to_string())
+            .collect();
+        if !missing.is_empty() {
+            warnings.push(format!("Timestamps at indices {} are not set", missing.join(", ")));
+        }
+    }
+}
+
+// Reads an optional numeric field, rejecting anything that isn't a finite number
+fn finite_field(data: &Value, field: &str) -> Result<Option<f64>, String> {
+    match data.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => finite_number(field, value).map(Some),
+    }
+}
+
+fn finite_number(field: &str, value: &Value) -> Result<f64, String> {
+    match value.as_f64() {
+        Some(n) if n.is_finite() => Ok(n),
+        Some(n) => Err(format!("{} must be a finite number, got {}", field, n)),
+        None => Err(format!("{} must be a number, got {}", field, value)),
+    }
+}
+
+// Checks the numbers that end up in the ffmpeg filter graph before anything is rendered
+pub fn validate_export_data(data: &Value, max_moves: usize) -> Result<(), String> {
+    migrate::schema_version(data)?;
+    if let Some(time_per_move) = finite_field(data, "timePerMove")? {
+        if time_per_move <= 0.0 || time_per_move > 60.0 {
+            return Err(format!(
+                "timePerMove must be greater than 0 and at most 60 seconds, got {}",
+                time_per_move
+            ));
+        }
+    }
+
+    let board_size = finite_field(data, "boardSize")?;
+    for field in ["x_offset", "y_offset"] {
+        if let Some(offset) = finite_field(data, field)? {
+            // A board pushed further left/up than its own size is entirely outside the frame. Without a
+            // boardSize, the composite checks the offsets against the probed frame instead.
+            if let Some(size) = board_size.filter(|&size| offset < -size) {
+                return Err(format!("{} is {}, which places the {} px board outside the frame", field, offset, size));
+            }
+        }
+    }
+
+    for field in ["x_offset_pct", "y_offset_pct"] {
+        finite_field(data, field)?;
+    }
+
+    if let Some(timestamps) = data.get("timestamps").and_then(|v| v.as_array()) {
+        if timestamps.len() > max_moves {
+            return Err(format!(
+                "timestamps has {} moves, more than the maximum of {}",
+                timestamps.len(), max_moves
+            ));
+        }
+        for (i, value) in timestamps.iter().enumerate().filter(|(_, v)| !v.is_null()) {
+            finite_number(&format!("timestamps[{}]", i), value)?;
+        }
+    }
+
+    if let Some(frames) = timestamps_frames(data)? {
+        if data.get("timestamps").is_some_and(|v| !v.is_null()) {
+            return Err("give either timestamps or timestamps_frames, not both".to_string());
+        }
+        if frames.len() > max_moves {
+            return Err(format!(
+                "timestamps_frames has {} moves, more than the maximum of {}",
+                frames.len(), max_moves
+            ));
+        }
+    }
+    FrameRate::from_value(data)?;
+
+    if let Some(speeds) = data.get("speed").and_then(|v| v.as_array()) {
+        for (i, value) in speeds.iter().enumerate().filter(|(_, v)| !v.is_null()) {
+            let speed = finite_number(&format!("speed[{}]", i), value)?;
+            if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+                return Err(format!("speed[{}] must be between {} and {}, got {}", i, MIN_SPEED, MAX_SPEED, speed));
+            }
+        }
+    }
+
+    MoveRange::from_value(data)?;
+    SeekMode::from_value(data)?;
+    ResourceLimits::from_value(data)?;
+    OverlayCrop::from_value(data)?;
+    BackgroundTreatment::from_value(data)?;
+    BackgroundZoom::from_value(data)?;
+    BackgroundBehavior::from_value(data)?;
+    LayoutMode::from_value(data)?;
+    CompositeStrategy::from_value(data)?;
+    HwaccelDecode::from_value(data)?;
+    ZeroDurationPolicy::from_value(data)?;
+    letterbox_fill(data)?;
+    OutOfBounds::from_value(data)?;
+    XyOffset::from_value(data)?;
+    OverlayAnimation::from_value(data)?;
+    SegmentTransition::from_value(data)?;
+    ColorGrade::from_value(data)?;
+    BackgroundTransform::from_value(data)?;
+    HdrHandling::from_value(data)?;
+    GeneratedCanvas::from_value(data)?;
+    BackgroundClip::from_value(data)?;
+    background_audio(data)?;
+    for field in ["deinterlace", "force_cfr", "loop_background_clips", "auto_shrink", "ignore_duration_mismatch", "managed_output", "skip_hooks"] {
+        if let Some(value) = data.get(field).filter(|v| !v.is_null() && !v.is_boolean()) {
+            return Err(format!("{} must be true or false, got {}", field, value));
+        }
+    }
+    SideBySide::from_value(data)?;
+    if let Some(template) = data.get("output_template").filter(|v| !v.is_null()) {
+        let template = template.as_str().ok_or_else(|| format!("output_template must be a string, got {}", template))?;
+        check_template(template)?;
+    }
+    FilenameMode::from_value(data)?;
+    ExportHook::from_value(data, "pre_export_hook")?;
+    ExportHook::from_value(data, "post_export_hook")?;
+    if let Some(gap) = data.get("merge_gap_ms").filter(|v| !v.is_null()) {
+        if gap.as_u64().is_none() {
+            return Err(format!("merge_gap_ms must be a whole number of milliseconds, got {}", gap));
+        }
+    }
+    for field in ["render_timeout_secs", "ffmpeg_timeout_secs"] {
+        if let Some(secs) = data.get(field).filter(|v| !v.is_null()) {
+            if !secs.as_u64().is_some_and(|n| n >= 1) {
+                return Err(format!("{} must be a whole number of seconds, at least 1, got {}", field, secs));
+            }
+        }
+    }
+    if let Some(preview) = data.get("preview_moves").filter(|v| !v.is_null()) {
+        if !preview.as_u64().is_some_and(|n| n >= 1) {
+            return Err(format!("preview_moves must be a whole number of at least 1, got {}", preview));
+        }
+    }
+
+    if let Some(target) = finite_field(data, "target_size_mb")? {
+        if target <= 0.0 {
+            return Err(format!("target_size_mb must be greater than 0, got {}", target));
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_export_data(content: &str) -> Result<ImportedExport, String> {
+    // serde_json's message already carries the offending line and column
+    let value: Value = serde_json::from_str(content)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    let (mut value, migration) = migrate::migrate(value)?;
+    let mut warnings = Vec::new();
+    if migration.migrated() {
+        warnings.push(format!("Migrated the export data from schema version {} to {}", migration.from, migration.to));
+    }
+    warnings.extend(migration.changes);
+
+    let object = value.as_object_mut()
+        .ok_or("Export data must be a JSON object")?;
+    coerce_fields(object, &mut warnings);
+
+    let mut data: ExportData = serde_json::from_value(value)
+        .map_err(|e| format!("Invalid export data: {}", e))?;
+
+    let mut unknown: Vec<&String> = data.unknown.keys().collect();
+    unknown.sort();
+    for key in unknown {
+        warnings.push(format!("Dropped unknown field '{}'", key));
+    }
+    data.unknown.clear();
+
+    Ok(ImportedExport { data, warnings })
+}
+
+#[command]
+pub fn import_export_json(path: String) -> Result<ImportedExport, String> {
+    let path = Path::new(&path);
+
+    let metadata = fs::metadata(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if metadata.len() > MAX_IMPORT_BYTES {
+        return Err(format!(
+            "{} is {} bytes, which exceeds the {} byte limit for export files",
+            path.display(), metadata.len(), MAX_IMPORT_BYTES
+        ));
+    }
+
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let imported = parse_export_data(&content)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    log::info!("Imported {} with {} warnings", path.display(), imported.warnings.len());
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MAX_MOVES: usize = 500;
+
+    fn rejection(data: Value) -> String {
+        validate_export_data(&data, MAX_MOVES).expect_err("the payload should have been rejected")
+    }
+
+    #[test]
+    fn accepts_an_ordinary_payload() {
+        let data = json!({"timestamps": [1.0, 2.5, 4.0], "timePerMove": 0.5, "x_offset": 100, "y_offset": 50});
+        assert_eq!(validate_export_data(&data, MAX_MOVES), Ok(()));
+    }
+
+    // JSON has no NaN or Infinity, so they arrive as strings when the frontend stringifies them
+    #[test]
+    fn rejects_non_finite_timestamps() {
+        for bad in ["NaN", "Infinity", "-Infinity"] {
+            let message = rejection(json!({"timestamps": [1.0, bad, 4.0]}));
+            assert_eq!(message, format!("timestamps[1] must be a number, got \"{}\"", bad));
+        }
+    }
+
+    #[test]
+    fn rejects_time_per_move_out_of_range() {
+        for bad in [0.0, -0.5, 60.5] {
+            let message = rejection(json!({"timestamps": [1.0], "timePerMove": bad}));
+            assert_eq!(message, format!("timePerMove must be greater than 0 and at most 60 seconds, got {}", bad));
+        }
+        let message = rejection(json!({"timestamps": [1.0], "timePerMove": "NaN"}));
+        assert_eq!(message, "timePerMove must be a number, got \"NaN\"");
+        for ok in [0.2, 60.0] {
+            assert_eq!(validate_export_data(&json!({"timestamps": [1.0], "timePerMove": ok}), MAX_MOVES), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rejects_more_moves_than_the_maximum() {
+        let timestamps: Vec<f64> = (0..=MAX_MOVES).map(|i| i as f64).collect();
+        let message = rejection(json!({"timestamps": timestamps}));
+        assert_eq!(message, "timestamps has 501 moves, more than the maximum of 500");
+        assert_eq!(validate_export_data(&json!({"timestamps": &timestamps[..MAX_MOVES]}), MAX_MOVES), Ok(()));
+
+        let frames: Vec<u64> = (0..=MAX_MOVES as u64).collect();
+        let message = rejection(json!({"timestamps_frames": frames, "background_fps": 30}));
+        assert_eq!(message, "timestamps_frames has 501 moves, more than the maximum of 500");
+    }
+
+    #[test]
+    fn rejects_non_finite_and_off_frame_offsets() {
+        for field in ["x_offset", "y_offset", "x_offset_pct", "y_offset_pct"] {
+            let message = rejection(json!({"timestamps": [1.0], field: "Infinity"}));
+            assert_eq!(message, format!("{} must be a number, got \"Infinity\"", field));
+        }
+        let message = rejection(json!({"timestamps": [1.0], "boardSize": 400, "x_offset": -401}));
+        assert_eq!(message, "x_offset is -401, which places the 400 px board outside the frame");
+        assert_eq!(validate_export_data(&json!({"timestamps": [1.0], "boardSize": 400, "x_offset": -400}), MAX_MOVES), Ok(()));
+    }
+
+    #[test]
+    fn percentage_offsets_are_not_exported_with_pixel_offsets() {
+        let imported = parse_export_data(r#"{"timestamps": [1.0], "x_offset_pct": 0.25, "y_offset_pct": 0.5}"#).unwrap();
+        let exported = serde_json::to_value(&imported.data).unwrap();
+        assert_eq!(exported.get("x_offset"), None);
+        assert_eq!(exported.get("y_offset"), None);
+        assert_eq!(exported["x_offset_pct"], 0.25);
+        assert_eq!(exported["y_offset_pct"], 0.5);
+
+        let imported = parse_export_data(r#"{"timestamps": [1.0], "x_offset": 12, "y_offset": 0}"#).unwrap();
+        let exported = serde_json::to_value(&imported.data).unwrap();
+        assert_eq!((&exported["x_offset"], &exported["y_offset"]), (&json!(12.0), &json!(0.0)));
+        assert_eq!(exported.get("x_offset_pct"), None);
+    }
+
+    #[test]
+    fn frame_payloads_survive_a_round_trip() {
+        let imported = parse_export_data(r#"{"timestamps_frames": [100, 250], "background_fps": "30000/1001"}"#).unwrap();
+        let exported = serde_json::to_value(&imported.data).unwrap();
+        // An empty timestamps list would count as giving both
+        assert_eq!(exported.get("timestamps"), None);
+        assert_eq!(exported["timestamps_frames"], json!([100, 250]));
+        assert_eq!(validate_export_data(&exported, MAX_MOVES), Ok(()));
+    }
+
+    #[test]
+    fn each_output_needs_a_path_and_a_valid_resolution() {
+        let outputs = OutputSpec::from_value(&json!({"outputs": [
+            {"path": "/exports/game.WEBM"},
+            {"path": "/exports/game", "resolution": "1280x720"},
+            {"path": "/exports/game.out", "format": "matroska"},
+        ]}))
+        .unwrap();
+        assert_eq!(outputs.iter().map(OutputSpec::container).collect::<Vec<_>>(), ["webm", "mp4", "matroska"]);
+        assert_eq!(outputs[1].dimensions(), Some((1280, 720)));
+        assert_eq!(OutputSpec::from_value(&json!({})), Ok(Vec::new()));
+
+        let bad = |outputs: Value| OutputSpec::from_value(&json!({"outputs": outputs})).unwrap_err();
+        assert_eq!(bad(json!([{"path": "a.mp4"}, {"path": " "}])), "outputs[1] has an empty path");
+        assert_eq!(bad(json!([{"path": "a.mp4", "resolution": "720p"}])), "outputs[0] resolution must look like 1280x720, got '720p'");
+    }
+
+    #[test]
+    fn xy_offset_is_one_pair_or_one_pair_per_move() {
+        let data = |offset: Value| json!({"timestamps": [1.0, 2.5], "xy_offset": offset});
+        assert_eq!(XyOffset::from_value(&data(json!([100, 50]))), Ok(Some(XyOffset::Shared([100.0, 50.0]))));
+        assert_eq!(
+            XyOffset::from_value(&data(json!([[100, 50], [300, 50]]))),
+            Ok(Some(XyOffset::PerMove(vec![[100.0, 50.0], [300.0, 50.0]])))
+        );
+        assert_eq!(XyOffset::from_value(&data(Value::Null)), Ok(None));
+        assert_eq!(
+            XyOffset::from_value(&data(json!([[100, 50]]))).unwrap_err(),
+            "xy_offset has 1 positions but there are 2 moves; give one [x, y] per move or a single pair"
+        );
+        assert_eq!(
+            XyOffset::from_value(&data(json!({"x": 100}))).unwrap_err(),
+            "xy_offset must be [x, y] or a list with one [x, y] per move, got {\"x\":100}"
+        );
+    }
+
+    #[test]
+    fn the_generated_canvas_defaults_to_black_1080p_and_checks_its_colour_and_size() {
+        let canvas = |value: Value| GeneratedCanvas::from_value(&json!({"generated_canvas": value}));
+        let default = GeneratedCanvas { color: "black".to_string(), width: 1920, height: 1080 };
+        assert_eq!(GeneratedCanvas::from_value(&json!({})), Ok(default.clone()));
+        assert_eq!(canvas(Value::Null), Ok(default));
+        assert_eq!(
+            canvas(json!({"color": "#202020", "width": 1280})),
+            Ok(GeneratedCanvas { color: "#202020".to_string(), width: 1280, height: 1080 })
+        );
+        assert_eq!(
+            canvas(json!({"color": "red:s=1x1"})).unwrap_err(),
+            "generated_canvas color must be a colour such as black or #202020, got 'red:s=1x1'"
+        );
+        assert_eq!(canvas(json!({"width": 1281})).unwrap_err(), "generated_canvas width must be an even number from 16 to 7680, got 1281");
+        assert_eq!(canvas(json!({"height": 8})).unwrap_err(), "generated_canvas height must be an even number from 16 to 7680, got 8");
+    }
+
+    #[test]
+    fn hardware_decoding_is_off_unless_a_known_method_is_asked_for() {
+        let decode = |value: Value| HwaccelDecode::from_value(&json!({"hwaccel_decode": value}));
+        assert_eq!(HwaccelDecode::from_value(&json!({})), Ok(HwaccelDecode::Off));
+        assert_eq!(decode(json!("auto")), Ok(HwaccelDecode::Auto));
+        assert_eq!(decode(json!("d3d11va")), Ok(HwaccelDecode::D3d11va));
+        assert_eq!(
+            decode(json!("cuda")).unwrap_err(),
+            "hwaccel_decode must be \"auto\", \"off\", \"d3d11va\", \"videotoolbox\" or \"vaapi\", got \"cuda\""
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Manager, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+use crate::activity::{LogFilter, RecentLogs};
+use crate::escape::render_command_line;
+use crate::failure::{self, ExportFailure, FailureStage};
+use crate::filtergraph::FilterGraph;
+use crate::pause::PauseState;
+use crate::scheduler::Scheduler;
+
+// Only the most recent exports are kept around for debugging
+const MAX_RECORDS: usize = 20;
+// How long get_export_status still returns a finished export's result
+const RESULT_RETENTION: Duration = Duration::from_secs(600);
+const STATUS_LOG_LINES: usize = 5;
+
+#[derive(Debug, Clone)]
+struct Outcome {
+    // "completed", "failed" or "rejected", as in the history, or "cancelled"
+    status: String,
+    result: Option<Value>,
+    error: Option<ExportFailure>,
+    finished: Instant,
+    finished_at: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportRecord {
+    pub id: String,
+    pub ffmpeg_args: Vec<String>,
+    pub filter_graph: Option<FilterGraph>,
+    started: Instant,
+    // "starting" until the first progress report, then "render" or "composite"
+    stage: &'static str,
+    percent: f64,
+    eta_secs: Option<f64>,
+    outcome: Option<Outcome>,
+}
+
+impl ExportRecord {
+    fn new(id: String) -> Self {
+        ExportRecord {
+            id,
+            ffmpeg_args: Vec::new(),
+            filter_graph: None,
+            started: Instant::now(),
+            stage: "starting",
+            percent: 0.0,
+            eta_secs: None,
+            outcome: None,
+        }
+    }
+}
+
+// What get_export_status reports for one export
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportStatus {
+    pub export_id: String,
+    // "queued", "running", "paused", or how the export ended
+    pub state: String,
+    pub stage: &'static str,
+    pub percent: f64,
+    pub elapsed_secs: f64,
+    pub eta_secs: Option<f64>,
+    // Oldest first
+    pub recent_logs: Vec<String>,
+    // 1-based, while the export waits for the scheduler to start it
+    pub queue_position: Option<usize>,
+    // Both dropped RESULT_RETENTION after the export finished
+    pub result: Option<Value>,
+    pub error: Option<ExportFailure>,
+    pub finished_at: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct ExportRegistry {
+    records: Mutex<Vec<ExportRecord>>,
+    // Exports started so far; the records are capped, so their count can repeat within a millisecond
+    started: AtomicUsize,
+}
+
+impl ExportRegistry {
+    pub fn start_export(&self) -> String {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let id = format!("{}-{}", millis, self.started.fetch_add(1, Ordering::Relaxed));
+        let mut records = self.records.lock().unwrap();
+
+        records.push(ExportRecord::new(id.clone()));
+        if records.len() > MAX_RECORDS {
+            records.remove(0);
+        }
+        id
+    }
+
+    // A resumed export runs again under its old id, which a restart may have forgotten
+    pub fn resume_export(&self, id: &str) {
+        let mut records = self.records.lock().unwrap();
+        records.retain(|r| r.id != id);
+        records.push(ExportRecord::new(id.to_string()));
+        if records.len() > MAX_RECORDS {
+            records.remove(0);
+        }
+    }
+
+    pub fn record_progress(&self, id: &str, stage: &'static str, percent: f64, eta_secs: Option<f64>) {
+        let mut records = self.records.lock().unwrap();
+        if let Some(record) = records.iter_mut().find(|r| r.id == id && r.outcome.is_none()) {
+            record.stage = stage;
+            record.percent = percent;
+            record.eta_secs = eta_secs;
+        }
+    }
+
+    pub fn record_finish(&self, id: &str, status: &str, result: &Result<String, ExportFailure>) {
+        // Parsed before the lock, which a poll every second shouldn't wait on
+        let value = result.as_deref().ok().and_then(|r| serde_json::from_str::<Value>(r).ok());
+        let mut records = self.records.lock().unwrap();
+        if let Some(record) = records.iter_mut().find(|r| r.id == id) {
+            if status == "completed" {
+                record.percent = 100.0;
+            }
+            record.eta_secs = None;
+            record.outcome = Some(Outcome {
+                status: status.to_string(),
+                result: value,
+                error: result.as_ref().err().cloned(),
+                finished: Instant::now(),
+                finished_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            });
+        }
+    }
+
+    pub fn is_running(&self, id: &str) -> bool {
+        self.records.lock().unwrap().iter().any(|r| r.id == id && r.outcome.is_none())
+    }
+
+    // For an export that ended without recording how; it's put down to the stage it was in
+    fn abandon(&self, id: &str) -> Option<ExportFailure> {
+        self.end_early(id, "failed", "export_abandoned", "The export stopped before it finished")
+    }
+
+    // For an export stopped by cancel_export
+    pub fn cancel(&self, id: &str) -> Option<ExportFailure> {
+        self.end_early(id, "cancelled", "export_cancelled", "The export was cancelled")
+    }
+
+    fn end_early(&self, id: &str, status: &str, code: &'static str, message: &str) -> Option<ExportFailure> {
+        let stage = self.records.lock().unwrap().iter().find(|r| r.id == id && r.outcome.is_none()).map(|r| r.stage)?;
+        let stage = match stage {
+            "render" => FailureStage::Render,
+            "composite" => FailureStage::Composite,
+            _ => FailureStage::WriteProps,
+        };
+        let failure = ExportFailure::new(stage, code, message.to_string()).for_export(id);
+        self.record_finish(id, status, &Err(failure.clone()));
+        Some(failure)
+    }
+
+    // Every known export, or just `id`, newest first
+    pub fn status(&self, id: Option<&str>) -> Vec<ExportStatus> {
+        let records = self.records.lock().unwrap();
+        records
+            .iter()
+            .rev()
+            .filter(|r| id.map_or(true, |id| r.id == id))
+            .map(|record| {
+                let outcome = record.outcome.as_ref();
+                let elapsed = outcome.map_or(Instant::now(), |o| o.finished) - record.started;
+                let kept = outcome.filter(|o| o.finished.elapsed() <= RESULT_RETENTION);
+                ExportStatus {
+                    export_id: record.id.clone(),
+                    state: outcome.map_or("running".to_string(), |o| o.status.clone()),
+                    stage: record.stage,
+                    percent: record.percent,
+                    elapsed_secs: elapsed.as_secs_f64(),
+                    eta_secs: record.eta_secs,
+                    recent_logs: Vec::new(),
+                    queue_position: None,
+                    result: kept.and_then(|o| o.result.clone()),
+                    error: kept.and_then(|o| o.error.clone()),
+                    finished_at: outcome.map(|o| o.finished_at),
+                }
+            })
+            .collect()
+    }
+
+    pub fn record_ffmpeg_args(&self, id: &str, args: &[String]) {
+        let mut records = self.records.lock().unwrap();
+        if let Some(record) = records.iter_mut().find(|r| r.id == id) {
+            record.ffmpeg_args = args.to_vec();
+        }
+    }
+
+    pub fn record_filter_graph(&self, id: &str, graph: &FilterGraph) {
+        let mut records = self.records.lock().unwrap();
+        if let Some(record) = records.iter_mut().find(|r| r.id == id) {
+            record.filter_graph = Some(graph.clone());
+        }
+    }
+
+    pub fn get(&self, id: Option<&str>) -> Option<ExportRecord> {
+        let records = self.records.lock().unwrap();
+        match id {
+            Some(id) => records.iter().find(|r| r.id == id).cloned(),
+            None => records.iter().rev().find(|r| !r.ffmpeg_args.is_empty()).cloned(),
+        }
+    }
+}
+
+// Marks the export failed when it ends, or is dropped, without recording a result
+pub struct ExportTracking {
+    app: AppHandle,
+    id: String,
+}
+
+impl ExportTracking {
+    pub fn new(app: &AppHandle, id: &str) -> Self {
+        ExportTracking { app: app.clone(), id: id.to_string() }
+    }
+}
+
+impl Drop for ExportTracking {
+    fn drop(&mut self) {
+        if let Some(failure) = self.app.state::<ExportRegistry>().abandon(&self.id) {
+            failure::emit(&self.app, &failure);
+        }
+        self.app.state::<PauseState>().forget(&self.id);
+    }
+}
+
+// The sidecar is bundled as ffmpeg-<target triple>, which means nothing outside this install
+fn portable_arg(arg: &str) -> &str {
+    let sidecar_name = concat!("ffmpeg-", env!("TAURI_ENV_TARGET_TRIPLE"));
+    let is_sidecar = Path::new(arg)
+        .file_stem()
+        .map(|stem| stem == sidecar_name)
+        .unwrap_or(false);
+    if is_sidecar { "ffmpeg" } else { arg }
+}
+
+#[command]
+pub fn copy_ffmpeg_command(
+    app: AppHandle,
+    registry: State<'_, ExportRegistry>,
+    export_id: Option<String>,
+) -> Result<String, String> {
+    let record = registry.get(export_id.as_deref()).ok_or_else(|| match &export_id {
+        Some(id) => format!("No FFmpeg command recorded for export {}", id),
+        None => "No export has generated an FFmpeg command yet".to_string(),
+    })?;
+
+    let args: Vec<String> = record.ffmpeg_args
+        .iter()
+        .map(|a| portable_arg(a).to_string())
+        .collect();
+    let command_line = render_command_line("ffmpeg", &args);
+
+    app.clipboard()
+        .write_text(command_line.clone())
+        .map_err(|e| format!("Failed to copy to clipboard: {}", e))?;
+
+    log::info!("Copied FFmpeg command for export {} to clipboard", record.id);
+    Ok(command_line)
+}
+
+// The composite filter graph as nodes and edges, the same one copy_ffmpeg_command's command runs
+#[command]
+pub fn get_export_filter_graph(
+    registry: State<'_, ExportRegistry>,
+    export_id: Option<String>,
+) -> Result<FilterGraph, String> {
+    let record = registry.get(export_id.as_deref()).ok_or_else(|| match &export_id {
+        Some(id) => format!("No FFmpeg command recorded for export {}", id),
+        None => "No export has generated an FFmpeg command yet".to_string(),
+    })?;
+    record.filter_graph.ok_or_else(|| format!("Export {} has no filter graph", record.id))
+}
+
+// A running export that still waits for a slot is queued; one with its processes stopped is paused
+fn waiting_state(status: &mut ExportStatus, queue_position: Option<usize>, paused: bool) {
+    status.queue_position = queue_position;
+    if queue_position.is_some() {
+        status.state = "queued".to_string();
+    } else if paused {
+        status.state = "paused".to_string();
+    }
+}
+
+// What a running export is doing, or how it ended, for a frontend that lost its progress events
+// (a reloaded webview, say). Every known export without an id, newest first. Cheap enough to poll.
+#[command]
+pub fn get_export_status(
+    registry: State<'_, ExportRegistry>,
+    logs: State<'_, RecentLogs>,
+    pauses: State<'_, PauseState>,
+    scheduler: State<'_, Scheduler>,
+    export_id: Option<String>,
+) -> Result<Vec<ExportStatus>, String> {
+    let mut statuses = registry.status(export_id.as_deref());
+    if let (Some(id), true) = (&export_id, statuses.is_empty()) {
+        return Err(format!("Export {} is unknown or too old to have a status", id));
+    }
+    // After the registry's lock is let go; the log buffer, pauses and scheduler have their own
+    for status in &mut statuses {
+        if status.state == "running" {
+            let queue_position = scheduler.queue_position(&status.export_id);
+            waiting_state(status, queue_position, pauses.is_paused(&status.export_id));
+        }
+        let filter = LogFilter { job_id: Some(status.export_id.clone()), ..Default::default() };
+        status.recent_logs = logs.query(&filter, STATUS_LOG_LINES, None).into_iter().rev().map(|e| e.message).collect();
+    }
+    Ok(statuses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_running_export_reports_its_stage_and_progress() {
+        let registry = ExportRegistry::default();
+        let id = registry.start_export();
+        let status = &registry.status(Some(&id))[0];
+        assert_eq!((status.state.as_str(), status.stage, status.percent, status.eta_secs), ("running", "starting", 0.0, None));
+
+        registry.record_progress(&id, "render", 42.5, Some(12.0));
+        let status = &registry.status(Some(&id))[0];
+        assert_eq!((status.state.as_str(), status.stage, status.percent, status.eta_secs), ("running", "render", 42.5, Some(12.0)));
+        assert_eq!((status.result.clone(), status.finished_at), (None, None));
+        assert!(registry.is_running(&id));
+        assert!(registry.status(Some("1-0")).is_empty());
+    }
+
+    #[test]
+    fn every_known_export_is_listed_newest_first() {
+        let registry = ExportRegistry::default();
+        let ids: Vec<String> = (0..MAX_RECORDS + 2).map(|_| registry.start_export()).collect();
+        let listed: Vec<String> = registry.status(None).into_iter().map(|s| s.export_id).collect();
+        // The two oldest were dropped to keep MAX_RECORDS
+        let expected: Vec<String> = ids[2..].iter().rev().cloned().collect();
+        assert_eq!(listed, expected);
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn a_completed_export_keeps_its_result_for_a_while() {
+        let registry = ExportRegistry::default();
+        let id = registry.start_export();
+        registry.record_progress(&id, "composite", 90.0, Some(3.0));
+        registry.record_finish(&id, "completed", &Ok(r#"{"outputPath":"/exports/game.mp4"}"#.to_string()));
+        // Progress reported after the end is ignored
+        registry.record_progress(&id, "composite", 10.0, Some(30.0));
+
+        let status = registry.status(Some(&id)).remove(0);
+        assert_eq!((status.state.as_str(), status.stage, status.percent, status.eta_secs), ("completed", "composite", 100.0, None));
+        assert_eq!(status.result, Some(serde_json::json!({"outputPath": "/exports/game.mp4"})));
+        assert!(status.finished_at.is_some());
+
+        // Past RESULT_RETENTION only how it ended is left
+        let long_ago = Instant::now().checked_sub(RESULT_RETENTION + Duration::from_secs(1)).unwrap();
+        registry.records.lock().unwrap()[0].outcome.as_mut().unwrap().finished = long_ago;
+        let status = registry.status(Some(&id)).remove(0);
+        assert_eq!((status.state.as_str(), status.result), ("completed", None));
+        assert!(status.error.is_none());
+        assert!(status.finished_at.is_some());
+    }
+
+    #[test]
+    fn a_running_export_is_queued_or_paused_while_it_waits() {
+        let registry = ExportRegistry::default();
+        let id = registry.start_export();
+        let running = || registry.status(Some(&id)).remove(0);
+
+        let mut status = running();
+        waiting_state(&mut status, Some(2), false);
+        assert_eq!((status.state.as_str(), status.queue_position), ("queued", Some(2)));
+
+        let mut status = running();
+        waiting_state(&mut status, None, true);
+        assert_eq!((status.state.as_str(), status.queue_position), ("paused", None));
+
+        let mut status = running();
+        waiting_state(&mut status, None, false);
+        assert_eq!((status.state.as_str(), status.queue_position), ("running", None));
+    }
+
+    #[test]
+    fn an_abandoned_export_fails_at_the_stage_it_was_in() {
+        let registry = ExportRegistry::default();
+        for (stage, expected) in [("starting", FailureStage::WriteProps), ("render", FailureStage::Render), ("composite", FailureStage::Composite)] {
+            let id = registry.start_export();
+            if stage != "starting" {
+                registry.record_progress(&id, stage, 40.0, None);
+            }
+            let failure = registry.abandon(&id).unwrap();
+            assert_eq!((failure.stage, failure.code, failure.export_id.as_deref()), (expected, "export_abandoned", Some(id.as_str())));
+
+            let status = registry.status(Some(&id)).remove(0);
+            assert_eq!(status.state, "failed");
+            assert_eq!(status.error.map(|e| e.stage), Some(expected));
+            // Only an export still running can be abandoned
+            assert!(registry.abandon(&id).is_none());
+        }
+    }
+
+    #[test]
+    fn a_finished_export_reports_its_failure() {
+        let registry = ExportRegistry::default();
+        let id = registry.start_export();
+        let failure = ExportFailure::new(FailureStage::Verify, "animation_duration_mismatch", "Too short".to_string()).for_export(&id);
+        registry.record_finish(&id, "failed", &Err(failure));
+        let status = &registry.status(None)[0];
+        let error = status.error.as_ref().unwrap();
+        assert_eq!((error.stage, error.code), (FailureStage::Verify, "animation_duration_mismatch"));
+        assert_eq!(status.result, None);
+        assert!(!registry.is_running(&id));
+    }
+}
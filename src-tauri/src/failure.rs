@@ -0,0 +1,155 @@
+use serde::Serialize;
+use std::fmt;
+use tauri::{AppHandle, Emitter};
+
+// The step of the export pipeline a failure happened in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureStage {
+    // The payload, the paths and everything else checked before anything renders
+    Validation,
+    // The working directory and the export.json the render reads
+    WriteProps,
+    Render,
+    // Reading the background, the extra layers and the rendered animation
+    Probe,
+    Composite,
+    // Checks on what was rendered
+    Verify,
+    // Size fitting, the extra outputs and copying to the destinations
+    PostProcess,
+}
+
+impl FailureStage {
+    // The code of a failure nothing more specific is known about
+    pub fn code(self) -> &'static str {
+        match self {
+            FailureStage::Validation => "invalid_export_data",
+            FailureStage::WriteProps => "write_props_failed",
+            FailureStage::Render => "render_failed",
+            FailureStage::Probe => "probe_failed",
+            FailureStage::Composite => "composite_failed",
+            FailureStage::Verify => "verify_failed",
+            FailureStage::PostProcess => "post_process_failed",
+        }
+    }
+}
+
+// Why an export failed, as the export command rejects with and export-failed carries. `code` is
+// stable like a warning's, so the frontend can offer help for it; `message` is the English text.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportFailure {
+    // Unset when the export failed before it got an id
+    pub export_id: Option<String>,
+    pub stage: FailureStage,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ExportFailure {
+    pub fn new(stage: FailureStage, code: &'static str, message: String) -> Self {
+        ExportFailure { export_id: None, stage, code, message }
+    }
+
+    pub fn for_export(mut self, export_id: &str) -> Self {
+        self.export_id.get_or_insert_with(|| export_id.to_string());
+        self
+    }
+
+    pub fn map_message(mut self, change: impl FnOnce(String) -> String) -> Self {
+        self.message = change(self.message);
+        self
+    }
+}
+
+impl fmt::Display for ExportFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// Tags a step's error with the stage it failed in, at the point of failure
+pub trait AtStage<T> {
+    fn at(self, stage: FailureStage) -> Result<T, ExportFailure>;
+    fn at_code(self, stage: FailureStage, code: &'static str) -> Result<T, ExportFailure>;
+}
+
+impl<T> AtStage<T> for Result<T, String> {
+    fn at(self, stage: FailureStage) -> Result<T, ExportFailure> {
+        self.at_code(stage, stage.code())
+    }
+
+    fn at_code(self, stage: FailureStage, code: &'static str) -> Result<T, ExportFailure> {
+        self.map_err(|message| ExportFailure::new(stage, code, message))
+    }
+}
+
+// Every failed export is announced, as the frontend that shows it may not be the one that started it
+pub fn emit(app: &AppHandle, failure: &ExportFailure) {
+    log::error!("Export {} failed at {:?} ({}): {}", failure.export_id.as_deref().unwrap_or("-"), failure.stage, failure.code, failure.message);
+    let _ = app.emit("export-failed", failure);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+
+    const STAGES: [FailureStage; 7] = [
+        FailureStage::Validation,
+        FailureStage::WriteProps,
+        FailureStage::Render,
+        FailureStage::Probe,
+        FailureStage::Composite,
+        FailureStage::Verify,
+        FailureStage::PostProcess,
+    ];
+
+    #[test]
+    fn every_stage_has_a_name_and_a_code_of_its_own() {
+        let names: Vec<Value> = STAGES.iter().map(|s| serde_json::to_value(s).unwrap()).collect();
+        assert_eq!(names, ["validation", "write_props", "render", "probe", "composite", "verify", "post_process"]);
+        let mut codes: Vec<&str> = STAGES.iter().map(|s| s.code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), STAGES.len());
+    }
+
+    #[test]
+    fn errors_are_tagged_with_the_stage_they_failed_in() {
+        for stage in STAGES {
+            let failure = Err::<(), _>("it broke".to_string()).at(stage).unwrap_err();
+            assert_eq!((failure.stage, failure.code, failure.message.as_str()), (stage, stage.code(), "it broke"));
+        }
+        let failure = Err::<(), _>("No space left".to_string()).at_code(FailureStage::PostProcess, "delivery_failed").unwrap_err();
+        assert_eq!((failure.stage, failure.code), (FailureStage::PostProcess, "delivery_failed"));
+        assert_eq!(Ok::<_, String>(3).at(FailureStage::Render).unwrap(), 3);
+    }
+
+    #[test]
+    fn a_failure_keeps_the_first_export_id_it_is_given() {
+        let failure = ExportFailure::new(FailureStage::Render, "render_failed", "Chromium crashed".to_string())
+            .for_export("1700000000000-0")
+            .for_export("1700000000000-1")
+            .map_message(|m| format!("Rendering failed: {}", m));
+        assert_eq!(failure.export_id.as_deref(), Some("1700000000000-0"));
+        assert_eq!(failure.to_string(), "Rendering failed: Chromium crashed");
+        assert_eq!(failure.stage, FailureStage::Render);
+    }
+
+    #[test]
+    fn the_rejection_and_the_event_carry_the_stage_and_code() {
+        let failure = ExportFailure::new(FailureStage::WriteProps, "props_write_failed", "Disk full".to_string());
+        assert_eq!(
+            serde_json::to_value(&failure).unwrap(),
+            json!({"export_id": null, "stage": "write_props", "code": "props_write_failed", "message": "Disk full"})
+        );
+        let failure = failure.for_export("1700000000000-0");
+        assert_eq!(serde_json::to_value(&failure).unwrap()["export_id"], "1700000000000-0");
+
+        let body = crate::webhook::body("1700000000000-0", "failed", &BTreeMap::new(), &Err(failure));
+        assert_eq!(body["event"], "export.failed");
+        assert_eq!((&body["error_stage"], &body["error_code"], &body["error"]), (&json!("write_props"), &json!("props_write_failed"), &json!("Disk full")));
+    }
+}
@@ -0,0 +1,430 @@
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{command, AppHandle, Manager};
+use tauri_plugin_shell::process::Command;
+use tauri_plugin_shell::ShellExt;
+
+use crate::paths::{for_child_process, path_arg};
+use crate::settings::SettingsState;
+use crate::timings;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FfmpegSource {
+    Sidecar,
+    Settings,
+    Path,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedFfmpeg {
+    pub source: FfmpegSource,
+    // None for the sidecar, which the shell plugin locates itself
+    pub path: Option<String>,
+    pub version: String,
+}
+
+impl ResolvedFfmpeg {
+    pub fn describe(&self) -> String {
+        match &self.path {
+            Some(path) => format!("{} ({:?})", path, self.source),
+            None => "bundled sidecar".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind")]
+pub enum FfmpegError {
+    FfmpegNotFound { tried: Vec<String> },
+}
+
+impl fmt::Display for FfmpegError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FfmpegError::FfmpegNotFound { tried } => write!(
+                f,
+                "FFmpeg not found. Install ffmpeg or set its path in the settings. Tried:\n  {}",
+                tried.join("\n  ")
+            ),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FfmpegResolver {
+    cached: Mutex<Option<ResolvedFfmpeg>>,
+}
+
+impl FfmpegResolver {
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+fn find_on_path() -> Option<PathBuf> {
+    let binary = if cfg!(target_os = "windows") { "ffmpeg.exe" } else { "ffmpeg" };
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|dir| dir.join(binary))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+async fn probe_version(command: Command) -> Result<String, String> {
+    let output = command
+        .args(["-version"])
+        .output()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        Ok(stdout.lines().next().unwrap_or("").trim().to_string())
+    } else {
+        Err(format!("exited with {:?}", output.status.code()))
+    }
+}
+
+// Resolution order: bundled sidecar, then the path from settings, then whatever is on PATH
+pub async fn resolve_ffmpeg(app: &AppHandle) -> Result<ResolvedFfmpeg, FfmpegError> {
+    let resolver = app.state::<FfmpegResolver>();
+    if let Some(resolved) = resolver.cached.lock().unwrap().clone() {
+        return Ok(resolved);
+    }
+
+    let mut tried = Vec::new();
+
+    match app.shell().sidecar("ffmpeg") {
+        Ok(command) => match probe_version(command).await {
+            Ok(version) => {
+                return Ok(cache(app, ResolvedFfmpeg { source: FfmpegSource::Sidecar, path: None, version }));
+            }
+            Err(e) => tried.push(format!("bundled sidecar: {}", e)),
+        },
+        Err(e) => tried.push(format!("bundled sidecar: {}", e)),
+    }
+
+    let mut candidates = Vec::new();
+    match app.state::<SettingsState>().get().ffmpeg_path {
+        Some(path) => candidates.push((FfmpegSource::Settings, path)),
+        None => tried.push("settings: no ffmpeg_path configured".to_string()),
+    }
+    match find_on_path() {
+        Some(path) => candidates.push((FfmpegSource::Path, path.to_string_lossy().to_string())),
+        None => tried.push("PATH: no ffmpeg executable found".to_string()),
+    }
+
+    for (source, path) in candidates {
+        match probe_version(app.shell().command(&path)).await {
+            Ok(version) => {
+                return Ok(cache(app, ResolvedFfmpeg { source, path: Some(path), version }));
+            }
+            Err(e) => tried.push(format!("{}: {}", path, e)),
+        }
+    }
+
+    Err(FfmpegError::FfmpegNotFound { tried })
+}
+
+fn cache(app: &AppHandle, resolved: ResolvedFfmpeg) -> ResolvedFfmpeg {
+    log::info!("Using FFmpeg: {} - {}", resolved.describe(), resolved.version);
+    *app.state::<FfmpegResolver>().cached.lock().unwrap() = Some(resolved.clone());
+    resolved
+}
+
+pub fn ffmpeg_command(app: &AppHandle, resolved: &ResolvedFfmpeg) -> Result<Command, String> {
+    match &resolved.path {
+        Some(path) => Ok(app.shell().command(path)),
+        None => app.shell().sidecar("ffmpeg")
+            .map_err(|e| format!("Failed to create FFmpeg sidecar command: {}", e)),
+    }
+}
+
+// Reads the first video stream's "WIDTHxHEIGHT" from ffmpeg's input banner
+fn parse_video_size(stderr: &str) -> Option<(u32, u32)> {
+    let line = stderr.lines().find(|l| l.contains("Stream #") && l.contains("Video:"))?;
+    line.split([',', ' '])
+        .filter_map(|token| token.split_once('x'))
+        .find_map(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+}
+
+// Reads the "29.97 fps" token from the first video stream
+fn parse_video_fps(stderr: &str) -> Option<f64> {
+    let line = stderr.lines().find(|l| l.contains("Stream #") && l.contains("Video:"))?;
+    line.split(',')
+        .map(str::trim)
+        .find_map(|token| token.strip_suffix(" fps"))
+        .and_then(|fps| fps.trim().parse().ok())
+}
+
+// Field order from the pixel format details, e.g. "yuv420p(tv, bt709, top first)"; None when ffmpeg doesn't say
+fn parse_interlaced(stderr: &str) -> Option<bool> {
+    let line = stderr.lines().find(|l| l.contains("Stream #") && l.contains("Video:"))?;
+    if ["top first", "bottom first", "top coded first", "bottom coded first"].iter().any(|order| line.contains(order)) {
+        return Some(true);
+    }
+    line.contains("progressive").then_some(false)
+}
+
+// Clockwise rotation players apply to the first video stream, from the display matrix side data
+// ("displaymatrix: rotation of -90.00 degrees", counter-clockwise) or an older "rotate : 90" tag
+fn parse_rotation(stderr: &str) -> u32 {
+    let mut lines = stderr.lines().skip_while(|l| !(l.contains("Stream #") && l.contains("Video:")));
+    let _ = lines.next();
+    let degrees = lines
+        .take_while(|l| !l.trim_start().starts_with("Stream #"))
+        .find_map(|line| {
+            if let Some((_, rest)) = line.split_once("rotation of ") {
+                let value: f64 = rest.split_whitespace().next()?.parse().ok()?;
+                return Some(-value);
+            }
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == "rotate").then(|| value.trim().parse::<f64>().ok()).flatten()
+        })
+        .unwrap_or(0.0);
+    // Only quarter turns are meaningful for video
+    ((degrees / 90.0).round() as i64).rem_euclid(4) as u32 * 90
+}
+
+// Image demuxers show up as "png_pipe", "jpeg_pipe" or "image2" in "Input #0, png_pipe, from '...'",
+// whatever the file's extension says
+fn parse_still_image(stderr: &str) -> bool {
+    stderr
+        .lines()
+        .find_map(|line| line.trim_start().strip_prefix("Input #0, "))
+        .and_then(|rest| rest.split(", from").next())
+        .is_some_and(|formats| formats.split(',').any(|f| f == "image2" || f.ends_with("_pipe")))
+}
+
+// HDR transfer functions, as named in ffmpeg's colour details
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HdrTransfer {
+    // smpte2084, used by HDR10
+    Pq,
+    // arib-std-b67, used by broadcast and most console captures
+    Hlg,
+}
+
+impl HdrTransfer {
+    pub fn ffmpeg_name(self) -> &'static str {
+        match self {
+            HdrTransfer::Pq => "smpte2084",
+            HdrTransfer::Hlg => "arib-std-b67",
+        }
+    }
+}
+
+// From the pixel format details, e.g. "yuv420p10le(tv, bt2020nc/bt2020/smpte2084)"
+fn parse_hdr_transfer(stderr: &str) -> Option<HdrTransfer> {
+    let line = stderr.lines().find(|l| l.contains("Stream #") && l.contains("Video:"))?;
+    [HdrTransfer::Pq, HdrTransfer::Hlg].into_iter().find(|t| line.contains(t.ffmpeg_name()))
+}
+
+// Reads the "30 tbr" token, ffmpeg's guess at the nominal frame rate; huge values are timebases, not rates
+fn parse_video_tbr(stderr: &str) -> Option<f64> {
+    let line = stderr.lines().find(|l| l.contains("Stream #") && l.contains("Video:"))?;
+    line.split(',')
+        .map(str::trim)
+        .find_map(|token| token.strip_suffix(" tbr"))
+        .and_then(|tbr| tbr.trim().parse::<f64>().ok())
+        .filter(|&tbr| tbr > 0.0 && tbr <= 1000.0)
+}
+
+// An average rate more than 1% off the nominal one means the frames aren't evenly spaced
+fn variable_frame_rate(average: Option<f64>, nominal: Option<f64>) -> bool {
+    match (average, nominal) {
+        (Some(average), Some(nominal)) => (average - nominal).abs() / nominal > 0.01,
+        _ => false,
+    }
+}
+
+// Parses "Duration: 00:01:23.45" from ffmpeg's input banner
+pub fn parse_duration_line(line: &str) -> Option<f64> {
+    let start = line.find("Duration: ")? + "Duration: ".len();
+    let timestamp = line[start..].split(',').next()?.trim();
+    let mut parts = timestamp.split(':').map(|p| p.parse::<f64>().ok());
+    let (h, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(h * 3600.0 + m * 60.0 + s)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct VideoProbe {
+    pub width: u32,
+    pub height: u32,
+    pub duration_secs: Option<f64>,
+    pub has_audio: bool,
+    pub fps: Option<f64>,
+    pub interlaced: Option<bool>,
+    // Clockwise degrees from the container's rotation metadata; width and height are before it is applied
+    pub rotation: u32,
+    // Nominal frame rate (ffmpeg's tbr)
+    pub base_fps: Option<f64>,
+    // The average rate is off from the nominal one, as with OBS and phone recordings
+    pub variable_frame_rate: bool,
+    pub hdr_transfer: Option<HdrTransfer>,
+    // A single picture rather than a video
+    pub still_image: bool,
+}
+
+impl VideoProbe {
+    // The rate a variable-rate source is normalised to
+    pub fn nominal_fps(&self) -> Option<f64> {
+        self.base_fps.or(self.fps)
+    }
+}
+
+// ffmpeg's description of an input file, as printed to stderr
+async fn input_banner(app: &AppHandle, path: &std::path::Path) -> Result<String, String> {
+    let _probing = timings::span("probes");
+    let resolved = resolve_ffmpeg(app).await.map_err(|e| e.to_string())?;
+    // Without an output ffmpeg exits non-zero after printing the input info, which is all that's needed
+    let output = ffmpeg_command(app, &resolved)?
+        .args(["-hide_banner", "-i"])
+        .arg(for_child_process(&path_arg(path)?))
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    Ok(String::from_utf8_lossy(&output.stderr).to_string())
+}
+
+pub async fn probe_video(app: &AppHandle, path: &std::path::Path) -> Result<VideoProbe, String> {
+    let stderr = input_banner(app, path).await?;
+    let (width, height) = parse_video_size(&stderr)
+        .ok_or_else(|| format!("No video stream found in {}", path.display()))?;
+    let fps = parse_video_fps(&stderr);
+    let base_fps = parse_video_tbr(&stderr);
+    Ok(VideoProbe {
+        width,
+        height,
+        duration_secs: stderr.lines().find_map(parse_duration_line),
+        has_audio: stderr.lines().any(|line| line.trim_start().starts_with("Stream #") && line.contains(": Audio:")),
+        fps,
+        interlaced: parse_interlaced(&stderr),
+        rotation: parse_rotation(&stderr),
+        base_fps,
+        variable_frame_rate: variable_frame_rate(fps, base_fps),
+        hdr_transfer: parse_hdr_transfer(&stderr),
+        still_image: parse_still_image(&stderr),
+    })
+}
+
+// Reads a container-level tag such as "title" from the metadata block ffmpeg prints before the streams
+fn parse_metadata_tag(stderr: &str, key: &str) -> Option<String> {
+    stderr
+        .lines()
+        .take_while(|line| !line.trim_start().starts_with("Stream #"))
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            (name.trim() == key).then(|| value.trim().to_string())
+        })
+}
+
+pub async fn probe_metadata_tag(app: &AppHandle, path: &std::path::Path, key: &str) -> Result<Option<String>, String> {
+    input_banner(app, path).await.map(|stderr| parse_metadata_tag(&stderr, key))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioStream {
+    pub codec: String,
+    pub sample_rate: Option<u32>,
+    // Channel layout as ffmpeg names it, e.g. "stereo" or "5.1"
+    pub channels: Option<String>,
+}
+
+// Parses "Stream #0:1(und): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s"
+fn parse_audio_stream(stderr: &str) -> Option<AudioStream> {
+    let line = stderr.lines().find(|l| l.trim_start().starts_with("Stream #") && l.contains(": Audio:"))?;
+    let details = &line[line.find(": Audio:")? + ": Audio:".len()..];
+    let mut fields = details.split(',').map(str::trim);
+    let codec = fields.next()?.split_whitespace().next().unwrap_or_default().to_string();
+    let sample_rate = fields.next().and_then(|f| f.strip_suffix(" Hz")).and_then(|f| f.parse().ok());
+    let channels = fields.next().filter(|f| !f.is_empty()).map(String::from);
+    Some(AudioStream { codec, sample_rate, channels })
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioProbe {
+    pub stream: Option<AudioStream>,
+    pub duration_secs: Option<f64>,
+}
+
+pub async fn probe_audio(app: &AppHandle, path: &std::path::Path) -> Result<AudioProbe, String> {
+    let stderr = input_banner(app, path).await?;
+    Ok(AudioProbe {
+        stream: parse_audio_stream(&stderr),
+        duration_secs: stderr.lines().find_map(parse_duration_line),
+    })
+}
+
+// Lets the frontend warn about variable frame rates and rotation before an export
+#[command]
+pub async fn get_video_metadata(app: AppHandle, path: String) -> Result<VideoProbe, String> {
+    probe_video(&app, std::path::Path::new(&path)).await
+}
+
+pub async fn probe_video_size(app: &AppHandle, path: &std::path::Path) -> Result<(u32, u32), String> {
+    probe_video(app, path).await.map(|probe| (probe.width, probe.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BANNER: &str = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'output.mp4':
+  Metadata:
+    major_brand     : isom
+    title           : Carlsen vs Nakamura: game 2
+    encoder         : Lavf60.16.100
+  Duration: 00:00:10.00, start: 0.000000, bitrate: 2000 kb/s
+  Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p, 1920x1080, 30 fps
+    Metadata:
+      title           : stream title
+";
+
+    #[test]
+    fn the_title_is_read_from_the_container_tags() {
+        assert_eq!(parse_metadata_tag(BANNER, "title").as_deref(), Some("Carlsen vs Nakamura: game 2"));
+        assert_eq!(parse_metadata_tag(BANNER, "encoder").as_deref(), Some("Lavf60.16.100"));
+    }
+
+    #[test]
+    fn stream_tags_are_not_the_containers() {
+        let untitled = BANNER.replace("    title           : Carlsen vs Nakamura: game 2\n", "");
+        assert_eq!(parse_metadata_tag(&untitled, "title"), None);
+    }
+
+    #[test]
+    fn the_nominal_rate_comes_from_tbr_when_it_is_believable() {
+        let phone = BANNER.replace("1920x1080, 30 fps", "1920x1080, 29.47 fps, 30 tbr, 90k tbn");
+        assert_eq!(parse_video_fps(&phone), Some(29.47));
+        assert_eq!(parse_video_tbr(&phone), Some(30.0));
+        // Some muxers report the timebase as tbr, which says nothing about the frame rate
+        let timebase = BANNER.replace("1920x1080, 30 fps", "1920x1080, 30 fps, 90000 tbr");
+        assert_eq!(parse_video_tbr(&timebase), None);
+        assert_eq!(parse_video_tbr("Stream #0:0: Audio: aac, 48000 Hz, 2000 tbr"), None);
+    }
+
+    #[test]
+    fn a_rate_more_than_a_percent_off_nominal_is_variable() {
+        assert!(variable_frame_rate(Some(29.47), Some(30.0)));
+        assert!(variable_frame_rate(Some(24.5), Some(24.0)));
+        assert!(!variable_frame_rate(Some(29.97), Some(30.0)));
+        assert!(!variable_frame_rate(Some(25.0), Some(25.0)));
+        assert!(!variable_frame_rate(Some(29.47), None));
+        assert!(!variable_frame_rate(None, Some(30.0)));
+    }
+
+    #[test]
+    fn an_image_is_told_apart_by_its_demuxer_not_its_extension() {
+        let mislabelled = "Input #0, png_pipe, from 'branding.mp4':\n  Duration: N/A, bitrate: N/A\n  Stream #0:0: Video: png, rgb24(pc), 1920x1080, 25 fps, 25 tbr, 25 tbn\n";
+        assert!(parse_still_image(mislabelled));
+        assert!(parse_still_image("Input #0, image2, from 'branding.jpg':\n"));
+        assert!(!parse_still_image(BANNER));
+        assert!(!parse_still_image(""));
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Manager};
+
+use crate::escape::render_command_line;
+use crate::workdir::WorkDirs;
+
+// Lines returned by get_export_ffmpeg_log unless asked for more
+const DEFAULT_TAIL_LINES: usize = 200;
+
+tokio::task_local! {
+    // Log of the export the current task belongs to; every ffmpeg run inside it is appended there
+    static EXPORT_LOG: PathBuf;
+}
+
+fn logs_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_log_dir()
+        .map(|dir| dir.join("exports"))
+        .map_err(|e| format!("Failed to resolve the app log directory: {}", e))
+}
+
+pub fn log_path(app: &AppHandle, export_id: &str) -> Result<PathBuf, String> {
+    logs_dir(app).map(|dir| dir.join(export_id).join("ffmpeg.log"))
+}
+
+// Runs `future` with its ffmpeg invocations logged to `path`
+pub async fn scope<F: Future>(path: Option<PathBuf>, future: F) -> F::Output {
+    match path {
+        Some(path) => EXPORT_LOG.scope(path, future).await,
+        None => future.await,
+    }
+}
+
+// The log of the export being run, once something has been written to it
+pub fn current() -> Option<PathBuf> {
+    EXPORT_LOG.try_with(|path| path.clone()).ok().filter(|path| path.is_file())
+}
+
+// Appends the log path to an error when the failed export left a log behind
+pub fn with_log_path(message: String, path: Option<&Path>) -> String {
+    match path.filter(|path| path.is_file()) {
+        Some(path) => format!("{}\nFFmpeg log: {}", message, path.display()),
+        None => message,
+    }
+}
+
+// The filter graph, one chain per line, whether inline or moved to a script file
+fn filter_graph(args: &[String]) -> Option<String> {
+    let value = |flag: &str| args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1));
+    let graph = match (value("-filter_complex"), value("-filter_complex_script")) {
+        (Some(graph), _) => graph.clone(),
+        (None, Some(script)) => fs::read_to_string(script).unwrap_or_else(|e| format!("<failed to read {}: {}>", script, e)),
+        (None, None) => return None,
+    };
+    Some(graph.split(';').map(str::trim).collect::<Vec<_>>().join(";\n"))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Records one ffmpeg run in the current export's log; outside an export this does nothing
+pub fn append_invocation(binary: &str, args: &[String], return_code: Option<i32>, stdout: &str, stderr: &str) {
+    let mut entry = format!(
+        "=== ffmpeg run at {} ===\nBinary: {}\nCommand: {}\n",
+        now_secs(), binary, render_command_line("ffmpeg", args)
+    );
+    if let Some(graph) = filter_graph(args) {
+        entry.push_str(&format!("--- filter graph ---\n{}\n", graph));
+    }
+    entry.push_str(&format!("--- return code: {:?} ---\n", return_code));
+    entry.push_str(&format!("--- stderr ---\n{}\n--- stdout ---\n{}\n\n", stderr.trim_end(), stdout.trim_end()));
+    append(&entry);
+}
+
+// Records a run of something other than ffmpeg, such as the post-export hook, in the same format
+pub fn append_run(title: &str, command: &str, return_code: Option<i32>, stdout: &str, stderr: &str) {
+    append(&format!(
+        "=== {} at {} ===\nCommand: {}\n--- return code: {:?} ---\n--- stderr ---\n{}\n--- stdout ---\n{}\n\n",
+        title, now_secs(), command, return_code, stderr.trim_end(), stdout.trim_end()
+    ));
+}
+
+fn append(entry: &str) {
+    let Ok(path) = EXPORT_LOG.try_with(|path| path.clone()) else {
+        return;
+    };
+    let written = path
+        .parent()
+        .map(|dir| fs::create_dir_all(dir).map_err(|e| e.to_string()))
+        .unwrap_or(Ok(()))
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(&path).map_err(|e| e.to_string()))
+        .and_then(|mut file| file.write_all(entry.as_bytes()).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        log::warn!("Failed to write the FFmpeg log {}: {}", path.display(), e);
+    }
+}
+
+fn age(path: &Path) -> Duration {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .unwrap_or_default()
+}
+
+// Removes the log directories of exports older than `older_than_days`, returning their ids
+pub fn prune(app: &AppHandle, workdirs: &WorkDirs, older_than_days: f64) -> Vec<String> {
+    let Ok(dir) = logs_dir(app).and_then(|dir| fs::read_dir(&dir).map_err(|e| e.to_string())) else {
+        return Vec::new();
+    };
+    let mut removed = Vec::new();
+    for entry in dir.flatten().filter(|e| e.path().is_dir()) {
+        let id = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        if workdirs.is_active(&id) || age(&path).as_secs_f64() / 86400.0 < older_than_days {
+            continue;
+        }
+        match fs::remove_dir_all(&path) {
+            Ok(_) => removed.push(id),
+            Err(e) => log::warn!("Failed to remove {}: {}", path.display(), e),
+        }
+    }
+    removed.sort();
+    removed
+}
+
+#[command]
+pub fn get_export_ffmpeg_log(app: AppHandle, export_id: String, lines: Option<usize>) -> Result<String, String> {
+    if export_id.is_empty() || export_id.contains(['/', '\\']) || export_id.contains("..") {
+        return Err(format!("Invalid export id: {}", export_id));
+    }
+    let path = log_path(&app, &export_id)?;
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("No FFmpeg log for export {} ({}): {}", export_id, path.display(), e))?;
+    let all: Vec<&str> = content.lines().collect();
+    let keep = lines.unwrap_or(DEFAULT_TAIL_LINES);
+    Ok(all[all.len().saturating_sub(keep)..].join("\n"))
+}
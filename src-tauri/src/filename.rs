@@ -0,0 +1,347 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+// Longest name most filesystems take: bytes on Linux and macOS, UTF-16 units on Windows
+const NAME_MAX: usize = 255;
+const WINDOWS_RESERVED_CHARS: &str = "<>:\"/\\|?*";
+const WINDOWS_RESERVED_NAMES: &[&str] = &["CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$"];
+
+// Whose rules a name has to follow
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Posix,
+    Windows,
+}
+
+impl Platform {
+    // The filesystem `dir` is on, as far as the path tells: Windows drives are also reached from
+    // WSL under /mnt/c, where Linux would allow names the drive itself refuses
+    pub fn of(dir: &Path) -> Self {
+        if cfg!(windows) {
+            return Platform::Windows;
+        }
+        let mut components = dir.components().map(|c| c.as_os_str().to_string_lossy().to_string());
+        let mounted_drive = components.next().as_deref() == Some("/")
+            && components.next().as_deref() == Some("mnt")
+            && components.next().is_some_and(|drive| drive.len() == 1 && drive.chars().all(|c| c.is_ascii_alphabetic()));
+        if mounted_drive {
+            Platform::Windows
+        } else {
+            Platform::Posix
+        }
+    }
+}
+
+// Strict refuses a bad name; lenient replaces what's wrong and lets the caller warn
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilenameMode {
+    Strict,
+    #[default]
+    Lenient,
+}
+
+impl FilenameMode {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        match data.get("filename_mode") {
+            None | Some(Value::Null) => Ok(FilenameMode::default()),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| format!("filename_mode must be \"strict\" or \"lenient\", got {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    Empty,
+    // . and .., which name directories rather than a file
+    DotName,
+    ControlChar,
+    ReservedChar,
+    // CON, COM1 and the rest, which Windows refuses even with an extension
+    ReservedName,
+    // Windows drops them, so the file would get another name than the one asked for
+    TrailingDotOrSpace,
+    TooLong,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub message: String,
+}
+
+fn issue(kind: IssueKind, message: String) -> Issue {
+    Issue { kind, message }
+}
+
+fn is_reserved_char(c: char, platform: Platform) -> bool {
+    match platform {
+        Platform::Posix => c == '/',
+        Platform::Windows => WINDOWS_RESERVED_CHARS.contains(c),
+    }
+}
+
+// Windows matches device names on the part before the first dot, ignoring case and trailing spaces
+fn is_reserved_name(name: &str) -> bool {
+    let base = name.split('.').next().unwrap_or_default().trim_end().to_uppercase();
+    let numbered = ["COM", "LPT"].iter().any(|prefix| {
+        base.strip_prefix(prefix)
+            .is_some_and(|n| matches!(n, "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "¹" | "²" | "³"))
+    });
+    numbered || WINDOWS_RESERVED_NAMES.contains(&base.as_str())
+}
+
+fn length(name: &str, platform: Platform) -> usize {
+    match platform {
+        Platform::Posix => name.len(),
+        Platform::Windows => name.encode_utf16().count(),
+    }
+}
+
+fn distinct(name: &str, matches: impl Fn(char) -> bool) -> Vec<char> {
+    let mut found: Vec<char> = Vec::new();
+    for c in name.chars().filter(|&c| matches(c)) {
+        if !found.contains(&c) {
+            found.push(c);
+        }
+    }
+    found
+}
+
+// Everything wrong with `name` as a single file name on `platform`; empty when it can be used as is
+pub fn check_filename(name: &str, platform: Platform) -> Vec<Issue> {
+    if name.trim().is_empty() {
+        return vec![issue(IssueKind::Empty, "The file name is empty".to_string())];
+    }
+    if name == "." || name == ".." {
+        return vec![issue(IssueKind::DotName, format!("\"{}\" names a directory, not a file", name))];
+    }
+    let mut issues = Vec::new();
+    let controls = distinct(name, char::is_control);
+    if !controls.is_empty() {
+        let codes: Vec<String> = controls.iter().map(|c| format!("U+{:04X}", *c as u32)).collect();
+        issues.push(issue(IssueKind::ControlChar, format!("The name contains the control characters {}", codes.join(", "))));
+    }
+    let reserved = distinct(name, |c| is_reserved_char(c, platform));
+    if !reserved.is_empty() {
+        let chars: String = reserved.iter().collect();
+        issues.push(issue(IssueKind::ReservedChar, format!("The name contains {}, which can't be used in a file name here", chars)));
+    }
+    if platform == Platform::Windows {
+        if is_reserved_name(name) {
+            issues.push(issue(IssueKind::ReservedName, format!("\"{}\" is a device name on Windows", name)));
+        }
+        if name.ends_with(['.', ' ']) {
+            issues.push(issue(IssueKind::TrailingDotOrSpace, "Windows drops the dots and spaces at the end of a name".to_string()));
+        }
+    }
+    if length(name, platform) > NAME_MAX {
+        issues.push(issue(IssueKind::TooLong, format!("The name is longer than the {} characters allowed", NAME_MAX)));
+    }
+    issues
+}
+
+// Shortens the part before the extension until the whole name fits
+fn truncate(name: &str, platform: Platform) -> String {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && length(extension, platform) < NAME_MAX / 2 => (stem, Some(extension)),
+        _ => (name, None),
+    };
+    let room = NAME_MAX - extension.map(|e| length(e, platform) + 1).unwrap_or(0);
+    let mut short = String::new();
+    for c in stem.chars() {
+        if length(&short, platform) + length(c.encode_utf8(&mut [0; 4]), platform) > room {
+            break;
+        }
+        short.push(c);
+    }
+    match extension {
+        Some(extension) => format!("{}.{}", short, extension),
+        None => short,
+    }
+}
+
+fn repair(name: &str, platform: Platform) -> String {
+    let mut fixed: String = name
+        .chars()
+        .map(|c| if c.is_control() || is_reserved_char(c, platform) { '_' } else { c })
+        .collect();
+    if fixed == "." || fixed == ".." {
+        fixed = fixed.replace('.', "_");
+    }
+    if platform == Platform::Windows {
+        fixed = fixed.trim_end_matches(['.', ' ']).to_string();
+        if is_reserved_name(&fixed) {
+            fixed.insert(0, '_');
+        }
+    }
+    if length(&fixed, platform) > NAME_MAX {
+        fixed = truncate(&fixed, platform);
+        if platform == Platform::Windows {
+            fixed = fixed.trim_end_matches(['.', ' ']).to_string();
+        }
+    }
+    if fixed.trim().is_empty() {
+        fixed = "_".to_string();
+    }
+    fixed
+}
+
+// `name` checked as a single file name on `platform`. Strict gives back every issue; lenient
+// replaces bad characters with _ and fixes the rest, and the caller can compare to warn.
+pub fn sanitize_filename(name: &str, platform: Platform, mode: FilenameMode) -> Result<String, Vec<Issue>> {
+    let issues = check_filename(name, platform);
+    match (issues.is_empty(), mode) {
+        (true, _) => Ok(name.to_string()),
+        (false, FilenameMode::Strict) => Err(issues),
+        (false, FilenameMode::Lenient) => Ok(repair(name, platform)),
+    }
+}
+
+// The issues as one sentence for error messages
+pub fn describe(issues: &[Issue]) -> String {
+    issues.iter().map(|i| i.message.as_str()).collect::<Vec<_>>().join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use IssueKind::*;
+    use Platform::{Posix, Windows};
+
+    fn kinds(name: &str, platform: Platform) -> Vec<IssueKind> {
+        check_filename(name, platform).into_iter().map(|i| i.kind).collect()
+    }
+
+    // (name, platform, what's wrong with it, what lenient mode makes of it)
+    fn table() -> Vec<(String, Platform, Vec<IssueKind>, String)> {
+        let row = |name: &str, platform, issues: &[IssueKind], lenient: &str| (name.to_string(), platform, issues.to_vec(), lenient.to_string());
+        vec![
+            row("Carlsen vs Nepo - Game 6.mp4", Windows, &[], "Carlsen vs Nepo - Game 6.mp4"),
+            row("ブリッツ対局.mp4", Windows, &[], "ブリッツ対局.mp4"),
+            // Reserved device names, with any case, extension or trailing spaces
+            row("CON", Windows, &[ReservedName], "_CON"),
+            row("con.mp4", Windows, &[ReservedName], "_con.mp4"),
+            row("Nul.tar.gz", Windows, &[ReservedName], "_Nul.tar.gz"),
+            row("AUX .mp4", Windows, &[ReservedName], "_AUX .mp4"),
+            row("com1.mp4", Windows, &[ReservedName], "_com1.mp4"),
+            row("LPT9", Windows, &[ReservedName], "_LPT9"),
+            row("COM²", Windows, &[ReservedName], "_COM²"),
+            row("CONIN$", Windows, &[ReservedName], "_CONIN$"),
+            row("conout$.log", Windows, &[ReservedName], "_conout$.log"),
+            // Only the exact names are reserved
+            row("COM0.mp4", Windows, &[], "COM0.mp4"),
+            row("LPT10.mp4", Windows, &[], "LPT10.mp4"),
+            row("CONSOLE.mp4", Windows, &[], "CONSOLE.mp4"),
+            row("my CON.mp4", Windows, &[], "my CON.mp4"),
+            row("CON", Posix, &[], "CON"),
+            // Trailing dots and spaces
+            row("game.", Windows, &[TrailingDotOrSpace], "game"),
+            row("game. . ", Windows, &[TrailingDotOrSpace], "game"),
+            row("CON.", Windows, &[ReservedName, TrailingDotOrSpace], "_CON"),
+            row("...", Windows, &[TrailingDotOrSpace], "_"),
+            row("game.", Posix, &[], "game."),
+            row(" leading space.mp4", Windows, &[], " leading space.mp4"),
+            // Reserved characters
+            row("Round 1: Carlsen | Nepo?.mp4", Windows, &[ReservedChar], "Round 1_ Carlsen _ Nepo_.mp4"),
+            row("<\"*\">.mp4", Windows, &[ReservedChar], "_____.mp4"),
+            row("a\\b/c.mp4", Windows, &[ReservedChar], "a_b_c.mp4"),
+            row("Round 1: Carlsen | Nepo?.mp4", Posix, &[], "Round 1: Carlsen | Nepo?.mp4"),
+            row("a/b.mp4", Posix, &[ReservedChar], "a_b.mp4"),
+            row("a\\b.mp4", Posix, &[], "a\\b.mp4"),
+            // Control characters
+            row("tab\there.mp4", Posix, &[ControlChar], "tab_here.mp4"),
+            row("\u{1b}[31mred.mp4", Posix, &[ControlChar], "_[31mred.mp4"),
+            row("line\nbreak\r\n.mp4", Windows, &[ControlChar], "line_break__.mp4"),
+            row("nul\0byte.mp4", Windows, &[ControlChar], "nul_byte.mp4"),
+            row("del\u{7f}.mp4", Posix, &[ControlChar], "del_.mp4"),
+            row("bad\t:name.", Windows, &[ControlChar, ReservedChar, TrailingDotOrSpace], "bad__name"),
+            // Empty names and directories
+            row("", Posix, &[Empty], "_"),
+            row("   ", Windows, &[Empty], "_"),
+            row(".", Posix, &[DotName], "_"),
+            row("..", Windows, &[DotName], "__"),
+            row(".hidden", Posix, &[], ".hidden"),
+        ]
+    }
+
+    #[test]
+    fn every_nasty_name_is_caught_and_repaired() {
+        for (name, platform, issues, lenient) in table() {
+            assert_eq!(kinds(&name, platform), issues, "{:?} on {:?}", name, platform);
+            assert_eq!(sanitize_filename(&name, platform, FilenameMode::Lenient), Ok(lenient.clone()), "{:?} on {:?}", name, platform);
+            // What lenient mode gives back is always fine as it is
+            assert_eq!(kinds(&lenient, platform), [], "{:?} repaired as {:?}", name, lenient);
+            let strict = sanitize_filename(&name, platform, FilenameMode::Strict);
+            match issues.is_empty() {
+                true => assert_eq!(strict, Ok(name.clone())),
+                false => assert_eq!(strict.unwrap_err().iter().map(|i| i.kind).collect::<Vec<_>>(), issues),
+            }
+        }
+    }
+
+    #[test]
+    fn names_are_measured_in_bytes_on_posix_and_utf16_units_on_windows() {
+        let fits = format!("{}.mp4", "x".repeat(251));
+        assert_eq!(kinds(&fits, Posix), []);
+        assert_eq!(kinds(&format!("x{}", fits), Posix), [TooLong]);
+
+        // 2 bytes but 1 UTF-16 unit each
+        let accented = format!("{}.mp4", "é".repeat(128));
+        assert_eq!(kinds(&accented, Posix), [TooLong]);
+        assert_eq!(kinds(&accented, Windows), []);
+        // 4 bytes and 2 UTF-16 units each
+        let trophies = format!("{}.mp4", "🏆".repeat(126));
+        assert_eq!(kinds(&trophies, Windows), [TooLong]);
+    }
+
+    #[test]
+    fn long_names_are_cut_before_the_extension_on_a_character_boundary() {
+        let repaired = sanitize_filename(&format!("{}.mp4", "é".repeat(200)), Posix, FilenameMode::Lenient).unwrap();
+        assert_eq!(repaired, format!("{}.mp4", "é".repeat(125)));
+        assert_eq!(repaired.len(), 254);
+
+        let repaired = sanitize_filename(&format!("{}.mp4", "🏆".repeat(200)), Windows, FilenameMode::Lenient).unwrap();
+        assert_eq!(repaired, format!("{}.mp4", "🏆".repeat(125)));
+
+        // Without a usable extension the whole name is cut
+        let repaired = sanitize_filename(&"a".repeat(300), Posix, FilenameMode::Lenient).unwrap();
+        assert_eq!(repaired, "a".repeat(255));
+        let long_extension = format!("game.{}", "x".repeat(300));
+        assert_eq!(sanitize_filename(&long_extension, Posix, FilenameMode::Lenient).unwrap(), long_extension[..255]);
+
+        // A cut that leaves a trailing dot or space on Windows loses that too
+        let repaired = sanitize_filename(&format!("{} .{}", "a".repeat(253), "b".repeat(10)), Windows, FilenameMode::Lenient).unwrap();
+        assert_eq!(kinds(&repaired, Windows), []);
+    }
+
+    #[test]
+    fn windows_rules_apply_to_drives_mounted_in_wsl() {
+        assert_eq!(Platform::of(Path::new("/mnt/c/Users/me/Videos")), Windows);
+        let expected = if cfg!(windows) { Windows } else { Posix };
+        assert_eq!(Platform::of(Path::new("/home/me/videos")), expected);
+        assert_eq!(Platform::of(Path::new("/mnt/wsl/share")), expected);
+        assert_eq!(Platform::of(Path::new("/media/c/Videos")), expected);
+    }
+
+    #[test]
+    fn strict_mode_describes_every_issue() {
+        let issues = sanitize_filename("con.\u{1}:.", Windows, FilenameMode::Strict).unwrap_err();
+        assert_eq!(
+            describe(&issues),
+            "The name contains the control characters U+0001; \
+             The name contains :, which can't be used in a file name here; \
+             \"con.\u{1}:.\" is a device name on Windows; \
+             Windows drops the dots and spaces at the end of a name"
+        );
+        assert_eq!(FilenameMode::from_value(&serde_json::json!({"filename_mode": "strict"})), Ok(FilenameMode::Strict));
+        assert_eq!(
+            FilenameMode::from_value(&serde_json::json!({"filename_mode": "loose"})),
+            Err("filename_mode must be \"strict\" or \"lenient\", got \"loose\"".to_string())
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+// The -filter_complex graph as nodes and edges for the frontend to draw. It is parsed from the
+// exact string handed to ffmpeg, so the two can't disagree.
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterGraph {
+    pub nodes: Vec<FilterNode>,
+    pub edges: Vec<FilterEdge>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    // A stream of an input file, such as 0:v
+    Input,
+    Filter,
+    // A labelled result nothing else in the graph consumes, normally picked up by -map
+    Output,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterNode {
+    pub id: String,
+    pub kind: NodeKind,
+    pub name: String,
+    // Positional parameters have no key; values are unescaped, as the filter sees them
+    pub params: Vec<FilterParam>,
+    // Index of the ;-separated chain a filter belongs to
+    pub chain: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterParam {
+    pub key: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterEdge {
+    pub from: String,
+    pub to: String,
+    // The stream label, when the link is a named one rather than the next filter in a chain
+    pub label: Option<String>,
+}
+
+// Splits on `separator` outside quotes, escapes and [labels]
+fn split_top(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped, mut depth) = (0, false, false, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if !quoted => escaped = true,
+            '\'' => quoted = !quoted,
+            '[' if !quoted => depth += 1,
+            ']' if !quoted && depth > 0 => depth -= 1,
+            c if c == separator && !quoted && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+// Takes the [labels] at the front of `text`
+fn take_labels(text: &str) -> Result<(Vec<String>, &str), String> {
+    let mut labels = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(inner) = rest.strip_prefix('[') {
+        let end = inner.find(']').ok_or_else(|| format!("Unclosed stream label in \"{}\"", text))?;
+        labels.push(inner[..end].to_string());
+        rest = inner[end + 1..].trim_start();
+    }
+    Ok((labels, rest))
+}
+
+// Removes one level of ffmpeg quoting: \x is a literal x and '...' is taken as it is. The graph
+// and each filter's options are two such levels, so option values are unescaped twice.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let (mut quoted, mut escaped) = (false, false);
+    for c in text.chars() {
+        match c {
+            _ if escaped => {
+                unescaped.push(c);
+                escaped = false;
+            }
+            '\\' if !quoted => escaped = true,
+            '\'' => quoted = !quoted,
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// Splits the options of one filter on `separator` outside quotes and escapes
+fn split_options(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if !quoted => escaped = true,
+            '\'' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+struct ParsedFilter {
+    inputs: Vec<String>,
+    name: String,
+    params: Vec<FilterParam>,
+    outputs: Vec<String>,
+}
+
+fn parse_filter(text: &str) -> Result<ParsedFilter, String> {
+    let (inputs, rest) = take_labels(text)?;
+    let name_end = rest.find(['=', '[']).unwrap_or(rest.len());
+    let name = rest[..name_end].trim().to_string();
+    if name.is_empty() {
+        return Err(format!("Missing filter name in \"{}\"", text.trim()));
+    }
+    let rest = &rest[name_end..];
+    let (args, labels) = match rest.strip_prefix('=') {
+        Some(args) => {
+            // The arguments run until the first [ outside quotes and escapes
+            let (mut quoted, mut escaped) = (false, false);
+            let end = args
+                .char_indices()
+                .find(|&(_, c)| {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' if !quoted => escaped = true,
+                        '\'' => quoted = !quoted,
+                        _ => return c == '[' && !quoted,
+                    }
+                    false
+                })
+                .map(|(i, _)| i)
+                .unwrap_or(args.len());
+            (&args[..end], &args[end..])
+        }
+        None => ("", rest),
+    };
+    let (outputs, trailing) = take_labels(labels)?;
+    if !trailing.trim().is_empty() {
+        return Err(format!("Unexpected \"{}\" after the labels of {}", trailing.trim(), name));
+    }
+    let params = if args.is_empty() {
+        Vec::new()
+    } else {
+        let options = unescape(args.trim());
+        split_options(&options, ':')
+            .into_iter()
+            .map(|param| match split_options(param, '=').as_slice() {
+                [key, value @ ..] if !value.is_empty() => FilterParam {
+                    key: Some(key.trim().to_string()),
+                    value: unescape(&param[key.len() + 1..]),
+                },
+                _ => FilterParam { key: None, value: unescape(param) },
+            })
+            .collect()
+    };
+    Ok(ParsedFilter { inputs, name, params, outputs })
+}
+
+pub fn parse(graph: &str) -> Result<FilterGraph, String> {
+    let mut nodes = Vec::new();
+    // (label, consumer) pairs, resolved once every producer is known
+    let mut wanted: Vec<(String, String)> = Vec::new();
+    let mut producers: HashMap<String, String> = HashMap::new();
+    let mut edges = Vec::new();
+
+    for (c, chain) in split_top(graph, ';').into_iter().enumerate().filter(|(_, chain)| !chain.trim().is_empty()) {
+        let mut previous: Option<String> = None;
+        for (f, text) in split_top(chain, ',').into_iter().enumerate() {
+            let filter = parse_filter(text)?;
+            let id = format!("f{}.{}", c, f);
+            if let Some(from) = previous.take() {
+                edges.push(FilterEdge { from, to: id.clone(), label: None });
+            }
+            wanted.extend(filter.inputs.into_iter().map(|label| (label, id.clone())));
+            for label in &filter.outputs {
+                producers.insert(label.clone(), id.clone());
+            }
+            if filter.outputs.is_empty() {
+                previous = Some(id.clone());
+            }
+            nodes.push(FilterNode { id, kind: NodeKind::Filter, name: filter.name, params: filter.params, chain: Some(c) });
+        }
+        // A chain that ends unlabelled goes straight to the output file
+        if let Some(from) = previous {
+            let id = format!("out:{}", c);
+            edges.push(FilterEdge { from, to: id.clone(), label: None });
+            nodes.push(FilterNode { id, kind: NodeKind::Output, name: "output".to_string(), params: Vec::new(), chain: None });
+        }
+    }
+
+    let mut consumed = std::collections::HashSet::new();
+    for (label, to) in wanted {
+        let from = match producers.get(&label) {
+            Some(from) => from.clone(),
+            None => {
+                let id = format!("in:{}", label);
+                if !nodes.iter().any(|n| n.id == id) {
+                    nodes.push(FilterNode { id: id.clone(), kind: NodeKind::Input, name: label.clone(), params: Vec::new(), chain: None });
+                }
+                id
+            }
+        };
+        consumed.insert(label.clone());
+        edges.push(FilterEdge { from, to, label: Some(label) });
+    }
+    let mut unused: Vec<(&String, &String)> = producers.iter().filter(|(label, _)| !consumed.contains(*label)).collect();
+    unused.sort();
+    for (label, from) in unused {
+        let id = format!("out:{}", label);
+        edges.push(FilterEdge { from: from.clone(), to: id.clone(), label: Some(label.clone()) });
+        nodes.push(FilterNode { id, kind: NodeKind::Output, name: label.clone(), params: Vec::new(), chain: None });
+    }
+    Ok(FilterGraph { nodes, edges })
+}
+
+// The graph of an ffmpeg argument list, if it has an inline -filter_complex
+pub fn from_args(args: &[String]) -> Option<FilterGraph> {
+    let index = args.iter().position(|a| a == "-filter_complex")?;
+    match parse(args.get(index + 1)?) {
+        Ok(graph) => Some(graph),
+        Err(e) => {
+            log::warn!("Failed to parse the filter graph: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+use crate::destination::{deliver_one, native_path, DestinationKind, Staged};
+use crate::paths::path_arg;
+use crate::progress::ProgressReporter;
+use crate::settings::SettingsState;
+use crate::timings;
+use crate::warnings;
+use crate::workdir::jobs_dir;
+
+// How far rename counts looking for a free name in the destination folder
+const MAX_RENAME_COUNTER: u32 = 9999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FinishMode {
+    Copy,
+    Move,
+}
+
+impl FinishMode {
+    fn verb(self) -> &'static str {
+        match self {
+            FinishMode::Copy => "copy",
+            FinishMode::Move => "move",
+        }
+    }
+}
+
+// What happens when the destination folder already has a file of that name. The main output is
+// overwritten, so that is the default here too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    #[default]
+    Overwrite,
+    // "name (2).mp4", "name (3).mp4", ...
+    Rename,
+    // Leaves the output where it is and warns
+    Fail,
+}
+
+// Where every successful export ends up besides, or instead of, its output path, e.g. an OBS
+// "ready to upload" folder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FinishAction {
+    pub mode: FinishMode,
+    pub destination_dir: String,
+    #[serde(default)]
+    pub on_conflict: OnConflict,
+}
+
+// One output the finish action copied or moved
+#[derive(Debug, Clone, Serialize)]
+pub struct Finished {
+    pub from: String,
+    pub to: String,
+    pub size_bytes: u64,
+}
+
+impl FinishAction {
+    fn dir(&self) -> Result<PathBuf, String> {
+        native_path(&self.destination_dir).map(PathBuf::from).map_err(|e| format!("finish_action destination_dir: {}", e))
+    }
+
+    // Run when the export starts, so a missing or read-only folder fails it before the render
+    pub fn check(&self, app: &AppHandle) -> Result<(), String> {
+        let scratch: Vec<PathBuf> = [Ok(std::env::temp_dir()), jobs_dir(app)].into_iter().flatten().collect();
+        self.check_outside(&scratch)
+    }
+
+    // `scratch` are the folders that get cleaned up, which the outputs mustn't land in
+    fn check_outside(&self, scratch: &[PathBuf]) -> Result<(), String> {
+        let dir = self.dir()?;
+        if !dir.is_dir() {
+            return Err(format!("The finish_action folder {} doesn't exist", dir.display()));
+        }
+        let canonical = dir.canonicalize().map_err(|e| format!("Failed to resolve {}: {}", dir.display(), e))?;
+        for scratch in scratch.iter().filter_map(|d| d.canonicalize().ok()) {
+            if canonical.starts_with(&scratch) {
+                return Err(format!("The finish_action folder {} is inside {}, which gets cleaned up", dir.display(), scratch.display()));
+            }
+        }
+        // Exports running side by side can check the same folder, so each probe gets its own name
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+        let probe = dir.join(format!(".boardcast-write-test-{}-{}", std::process::id(), nanos));
+        fs::write(&probe, b"").map_err(|e| format!("The finish_action folder {} isn't writable: {}", dir.display(), e))?;
+        let _ = fs::remove_file(&probe);
+        Ok(())
+    }
+
+    // The path the output gets in the folder, or None when on_conflict says to leave it
+    fn target(&self, dir: &Path, name: &str) -> Result<Option<PathBuf>, String> {
+        let path = dir.join(name);
+        if !path.exists() {
+            return Ok(Some(path));
+        }
+        match self.on_conflict {
+            OnConflict::Overwrite => Ok(Some(path)),
+            OnConflict::Fail => Ok(None),
+            OnConflict::Rename => {
+                let (stem, extension) = match name.rsplit_once('.') {
+                    Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+                    _ => (name, String::new()),
+                };
+                (2..=MAX_RENAME_COUNTER)
+                    .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+                    .find(|p| !p.exists())
+                    .map(Some)
+                    .ok_or_else(|| format!("Every name for {} up to ({}) is taken in {}", name, MAX_RENAME_COUNTER, dir.display()))
+            }
+        }
+    }
+
+    async fn apply_one(&self, source: &str, progress: &ProgressReporter) -> Result<Option<Finished>, String> {
+        let dir = self.dir()?;
+        let name = Path::new(source).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let Some(target) = self.target(&dir, &name)? else {
+            return Ok(None);
+        };
+        let to = path_arg(&target)?;
+        // Moving within one filesystem is a rename; anything else is a verified copy first
+        if self.mode == FinishMode::Move && tokio::fs::rename(source, &target).await.is_ok() {
+            let size_bytes = fs::metadata(&target).map(|m| m.len()).unwrap_or(0);
+            return Ok(Some(Finished { from: source.to_string(), to, size_bytes }));
+        }
+        let staged = Staged { local: source.to_string(), destination: to.clone(), kind: DestinationKind::Local };
+        let copy = deliver_one(&staged, progress).await?;
+        // Only once the copy is verified in place
+        if self.mode == FinishMode::Move {
+            if let Err(e) = tokio::fs::remove_file(source).await {
+                log::warn!("Failed to remove {} after moving it: {}", source, e);
+            }
+        }
+        Ok(Some(Finished { from: source.to_string(), to, size_bytes: copy.size_bytes }))
+    }
+}
+
+// The written outputs in a composite result: output_path and every successful outputs[].path
+fn written_outputs(result: &Value) -> Vec<String> {
+    let mut paths: Vec<String> = result.get("output_path").and_then(|v| v.as_str()).map(String::from).into_iter().collect();
+    for output in result.get("outputs").and_then(|v| v.as_array()).into_iter().flatten() {
+        if let Some(path) = output.get("path").and_then(|p| p.as_str()).filter(|_| output["status"] == "success") {
+            if !paths.iter().any(|p| p == path) {
+                paths.push(path.to_string());
+            }
+        }
+    }
+    paths
+}
+
+// Copies or moves the written outputs into the finish_action setting's folder and returns the
+// result with a finish_action report; in move mode the result's paths point at the new files.
+// A copy that fails is a warning and the output stays where it was written.
+pub async fn apply(app: &AppHandle, result: String, progress: &ProgressReporter) -> String {
+    let Some(action) = app.state::<SettingsState>().get().finish_action else {
+        return result;
+    };
+    let Ok(mut value) = serde_json::from_str::<Value>(&result) else {
+        return result;
+    };
+    let _finishing = timings::span("finish_action");
+    let mut finished = Vec::new();
+    for source in written_outputs(&value).into_iter().filter(|p| Path::new(p).is_file()) {
+        match action.apply_one(&source, progress).await {
+            Ok(Some(file)) => {
+                log::info!("finish_action: {} {} to {}", action.mode.verb(), file.from, file.to);
+                finished.push(file);
+            }
+            Ok(None) => warnings::warn(
+                "finish_action_failed",
+                format!("Left {} where it is: the finish_action folder already has a file of that name", source),
+            ),
+            Err(e) => warnings::warn("finish_action_failed", format!("Failed to {} {}: {}", action.mode.verb(), source, e)),
+        }
+    }
+    if action.mode == FinishMode::Move {
+        for file in &finished {
+            let from = Value::String(file.from.clone());
+            if value.get("output_path") == Some(&from) {
+                value["output_path"] = Value::String(file.to.clone());
+            }
+            for output in value.get_mut("outputs").and_then(|v| v.as_array_mut()).into_iter().flatten() {
+                if output.get("path") == Some(&from) {
+                    output["path"] = Value::String(file.to.clone());
+                }
+            }
+        }
+    }
+    value["finish_action"] = serde_json::json!({ "mode": action.mode, "files": finished });
+    value["warnings"] = serde_json::json!(warnings::collected());
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn action(destination_dir: &Path, on_conflict: OnConflict) -> FinishAction {
+        FinishAction { mode: FinishMode::Copy, destination_dir: destination_dir.display().to_string(), on_conflict }
+    }
+
+    fn folder(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("boardcast-finish-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn a_taken_name_is_overwritten_left_or_counted_past() {
+        let dir = folder("conflict");
+        for taken in ["game.mp4", "game (2).mp4", "notes"] {
+            fs::write(dir.join(taken), b"").unwrap();
+        }
+        let target = |on_conflict, name| action(&dir, on_conflict).target(&dir, name).unwrap();
+        assert_eq!(target(OnConflict::Overwrite, "game.mp4"), Some(dir.join("game.mp4")));
+        assert_eq!(target(OnConflict::Fail, "game.mp4"), None);
+        assert_eq!(target(OnConflict::Rename, "game.mp4"), Some(dir.join("game (3).mp4")));
+        assert_eq!(target(OnConflict::Rename, "notes"), Some(dir.join("notes (2)")));
+        // A free name is used as it is whatever the policy
+        assert_eq!(target(OnConflict::Fail, "other.mp4"), Some(dir.join("other.mp4")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_folder_is_checked_when_the_export_starts() {
+        let dir = folder("check");
+        assert_eq!(action(&dir, OnConflict::Overwrite).check_outside(&[]), Ok(()));
+        assert_eq!(
+            action(&dir, OnConflict::Overwrite).check_outside(&[std::env::temp_dir()]),
+            Err(format!(
+                "The finish_action folder {} is inside {}, which gets cleaned up",
+                dir.display(),
+                std::env::temp_dir().canonicalize().unwrap().display()
+            ))
+        );
+        // Nothing is left behind by the write test
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        let missing = dir.join("missing");
+        assert_eq!(
+            action(&missing, OnConflict::Overwrite).check_outside(&[]),
+            Err(format!("The finish_action folder {} doesn't exist", missing.display()))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn every_written_output_is_finished_once() {
+        let result = json!({
+            "output_path": "/exports/game.mp4",
+            "outputs": [
+                {"path": "/exports/game.mp4", "status": "success"},
+                {"path": "/exports/game.webm", "status": "failed"},
+                {"path": "/exports/game-720p.mp4", "status": "success"},
+            ],
+        });
+        assert_eq!(written_outputs(&result), ["/exports/game.mp4", "/exports/game-720p.mp4"]);
+        let action: FinishAction = serde_json::from_value(json!({"mode": "move", "destination_dir": "/ready"})).unwrap();
+        assert_eq!((action.mode, action.on_conflict), (FinishMode::Move, OnConflict::Overwrite));
+    }
+}
@@ -1,475 +1,4006 @@
-use tauri::command;
+use tauri::{AppHandle, Manager};
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::env;
 use std::thread;
-use std::time::Duration;
 use serde_json::Value;
 use tauri_plugin_shell::ShellExt;
-use tokio::time::timeout;
 
-async fn render_chess_animation() -> Result<String, String> {
-    let current_dir: PathBuf = env::current_dir()
-        .map_err(|e| format!("Failed to get current directory: {}", e))?;
-    let root_dir = current_dir.parent()
-        .ok_or("Failed to get parent directory")?
-        .to_path_buf();
+use crate::drawtext::{escape_filter_path, DrawText};
+use crate::escape::{concat_entry, render_command_line};
+use crate::export_data::{
+    move_count, timestamps_frames, AnimationKind, BackgroundBehavior, BackgroundTransform, BackgroundTreatment, BackgroundZoom,
+    BoardSide, ClockFormat, FrameRate, ClockOverlay, ColorGrade, Corner, ExtraLayer, HdrHandling, MoveFlash, MoveRange, OutOfBounds, OverlayAnimation, OverlayCrop, ResourceLimits, SeekMode, SideBySide, SlideEdge,
+    SegmentTransition, TreatmentMode, XyOffset, ZoomMode,
+};
+use crate::failure::{AtStage, ExportFailure, FailureStage};
+use crate::ffmpeglog;
+use crate::ffmpeg::{
+    ffmpeg_command, parse_duration_line,
+    resolve_ffmpeg, HdrTransfer, VideoProbe,
+};
+use crate::hwdecode::HwDecode;
+use crate::paths::{for_child_process, path_arg, ProjectPaths};
+use crate::pause::PauseState;
+use crate::process::run_streaming;
+use crate::renderservice::{RemotionService, RenderRequest};
+use crate::segments::{Segment, SegmentJob, SegmentPlan};
+use crate::progress::{ProgressReporter, Stage};
+use crate::settings::{RenderBackend, SettingsState};
+use crate::timeouts;
+use crate::warnings;
 
-    println!("Starting chess animation rendering...");
-    println!("Working directory: {}", root_dir.display());
-    
-    let command_str = "npx remotion render remotion/index.ts Chess sample_exporting/chess-animation.mp4";
-    println!("Command: {}", command_str);
+// Remotion prints lines like "Rendered 12/60, time remaining: 3s" while rendering
+fn parse_rendered_frames(line: &str) -> Option<(f64, f64)> {
+    let start = line.find("Rendered ")? + "Rendered ".len();
+    let (done, rest) = line[start..].split_once('/')?;
+    let total: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    Some((done.trim().parse().ok()?, total.parse().ok()?))
+}
 
-    let (sender, receiver) = std::sync::mpsc::channel();
-    
-    thread::spawn(move || {
-        let mut cmd = if cfg!(target_os = "windows") {
-            let mut cmd = Command::new("cmd");
-            cmd.args(["/C", command_str]);
-            cmd
-        } else {
-            let mut cmd = Command::new("sh");
-            cmd.args(["-c", command_str]);
-            cmd
-        };
+// Splits frames 0..total into `chunks` contiguous inclusive ranges whose sizes differ by at most one
+fn chunk_ranges(total: u64, chunks: u64) -> Vec<(u64, u64)> {
+    if total == 0 {
+        return Vec::new();
+    }
+    let chunks = chunks.clamp(1, total);
+    let (base, extra) = (total / chunks, total % chunks);
+    let mut start = 0;
+    (0..chunks)
+        .map(|i| {
+            let len = base + u64::from(i < extra);
+            let range = (start, start + len - 1);
+            start += len;
+            range
+        })
+        .collect()
+}
 
-        cmd.current_dir(&root_dir);
-        let result = cmd.output();
-        let _ = sender.send(result);
-    });
+// npx is a batch script on Windows and has to be named with its extension when spawned directly
+const NPX: &str = if cfg!(target_os = "windows") { "npx.cmd" } else { "npx" };
 
-    let timeout_duration = Duration::from_secs(300); // 5 minutes
-    let start_time = std::time::Instant::now();
-    
-    loop {
-        if let Ok(result) = receiver.try_recv() {
-            match result {
-                Ok(output) => {
-                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                    
-                    if output.status.success() {
-                        println!("Chess animation rendered successfully.");
-                        return Ok(stdout);
-                    } else {
-                        let error_msg = format!(
-                            "Rendering failed with return code {:?}\nSTDERR: {}\nSTDOUT: {}",
-                            output.status.code(), stderr, stdout
-                        );
-                        println!("{}", error_msg);
-                        return Err(error_msg);
-                    }
-                }
-                Err(e) => {
-                    let error_msg = format!("Failed to execute command: {}", e);
-                    println!("{}", error_msg);
-                    return Err(error_msg);
-                }
-            }
+// Per-process Remotion flags shared by every chunk of a render
+#[derive(Debug, Clone, Copy)]
+struct RenderFlags<'a> {
+    // The export's own export.json, read by remotion/data.ts in place of the bundled one
+    props: &'a Path,
+    concurrency: Option<usize>,
+    // Fraction of the composition's size, used by previews
+    scale: Option<f64>,
+    backend: RenderBackend,
+}
+
+fn remotion_render_args(output_path: &Path, frames: Option<(u64, u64)>, flags: RenderFlags) -> Result<Vec<String>, String> {
+    let mut args: Vec<String> = ["remotion", "render", "remotion/index.ts", "Chess"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    args.push(path_arg(output_path)?);
+    args.push(format!("--props={}", path_arg(flags.props)?));
+    if let Some((first, last)) = frames {
+        args.push(format!("--frames={}-{}", first, last));
+    }
+    if let Some(concurrency) = flags.concurrency {
+        args.push(format!("--concurrency={}", concurrency));
+    }
+    if let Some(scale) = flags.scale {
+        args.push(format!("--scale={}", scale));
+    }
+    Ok(args)
+}
+
+// The same render as a request to the render service. The service is shared, so pausing the export
+// holds the render back until it resumes but can't stop one already running.
+async fn render_with_service<F>(
+    app: &AppHandle,
+    root_dir: &Path,
+    output_path: &Path,
+    frames: Option<(u64, u64)>,
+    flags: RenderFlags<'_>,
+    on_progress: F,
+) -> Result<String, String>
+where
+    F: FnMut(f64, f64),
+{
+    let request = RenderRequest {
+        composition: "Chess".to_string(),
+        props: path_arg(flags.props)?,
+        output: path_arg(output_path)?,
+        frames,
+        concurrency: flags.concurrency,
+        scale: flags.scale,
+    };
+    log::info!("Render service request: {}", serde_json::to_string(&request).unwrap_or_default());
+    if let Some(id) = warnings::current_export_id() {
+        app.state::<PauseState>().wait_resumed(&id).await;
+    }
+    let timeout = timeouts::render();
+    let result = app.state::<RemotionService>().render(root_dir, &request, &timeout, on_progress).await;
+    match result {
+        Ok(()) => Ok(format!("Rendered {} with the render service", request.output)),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(e)
+        }
+    }
+}
+
+// Runs one `npx remotion render`, optionally limited to an inclusive frame range
+async fn render_frames<F>(
+    app: &AppHandle,
+    root_dir: &Path,
+    output_path: &Path,
+    frames: Option<(u64, u64)>,
+    low_priority: bool,
+    flags: RenderFlags<'_>,
+    mut on_progress: F,
+) -> Result<String, String>
+where
+    F: FnMut(f64, f64),
+{
+    if flags.backend == RenderBackend::Service {
+        return render_with_service(app, root_dir, output_path, frames, flags, on_progress).await;
+    }
+    let args = remotion_render_args(output_path, frames, flags)?;
+    log::info!("Command: {}", render_command_line(NPX, &args));
+
+    // No shell in between, so every argument reaches npx intact whatever characters the paths contain
+    let cmd = app.shell().command(NPX).args(&args);
+
+    let timeout = timeouts::render();
+    let result = run_streaming(app, cmd.current_dir(root_dir), &timeout, low_priority, |line, _| {
+        if let Some((done, total)) = parse_rendered_frames(line) {
+            on_progress(done, total);
+        }
+        true
+    }).await;
+
+    match result {
+        Ok(output) if output.code == Some(0) => Ok(output.stdout),
+        Ok(output) => {
+            let error_msg = format!(
+                "Rendering failed with return code {:?}\nSTDERR: {}\nSTDOUT: {}",
+                output.code, output.stderr, output.stdout
+            );
+            log::error!("{}", error_msg);
+            Err(error_msg)
         }
-        
-        if start_time.elapsed() >= timeout_duration {
-            let error_msg = "Rendering timed out after 5 minutes".to_string();
-            println!("{}", error_msg);
-            return Err(error_msg);
+        Err(e) => {
+            let error_msg = format!("Rendering {}", e);
+            log::error!("{}", error_msg);
+            Err(error_msg)
         }
-        
-        thread::sleep(Duration::from_millis(100));
     }
 }
 
-fn process_overlay_data(export_data: &Value) -> Result<(Vec<[f64; 2]>, Vec<[f64; 2]>, [f64; 2]), String> {
-    let time_per_move = export_data.get("timePerMove")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(0.2);
-    
-    let timestamps = export_data.get("timestamps")
-        .and_then(|v| v.as_array())
-        .ok_or("No timestamps found in export data")?;
-    
-    let number_of_moves = timestamps.len();
-    
-    if number_of_moves == 0 {
-        return Err("No timestamps found in export data".to_string());
+// A parallel render's chunk files and the concat list joining them, removed however the render ends
+struct ChunkFiles {
+    chunks: Vec<PathBuf>,
+    list: PathBuf,
+}
+
+impl Drop for ChunkFiles {
+    fn drop(&mut self) {
+        for path in self.chunks.iter().chain(std::iter::once(&self.list)) {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    log::warn!("Failed to remove render chunk file {}: {}", path.display(), e);
+                }
+                _ => {}
+            }
+        }
     }
-    
-    let overlay_segs: Vec<[f64; 2]> = (0..number_of_moves)
-        .map(|i| {
-            let start = (i as f64 * time_per_move * 1000.0).round() / 1000.0;
-            let end = ((i + 1) as f64 * time_per_move * 1000.0).round() / 1000.0;
-            [start, end]
-        })
-        .collect();
-    
-    let mut timestamps_copy: Vec<f64> = timestamps
+}
+
+// Renders each range to its own chunk file in `chunk_dir` and writes the concat list of the chunks.
+// try_join_all drops the remaining renders as soon as one fails, which kills their processes, and
+// the returned ChunkFiles removes the files once the caller has joined them.
+async fn render_chunks<F, Fut>(chunk_dir: &Path, ranges: &[(u64, u64)], render: F) -> Result<(Vec<String>, ChunkFiles), String>
+where
+    F: Fn(usize, (u64, u64), PathBuf) -> Fut,
+    Fut: Future<Output = Result<String, String>>,
+{
+    let files = ChunkFiles {
+        chunks: (0..ranges.len()).map(|i| chunk_dir.join(format!("chunk-{:03}.mp4", i))).collect(),
+        list: chunk_dir.join("chunks.txt"),
+    };
+    let outputs = futures_util::future::try_join_all(ranges.iter().zip(&files.chunks).enumerate().map(
+        |(i, (&range, chunk_path))| {
+            let rendered = render(i, range, chunk_path.clone());
+            async move {
+                rendered.await.map_err(|e| format!("Chunk {} (frames {}-{}) failed: {}", i, range.0, range.1, e))
+            }
+        },
+    ))
+    .await?;
+
+    // Every chunk is an independently encoded file starting on a keyframe, so a stream copy joins them cleanly
+    let list: String = files
+        .chunks
         .iter()
-        .filter_map(|v| v.as_f64())
-        .collect();
-    
-    timestamps_copy.push(7.0);
-    
-    let mut bg_segs: Vec<[f64; 2]> = (1..=number_of_moves)
-        .map(|i| {
-            // Fixed: Match Python logic - subtract time_per_move and round to 3 decimal places
-            let start = ((timestamps_copy[i-1] - time_per_move) * 1000.0).round() / 1000.0;
-            let end = timestamps_copy[i];
-            [start, end]
-        })
+        .map(|p| path_arg(p).and_then(|p| concat_entry(&p)))
+        .collect::<Result<_, _>>()?;
+    tokio::fs::write(&files.list, list)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", files.list.display(), e))?;
+    Ok((outputs, files))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+    pub total_frames: u64,
+    // Only these frames (inclusive) of the composition are rendered when set
+    pub frame_range: Option<(u64, u64)>,
+    pub parallel: u32,
+    pub scale: Option<f64>,
+}
+
+pub async fn render_chess_animation(
+    app: &AppHandle,
+    props_path: &Path,
+    output_path: &Path,
+    options: RenderOptions,
+    limits: Option<ResourceLimits>,
+    progress: &ProgressReporter,
+) -> Result<String, String> {
+    let RenderOptions { total_frames, frame_range, parallel, scale } = options;
+    let root_dir = ProjectPaths::resolve(app)?.root;
+
+    log::info!("Starting chess animation rendering...");
+    log::info!("Working directory: {}", root_dir.display());
+    progress.start(Stage::Render);
+
+    let (first_frame, frame_count) = match frame_range {
+        Some((first, last)) => (first, last - first + 1),
+        None => (0, total_frames),
+    };
+    let ranges: Vec<(u64, u64)> = chunk_ranges(frame_count, u64::from(parallel))
+        .into_iter()
+        .map(|(first, last)| (first + first_frame, last + first_frame))
         .collect();
-    
-    if !bg_segs.is_empty() {
-        // Fixed: Match Python logic - add time_per_move and round to 3 decimal places
-        bg_segs[0][0] = ((bg_segs[0][0] + time_per_move) * 1000.0).round() / 1000.0;
+
+    // In low-priority mode each Remotion process gets a share of half the cores
+    let low_priority = limits.map(|l| l.low_priority).unwrap_or(false);
+    let concurrency = low_priority.then(|| {
+        let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(2);
+        (cores / 2 / ranges.len().max(1)).max(1)
+    });
+    let mut backend = app.state::<SettingsState>().get().render_backend;
+    if backend == RenderBackend::Service {
+        if let Err(e) = app.state::<RemotionService>().ensure_running(&root_dir).await {
+            warnings::warn("render_service_unavailable", format!("The render service is unavailable, rendering with the Remotion CLI instead: {}", e));
+            backend = RenderBackend::Cli;
+        }
     }
-    
-    let x_offset = export_data.get("x_offset")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(0.0);
-    
-    let y_offset = export_data.get("y_offset")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(0.0);
-    
-    let xy_offset = [x_offset, y_offset];
-    
-    println!("Processed overlay data: {} moves", number_of_moves);
-    println!("Overlay segments: {:?}", overlay_segs);
-    println!("Background segments: {:?}", bg_segs);
-    println!("XY Offset: {:?}", xy_offset);
-    
-    Ok((overlay_segs, bg_segs, xy_offset))
-}
+    let flags = RenderFlags { props: props_path, concurrency, scale, backend };
 
-fn get_multiple_overlay_command(
-    overlay_segs: &[[f64; 2]], 
-    bg_segs: &[[f64; 2]], 
-    xy_offset: Option<[f64; 2]>,
-    background_file: Option<&str>,
-    overlay_file: Option<&str>,
-    output_file: Option<&str>
-) -> Result<Vec<String>, String> {
-    if overlay_segs.len() != bg_segs.len() {
-        return Err("The number of overlay segments must match the number of background segments.".to_string());
+    if ranges.len() <= 1 {
+        let output = render_frames(app, &root_dir, output_path, frame_range, low_priority, flags, |done, total| {
+            progress.report(Stage::Render, done, total);
+        }).await?;
+        log::info!("Chess animation rendered successfully.");
+        return Ok(output);
     }
 
-    let xy_offset = xy_offset.unwrap_or([0.0, 0.0]);
-    
-    // Get the root directory (parent of src-tauri)
-    let current_dir = env::current_dir()
-        .map_err(|e| format!("Failed to get current directory: {}", e))?;
-    let root_dir = current_dir.parent()
-        .ok_or("Failed to get parent directory")?;
-    
-    // Build paths: use provided paths for background and output, keep overlay hardcoded
-    let background_file = background_file
-        .map(|f| f.to_string())
-        .unwrap_or_else(|| root_dir.join("sample_exporting").join("background.mp4").to_string_lossy().to_string());
-    let overlay_file = overlay_file
-        .map(|f| root_dir.join("sample_exporting").join(f).to_string_lossy().to_string())
-        .unwrap_or_else(|| root_dir.join("sample_exporting").join("chess-animation.mp4").to_string_lossy().to_string());
-    let output_file = output_file
-        .map(|f| f.to_string())
-        .unwrap_or_else(|| root_dir.join("sample_exporting").join("output.mp4").to_string_lossy().to_string());
-
-    println!("Using paths:");
-    println!("  Background: {}", background_file);
-    println!("  Overlay: {}", overlay_file);
-    println!("  Output: {}", output_file);
-
-    let x_pos = xy_offset[0];
-    let y_pos = xy_offset[1];
-
-    // Build a vector of arguments
-    let mut args: Vec<String> = Vec::new();
+    log::info!("Rendering {} frames in {} parallel chunks", frame_count, ranges.len());
+    let chunk_dir = output_path.parent().ok_or("Animation path has no parent directory")?;
+    let chunk_done = std::sync::Mutex::new(vec![0.0; ranges.len()]);
+    let (outputs, files) = render_chunks(chunk_dir, &ranges, |i, range, chunk_path| {
+        let chunk_done = &chunk_done;
+        let root_dir = &root_dir;
+        async move {
+            render_frames(app, root_dir, &chunk_path, Some(range), low_priority, flags, |done, _| {
+                let mut chunk_done = chunk_done.lock().unwrap();
+                chunk_done[i] = done;
+                progress.report(Stage::Render, chunk_done.iter().sum(), frame_count as f64);
+            })
+            .await
+        }
+    })
+    .await?;
 
-    // Background input
-    args.push("-i".to_string());
-    args.push(background_file.to_string());
-    
-    // Overlay inputs
-    for seg in overlay_segs {
-        let start = seg[0];
-        let end = seg[1];
-        let duration = end - start;
-        args.push("-ss".to_string());
-        args.push(start.to_string());
-        args.push("-t".to_string());
-        args.push(duration.to_string());
-        args.push("-i".to_string());
-        args.push(overlay_file.to_string());
+    let concat_args: Vec<String> = vec![
+        "-y".into(), "-f".into(), "concat".into(), "-safe".into(), "0".into(),
+        "-i".into(), path_arg(&files.list)?,
+        "-c".into(), "copy".into(),
+        path_arg(output_path)?,
+    ];
+    let concat = execute_ffmpeg_command(app.clone(), &concat_args, None, low_priority).await?;
+    if !concat.success {
+        return Err(format!("Failed to join render chunks: {}", concat.error));
     }
-    
-    // Build the filter complex chain
-    let mut filter_complex_parts = Vec::new();
-    let mut last_video_stream = "[0:v]".to_string();
+    log::info!("Chess animation rendered successfully.");
+    Ok(outputs.join("\n"))
+}
 
-    for (i, (overlay_seg, bg_seg)) in overlay_segs.iter().zip(bg_segs.iter()).enumerate() {
-        let overlay_start = overlay_seg[0];
-        let overlay_end = overlay_seg[1];
-        let bg_start = bg_seg[0];
-        let bg_end = bg_seg[1];
+// Where the board goes on the background: pixels as given, or fractions of the frame size
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum OverlayPosition {
+    Pixels { x: f64, y: f64 },
+    Fraction { x: f64, y: f64 },
+}
 
-        let overlay_duration = overlay_end - overlay_start;
-        let bg_overlay_duration = bg_end - bg_start;
+impl OverlayPosition {
+    pub fn to_pixels(self, frame_size: Option<(u32, u32)>) -> Result<[f64; 2], String> {
+        match self {
+            OverlayPosition::Pixels { x, y } => Ok([x, y]),
+            OverlayPosition::Fraction { x, y } => {
+                let (width, height) = frame_size
+                    .ok_or("The background dimensions are needed for percentage offsets")?;
+                Ok([(x * width as f64).round(), (y * height as f64).round()])
+            }
+        }
+    }
+}
 
-        let current_overlay_stream = format!("[{}:v]", i + 1);
-        let processed_overlay_stream = format!("[processed_overlay_{}]", i + 1);
-        let output_stream_label = format!("[v_out_{}]", i + 1);
+// A still background stays up this long after the last move's window
+const STILL_BACKGROUND_TAIL: f64 = 2.0;
 
-        // Build overlay processing filters
-        let mut overlay_filters = Vec::new();
-        let freeze_duration = bg_overlay_duration - overlay_duration;
-        
-        if freeze_duration > 0.001 {
-            overlay_filters.push(format!("tpad=stop_mode=clone:stop_duration={}", freeze_duration));
-        }
-        
-        overlay_filters.push(format!("setpts=PTS+{}/TB", bg_start));
+// Moves shown closer together than this count as back to back for segment transitions
+const ADJACENT_GAP: f64 = 0.5;
 
-        // Create the overlay processing filter chain
-        let overlay_filter_chain = if overlay_filters.is_empty() {
-            format!("{}{}", current_overlay_stream, processed_overlay_stream)
-        } else {
-            format!("{}{}{}",
-                current_overlay_stream,
-                overlay_filters.join(","),
-                processed_overlay_stream
-            )
-        };
+// The unit of every time in a TimingPlan: seconds, or frames of the background when the moves are
+// given as timestamps_frames. A plan in frames is converted to seconds as its filter values are written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeBase {
+    Seconds,
+    Frames(FrameRate),
+}
 
-        filter_complex_parts.push(overlay_filter_chain);
+impl TimeBase {
+    pub fn from_value(data: &Value) -> Result<Self, String> {
+        if timestamps_frames(data)?.is_none() {
+            return Ok(TimeBase::Seconds);
+        }
+        FrameRate::from_value(data)?
+            .map(TimeBase::Frames)
+            .ok_or_else(|| "timestamps_frames needs background_fps".to_string())
+    }
 
-        // Create the overlay application filter
-        let overlay_application = format!(
-            "{}{}overlay={}:{}:enable='between(t,{},{})'{}", 
-            last_video_stream,
-            processed_overlay_stream,
-            x_pos,
-            y_pos,
-            bg_start,
-            bg_end,
-            output_stream_label
-        );
-        filter_complex_parts.push(overlay_application);
-        
-        last_video_stream = output_stream_label;
+    // `secs` seconds in this base
+    fn units(self, secs: f64) -> f64 {
+        match self {
+            TimeBase::Seconds => secs,
+            TimeBase::Frames(rate) => secs * rate.num as f64 / rate.den as f64,
+        }
     }
 
-    let full_filter_complex = filter_complex_parts.join(";");
+    // A time in this base in seconds
+    pub fn seconds(self, t: f64) -> f64 {
+        match self {
+            TimeBase::Seconds => t,
+            TimeBase::Frames(rate) => t * rate.den as f64 / rate.num as f64,
+        }
+    }
+}
 
-    // Add remaining arguments to the vector
-    args.push("-filter_complex".to_string());
-    args.push(full_filter_complex);
-    args.push("-map".to_string());
-    args.push(last_video_stream);
-    args.push("-map".to_string());
-    args.push("0:a?".to_string());
-    args.push("-c:a".to_string());
-    args.push("copy".to_string());
-    args.push("-y".to_string());
-    args.push(output_file.to_string());
+// The move timestamps, in `base`
+pub fn move_times(data: &Value, base: TimeBase) -> Vec<f64> {
+    let field = match base {
+        TimeBase::Seconds => "timestamps",
+        TimeBase::Frames(_) => "timestamps_frames",
+    };
+    data.get(field)
+        .and_then(|v| v.as_array())
+        .map(|ts| ts.iter().filter_map(|t| t.as_f64()).collect())
+        .unwrap_or_default()
+}
 
-    Ok(args)
+// A stretch of the source background and how it plays in the output: `hold` seconds of its first
+// frame, then the span itself at `speed`. An open `end` runs to the end of the video.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct BackgroundSpan {
+    start: f64,
+    end: Option<f64>,
+    hold: f64,
+    speed: f64,
 }
 
-#[derive(Debug, serde::Serialize)]
-struct FFmpegResult {
-    success: bool,
-    output: String,
-    error: String,
-    return_code: Option<i32>,
+impl BackgroundSpan {
+    fn is_plain(&self) -> bool {
+        self.hold <= 0.0 && self.speed == 1.0
+    }
 }
 
-async fn execute_ffmpeg_command(app: tauri::AppHandle, args: &[String]) -> Result<FFmpegResult, String> {
-    // Log the current working directory
-    match env::current_dir() {
-        Ok(current_dir) => {
-            println!("FFmpeg executing from directory: {}", current_dir.display());
+// The stretch of the source background kept when only a range of moves is exported; every
+// other time in the plan is relative to `start`
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct SourceTrim {
+    start: f64,
+    end: f64,
+    // 0-based index of the first exported move
+    first_move: usize,
+    moves: usize,
+}
+
+// Output timing for the composite: which slice of the animation each move uses and when it is
+// shown. Windows are in output time; `spans` is empty unless the background timeline is rebuilt.
+// Every time is in `base`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingPlan {
+    pub base: TimeBase,
+    pub overlay_segs: Vec<[f64; 2]>,
+    pub windows: Vec<[f64; 2]>,
+    pub spans: Vec<BackgroundSpan>,
+    pub trim: Option<SourceTrim>,
+    // Board position for each exported move when xy_offset gives one per move; empty when it is shared
+    pub positions: Vec<[f64; 2]>,
+    // Parallel to `windows`: the move comes within ADJACENT_GAP of the previous one
+    pub adjacent: Vec<bool>,
+    // Set once the boundaries are snapped to frames; times are rounded to milliseconds until then
+    pub frame_rate: Option<f64>,
+    // Runs of moves composited as one overlay branch; each move is its own run unless merged
+    pub groups: Vec<std::ops::Range<usize>>,
+}
+
+impl TimingPlan {
+    fn new(base: TimeBase, overlay_segs: Vec<[f64; 2]>, bg_segs: Vec<[f64; 2]>, behavior: BackgroundBehavior, speeds: &[f64]) -> Self {
+        let freeze = behavior == BackgroundBehavior::Freeze;
+        let speed = |i: usize| speeds.get(i).copied().unwrap_or(1.0);
+
+        // Each move owns the background from its window start up to the next move's, so spans never overlap
+        let mut cuts: Vec<(f64, f64, f64)> = Vec::with_capacity(bg_segs.len());
+        let mut previous = 0.0_f64;
+        for (i, (overlay_seg, bg_seg)) in overlay_segs.iter().zip(&bg_segs).enumerate() {
+            let at = bg_seg[0].max(previous);
+            let hold = if freeze { overlay_seg[1] - overlay_seg[0] } else { 0.0 };
+            cuts.push((at, hold, speed(i)));
+            previous = at;
         }
-        Err(e) => {
-            println!("Failed to get current directory for FFmpeg: {}", e);
+        let last_end = bg_segs.last().map(|seg| seg[1].max(previous));
+
+        let mut spans = vec![BackgroundSpan { start: 0.0, end: None, hold: 0.0, speed: 1.0 }];
+        for (at, hold, speed) in cuts {
+            let current = spans.last_mut().unwrap();
+            if at - current.start <= 0.001 {
+                // Nothing to play before this cut, so it just extends the hold already in place
+                current.hold += hold;
+                current.speed = speed;
+                continue;
+            }
+            current.end = Some(at);
+            spans.push(BackgroundSpan { start: at, end: None, hold, speed });
+        }
+        // Past the last move the background plays normally again
+        let last = spans.last_mut().unwrap();
+        if let Some(end) = last_end.filter(|&end| last.speed != 1.0 && end - last.start > 0.001) {
+            last.end = Some(end);
+            spans.push(BackgroundSpan { start: end, end: None, hold: 0.0, speed: 1.0 });
+        }
+
+        if spans.iter().all(BackgroundSpan::is_plain) {
+            spans.clear();
         }
+        let mut plan = TimingPlan {
+            base,
+            overlay_segs,
+            windows: Vec::new(),
+            spans,
+            trim: None,
+            positions: Vec::new(),
+            adjacent: Vec::new(),
+            frame_rate: None,
+            groups: Vec::new(),
+        };
+        plan.windows = bg_segs.iter().map(|seg| [plan.output_time(seg[0]), plan.output_time(seg[1])]).collect();
+        plan.adjacent = adjacent_windows(&plan.windows, base.units(ADJACENT_GAP));
+        plan.groups = (0..plan.windows.len()).map(|i| i..i + 1).collect();
+        plan
     }
-    
-    println!("Executing ffmpeg with arguments: {:?}", args);
-    
-    // Create the sidecar command
-    let sidecar_command = app.shell().sidecar("ffmpeg")
-        .map_err(|e| format!("Failed to create FFmpeg sidecar command: {}", e))?;
-    
-    // Execute the command with a timeout
-    let execution_future = sidecar_command
-        .args(args) // Pass the arguments slice directly
-        .output();
-    
-    let timeout_duration = Duration::from_secs(300);
-    
-    match timeout(timeout_duration, execution_future).await {
-        Ok(result) => {
-            match result {
-                Ok(output) => {
-                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                    let return_code = output.status.code();
-                    let success = output.status.success();
-                    
-                    println!("FFmpeg execution completed:");
-                    println!("Success: {}", success);
-                    println!("Return code: {:?}", return_code);
-                    
-                    // Print FULL stderr output - this is key for debugging
-                    if !stderr.is_empty() {
-                        println!("=== FULL STDERR OUTPUT ===");
-                        println!("{}", stderr);
-                        println!("=== END STDERR OUTPUT ===");
-                    }
-                    
-                    if !stdout.is_empty() {
-                        println!("=== FULL STDOUT OUTPUT ===");
-                        println!("{}", stdout);
-                        println!("=== END STDOUT OUTPUT ===");
-                    }
-                    
-                    Ok(FFmpegResult {
-                        success,
-                        output: stdout,
-                        error: stderr,
-                        return_code,
-                    })
-                }
-                Err(e) => {
-                    let error_msg = format!("Failed to execute FFmpeg command: {}", e);
-                    println!("{}", error_msg);
-                    Ok(FFmpegResult {
-                        success: false,
-                        output: String::new(),
-                        error: error_msg,
-                        return_code: None,
-                    })
+
+    // Joins moves whose windows are less than `gap` apart, overlapping ones included, into one
+    // continuous window. Inside it each move's slice runs until the next one starts, which is
+    // what the stacked branches showed anyway. Moves at different positions, or crossfading into
+    // each other, stay apart. Returns how many joins were made.
+    fn merge_windows(&mut self, gap: f64, crossfade: bool) -> usize {
+        let mut groups: Vec<std::ops::Range<usize>> = Vec::new();
+        for i in 0..self.windows.len() {
+            match groups.last_mut() {
+                Some(group)
+                    if self.windows[i][0] - self.windows[i - 1][1] < gap
+                        && self.positions.get(i) == self.positions.get(i - 1)
+                        && !(crossfade && self.adjacent[i]) =>
+                {
+                    group.end = i + 1;
                 }
+                _ => groups.push(i..i + 1),
             }
         }
-        Err(_) => {
-            let error_msg = "FFmpeg command timed out after 5 minutes".to_string();
-            println!("{}", error_msg);
-            Ok(FFmpegResult {
-                success: false,
-                output: String::new(),
-                error: error_msg,
-                return_code: Some(-1),
-            })
+        let merges = self.windows.len() - groups.len();
+        self.groups = groups;
+        merges
+    }
+
+    // Moves every overlay and background boundary onto the nearest frame of a `fps` output.
+    // Rounding never reorders two times, so ordered boundaries stay ordered and segments that
+    // didn't overlap still don't.
+    pub fn snap_to_frames(&mut self, fps: f64) {
+        self.frame_rate = Some(fps);
+        let snap = |t: f64| snap_to_frame(t, fps);
+        for seg in self.overlay_segs.iter_mut().chain(self.windows.iter_mut()) {
+            *seg = seg.map(snap);
+        }
+        for span in &mut self.spans {
+            span.start = snap(span.start);
+            span.end = span.end.map(snap);
+            span.hold = snap(span.hold);
+        }
+        if let Some(trim) = &mut self.trim {
+            trim.start = snap(trim.start);
+            trim.end = snap(trim.end);
         }
+        self.adjacent = adjacent_windows(&self.windows, ADJACENT_GAP);
     }
-}
 
-#[command]
-pub async fn export(app: tauri::AppHandle, data: Value) -> Result<String, String> {
-    // First, write the JSON data to file
-    let content = serde_json::to_string_pretty(&data)
-        .map_err(|e| format!("Failed to serialize data: {}", e))?;
-    
-    let mut path = PathBuf::from("..");
-    path.push("remotion");
-    path.push("export.json");
-    
-    let path_clone = path.clone();
-    let content_clone = content.clone();
-    
-    let (sender, receiver) = std::sync::mpsc::channel();
-    thread::spawn(move || {
-        let result = fs::write(&path_clone, content_clone);
-        let _ = sender.send(result);
-    });
-    
-    match receiver.recv() {
-        Ok(Ok(_)) => println!("File written successfully to {:?}", path),
-        Ok(Err(e)) => return Err(format!("Failed to write file to {:?}: {}", path, e)),
-        Err(_) => return Err("File write operation failed".to_string()),
+    // The plan with its times in seconds, rounded to milliseconds, for writing the filter values
+    pub fn in_seconds(&self) -> TimingPlan {
+        let base = self.base;
+        let mut plan = self.clone();
+        if base == TimeBase::Seconds {
+            return plan;
+        }
+        let seconds = |t: f64| round_ms(base.seconds(t));
+        for seg in plan.overlay_segs.iter_mut().chain(plan.windows.iter_mut()) {
+            *seg = seg.map(seconds);
+        }
+        for span in &mut plan.spans {
+            span.start = seconds(span.start);
+            span.end = span.end.map(seconds);
+            span.hold = seconds(span.hold);
+        }
+        if let Some(trim) = &mut plan.trim {
+            trim.start = seconds(trim.start);
+            trim.end = seconds(trim.end);
+        }
+        plan.base = TimeBase::Seconds;
+        plan.adjacent = adjacent_windows(&plan.windows, ADJACENT_GAP);
+        plan
     }
-    
-    // Now render the chess animation
-    println!("Starting chess animation rendering...");
-    if let Err(e) = render_chess_animation().await {
-        let error_msg = format!("Rendering failed: {}", e);
-        println!("{}", error_msg);
-        return Err(error_msg);
-    }
-    println!("Chess animation rendered successfully!");
-
-    println!("Processing overlay data...");
-    match process_overlay_data(&data) {
-        Ok((overlay_segs, bg_segs, xy_offset)) => {
-            println!("Overlay data processed successfully!");
-            
-            // Extract videoPath and outputPath from the JSON data
-            let video_path = data.get("videoPath")
-                .and_then(|v| v.as_str());
-            let output_path = data.get("outputPath")
-                .and_then(|v| v.as_str());
-            
-            println!("Using paths from JSON:");
-            println!("  Video path (background): {:?}", video_path);
-            println!("  Output path: {:?}", output_path);
-            
-            match get_multiple_overlay_command(
-                &overlay_segs,
-                &bg_segs,
-                Some(xy_offset),
-                video_path,        // Use videoPath as background_file
-                None,             // Keep overlay_file hardcoded (None means use default)
-                output_path       // Use outputPath as output_file
-            ) {
-                Ok(ffmpeg_args) => {
-                    println!("Generated FFmpeg arguments: {:?}", ffmpeg_args);
-                    
-                    match execute_ffmpeg_command(app, &ffmpeg_args).await {
-                        Ok(ffmpeg_result) => {
-                            if ffmpeg_result.success {
-                                println!("FFmpeg command executed successfully!");
-                                
-                                let result = serde_json::json!({
-                                    "status": "success",
-                                    "overlay_segments": overlay_segs,
-                                    "background_segments": bg_segs,
-                                    "xy_offset": xy_offset,
-                                    "video_path": video_path,
-                                    "output_path": output_path,
-                                    "ffmpeg_command": format!("ffmpeg {}", ffmpeg_args.join(" ")),
-                                    "ffmpeg_output": ffmpeg_result.output,
-                                    "message": "Chess animation rendered, overlay data processed, and FFmpeg command executed successfully"
-                                });
-                                
-                                Ok(result.to_string())
-                            } else {
-                                let error_msg = format!(
-                                    "FFmpeg command failed: {}\nReturn code: {:?}",
-                                    ffmpeg_result.error,
-                                    ffmpeg_result.return_code,
-                                );
-                                println!("{}", error_msg);
-                                Err(error_msg)
-                            }
-                        }
-                        Err(e) => {
-                            let error_msg = format!("Failed to execute FFmpeg command: {}", e);
-                            println!("{}", error_msg);
-                            Err(error_msg)
-                        }
-                    }
-                }
-                Err(e) => {
-                    let error_msg = format!("Failed to generate FFmpeg command: {}", e);
-                    println!("{}", error_msg);
-                    Err(error_msg)
-                }
+
+    // Rounds a time derived from the boundaries the same way the boundaries were
+    fn round(&self, t: f64) -> f64 {
+        match self.frame_rate {
+            Some(fps) => snap_to_frame(t, fps),
+            None => round_ms(t),
+        }
+    }
+
+    // Maps a source background timestamp onto the output timeline. A hold counts only once the
+    // source time is past its span's start, so a window opening on a freeze begins with the hold.
+    fn output_time(&self, source: f64) -> f64 {
+        if self.spans.is_empty() {
+            return source;
+        }
+        let mut output = 0.0;
+        for span in &self.spans {
+            if source <= span.start {
+                break;
+            }
+            let end = span.end.unwrap_or(f64::INFINITY);
+            output += span.hold + (source.min(end) - span.start) / span.speed;
+            if source <= end {
+                break;
             }
         }
-        Err(e) => {
-            let error_msg = format!("Failed to process overlay data: {}", e);
-            println!("{}", error_msg);
-            Err(error_msg)
+        self.round(output)
+    }
+
+    pub fn output_duration(&self, source_duration: f64) -> f64 {
+        let kept = match self.trim {
+            Some(trim) => (source_duration.min(trim.end) - trim.start).max(0.0),
+            None => source_duration,
+        };
+        self.output_time(kept)
+    }
+
+    // The entries of a per-move list that belong to the exported moves
+    pub fn exported<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        match self.trim {
+            Some(trim) => items.iter().skip(trim.first_move).take(trim.moves).cloned().collect(),
+            None => items.to_vec(),
+        }
+    }
+
+    // Source background time relative to the trimmed background
+    fn rebase(&self, source: f64) -> f64 {
+        source - self.trim.map(|t| t.start).unwrap_or(0.0)
+    }
+
+    // Number shown for the first exported move
+    pub fn first_move_number(&self) -> usize {
+        self.trim.map(|t| t.first_move).unwrap_or(0) + 1
+    }
+
+    // Output times each group's board is on screen, as get_multiple_overlay_command draws it: a
+    // crossfade keeps it up into the next move, a persistent board is up before and after the moves
+    pub fn group_ranges(&self, crossfade: Option<f64>, persistent: bool) -> Vec<[f64; 2]> {
+        let count = self.groups.len();
+        self.groups
+            .iter()
+            .enumerate()
+            .map(|(g, group)| {
+                let (i, last) = (group.start, group.end - 1);
+                let end = match crossfade.filter(|_| self.adjacent.get(last + 1) == Some(&true)) {
+                    Some(d) => self.windows[last][1].max(self.round(self.windows[last + 1][0] + d)),
+                    None => self.windows[last][1],
+                };
+                match (persistent, g == 0, g + 1 == count) {
+                    (true, _, true) => [if g == 0 { 0.0 } else { self.windows[i][0] }, f64::INFINITY],
+                    (true, true, false) => [0.0, end],
+                    _ => [self.windows[i][0], end],
+                }
+            })
+            .collect()
+    }
+}
+
+// The solid colour stand-in for a background file that only has audio
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SyntheticCanvas {
+    pub color: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    // Source seconds, normally the audio's length
+    pub duration: f64,
+}
+
+// Length of the clip looped from a still background: through the last window, plus a tail
+pub fn still_duration(plan: &TimingPlan) -> f64 {
+    round_ms(plan.base.seconds(plan.windows.iter().map(|w| w[1]).fold(0.0, f64::max)) + STILL_BACKGROUND_TAIL)
+}
+
+pub fn round_ms(t: f64) -> f64 {
+    (t * 1000.0).round() / 1000.0
+}
+
+fn snap_to_frame(t: f64, fps: f64) -> f64 {
+    (t * fps).round() / fps
+}
+
+// Each window stays open until the next move, so the gap that matters is between the window starts
+fn adjacent_windows(windows: &[[f64; 2]], gap: f64) -> Vec<bool> {
+    (0..windows.len()).map(|i| i > 0 && windows[i][0] - windows[i - 1][0] < gap).collect()
+}
+
+// Keeps only the moves in `range`: overlay times are rebased onto the partial render, background
+// times onto the trimmed background
+fn trim_to_range(
+    range: MoveRange,
+    timestamps: &[f64],
+    overlay_segs: &mut Vec<[f64; 2]>,
+    bg_segs: &mut Vec<[f64; 2]>,
+    speeds: &mut Vec<f64>,
+    base: TimeBase,
+) -> SourceTrim {
+    let first = range.first - 1;
+    let moves = range.last - range.first + 1;
+    let start = round_ms((timestamps[first] - base.units(range.lead_in)).max(0.0));
+    let end = round_ms(timestamps[range.last - 1] + base.units(range.tail));
+
+    let render_start = overlay_segs[first][0];
+    *overlay_segs = overlay_segs[first..first + moves]
+        .iter()
+        .map(|seg| seg.map(|t| round_ms(t - render_start)))
+        .collect();
+    *bg_segs = bg_segs[first..first + moves]
+        .iter()
+        .map(|seg| seg.map(|t| round_ms((t.min(end) - start).max(0.0))))
+        .collect();
+    *speeds = speeds.iter().skip(first).take(moves).copied().collect();
+    SourceTrim { start, end, first_move: first, moves }
+}
+
+// Matches durationInFrames in remotion/Root.tsx
+pub fn composition_frames(data: &Value) -> u64 {
+    let positions = data.get("positions").and_then(|v| v.as_array()).map(|a| a.len()).unwrap_or(0);
+    let frame_per_move = data.get("framePerMove").and_then(|v| v.as_u64()).unwrap_or(5);
+    positions as u64 * frame_per_move
+}
+
+// Inclusive frame range of the composition covering a move range
+pub fn range_frames(data: &Value) -> Option<(u64, u64)> {
+    let range = MoveRange::from_value(data).ok().flatten()?;
+    let frame_per_move = data.get("framePerMove").and_then(|v| v.as_u64()).unwrap_or(5);
+    Some(((range.first as u64 - 1) * frame_per_move, range.last as u64 * frame_per_move - 1))
+}
+
+pub fn rendered_frames(data: &Value) -> u64 {
+    range_frames(data).map_or_else(|| composition_frames(data), |(first, last)| last - first + 1)
+}
+
+pub type OverlayPlan = (TimingPlan, OverlayPosition);
+
+pub fn overlay_plan(data: &Value) -> Result<OverlayPlan, ExportFailure> {
+    process_overlay_data(data)
+        .map_err(|e| format!("Failed to process overlay data: {}", e))
+        .at_code(FailureStage::Validation, "overlay_data_invalid")
+}
+
+fn overlay_position(export_data: &Value) -> Result<OverlayPosition, String> {
+    let number = |field: &str| export_data.get(field).and_then(|v| v.as_f64());
+    let pixels = (number("x_offset"), number("y_offset"));
+    let fraction = (number("x_offset_pct"), number("y_offset_pct"));
+
+    if let Some(offset) = XyOffset::from_value(export_data)? {
+        if pixels != (None, None) || fraction != (None, None) {
+            warnings::warn("conflicting_offsets", "xy_offset was given along with x/y offsets, using xy_offset".to_string());
+        }
+        // Per-move positions are placed move by move; the first one stands in wherever a single position is needed
+        let [x, y] = match offset {
+            XyOffset::Shared(pair) => pair,
+            XyOffset::PerMove(positions) => positions.first().copied().unwrap_or_default(),
+        };
+        return Ok(OverlayPosition::Pixels { x, y });
+    }
+
+    if fraction == (None, None) {
+        return Ok(OverlayPosition::Pixels {
+            x: pixels.0.unwrap_or(0.0),
+            y: pixels.1.unwrap_or(0.0),
+        });
+    }
+    if pixels != (None, None) {
+        warnings::warn("conflicting_offsets", "both pixel and percentage offsets were given, using the pixel offsets".to_string());
+        return Ok(OverlayPosition::Pixels {
+            x: pixels.0.unwrap_or(0.0),
+            y: pixels.1.unwrap_or(0.0),
+        });
+    }
+
+    let (x, y) = (fraction.0.unwrap_or(0.0), fraction.1.unwrap_or(0.0));
+    for (field, value) in [("x_offset_pct", x), ("y_offset_pct", y)] {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(format!("{} must be between 0.0 and 1.0, got {}", field, value));
         }
     }
+    Ok(OverlayPosition::Fraction { x, y })
+}
+
+// Length of the finished video for a background of `source_duration` seconds
+pub fn planned_output_duration(export_data: &Value, source_duration: f64) -> Result<f64, String> {
+    process_overlay_data(export_data).map(|(plan, _)| plan.in_seconds().output_duration(source_duration))
+}
+
+// Per-move background speed, parallel to timestamps; missing entries play at normal speed
+fn move_speeds(export_data: &Value) -> Vec<f64> {
+    export_data.get("speed")
+        .and_then(|v| v.as_array())
+        .map(|speeds| speeds.iter().map(|v| v.as_f64().unwrap_or(1.0)).collect())
+        .unwrap_or_default()
+}
+
+// Stretch of the source background behind each move; `timestamps` ends with the closing time
+fn background_segments(timestamps: &[f64], time_per_move: f64) -> Vec<[f64; 2]> {
+    let mut bg_segs: Vec<[f64; 2]> = (1..timestamps.len())
+        .map(|i| {
+            // Fixed: Match Python logic - subtract time_per_move and round to 3 decimal places
+            let start = ((timestamps[i-1] - time_per_move) * 1000.0).round() / 1000.0;
+            let end = timestamps[i];
+            [start, end]
+        })
+        .collect();
+    
+    if !bg_segs.is_empty() {
+        // Fixed: Match Python logic - add time_per_move and round to 3 decimal places
+        bg_segs[0][0] = ((bg_segs[0][0] + time_per_move) * 1000.0).round() / 1000.0;
+    }
+    bg_segs
+}
+
+// The move timestamps with the time the last move's background window closes appended: once its
+// animation has played, or at the end of the move range's trim if that's later
+fn closed_timestamps(export_data: &Value, base: TimeBase, time_per_move: f64) -> Vec<f64> {
+    let mut timestamps = move_times(export_data, base);
+    if let Some(&last) = timestamps.last() {
+        let trim_end = MoveRange::from_value(export_data).ok().flatten()
+            .and_then(|range| Some(timestamps.get(range.last.checked_sub(1)?)? + base.units(range.tail)));
+        let close = last + time_per_move;
+        timestamps.push(round_ms(trim_end.map_or(close, |end| end.max(close))));
+    }
+    timestamps
+}
+
+// Where each move's background starts on the untrimmed timeline, in seconds
+pub fn move_cuts(export_data: &Value) -> Result<Vec<f64>, String> {
+    let base = TimeBase::from_value(export_data)?;
+    let time_per_move = base.units(export_data.get("timePerMove").and_then(|v| v.as_f64()).unwrap_or(0.2));
+    let timestamps = closed_timestamps(export_data, base, time_per_move);
+    Ok(background_segments(&timestamps, time_per_move).iter().map(|seg| round_ms(base.seconds(seg[0]))).collect())
+}
+
+// Room left between a shrunk move and the one before it
+const SHRINK_EPSILON: f64 = 0.001;
+
+// A move's animation plays from its window start for its overlay duration. One that would start
+// before the previous move has landed is reported, or with auto_shrink given only the time left
+// and started that much later, showing the end of its animation. Times are in `base`, the messages
+// in seconds. Returns the adjustments made.
+fn fit_move_gaps(
+    overlay_segs: &mut [[f64; 2]],
+    bg_segs: &mut [[f64; 2]],
+    timestamps: &[f64],
+    time_per_move: f64,
+    auto_shrink: bool,
+    base: TimeBase,
+) -> Result<Vec<String>, String> {
+    let seconds = |t: f64| round_ms(base.seconds(t));
+    let epsilon = base.units(SHRINK_EPSILON);
+    let mut violations = Vec::new();
+    let mut adjustments = Vec::new();
+    for i in 1..overlay_segs.len().min(bg_segs.len()) {
+        let previous_landing = bg_segs[i - 1][0] + (overlay_segs[i - 1][1] - overlay_segs[i - 1][0]);
+        if bg_segs[i][0] >= previous_landing - epsilon {
+            continue;
+        }
+        let gap = seconds(timestamps[i] - timestamps[i - 1]);
+        let available = round_ms(bg_segs[i][1].min(timestamps[i]) - previous_landing - epsilon);
+        if !auto_shrink || available <= 0.0 {
+            violations.push(format!(
+                "move {} is {}s after move {} but would start {}s before it lands (timePerMove {}s)",
+                i + 1, gap, i, seconds(previous_landing - bg_segs[i][0]), seconds(time_per_move)
+            ));
+            continue;
+        }
+        let duration = overlay_segs[i][1] - overlay_segs[i][0];
+        let cut = round_ms(duration - available);
+        overlay_segs[i][0] = round_ms(overlay_segs[i][0] + cut);
+        bg_segs[i][0] = round_ms(bg_segs[i][0] + cut);
+        adjustments.push(format!(
+            "move {} was shortened from {}s to {}s to fit its {}s gap",
+            i + 1, seconds(duration), seconds(available), gap
+        ));
+    }
+    if !violations.is_empty() {
+        return Err(format!(
+            "timePerMove doesn't fit between the timestamps ({}); space the moves out, lower timePerMove or set auto_shrink",
+            violations.join("; ")
+        ));
+    }
+    Ok(adjustments)
+}
+
+// The animation slice and the background window of each move, fitted to the gaps between moves
+struct MoveSegments {
+    base: TimeBase,
+    // With the closing time appended
+    timestamps: Vec<f64>,
+    overlay_segs: Vec<[f64; 2]>,
+    bg_segs: Vec<[f64; 2]>,
+    // What auto_shrink changed, to be reported as warnings
+    adjustments: Vec<String>,
+}
+
+fn move_segments(export_data: &Value) -> Result<MoveSegments, String> {
+    let base = TimeBase::from_value(export_data)?;
+    let time_per_move = base.units(export_data.get("timePerMove")
+        .and_then(|v| v.as_f64())
+        .unwrap_or(0.2));
+    
+    let number_of_moves = move_count(export_data);
+    
+    if number_of_moves == 0 {
+        return Err("No timestamps found in export data".to_string());
+    }
+    
+    let mut overlay_segs: Vec<[f64; 2]> = (0..number_of_moves)
+        .map(|i| {
+            let start = (i as f64 * time_per_move * 1000.0).round() / 1000.0;
+            let end = ((i + 1) as f64 * time_per_move * 1000.0).round() / 1000.0;
+            [start, end]
+        })
+        .collect();
+    
+    let timestamps_copy = closed_timestamps(export_data, base, time_per_move);
+    
+    let mut bg_segs = background_segments(&timestamps_copy, time_per_move);
+    let auto_shrink = export_data.get("auto_shrink").and_then(|v| v.as_bool()).unwrap_or(false);
+    let adjustments = fit_move_gaps(&mut overlay_segs, &mut bg_segs, &timestamps_copy, time_per_move, auto_shrink, base)?;
+    Ok(MoveSegments { base, timestamps: timestamps_copy, overlay_segs, bg_segs, adjustments })
+}
+
+// Run before rendering so moves that don't fit fail the export straight away
+pub fn validate_move_gaps(export_data: &Value) -> Result<(), String> {
+    move_segments(export_data).map(|_| ())
+}
+
+fn process_overlay_data(export_data: &Value) -> Result<OverlayPlan, String> {
+    let MoveSegments { base, timestamps: timestamps_copy, mut overlay_segs, mut bg_segs, adjustments } = move_segments(export_data)?;
+    let merge_gap = export_data.get("merge_gap_ms").and_then(|v| v.as_u64());
+    for adjustment in adjustments {
+        warnings::warn("move_duration_shrunk", adjustment);
+    }
+    let number_of_moves = overlay_segs.len();
+    
+    let position = overlay_position(export_data)?;
+    let mut speeds = move_speeds(export_data);
+    let trim = MoveRange::from_value(export_data)?
+        .map(|range| trim_to_range(range, &timestamps_copy, &mut overlay_segs, &mut bg_segs, &mut speeds, base));
+    let mut plan = TimingPlan::new(base, overlay_segs, bg_segs, BackgroundBehavior::from_value(export_data)?, &speeds);
+    plan.trim = trim;
+    if let Some(XyOffset::PerMove(positions)) = XyOffset::from_value(export_data)? {
+        plan.positions = plan.exported(&positions);
+    }
+    if let Some(gap_ms) = merge_gap {
+        let crossfade = SegmentTransition::from_value(export_data)?.is_some();
+        let merges = plan.merge_windows(base.units(gap_ms as f64 / 1000.0), crossfade);
+        log::info!("Merged {} overlay windows less than {} ms apart into {} branches", merges, gap_ms, plan.groups.len());
+    }
+    let position = match plan.positions.first() {
+        Some(&[x, y]) => OverlayPosition::Pixels { x, y },
+        None => position,
+    };
+    
+    log::info!("Processed overlay data: {} moves", number_of_moves);
+    if let TimeBase::Frames(rate) = base {
+        log::info!("Plan times are background frames at {}/{} fps", rate.num, rate.den);
+    }
+    log::info!("Overlay segments: {:?}", plan.overlay_segs);
+    log::info!("Background segments: {:?}", plan.windows);
+    if !plan.spans.is_empty() {
+        log::info!("Background timeline: {:?}", plan.spans);
+    }
+    if let Some(trim) = plan.trim {
+        log::info!(
+            "Exporting moves {}-{} from {}s to {}s of the background",
+            trim.first_move + 1, trim.first_move + trim.moves, round_ms(base.seconds(trim.start)), round_ms(base.seconds(trim.end))
+        );
+    }
+    log::info!("Overlay position: {:?}", position);
+    if !plan.positions.is_empty() {
+        log::info!("Per-move positions: {:?}", plan.positions);
+    }
+    
+    Ok((plan, position))
+}
+
+#[derive(Debug, Clone)]
+pub struct CompositeOptions {
+    pub position: OverlayPosition,
+    // Probed from the background; required for fractional positions
+    pub frame_size: Option<(u32, u32)>,
+    pub seek_mode: SeekMode,
+    // The animation's frame rate, used by accurate seeking
+    pub fps: f64,
+    // Applied first, so the position refers to the cropped region's top-left
+    pub crop: Option<OverlayCrop>,
+    pub treatment: Option<BackgroundTreatment>,
+    // Size of the overlay as placed (after cropping); needed for the treated region
+    pub overlay_size: Option<(u32, u32)>,
+    pub zoom: Option<BackgroundZoom>,
+    pub background_duration: Option<f64>,
+    // Decides whether freeze mode rebuilds an audio track alongside the video
+    pub background_has_audio: bool,
+    // Already validated; layers whose audio can't be mixed have mix_audio cleared
+    pub layers: Vec<ExtraLayer>,
+    pub flash: Option<MoveFlash>,
+    // Output-time windows for the flash, already merged
+    pub flash_windows: Vec<[f64; 2]>,
+    // Drawn last, over the board, so it is never covered
+    pub clock: Option<ClockOverlay>,
+    pub font_file: Option<String>,
+    // Box the finished frame is scaled to fit, keeping its aspect ratio
+    pub output_size: Option<(u32, u32)>,
+    // Applied to the animation before anything else, for previews rendered at a smaller size
+    pub overlay_scale: Option<f64>,
+    // Side-by-side layout; the background is boxed into its part of the canvas and the board fills the rest
+    pub canvas: Option<CanvasPlan>,
+    // Fits the background into an explicit output resolution
+    pub letterbox: Option<Letterbox>,
+    // Side-by-side only: the board stays up before the first and after the last move
+    pub persistent_board: bool,
+    // One position per move in background pixels, already bounds-checked; empty when `position` is shared
+    pub segment_positions: Vec<[f64; 2]>,
+    // Not used with a persistent board, which never leaves
+    pub animation: Option<OverlayAnimation>,
+    pub transition: Option<SegmentTransition>,
+    // Graded before anything is drawn over the background
+    pub color_grade: Option<ColorGrade>,
+    pub deinterlace: bool,
+    // Turns off ffmpeg's autorotation; `frame_size` is already the transformed size
+    pub transform: Option<BackgroundTransform>,
+    // Clockwise degrees from the background's rotation metadata
+    pub source_rotation: u32,
+    // Constant frame rate the background is normalised to
+    pub cfr_rate: Option<f64>,
+    pub hdr: Option<HdrPath>,
+    // Decodes a background video file on the GPU
+    pub hw_decode: Option<HwDecode>,
+    // Seconds to loop a still-image background for; it gets a silent track since it has no audio
+    pub still_background: Option<f64>,
+    // Input 0 is generated and the background file only supplies the audio
+    pub synthetic_canvas: Option<SyntheticCanvas>,
+    // Set when the command composites one segment of a segmented composite
+    pub segment: Option<Segment>,
+}
+
+// The zoom crops back to the background's own frame, which is no longer what the output shows once the
+// background is boxed into a canvas or a letterbox
+pub fn zoom_for_layout(zoom: Option<BackgroundZoom>, canvas: Option<&CanvasPlan>, letterbox: Option<&Letterbox>) -> Option<BackgroundZoom> {
+    let reframed = match (canvas, letterbox) {
+        (Some(_), _) => "boxed into the side_by_side canvas",
+        (None, Some(_)) => "letterboxed to the requested resolution",
+        (None, None) => return zoom,
+    };
+    if zoom.is_some() {
+        warnings::warn("option_ignored", format!("background_zoom is ignored because the background is {}", reframed));
+    }
+    None
+}
+
+// Where the background and the board go on the output canvas in side-by-side layout. Regions are
+// [x, y, width, height]; each input is scaled to fit its region and centred in it.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct CanvasPlan {
+    width: u32,
+    height: u32,
+    background: [u32; 4],
+    board: [u32; 4],
+}
+
+// The canvas keeps the background's own resolution
+pub fn canvas_plan(options: SideBySide, frame_size: (u32, u32)) -> CanvasPlan {
+    let (width, height) = frame_size;
+    let background_width = ((width as f64 * options.split / 2.0).floor() as u32) * 2;
+    let board_width = width - background_width;
+    let (background_x, board_x) = match options.board_side {
+        BoardSide::Right => (0, background_width),
+        BoardSide::Left => (board_width, 0),
+    };
+    CanvasPlan {
+        width,
+        height,
+        background: [background_x, 0, background_width, height],
+        board: [board_x, 0, board_width, height],
+    }
+}
+
+// How the background fills an explicitly requested output resolution: scaled to fit inside
+// `content` ([x, y, width, height]) and padded out to the full size
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Letterbox {
+    pub width: u32,
+    pub height: u32,
+    pub content: [u32; 4],
+    // Background pixels to output pixels; board positions and sizes are scaled by the same factor
+    pub scale: f64,
+    // A colour, or "blur" for a blurred copy of the background behind the content
+    pub fill: String,
+}
+
+impl Letterbox {
+    pub fn padded(&self) -> bool {
+        self.content[2] != self.width || self.content[3] != self.height
+    }
+
+    // Background coordinates to output coordinates
+    pub fn map(&self, point: [f64; 2]) -> [f64; 2] {
+        [
+            (self.content[0] as f64 + point[0] * self.scale).round(),
+            (self.content[1] as f64 + point[1] * self.scale).round(),
+        ]
+    }
+}
+
+pub fn letterbox_plan(frame_size: (u32, u32), output_size: (u32, u32), fill: &str) -> Option<Letterbox> {
+    if frame_size == output_size || frame_size.0 == 0 || frame_size.1 == 0 {
+        return None;
+    }
+    let (width, height) = output_size;
+    let scale = (width as f64 / frame_size.0 as f64).min(height as f64 / frame_size.1 as f64);
+    let even = |side: f64| (((side / 2.0).round() as u32) * 2).max(2);
+    let (content_w, content_h) = (even(frame_size.0 as f64 * scale).min(width), even(frame_size.1 as f64 * scale).min(height));
+    Some(Letterbox {
+        width,
+        height,
+        content: [(width - content_w) / 2, (height - content_h) / 2, content_w, content_h],
+        scale,
+        fill: fill.to_string(),
+    })
+}
+
+fn letterbox_filters(letterbox: &Letterbox, input: &str) -> Vec<String> {
+    let [x, y, w, h] = letterbox.content;
+    let (width, height) = (letterbox.width, letterbox.height);
+    // Without bars there is nothing for a blurred backdrop to show through
+    if letterbox.fill == "blur" && letterbox.padded() {
+        vec![
+            format!("{}split=2[lb_front_src][lb_back_src]", input),
+            format!(
+                "[lb_back_src]scale=w={W}:h={H}:force_original_aspect_ratio=increase,crop={W}:{H},boxblur=20[lb_back]",
+                W = width, H = height
+            ),
+            format!("[lb_front_src]scale={}:{}[lb_front]", w, h),
+            format!("[lb_back][lb_front]overlay={}:{}[bg_letterbox]", x, y),
+        ]
+    } else {
+        let color = if letterbox.fill == "blur" { "black" } else { letterbox.fill.as_str() };
+        vec![format!(
+            "{}scale={}:{},pad={}:{}:{}:{}:color={}[bg_letterbox]",
+            input, w, h, width, height, x, y, color
+        )]
+    }
+}
+
+// Pillarboxes the background into its region of a black canvas
+fn canvas_filter(canvas: &CanvasPlan, input: &str) -> String {
+    let [x, y, w, h] = canvas.background;
+    format!(
+        "{input}scale=w={w}:h={h}:force_original_aspect_ratio=decrease:force_divisible_by=2,pad={cw}:{ch}:{x}+({w}-iw)/2:{y}+({h}-ih)/2:color=black[bg_canvas]",
+        input = input, w = w, h = h, cw = canvas.width, ch = canvas.height, x = x, y = y
+    )
+}
+
+// One window per move starting at its raw timestamp; flashes that would overlap on fast moves merge into one
+pub fn flash_windows(plan: &TimingPlan, timestamps: &[f64], flash: MoveFlash) -> Vec<[f64; 2]> {
+    let timestamps: Vec<f64> = plan.exported(timestamps).into_iter().map(|t| plan.rebase(t)).collect();
+    let duration = f64::from(flash.duration_ms) / 1000.0;
+    let mut starts: Vec<f64> = timestamps.iter().map(|&t| plan.output_time(t)).collect();
+    starts.sort_by(|a, b| a.total_cmp(b));
+
+    let mut windows: Vec<[f64; 2]> = Vec::new();
+    for start in starts {
+        match windows.last_mut() {
+            Some(last) if start <= last[1] => last[1] = last[1].max(start + duration),
+            _ => windows.push([start, start + duration]),
+        }
+    }
+    windows.iter().map(|w| w.map(|t| (t * 1000.0).round() / 1000.0)).collect()
+}
+
+// A single eq filter for every flash: each window ramps from full intensity down to nothing
+fn flash_filter(flash: MoveFlash, windows: &[[f64; 2]]) -> String {
+    let terms: Vec<String> = windows
+        .iter()
+        .map(|w| {
+            let len = ((w[1] - w[0]) * 1000.0).round() / 1000.0;
+            format!("between(t,{a},{b})*({b}-t)/{len}", a = w[0], b = w[1], len = len)
+        })
+        .collect();
+    let enable: Vec<String> = windows.iter().map(|w| format!("between(t,{},{})", w[0], w[1])).collect();
+    format!(
+        "eq=brightness='{}*({})':eval=frame:enable='{}'",
+        flash.intensity,
+        terms.join("+"),
+        enable.join("+")
+    )
+}
+
+// 0 while the board is off-screen or transparent, 1 once it is in place: ramps up over the
+// `duration` after the window opens and down over the `duration` before it closes. clip() holds
+// it at 0 before a ramp-in starts, so a sliding board is fully off-screen when its window opens.
+fn animation_progress(window: [f64; 2], duration: f64, ramp_in: bool, ramp_out: bool) -> Option<String> {
+    let d = ramp_duration(window, duration, ramp_in, ramp_out)?;
+    let rising = format!("clip((t-{})/{},0,1)", window[0], d);
+    let falling = format!("clip(({}-t)/{},0,1)", window[1], d);
+    Some(match (ramp_in, ramp_out) {
+        (true, true) => format!("min({},{})", rising, falling),
+        (true, false) => rising,
+        _ => falling,
+    })
+}
+
+// A window too short for both ramps splits its time between them
+fn ramp_duration(window: [f64; 2], duration: f64, ramp_in: bool, ramp_out: bool) -> Option<f64> {
+    let ramps = u8::from(ramp_in) + u8::from(ramp_out);
+    if ramps == 0 {
+        return None;
+    }
+    Some(round_ms(duration.min((window[1] - window[0]) / f64::from(ramps))).max(0.001))
+}
+
+// The edge with the smallest gap to the board; `board` is [x, y, width, height] in frame pixels
+fn nearest_edge(board: [f64; 4], frame_size: (u32, u32)) -> SlideEdge {
+    let (width, height) = (frame_size.0 as f64, frame_size.1 as f64);
+    [
+        (SlideEdge::Left, board[0]),
+        (SlideEdge::Right, width - board[0] - board[2]),
+        (SlideEdge::Top, board[1]),
+        (SlideEdge::Bottom, height - board[1] - board[3]),
+    ]
+    .into_iter()
+    .min_by(|a, b| a.1.total_cmp(&b.1))
+    .map(|(edge, _)| edge)
+    .unwrap_or(SlideEdge::Left)
+}
+
+// overlay= x/y expressions moving the board from just past `edge` (progress 0) to (x, y) (progress 1)
+fn slide_expressions(edge: SlideEdge, x: &str, y: &str, progress: &str) -> (String, String) {
+    match edge {
+        SlideEdge::Left | SlideEdge::Nearest => (format!("-w+({}+w)*{}", x, progress), y.to_string()),
+        SlideEdge::Right => (format!("W+({}-W)*{}", x, progress), y.to_string()),
+        SlideEdge::Top => (x.to_string(), format!("-h+({}+h)*{}", y, progress)),
+        SlideEdge::Bottom => (x.to_string(), format!("H+({}-H)*{}", y, progress)),
+    }
+}
+
+// Alpha fades for a pop, applied to the board's branch in output time
+fn pop_filters(window: [f64; 2], duration: f64, ramp_in: bool, ramp_out: bool) -> Vec<String> {
+    let Some(d) = ramp_duration(window, duration, ramp_in, ramp_out) else {
+        return Vec::new();
+    };
+    let mut filters = vec!["format=yuva420p".to_string()];
+    if ramp_in {
+        filters.push(format!("fade=t=in:st={}:d={}:alpha=1", window[0], d));
+    }
+    if ramp_out {
+        filters.push(format!("fade=t=out:st={}:d={}:alpha=1", round_ms(window[1] - d), d));
+    }
+    filters
+}
+
+// Clockwise rotation the background ends up with. Without a transform ffmpeg applies the
+// metadata rotation itself; with one, autorotation is off and only honoured metadata counts.
+pub fn applied_rotation(transform: Option<BackgroundTransform>, source_rotation: u32) -> u32 {
+    match transform {
+        Some(t) if t.honor_rotation_metadata => (t.rotate + source_rotation) % 360,
+        Some(t) => t.rotate % 360,
+        None => source_rotation % 360,
+    }
+}
+
+// The constant rate the background is normalised to: on request, or when it was found to be
+// variable and force_cfr wasn't set either way
+pub fn cfr_rate(background: Option<VideoProbe>, force_cfr: Option<bool>) -> Option<f64> {
+    match (background, force_cfr) {
+        (Some(b), Some(true)) => Some(b.nominal_fps().unwrap_or(30.0)),
+        (Some(b), None) if b.variable_frame_rate => b.nominal_fps(),
+        _ => None,
+    }
+}
+
+// Frame size after a clockwise rotation
+pub fn rotated_size(size: (u32, u32), rotation: u32) -> (u32, u32) {
+    match rotation {
+        90 | 270 => (size.1, size.0),
+        _ => size,
+    }
+}
+
+// The rotation (metadata first, when honoured, then `rotate`), then the flips
+fn transform_filters(transform: BackgroundTransform, source_rotation: u32) -> Vec<String> {
+    let mut filters: Vec<String> = match applied_rotation(Some(transform), source_rotation) {
+        90 => vec!["transpose=clock".to_string()],
+        180 => vec!["hflip".to_string(), "vflip".to_string()],
+        270 => vec!["transpose=cclock".to_string()],
+        _ => Vec::new(),
+    };
+    if transform.hflip {
+        filters.push("hflip".to_string());
+    }
+    if transform.vflip {
+        filters.push("vflip".to_string());
+    }
+    filters
+}
+
+// How an HDR background is handled, with the transfer it was treated as
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(tag = "path", content = "transfer", rename_all = "lowercase")]
+pub enum HdrPath {
+    Tonemap(HdrTransfer),
+    Passthrough(HdrTransfer),
+}
+
+pub fn hdr_path(handling: HdrHandling, detected: Option<HdrTransfer>) -> Option<HdrPath> {
+    match handling {
+        HdrHandling::Auto => detected.map(HdrPath::Tonemap),
+        HdrHandling::Tonemap => Some(HdrPath::Tonemap(detected.unwrap_or(HdrTransfer::Pq))),
+        HdrHandling::Passthrough => detected.map(HdrPath::Passthrough),
+    }
+}
+
+// Linearises from the source transfer, tonemaps with hable and converts to 8-bit BT.709 SDR
+fn tonemap_filter(transfer: HdrTransfer) -> String {
+    format!(
+        "zscale=tin={}:pin=bt2020:min=bt2020nc:t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+         tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p",
+        transfer.ffmpeg_name()
+    )
+}
+
+// The LUT first, then eq for any scalar adjustments on top of it
+fn color_grade_filter(grade: &ColorGrade) -> Option<String> {
+    let mut filters = Vec::new();
+    if let Some(lut) = &grade.lut_file {
+        filters.push(format!("lut3d=file={}", escape_filter_path(lut)));
+    }
+    if grade.has_adjustments() {
+        filters.push(format!(
+            "eq=brightness={}:contrast={}:saturation={}",
+            grade.brightness.unwrap_or(0.0),
+            grade.contrast.unwrap_or(1.0),
+            grade.saturation.unwrap_or(1.0)
+        ));
+    }
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+fn is_still_image(file: &str) -> bool {
+    let extension = Path::new(file)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "bmp" | "webp")
+}
+
+// Scales one extra layer and overlays it onto `base`, returning the filters and the new stream label
+fn layer_filters(layer: &ExtraLayer, input_index: usize, base: &str) -> (Vec<String>, String) {
+    const MARGIN: u32 = 20;
+    let scaled = format!("[layer_{}]", input_index);
+    let output = format!("[v_layer_{}]", input_index);
+
+    let mut chain = format!(
+        "[{}:v]scale=w='trunc(iw*{s}/2)*2':h='trunc(ih*{s}/2)*2'",
+        input_index,
+        s = layer.scale
+    );
+    let start = layer.time_range.map(|r| r.start).unwrap_or(0.0);
+    if start > 0.0 {
+        // The layer plays from its own beginning once it appears
+        chain.push_str(&format!(",setpts=PTS-STARTPTS+{}/TB", start));
+    }
+
+    let (x, y) = match (layer.x, layer.y) {
+        (Some(x), Some(y)) => (x.round().to_string(), y.round().to_string()),
+        _ => match layer.anchor.unwrap_or_default() {
+            Corner::TopLeft => (MARGIN.to_string(), MARGIN.to_string()),
+            Corner::TopRight => (format!("W-w-{}", MARGIN), MARGIN.to_string()),
+            Corner::BottomLeft => (MARGIN.to_string(), format!("H-h-{}", MARGIN)),
+            Corner::BottomRight => (format!("W-w-{}", MARGIN), format!("H-h-{}", MARGIN)),
+        },
+    };
+    let enable = match layer.time_range {
+        Some(range) => match range.end {
+            Some(end) => format!(":enable='between(t,{},{})'", range.start, end),
+            None => format!(":enable='gte(t,{})'", range.start),
+        },
+        None => String::new(),
+    };
+    // eof_action=pass keeps the video going when a shorter layer runs out
+    let filters = vec![
+        format!("{}{}", chain, scaled),
+        format!("{}{}overlay={}:{}{}:eof_action=pass{}", base, scaled, x, y, enable, output),
+    ];
+    (filters, output)
+}
+
+// drawtext filters for the clock overlay, applied in sequence to the finished video
+fn clock_filters(clock: &ClockOverlay, windows: &[[f64; 2]], first_move: usize, font_file: Option<&str>) -> Vec<String> {
+    const MARGIN: u32 = 20;
+    let (x, y) = match clock.position {
+        Corner::TopLeft => (MARGIN.to_string(), MARGIN.to_string()),
+        Corner::TopRight => (format!("w-tw-{}", MARGIN), MARGIN.to_string()),
+        Corner::BottomLeft => (MARGIN.to_string(), format!("h-th-{}", MARGIN)),
+        Corner::BottomRight => (format!("w-tw-{}", MARGIN), format!("h-th-{}", MARGIN)),
+    };
+    let styled = |text: DrawText| {
+        let mut text = text
+            .font_file(font_file)
+            .option("fontsize", clock.style.font_size)
+            .option("fontcolor", &clock.style.color)
+            .option("x", &x)
+            .option("y", &y);
+        if let Some(background) = &clock.style.background {
+            text = text.option("box", 1).option("boxcolor", background).option("boxborderw", 8);
+        }
+        text
+    };
+
+    match clock.format {
+        ClockFormat::Elapsed => vec![styled(DrawText::expansion("%{pts:hms}")).build()],
+        // Each label lasts until the next move's window opens, so two never overlap
+        ClockFormat::MoveNumber => windows
+            .iter()
+            .enumerate()
+            .map(|(i, window)| {
+                let end = windows.get(i + 1).map(|next| next[0]).unwrap_or(window[1]);
+                styled(DrawText::literal(&format!("Move {}", i + first_move)))
+                    .option("enable", format!("between(t,{},{})", window[0], end))
+                    .build()
+            })
+            .collect(),
+    }
+}
+
+// atempo only accepts 0.5-2.0 per instance, so larger changes are chained
+fn atempo_chain(speed: f64) -> String {
+    let mut stages = Vec::new();
+    let mut remaining = speed;
+    while remaining > 2.0 {
+        stages.push("atempo=2".to_string());
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        stages.push("atempo=0.5".to_string());
+        remaining /= 0.5;
+    }
+    stages.push(format!("atempo={}", remaining));
+    stages.join(",")
+}
+
+// Rebuilds the background timeline from its spans: held frames (with silence) and retimed stretches
+fn background_timeline_filters(video: &str, audio: &str, spans: &[BackgroundSpan], has_audio: bool) -> Vec<String> {
+    let count = spans.len();
+    let mut filters = vec![format!(
+        "{}split={}{}",
+        video,
+        count,
+        (0..count).map(|i| format!("[bg_src_{}]", i)).collect::<String>()
+    )];
+    if has_audio {
+        filters.push(format!(
+            "{}asplit={}{}",
+            audio,
+            count,
+            (0..count).map(|i| format!("[bg_asrc_{}]", i)).collect::<String>()
+        ));
+    }
+
+    let mut concat_inputs = String::new();
+    for (i, span) in spans.iter().enumerate() {
+        let range = match span.end {
+            Some(end) => format!("start={}:end={}", span.start, end),
+            None => format!("start={}", span.start),
+        };
+        let mut video = format!("[bg_src_{}]trim={}", i, range);
+        if span.speed == 1.0 {
+            video.push_str(",setpts=PTS-STARTPTS");
+        } else {
+            video.push_str(&format!(",setpts=(PTS-STARTPTS)/{}", span.speed));
+        }
+        if span.hold > 0.0 {
+            video.push_str(&format!(",tpad=start_mode=clone:start_duration={}", span.hold));
+        }
+        filters.push(format!("{}[bg_piece_{}]", video, i));
+        concat_inputs.push_str(&format!("[bg_piece_{}]", i));
+
+        if has_audio {
+            let mut audio = format!("[bg_asrc_{}]atrim={},asetpts=PTS-STARTPTS", i, range);
+            if span.speed != 1.0 {
+                audio.push(',');
+                audio.push_str(&atempo_chain(span.speed));
+            }
+            if span.hold > 0.0 {
+                audio.push_str(&format!(",adelay=delays={}:all=1", (span.hold * 1000.0).round()));
+            }
+            filters.push(format!("{}[bg_apiece_{}]", audio, i));
+            concat_inputs.push_str(&format!("[bg_apiece_{}]", i));
+        }
+    }
+
+    filters.push(if has_audio {
+        format!("{}concat=n={}:v=1:a=1[bg_timeline][a_timeline]", concat_inputs, count)
+    } else {
+        format!("{}concat=n={}:v=1:a=0[bg_timeline]", concat_inputs, count)
+    });
+    filters
+}
+
+// Zoom factor over time as an ffmpeg expression. During the overlay each window eases in and back out on
+// its own, so the zoom is back at 1 when the window ends and nothing carries over.
+fn zoom_expression(zoom: BackgroundZoom, windows: &[[f64; 2]], duration: Option<f64>) -> Result<String, String> {
+    let ramp = match zoom.mode {
+        ZoomMode::DuringOverlay => {
+            let terms: Vec<String> = windows
+                .iter()
+                .filter(|w| w[1] > w[0])
+                .map(|w| format!("between(t,{a},{b})*sin(PI*(t-{a})/{len})", a = w[0], b = w[1], len = w[1] - w[0]))
+                .collect();
+            if terms.is_empty() {
+                return Err("background_zoom needs at least one overlay segment".to_string());
+            }
+            terms.join("+")
+        }
+        ZoomMode::Whole => {
+            let duration = duration
+                .filter(|d| *d > 0.0)
+                .ok_or("The background duration is needed for a whole-video zoom")?;
+            format!("min(t/{},1)", duration)
+        }
+    };
+    Ok(format!("(1+{}*({}))", zoom.amount, ramp))
+}
+
+// The background rectangle under the board plus padding, clamped to the frame, as x:y:w:h
+fn treatment_region(
+    position: [f64; 2],
+    padding: u32,
+    overlay_size: Option<(u32, u32)>,
+    frame_size: Option<(u32, u32)>,
+) -> Result<[i64; 4], String> {
+    let (overlay_w, overlay_h) = overlay_size.ok_or("The overlay dimensions are needed for the background treatment")?;
+    let (frame_w, frame_h) = frame_size.ok_or("The background dimensions are needed for the background treatment")?;
+    let padding = i64::from(padding);
+
+    let left = (position[0] as i64 - padding).max(0);
+    let top = (position[1] as i64 - padding).max(0);
+    let right = (position[0] as i64 + i64::from(overlay_w) + padding).min(i64::from(frame_w));
+    let bottom = (position[1] as i64 + i64::from(overlay_h) + padding).min(i64::from(frame_h));
+    if right <= left || bottom <= top {
+        return Err("The overlay lies outside the background, so there is nothing to treat".to_string());
+    }
+    Ok([left, top, right - left, bottom - top])
+}
+
+// How far the board sticks out past each edge of the frame, in pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct Overflow {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl Overflow {
+    fn measure(position: [f64; 2], overlay_size: (u32, u32), frame_size: (u32, u32)) -> Self {
+        Overflow {
+            left: (-position[0]).max(0.0),
+            top: (-position[1]).max(0.0),
+            right: (position[0] + overlay_size.0 as f64 - frame_size.0 as f64).max(0.0),
+            bottom: (position[1] + overlay_size.1 as f64 - frame_size.1 as f64).max(0.0),
+        }
+    }
+
+    fn any(&self) -> bool {
+        self.left > 0.0 || self.top > 0.0 || self.right > 0.0 || self.bottom > 0.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Placement {
+    pub policy: OutOfBounds,
+    pub overflow: Overflow,
+    // Where the board ended up when it had to be moved
+    pub clamped_to: Option<[f64; 2]>,
+}
+
+// Checks the board against the frame (both in background pixels) and applies the out_of_bounds policy
+pub fn check_placement(
+    policy: OutOfBounds,
+    position: [f64; 2],
+    overlay_size: (u32, u32),
+    frame_size: (u32, u32),
+) -> Result<Placement, String> {
+    let overflow = Overflow::measure(position, overlay_size, frame_size);
+    let mut placement = Placement { policy, overflow, clamped_to: None };
+    if !overflow.any() {
+        return Ok(placement);
+    }
+    match policy {
+        OutOfBounds::Error => Err(format!(
+            "The {}x{} board at ({}, {}) extends past the {}x{} frame by left {}, top {}, right {}, bottom {} px; \
+             move it, or set out_of_bounds to \"clamp\" or \"allow\"",
+            overlay_size.0, overlay_size.1, position[0], position[1], frame_size.0, frame_size.1,
+            overflow.left, overflow.top, overflow.right, overflow.bottom
+        )),
+        OutOfBounds::Clamp => {
+            // A board bigger than the frame keeps its top-left corner visible
+            let clamp = |value: f64, size: u32, frame: u32| value.min(frame as f64 - size as f64).max(0.0);
+            placement.clamped_to = Some([
+                clamp(position[0], overlay_size.0, frame_size.0),
+                clamp(position[1], overlay_size.1, frame_size.1),
+            ]);
+            Ok(placement)
+        }
+        OutOfBounds::Allow => Ok(placement),
+    }
+}
+
+// Rejects a board offset more than a whole frame away, whatever the out_of_bounds policy. Such an
+// offset usually comes from the frontend dividing by zero and would only show an empty background.
+pub fn check_offset_in_frame(position: [f64; 2], frame_size: (u32, u32)) -> Result<(), String> {
+    for (field, offset, size) in [("x_offset", position[0], frame_size.0), ("y_offset", position[1], frame_size.1)] {
+        if offset < -(size as f64) || offset >= size as f64 {
+            return Err(format!(
+                "{} is {}, which places the board outside the {}x{} frame",
+                field, offset, frame_size.0, frame_size.1
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Where each move's board is drawn, in pixels of the frame it's drawn on
+struct BoardPositions {
+    shared: [f64; 2],
+    // One per move when the board moves between moves, else empty
+    per_move: Vec<[f64; 2]>,
+    // The frame and the board as placed on it; both scaled along with a letterbox
+    frame_size: Option<(u32, u32)>,
+    overlay_size: Option<(u32, u32)>,
+    // Side by side, the board is centred in its region whatever size it was scaled to
+    canvas_xy: Option<(String, String)>,
+}
+
+impl BoardPositions {
+    fn new(options: &CompositeOptions, moves: usize) -> Result<Self, String> {
+        // overlay= needs whole pixels
+        let shared = options.position.to_pixels(options.frame_size)?.map(f64::round);
+        // Letterboxed, everything placed over the background works on the output canvas instead
+        let (shared, frame_size, overlay_size) = match &options.letterbox {
+            Some(letterbox) => (
+                letterbox.map(shared),
+                Some((letterbox.width, letterbox.height)),
+                options.overlay_size.map(|(w, h)| ((w as f64 * letterbox.scale).round() as u32, (h as f64 * letterbox.scale).round() as u32)),
+            ),
+            None => (shared, options.frame_size, options.overlay_size),
+        };
+        if !options.segment_positions.is_empty() && options.segment_positions.len() != moves {
+            return Err(format!(
+                "There are {} per-move positions for {} overlay segments",
+                options.segment_positions.len(), moves
+            ));
+        }
+        let per_move = options.segment_positions
+            .iter()
+            .map(|position| {
+                let position = position.map(f64::round);
+                options.letterbox.as_ref().map(|l| l.map(position)).unwrap_or(position)
+            })
+            .collect();
+        let canvas_xy = options.canvas.as_ref().map(|canvas| {
+            let [x, y, w, h] = canvas.board;
+            (format!("{}+({}-w)/2", x, w), format!("{}+({}-h)/2", y, h))
+        });
+        Ok(BoardPositions { shared, per_move, frame_size, overlay_size, canvas_xy })
+    }
+
+    // Where the board goes for move `i`
+    fn at(&self, i: usize) -> [f64; 2] {
+        self.per_move.get(i).copied().unwrap_or(self.shared)
+    }
+}
+
+// The overlay groups a command draws and their moves. A segment only draws the groups it overlaps,
+// and reads the animation from the first of their moves.
+struct DrawnMoves {
+    groups: Vec<usize>,
+    moves: Vec<usize>,
+    overlay_seek: f64,
+}
+
+impl DrawnMoves {
+    fn new(plan: &TimingPlan, options: &CompositeOptions) -> Self {
+        let groups: Vec<usize> = match &options.segment {
+            Some(segment) => segment.groups.clone(),
+            None => (0..plan.groups.len()).collect(),
+        };
+        let moves: Vec<usize> = groups.iter().flat_map(|&g| plan.groups[g].clone()).collect();
+        let overlay_seek = match (&options.segment, moves.iter().map(|&k| plan.overlay_segs[k][0]).reduce(f64::min)) {
+            (Some(_), Some(first)) if options.fps.is_finite() && options.fps > 0.0 => (first * options.fps + 1e-6).floor() / options.fps,
+            (Some(_), Some(first)) => first,
+            _ => 0.0,
+        };
+        DrawnMoves { groups, moves, overlay_seek }
+    }
+}
+
+// The inputs the graph refers to past the background (0) and the animation (1)
+struct Inputs<'a> {
+    // Each extra layer with its input number, in z-order
+    layers: Vec<(usize, &'a ExtraLayer)>,
+    // Where the background's audio comes from
+    background_audio: usize,
+}
+
+// The background input, then the overlay input opened once and split into one branch per move in the
+// graph, then the extra layers and any separate background audio
+fn input_args<'a>(
+    plan: &TimingPlan,
+    background_file: &str,
+    overlay_file: &str,
+    options: &'a CompositeOptions,
+    overlay_seek: f64,
+) -> (Vec<String>, Inputs<'a>) {
+    let mut args: Vec<String> = Vec::new();
+    let seek_args = match &options.segment {
+        Some(segment) => {
+            // Only the segment's stretch is read, offset back to output time so every window and
+            // expression in the graph applies unchanged
+            let seek = plan.trim.map(|t| t.start).unwrap_or(0.0) + segment.start - segment.lead;
+            let mut seek_args = vec!["-ss".to_string(), seek.to_string()];
+            match (segment.last, plan.trim) {
+                (false, _) => seek_args.extend(["-t".to_string(), (segment.end - segment.start).to_string()]),
+                (true, Some(trim)) => seek_args.extend(["-t".to_string(), (trim.end - seek).to_string()]),
+                (true, None) => {}
+            }
+            seek_args.extend(["-itsoffset".to_string(), (segment.start - segment.lead).to_string()]);
+            Some(seek_args)
+        }
+        None => plan.trim.map(|trim| {
+            // Input seeking restarts the background's timestamps at zero, which is what the rebased plan expects
+            vec!["-ss".to_string(), trim.start.to_string(), "-t".to_string(), round_ms(trim.end - trim.start).to_string()]
+        }),
+    };
+    match (options.still_background, &options.synthetic_canvas) {
+        (Some(duration), _) => {
+            // The plan's times are already rebased, so the looped picture just has to last long enough
+            args.extend([
+                "-loop".to_string(), "1".to_string(),
+                "-framerate".to_string(), options.fps.to_string(),
+                "-t".to_string(), duration.to_string(),
+            ]);
+        }
+        (None, Some(_)) => {}
+        (None, None) => {
+            args.extend(seek_args.clone().unwrap_or_default());
+            args.extend(options.hw_decode.map(HwDecode::input_args).unwrap_or_default());
+        }
+    }
+    if options.transform.is_some() {
+        // The transform bakes in any rotation itself, so ffmpeg mustn't rotate the frames first
+        args.push("-noautorotate".to_string());
+    }
+    match &options.synthetic_canvas {
+        Some(canvas) => {
+            let duration = plan.trim.map(|t| round_ms(t.end - t.start)).unwrap_or(canvas.duration);
+            args.extend([
+                "-f".to_string(), "lavfi".to_string(),
+                "-t".to_string(), duration.to_string(),
+                "-i".to_string(), format!("color=c={}:s={}x{}:r={}", canvas.color, canvas.width, canvas.height, canvas.fps),
+            ]);
+        }
+        None => args.extend(["-i".to_string(), background_file.to_string()]),
+    }
+    if overlay_seek > 0.0 {
+        args.extend(["-ss".to_string(), overlay_seek.to_string()]);
+    }
+    args.extend(["-i".to_string(), overlay_file.to_string()]);
+    // Extra layers follow as inputs 2, 3, ... in payload order; z-order only decides when each is overlaid
+    let mut layers: Vec<(usize, &ExtraLayer)> = Vec::with_capacity(options.layers.len());
+    for (i, layer) in options.layers.iter().enumerate() {
+        if is_still_image(&layer.file) {
+            // A single frame would vanish after the first frame; looping it lasts as long as the background
+            args.extend(["-loop".to_string(), "1".to_string()]);
+        }
+        args.push("-i".to_string());
+        args.push(layer.file.clone());
+        layers.push((i + 2, layer));
+    }
+    layers.sort_by_key(|(_, layer)| layer.z_order);
+    // Silence for a still background, or the audio file under a generated canvas, as the input after the layers
+    let mut background_audio = 0;
+    if let Some(duration) = options.still_background {
+        args.extend([
+            "-f".to_string(), "lavfi".to_string(),
+            "-t".to_string(), duration.to_string(),
+            "-i".to_string(), "anullsrc=r=48000:cl=stereo".to_string(),
+        ]);
+        background_audio = options.layers.len() + 2;
+    } else if options.synthetic_canvas.is_some() {
+        args.extend(seek_args.unwrap_or_default());
+        args.extend(["-i".to_string(), background_file.to_string()]);
+        background_audio = options.layers.len() + 2;
+    }
+    (args, Inputs { layers, background_audio })
+}
+
+// The -filter_complex chains built so far and the label of the video they end in
+struct GraphChains {
+    parts: Vec<String>,
+    video: String,
+}
+
+impl GraphChains {
+    // Runs the video so far through `filters` into `[label]`
+    fn chain(&mut self, filters: &str, label: &str) {
+        self.parts.push(format!("{}{}[{}]", self.video, filters, label));
+        self.video = format!("[{}]", label);
+    }
+}
+
+// The background's audio as the background chain leaves it
+struct BackgroundAudio {
+    input: usize,
+    // [bg_audio] once resampled, else the input's own stream
+    stream: String,
+    // Rebuilt as [a_timeline] along with a frozen or sped-up background
+    retimed: bool,
+    resampled: bool,
+}
+
+// Everything done to the background before the board goes over it, from the GPU download to the move flash
+fn background_chain(
+    graph: &mut GraphChains,
+    plan: &TimingPlan,
+    options: &CompositeOptions,
+    positions: &BoardPositions,
+    audio_input: usize,
+) -> Result<BackgroundAudio, String> {
+    let bg_segs = &plan.windows;
+
+    // Back in system memory as the software decoder's pixel format, ahead of every other filter
+    if let Some(decode) = options.hw_decode {
+        graph.chain(&decode.download_filter(), "bg_downloaded");
+    }
+
+    // Deinterlacing needs the source's own frames and field order, so it runs before any retiming
+    if options.deinterlace {
+        graph.chain("bwdif=mode=send_frame", "bg_deinterlaced");
+    }
+
+    // A variable frame rate drifts against the enable windows, so the frames and audio are made regular first
+    let resampled = options.cfr_rate.is_some() && options.background_has_audio;
+    let source_audio = format!("[{}:a]", audio_input);
+    let audio = BackgroundAudio {
+        input: audio_input,
+        stream: if resampled { "[bg_audio]".to_string() } else { source_audio.clone() },
+        retimed: !plan.spans.is_empty() && options.background_has_audio,
+        resampled,
+    };
+    if let Some(rate) = options.cfr_rate {
+        graph.chain(&format!("fps={}", rate), "bg_cfr");
+        if resampled {
+            graph.parts.push(format!("{}aresample=async=1[bg_audio]", source_audio));
+        }
+    }
+
+    if let Some(transform) = options.transform {
+        let filters = transform_filters(transform, options.source_rotation);
+        if !filters.is_empty() {
+            graph.chain(&filters.join(","), "bg_transformed");
+        }
+    }
+
+    // The board is SDR, so the background is brought down to SDR before anything is drawn on it
+    if let Some(HdrPath::Tonemap(transfer)) = options.hdr {
+        graph.chain(&tonemap_filter(transfer), "bg_sdr");
+    }
+
+    // Everything after this works in output time, so a rebuilt background timeline comes first
+    if !plan.spans.is_empty() {
+        graph.parts.extend(background_timeline_filters(&graph.video, &audio.stream, &plan.spans, options.background_has_audio));
+        graph.video = "[bg_timeline]".to_string();
+    }
+
+    if let Some(zoom) = options.zoom {
+        let (width, height) = options.frame_size.ok_or("The background dimensions are needed for the zoom")?;
+        let factor = zoom_expression(zoom, bg_segs, options.background_duration)?;
+        // Scale up per frame, then crop back to the original size around the centre
+        graph.chain(
+            &format!(
+                "scale=w='trunc(iw*{f}/2)*2':h='trunc(ih*{f}/2)*2':eval=frame,crop={w}:{h}:(in_w-{w})/2:(in_h-{h})/2",
+                f = factor, w = width, h = height
+            ),
+            "bg_zoomed",
+        );
+    }
+
+    if let Some(grade) = options.color_grade.as_ref().and_then(color_grade_filter) {
+        graph.chain(&grade, "bg_graded");
+    }
+
+    if let Some(canvas) = &options.canvas {
+        graph.parts.push(canvas_filter(canvas, &graph.video));
+        graph.video = "[bg_canvas]".to_string();
+    }
+
+    if let Some(letterbox) = &options.letterbox {
+        graph.parts.extend(letterbox_filters(letterbox, &graph.video));
+        graph.video = "[bg_letterbox]".to_string();
+    }
+
+    if let Some(treatment) = options.treatment {
+        // Same windows as the board itself, so the treatment appears and disappears with it; a board that
+        // moves between moves gets one treated region per position
+        let mut regions: Vec<([i64; 4], Vec<String>)> = Vec::new();
+        for (i, seg) in bg_segs.iter().enumerate() {
+            let region = treatment_region(positions.at(i), treatment.padding_px, positions.overlay_size, positions.frame_size)?;
+            let window = format!("between(t,{},{})", seg[0], seg[1]);
+            match regions.iter_mut().find(|(r, _)| *r == region) {
+                Some((_, windows)) => windows.push(window),
+                None => regions.push((region, vec![window])),
+            }
+        }
+        for (n, ([x, y, w, h], windows)) in regions.iter().enumerate() {
+            let effect = match treatment.mode {
+                // boxblur's radius can't exceed half the region's smaller side
+                TreatmentMode::Blur => format!("boxblur={}", (treatment.strength.round() as i64).min(w.min(h) / 2).max(1)),
+                TreatmentMode::Dim => format!("eq=brightness={}", -treatment.strength),
+            };
+            let suffix = if n == 0 { String::new() } else { format!("_{}", n + 1) };
+
+            graph.parts.push(format!("{}split=2[bg_base{s}][bg_region_src{s}]", graph.video, s = suffix));
+            graph.parts.push(format!("[bg_region_src{s}]crop={}:{}:{}:{},{}[bg_region{s}]", w, h, x, y, effect, s = suffix));
+            graph.parts.push(format!(
+                "[bg_base{s}][bg_region{s}]overlay={}:{}:enable='{}'[bg_treated{s}]",
+                x, y, windows.join("+"), s = suffix
+            ));
+            graph.video = format!("[bg_treated{}]", suffix);
+        }
+    }
+
+    if let (Some(flash), false) = (options.flash, options.flash_windows.is_empty()) {
+        graph.chain(&flash_filter(flash, &options.flash_windows), "bg_flash");
+    }
+    Ok(audio)
+}
+
+// Overlays the extra layers that go under the board (negative z-order), or those that go over it
+fn layer_chain(graph: &mut GraphChains, layers: &[(usize, &ExtraLayer)], over_board: bool) {
+    for (input_index, layer) in layers.iter().filter(|(_, layer)| (layer.z_order >= 0) == over_board) {
+        let (filters, output) = layer_filters(layer, *input_index, &graph.video);
+        graph.parts.extend(filters);
+        graph.video = output;
+    }
+}
+
+// The animation split into one branch per drawn move, each trimmed to its move's slice and
+// overlaid over the background in its window
+fn board_branches(
+    graph: &mut GraphChains,
+    plan: &TimingPlan,
+    options: &CompositeOptions,
+    positions: &BoardPositions,
+    drawn: &DrawnMoves,
+) -> Result<(), String> {
+    let overlay_segs = &plan.overlay_segs;
+    let bg_segs = &plan.windows;
+
+    // Spatial filters run once on the whole animation, before it is split per move: a preview's
+    // upscale, then the crop, then the side-by-side fit
+    let mut spatial_filters = Vec::new();
+    if let Some(scale) = options.overlay_scale {
+        spatial_filters.push(format!("scale=w='trunc(iw*{s}/2)*2':h='trunc(ih*{s}/2)*2'", s = scale));
+    }
+    if let Some(crop) = options.crop {
+        spatial_filters.push(format!("crop={}:{}:{}:{}", crop.width, crop.height, crop.x, crop.y));
+    }
+    if let Some(canvas) = &options.canvas {
+        spatial_filters.push(format!(
+            "scale=w={}:h={}:force_original_aspect_ratio=decrease:force_divisible_by=2",
+            canvas.board[2], canvas.board[3]
+        ));
+    }
+    if let Some(letterbox) = options.letterbox.as_ref().filter(|l| l.scale != 1.0) {
+        spatial_filters.push(format!("scale=w='trunc(iw*{s}/2)*2':h='trunc(ih*{s}/2)*2'", s = letterbox.scale));
+    }
+    spatial_filters.push(format!(
+        "split={}{}",
+        drawn.moves.len(),
+        drawn.moves.iter().map(|k| format!("[overlay_{}]", k + 1)).collect::<String>()
+    ));
+    if !drawn.moves.is_empty() {
+        graph.parts.push(format!("[1:v]{}", spatial_filters.join(",")));
+    }
+    let persistent = options.canvas.is_some() && options.persistent_board;
+    let animation = options.animation.filter(|_| !persistent);
+
+    // Move `k`'s slice of the animation starting at t=0 and lasting `length`, frozen on its last
+    // frame when the window is longer; `cut` shortens a slice that a merged window moves past early
+    let slice_filters = |k: usize, length: f64, cut: bool| {
+        let overlay_start = overlay_segs[k][0];
+        let overlay_end = if cut { overlay_segs[k][1].min(plan.round(overlay_start + length)) } else { overlay_segs[k][1] };
+        let (overlay_start, overlay_end) = (overlay_start - drawn.overlay_seek, overlay_end - drawn.overlay_seek);
+        let trim = match options.seek_mode {
+            SeekMode::Accurate if options.fps.is_finite() && options.fps > 0.0 => format!(
+                "trim=start_frame={}:end_frame={}",
+                (overlay_start * options.fps).round(),
+                (overlay_end * options.fps).round()
+            ),
+            _ => format!("trim=start={}:end={}", overlay_start, overlay_end),
+        };
+        let mut filters = vec![trim, "setpts=PTS-STARTPTS".to_string()];
+        let freeze_duration = plan.round(length - (overlay_end - overlay_start));
+        if freeze_duration > 0.001 {
+            filters.push(format!("tpad=stop_mode=clone:stop_duration={}", freeze_duration));
+        }
+        filters
+    };
+
+    for group in drawn.groups.iter().map(|&g| &plan.groups[g]) {
+        // A merged group is drawn like a single move spanning its first to its last window
+        let (i, last) = (group.start, group.end - 1);
+        let bg_start = bg_segs[i][0];
+        let bg_end = bg_segs[last][1];
+
+        // A crossfade keeps this board up, fading out, until the next one has fully faded in over it
+        let crossfade = options.transition.map(|t| round_ms(t.duration_ms as f64 / 1000.0));
+        let fade_in = crossfade.filter(|_| plan.adjacent.get(i) == Some(&true));
+        let fade_out = crossfade.filter(|_| plan.adjacent.get(last + 1) == Some(&true));
+        let bg_end = match fade_out {
+            Some(d) => bg_end.max(plan.round(bg_segs[last + 1][0] + d)),
+            None => bg_end,
+        };
+
+        let processed_overlay_stream = format!("[processed_overlay_{}]", i + 1);
+        let output_stream_label = format!("[v_out_{}]", i + 1);
+
+        // Build overlay processing filters; trim plus the PTS reset gives each branch its move's slice starting at t=0
+        let (current_overlay_stream, mut overlay_filters) = if group.len() == 1 {
+            (format!("[overlay_{}]", i + 1), slice_filters(i, bg_end - bg_start, false))
+        } else {
+            // Each move's slice runs until the next move's window opens, then they play back to back
+            let mut pieces = String::new();
+            for k in group.clone() {
+                let length = if k == last { bg_end - bg_segs[k][0] } else { bg_segs[k + 1][0] - bg_segs[k][0] };
+                graph.parts.push(format!("[overlay_{}]{}[piece_{}]", k + 1, slice_filters(k, length, k != last).join(","), k + 1));
+                pieces.push_str(&format!("[piece_{}]", k + 1));
+            }
+            (pieces, vec![format!("concat=n={}:v=1:a=0", group.len())])
+        };
+
+        // A persistent board shows the first move's opening frame from the very start
+        let first_persistent = persistent && i == 0;
+        if first_persistent && bg_start > 0.001 {
+            overlay_filters.push(format!("tpad=start_mode=clone:start_duration={}", bg_start));
+        } else if !first_persistent {
+            overlay_filters.push(format!("setpts=PTS+{}/TB", bg_start));
+        }
+        // Only the edges of a run of back-to-back moves animate, so the board doesn't leave between moves
+        let ramp_in = i == 0 || bg_segs[i - 1][1] < bg_start - 0.001;
+        let ramp_out = last + 1 == bg_segs.len() || bg_segs[last + 1][0] > bg_end + 0.001;
+        let ramp_secs = animation.map(|a| a.duration_ms as f64 / 1000.0).unwrap_or(0.0);
+        if animation.is_some_and(|a| a.kind == AnimationKind::Pop) {
+            overlay_filters.extend(pop_filters([bg_start, bg_end], ramp_secs, ramp_in, ramp_out));
+        }
+        if fade_in.is_some() || fade_out.is_some() {
+            overlay_filters.push("format=yuva420p".to_string());
+        }
+        if let Some(d) = fade_in {
+            overlay_filters.push(format!("fade=t=in:st={}:d={}:alpha=1", bg_start, d));
+        }
+        if let Some(d) = fade_out {
+            overlay_filters.push(format!("fade=t=out:st={}:d={}:alpha=1", bg_segs[last + 1][0], d));
+        }
+        // overlay repeats the last frame once a branch ends, which holds the final position
+        let enable = match (persistent, i == 0, last + 1 == overlay_segs.len()) {
+            (true, _, true) => format!("gte(t,{})", if i == 0 { 0.0 } else { bg_start }),
+            (true, true, false) => format!("between(t,0,{})", bg_end),
+            _ => format!("between(t,{},{})", bg_start, bg_end),
+        };
+
+        // Create the overlay processing filter chain
+        let overlay_filter_chain = if overlay_filters.is_empty() {
+            format!("{}{}", current_overlay_stream, processed_overlay_stream)
+        } else {
+            format!("{}{}{}",
+                current_overlay_stream,
+                overlay_filters.join(","),
+                processed_overlay_stream
+            )
+        };
+
+        graph.parts.push(overlay_filter_chain);
+
+        // Create the overlay application filter
+        let (mut x_pos, mut y_pos) = positions.canvas_xy.clone().unwrap_or_else(|| {
+            let [x, y] = positions.at(i);
+            (x.to_string(), y.to_string())
+        });
+        if let Some(animation) = animation.filter(|a| a.kind == AnimationKind::Slide) {
+            if let Some(progress) = animation_progress([bg_start, bg_end], ramp_secs, ramp_in, ramp_out) {
+                let edge = match (animation.edge, &options.canvas) {
+                    (SlideEdge::Nearest, Some(canvas)) => {
+                        nearest_edge(canvas.board.map(f64::from), (canvas.width, canvas.height))
+                    }
+                    (SlideEdge::Nearest, None) => {
+                        let (Some((w, h)), Some(frame)) = (positions.overlay_size, positions.frame_size) else {
+                            return Err("The overlay and background dimensions are needed to slide from the nearest edge".to_string());
+                        };
+                        let [x, y] = positions.at(i);
+                        nearest_edge([x, y, w as f64, h as f64], frame)
+                    }
+                    (edge, _) => edge,
+                };
+                let (x, y) = slide_expressions(edge, &x_pos, &y_pos, &progress);
+                (x_pos, y_pos) = (format!("'{}'", x), format!("'{}'", y));
+            }
+        }
+        let overlay_application = format!(
+            "{}{}overlay={}:{}:enable='{}'{}",
+            graph.video,
+            processed_overlay_stream,
+            x_pos,
+            y_pos,
+            enable,
+            output_stream_label
+        );
+        graph.parts.push(overlay_application);
+
+        graph.video = output_stream_label;
+    }
+    Ok(())
+}
+
+// Mixes the audio of the layers that ask for it over whatever the background track became; false
+// when none does
+fn layer_audio_mix(graph: &mut GraphChains, layers: &[(usize, &ExtraLayer)], audio: &BackgroundAudio, background_has_audio: bool) -> bool {
+    let mixed: Vec<&(usize, &ExtraLayer)> = layers.iter().filter(|(_, layer)| layer.mix_audio).collect();
+    if mixed.is_empty() {
+        return false;
+    }
+    let mut mix_inputs = match (audio.retimed, background_has_audio) {
+        (true, _) => "[a_timeline]".to_string(),
+        (false, true) => audio.stream.clone(),
+        (false, false) => String::new(),
+    };
+    let has_base = !mix_inputs.is_empty();
+    for (input_index, layer) in &mixed {
+        let start = layer.time_range.map(|r| r.start).unwrap_or(0.0);
+        let delay = if start > 0.0 {
+            format!("adelay=delays={}:all=1", (start * 1000.0).round())
+        } else {
+            "anull".to_string()
+        };
+        graph.parts.push(format!("[{}:a]{}[layer_audio_{}]", input_index, delay, input_index));
+        mix_inputs.push_str(&format!("[layer_audio_{}]", input_index));
+    }
+    graph.parts.push(format!(
+        "{}amix=inputs={}:duration={}:dropout_transition=0[a_mix]",
+        mix_inputs,
+        mixed.len() + usize::from(has_base),
+        if has_base { "first" } else { "longest" }
+    ));
+    true
+}
+
+// The clock over everything, then the scale to the output size, then a segment's timestamps back to zero
+fn finishing_chain(graph: &mut GraphChains, plan: &TimingPlan, options: &CompositeOptions) {
+    if let Some(clock) = &options.clock {
+        let filters = clock_filters(clock, &plan.windows, plan.first_move_number(), options.font_file.as_deref());
+        graph.chain(&filters.join(","), "v_clock");
+    }
+
+    if let Some((width, height)) = options.output_size {
+        graph.chain(
+            &format!("scale=w={}:h={}:force_original_aspect_ratio=decrease:force_divisible_by=2", width, height),
+            "v_scaled",
+        );
+    }
+
+    if options.segment.is_some() {
+        graph.chain("setpts=PTS-STARTPTS", "v_segment");
+    }
+}
+
+// The output's audio track, colour tags and file, after the video is mapped
+fn output_args(options: &CompositeOptions, audio: BackgroundAudio, layers_mixed: bool, output_file: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    // The audio track's stream and codec
+    let track = if options.segment.is_some() {
+        // The background's audio is laid under the joined segments in one piece
+        None
+    } else if layers_mixed {
+        Some(("[a_mix]".to_string(), "aac"))
+    } else if audio.retimed {
+        // The rebuilt audio is filtered, so it has to be re-encoded
+        Some(("[a_timeline]".to_string(), "aac"))
+    } else if options.still_background.is_some() {
+        // The generated silence has to be encoded
+        Some((format!("{}:a", audio.input), "aac"))
+    } else if audio.resampled {
+        Some((audio.stream, "aac"))
+    } else {
+        Some((format!("{}:a?", audio.input), "copy"))
+    };
+    match track {
+        Some((stream, codec)) => args.extend(["-map".to_string(), stream, "-c:a".to_string(), codec.to_string()]),
+        None => args.push("-an".to_string()),
+    }
+    if let Some(HdrPath::Passthrough(transfer)) = options.hdr {
+        args.extend(
+            ["-color_primaries", "bt2020", "-color_trc", transfer.ffmpeg_name(), "-colorspace", "bt2020nc"]
+                .iter()
+                .map(|a| a.to_string()),
+        );
+    }
+    if options.transform.is_some() {
+        // The rotation is in the pixels now; a leftover tag would make players rotate it again
+        args.extend(["-metadata:s:v:0".to_string(), "rotate=0".to_string()]);
+    }
+    args.push("-y".to_string());
+    args.push(output_file.to_string());
+    args
+}
+
+pub fn get_multiple_overlay_command(
+    plan: &TimingPlan,
+    background_file: &str,
+    overlay_file: &str,
+    output_file: &str,
+    options: CompositeOptions,
+) -> Result<Vec<String>, String> {
+    if plan.overlay_segs.len() != plan.windows.len() {
+        return Err("The number of overlay segments must match the number of background segments.".to_string());
+    }
+    let positions = BoardPositions::new(&options, plan.overlay_segs.len())?;
+
+    log::info!("Using paths:");
+    log::info!("  Background: {}", background_file);
+    log::info!("  Overlay: {}", overlay_file);
+    log::info!("  Output: {}", output_file);
+
+    let drawn = DrawnMoves::new(plan, &options);
+    let (mut args, inputs) = input_args(plan, background_file, overlay_file, &options, drawn.overlay_seek);
+
+    // The background, the layers under the board, the boards, the layers over them, then the finish
+    let mut graph = GraphChains { parts: Vec::new(), video: "[0:v]".to_string() };
+    let audio = background_chain(&mut graph, plan, &options, &positions, inputs.background_audio)?;
+    layer_chain(&mut graph, &inputs.layers, false);
+    board_branches(&mut graph, plan, &options, &positions, &drawn)?;
+    layer_chain(&mut graph, &inputs.layers, true);
+    let layers_mixed = layer_audio_mix(&mut graph, &inputs.layers, &audio, options.background_has_audio);
+    finishing_chain(&mut graph, plan, &options);
+
+    args.extend(["-filter_complex".to_string(), graph.parts.join(";"), "-map".to_string(), graph.video]);
+    args.extend(output_args(&options, audio, layers_mixed, output_file));
+    Ok(args)
+}
+
+// Long graphs (background treatments, many moves) can exceed the Windows command-line limit
+const MAX_INLINE_FILTER_LEN: usize = 8000;
+
+// Swaps an oversized -filter_complex for -filter_complex_script pointing at a file holding the graph
+pub fn move_filter_to_script(args: &mut [String], script_path: &Path) -> Result<(), String> {
+    let Some(index) = args.iter().position(|a| a == "-filter_complex") else {
+        return Ok(());
+    };
+    if args.get(index + 1).map(|graph| graph.len() <= MAX_INLINE_FILTER_LEN).unwrap_or(true) {
+        return Ok(());
+    }
+
+    fs::write(script_path, &args[index + 1])
+        .map_err(|e| format!("Failed to write {}: {}", script_path.display(), e))?;
+    log::info!("Filter graph is {} bytes, passing it via {}", args[index + 1].len(), script_path.display());
+    args[index] = "-filter_complex_script".to_string();
+    args[index + 1] = path_arg(script_path)?;
+    Ok(())
+}
+
+// What keeps an export from compositing in segments: each of these needs the whole timeline at once,
+// or has no frame rate to cut it on
+pub fn segmented_unsupported(plan: &TimingPlan, options: &CompositeOptions) -> Option<&'static str> {
+    if !plan.spans.is_empty() {
+        Some("a frozen or sped-up background")
+    } else if options.still_background.is_some() {
+        Some("a still-image background")
+    } else if options.synthetic_canvas.is_some() {
+        Some("an audio-only background")
+    } else if !options.layers.is_empty() {
+        Some("extra_layers")
+    } else if options.deinterlace {
+        // bwdif would lose the neighbouring frames at every cut
+        Some("deinterlace")
+    } else {
+        None
+    }
+}
+
+// The ffmpeg job of each segment: the single-pass command limited to the segment's stretch and
+// boards, encoded with `video_args` so the segments join without re-encoding
+pub fn segment_jobs(
+    plan: &TimingPlan,
+    background_file: &str,
+    overlay_file: &str,
+    options: &CompositeOptions,
+    segments: &SegmentPlan,
+    dir: &Path,
+    video_args: &[String],
+) -> Result<Vec<SegmentJob>, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    segments
+        .segments
+        .iter()
+        .map(|segment| {
+            let (path, partial) = SegmentJob::paths(dir, segment);
+            let options = CompositeOptions { segment: Some(segment.clone()), ..options.clone() };
+            let mut args = get_multiple_overlay_command(plan, background_file, overlay_file, &path_arg(&partial)?, options)?;
+            move_filter_to_script(&mut args, &dir.join(format!("filter-{:04}.txt", segment.index + 1)))?;
+            let output_index = args.len() - 1;
+            args.splice(output_index..output_index, video_args.iter().cloned());
+            Ok(SegmentJob { args, path, partial })
+        })
+        .collect()
+}
+
+// Joins the segments by stream copy, with the background's audio and the chapters and metadata
+// the single-pass command would have written. `output_args` go right before the output.
+pub fn segment_concat_args(
+    plan: &TimingPlan,
+    background_file: &str,
+    options: &CompositeOptions,
+    list: &Path,
+    chapters: Option<&Path>,
+    output_args: Vec<String>,
+    output_file: &str,
+) -> Result<Vec<String>, String> {
+    let mut args: Vec<String> = vec!["-f".into(), "concat".into(), "-safe".into(), "0".into(), "-i".into(), path_arg(list)?];
+    if let Some(trim) = plan.trim {
+        args.extend(["-ss".to_string(), trim.start.to_string(), "-t".to_string(), round_ms(trim.end - trim.start).to_string()]);
+    }
+    args.extend(["-i".to_string(), background_file.to_string()]);
+    if let Some(chapters) = chapters {
+        args.extend(["-i".to_string(), path_arg(chapters)?, "-map_metadata".to_string(), "2".to_string(), "-map_chapters".to_string(), "2".to_string()]);
+    }
+    args.extend(["-map".to_string(), "0:v".to_string(), "-map".to_string(), "1:a?".to_string(), "-c:v".to_string(), "copy".to_string()]);
+    // The same audio handling as the single pass: copied, or resampled along with a normalised frame rate
+    if options.cfr_rate.is_some() && options.background_has_audio {
+        args.extend(["-af".to_string(), "aresample=async=1".to_string(), "-c:a".to_string(), "aac".to_string()]);
+    } else {
+        args.extend(["-c:a".to_string(), "copy".to_string()]);
+    }
+    args.extend(output_args);
+    args.extend(["-y".to_string(), output_file.to_string()]);
+    Ok(args)
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct FFmpegResult {
+    pub success: bool,
+    pub output: String,
+    pub error: String,
+    pub return_code: Option<i32>,
+    pub binary: String,
+}
+
+pub async fn execute_ffmpeg_command(
+    app: tauri::AppHandle,
+    args: &[String],
+    on_progress: Option<&(dyn Fn(f64, f64) + Sync)>,
+    low_priority: bool,
+) -> Result<FFmpegResult, String> {
+    // Log the current working directory
+    match env::current_dir() {
+        Ok(current_dir) => {
+            log::info!("FFmpeg executing from directory: {}", current_dir.display());
+        }
+        Err(e) => {
+            log::warn!("Failed to get current directory for FFmpeg: {}", e);
+        }
+    }
+    
+    log::info!("Executing ffmpeg with arguments: {:?}", args);
+    
+    // Find a working ffmpeg (sidecar, settings path or PATH)
+    let resolved = resolve_ffmpeg(&app).await.map_err(|e| e.to_string())?;
+    let binary = resolved.describe();
+    let ffmpeg = ffmpeg_command(&app, &resolved)?;
+    
+    // Machine-readable progress goes to stdout, leaving stderr for the log
+    let ffmpeg = ffmpeg
+        .args(["-progress", "pipe:1", "-nostats"])
+        .args(args.iter().map(|a| for_child_process(a)));
+    
+    let timeout = timeouts::ffmpeg();
+    let mut total_secs: Option<f64> = None;
+
+    let result = run_streaming(&app, ffmpeg, &timeout, low_priority, |line, is_stderr| {
+        if is_stderr {
+            if total_secs.is_none() {
+                total_secs = parse_duration_line(line);
+            }
+            return true;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return true;
+        };
+        if key == "out_time_us" {
+            if let (Some(on_progress), Some(total), Ok(us)) = (on_progress, total_secs, value.parse::<f64>()) {
+                on_progress(us / 1_000_000.0, total);
+            }
+        }
+        false
+    }).await;
+    
+    match &result {
+        Ok(output) => {
+            ffmpeglog::append_invocation(&binary, args, output.code, &output.stdout, &output.stderr);
+            warnings::scan_ffmpeg_stderr(&output.stderr);
+        }
+        Err(e) => ffmpeglog::append_invocation(&binary, args, None, "", &format!("FFmpeg command {}", e)),
+    }
+    
+    match result {
+        Ok(output) => {
+            let return_code = output.code;
+            let success = return_code == Some(0);
+            
+            log::info!("FFmpeg execution completed:");
+            log::info!("Success: {}", success);
+            log::info!("Return code: {:?}", return_code);
+            
+            // Print FULL stderr output - this is key for debugging
+            if !output.stderr.is_empty() {
+                log::info!("=== FULL STDERR OUTPUT ===");
+                log::info!("{}", output.stderr);
+                log::info!("=== END STDERR OUTPUT ===");
+            }
+            
+            if !output.stdout.is_empty() {
+                log::info!("=== FULL STDOUT OUTPUT ===");
+                log::info!("{}", output.stdout);
+                log::info!("=== END STDOUT OUTPUT ===");
+            }
+            
+            Ok(FFmpegResult {
+                success,
+                output: output.stdout,
+                error: output.stderr,
+                return_code,
+                binary,
+            })
+        }
+        Err(e) => {
+            let error_msg = format!("FFmpeg command {}", e);
+            log::error!("{}", error_msg);
+            Ok(FFmpegResult {
+                success: false,
+                output: String::new(),
+                error: error_msg,
+                return_code: Some(-1),
+                binary,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chapters;
+    use crate::export_data::{drop_zero_duration_moves, validate_export_data};
+    use crate::filtergraph;
+    use crate::pipeline::move_chapters;
+    use crate::segments;
+    use serde_json::json;
+    use std::process::Command;
+    use std::sync::Arc;
+
+    // Everything optional left off, as for a plain export onto a 1920x1080 background
+    fn options(position: OverlayPosition) -> CompositeOptions {
+        CompositeOptions {
+            position,
+            frame_size: Some((1920, 1080)),
+            seek_mode: SeekMode::Fast,
+            fps: 30.0,
+            crop: None,
+            treatment: None,
+            overlay_size: None,
+            zoom: None,
+            background_duration: None,
+            background_has_audio: false,
+            layers: Vec::new(),
+            clock: None,
+            font_file: None,
+            flash: None,
+            flash_windows: Vec::new(),
+            output_size: None,
+            overlay_scale: None,
+            canvas: None,
+            letterbox: None,
+            persistent_board: false,
+            segment_positions: Vec::new(),
+            animation: None,
+            transition: None,
+            color_grade: None,
+            deinterlace: false,
+            transform: None,
+            source_rotation: 0,
+            cfr_rate: None,
+            hdr: None,
+            hw_decode: None,
+            still_background: None,
+            synthetic_canvas: None,
+            segment: None,
+        }
+    }
+
+    fn plan(payload: Value) -> (TimingPlan, OverlayPosition) {
+        let (plan, position) = process_overlay_data(&payload).unwrap();
+        (plan.in_seconds(), position)
+    }
+
+    fn command(plan: &TimingPlan, options: CompositeOptions) -> Vec<String> {
+        get_multiple_overlay_command(plan, "background.mp4", "overlay.mp4", "output.mp4", options).unwrap()
+    }
+
+    fn filter_graph(args: &[String]) -> &str {
+        let i = args.iter().position(|a| a == "-filter_complex").expect("no -filter_complex");
+        &args[i + 1]
+    }
+
+    fn three_moves() -> Value {
+        json!({"timestamps": [1.0, 2.5, 4.0], "timePerMove": 0.5, "x_offset": 100, "y_offset": 50})
+    }
+
+    #[test]
+    fn overlay_is_opened_once_and_split_per_move() {
+        let (plan, position) = plan(three_moves());
+        let args = command(&plan, options(position));
+
+        assert_eq!(args.iter().filter(|a| *a == "overlay.mp4").count(), 1);
+        assert_eq!(args.iter().filter(|a| *a == "-i").count(), 2);
+        let parts: Vec<&str> = filter_graph(&args).split(';').collect();
+        assert_eq!(parts[0], "[1:v]split=3[overlay_1][overlay_2][overlay_3]");
+        assert_eq!(
+            &parts[1..],
+            [
+                "[overlay_1]trim=start=0:end=0.5,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=1,setpts=PTS+1/TB[processed_overlay_1]",
+                "[0:v][processed_overlay_1]overlay=100:50:enable='between(t,1,2.5)'[v_out_1]",
+                "[overlay_2]trim=start=0.5:end=1,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=1.5,setpts=PTS+2/TB[processed_overlay_2]",
+                "[v_out_1][processed_overlay_2]overlay=100:50:enable='between(t,2,4)'[v_out_2]",
+                "[overlay_3]trim=start=1:end=1.5,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.5,setpts=PTS+3.5/TB[processed_overlay_3]",
+                "[v_out_2][processed_overlay_3]overlay=100:50:enable='between(t,3.5,4.5)'[v_out_3]",
+            ]
+        );
+        let map = args.iter().position(|a| a == "-map").unwrap();
+        assert_eq!(args[map + 1], "[v_out_3]");
+    }
+
+    // The graph before the split: each move opened its own seeked copy of the overlay
+    fn repeated_input_args(plan: &TimingPlan, background: &str, overlay: &str, output: &str) -> Vec<String> {
+        let mut args = vec!["-y".to_string(), "-i".to_string(), background.to_string()];
+        let mut parts = Vec::new();
+        let mut last = "[0:v]".to_string();
+        for (i, (overlay_seg, bg_seg)) in plan.overlay_segs.iter().zip(&plan.windows).enumerate() {
+            args.extend([
+                "-ss".to_string(), overlay_seg[0].to_string(),
+                "-t".to_string(), (overlay_seg[1] - overlay_seg[0]).to_string(),
+                "-i".to_string(), overlay.to_string(),
+            ]);
+            let freeze = (((bg_seg[1] - bg_seg[0]) - (overlay_seg[1] - overlay_seg[0])) * 1000.0).round() / 1000.0;
+            parts.push(format!(
+                "[{}:v]tpad=stop_mode=clone:stop_duration={},setpts=PTS+{}/TB[processed_overlay_{}]",
+                i + 1, freeze.max(0.0), bg_seg[0], i + 1
+            ));
+            parts.push(format!(
+                "{}[processed_overlay_{}]overlay=0:0:enable='between(t,{},{})'[v_out_{}]",
+                last, i + 1, bg_seg[0], bg_seg[1], i + 1
+            ));
+            last = format!("[v_out_{}]", i + 1);
+        }
+        args.extend(["-filter_complex".to_string(), parts.join(";"), "-map".to_string(), last, output.to_string()]);
+        args
+    }
+
+    fn time_ffmpeg(args: &[String]) -> std::time::Duration {
+        let started = std::time::Instant::now();
+        let status = Command::new("ffmpeg").args(["-v", "error"]).args(args).status().unwrap();
+        assert!(status.success(), "ffmpeg failed: {:?}", args);
+        started.elapsed()
+    }
+
+    // Needs ffmpeg on the PATH: cargo test split_against_repeated_inputs -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn split_against_repeated_inputs() {
+        let dir = env::temp_dir().join(format!("boardcast-split-bench-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let moves = 50;
+        let duration = moves as f64 * 0.5 + 2.0;
+        for (name, source) in [
+            ("background.mp4", format!("testsrc2=s=1920x1080:r=30:d={}", duration)),
+            ("overlay.mp4", format!("testsrc=s=600x600:r=30:d={}", moves as f64 * 0.2)),
+        ] {
+            time_ffmpeg(&["-y".to_string(), "-f".to_string(), "lavfi".to_string(), "-i".to_string(), source, file(name)]);
+        }
+        let timestamps: Vec<f64> = (0..moves).map(|i| 1.0 + i as f64 * 0.5).collect();
+        let (plan, position) = plan(json!({"timestamps": timestamps, "timePerMove": 0.2}));
+
+        let split = get_multiple_overlay_command(
+            &plan, &file("background.mp4"), &file("overlay.mp4"), &file("split.mp4"), options(position),
+        ).unwrap();
+        let split_time = time_ffmpeg(&split);
+        let repeated_time = time_ffmpeg(&repeated_input_args(&plan, &file("background.mp4"), &file("overlay.mp4"), &file("repeated.mp4")));
+        println!("{} moves: split {:?}, repeated inputs {:?}", moves, split_time, repeated_time);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn accurate_seeking_trims_on_frames_instead_of_timestamps() {
+        let (plan, position) = plan(three_moves());
+        let fast = command(&plan, options(position));
+        let accurate = command(&plan, CompositeOptions { seek_mode: SeekMode::Accurate, ..options(position) });
+
+        // Both trim in the graph, so neither seeks the overlay input itself
+        for args in [&fast, &accurate] {
+            let overlay = args.iter().position(|a| a == "overlay.mp4").unwrap();
+            assert_ne!(args[overlay - 2], "-ss");
+        }
+        assert!(filter_graph(&fast).contains("[overlay_2]trim=start=0.5:end=1,"));
+        assert!(!filter_graph(&fast).contains("start_frame"));
+        assert!(filter_graph(&accurate).contains("[overlay_2]trim=start_frame=15:end_frame=30,"));
+        assert_eq!(SeekMode::from_value(&json!({"seek_mode": "accurate"})), Ok(SeekMode::Accurate));
+        assert_eq!(SeekMode::from_value(&json!({})), Ok(SeekMode::Fast));
+        assert!(SeekMode::from_value(&json!({"seek_mode": "exact"})).is_err());
+    }
+
+    #[test]
+    fn render_paths_with_spaces_and_unicode_stay_single_arguments() {
+        let output = Path::new("/Users/John Smith/Documents/board cast (copy)/échecs ♞/sample_exporting/chess animation.mp4");
+        let props = Path::new("/Users/John Smith/Documents/board cast (copy)/échecs ♞/renders/export 1/export.json");
+        let flags = RenderFlags { props, concurrency: Some(2), scale: None, backend: RenderBackend::default() };
+        let args = remotion_render_args(output, Some((0, 59)), flags).unwrap();
+
+        assert_eq!(
+            args,
+            [
+                "remotion",
+                "render",
+                "remotion/index.ts",
+                "Chess",
+                "/Users/John Smith/Documents/board cast (copy)/échecs ♞/sample_exporting/chess animation.mp4",
+                "--props=/Users/John Smith/Documents/board cast (copy)/échecs ♞/renders/export 1/export.json",
+                "--frames=0-59",
+                "--concurrency=2",
+            ]
+        );
+    }
+
+    // Chunk renders that set a flag when they're dropped before finishing
+    struct Cancelled(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl Drop for Cancelled {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failing_chunk_cancels_the_others_and_leaves_no_chunk_files() {
+        let dir = env::temp_dir().join(format!("boardcast-chunks-failed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cancelled = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let result = render_chunks(&dir, &[(0, 29), (30, 59), (60, 89)], |i, _, chunk_path| {
+            let cancelled = cancelled.clone();
+            async move {
+                fs::write(&chunk_path, b"partial").unwrap();
+                if i == 1 {
+                    return Err("renderer crashed".to_string());
+                }
+                let _cancelled = Cancelled(cancelled);
+                std::future::pending::<()>().await;
+                Ok(String::new())
+            }
+        })
+        .await;
+
+        let error = result.err().unwrap();
+        assert_eq!(error, "Chunk 1 (frames 30-59) failed: renderer crashed");
+        assert!(cancelled.load(std::sync::atomic::Ordering::SeqCst) >= 1);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn joined_chunk_files_are_removed_with_their_list() {
+        let dir = env::temp_dir().join(format!("boardcast-chunks-joined-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (outputs, files) = render_chunks(&dir, &[(0, 29), (30, 59)], |i, _, chunk_path| async move {
+            fs::write(&chunk_path, b"frames").unwrap();
+            Ok(format!("chunk {}", i))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(outputs, ["chunk 0", "chunk 1"]);
+        let list = fs::read_to_string(&files.list).unwrap();
+        assert_eq!(list.lines().count(), 2);
+        assert!(list.contains("chunk-000.mp4") && list.contains("chunk-001.mp4"));
+        drop(files);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // process_overlay_data builds whatever graph the numbers give, so run_export validates before it
+    // gets that far and returns the validation error instead
+    #[test]
+    fn payloads_that_validation_rejects_would_have_built_broken_graphs() {
+        for (data, broken) in [
+            (json!({"timestamps": [1.0, 2.5], "timePerMove": 0}), "trim=start=0:end=0,"),
+            (json!({"timestamps": [1.0, 2.5], "timePerMove": -0.5}), "trim=start=-0:end=-0.5,"),
+        ] {
+            let (plan, position) = process_overlay_data(&data).unwrap();
+            assert!(filter_graph(&command(&plan, options(position))).contains(broken));
+            let rejected = validate_export_data(&data, 500).unwrap_err();
+            assert!(rejected.starts_with("timePerMove must be greater than 0"), "{}", rejected);
+        }
+    }
+
+    #[test]
+    fn a_graph_past_8000_characters_is_passed_in_a_script_file() {
+        let dir = env::temp_dir().join(format!("boardcast-filter-script-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("filter_complex.txt");
+        let args = |graph: &str| -> Vec<String> {
+            ["-i", "background.mp4", "-filter_complex", graph, "-map", "[v_out_1]", "-y", "output.mp4"].map(String::from).to_vec()
+        };
+
+        let fits = format!("[0:v]null{}", ",null".repeat((MAX_INLINE_FILTER_LEN - 9) / 5));
+        assert!(fits.len() <= MAX_INLINE_FILTER_LEN && fits.len() + 5 > MAX_INLINE_FILTER_LEN);
+        let mut inline = args(&fits);
+        move_filter_to_script(&mut inline, &script).unwrap();
+        assert_eq!(inline, args(&fits));
+        assert!(!script.exists());
+
+        let long = format!("{},null", fits);
+        let mut moved = args(&long);
+        move_filter_to_script(&mut moved, &script).unwrap();
+        assert_eq!(moved[2..4], ["-filter_complex_script", path_arg(&script).unwrap().as_str()]);
+        assert_eq!((&moved[..2], &moved[4..]), (&args(&long)[..2], &args(&long)[4..]));
+        assert_eq!(fs::read_to_string(&script).unwrap(), long);
+
+        // A command without a graph is left alone
+        let mut plain = ["-i", "background.mp4", "-c", "copy", "output.mp4"].map(String::from).to_vec();
+        move_filter_to_script(&mut plain, &dir.join("unused.txt")).unwrap();
+        assert_eq!(plain, ["-i", "background.mp4", "-c", "copy", "output.mp4"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn overlay_xy(args: &[String]) -> &str {
+        let graph = filter_graph(args);
+        let start = graph.find("overlay=").unwrap() + "overlay=".len();
+        &graph[start..start + graph[start..].find(":enable").unwrap()]
+    }
+
+    #[test]
+    fn fractional_offsets_place_the_board_proportionally() {
+        let (plan, position) = plan(json!({"timestamps": [1.0, 2.5], "x_offset_pct": 0.25, "y_offset_pct": 0.5}));
+        assert_eq!(position, OverlayPosition::Fraction { x: 0.25, y: 0.5 });
+
+        let hd = command(&plan, CompositeOptions { frame_size: Some((1920, 1080)), ..options(position) });
+        let uhd = command(&plan, CompositeOptions { frame_size: Some((3840, 2160)), ..options(position) });
+        assert_eq!(overlay_xy(&hd), "480:540");
+        assert_eq!(overlay_xy(&uhd), "960:1080");
+    }
+
+    #[test]
+    fn the_crop_comes_before_any_scaling_of_the_board() {
+        let (plan, position) = plan(three_moves());
+        let crop = OverlayCrop::from_value(&json!({"overlay_crop": {"x": 40, "y": 20, "width": 600, "height": 600}})).unwrap();
+        let letterbox = Letterbox { width: 1280, height: 720, content: [0, 0, 1280, 720], scale: 2.0 / 3.0, fill: "black".to_string() };
+        let args = command(&plan, CompositeOptions { crop, overlay_scale: Some(2.0), letterbox: Some(letterbox), ..options(position) });
+
+        let spatial = filter_graph(&args).split(';').find(|part| part.starts_with("[1:v]")).unwrap();
+        assert_eq!(
+            spatial,
+            "[1:v]scale=w='trunc(iw*2/2)*2':h='trunc(ih*2/2)*2',crop=600:600:40:20,\
+             scale=w='trunc(iw*0.6666666666666666/2)*2':h='trunc(ih*0.6666666666666666/2)*2',\
+             split=3[overlay_1][overlay_2][overlay_3]"
+        );
+        // The per-move branches only trim and retime what the split hands them
+        assert!(filter_graph(&args).contains("[overlay_1]trim=start=0:end=0.5,setpts=PTS-STARTPTS,"));
+    }
+
+    #[test]
+    fn the_zoom_restarts_in_each_of_two_disjoint_windows() {
+        let zoom = BackgroundZoom::from_value(&json!({"background_zoom": {"amount": 0.1, "mode": "during_overlay"}}))
+            .unwrap()
+            .unwrap();
+        let factor = zoom_expression(zoom, &[[1.0, 2.5], [4.0, 6.0]], None).unwrap();
+        // Each window eases from 1 to 1.1 and back on its own and contributes nothing outside itself
+        assert_eq!(factor, "(1+0.1*(between(t,1,2.5)*sin(PI*(t-1)/1.5)+between(t,4,6)*sin(PI*(t-4)/2)))");
+
+        let whole = BackgroundZoom { mode: ZoomMode::Whole, ..zoom };
+        assert_eq!(zoom_expression(whole, &[[1.0, 2.5], [4.0, 6.0]], Some(30.0)).unwrap(), "(1+0.1*(min(t/30,1)))");
+        assert!(zoom_expression(zoom, &[[2.0, 2.0]], None).is_err());
+    }
+
+    #[test]
+    fn the_canvas_splits_the_frame_between_background_and_board() {
+        let right = canvas_plan(SideBySide::default(), (1920, 1080));
+        assert_eq!((right.width, right.height), (1920, 1080));
+        // 60% of the width for the background, the rest for the board
+        assert_eq!(right.background, [0, 0, 1152, 1080]);
+        assert_eq!(right.board, [1152, 0, 768, 1080]);
+
+        let left = canvas_plan(SideBySide { board_side: BoardSide::Left, ..SideBySide::default() }, (1920, 1080));
+        assert_eq!(left.background, [768, 0, 1152, 1080]);
+        assert_eq!(left.board, [0, 0, 768, 1080]);
+    }
+
+    #[test]
+    fn an_odd_frame_gives_the_board_the_odd_column() {
+        let options = SideBySide { split: 0.5, ..SideBySide::default() };
+        let canvas = canvas_plan(options, (1279, 719));
+        assert_eq!((canvas.width, canvas.height), (1279, 719));
+        // The background's share is rounded down to even, for yuv420p, and the board fills the rest
+        assert_eq!(canvas.background, [0, 0, 638, 719]);
+        assert_eq!(canvas.board, [638, 0, 641, 719]);
+        for split in [0.3, 0.45, 0.6, 0.77] {
+            let canvas = canvas_plan(SideBySide { split, ..options }, (1279, 719));
+            assert_eq!(canvas.background[2] % 2, 0);
+            assert_eq!(canvas.background[2] + canvas.board[2], 1279);
+        }
+    }
+
+    #[test]
+    fn the_background_and_board_are_centred_in_their_regions() {
+        let (plan, position) = plan(three_moves());
+        let canvas = canvas_plan(SideBySide { board_side: BoardSide::Left, ..SideBySide::default() }, (1920, 1080));
+        let args = command(&plan, CompositeOptions { canvas: Some(canvas), ..options(position) });
+        let parts: Vec<&str> = filter_graph(&args).split(';').collect();
+        assert_eq!(
+            &parts[..4],
+            [
+                "[0:v]scale=w=1152:h=1080:force_original_aspect_ratio=decrease:force_divisible_by=2,pad=1920:1080:768+(1152-iw)/2:0+(1080-ih)/2:color=black[bg_canvas]",
+                "[1:v]scale=w=768:h=1080:force_original_aspect_ratio=decrease:force_divisible_by=2,split=3[overlay_1][overlay_2][overlay_3]",
+                "[overlay_1]trim=start=0:end=0.5,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=1,setpts=PTS+1/TB[processed_overlay_1]",
+                "[bg_canvas][processed_overlay_1]overlay=0+(768-w)/2:0+(1080-h)/2:enable='between(t,1,2.5)'[v_out_1]",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_background_is_fitted_inside_the_output_at_even_sizes() {
+        let vertical = letterbox_plan((1920, 1080), (1080, 1920), "black").unwrap();
+        // 1080 * 0.5625 = 607.5 rounds to the even 608
+        assert_eq!((vertical.content, vertical.scale), ([0, 656, 1080, 608], 0.5625));
+        assert!(vertical.padded());
+        assert_eq!(vertical.map([100.0, 50.0]), [56.0, 684.0]);
+
+        let pillarboxed = letterbox_plan((1440, 1080), (1920, 1080), "black").unwrap();
+        assert_eq!((pillarboxed.content, pillarboxed.scale), ([240, 0, 1440, 1080], 1.0));
+        let square = letterbox_plan((1280, 720), (1000, 1000), "black").unwrap();
+        assert_eq!(square.content, [0, 219, 1000, 562]);
+
+        let scaled = letterbox_plan((1920, 1080), (1280, 720), "black").unwrap();
+        assert!(!scaled.padded());
+        assert_eq!(letterbox_plan((1920, 1080), (1920, 1080), "black"), None);
+        assert_eq!(letterbox_plan((0, 0), (1920, 1080), "black"), None);
+    }
+
+    #[test]
+    fn the_bars_are_a_colour_or_a_blurred_copy_of_the_background() {
+        let coloured = letterbox_plan((1920, 1080), (1080, 1920), "#202020").unwrap();
+        assert_eq!(letterbox_filters(&coloured, "[0:v]"), ["[0:v]scale=1080:608,pad=1080:1920:0:656:color=#202020[bg_letterbox]"]);
+
+        let blurred = letterbox_plan((1920, 1080), (1080, 1920), "blur").unwrap();
+        assert_eq!(
+            letterbox_filters(&blurred, "[0:v]"),
+            [
+                "[0:v]split=2[lb_front_src][lb_back_src]",
+                "[lb_back_src]scale=w=1080:h=1920:force_original_aspect_ratio=increase,crop=1080:1920,boxblur=20[lb_back]",
+                "[lb_front_src]scale=1080:608[lb_front]",
+                "[lb_back][lb_front]overlay=0:656[bg_letterbox]",
+            ]
+        );
+        // Nothing would show of a blurred backdrop without bars
+        let unpadded = letterbox_plan((1920, 1080), (1280, 720), "blur").unwrap();
+        assert_eq!(letterbox_filters(&unpadded, "[bg_cfr]"), ["[bg_cfr]scale=1280:720,pad=1280:720:0:0:color=black[bg_letterbox]"]);
+    }
+
+    #[test]
+    fn the_zoom_is_off_once_the_background_is_reframed() {
+        let zoom = Some(BackgroundZoom { amount: 0.1, mode: ZoomMode::DuringOverlay });
+        let canvas = canvas_plan(SideBySide::default(), (1920, 1080));
+        let letterbox = letterbox_plan((1920, 1080), (1080, 1920), "black").unwrap();
+        assert_eq!(zoom_for_layout(zoom, Some(&canvas), None), None);
+        assert_eq!(zoom_for_layout(zoom, None, Some(&letterbox)), None);
+        assert_eq!(zoom_for_layout(zoom, None, None), zoom);
+        assert_eq!(zoom_for_layout(None, Some(&canvas), None), None);
+    }
+
+    #[test]
+    fn atempo_is_chained_past_its_half_to_double_range() {
+        assert_eq!(atempo_chain(1.5), "atempo=1.5");
+        assert_eq!(atempo_chain(2.0), "atempo=2");
+        assert_eq!(atempo_chain(0.5), "atempo=0.5");
+        assert_eq!(atempo_chain(5.0), "atempo=2,atempo=2,atempo=1.25");
+        assert_eq!(atempo_chain(10.0), "atempo=2,atempo=2,atempo=2,atempo=1.25");
+        assert_eq!(atempo_chain(0.25), "atempo=0.5,atempo=0.5");
+        assert_eq!(atempo_chain(0.2), "atempo=0.5,atempo=0.5,atempo=0.8");
+    }
+
+    #[test]
+    fn each_move_plays_its_stretch_of_background_at_its_speed() {
+        let mut payload = three_moves();
+        payload["speed"] = json!([1.0, 2.0, 0.5]);
+        let (plan, position) = plan(payload);
+        let span = |start: f64, end: Option<f64>, speed: f64| BackgroundSpan { start, end, hold: 0.0, speed };
+        assert_eq!(
+            plan.spans,
+            [span(0.0, Some(1.0), 1.0), span(1.0, Some(2.0), 1.0), span(2.0, Some(3.5), 2.0), span(3.5, Some(4.5), 0.5), span(4.5, None, 1.0)]
+        );
+        // Move 2's 1.5s of background take 0.75s, move 3's 1s takes 2s
+        assert_eq!(plan.windows, [[1.0, 2.25], [2.0, 3.75], [2.75, 4.75]]);
+        assert_eq!(plan.output_duration(10.0), 10.25);
+
+        let args = command(&plan, CompositeOptions { background_has_audio: true, ..options(position) });
+        let parts: Vec<&str> = filter_graph(&args).split(';').collect();
+        assert_eq!(
+            &parts[..13],
+            [
+                "[0:v]split=5[bg_src_0][bg_src_1][bg_src_2][bg_src_3][bg_src_4]",
+                "[0:a]asplit=5[bg_asrc_0][bg_asrc_1][bg_asrc_2][bg_asrc_3][bg_asrc_4]",
+                "[bg_src_0]trim=start=0:end=1,setpts=PTS-STARTPTS[bg_piece_0]",
+                "[bg_asrc_0]atrim=start=0:end=1,asetpts=PTS-STARTPTS[bg_apiece_0]",
+                "[bg_src_1]trim=start=1:end=2,setpts=PTS-STARTPTS[bg_piece_1]",
+                "[bg_asrc_1]atrim=start=1:end=2,asetpts=PTS-STARTPTS[bg_apiece_1]",
+                "[bg_src_2]trim=start=2:end=3.5,setpts=(PTS-STARTPTS)/2[bg_piece_2]",
+                "[bg_asrc_2]atrim=start=2:end=3.5,asetpts=PTS-STARTPTS,atempo=2[bg_apiece_2]",
+                "[bg_src_3]trim=start=3.5:end=4.5,setpts=(PTS-STARTPTS)/0.5[bg_piece_3]",
+                "[bg_asrc_3]atrim=start=3.5:end=4.5,asetpts=PTS-STARTPTS,atempo=0.5[bg_apiece_3]",
+                "[bg_src_4]trim=start=4.5,setpts=PTS-STARTPTS[bg_piece_4]",
+                "[bg_asrc_4]atrim=start=4.5,asetpts=PTS-STARTPTS[bg_apiece_4]",
+                "[bg_piece_0][bg_apiece_0][bg_piece_1][bg_apiece_1][bg_piece_2][bg_apiece_2][bg_piece_3][bg_apiece_3]\
+                 [bg_piece_4][bg_apiece_4]concat=n=5:v=1:a=1[bg_timeline][a_timeline]",
+            ]
+        );
+        assert_eq!(parts[15], "[bg_timeline][processed_overlay_1]overlay=100:50:enable='between(t,1,2.25)'[v_out_1]");
+    }
+
+    #[test]
+    fn speeds_outside_a_tenth_to_ten_times_are_rejected() {
+        let mut payload = three_moves();
+        payload["speed"] = json!([1.0, null, 10.0]);
+        assert_eq!(validate_export_data(&payload, 500), Ok(()));
+        payload["speed"] = json!([1.0, 0.05]);
+        assert_eq!(validate_export_data(&payload, 500).unwrap_err(), "speed[1] must be between 0.1 and 10, got 0.05");
+    }
+
+    #[test]
+    fn freezing_lengthens_the_output_by_every_overlay_window() {
+        let continued = plan(three_moves()).0;
+        let mut frozen_payload = three_moves();
+        frozen_payload["background_behavior"] = json!("freeze");
+        let frozen = plan(frozen_payload).0;
+
+        assert!(continued.spans.is_empty());
+        assert_eq!(continued.output_duration(10.0), 10.0);
+        // Each of the three moves holds the background for its 0.5s animation
+        assert_eq!(frozen.spans.iter().map(|s| s.hold).sum::<f64>(), 1.5);
+        assert_eq!(frozen.output_duration(10.0), 11.5);
+        assert_eq!(frozen.output_time(3.0), 4.0);
+        assert!(BackgroundBehavior::from_value(&json!({"background_behavior": "pause"})).is_err());
+    }
+
+    fn flash_graph(timestamps: Vec<f64>) -> (Vec<[f64; 2]>, String) {
+        let (plan, position) = plan(json!({"timestamps": timestamps}));
+        let flash = MoveFlash { duration_ms: 300, intensity: 0.4 };
+        let windows = flash_windows(&plan, &timestamps, flash);
+        let args = command(&plan, CompositeOptions { flash: Some(flash), flash_windows: windows.clone(), ..options(position) });
+        (windows, filter_graph(&args).to_string())
+    }
+
+    #[test]
+    fn one_flash_filter_covers_every_move() {
+        let (windows, graph) = flash_graph(vec![2.0]);
+        assert_eq!(windows, [[2.0, 2.3]]);
+        assert_eq!(
+            graph.split(';').collect::<Vec<_>>(),
+            [
+                "[0:v]eq=brightness='0.4*(between(t,2,2.3)*(2.3-t)/0.3)':eval=frame:enable='between(t,2,2.3)'[bg_flash]",
+                "[1:v]split=1[overlay_1]",
+                "[overlay_1]trim=start=0:end=0.2,setpts=PTS-STARTPTS,setpts=PTS+2/TB[processed_overlay_1]",
+                "[bg_flash][processed_overlay_1]overlay=0:0:enable='between(t,2,2.2)'[v_out_1]",
+            ]
+        );
+
+        let (windows, graph) = flash_graph(vec![2.0, 5.0]);
+        assert_eq!(windows, [[2.0, 2.3], [5.0, 5.3]]);
+        assert_eq!(
+            graph.split(';').collect::<Vec<_>>(),
+            [
+                "[0:v]eq=brightness='0.4*(between(t,2,2.3)*(2.3-t)/0.3+between(t,5,5.3)*(5.3-t)/0.3)':eval=frame:\
+                 enable='between(t,2,2.3)+between(t,5,5.3)'[bg_flash]",
+                "[1:v]split=2[overlay_1][overlay_2]",
+                "[overlay_1]trim=start=0:end=0.2,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=2.8,setpts=PTS+2/TB[processed_overlay_1]",
+                "[bg_flash][processed_overlay_1]overlay=0:0:enable='between(t,2,5)'[v_out_1]",
+                "[overlay_2]trim=start=0.2:end=0.4,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.2,setpts=PTS+4.8/TB[processed_overlay_2]",
+                "[v_out_1][processed_overlay_2]overlay=0:0:enable='between(t,4.8,5.2)'[v_out_2]",
+            ]
+        );
+
+        // The flash stays one filter however long the game, so the graph only grows by the boards
+        let (windows, graph) = flash_graph((0..40).map(|i| 1.0 + i as f64).collect());
+        assert_eq!(windows.len(), 40);
+        let parts: Vec<&str> = graph.split(';').collect();
+        assert_eq!(parts.iter().filter(|p| p.contains("eq=brightness")).count(), 1);
+        assert_eq!(parts.len(), 1 + 1 + 2 * 40);
+        assert_eq!(parts[0].matches("between(t,").count(), 80);
+    }
+
+    #[test]
+    fn board_facecam_and_watermark_share_one_graph() {
+        let (plan, position) = plan(json!({"timestamps": [1.0, 2.5], "timePerMove": 0.5}));
+        // Payload order decides the input indices, z_order the drawing order
+        let layers = ExtraLayer::from_value(&json!({"extra_layers": [
+            {"file": "logo.png", "anchor": "top_right", "z_order": 1},
+            {"file": "facecam.mp4", "anchor": "bottom_left", "scale": 0.5, "z_order": -1, "mix_audio": true},
+        ]}))
+        .unwrap();
+        let args = command(&plan, CompositeOptions { layers, background_has_audio: true, ..options(position) });
+
+        let inputs: Vec<&str> = args.windows(2).filter(|w| w[0] == "-i").map(|w| w[1].as_str()).collect();
+        assert_eq!(inputs, ["background.mp4", "overlay.mp4", "logo.png", "facecam.mp4"]);
+        let logo = args.iter().position(|a| a == "logo.png").unwrap();
+        assert_eq!(args[logo - 3..logo - 1], ["-loop", "1"]);
+
+        let parts: Vec<&str> = filter_graph(&args).split(';').collect();
+        assert_eq!(
+            parts,
+            [
+                "[3:v]scale=w='trunc(iw*0.5/2)*2':h='trunc(ih*0.5/2)*2'[layer_3]",
+                "[0:v][layer_3]overlay=20:H-h-20:eof_action=pass[v_layer_3]",
+                "[1:v]split=2[overlay_1][overlay_2]",
+                "[overlay_1]trim=start=0:end=0.5,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=1,setpts=PTS+1/TB[processed_overlay_1]",
+                "[v_layer_3][processed_overlay_1]overlay=0:0:enable='between(t,1,2.5)'[v_out_1]",
+                "[overlay_2]trim=start=0.5:end=1,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.5,setpts=PTS+2/TB[processed_overlay_2]",
+                "[v_out_1][processed_overlay_2]overlay=0:0:enable='between(t,2,3)'[v_out_2]",
+                "[2:v]scale=w='trunc(iw*1/2)*2':h='trunc(ih*1/2)*2'[layer_2]",
+                "[v_out_2][layer_2]overlay=W-w-20:20:eof_action=pass[v_layer_2]",
+                "[3:a]anull[layer_audio_3]",
+                "[0:a][layer_audio_3]amix=inputs=2:duration=first:dropout_transition=0[a_mix]",
+            ]
+        );
+        let maps: Vec<&str> = args.windows(2).filter(|w| w[0] == "-map").map(|w| w[1].as_str()).collect();
+        assert_eq!(maps, ["[v_layer_2]", "[a_mix]"]);
+    }
+
+    #[test]
+    fn a_move_range_rebases_the_whole_plan() {
+        let data = json!({
+            "timestamps": [2.0, 4.0, 6.0, 8.0, 10.0],
+            "timePerMove": 0.5,
+            "framePerMove": 15,
+            "move_range": [2, 4],
+            "move_range_lead_in": 1.0,
+            "move_range_tail": 1.5,
+        });
+        let (plan, position) = plan(data.clone());
+
+        assert_eq!(plan.trim, Some(SourceTrim { start: 3.0, end: 9.5, first_move: 1, moves: 3 }));
+        // The render starts at move 2's first frame, so the animation times restart at zero too
+        assert_eq!(plan.overlay_segs, [[0.0, 0.5], [0.5, 1.0], [1.0, 1.5]]);
+        // Background windows are measured from the trimmed start, the last one cut at the tail
+        assert_eq!(plan.windows, [[0.5, 3.0], [2.5, 5.0], [4.5, 6.5]]);
+        assert_eq!(plan.groups, [0..1, 1..2, 2..3]);
+        assert_eq!(range_frames(&data), Some((15, 59)));
+        assert_eq!(rendered_frames(&data), 45);
+        assert_eq!(plan.output_duration(60.0), 6.5);
+
+        let args = command(&plan, options(position));
+        let background = args.iter().position(|a| a == "background.mp4").unwrap();
+        assert_eq!(args[..background + 1], ["-ss", "3", "-t", "6.5", "-i", "background.mp4"]);
+        let graph = filter_graph(&args);
+        assert!(graph.starts_with("[1:v]split=3[overlay_1][overlay_2][overlay_3];[overlay_1]trim=start=0:end=0.5,"));
+        assert!(graph.contains("overlay=0:0:enable='between(t,4.5,6.5)'[v_out_3]"));
+    }
+
+    #[test]
+    fn the_last_window_closes_after_its_animation_or_at_the_trim_end() {
+        let data = json!({"timestamps": [1.0, 2.5, 9.0], "timePerMove": 0.5});
+        assert_eq!(closed_timestamps(&data, TimeBase::Seconds, 0.5), [1.0, 2.5, 9.0, 9.5]);
+        let (plan, _) = plan(data.clone());
+        assert_eq!(plan.windows, [[1.0, 2.5], [2.0, 9.0], [8.5, 9.5]]);
+        // The stitched background cuts at the same window starts
+        assert_eq!(move_cuts(&data).unwrap(), [1.0, 2.0, 8.5]);
+
+        let mut ranged = data.clone();
+        ranged["move_range"] = json!([2, 3]);
+        ranged["move_range_tail"] = json!(1.5);
+        assert_eq!(closed_timestamps(&ranged, TimeBase::Seconds, 0.5), [1.0, 2.5, 9.0, 10.5]);
+        // A shorter tail still leaves the last move its whole animation
+        ranged["move_range_tail"] = json!(0.25);
+        assert_eq!(closed_timestamps(&ranged, TimeBase::Seconds, 0.5), [1.0, 2.5, 9.0, 9.5]);
+    }
+
+    #[test]
+    fn offsets_a_frame_away_are_rejected_without_a_board_size() {
+        let frame = (1920, 1080);
+        let (_, position) = plan(json!({"timestamps": [1.0], "x_offset": -1e9, "y_offset": 0}));
+        let message = check_offset_in_frame(position.to_pixels(Some(frame)).unwrap(), frame).unwrap_err();
+        assert_eq!(message, "x_offset is -1000000000, which places the board outside the 1920x1080 frame");
+        assert!(check_offset_in_frame([100.0, 1080.0], frame).unwrap_err().starts_with("y_offset is 1080,"));
+        for inside in [[-1920.0, 0.0], [0.0, -1080.0], [1919.0, 1079.0], [-400.0, 900.0]] {
+            assert_eq!(check_offset_in_frame(inside, frame), Ok(()));
+        }
+    }
+
+    #[test]
+    fn each_out_of_bounds_policy_handles_a_board_past_the_right_and_bottom_edges() {
+        let (position, board, frame) = ([1500.0, 700.0], (600, 600), (1920, 1080));
+        let overflow = Overflow { left: 0.0, top: 0.0, right: 180.0, bottom: 220.0 };
+
+        let message = check_placement(OutOfBounds::Error, position, board, frame).unwrap_err();
+        assert_eq!(
+            message,
+            "The 600x600 board at (1500, 700) extends past the 1920x1080 frame by left 0, top 0, right 180, bottom 220 px; \
+             move it, or set out_of_bounds to \"clamp\" or \"allow\""
+        );
+        assert_eq!(
+            check_placement(OutOfBounds::Clamp, position, board, frame),
+            Ok(Placement { policy: OutOfBounds::Clamp, overflow, clamped_to: Some([1320.0, 480.0]) })
+        );
+        assert_eq!(
+            check_placement(OutOfBounds::Allow, position, board, frame),
+            Ok(Placement { policy: OutOfBounds::Allow, overflow, clamped_to: None })
+        );
+        // A board that fits passes every policy untouched
+        for policy in [OutOfBounds::Error, OutOfBounds::Clamp, OutOfBounds::Allow] {
+            let placement = check_placement(policy, [1320.0, 480.0], board, frame).unwrap();
+            assert_eq!((placement.overflow, placement.clamped_to), (Overflow::default(), None));
+        }
+        // Bigger than the frame, the clamped board keeps its top-left corner on screen
+        let clamped = check_placement(OutOfBounds::Clamp, [100.0, 100.0], (2000, 1200), frame).unwrap();
+        assert_eq!(clamped.clamped_to, Some([0.0, 0.0]));
+    }
+
+    #[test]
+    fn the_progress_expression_ramps_only_the_requested_edges() {
+        assert_eq!(
+            animation_progress([1.0, 3.0], 0.2, true, true).unwrap(),
+            "min(clip((t-1)/0.2,0,1),clip((3-t)/0.2,0,1))"
+        );
+        assert_eq!(animation_progress([1.0, 3.0], 0.2, true, false).unwrap(), "clip((t-1)/0.2,0,1)");
+        assert_eq!(animation_progress([1.0, 3.0], 0.2, false, true).unwrap(), "clip((3-t)/0.2,0,1)");
+        assert_eq!(animation_progress([1.0, 3.0], 0.2, false, false), None);
+        // Two ramps can't take more than half a short window each
+        assert_eq!(ramp_duration([1.0, 1.3], 0.2, true, true), Some(0.15));
+        assert_eq!(ramp_duration([1.0, 1.3], 0.2, true, false), Some(0.2));
+        assert_eq!(ramp_duration([1.0, 1.0], 0.2, true, false), Some(0.001));
+    }
+
+    #[test]
+    fn slides_start_fully_past_their_edge() {
+        // At progress 0 each expression puts the board just outside the frame, at 1 on (100, 50)
+        for (edge, x, y) in [
+            (SlideEdge::Left, "-w+(100+w)*p", "50"),
+            (SlideEdge::Right, "W+(100-W)*p", "50"),
+            (SlideEdge::Top, "100", "-h+(50+h)*p"),
+            (SlideEdge::Bottom, "100", "H+(50-H)*p"),
+        ] {
+            assert_eq!(slide_expressions(edge, "100", "50", "p"), (x.to_string(), y.to_string()));
+        }
+        let frame = (1920, 1080);
+        assert_eq!(nearest_edge([1500.0, 700.0, 300.0, 300.0], frame), SlideEdge::Bottom);
+        assert_eq!(nearest_edge([1500.0, 100.0, 300.0, 300.0], frame), SlideEdge::Top);
+        assert_eq!(nearest_edge([40.0, 400.0, 300.0, 300.0], frame), SlideEdge::Left);
+        assert_eq!(nearest_edge([1580.0, 400.0, 300.0, 300.0], frame), SlideEdge::Right);
+    }
+
+    #[test]
+    fn pops_fade_the_board_in_and_out_inside_its_window() {
+        assert_eq!(
+            pop_filters([1.0, 3.0], 0.2, true, true),
+            ["format=yuva420p", "fade=t=in:st=1:d=0.2:alpha=1", "fade=t=out:st=2.8:d=0.2:alpha=1"]
+        );
+        assert_eq!(pop_filters([1.0, 3.0], 0.2, false, true), ["format=yuva420p", "fade=t=out:st=2.8:d=0.2:alpha=1"]);
+        assert!(pop_filters([1.0, 3.0], 0.2, false, false).is_empty());
+    }
+
+    #[test]
+    fn a_sliding_board_gets_quoted_expressions_in_its_overlay() {
+        let (plan, position) = plan(json!({"timestamps": [1.0, 4.0], "timePerMove": 0.5, "x_offset": 100, "y_offset": 50}));
+        let animation = OverlayAnimation::from_value(&json!({"overlay_animation": {"type": "slide", "duration_ms": 200, "edge": "left"}}))
+            .unwrap();
+        let args = command(&plan, CompositeOptions { animation, ..options(position) });
+        // The windows overlap, so the board slides in once and out once instead of leaving between moves
+        let graph = filter_graph(&args);
+        assert!(graph.contains("[0:v][processed_overlay_1]overlay='-w+(100+w)*clip((t-1)/0.2,0,1)':'50':enable='between(t,1,4)'[v_out_1]"));
+        assert!(graph.contains("[v_out_1][processed_overlay_2]overlay='-w+(100+w)*clip((4.5-t)/0.2,0,1)':'50':enable='between(t,3.5,4.5)'[v_out_2]"));
+    }
+
+    #[test]
+    fn two_moves_a_quarter_second_apart_crossfade() {
+        let data = json!({"timestamps": [1.0, 1.25, 4.0], "timePerMove": 0.125, "segment_transition": {"type": "crossfade", "duration_ms": 250}});
+        let (plan, position) = plan(data.clone());
+        let transition = SegmentTransition::from_value(&data).unwrap();
+        let args = command(&plan, CompositeOptions { transition, ..options(position) });
+
+        assert_eq!(plan.windows, [[1.0, 1.25], [1.125, 4.0], [3.875, 4.125]]);
+        assert_eq!(plan.adjacent, [false, true, false]);
+        let parts: Vec<&str> = filter_graph(&args).split(';').collect();
+        // The first board stays up, fading out, until the second has faded in over it
+        assert_eq!(
+            parts[1..5],
+            [
+                "[overlay_1]trim=start=0:end=0.125,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.25,\
+                 setpts=PTS+1/TB,format=yuva420p,fade=t=out:st=1.125:d=0.25:alpha=1[processed_overlay_1]",
+                "[0:v][processed_overlay_1]overlay=0:0:enable='between(t,1,1.375)'[v_out_1]",
+                "[overlay_2]trim=start=0.125:end=0.25,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=2.75,\
+                 setpts=PTS+1.125/TB,format=yuva420p,fade=t=in:st=1.125:d=0.25:alpha=1[processed_overlay_2]",
+                "[v_out_1][processed_overlay_2]overlay=0:0:enable='between(t,1.125,4)'[v_out_2]",
+            ]
+        );
+        // The third move comes 2.75s later and cuts in as usual
+        assert_eq!(
+            parts[5],
+            "[overlay_3]trim=start=0.25:end=0.375,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.125,setpts=PTS+3.875/TB[processed_overlay_3]"
+        );
+    }
+
+    #[test]
+    fn the_grade_reaches_the_background_before_anything_is_drawn_on_it() {
+        let (plan, position) = plan(three_moves());
+        let color_grade: ColorGrade = serde_json::from_value(json!({
+            "lut_file": r"C:\LUTs\teal orange.cube", "contrast": 1.2, "saturation": 1.1,
+        }))
+        .unwrap();
+        let zoom = BackgroundZoom { amount: 0.1, mode: ZoomMode::DuringOverlay };
+        let treatment = BackgroundTreatment { mode: TreatmentMode::Dim, strength: 0.3, padding_px: 0 };
+        let args = command(&plan, CompositeOptions {
+            color_grade: Some(color_grade),
+            zoom: Some(zoom),
+            treatment: Some(treatment),
+            overlay_size: Some((600, 600)),
+            ..options(position)
+        });
+
+        let parts: Vec<&str> = filter_graph(&args).split(';').collect();
+        let index = |label: &str| parts.iter().position(|p| p.ends_with(label)).unwrap();
+        assert_eq!(
+            parts[index("[bg_graded]")],
+            r"[bg_zoomed]lut3d=file=C\\:/LUTs/teal orange.cube,eq=brightness=0:contrast=1.2:saturation=1.1[bg_graded]"
+        );
+        assert!(index("[bg_zoomed]") < index("[bg_graded]"));
+        assert!(parts[index("[bg_graded]") + 1].starts_with("[bg_graded]split=2[bg_base]"));
+        assert!(index("[bg_graded]") < index("[v_out_1]"));
+        // The board's own branch is never graded
+        let board: Vec<&&str> = parts.iter().filter(|p| p.contains("overlay_")).collect();
+        assert!(board.iter().all(|p| !p.contains("lut3d") && !p.contains("eq=")));
+    }
+
+    #[test]
+    fn a_quarter_turn_makes_a_landscape_background_portrait() {
+        let transform = BackgroundTransform { rotate: 90, hflip: false, vflip: false, honor_rotation_metadata: true };
+        let rotation = applied_rotation(Some(transform), 0);
+        assert_eq!(rotation, 90);
+        let frame_size = rotated_size((1920, 1080), rotation);
+        assert_eq!(frame_size, (1080, 1920));
+
+        let (plan, _) = plan(three_moves());
+        let position = OverlayPosition::Fraction { x: 0.5, y: 0.25 };
+        let args = command(&plan, CompositeOptions {
+            transform: Some(transform),
+            frame_size: Some(frame_size),
+            ..options(position)
+        });
+        let background = args.iter().position(|a| a == "background.mp4").unwrap();
+        assert_eq!(args[background - 2], "-noautorotate");
+        assert!(filter_graph(&args).starts_with("[0:v]transpose=clock[bg_transformed];"));
+        // Placed against the rotated 1080x1920 frame
+        assert_eq!(overlay_xy(&args), "540:480");
+        let tag = args.iter().position(|a| a == "-metadata:s:v:0").unwrap();
+        assert_eq!(args[tag + 1], "rotate=0");
+    }
+
+    #[test]
+    fn rotation_metadata_is_baked_in_before_the_requested_turn() {
+        let transform = BackgroundTransform { rotate: 90, hflip: true, vflip: false, honor_rotation_metadata: true };
+        assert_eq!(transform_filters(transform, 90), ["hflip", "vflip", "hflip"]);
+        assert_eq!(rotated_size((1920, 1080), applied_rotation(Some(transform), 90)), (1920, 1080));
+        let ignored = BackgroundTransform { honor_rotation_metadata: false, ..transform };
+        assert_eq!(transform_filters(ignored, 90), ["transpose=clock", "hflip"]);
+        assert_eq!(transform_filters(BackgroundTransform { rotate: 270, hflip: false, ..ignored }, 0), ["transpose=cclock"]);
+        // Without a transform ffmpeg autorotates by the metadata itself
+        assert_eq!(rotated_size((1920, 1080), applied_rotation(None, 270)), (1080, 1920));
+    }
+
+    #[test]
+    fn hlg_and_pq_backgrounds_take_the_chosen_hdr_path() {
+        for transfer in [HdrTransfer::Hlg, HdrTransfer::Pq] {
+            assert_eq!(hdr_path(HdrHandling::Auto, Some(transfer)), Some(HdrPath::Tonemap(transfer)));
+            assert_eq!(hdr_path(HdrHandling::Tonemap, Some(transfer)), Some(HdrPath::Tonemap(transfer)));
+            assert_eq!(hdr_path(HdrHandling::Passthrough, Some(transfer)), Some(HdrPath::Passthrough(transfer)));
+        }
+        assert_eq!(hdr_path(HdrHandling::Auto, None), None);
+        assert_eq!(hdr_path(HdrHandling::Passthrough, None), None);
+        assert_eq!(hdr_path(HdrHandling::Tonemap, None), Some(HdrPath::Tonemap(HdrTransfer::Pq)));
+    }
+
+    #[test]
+    fn hlg_and_pq_are_tonemapped_from_their_own_transfer() {
+        let (plan, position) = plan(three_moves());
+        for (transfer, name) in [(HdrTransfer::Hlg, "arib-std-b67"), (HdrTransfer::Pq, "smpte2084")] {
+            let args = command(&plan, CompositeOptions { hdr: Some(HdrPath::Tonemap(transfer)), ..options(position) });
+            assert!(filter_graph(&args).starts_with(&format!(
+                "[0:v]zscale=tin={}:pin=bt2020:min=bt2020nc:t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+                 tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p[bg_sdr];",
+                name
+            )));
+            assert!(filter_graph(&args).contains("[bg_sdr][processed_overlay_1]overlay="));
+            assert!(!args.iter().any(|a| a == "-color_trc"));
+
+            let args = command(&plan, CompositeOptions { hdr: Some(HdrPath::Passthrough(transfer)), ..options(position) });
+            assert!(!filter_graph(&args).contains("zscale"));
+            let trc = args.iter().position(|a| a == "-color_trc").unwrap();
+            assert_eq!(args[trc + 1], name);
+        }
+    }
+
+    fn phone_clip(fps: f64, base_fps: Option<f64>) -> VideoProbe {
+        VideoProbe {
+            width: 1080,
+            height: 1920,
+            duration_secs: Some(10.0),
+            has_audio: true,
+            fps: Some(fps),
+            interlaced: None,
+            rotation: 0,
+            base_fps,
+            variable_frame_rate: base_fps.is_some_and(|nominal| (fps - nominal).abs() / nominal > 0.01),
+            hdr_transfer: None,
+            still_image: false,
+        }
+    }
+
+    #[test]
+    fn a_variable_rate_is_normalised_unless_force_cfr_says_otherwise() {
+        let variable = phone_clip(29.47, Some(30.0));
+        assert_eq!(cfr_rate(Some(variable), None), Some(30.0));
+        assert_eq!(cfr_rate(Some(variable), Some(true)), Some(30.0));
+        assert_eq!(cfr_rate(Some(variable), Some(false)), None);
+        let constant = phone_clip(25.0, Some(25.0));
+        assert_eq!(cfr_rate(Some(constant), None), None);
+        assert_eq!(cfr_rate(Some(constant), Some(true)), Some(25.0));
+        // Forced without any rate to go on
+        assert_eq!(cfr_rate(Some(VideoProbe { fps: None, ..phone_clip(25.0, None) }), Some(true)), Some(30.0));
+        assert_eq!(cfr_rate(None, Some(true)), None);
+    }
+
+    #[test]
+    fn a_normalised_background_has_its_frames_and_audio_made_regular() {
+        let (plan, position) = plan(three_moves());
+        let args = command(&plan, CompositeOptions { cfr_rate: Some(30.0), background_has_audio: true, ..options(position) });
+        let graph = filter_graph(&args);
+        assert!(graph.starts_with(
+            "[0:v]fps=30[bg_cfr];[0:a]aresample=async=1[bg_audio];[1:v]split=3[overlay_1][overlay_2][overlay_3];"
+        ));
+        assert!(graph.contains(";[bg_cfr][processed_overlay_1]overlay=100:50:enable='between(t,1,2.5)'[v_out_1];"));
+        assert_eq!(args[args.len() - 8..], ["-map", "[v_out_3]", "-map", "[bg_audio]", "-c:a", "aac", "-y", "output.mp4"]);
+
+        // A silent background has nothing to resample, so nothing is mapped but the optional source audio
+        let args = command(&plan, CompositeOptions { cfr_rate: Some(30.0), background_has_audio: false, ..options(position) });
+        assert!(filter_graph(&args).starts_with("[0:v]fps=30[bg_cfr];[1:v]split=3"));
+        assert_eq!(args[args.len() - 8..], ["-map", "[v_out_3]", "-map", "0:a?", "-c:a", "copy", "-y", "output.mp4"]);
+    }
+
+    #[test]
+    fn a_hardware_decoded_background_is_downloaded_before_any_other_filter() {
+        let (plan, position) = plan(three_moves());
+        let decode = HwDecode { method: "vaapi", download_format: "p010le", software_format: "yuv420p10le" };
+        let args = command(&plan, CompositeOptions { hw_decode: Some(decode), deinterlace: true, ..options(position) });
+        assert_eq!(args[..6], ["-hwaccel", "vaapi", "-hwaccel_output_format", "vaapi", "-i", "background.mp4"]);
+        assert!(filter_graph(&args).starts_with(
+            "[0:v]hwdownload,format=p010le,format=yuv420p10le[bg_downloaded];\
+             [bg_downloaded]bwdif=mode=send_frame[bg_deinterlaced];[1:v]split=3[overlay_1][overlay_2][overlay_3];"
+        ));
+        assert!(filter_graph(&args).contains(";[bg_deinterlaced][processed_overlay_1]overlay=100:50:enable='between(t,1,2.5)'[v_out_1];"));
+
+        // Software decoding leaves the input and the graph as they were
+        let args = command(&plan, options(position));
+        assert_eq!(args[..2], ["-i", "background.mp4"]);
+        assert!(filter_graph(&args).starts_with("[1:v]split=3"));
+    }
+
+    #[test]
+    fn a_still_background_is_looped_past_the_last_window_over_generated_silence() {
+        let (plan, position) = plan(three_moves());
+        // The last window closes at 4.5s
+        assert_eq!(still_duration(&plan), 4.5 + STILL_BACKGROUND_TAIL);
+        let args = command(&plan, CompositeOptions { still_background: Some(6.5), ..options(position) });
+        assert_eq!(
+            args[..args.iter().position(|a| a == "-filter_complex").unwrap()],
+            [
+                "-loop", "1", "-framerate", "30", "-t", "6.5", "-i", "background.mp4",
+                "-i", "overlay.mp4",
+                "-f", "lavfi", "-t", "6.5", "-i", "anullsrc=r=48000:cl=stereo",
+            ]
+        );
+        // The picture is drawn on as it is; only the silence needs encoding
+        assert!(filter_graph(&args).contains(";[0:v][processed_overlay_1]overlay=100:50:enable='between(t,1,2.5)'[v_out_1];"));
+        assert_eq!(args[args.len() - 8..], ["-map", "[v_out_3]", "-map", "2:a", "-c:a", "aac", "-y", "output.mp4"]);
+    }
+
+    #[test]
+    fn an_audio_only_background_plays_under_a_generated_canvas() {
+        let (plan, position) = plan(three_moves());
+        let canvas = SyntheticCanvas { color: "#202020".to_string(), width: 1280, height: 720, fps: 30.0, duration: 12.0 };
+        let args = command(&plan, CompositeOptions { synthetic_canvas: Some(canvas), ..options(position) });
+        assert_eq!(
+            args[..args.iter().position(|a| a == "-filter_complex").unwrap()],
+            ["-f", "lavfi", "-t", "12", "-i", "color=c=#202020:s=1280x720:r=30", "-i", "overlay.mp4", "-i", "background.mp4"]
+        );
+        assert!(filter_graph(&args).contains(";[0:v][processed_overlay_1]overlay=100:50:enable='between(t,1,2.5)'[v_out_1];"));
+        // The commentary is the output's audio, copied from the file the canvas stands in for
+        assert_eq!(args[args.len() - 8..], ["-map", "[v_out_3]", "-map", "2:a?", "-c:a", "copy", "-y", "output.mp4"]);
+    }
+
+    // A small LCG so the property tests are repeatable without a dependency
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+
+        fn range(&mut self, low: f64, high: f64) -> f64 {
+            low + self.next() * (high - low)
+        }
+    }
+
+    // Moves at random millisecond times, far enough apart that the segments fit without shrinking.
+    // The last window always closes at 7s, so every move lands well before that.
+    fn random_payload(rng: &mut Lcg) -> Value {
+        let time_per_move = round_ms(rng.range(0.05, 0.5));
+        let mut timestamps = vec![round_ms(rng.range(0.0, 2.0))];
+        loop {
+            let next = round_ms(timestamps[timestamps.len() - 1] + 2.0 * time_per_move + rng.range(0.01, 1.5));
+            if next + 2.0 * time_per_move > 7.0 {
+                break;
+            }
+            timestamps.push(next);
+        }
+        let behavior = if rng.next() < 0.5 { "continue" } else { "freeze" };
+        json!({"timestamps": timestamps, "timePerMove": time_per_move, "x_offset": 0, "y_offset": 0, "background_behavior": behavior})
+    }
+
+    fn is_multiple(t: f64, rate: f64) -> bool {
+        ((t * rate).round() - t * rate).abs() < 1e-6
+    }
+
+    // Every time the graph gives in seconds: trims, freezes, offsets and the enable windows
+    fn graph_times(graph: &str) -> Vec<f64> {
+        // Stream labels like [1:v] hold numbers that aren't times
+        let mut unlabelled = String::new();
+        let mut in_label = false;
+        for c in graph.chars() {
+            match c {
+                '[' => in_label = true,
+                ']' => in_label = false,
+                c if !in_label => unlabelled.push(c),
+                _ => {}
+            }
+        }
+        let mut times = Vec::new();
+        for part in unlabelled.split([',', ';', '\'', '(', ')', ':']) {
+            let value = part
+                .strip_prefix("start=")
+                .or_else(|| part.strip_prefix("end="))
+                .or_else(|| part.strip_prefix("stop_duration="))
+                .or_else(|| part.strip_prefix("setpts=PTS+").and_then(|p| p.strip_suffix("/TB")))
+                .unwrap_or(part);
+            if let Ok(t) = value.parse::<f64>() {
+                times.push(t);
+            }
+        }
+        times
+    }
+
+    fn assert_ordered(plan: &TimingPlan, payload: &Value) {
+        for seg in plan.overlay_segs.iter().chain(&plan.windows) {
+            assert!(seg[0] <= seg[1], "{:?} is reversed for {}", seg, payload);
+        }
+        for pair in plan.overlay_segs.windows(2) {
+            assert!(pair[0][1] <= pair[1][0], "overlay segments {:?} overlap for {}", pair, payload);
+        }
+        for pair in plan.windows.windows(2) {
+            assert!(pair[0][0] <= pair[1][0] && pair[0][1] <= pair[1][1], "windows {:?} are out of order for {}", pair, payload);
+        }
+    }
+
+    #[test]
+    fn millisecond_boundaries_stay_ordered_for_random_moves() {
+        let mut rng = Lcg(464);
+        for _ in 0..500 {
+            let payload = random_payload(&mut rng);
+            let (plan, position) = plan(payload.clone());
+            assert_ordered(&plan, &payload);
+            let args = command(&plan, options(position));
+            for t in graph_times(filter_graph(&args)) {
+                assert!(is_multiple(t, 1000.0), "{} isn't a whole millisecond for {}", t, payload);
+            }
+        }
+    }
+
+    #[test]
+    fn frame_boundaries_stay_ordered_and_on_frames_for_random_moves() {
+        let mut rng = Lcg(60);
+        let rates = [23.976, 24.0, 25.0, 29.97, 30.0, 50.0, 59.94, 60.0];
+        for _ in 0..500 {
+            let payload = random_payload(&mut rng);
+            let fps = rates[(rng.next() * rates.len() as f64) as usize];
+            let (mut plan, position) = plan(payload.clone());
+            let unsnapped = plan.windows.clone();
+            plan.snap_to_frames(fps);
+            assert_ordered(&plan, &payload);
+            for (snapped, original) in plan.windows.iter().flatten().zip(unsnapped.iter().flatten()) {
+                assert!((snapped - original).abs() <= 0.5 / fps + 0.001, "{} moved more than half a frame from {}", snapped, original);
+            }
+            let args = command(&plan, options(position));
+            for t in graph_times(filter_graph(&args)) {
+                assert!(is_multiple(t, fps), "{} isn't on a {} fps frame for {}", t, fps, payload);
+            }
+        }
+    }
+
+    // Merging, crossfades, speed changes and a move range on top of the random moves
+    fn random_options(rng: &mut Lcg, payload: &mut Value) {
+        let moves = payload["timestamps"].as_array().unwrap().len();
+        payload["merge_gap_ms"] = json!((rng.range(0.0, 1200.0)) as u64);
+        if rng.next() < 0.3 {
+            payload["segment_transition"] = json!({"type": "crossfade", "duration_ms": rng.range(1.0, 400.0) as u64});
+        }
+        if rng.next() < 0.3 {
+            payload["speed"] = json!((0..moves).map(|_| [0.5, 1.0, 2.0][(rng.next() * 3.0) as usize]).collect::<Vec<_>>());
+        }
+        if rng.next() < 0.3 {
+            let first = 1 + (rng.next() * moves as f64) as usize;
+            payload["move_range"] = json!([first, first + (rng.next() * (moves - first + 1) as f64) as usize]);
+        }
+    }
+
+    #[test]
+    fn merged_groups_partition_the_moves_and_their_ranges_stay_ordered() {
+        let mut rng = Lcg(4640);
+        for _ in 0..500 {
+            let mut payload = random_payload(&mut rng);
+            random_options(&mut rng, &mut payload);
+            let (mut plan, _) = plan(payload.clone());
+            if rng.next() < 0.5 {
+                plan.snap_to_frames([24.0, 29.97, 60.0][(rng.next() * 3.0) as usize]);
+            }
+            assert_ordered(&plan, &payload);
+
+            let moves = plan.windows.len();
+            assert_eq!(plan.groups.first().map(|g| g.start), Some(0), "{}", payload);
+            assert_eq!(plan.groups.last().map(|g| g.end), Some(moves), "{}", payload);
+            assert!(plan.groups.iter().all(|g| !g.is_empty()), "{:?} for {}", plan.groups, payload);
+            for pair in plan.groups.windows(2) {
+                assert_eq!(pair[0].end, pair[1].start, "{:?} for {}", plan.groups, payload);
+            }
+
+            let crossfade = SegmentTransition::from_value(&payload).unwrap().map(|t| t.duration_ms as f64 / 1000.0);
+            for persistent in [false, true] {
+                let ranges = plan.group_ranges(crossfade, persistent);
+                assert_eq!(ranges.len(), plan.groups.len());
+                for range in &ranges {
+                    assert!(range[0] <= range[1], "{:?} is reversed for {}", range, payload);
+                }
+                for pair in ranges.windows(2) {
+                    assert!(pair[0][0] <= pair[1][0], "ranges {:?} are out of order for {}", pair, payload);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn frame_100_at_29_97_fps_lands_on_its_frame() {
+        let payload = json!({"timestamps_frames": [100, 250], "background_fps": 29.97, "timePerMove": 0.5, "x_offset": 0, "y_offset": 0});
+        let rate = FrameRate::from_value(&payload).unwrap().unwrap();
+        assert_eq!(rate, FrameRate { num: 30000, den: 1001 });
+        assert_eq!(validate_export_data(&payload, 500), Ok(()));
+
+        // The plan stays in frames: move 1's window closes on frame 250, where move 2 lands
+        let (frames, _) = process_overlay_data(&payload).unwrap();
+        assert_eq!(frames.base, TimeBase::Frames(rate));
+        assert_eq!(frames.windows[0][1], 250.0);
+
+        let (plan, position) = plan(payload);
+        assert_eq!(plan.base, TimeBase::Seconds);
+        let graph = filter_graph(&command(&plan, options(position))).to_string();
+        // Frame 100 is 100 * 1001 / 30000 = 3.33667s
+        let exact = 100.0 * 1001.0 / 30000.0;
+        let start: f64 = graph.split("between(t,").nth(1).and_then(|rest| rest.split(',').next()).unwrap().parse().unwrap();
+        assert!((start - exact).abs() < 1001.0 / 30000.0, "{} is more than a frame from {}", start, exact);
+        assert!(graph.contains(&format!("setpts=PTS+{}/TB", start)), "{}", graph);
+        assert_eq!(plan.windows[0][1], round_ms(250.0 * 1001.0 / 30000.0));
+    }
+
+    #[test]
+    fn frames_without_a_rate_are_rejected_by_the_plan() {
+        let payload = json!({"timestamps_frames": [100, 250], "timePerMove": 0.5});
+        assert_eq!(process_overlay_data(&payload).err().unwrap(), "timestamps_frames needs background_fps");
+    }
+
+    #[test]
+    fn every_move_that_starts_before_the_last_lands_is_reported() {
+        let payload = json!({"timestamps": [1.0, 2.0, 2.3, 2.5, 5.0], "timePerMove": 0.5});
+        assert_eq!(
+            move_segments(&payload).err().unwrap(),
+            "timePerMove doesn't fit between the timestamps (\
+            move 3 is 0.3s after move 2 but would start 0.2s before it lands (timePerMove 0.5s); \
+            move 4 is 0.2s after move 3 but would start 0.3s before it lands (timePerMove 0.5s)\
+            ); space the moves out, lower timePerMove or set auto_shrink"
+        );
+    }
+
+    #[test]
+    fn auto_shrink_gives_each_move_the_time_left() {
+        let payload = json!({"timestamps": [1.0, 2.0, 2.3, 2.5, 5.0], "timePerMove": 0.5, "auto_shrink": true});
+        let MoveSegments { overlay_segs, bg_segs, adjustments, .. } = move_segments(&payload).unwrap();
+        assert_eq!(
+            adjustments,
+            [
+                "move 3 was shortened from 0.5s to 0.299s to fit its 0.3s gap",
+                "move 4 was shortened from 0.5s to 0.199s to fit its 0.2s gap",
+            ]
+        );
+        // The end of each animation is kept, so it still lands on its timestamp
+        assert_eq!(overlay_segs[2..4], [[1.201, 1.5], [1.801, 2.0]]);
+        assert_eq!(bg_segs[2..4], [[2.001, 2.5], [2.301, 5.0]]);
+    }
+
+    #[test]
+    fn each_move_is_checked_against_its_own_duration() {
+        // Moves of 0.5s, 0.8s and 0.3s, each window starting as its animation does
+        let timestamps = [1.0, 2.0, 2.5, 7.0];
+        let mut overlay_segs = [[0.0, 0.5], [0.5, 1.3], [1.3, 1.6]];
+        let mut bg_segs = [[1.0, 2.0], [1.2, 2.5], [2.2, 7.0]];
+        assert_eq!(
+            fit_move_gaps(&mut overlay_segs.clone(), &mut bg_segs.clone(), &timestamps, 0.5, false, TimeBase::Seconds).unwrap_err(),
+            "timePerMove doesn't fit between the timestamps (\
+            move 2 is 1s after move 1 but would start 0.3s before it lands (timePerMove 0.5s)\
+            ); space the moves out, lower timePerMove or set auto_shrink"
+        );
+
+        let adjustments = fit_move_gaps(&mut overlay_segs, &mut bg_segs, &timestamps, 0.5, true, TimeBase::Seconds).unwrap();
+        assert_eq!(adjustments, ["move 2 was shortened from 0.8s to 0.499s to fit its 1s gap"]);
+        assert_eq!(overlay_segs, [[0.0, 0.5], [0.801, 1.3], [1.3, 1.6]]);
+        // Move 3's 0.3s still fits after move 2 lands at 2s
+        assert_eq!(bg_segs, [[1.0, 2.0], [1.501, 2.5], [2.2, 7.0]]);
+    }
+
+    // Five quick moves and a slower one, as in a blitz game
+    fn blitz() -> Value {
+        json!({"timestamps": [1.0, 1.4, 1.7, 2.1, 2.4, 5.0], "timePerMove": 0.2, "x_offset": 100, "y_offset": 50, "merge_gap_ms": 50})
+    }
+
+    #[test]
+    fn rapid_moves_merge_into_one_window_that_plays_every_slice() {
+        let (mut plan, position) = plan(blitz());
+        assert_eq!(plan.groups, vec![0..6]);
+        assert_eq!(plan.merge_windows(0.05, false), 5);
+
+        let args = command(&plan, options(position));
+        let parts: Vec<&str> = filter_graph(&args).split(';').collect();
+        assert_eq!(
+            parts[1..],
+            [
+                "[overlay_1]trim=start=0:end=0.2,setpts=PTS-STARTPTS[piece_1]",
+                "[overlay_2]trim=start=0.2:end=0.4,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.1[piece_2]",
+                "[overlay_3]trim=start=0.4:end=0.6,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.2[piece_3]",
+                "[overlay_4]trim=start=0.6:end=0.8,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.1[piece_4]",
+                "[overlay_5]trim=start=0.8:end=1,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=2.4[piece_5]",
+                "[overlay_6]trim=start=1:end=1.2,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.2[piece_6]",
+                "[piece_1][piece_2][piece_3][piece_4][piece_5][piece_6]concat=n=6:v=1:a=0,setpts=PTS+1/TB[processed_overlay_1]",
+                "[0:v][processed_overlay_1]overlay=100:50:enable='between(t,1,5.2)'[v_out_1]",
+            ]
+        );
+        // Every move's whole slice is still in there, and the pieces fill the merged window exactly
+        let trimmed: f64 = plan.overlay_segs.iter().map(|s| s[1] - s[0]).sum();
+        let frozen: f64 = parts.iter().filter_map(|p| p.split("stop_duration=").nth(1)?.split('[').next()?.parse::<f64>().ok()).sum();
+        assert!((trimmed - 1.2).abs() < 1e-9);
+        assert!((trimmed + frozen - (5.2 - 1.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn each_move_is_placed_and_treated_at_its_own_position() {
+        let mut payload = three_moves();
+        payload["xy_offset"] = json!([[100, 50], [500, 60], [100, 50]]);
+        let (plan, position) = plan(payload);
+        assert_eq!(plan.positions, [[100.0, 50.0], [500.0, 60.0], [100.0, 50.0]]);
+        // The first move's position stands in wherever a single one is needed
+        assert_eq!(position, OverlayPosition::Pixels { x: 100.0, y: 50.0 });
+
+        let treatment = BackgroundTreatment { mode: TreatmentMode::Dim, strength: 0.4, padding_px: 10 };
+        let options = CompositeOptions {
+            segment_positions: plan.positions.clone(),
+            treatment: Some(treatment),
+            overlay_size: Some((400, 400)),
+            ..options(position)
+        };
+        let args = command(&plan, options.clone());
+        let parts: Vec<&str> = filter_graph(&args).split(';').collect();
+        assert_eq!(
+            parts,
+            [
+                // Moves 1 and 3 share a position, so they share a treated region
+                "[0:v]split=2[bg_base][bg_region_src]",
+                "[bg_region_src]crop=420:420:90:40,eq=brightness=-0.4[bg_region]",
+                "[bg_base][bg_region]overlay=90:40:enable='between(t,1,2.5)+between(t,3.5,4.5)'[bg_treated]",
+                "[bg_treated]split=2[bg_base_2][bg_region_src_2]",
+                "[bg_region_src_2]crop=420:420:490:50,eq=brightness=-0.4[bg_region_2]",
+                "[bg_base_2][bg_region_2]overlay=490:50:enable='between(t,2,4)'[bg_treated_2]",
+                "[1:v]split=3[overlay_1][overlay_2][overlay_3]",
+                "[overlay_1]trim=start=0:end=0.5,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=1,setpts=PTS+1/TB[processed_overlay_1]",
+                "[bg_treated_2][processed_overlay_1]overlay=100:50:enable='between(t,1,2.5)'[v_out_1]",
+                "[overlay_2]trim=start=0.5:end=1,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=1.5,setpts=PTS+2/TB[processed_overlay_2]",
+                "[v_out_1][processed_overlay_2]overlay=500:60:enable='between(t,2,4)'[v_out_2]",
+                "[overlay_3]trim=start=1:end=1.5,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.5,setpts=PTS+3.5/TB[processed_overlay_3]",
+                "[v_out_2][processed_overlay_3]overlay=100:50:enable='between(t,3.5,4.5)'[v_out_3]",
+            ]
+        );
+
+        let missing = CompositeOptions { segment_positions: plan.positions[..2].to_vec(), ..options };
+        let error = get_multiple_overlay_command(&plan, "background.mp4", "overlay.mp4", "output.mp4", missing).unwrap_err();
+        assert_eq!(error, "There are 2 per-move positions for 3 overlay segments");
+    }
+
+    #[test]
+    fn moves_at_different_positions_are_not_merged() {
+        let mut payload = blitz();
+        payload["xy_offset"] = json!([[100, 50], [100, 50], [100, 50], [300, 50], [300, 50], [100, 50]]);
+        let (mut plan, position) = plan(payload);
+        assert_eq!(plan.groups, [0..3, 3..5, 5..6]);
+        assert_eq!(plan.merge_windows(0.05, false), 3);
+
+        let graph = filter_graph(&command(&plan, options(position))).to_string();
+        assert!(graph.contains("[piece_1][piece_2][piece_3]concat=n=3:v=1:a=0,setpts=PTS+1/TB[processed_overlay_1]"));
+        assert!(graph.contains("[piece_4][piece_5]concat=n=2:v=1:a=0,setpts=PTS+1.9/TB[processed_overlay_4]"));
+        assert!(graph.contains("[overlay_6]trim=start=1:end=1.2,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.2,setpts=PTS+4.8/TB[processed_overlay_6]"));
+    }
+
+    #[test]
+    fn crossfading_moves_stay_apart() {
+        let mut payload = blitz();
+        payload["segment_transition"] = json!({"type": "crossfade", "duration_ms": 100});
+        let (plan, _) = plan(payload);
+        // Only the slow last move is far enough from the one before it to skip the crossfade
+        assert_eq!(plan.adjacent, [false, true, true, true, true, false]);
+        assert_eq!(plan.groups, [0..1, 1..2, 2..3, 3..4, 4..6]);
+    }
+
+    // Move 3 repeats move 2's timestamp, as a double click on the timeline leaves it
+    fn with_duplicate() -> Value {
+        json!({
+            "timestamps": [1.0, 2.0, 2.0, 4.0],
+            "timePerMove": 0.5,
+            "framePerMove": 15,
+            "moves": ["e4", "e5", "e5", "Nf3"],
+            "positions": ["start", "after e4", "after e5", "after e5 again"],
+            "xy_offset": [[100, 50], [200, 50], [200, 50], [300, 50]],
+            "speed": [1.0, 1.0, 1.0, 2.0],
+            "chapters": true,
+            "clock_overlay": {"format": "move_number"},
+            "move_flash": {"duration_ms": 100},
+        })
+    }
+
+    #[test]
+    fn duplicate_timestamps_are_rejected_by_default() {
+        let mut data = with_duplicate();
+        assert_eq!(
+            drop_zero_duration_moves(&mut data).unwrap_err(),
+            "timestamps[1] and [2] are both 2s; remove the duplicates or set zero_duration_policy to \"skip\""
+        );
+        assert_eq!(data, with_duplicate());
+    }
+
+    #[test]
+    fn a_skipped_duplicate_leaves_every_per_move_output_aligned() {
+        let mut data = with_duplicate();
+        data["zero_duration_policy"] = json!("skip");
+        assert_eq!(drop_zero_duration_moves(&mut data).unwrap(), [2]);
+        for field in ["timestamps", "moves", "positions", "xy_offset", "speed"] {
+            assert_eq!(data[field].as_array().unwrap().len(), 3, "{}", field);
+        }
+        assert_eq!(data["moves"], json!(["e4", "e5", "Nf3"]));
+        assert_eq!(composition_frames(&data), 45);
+
+        let (plan, position) = plan(data.clone());
+        assert_eq!(plan.windows.len(), 3);
+        assert!(plan.windows.iter().all(|w| w[1] - w[0] > 0.001), "{:?}", plan.windows);
+        assert_eq!(plan.positions, [[100.0, 50.0], [200.0, 50.0], [300.0, 50.0]]);
+
+        let titles: Vec<String> = move_chapters(&data, &plan, Some("output.mp4")).into_iter().map(|c| c.title).collect();
+        assert_eq!(titles, ["Move 1: e4", "Move 2: e5", "Move 3: Nf3"]);
+        let clock = ClockOverlay::from_value(&data).unwrap().unwrap();
+        let labels = clock_filters(&clock, &plan.windows, plan.first_move_number(), None);
+        assert_eq!(labels.len(), 3);
+        assert!(labels[2].contains("Move 3"));
+        let move_times: Vec<f64> = data["timestamps"].as_array().unwrap().iter().filter_map(|t| t.as_f64()).collect();
+        let flash = MoveFlash::from_value(&data).unwrap().unwrap();
+        assert_eq!(flash_windows(&plan, &move_times, flash).len(), 3);
+
+        // Nothing in the graph is left switching on and off at the same instant
+        let graph = filter_graph(&command(&plan, options(position))).to_string();
+        assert_eq!(graph.matches("enable='between").count(), 3);
+        assert!(!graph.contains("between(t,2,2)"), "{}", graph);
+    }
+
+    #[test]
+    fn moves_on_the_same_frame_are_duplicates() {
+        let mut data = json!({"timestamps_frames": [30, 60, 60, 120], "background_fps": 30, "moves": ["e4", "e5", "e5", "Nf3"]});
+        assert_eq!(
+            drop_zero_duration_moves(&mut data.clone()).unwrap_err(),
+            "timestamps_frames[1] and [2] are both frame 60; remove the duplicates or set zero_duration_policy to \"skip\""
+        );
+        data["zero_duration_policy"] = json!("skip");
+        assert_eq!(drop_zero_duration_moves(&mut data).unwrap(), [2]);
+        assert_eq!(data["timestamps_frames"], json!([30, 60, 120]));
+        assert_eq!(data["moves"], json!(["e4", "e5", "Nf3"]));
+        assert_eq!(move_count(&data), 3);
+    }
+
+    #[test]
+    fn the_filter_graph_of_two_moves_as_json() {
+        let (plan, position) = plan(json!({"timestamps": [1.0, 2.5], "timePerMove": 0.5, "x_offset": 100, "y_offset": 50}));
+        let args = command(&plan, options(position));
+        assert_eq!(
+            filter_graph(&args),
+            "[1:v]split=2[overlay_1][overlay_2];\
+             [overlay_1]trim=start=0:end=0.5,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=1,setpts=PTS+1/TB[processed_overlay_1];\
+             [0:v][processed_overlay_1]overlay=100:50:enable='between(t,1,2.5)'[v_out_1];\
+             [overlay_2]trim=start=0.5:end=1,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=0.5,setpts=PTS+2/TB[processed_overlay_2];\
+             [v_out_1][processed_overlay_2]overlay=100:50:enable='between(t,2,3)'[v_out_2]"
+        );
+        let expected = json!({
+            "nodes": [
+                {"id": "f0.0", "kind": "filter", "name": "split", "params": [{"key": null, "value": "2"}], "chain": 0},
+                {"id": "f1.0", "kind": "filter", "name": "trim", "params": [{"key": "start", "value": "0"}, {"key": "end", "value": "0.5"}], "chain": 1},
+                {"id": "f1.1", "kind": "filter", "name": "setpts", "params": [{"key": null, "value": "PTS-STARTPTS"}], "chain": 1},
+                {"id": "f1.2", "kind": "filter", "name": "tpad", "params": [{"key": "stop_mode", "value": "clone"}, {"key": "stop_duration", "value": "1"}], "chain": 1},
+                {"id": "f1.3", "kind": "filter", "name": "setpts", "params": [{"key": null, "value": "PTS+1/TB"}], "chain": 1},
+                {"id": "f2.0", "kind": "filter", "name": "overlay", "params": [{"key": null, "value": "100"}, {"key": null, "value": "50"}, {"key": "enable", "value": "between(t,1,2.5)"}], "chain": 2},
+                {"id": "f3.0", "kind": "filter", "name": "trim", "params": [{"key": "start", "value": "0.5"}, {"key": "end", "value": "1"}], "chain": 3},
+                {"id": "f3.1", "kind": "filter", "name": "setpts", "params": [{"key": null, "value": "PTS-STARTPTS"}], "chain": 3},
+                {"id": "f3.2", "kind": "filter", "name": "tpad", "params": [{"key": "stop_mode", "value": "clone"}, {"key": "stop_duration", "value": "0.5"}], "chain": 3},
+                {"id": "f3.3", "kind": "filter", "name": "setpts", "params": [{"key": null, "value": "PTS+2/TB"}], "chain": 3},
+                {"id": "f4.0", "kind": "filter", "name": "overlay", "params": [{"key": null, "value": "100"}, {"key": null, "value": "50"}, {"key": "enable", "value": "between(t,2,3)"}], "chain": 4},
+                {"id": "in:1:v", "kind": "input", "name": "1:v", "params": [], "chain": null},
+                {"id": "in:0:v", "kind": "input", "name": "0:v", "params": [], "chain": null},
+                {"id": "out:v_out_2", "kind": "output", "name": "v_out_2", "params": [], "chain": null},
+            ],
+            "edges": [
+                {"from": "f1.0", "to": "f1.1", "label": null},
+                {"from": "f1.1", "to": "f1.2", "label": null},
+                {"from": "f1.2", "to": "f1.3", "label": null},
+                {"from": "f3.0", "to": "f3.1", "label": null},
+                {"from": "f3.1", "to": "f3.2", "label": null},
+                {"from": "f3.2", "to": "f3.3", "label": null},
+                {"from": "in:1:v", "to": "f0.0", "label": "1:v"},
+                {"from": "f0.0", "to": "f1.0", "label": "overlay_1"},
+                {"from": "in:0:v", "to": "f2.0", "label": "0:v"},
+                {"from": "f1.3", "to": "f2.0", "label": "processed_overlay_1"},
+                {"from": "f0.0", "to": "f3.0", "label": "overlay_2"},
+                {"from": "f2.0", "to": "f4.0", "label": "v_out_1"},
+                {"from": "f3.3", "to": "f4.0", "label": "processed_overlay_2"},
+                {"from": "f4.0", "to": "out:v_out_2", "label": "v_out_2"},
+            ],
+        });
+        // Built from the same string ffmpeg gets, so the quotes around enable are already removed
+        assert_eq!(serde_json::to_value(filtergraph::from_args(&args).unwrap()).unwrap(), expected);
+    }
+
+    #[test]
+    fn unicode_paths_and_labels_survive_into_the_export_args() {
+        let data = json!({
+            "timestamps": [1.0, 2.5],
+            "timePerMove": 0.5,
+            "x_offset": 100,
+            "y_offset": 50,
+            "moves": ["Фxe5#", "O-O; 1-0 = ½"],
+            "chapters": true,
+        });
+        let (plan, position) = plan(data.clone());
+        let (background, output) = ("/videos/ブリッツ対局 $1.mp4", "/exports/Ränkspiel #3: 100%.mkv");
+        let args = get_multiple_overlay_command(&plan, background, "overlay.mp4", output, options(position)).unwrap();
+        // Arguments go to the process as they are, with no quoting of their own
+        assert_eq!(args[args.iter().position(|a| a == "-i").unwrap() + 1], background);
+        assert_eq!(args.last().unwrap(), output);
+
+        let metadata = chapters::ffmetadata(&move_chapters(&data, &plan, Some(output)));
+        assert!(metadata.contains("title=Move 1: Фxe5\\#\n"), "{}", metadata);
+        assert!(metadata.contains("title=Move 2: O-O\\; 1-0 \\= ½\n"), "{}", metadata);
+    }
+
+    // The -ss, -t and -itsoffset of each segment's background input
+    fn segment_reads(plan: &TimingPlan, segments: &SegmentPlan, position: OverlayPosition) -> Vec<(f64, Option<f64>, f64)> {
+        segments
+            .segments
+            .iter()
+            .map(|segment| {
+                let args = command(plan, CompositeOptions { segment: Some(segment.clone()), ..options(position) });
+                let value = |flag: &str| args.iter().position(|a| a == flag).map(|i| args[i + 1].parse::<f64>().unwrap());
+                (value("-ss").unwrap(), value("-t"), value("-itsoffset").unwrap())
+            })
+            .collect()
+    }
+
+    // The 30 fps frames from `offset` for `read` seconds; one right at the end is the next read's
+    fn output_frames(offset: f64, read: f64) -> std::ops::Range<u64> {
+        let frame = |t: f64| (t * 30.0 - 1e-6).ceil() as u64;
+        frame(offset)..frame(offset + read)
+    }
+
+    #[test]
+    fn segmented_composite_lasts_as_long_as_the_single_pass() {
+        let mut payload = three_moves();
+        payload["move_range"] = json!([2, 3]);
+        let (plan, position) = plan(payload);
+        let trim = plan.trim.unwrap();
+        let single_pass = command(&plan, options(position));
+        let i = single_pass.iter().position(|a| a == "-t").unwrap();
+        let length: f64 = single_pass[i + 1].parse().unwrap();
+        assert_eq!(single_pass[i - 1], trim.start.to_string());
+
+        let segments = segments::plan_segments(&plan.group_ranges(None, false), length, 30.0);
+        assert!(segments.segments.len() > 2, "{:?}", segments);
+        let reads = segment_reads(&plan, &segments, position);
+        // Laid out in output time, each segment holds the frames after the previous one's, and
+        // together they hold as many as the single pass
+        let mut next_frame = 0;
+        for ((seek, read, offset), segment) in reads.iter().zip(&segments.segments) {
+            assert!((seek - trim.start - offset).abs() < 1e-9, "{:?} reads the background from {}", segment, seek);
+            let frames = output_frames(*offset, read.unwrap());
+            assert_eq!(frames.start, next_frame, "{:?}", segment);
+            next_frame = frames.end;
+        }
+        assert_eq!(next_frame, (length * 30.0).round() as u64);
+    }
+
+    #[test]
+    fn the_last_segment_reads_an_untrimmed_background_to_its_end() {
+        let (plan, position) = plan(three_moves());
+        let segments = segments::plan_segments(&plan.group_ranges(None, false), 7.0, 30.0);
+        let reads = segment_reads(&plan, &segments, position);
+        let (last, others) = reads.split_last().unwrap();
+        assert_eq!(last.1, None);
+        let next_frame = others.iter().fold(0, |next_frame, (seek, read, offset)| {
+            assert_eq!(seek, offset);
+            let frames = output_frames(*offset, read.unwrap());
+            assert_eq!(frames.start, next_frame);
+            frames.end
+        });
+        assert_eq!(output_frames(last.2, 0.0).start, next_frame);
+
+        // Each segment draws only the boards on screen in it
+        for segment in &segments.segments {
+            let args = command(&plan, CompositeOptions { segment: Some(segment.clone()), ..options(position) });
+            let graph = filter_graph(&args);
+            assert!(graph.contains("setpts=PTS-STARTPTS[v_segment]"), "{}", graph);
+            assert_eq!(graph.matches("overlay=").count(), segment.groups.len(), "{:?}: {}", segment, graph);
+        }
+    }
+
+    #[test]
+    fn each_segment_job_writes_its_partial_file_with_the_joinable_encode() {
+        let (plan, position) = plan(three_moves());
+        let segments = segments::plan_segments(&plan.group_ranges(None, false), 7.0, 30.0);
+        let dir = env::temp_dir().join(format!("boardcast-segment-jobs-{}", std::process::id()));
+        let video_args = ["-c:v".to_string(), "libx264".to_string()];
+        let jobs = segment_jobs(&plan, "background.mp4", "overlay.mp4", &options(position), &segments, &dir, &video_args).unwrap();
+        assert!(dir.is_dir());
+
+        let names: Vec<String> = jobs.iter().map(|job| job.path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert_eq!(
+            names,
+            ["segment-0001-0-1000.mp4", "segment-0002-1000-2000.mp4", "segment-0003-2000-3500.mp4", "segment-0004-3500-4500.mp4", "segment-0005-4500-7000.mp4"]
+        );
+        for job in &jobs {
+            assert_eq!(job.partial, job.path.with_extension("partial.mp4"));
+            assert_eq!(job.args[job.args.len() - 3..], ["-c:v", "libx264", path_arg(&job.partial).unwrap().as_str()]);
+        }
+        assert_eq!(
+            jobs[1].args[..jobs[1].args.len() - 1],
+            [
+                "-ss", "0.9833333333333333", "-t", "1", "-itsoffset", "0.9833333333333333", "-i", "background.mp4", "-i", "overlay.mp4",
+                "-filter_complex",
+                "[1:v]split=1[overlay_1];\
+                 [overlay_1]trim=start=0:end=0.5,setpts=PTS-STARTPTS,tpad=stop_mode=clone:stop_duration=1,setpts=PTS+1/TB[processed_overlay_1];\
+                 [0:v][processed_overlay_1]overlay=100:50:enable='between(t,1,2.5)'[v_out_1];\
+                 [v_out_1]setpts=PTS-STARTPTS[v_segment]",
+                "-map", "[v_segment]", "-an", "-y", "-c:v", "libx264",
+            ]
+        );
+        // Boardless stretches have nothing to overlay, and the last one reads the background to its end
+        assert_eq!(filter_graph(&jobs[0].args), "[0:v]setpts=PTS-STARTPTS[v_segment]");
+        assert_eq!(jobs[4].args[..4], ["-ss", "4.483333333333333", "-itsoffset", "4.483333333333333"]);
+        // Graphs this short stay on the command line
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn failure_of<T: std::fmt::Debug>(result: Result<T, ExportFailure>) -> (FailureStage, &'static str) {
+        let failure = result.unwrap_err();
+        (failure.stage, failure.code)
+    }
+
+    #[test]
+    fn a_bad_overlay_plan_fails_validation() {
+        let unordered = json!({"timestamps": [2.0, 1.0], "timePerMove": 0.5});
+        assert_eq!(failure_of(overlay_plan(&unordered)), (FailureStage::Validation, "overlay_data_invalid"));
+        let no_moves = json!({"timePerMove": 0.5});
+        assert_eq!(failure_of(overlay_plan(&no_moves)), (FailureStage::Validation, "overlay_data_invalid"));
+        assert!(overlay_plan(&three_moves()).is_ok());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,409 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::settings::{OutputRetention, SettingsState};
+
+const MAX_ENTRIES: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub export_id: String,
+    pub status: String,
+    pub finished_at: u64,
+    pub moves: usize,
+    pub video_path: Option<String>,
+    pub output_path: Option<String>,
+    // Wall-clock seconds per pipeline stage, keyed by stage name
+    #[serde(default)]
+    pub stage_durations: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub encode: Option<EncodeStats>,
+    #[serde(default)]
+    pub preview: bool,
+    // outputPath was named by boardcast rather than chosen by the user
+    #[serde(default)]
+    pub managed_output: bool,
+    // The output was deleted by prune_outputs or had already gone
+    #[serde(default)]
+    pub pruned: bool,
+    // The export was run with skip_hooks
+    #[serde(default)]
+    pub hooks_skipped: bool,
+    // Where the finish_action setting copied or moved the outputs
+    #[serde(default)]
+    pub finished_to: Vec<String>,
+    // Frames Remotion rendered, to learn the render timeout from
+    #[serde(default)]
+    pub rendered_frames: Option<u64>,
+}
+
+// Measured encode of a finished export, used to calibrate size estimates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodeStats {
+    pub codec: String,
+    pub width: u32,
+    pub height: u32,
+    pub crf: Option<u32>,
+    pub fps: f64,
+    pub duration_secs: f64,
+    pub size_bytes: u64,
+}
+
+impl EncodeStats {
+    pub fn bits_per_pixel(&self) -> Option<f64> {
+        let pixels = f64::from(self.width) * f64::from(self.height) * self.fps * self.duration_secs;
+        (pixels > 0.0).then(|| self.size_bytes as f64 * 8.0 / pixels)
+    }
+}
+
+// Without a path nothing is saved; that's what a failed app data lookup leaves
+#[derive(Default)]
+pub struct ExportHistory {
+    path: Option<PathBuf>,
+    entries: Mutex<Vec<HistoryEntry>>,
+}
+
+impl ExportHistory {
+    pub fn load(app: &AppHandle) -> Self {
+        let path = app.path().app_data_dir().ok().map(|dir| dir.join("export_history.json"));
+        let entries = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        ExportHistory {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    pub fn record(&self, entry: HistoryEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| e.export_id != entry.export_id);
+        entries.push(entry);
+        let overflow = entries.len().saturating_sub(MAX_ENTRIES);
+        entries.drain(..overflow);
+
+        if let Err(e) = self.save(&entries) {
+            log::warn!("Failed to save export history: {}", e);
+        }
+    }
+
+    fn save(&self, entries: &[HistoryEntry]) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("Failed to resolve the app data directory")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let content = serde_json::to_string_pretty(entries).map_err(|e| e.to_string())?;
+        fs::write(path, content).map_err(|e| e.to_string())
+    }
+
+    fn mark_pruned(&self, export_ids: &HashSet<String>) {
+        let mut entries = self.entries.lock().unwrap();
+        for entry in entries.iter_mut().filter(|e| export_ids.contains(&e.export_id)) {
+            entry.pruned = true;
+        }
+        if let Err(e) = self.save(&entries) {
+            log::warn!("Failed to save export history: {}", e);
+        }
+    }
+
+    // Mean duration of a stage over completed exports with a comparable number of moves
+    pub fn average_stage_duration(&self, stage: &str, moves: usize) -> Option<f64> {
+        let tolerance = (moves / 5).max(2);
+        let durations: Vec<f64> = self.entries.lock().unwrap()
+            .iter()
+            .filter(|e| e.status == "completed" && !e.preview && e.moves.abs_diff(moves) <= tolerance)
+            .filter_map(|e| e.stage_durations.get(stage).copied())
+            .collect();
+
+        if durations.is_empty() {
+            None
+        } else {
+            Some(durations.iter().sum::<f64>() / durations.len() as f64)
+        }
+    }
+
+    // Mean render seconds per frame over completed exports, previews aside as they render small
+    pub fn render_frame_secs(&self) -> Option<f64> {
+        let samples: Vec<f64> = self.entries.lock().unwrap()
+            .iter()
+            .filter(|e| e.status == "completed" && !e.preview)
+            .filter_map(|e| Some(e.stage_durations.get("render")? / e.rendered_frames.filter(|&f| f > 0)? as f64))
+            .collect();
+        (!samples.is_empty()).then(|| samples.iter().sum::<f64>() / samples.len() as f64)
+    }
+
+    // Mean measured bits per pixel over completed exports encoded the same way
+    pub fn measured_bits_per_pixel(&self, codec: &str, width: u32, height: u32, crf: Option<u32>) -> Option<(f64, usize)> {
+        let samples: Vec<f64> = self.entries.lock().unwrap()
+            .iter()
+            .filter(|e| e.status == "completed" && !e.preview)
+            .filter_map(|e| e.encode.as_ref())
+            .filter(|s| s.codec == codec && s.width == width && s.height == height && s.crf == crf)
+            .filter_map(EncodeStats::bits_per_pixel)
+            .collect();
+
+        if samples.is_empty() {
+            None
+        } else {
+            Some((samples.iter().sum::<f64>() / samples.len() as f64, samples.len()))
+        }
+    }
+}
+
+#[command]
+pub fn get_export_history(history: State<'_, ExportHistory>) -> Vec<HistoryEntry> {
+    history.entries()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrunedOutput {
+    pub export_id: String,
+    pub path: String,
+    pub size_bytes: u64,
+    pub age_secs: u64,
+    // Why it goes: "keep_last_n" or "max_total_gb"
+    pub reason: &'static str,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneReport {
+    pub dry_run: bool,
+    pub outputs: Vec<PrunedOutput>,
+    pub freed_bytes: u64,
+    // Outputs in the history that were already moved or deleted
+    pub missing: Vec<String>,
+}
+
+struct Output<'a> {
+    entry: &'a HistoryEntry,
+    path: &'a str,
+    size_bytes: u64,
+}
+
+// Newest first; a later export to the same path (as every preview is) overwrote the older ones' file
+fn managed_outputs(entries: &[HistoryEntry]) -> (Vec<Output<'_>>, Vec<&HistoryEntry>) {
+    // Paths the user also exported to explicitly are theirs
+    let user_paths: HashSet<&str> = entries
+        .iter()
+        .filter(|e| !e.preview && !e.managed_output)
+        .filter_map(|e| e.output_path.as_deref())
+        .collect();
+    let mut paths = HashSet::new();
+    let mut outputs = Vec::new();
+    let mut gone = Vec::new();
+    let mut managed: Vec<&HistoryEntry> = entries.iter().filter(|e| (e.preview || e.managed_output) && !e.pruned).collect();
+    managed.sort_by_key(|e| std::cmp::Reverse(e.finished_at));
+    for entry in managed {
+        let Some(path) = entry.output_path.as_deref().filter(|p| !user_paths.contains(p)) else {
+            continue;
+        };
+        let metadata = fs::metadata(path).ok().filter(|m| m.is_file());
+        match metadata {
+            Some(metadata) if paths.insert(path) => outputs.push(Output { entry, path, size_bytes: metadata.len() }),
+            _ => gone.push(entry),
+        }
+    }
+    (outputs, gone)
+}
+
+fn to_prune<'a>(outputs: Vec<Output<'a>>, retention: &OutputRetention) -> Vec<(Output<'a>, &'static str)> {
+    let max_bytes = retention.max_total_gb.map(|gb| (gb * 1024.0 * 1024.0 * 1024.0) as u64);
+    let mut kept_bytes = 0;
+    let mut pruned = Vec::new();
+    for (i, output) in outputs.into_iter().enumerate() {
+        if retention.keep_last_n.is_some_and(|n| i >= n) {
+            pruned.push((output, "keep_last_n"));
+        } else if max_bytes.is_some_and(|max| kept_bytes + output.size_bytes > max) {
+            pruned.push((output, "max_total_gb"));
+        } else {
+            kept_bytes += output.size_bytes;
+        }
+    }
+    pruned
+}
+
+fn age_secs(path: &Path, finished_at: u64) -> u64 {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    let modified = modified.and_then(|m| m.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs()).unwrap_or(finished_at);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    now.saturating_sub(modified)
+}
+
+// Lists the outputs the retention setting doesn't keep, and deletes them unless `dry_run`
+#[command]
+pub fn prune_outputs(
+    history: State<'_, ExportHistory>,
+    settings: State<'_, SettingsState>,
+    dry_run: bool,
+) -> Result<PruneReport, String> {
+    let retention = settings.get().output_retention;
+    if retention.keep_last_n.is_none() && retention.max_total_gb.is_none() {
+        return Err("No output retention is set; set keep_last_n or max_total_gb first".to_string());
+    }
+    let entries = history.entries();
+    let (outputs, gone) = managed_outputs(&entries);
+    let mut pruned_ids: HashSet<String> = gone.iter().map(|e| e.export_id.clone()).collect();
+    let missing = gone
+        .iter()
+        .filter_map(|e| e.output_path.clone())
+        .filter(|path| !Path::new(path).is_file())
+        .collect();
+
+    let mut report = PruneReport { dry_run, outputs: Vec::new(), freed_bytes: 0, missing };
+    for (output, reason) in to_prune(outputs, &retention) {
+        let age_secs = age_secs(Path::new(output.path), output.entry.finished_at);
+        let error = if dry_run {
+            None
+        } else {
+            match fs::remove_file(output.path) {
+                Ok(_) => None,
+                // Moved away since it was listed
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => Some(format!("Failed to delete {}: {}", output.path, e)),
+            }
+        };
+        if error.is_none() {
+            report.freed_bytes += output.size_bytes;
+            pruned_ids.insert(output.entry.export_id.clone());
+        }
+        report.outputs.push(PrunedOutput {
+            export_id: output.entry.export_id.clone(),
+            path: output.path.to_string(),
+            size_bytes: output.size_bytes,
+            age_secs,
+            reason,
+            error,
+        });
+    }
+    if !dry_run {
+        log::info!("Pruned {} outputs, freeing {} bytes", report.outputs.len(), report.freed_bytes);
+        history.mark_pruned(&pruned_ids);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("boardcast-prune-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn exported(export_id: &str, finished_at: u64, output_path: &Path, preview: bool, managed_output: bool) -> HistoryEntry {
+        HistoryEntry {
+            export_id: export_id.to_string(),
+            status: "completed".to_string(),
+            finished_at,
+            moves: 40,
+            video_path: None,
+            output_path: Some(output_path.display().to_string()),
+            stage_durations: BTreeMap::new(),
+            encode: None,
+            preview,
+            managed_output,
+            pruned: false,
+            hooks_skipped: false,
+            finished_to: Vec::new(),
+            rendered_frames: None,
+        }
+    }
+
+    #[test]
+    fn only_outputs_boardcast_named_are_pruned_newest_kept_first() {
+        let dir = scratch_dir("managed");
+        let file = |name: &str, size: usize| {
+            let path = dir.join(name);
+            fs::write(&path, vec![0u8; size]).unwrap();
+            path
+        };
+        let (preview, auto_1, auto_2, chosen) = (file("preview.mp4", 10), file("auto-1.mp4", 20), file("auto-2.mp4", 30), file("final.mp4", 40));
+        let entries = vec![
+            exported("p1", 100, &preview, true, false),
+            exported("a1", 200, &auto_1, false, true),
+            exported("a2", 300, &auto_2, false, true),
+            // The later preview overwrote the first one's file
+            exported("p2", 400, &preview, true, false),
+            exported("f1", 500, &chosen, false, false),
+            // Deleted by hand since
+            exported("a3", 600, &dir.join("moved.mp4"), false, true),
+        ];
+
+        let (outputs, gone) = managed_outputs(&entries);
+        let listed: Vec<(&str, u64)> = outputs.iter().map(|o| (o.entry.export_id.as_str(), o.size_bytes)).collect();
+        assert_eq!(listed, [("p2", 10), ("a2", 30), ("a1", 20)]);
+        assert_eq!(gone.iter().map(|e| e.export_id.as_str()).collect::<Vec<_>>(), ["a3", "p1"]);
+
+        let reasons = |retention: OutputRetention| -> Vec<(String, &'static str)> {
+            let (outputs, _) = managed_outputs(&entries);
+            to_prune(outputs, &retention).into_iter().map(|(o, reason)| (o.entry.export_id.clone(), reason)).collect()
+        };
+        assert_eq!(reasons(OutputRetention { keep_last_n: Some(2), max_total_gb: None }), [("a1".to_string(), "keep_last_n")]);
+        // With 35 bytes allowed the 30 byte export would bring the total to 40; the older 20 byte one still fits
+        let max_total_gb = Some(35.0 / (1024.0 * 1024.0 * 1024.0));
+        assert_eq!(reasons(OutputRetention { keep_last_n: None, max_total_gb }), [("a2".to_string(), "max_total_gb")]);
+        assert!(reasons(OutputRetention { keep_last_n: Some(3), max_total_gb: None }).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_user_path_stays_theirs_even_when_a_preview_also_wrote_it() {
+        let dir = scratch_dir("shared");
+        let path = dir.join("game.mp4");
+        fs::write(&path, b"video").unwrap();
+        let entries = vec![exported("f1", 100, &path, false, false), exported("p1", 200, &path, true, false)];
+        let (outputs, gone) = managed_outputs(&entries);
+        assert!(outputs.is_empty() && gone.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pruned_entries_are_marked_and_left_out_next_time() {
+        let history = ExportHistory::default();
+        let path = PathBuf::from("/exports/preview.mp4");
+        history.record(exported("p1", 100, &path, true, false));
+        history.record(exported("p2", 200, &path, true, false));
+        history.mark_pruned(&HashSet::from(["p1".to_string()]));
+        let pruned: Vec<(String, bool)> = history.entries().into_iter().map(|e| (e.export_id, e.pruned)).collect();
+        assert_eq!(pruned, [("p1".to_string(), true), ("p2".to_string(), false)]);
+        let entries = history.entries();
+        let (_, gone) = managed_outputs(&entries);
+        assert_eq!(gone.iter().map(|e| e.export_id.as_str()).collect::<Vec<_>>(), ["p2"]);
+    }
+
+    #[test]
+    fn the_render_budget_is_learned_from_completed_full_exports() {
+        let history = ExportHistory::default();
+        assert_eq!(history.render_frame_secs(), None);
+        let path = PathBuf::from("/exports/game.mp4");
+        let rendered = |id: &str, render_secs: f64, frames: Option<u64>, preview: bool, status: &str| HistoryEntry {
+            status: status.to_string(),
+            stage_durations: BTreeMap::from([("render".to_string(), render_secs)]),
+            rendered_frames: frames,
+            ..exported(id, 100, &path, preview, false)
+        };
+        history.record(rendered("e1", 30.0, Some(240), false, "completed"));
+        history.record(rendered("e2", 60.0, Some(240), false, "completed"));
+        // Left out: small preview frames, a failed render, and entries from before frames were recorded
+        history.record(rendered("p1", 1.0, Some(300), true, "completed"));
+        history.record(rendered("e3", 500.0, Some(300), false, "failed"));
+        history.record(rendered("e4", 500.0, None, false, "completed"));
+        history.record(rendered("e5", 500.0, Some(0), false, "completed"));
+        assert_eq!(history.render_frame_secs(), Some(0.1875));
+    }
+}
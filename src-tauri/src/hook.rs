@@ -0,0 +1,410 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+use crate::destination::{native_path, wsl_path};
+use crate::escape::render_command_line;
+use crate::ffmpeglog;
+use crate::paths::path_arg;
+use crate::python::{run_python_script, OsEnvironment, ScriptOptions};
+use crate::settings::SettingsState;
+use crate::warnings;
+
+// Long enough for an upload; run_python_script's own 10 minute default is meant for analyses
+const DEFAULT_POST_TIMEOUT_SECS: u64 = 900;
+// The pre-export hook only looks at the payload, and the user is waiting on it
+const DEFAULT_PRE_TIMEOUT_SECS: u64 = 30;
+
+// A python script run around exports. The pre-export hook gets the export.json path and the
+// export id and can refuse the export; the post-export hook runs after every successful export,
+// e.g. to upload the video, with the output path, the export id and a JSON summary. Both get
+// extra_args after those.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportHook {
+    // A .py file in the script directory, as run_python_script takes it
+    pub script: String,
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    #[serde(default)]
+    pub os_env: OsEnvironment,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+impl ExportHook {
+    // `field` names the hook in the messages: pre_export_hook or post_export_hook
+    pub fn check(&self, field: &str) -> Result<(), String> {
+        if !self.script.ends_with(".py") || self.script.contains(['/', '\\']) {
+            return Err(format!("{} script must be a .py file in the script directory, got \"{}\"", field, self.script));
+        }
+        if self.timeout_secs == Some(0) {
+            return Err(format!("{} timeout_secs must be at least 1", field));
+        }
+        Ok(())
+    }
+
+    // The payload's hook wins over the setting's; a null in the payload turns the setting's off
+    pub fn from_value(data: &Value, field: &str) -> Result<Option<Option<Self>>, String> {
+        let hook = match data.get(field) {
+            None => return Ok(None),
+            Some(Value::Null) => None,
+            Some(value) => Some(
+                serde_json::from_value::<Self>(value.clone()).map_err(|e| format!("Invalid {}: {}", field, e))?,
+            ),
+        };
+        if let Some(hook) = &hook {
+            hook.check(field)?;
+        }
+        Ok(Some(hook))
+    }
+
+    // None also when the payload sets skip_hooks
+    fn resolve(app: &AppHandle, data: &Value, field: &str) -> Result<Option<Self>, String> {
+        if skip_hooks(data) {
+            return Ok(None);
+        }
+        let settings = app.state::<SettingsState>().get();
+        match Self::from_value(data, field)? {
+            Some(hook) => Ok(hook),
+            None if field == "pre_export_hook" => Ok(settings.pre_export_hook),
+            None => Ok(settings.post_export_hook),
+        }
+    }
+
+    fn translate(&self, path: &str) -> String {
+        match self.os_env {
+            OsEnvironment::Wsl => wsl_path(path),
+            OsEnvironment::Windows => path.to_string(),
+        }
+    }
+
+    // Only a script that is plainly missing from a script directory this process can see; a
+    // directory inside WSL is left to the run to report
+    fn missing_script(&self, app: &AppHandle) -> Option<PathBuf> {
+        let settings = app.state::<SettingsState>().get();
+        let dir = match self.os_env {
+            OsEnvironment::Windows => settings.script_dir(),
+            OsEnvironment::Wsl => native_path(&settings.wsl_script_dir()).ok()?,
+        };
+        let path = Path::new(&dir).join(&self.script);
+        (Path::new(&dir).is_dir() && !path.is_file()).then_some(path)
+    }
+
+    async fn run(&self, app: &AppHandle, args: Vec<String>, timeout_secs: u64) -> (String, Result<Value, String>) {
+        let mut args = args;
+        args.extend(self.extra_args.iter().cloned());
+        let command = render_command_line(&self.script, &args);
+        let options = ScriptOptions { structured: true, timeout_secs: Some(timeout_secs), ..Default::default() };
+        let outcome = run_python_script(app.clone(), self.script.clone(), args, Some(self.os_env), Some(false), Some(options)).await;
+        (command, outcome)
+    }
+}
+
+// Exports that set skip_hooks run neither hook, and their history entry says so
+pub fn skip_hooks(data: &Value) -> bool {
+    data.get("skip_hooks").and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+fn last_line(text: &str) -> &str {
+    text.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or_default().trim()
+}
+
+// Why the pre-export hook stopped an export: the script said no, or it couldn't give an answer
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreExportError {
+    Rejected { script: String, reason: String },
+    Unavailable(String),
+}
+
+impl fmt::Display for PreExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreExportError::Rejected { script, reason } => write!(f, "The pre-export hook {} rejected the export: {}", script, reason),
+            PreExportError::Unavailable(detail) => write!(f, "The pre-export hook couldn't run: {}", detail),
+        }
+    }
+}
+
+impl PreExportError {
+    // The history entry's status
+    pub fn status(&self) -> &'static str {
+        match self {
+            PreExportError::Rejected { .. } => "rejected",
+            PreExportError::Unavailable(_) => "failed",
+        }
+    }
+
+    // The export failure's code
+    pub fn code(&self) -> &'static str {
+        match self {
+            PreExportError::Rejected { .. } => "pre_export_hook_rejected",
+            PreExportError::Unavailable(_) => "pre_export_hook_failed",
+        }
+    }
+}
+
+// Runs the pre-export hook on the export.json at `props_path`, which the export only goes ahead
+// after. A rejection carries the script's stdout as the reason.
+pub async fn before_export(app: &AppHandle, export_id: &str, data: &Value, props_path: &Path) -> Result<(), PreExportError> {
+    let hook = match ExportHook::resolve(app, data, "pre_export_hook") {
+        Ok(Some(hook)) => hook,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(PreExportError::Unavailable(e)),
+    };
+    if let Some(path) = hook.missing_script(app) {
+        return Err(PreExportError::Unavailable(format!("{} doesn't exist", path.display())));
+    }
+    let props = path_arg(props_path).map_err(PreExportError::Unavailable)?;
+    let timeout_secs = hook.timeout_secs.unwrap_or(DEFAULT_PRE_TIMEOUT_SECS);
+    log::info!("Running the pre-export hook {}", hook.script);
+    let (command, outcome) = hook.run(app, vec![hook.translate(&props), export_id.to_string()], timeout_secs).await;
+    let run = match outcome {
+        Ok(run) => run,
+        Err(e) => {
+            ffmpeglog::append_run("pre-export hook", &command, None, "", &e);
+            return Err(PreExportError::Unavailable(format!("{}: {}", hook.script, e)));
+        }
+    };
+    let stdout = run["output"].as_str().unwrap_or_default();
+    let stderr = run["stderr"].as_str().unwrap_or_default();
+    let exit_code = run["exit_code"].as_i64().map(|c| c as i32);
+    ffmpeglog::append_run("pre-export hook", &command, exit_code, stdout, stderr);
+    verdict(&hook.script, &run, timeout_secs)
+}
+
+// What a finished pre-export run decided: exit 0 allows the export, any other exit is a rejection
+// with stdout (or the last line of stderr) as the reason, and a stopped run decided nothing
+fn verdict(script: &str, run: &Value, timeout_secs: u64) -> Result<(), PreExportError> {
+    if run["success"] == true {
+        return Ok(());
+    }
+    if run["termination"].is_object() {
+        return Err(PreExportError::Unavailable(format!("{} didn't finish within {}s", script, timeout_secs)));
+    }
+    let stdout = run["output"].as_str().unwrap_or_default();
+    let reason = match stdout.trim() {
+        "" => last_line(run["stderr"].as_str().unwrap_or_default()),
+        stdout => stdout,
+    };
+    let reason = if reason.is_empty() { "no reason given" } else { reason };
+    Err(PreExportError::Rejected { script: script.to_string(), reason: reason.to_string() })
+}
+
+// The main output: outputPath, or the first of outputs that was written
+fn output_path(result: &Value) -> Option<String> {
+    result.get("output_path").and_then(|v| v.as_str()).map(String::from).or_else(|| {
+        result
+            .get("outputs")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .find(|o| o["status"] == "success")
+            .and_then(|o| o.get("path").and_then(|p| p.as_str()).map(String::from))
+    })
+}
+
+// What the script gets to know about the export, with paths as it will open them
+fn summary(result: &Value, translate: &dyn Fn(&str) -> String) -> Value {
+    let path = |value: &Value| value.as_str().map(|p| Value::String(translate(p))).unwrap_or(Value::Null);
+    let outputs: Vec<Value> = result
+        .get("outputs")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .map(|o| serde_json::json!({ "path": path(&o["path"]), "status": o["status"] }))
+        .collect();
+    let warnings: Vec<&Value> = result.get("warnings").and_then(|v| v.as_array()).into_iter().flatten().map(|w| &w["code"]).collect();
+    serde_json::json!({
+        "export_id": result["export_id"],
+        "output_path": path(&result["output_path"]),
+        "outputs": outputs,
+        "output_duration": result["output_duration"],
+        "video_path": path(&result["video_path"]),
+        "timings": result["timings"],
+        "warnings": warnings,
+    })
+}
+
+// How a structured run that didn't succeed ended
+fn failure_reason(run: &Value, timeout_secs: u64) -> String {
+    match run["termination"]["reason"].as_str() {
+        Some("timeout") => format!("timed out after {}s", timeout_secs),
+        Some(reason) => format!("was stopped ({})", reason),
+        None => format!("exited with {:?}", run["exit_code"].as_i64().map(|c| c as i32)),
+    }
+}
+
+// Runs the post-export hook for a finished export, whose `result` it is given and returned with
+// a post_export_hook report. The hook's output goes to the export's log; a failure is a warning
+// and never fails the export. Runs inside the export's context.
+pub async fn after_export(app: &AppHandle, export_id: &str, data: &Value, result: String) -> String {
+    let hook = match ExportHook::resolve(app, data, "post_export_hook") {
+        Ok(Some(hook)) => hook,
+        Ok(None) => return result,
+        Err(e) => {
+            warnings::warn("post_export_hook_failed", format!("The post-export hook didn't run: {}", e));
+            return result;
+        }
+    };
+    let Ok(mut value) = serde_json::from_str::<Value>(&result) else {
+        return result;
+    };
+
+    let args = vec![
+        output_path(&value).map(|p| hook.translate(&p)).unwrap_or_default(),
+        export_id.to_string(),
+        summary(&value, &|p| hook.translate(p)).to_string(),
+    ];
+    let timeout_secs = hook.timeout_secs.unwrap_or(DEFAULT_POST_TIMEOUT_SECS);
+    log::info!("Running the post-export hook {}", hook.script);
+    let (command, outcome) = hook.run(app, args, timeout_secs).await;
+    let report = match outcome {
+        Ok(run) => {
+            let stdout = run["output"].as_str().unwrap_or_default();
+            let stderr = run["stderr"].as_str().unwrap_or_default();
+            let exit_code = run["exit_code"].as_i64().map(|c| c as i32);
+            ffmpeglog::append_run("post-export hook", &command, exit_code, stdout, stderr);
+            if run["success"] != true {
+                warnings::warn_with(
+                    "post_export_hook_failed",
+                    format!("The post-export hook {} {}: {}", hook.script, failure_reason(&run, timeout_secs), last_line(stderr)),
+                    Some(serde_json::json!({ "exit_code": exit_code, "log_path": run["log_path"] })),
+                );
+            }
+            serde_json::json!({
+                "script": hook.script,
+                "success": run["success"],
+                "exit_code": exit_code,
+                "termination": run["termination"],
+                "log_path": run["log_path"],
+            })
+        }
+        Err(e) => {
+            ffmpeglog::append_run("post-export hook", &command, None, "", &e);
+            warnings::warn("post_export_hook_failed", format!("The post-export hook {} couldn't run: {}", hook.script, e));
+            serde_json::json!({ "script": hook.script, "success": false, "error": e })
+        }
+    };
+    value["post_export_hook"] = report;
+    value["warnings"] = serde_json::json!(warnings::collected());
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn hook(os_env: OsEnvironment) -> ExportHook {
+        ExportHook { script: "upload.py".to_string(), extra_args: Vec::new(), os_env, timeout_secs: None }
+    }
+
+    #[test]
+    fn the_payloads_hook_wins_and_a_null_turns_the_settings_off() {
+        assert_eq!(ExportHook::from_value(&json!({}), "post_export_hook"), Ok(None));
+        assert_eq!(ExportHook::from_value(&json!({"post_export_hook": null}), "post_export_hook"), Ok(Some(None)));
+        assert_eq!(
+            ExportHook::from_value(&json!({"post_export_hook": {"script": "upload.py", "os_env": "Wsl"}}), "post_export_hook"),
+            Ok(Some(Some(hook(OsEnvironment::Wsl))))
+        );
+        assert_eq!(
+            ExportHook::from_value(&json!({"post_export_hook": {"script": "../upload.py"}}), "post_export_hook"),
+            Err("post_export_hook script must be a .py file in the script directory, got \"../upload.py\"".to_string())
+        );
+        assert_eq!(
+            ExportHook::from_value(&json!({"post_export_hook": {"script": "upload.py", "timeout_secs": 0}}), "post_export_hook"),
+            Err("post_export_hook timeout_secs must be at least 1".to_string())
+        );
+    }
+
+    #[test]
+    fn the_hook_is_given_the_main_output() {
+        assert_eq!(output_path(&json!({"output_path": "/exports/game.mp4"})).as_deref(), Some("/exports/game.mp4"));
+        let outputs = json!({"outputs": [
+            {"path": "/exports/game.webm", "status": "failed"},
+            {"path": "/exports/game-720p.mp4", "status": "success"},
+        ]});
+        assert_eq!(output_path(&outputs).as_deref(), Some("/exports/game-720p.mp4"));
+        assert_eq!(output_path(&json!({})), None);
+    }
+
+    #[test]
+    fn the_summary_has_paths_as_a_wsl_script_opens_them() {
+        let result = json!({
+            "export_id": "1700000000000-0",
+            "output_path": r"C:\Videos\game.mp4",
+            "outputs": [{"path": r"C:\Videos\game.webm", "status": "success", "size_bytes": 10}],
+            "output_duration": 42.5,
+            "video_path": r"C:\Videos\stream.mkv",
+            "timings": {"total": 60.0},
+            "warnings": [{"code": "silent_output", "message": "The background has no audio, so the output is silent"}],
+            "encode": {"codec": "libx264"},
+        });
+        let wsl = hook(OsEnvironment::Wsl);
+        assert_eq!(
+            summary(&result, &|p| wsl.translate(p)),
+            json!({
+                "export_id": "1700000000000-0",
+                "output_path": "/mnt/c/Videos/game.mp4",
+                "outputs": [{"path": "/mnt/c/Videos/game.webm", "status": "success"}],
+                "output_duration": 42.5,
+                "video_path": "/mnt/c/Videos/stream.mkv",
+                "timings": {"total": 60.0},
+                "warnings": ["silent_output"],
+            })
+        );
+        let windows = hook(OsEnvironment::Windows);
+        assert_eq!(summary(&result, &|p| windows.translate(p))["output_path"], r"C:\Videos\game.mp4");
+    }
+
+    #[test]
+    fn a_failed_hook_says_how_it_ended() {
+        assert_eq!(failure_reason(&json!({"success": false, "exit_code": 2, "termination": null}), 900), "exited with Some(2)");
+        let timed_out = json!({"success": false, "exit_code": null, "termination": {"reason": "timeout", "graceful": true}});
+        assert_eq!(failure_reason(&timed_out, 900), "timed out after 900s");
+        let idle = json!({"success": false, "exit_code": null, "termination": {"reason": "idle_timeout", "graceful": false}});
+        assert_eq!(failure_reason(&idle, 900), "was stopped (idle_timeout)");
+    }
+
+    fn pre_run(success: bool, output: &str, stderr: &str) -> Value {
+        json!({"success": success, "exit_code": if success { 0 } else { 1 }, "output": output, "stderr": stderr, "termination": null})
+    }
+
+    #[test]
+    fn a_rejection_gives_the_scripts_stdout_as_the_reason() {
+        assert_eq!(verdict("house_rules.py", &pre_run(true, "", ""), 30), Ok(()));
+        let rejected = verdict("house_rules.py", &pre_run(false, "The video is longer than 10 minutes\n", "Traceback ...\n"), 30).unwrap_err();
+        assert_eq!(rejected.to_string(), "The pre-export hook house_rules.py rejected the export: The video is longer than 10 minutes");
+        assert_eq!((rejected.status(), rejected.code()), ("rejected", "pre_export_hook_rejected"));
+        // Without stdout the last thing on stderr is the best reason there is
+        assert_eq!(
+            verdict("house_rules.py", &pre_run(false, " ", "checking\nmissing Event header\n\n"), 30),
+            Err(PreExportError::Rejected { script: "house_rules.py".to_string(), reason: "missing Event header".to_string() })
+        );
+        assert_eq!(
+            verdict("house_rules.py", &pre_run(false, "", ""), 30),
+            Err(PreExportError::Rejected { script: "house_rules.py".to_string(), reason: "no reason given".to_string() })
+        );
+    }
+
+    #[test]
+    fn a_hook_that_couldnt_decide_isnt_a_rejection() {
+        let stopped = json!({"success": false, "exit_code": null, "output": "", "stderr": "", "termination": {"reason": "timeout"}});
+        let unavailable = verdict("house_rules.py", &stopped, 30).unwrap_err();
+        assert_eq!(unavailable, PreExportError::Unavailable("house_rules.py didn't finish within 30s".to_string()));
+        assert_eq!((unavailable.status(), unavailable.code()), ("failed", "pre_export_hook_failed"));
+        assert_eq!(
+            PreExportError::Unavailable("/scripts/house_rules.py doesn't exist".to_string()).to_string(),
+            "The pre-export hook couldn't run: /scripts/house_rules.py doesn't exist"
+        );
+    }
+
+    #[test]
+    fn skip_hooks_is_off_unless_set() {
+        assert!(!skip_hooks(&json!({})));
+        assert!(!skip_hooks(&json!({"skip_hooks": false})));
+        assert!(skip_hooks(&json!({"skip_hooks": true})));
+    }
+}
@@ -0,0 +1,210 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+use crate::encoders::{build_hash, ffmpeg_output};
+use crate::export_data::HwaccelDecode;
+use crate::warnings;
+
+// Frames a test decode reads before the export commits to a hardware decoder
+const TEST_FRAMES: &str = "5";
+
+// A hardware decoder for the background, with the frames downloaded for the software filter graph
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HwDecode {
+    pub method: &'static str,
+    // hwdownload can only produce the surface's own layout: nv12, or p010le for 10-bit video
+    pub download_format: &'static str,
+    // What the software decoder would have produced, so the filters after it see the same frames
+    pub software_format: &'static str,
+}
+
+impl HwDecode {
+    fn new(method: &'static str, ten_bit: bool) -> Self {
+        let (download_format, software_format) = if ten_bit { ("p010le", "yuv420p10le") } else { ("nv12", "yuv420p") };
+        HwDecode { method, download_format, software_format }
+    }
+
+    fn surface_format(self) -> &'static str {
+        match self.method {
+            "d3d11va" => "d3d11",
+            "videotoolbox" => "videotoolbox_vld",
+            _ => "vaapi",
+        }
+    }
+
+    // Input options for the background. Keeping the frames on the device makes a decoder that
+    // doesn't work fail the command, where ffmpeg would otherwise quietly decode in software.
+    pub fn input_args(self) -> Vec<String> {
+        vec![
+            "-hwaccel".to_string(), self.method.to_string(),
+            "-hwaccel_output_format".to_string(), self.surface_format().to_string(),
+        ]
+    }
+
+    pub fn download_filter(self) -> String {
+        format!("hwdownload,format={},format={}", self.download_format, self.software_format)
+    }
+}
+
+// How the background was decoded, for the export's result
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodeReport {
+    pub requested: HwaccelDecode,
+    // "software" or the hardware method
+    pub path: String,
+    // Why auto mode or a fallback ended up in software
+    pub reason: Option<String>,
+    // The composite stage, to compare against software decoding
+    pub composite_secs: Option<f64>,
+}
+
+// The ffmpeg build's -hwaccels list, probed once per build
+#[derive(Default)]
+pub struct HwDecodeCache {
+    methods: Mutex<Option<(String, Vec<String>)>>,
+}
+
+fn method_name(choice: HwaccelDecode) -> Option<&'static str> {
+    match choice {
+        HwaccelDecode::D3d11va => Some("d3d11va"),
+        HwaccelDecode::Videotoolbox => Some("videotoolbox"),
+        HwaccelDecode::Vaapi => Some("vaapi"),
+        HwaccelDecode::Auto | HwaccelDecode::Off => None,
+    }
+}
+
+fn platform_method() -> Option<&'static str> {
+    if cfg!(windows) {
+        Some("d3d11va")
+    } else if cfg!(target_os = "macos") {
+        Some("videotoolbox")
+    } else if cfg!(target_os = "linux") {
+        Some("vaapi")
+    } else {
+        None
+    }
+}
+
+// "Hardware acceleration methods:" followed by one name per line
+fn parse_hwaccels(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.ends_with(':'))
+        .map(String::from)
+        .collect()
+}
+
+async fn hwaccels(app: &AppHandle) -> Result<Vec<String>, String> {
+    let build_hash = build_hash(app).await?;
+    let cache = app.state::<HwDecodeCache>();
+    if let Some((_, methods)) = cache.methods.lock().unwrap().as_ref().filter(|(hash, _)| *hash == build_hash) {
+        return Ok(methods.clone());
+    }
+    let (_, listing, _) = ffmpeg_output(app, &["-hide_banner", "-hwaccels"]).await?;
+    let methods = parse_hwaccels(&listing);
+    log::info!("ffmpeg hardware decoders: {:?}", methods);
+    *cache.methods.lock().unwrap() = Some((build_hash, methods.clone()));
+    Ok(methods)
+}
+
+// A few frames of the background through the decoder and the download
+async fn test_decode(app: &AppHandle, decode: HwDecode, background: &str) -> Result<(), String> {
+    let input_args = decode.input_args();
+    let filter = decode.download_filter();
+    let mut args: Vec<&str> = vec!["-hide_banner", "-v", "error"];
+    args.extend(input_args.iter().map(String::as_str));
+    args.extend(["-i", background, "-frames:v", TEST_FRAMES, "-vf", &filter, "-f", "null", "-"]);
+    match ffmpeg_output(app, &args).await? {
+        (true, _, _) => Ok(()),
+        (false, _, stderr) => Err(decode_error(&stderr)),
+    }
+}
+
+// ffmpeg's last word on a failed test decode is the reason it gives up
+fn decode_error(stderr: &str) -> String {
+    stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("test decode failed").trim().to_string()
+}
+
+// The decoder for `background`, None for software. An explicit method has to be in ffmpeg's list;
+// auto mode falls back to software when the platform's decoder is missing or its test decode fails.
+pub async fn choose(
+    app: &AppHandle,
+    requested: HwaccelDecode,
+    background: Option<&str>,
+    ten_bit: bool,
+) -> Result<(Option<HwDecode>, DecodeReport), String> {
+    let software = |reason: Option<String>| {
+        (None, DecodeReport { requested, path: "software".to_string(), reason, composite_secs: None })
+    };
+    let Some(background) = background.filter(|_| requested != HwaccelDecode::Off) else {
+        return Ok(software(None));
+    };
+
+    if let Some(method) = method_name(requested) {
+        let methods = hwaccels(app).await?;
+        if !methods.iter().any(|m| m == method) {
+            return Err(format!("hwaccel_decode {} isn't supported by this ffmpeg build, which has: {}", method, methods.join(", ")));
+        }
+        let decode = HwDecode::new(method, ten_bit);
+        return Ok((Some(decode), DecodeReport { requested, path: method.to_string(), reason: None, composite_secs: None }));
+    }
+
+    let methods = match hwaccels(app).await {
+        Ok(methods) => methods,
+        Err(e) => return Ok(software(Some(format!("listing ffmpeg's hardware decoders failed: {}", e)))),
+    };
+    let Some(method) = platform_method().filter(|m| methods.iter().any(|listed| listed == m)) else {
+        return Ok(software(Some("this ffmpeg build has no hardware decoder for this platform".to_string())));
+    };
+    let decode = HwDecode::new(method, ten_bit);
+    match test_decode(app, decode, background).await {
+        Ok(()) => {
+            log::info!("Decoding the background with {}", method);
+            Ok((Some(decode), DecodeReport { requested, path: method.to_string(), reason: None, composite_secs: None }))
+        }
+        Err(e) => {
+            let reason = format!("the test decode with {} failed: {}", method, e);
+            warnings::warn("hwaccel_fallback", format!("The background is decoded in software because {}", reason));
+            Ok(software(Some(reason)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_method_keeps_its_frames_on_its_own_surface() {
+        let args: Vec<Vec<String>> = ["d3d11va", "videotoolbox", "vaapi"].into_iter().map(|m| HwDecode::new(m, false).input_args()).collect();
+        assert_eq!(args[0], ["-hwaccel", "d3d11va", "-hwaccel_output_format", "d3d11"]);
+        assert_eq!(args[1], ["-hwaccel", "videotoolbox", "-hwaccel_output_format", "videotoolbox_vld"]);
+        assert_eq!(args[2], ["-hwaccel", "vaapi", "-hwaccel_output_format", "vaapi"]);
+    }
+
+    #[test]
+    fn ten_bit_frames_are_downloaded_as_p010_and_handed_on_as_ten_bit_yuv() {
+        assert_eq!(HwDecode::new("vaapi", false).download_filter(), "hwdownload,format=nv12,format=yuv420p");
+        assert_eq!(HwDecode::new("vaapi", true).download_filter(), "hwdownload,format=p010le,format=yuv420p10le");
+    }
+
+    #[test]
+    fn the_hwaccels_listing_is_read_without_its_heading() {
+        let listing = "Hardware acceleration methods:\nvdpau\ncuda\nvaapi\n\n";
+        assert_eq!(parse_hwaccels(listing), ["vdpau", "cuda", "vaapi"]);
+        assert!(parse_hwaccels("Hardware acceleration methods:\n").is_empty());
+        assert_eq!(method_name(HwaccelDecode::Videotoolbox), Some("videotoolbox"));
+        assert_eq!(method_name(HwaccelDecode::Auto), None);
+    }
+
+    #[test]
+    fn a_failed_test_decode_is_put_down_to_ffmpegs_last_line() {
+        let stderr = "[AVHWDeviceContext @ 0x1] No VA display found for device /dev/dri/renderD128.\n\
+                      Device creation failed: -22.\n\
+                      Failed to set value 'vaapi' for option 'hwaccel': Invalid argument  \n\n";
+        assert_eq!(decode_error(stderr), "Failed to set value 'vaapi' for option 'hwaccel': Invalid argument");
+        assert_eq!(decode_error(""), "test decode failed");
+    }
+}
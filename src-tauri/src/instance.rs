@@ -0,0 +1,148 @@
+use fs4::fs_std::FileExt;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+
+use crate::paths::ProjectPaths;
+use crate::process::is_process_alive;
+
+// In the project root, next to remotion/ and sample_exporting/. The single-instance plugin only
+// sees other GUI launches; this file is also what a CLI or a build with another identifier checks.
+const LOCK_FILE: &str = ".boardcast.lock";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LockOwner {
+    pid: u32,
+    started_at: u64,
+}
+
+struct HeldLock {
+    path: PathBuf,
+    // The OS lock lasts as long as the file stays open
+    _file: File,
+}
+
+impl Drop for HeldLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// This process's hold on the workspace: taken at startup, retried before each export while another
+// process has it or the project root couldn't be found yet
+#[derive(Default)]
+pub struct InstanceLock {
+    held: Mutex<Option<HeldLock>>,
+}
+
+fn read_owner(path: &Path) -> Option<LockOwner> {
+    fs::read_to_string(path).ok().and_then(|content| serde_json::from_str(&content).ok())
+}
+
+fn acquire(root: &Path) -> Result<HeldLock, String> {
+    let path = root.join(LOCK_FILE);
+    let recorded = read_owner(&path);
+    let busy = |pid: Option<u32>| match pid {
+        Some(pid) => format!("Another boardcast process (pid {}) is using the workspace {}", pid, root.display()),
+        None => format!("Another boardcast process is using the workspace {}", root.display()),
+    };
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    if !file.try_lock_exclusive().map_err(|e| format!("Failed to lock {}: {}", path.display(), e))? {
+        return Err(busy(recorded.map(|o| o.pid)));
+    }
+    // A process that writes its pid without taking the lock still counts while it runs
+    if let Some(owner) = recorded {
+        if owner.pid != std::process::id() && is_process_alive(owner.pid) {
+            return Err(busy(Some(owner.pid)));
+        }
+        if owner.pid != std::process::id() {
+            log::info!("Removing the stale workspace lock of pid {}, which is no longer running", owner.pid);
+        }
+    }
+    let owner = LockOwner {
+        pid: std::process::id(),
+        started_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+    };
+    let content = serde_json::to_string(&owner).map_err(|e| e.to_string())?;
+    file.set_len(0)
+        .and_then(|_| file.write_all(content.as_bytes()))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(HeldLock { path, _file: file })
+}
+
+impl InstanceLock {
+    // Ok once this process holds the workspace lock
+    pub fn ensure(&self, app: &AppHandle) -> Result<(), String> {
+        let mut held = self.held.lock().unwrap();
+        if held.is_none() {
+            let root = ProjectPaths::resolve(app)?.root;
+            let lock = acquire(&root)?;
+            log::info!("Holding the workspace lock {}", lock.path.display());
+            *held = Some(lock);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("boardcast-instance-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn record_owner(root: &Path, pid: u32) {
+        fs::write(root.join(LOCK_FILE), serde_json::to_string(&LockOwner { pid, started_at: 0 }).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn the_workspace_is_held_until_the_lock_is_dropped() {
+        let root = workspace("held");
+        let lock = acquire(&root).unwrap();
+        assert_eq!(read_owner(&root.join(LOCK_FILE)).unwrap().pid, std::process::id());
+        assert_eq!(
+            acquire(&root).err(),
+            Some(format!("Another boardcast process (pid {}) is using the workspace {}", std::process::id(), root.display()))
+        );
+        drop(lock);
+        assert!(!root.join(LOCK_FILE).exists());
+        assert!(acquire(&root).is_ok());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_crashed_owners_lock_is_taken_over_and_a_running_ones_is_not() {
+        let root = workspace("stale");
+        let mut exited = std::process::Command::new("true").spawn().unwrap();
+        exited.wait().unwrap();
+        record_owner(&root, exited.id());
+        let lock = acquire(&root).unwrap();
+        assert_eq!(read_owner(&root.join(LOCK_FILE)).unwrap().pid, std::process::id());
+        drop(lock);
+
+        // A process that wrote its pid without the OS lock, like a CLI run, still counts while it runs
+        let mut running = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        record_owner(&root, running.id());
+        assert_eq!(
+            acquire(&root).err(),
+            Some(format!("Another boardcast process (pid {}) is using the workspace {}", running.id(), root.display()))
+        );
+        running.kill().unwrap();
+        running.wait().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
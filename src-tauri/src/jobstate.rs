@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, State};
+
+use crate::process::is_process_alive;
+use crate::workdir::{jobs_dir, WorkDirs};
+
+const STATE_FILE: &str = "job.json";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStage {
+    Started,
+    PropsWritten,
+    Rendering,
+    Rendered,
+    Compositing,
+    Completed,
+    Failed,
+    Crashed,
+}
+
+impl JobStage {
+    fn is_finished(self) -> bool {
+        matches!(self, JobStage::Completed | JobStage::Failed | JobStage::Crashed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobState {
+    pub export_id: String,
+    pub stage: JobStage,
+    // Stage reached before the process died, kept once the job is marked crashed
+    pub last_stage: Option<JobStage>,
+    pub pid: u32,
+    pub workdir: PathBuf,
+    pub animation_path: PathBuf,
+    pub video_path: Option<String>,
+    pub output_path: Option<String>,
+    pub data_hash: String,
+    pub updated_at: u64,
+}
+
+// FNV-1a, stable across builds unlike std's DefaultHasher
+// FNV-1a, which can be fed in chunks: start from FNV_OFFSET and pass each call's result to the next
+pub const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn hash_content(content: &str) -> String {
+    format!("{:016x}", fnv1a(FNV_OFFSET, content.as_bytes()))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl JobState {
+    pub fn new(export_id: &str, workdir: &Path, animation_path: &Path, data: &serde_json::Value, content: &str) -> Self {
+        JobState {
+            export_id: export_id.to_string(),
+            stage: JobStage::Started,
+            last_stage: None,
+            pid: std::process::id(),
+            workdir: workdir.to_path_buf(),
+            animation_path: animation_path.to_path_buf(),
+            video_path: data.get("videoPath").and_then(|v| v.as_str()).map(String::from),
+            output_path: data.get("outputPath").and_then(|v| v.as_str()).map(String::from),
+            data_hash: hash_content(content),
+            updated_at: now_secs(),
+        }
+    }
+
+    // Writes to a temp file first so a crash mid-write never leaves a truncated state file
+    fn save(&self) -> Result<(), String> {
+        let path = self.workdir.join(STATE_FILE);
+        let temp = self.workdir.join(format!("{}.tmp", STATE_FILE));
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize job state: {}", e))?;
+
+        fs::write(&temp, content)
+            .map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+        fs::rename(&temp, &path)
+            .map_err(|e| format!("Failed to move {} into place: {}", path.display(), e))
+    }
+
+    pub fn set_stage(&mut self, stage: JobStage) {
+        self.stage = stage;
+        self.pid = std::process::id();
+        self.updated_at = now_secs();
+        if let Err(e) = self.save() {
+            log::warn!("Failed to persist job state for {}: {}", self.export_id, e);
+        }
+    }
+
+    pub fn can_resume(&self) -> bool {
+        self.last_stage.map(|s| s >= JobStage::Rendered).unwrap_or(false) && self.animation_path.exists()
+    }
+}
+
+pub fn load(workdir: &Path) -> Option<JobState> {
+    let content = fs::read_to_string(workdir.join(STATE_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn remove_partial_outputs(state: &JobState, stage: JobStage) {
+    let mut partial = Vec::new();
+    if stage < JobStage::Rendered {
+        partial.push(state.animation_path.clone());
+    }
+    if stage == JobStage::Compositing {
+        if let Some(output) = &state.output_path {
+            partial.push(PathBuf::from(output));
+        }
+    }
+
+    for path in partial.into_iter().filter(|p| p.exists()) {
+        match fs::remove_file(&path) {
+            Ok(_) => log::info!("Removed partial output {}", path.display()),
+            Err(e) => log::warn!("Failed to remove partial output {}: {}", path.display(), e),
+        }
+    }
+}
+
+// Marks jobs whose owning process is gone as crashed and removes what they left half-written
+pub fn recover_crashed_jobs(app: &AppHandle, workdirs: &WorkDirs) {
+    let Ok(root) = jobs_dir(app) else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(&root) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let Some(mut state) = load(&entry.path()) else {
+            continue;
+        };
+        // Jobs started by this process since launch are still running
+        if state.stage.is_finished() || workdirs.is_active(&state.export_id) {
+            continue;
+        }
+        if state.pid != std::process::id() && is_process_alive(state.pid) {
+            continue;
+        }
+
+        log::info!("Export {} was interrupted during {:?}", state.export_id, state.stage);
+        let stage = state.stage;
+        remove_partial_outputs(&state, stage);
+        state.last_stage = Some(stage);
+        state.set_stage(JobStage::Crashed);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InterruptedExport {
+    pub export_id: String,
+    pub last_stage: Option<JobStage>,
+    pub can_resume: bool,
+    pub video_path: Option<String>,
+    pub output_path: Option<String>,
+    pub updated_at: u64,
+}
+
+pub fn find_crashed(app: &AppHandle, export_id: &str) -> Result<JobState, String> {
+    let state = load(&jobs_dir(app)?.join(export_id))
+        .ok_or_else(|| format!("No saved state for export {}", export_id))?;
+    if state.stage != JobStage::Crashed {
+        return Err(format!("Export {} was not interrupted", export_id));
+    }
+    Ok(state)
+}
+
+#[command]
+pub fn get_interrupted_exports(app: AppHandle) -> Result<Vec<InterruptedExport>, String> {
+    let mut interrupted: Vec<InterruptedExport> = fs::read_dir(jobs_dir(&app)?)
+        .map(|entries| entries.flatten().filter_map(|e| load(&e.path())).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|state| state.stage == JobStage::Crashed)
+        .map(|state| InterruptedExport {
+            can_resume: state.can_resume(),
+            export_id: state.export_id,
+            last_stage: state.last_stage,
+            video_path: state.video_path,
+            output_path: state.output_path,
+            updated_at: state.updated_at,
+        })
+        .collect();
+
+    interrupted.sort_by_key(|e| std::cmp::Reverse(e.updated_at));
+    Ok(interrupted)
+}
+
+#[command]
+pub fn discard_interrupted_export(app: AppHandle, workdirs: State<'_, WorkDirs>, export_id: String) -> Result<(), String> {
+    let state = find_crashed(&app, &export_id)?;
+    // Allocating marks the directory active so a concurrent cache cleanup leaves it alone
+    let workdir = workdirs.allocate(&app, &export_id)?;
+    fs::remove_dir_all(workdir.path())
+        .map_err(|e| format!("Failed to remove {}: {}", state.workdir.display(), e))?;
+
+    log::info!("Discarded interrupted export {}", export_id);
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{command, AppHandle, Emitter, Manager, State};
+
+use crate::pgn::{self, PgnGame};
+
+// Large databases belong in the import flow, not a double-click
+const MAX_LAUNCH_PGN_BYTES: u64 = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenedPgn {
+    pub path: String,
+    pub game: PgnGame,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenPgnError {
+    pub path: String,
+    pub error: String,
+}
+
+// Events raised before the webview has registered its listeners are held here
+#[derive(Default)]
+pub struct LaunchQueue {
+    ready: Mutex<bool>,
+    pending: Mutex<Vec<(&'static str, Value)>>,
+}
+
+impl LaunchQueue {
+    fn emit_or_queue(&self, app: &AppHandle, event: &'static str, payload: Value) {
+        if *self.ready.lock().unwrap() {
+            let _ = app.emit(event, payload);
+        } else {
+            self.pending.lock().unwrap().push((event, payload));
+        }
+    }
+}
+
+fn pgn_arg(args: &[String]) -> Option<PathBuf> {
+    // argv[0] is the executable; anything else with a .pgn extension is a file to open
+    args.iter()
+        .skip(1)
+        .map(PathBuf::from)
+        .find(|p| p.extension().map(|ext| ext.eq_ignore_ascii_case("pgn")).unwrap_or(false))
+}
+
+fn load_pgn(path: &Path) -> Result<PgnGame, String> {
+    let meta = fs::metadata(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    if !meta.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    if meta.len() > MAX_LAUNCH_PGN_BYTES {
+        return Err(format!(
+            "{} is {} bytes, larger than the {} byte limit",
+            path.display(), meta.len(), MAX_LAUNCH_PGN_BYTES
+        ));
+    }
+
+    let bytes = fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let (text, _) = pgn::decode_text(&bytes);
+    pgn::parse_game(&text).map_err(|e| format!("Malformed PGN in {}: {}", path.display(), e))
+}
+
+// Handles argv from the first launch or one forwarded by the single-instance plugin
+pub fn handle_args(app: &AppHandle, args: &[String], cwd: &str) {
+    let Some(path) = pgn_arg(args) else {
+        return;
+    };
+    // Forwarded arguments are relative to the second instance's working directory
+    let path = if path.is_relative() && !cwd.is_empty() { Path::new(cwd).join(path) } else { path };
+    let display = path.display().to_string();
+    log::info!("Opening PGN from launch arguments: {}", display);
+
+    let queue = app.state::<LaunchQueue>();
+    match load_pgn(&path) {
+        Ok(game) => {
+            let payload = serde_json::to_value(OpenedPgn { path: display, game }).unwrap_or_default();
+            queue.emit_or_queue(app, "open-pgn", payload);
+        }
+        Err(error) => {
+            log::warn!("Failed to open PGN: {}", error);
+            let payload = serde_json::to_value(OpenPgnError { path: display, error }).unwrap_or_default();
+            queue.emit_or_queue(app, "open-pgn-error", payload);
+        }
+    }
+}
+
+pub fn focus_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+// Called by the frontend once its event listeners are in place
+#[command]
+pub fn frontend_ready(app: AppHandle, queue: State<'_, LaunchQueue>) {
+    let mut ready = queue.ready.lock().unwrap();
+    *ready = true;
+    for (event, payload) in queue.pending.lock().unwrap().drain(..) {
+        let _ = app.emit(event, payload);
+    }
+}
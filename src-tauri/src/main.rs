@@ -1,38 +1,81 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::process::Command;
 use tauri::command;
-use serde_json;
 
+mod activity;
+mod audio;
+mod benchmark;
+mod cancel;
+mod chapters;
+mod destination;
+mod diagnostics;
+mod drawtext;
+mod encoders;
+mod escape;
+mod estimate;
+mod export_data;
+mod exports;
+mod failure;
+mod ffmpeg;
+mod ffmpeglog;
+mod filename;
+mod finish;
+mod filtergraph;
 mod hello;
+mod history;
+mod hook;
+mod hwdecode;
+mod instance;
+mod jobstate;
+mod launch;
+mod metadata;
+mod migrate;
+mod outputname;
+mod pause;
+mod paths;
+mod placement;
+mod pgn;
+mod pipeline;
+mod preflight;
+mod presets;
+mod process;
+mod progress;
+mod python;
+mod renderservice;
+mod scheduler;
+mod schema;
+mod segments;
+mod selftest;
+mod settings;
+mod setup;
+mod sizetarget;
+mod stitch;
+mod support;
+mod timecode;
+mod timeouts;
+mod timings;
+mod warnings;
+mod watch;
+mod webhook;
+mod workdir;
+mod wsl;
 
 // Import and initialize Tauri Dialog plugin (v2)
 use tauri_plugin_dialog::init as dialog_init;
-// Import shell plugin for sidecar commands
-use tauri_plugin_shell::ShellExt;
+use tauri::Manager;
 
-#[derive(serde::Deserialize)]
-pub enum OsEnvironment {
-    Windows,
-    Wsl,
-}
-
-impl Default for OsEnvironment {
-    fn default() -> Self {
-        OsEnvironment::Windows
-    }
-}
+pub const WINDOWS_SCRIPT_DIR: &str = r"C:\Users\User\Documents\boardcast\py-util";
+pub const WSL_SCRIPT_DIR: &str = "/mnt/c/Users/User/Documents/sample_script";
 
 #[command]
 async fn run_ffmpeg_version(app: tauri::AppHandle) -> Result<String, String> {
-    // Get the sidecar command for ffmpeg using the shell plugin
-    let sidecar_command = app.shell().sidecar("ffmpeg")
-        .map_err(|e| format!("Failed to create sidecar command: {}", e))?;
+    // Resolve ffmpeg (sidecar, settings path or PATH)
+    let resolved = ffmpeg::resolve_ffmpeg(&app).await.map_err(|e| e.to_string())?;
     
     // Execute ffmpeg with -version flag
-    let output = sidecar_command
-        .args(&["-version"])
+    let output = ffmpeg::ffmpeg_command(&app, &resolved)?
+        .args(["-version"])
         .output()
         .await
         .map_err(|e| format!("Failed to execute ffmpeg: {}", e))?;
@@ -44,96 +87,120 @@ async fn run_ffmpeg_version(app: tauri::AppHandle) -> Result<String, String> {
     }
 }
 
-#[command]
-fn run_python_script(
-    script: String, 
-    cli_args: Vec<String>,
-    os_env: Option<OsEnvironment>,
-    json_output: Option<bool>
-) -> Result<serde_json::Value, String> {
-    let os_env = os_env.unwrap_or_default();
-    let json_output = json_output.unwrap_or(false);
-    
-    // Validate script name
-    if !script.ends_with(".py") || script.contains('/') || script.contains('\\') {
-        return Err("Invalid script name.".to_string());
-    }
-
-    let output = match os_env {
-        OsEnvironment::Windows => run_windows_script(script, cli_args)?,
-        OsEnvironment::Wsl => run_wsl_script(script, cli_args)?,
-    };
-
-    // If json_output is true, try to parse the output as JSON
-    if json_output {
-        match serde_json::from_str(&output) {
-            Ok(json_value) => Ok(json_value),
-            Err(e) => Err(format!("Failed to parse JSON output: {}", e)),
-        }
-    } else {
-        // Return the raw string output wrapped in a JSON string value
-        Ok(serde_json::Value::String(output))
-    }
-}
-
-fn run_windows_script(script: String, cli_args: Vec<String>) -> Result<String, String> {
-    let windows_path = r"C:\Users\User\Documents\boardcast\py-util";
-    
-    // For Windows, we'll use cmd to run the script
-    let mut command = Command::new("cmd");
-    command.args(&["/C", "cd", "/D", windows_path, "&&", "pipenv", "run", "python", &script]);
-    
-    // Add CLI arguments
-    for arg in cli_args {
-        command.arg(arg);
-    }
-
-    let output = command.output().map_err(|e| e.to_string())?;
-
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
-    }
-}
-
-
-fn run_wsl_script(script: String, cli_args: Vec<String>) -> Result<String, String> {
-    let wsl_path = "/mnt/c/Users/User/Documents/sample_script";
-
-    // Escape and format CLI arguments for WSL
-    let args_str = cli_args
-        .iter()
-        .map(|arg| format!("'{}'", arg.replace('\'', "'\\''")))
-        .collect::<Vec<String>>()
-        .join(" ");
-
-    // Construct WSL command
-    let command = format!(
-        "cd '{}' && pipenv run python {} {}",
-        wsl_path, script, args_str
-    );
-
-    // Execute the command in WSL
-    let output = Command::new("wsl")
-        .args(&["bash", "-c", &command])
-        .output()
-        .map_err(|e| e.to_string())?;
-
-    // Return stdout if successful, stderr otherwise
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
-    }
-}
-
 fn main() {
+    let recent_logs = activity::RecentLogs::install();
     tauri::Builder::default()
+        // Must be registered first so a second launch (e.g. double-clicking a .pgn) forwards here and exits
+        .plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+            launch::handle_args(app, &args, &cwd);
+            launch::focus_main_window(app);
+        }))
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init()) // Initialize shell plugin
         .plugin(dialog_init()) // Initialize dialog plugin
-        .invoke_handler(tauri::generate_handler![run_python_script, run_ffmpeg_version, hello::export])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .manage(exports::ExportRegistry::default())
+        .manage(ffmpeg::FfmpegResolver::default())
+        .manage(workdir::WorkDirs::default())
+        .manage(launch::LaunchQueue::default())
+        .manage(instance::InstanceLock::default())
+        .manage(pgn::PgnIndex::default())
+        .manage(python::PythonRuns::default())
+        .manage(watch::WatchFolderState::default())
+        .manage(wsl::WslProbe::default())
+        .manage(pause::PauseState::default())
+        .manage(cancel::CancelState::default())
+        .manage(scheduler::Scheduler::default())
+        .manage(hwdecode::HwDecodeCache::default())
+        .manage(renderservice::RemotionService::default())
+        .manage(recent_logs.clone())
+        .setup(move |app| {
+            recent_logs.attach(app.handle());
+            let settings = settings::SettingsState::load(app.handle());
+            let max_age_days = settings.get().cache_max_age_days;
+            let watch_folder = settings.get().watch_folder;
+            app.manage(settings);
+            app.manage(history::ExportHistory::load(app.handle()));
+            app.manage(encoders::EncoderCache::load(app.handle()));
+            app.manage(benchmark::BenchmarkStore::load(app.handle()));
+            // Exports retry this, so a failure here only means they check again later
+            if let Err(e) = app.state::<instance::InstanceLock>().ensure(app.handle()) {
+                log::warn!("Workspace lock not taken: {}", e);
+            }
+
+            if let Some(config) = watch_folder {
+                watch::start(app.handle(), config);
+            }
+
+            // A .pgn opened through the file association arrives as a launch argument
+            let args: Vec<String> = std::env::args().collect();
+            let cwd = std::env::current_dir().map(|d| d.display().to_string()).unwrap_or_default();
+            launch::handle_args(app.handle(), &args, &cwd);
+
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                let workdirs = handle.state::<workdir::WorkDirs>();
+                jobstate::recover_crashed_jobs(&handle, &workdirs);
+                if let Err(e) = workdir::prune(&handle, &workdirs, max_age_days) {
+                    log::warn!("Failed to prune the cache directory: {}", e);
+                }
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            python::run_python_script,
+            python::cancel_python_script,
+            activity::get_recent_logs,
+            activity::subscribe_logs,
+            activity::unsubscribe_logs,
+            support::create_support_bundle,
+            run_ffmpeg_version,
+            pipeline::export,
+            pipeline::resume_crashed_export,
+            preflight::validate_export_paths,
+            setup::run_first_time_setup,
+            timecode::parse_timecode,
+            outputname::expand_output_template,
+            audio::extract_audio,
+            audio::replace_audio,
+            jobstate::get_interrupted_exports,
+            jobstate::discard_interrupted_export,
+            export_data::import_export_json,
+            exports::copy_ffmpeg_command,
+            exports::get_export_filter_graph,
+            exports::get_export_status,
+            pause::pause_export,
+            pause::resume_export,
+            cancel::cancel_export,
+            scheduler::get_export_queue,
+            placement::suggest_overlay_position,
+            diagnostics::system_diagnostics,
+            encoders::get_hardware_encoders,
+            benchmark::run_benchmark,
+            benchmark::cancel_benchmark,
+            benchmark::get_benchmark,
+            selftest::run_self_test,
+            selftest::cancel_self_test,
+            estimate::estimate_export_size,
+            ffmpeg::get_video_metadata,
+            ffmpeglog::get_export_ffmpeg_log,
+            settings::get_settings,
+            settings::update_settings,
+            workdir::get_cache_usage,
+            workdir::clear_cache,
+            history::get_export_history,
+            history::prune_outputs,
+            launch::frontend_ready,
+            pgn::read_pgn_file,
+            pgn::load_game,
+            watch::start_watch_folder,
+            watch::stop_watch_folder
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                tauri::async_runtime::block_on(app.state::<renderservice::RemotionService>().shutdown());
+            }
+        });
 }
\ No newline at end of file
@@ -0,0 +1,138 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// Tags written to the output container; anything else in the payload is ignored
+const ALLOWED_KEYS: &[&str] = &["title", "artist", "author", "comment", "date"];
+
+// Keeps a pasted PGN comment from bloating the container header
+const MAX_VALUE_CHARS: usize = 256;
+
+// Control characters would break the key=value argument and long values are cut
+fn sanitize(value: &str) -> Option<String> {
+    let cleaned: String = value
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let capped: String = cleaned.chars().take(MAX_VALUE_CHARS).collect();
+    (!capped.is_empty()).then_some(capped)
+}
+
+// PGN uses "?" for unknown header values
+pub fn header<'a>(headers: &'a Value, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && !v.chars().all(|c| c == '?'))
+}
+
+// PGN dates are "2024.05.01", possibly with unknown parts like "2024.??.??"; the known part as "2024-05-01"
+pub fn pgn_date(headers: &Value) -> Option<String> {
+    let parts: Vec<&str> = header(headers, "Date")?.split('.').take_while(|p| !p.contains('?')).collect();
+    (!parts.is_empty()).then(|| parts.join("-"))
+}
+
+// Tags derived from the game's PGN headers, used where the payload doesn't set them itself
+fn from_pgn_headers(headers: &Value) -> BTreeMap<String, String> {
+    let mut tags = BTreeMap::new();
+    let white = header(headers, "White");
+    let black = header(headers, "Black");
+
+    if let (Some(white), Some(black)) = (white, black) {
+        let title = match header(headers, "Event") {
+            Some(event) => format!("{} vs {} \u{2014} {}", white, black, event),
+            None => format!("{} vs {}", white, black),
+        };
+        tags.insert("title".to_string(), title);
+        tags.insert("artist".to_string(), format!("{}, {}", white, black));
+    }
+    if let Some(date) = pgn_date(headers) {
+        tags.insert("date".to_string(), date);
+    }
+    if let Some(result) = header(headers, "Result").filter(|r| *r != "*") {
+        tags.insert("comment".to_string(), format!("Result: {}", result));
+    }
+    tags
+}
+
+// Container tags for the export: explicit `metadata` entries win over values derived from `pgnHeaders`
+pub fn container_metadata(data: &Value) -> BTreeMap<String, String> {
+    let mut tags = data.get("pgnHeaders").map(from_pgn_headers).unwrap_or_default();
+    if let Some(metadata) = data.get("metadata").and_then(|v| v.as_object()) {
+        for (key, value) in metadata {
+            // A blank entry leaves the derived value in place
+            if let Some(value) = value.as_str().filter(|v| !v.trim().is_empty()) {
+                tags.insert(key.to_lowercase(), value.to_string());
+            }
+        }
+    }
+
+    tags.into_iter()
+        .filter(|(key, _)| ALLOWED_KEYS.contains(&key.as_str()))
+        .filter_map(|(key, value)| sanitize(&value).map(|value| (key, value)))
+        .collect()
+}
+
+pub fn metadata_args(tags: &BTreeMap<String, String>) -> Vec<String> {
+    tags.iter()
+        .flat_map(|(key, value)| ["-metadata".to_string(), format!("{}={}", key, value)])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn headers() -> Value {
+        json!({"White": "Carlsen", "Black": "Nakamura", "Event": "Speed Chess", "Date": "2024.05.??", "Result": "1-0"})
+    }
+
+    #[test]
+    fn tags_are_derived_from_the_pgn_headers() {
+        let tags = container_metadata(&json!({"pgnHeaders": headers()}));
+        let expected = [
+            ("artist", "Carlsen, Nakamura"),
+            ("comment", "Result: 1-0"),
+            ("date", "2024-05"),
+            ("title", "Carlsen vs Nakamura \u{2014} Speed Chess"),
+        ];
+        assert_eq!(tags, expected.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect());
+    }
+
+    #[test]
+    fn unknown_headers_are_left_out() {
+        let tags = container_metadata(&json!({"pgnHeaders": {"White": "?", "Black": "Nakamura", "Date": "????.??.??", "Result": "*"}}));
+        assert!(tags.is_empty(), "{:?}", tags);
+        let tags = container_metadata(&json!({"pgnHeaders": {"White": "Carlsen", "Black": "Nakamura"}}));
+        assert_eq!(tags["title"], "Carlsen vs Nakamura");
+    }
+
+    #[test]
+    fn explicit_entries_win_and_only_allowed_keys_are_written() {
+        let data = json!({
+            "pgnHeaders": headers(),
+            "metadata": {"Title": "My best game", "artist": "  ", "encoder": "boardcast", "comment": "line one\nline\ttwo"},
+        });
+        let tags = container_metadata(&data);
+        assert_eq!(tags["title"], "My best game");
+        assert_eq!(tags["artist"], "Carlsen, Nakamura");
+        assert_eq!(tags["comment"], "line one line two");
+        assert!(!tags.contains_key("encoder"));
+    }
+
+    #[test]
+    fn long_values_are_cut_at_the_cap() {
+        let tags = container_metadata(&json!({"metadata": {"comment": "é".repeat(MAX_VALUE_CHARS + 10)}}));
+        assert_eq!(tags["comment"].chars().count(), MAX_VALUE_CHARS);
+    }
+
+    #[test]
+    fn each_tag_is_one_metadata_argument() {
+        let tags = container_metadata(&json!({"metadata": {"title": "a = b", "date": "2024"}}));
+        assert_eq!(metadata_args(&tags), ["-metadata", "date=2024", "-metadata", "title=a = b"]);
+    }
+}
@@ -0,0 +1,145 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+// The export payload shape this build reads. Payloads without schema_version are version 1.
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+// Version 1 frontends used different spellings for some fields
+const FIELD_ALIASES: &[(&str, &str)] = &[
+    ("timeperMove", "timePerMove"),
+    ("time_per_move", "timePerMove"),
+    ("frameperMove", "framePerMove"),
+    ("frame_per_move", "framePerMove"),
+    ("video_path", "videoPath"),
+    ("output_path", "outputPath"),
+    ("board_size", "boardSize"),
+    ("xOffset", "x_offset"),
+    ("yOffset", "y_offset"),
+];
+
+type Step = fn(&mut Map<String, Value>, &mut Vec<String>);
+
+// STEPS[i] upgrades a version i + 1 payload to version i + 2
+const STEPS: &[Step] = &[v1_to_v2];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Migration {
+    pub from: u32,
+    pub to: u32,
+    // What the steps changed, e.g. "Renamed legacy field 'xOffset' to 'x_offset'"
+    pub changes: Vec<String>,
+}
+
+impl Migration {
+    pub fn migrated(&self) -> bool {
+        self.from != self.to
+    }
+}
+
+fn v1_to_v2(object: &mut Map<String, Value>, changes: &mut Vec<String>) {
+    for (alias, canonical) in FIELD_ALIASES {
+        if let Some(value) = object.remove(*alias) {
+            if object.contains_key(*canonical) {
+                changes.push(format!("Dropped '{}' because '{}' is also present", alias, canonical));
+            } else {
+                changes.push(format!("Renamed legacy field '{}' to '{}'", alias, canonical));
+                object.insert(canonical.to_string(), value);
+            }
+        }
+    }
+}
+
+pub fn schema_version(data: &Value) -> Result<u32, String> {
+    match data.get("schema_version") {
+        None | Some(Value::Null) => Ok(1),
+        Some(value) => {
+            let version = value.as_u64().filter(|&v| v >= 1);
+            let version = version.ok_or_else(|| format!("schema_version must be a whole number of at least 1, got {}", value))?;
+            if version > CURRENT_SCHEMA_VERSION as u64 {
+                return Err(format!(
+                    "the export data is schema version {}, but this version of boardcast only reads up to version {}; please update boardcast",
+                    version, CURRENT_SCHEMA_VERSION
+                ));
+            }
+            Ok(version as u32)
+        }
+    }
+}
+
+// Upgrades a payload of any earlier version to the current shape, one step at a time. A version
+// newer than this build is rejected as a whole, before any of its fields are looked at.
+pub fn migrate(mut data: Value) -> Result<(Value, Migration), String> {
+    let from = schema_version(&data)?;
+    let object = data.as_object_mut().ok_or("Export data must be a JSON object")?;
+    let mut changes = Vec::new();
+    for step in &STEPS[from as usize - 1..] {
+        step(object, &mut changes);
+    }
+    object.insert("schema_version".to_string(), Value::from(CURRENT_SCHEMA_VERSION));
+    Ok((data, Migration { from, to: CURRENT_SCHEMA_VERSION, changes }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fixture(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn a_payload_without_schema_version_is_migrated_as_version_1() {
+        let (data, migration) = migrate(fixture(include_str!("../fixtures/migrate/v1.json"))).unwrap();
+        assert_eq!(data, fixture(include_str!("../fixtures/migrate/v1.expected.json")));
+        assert_eq!((migration.from, migration.to, migration.migrated()), (1, 2, true));
+        assert_eq!(
+            migration.changes,
+            [
+                "Renamed legacy field 'timeperMove' to 'timePerMove'",
+                "Renamed legacy field 'frame_per_move' to 'framePerMove'",
+                "Renamed legacy field 'video_path' to 'videoPath'",
+                "Renamed legacy field 'output_path' to 'outputPath'",
+                "Renamed legacy field 'board_size' to 'boardSize'",
+                "Renamed legacy field 'xOffset' to 'x_offset'",
+                "Renamed legacy field 'yOffset' to 'y_offset'",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_legacy_field_next_to_its_canonical_one_is_dropped() {
+        let (data, migration) = migrate(fixture(include_str!("../fixtures/migrate/v1_conflicting.json"))).unwrap();
+        assert_eq!(data, fixture(include_str!("../fixtures/migrate/v1_conflicting.expected.json")));
+        assert_eq!(
+            migration.changes,
+            [
+                "Dropped 'time_per_move' because 'timePerMove' is also present",
+                "Dropped 'xOffset' because 'x_offset' is also present",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_current_payload_passes_through_unchanged() {
+        let current = fixture(include_str!("../fixtures/migrate/v2.json"));
+        let (data, migration) = migrate(current.clone()).unwrap();
+        assert_eq!(data, current);
+        assert!(!migration.migrated());
+        assert!(migration.changes.is_empty());
+    }
+
+    #[test]
+    fn a_newer_or_malformed_version_is_rejected() {
+        assert_eq!(
+            migrate(json!({"schema_version": 3, "timePerMove": 0.5})).unwrap_err(),
+            "the export data is schema version 3, but this version of boardcast only reads up to version 2; please update boardcast"
+        );
+        for bad in [json!(0), json!(1.5), json!("2"), json!(-1)] {
+            let message = migrate(json!({"schema_version": bad})).unwrap_err();
+            assert!(message.starts_with("schema_version must be a whole number of at least 1"), "{}", message);
+        }
+        assert_eq!(schema_version(&json!({"schema_version": null})), Ok(1));
+        assert_eq!(migrate(json!([1, 2])).unwrap_err(), "Export data must be a JSON object");
+    }
+}
@@ -0,0 +1,302 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tauri::{command, AppHandle, Manager};
+
+use crate::export_data::move_count;
+use crate::filename::{check_filename, describe, sanitize_filename, FilenameMode, Issue, Platform};
+use crate::metadata::{header, pgn_date};
+use crate::paths::path_arg;
+use crate::settings::SettingsState;
+
+const PLACEHOLDERS: &[&str] = &["white", "black", "event", "date", "moves", "preset", "n"];
+// Stands in for a value the payload doesn't have
+const UNKNOWN: &str = "unknown";
+// Characters in the name before the extension; well under the 255 most filesystems allow
+const MAX_STEM_CHARS: usize = 150;
+// How far {n} counts looking for a name that isn't taken
+const MAX_COUNTER: u32 = 9999;
+
+// What the placeholders expand to; missing values become "unknown"
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TemplateContext {
+    pub white: Option<String>,
+    pub black: Option<String>,
+    pub event: Option<String>,
+    pub date: Option<String>,
+    pub moves: Option<usize>,
+    pub preset: Option<String>,
+    pub n: Option<u32>,
+}
+
+// PGN names are "Surname, Given", and the surname is what people put in file names
+fn surname(name: &str) -> String {
+    name.split(',').next().unwrap_or(name).trim().to_string()
+}
+
+impl TemplateContext {
+    // From the payload's pgnHeaders, its timestamps and its platform_preset
+    pub fn from_payload(data: &Value) -> Self {
+        let headers = data.get("pgnHeaders").cloned().unwrap_or(Value::Null);
+        TemplateContext {
+            white: header(&headers, "White").map(surname),
+            black: header(&headers, "Black").map(surname),
+            event: header(&headers, "Event").map(String::from),
+            date: pgn_date(&headers),
+            moves: Some(move_count(data)).filter(|&moves| moves > 0),
+            preset: data.get("platform_preset").and_then(|v| v.as_str()).map(String::from),
+            n: None,
+        }
+    }
+
+    fn value(&self, placeholder: &str) -> String {
+        let value = match placeholder {
+            "white" => self.white.clone(),
+            "black" => self.black.clone(),
+            "event" => self.event.clone(),
+            "date" => self.date.clone(),
+            "moves" => self.moves.map(|m| m.to_string()),
+            "preset" => self.preset.clone(),
+            _ => Some(self.n.unwrap_or(1).to_string()),
+        };
+        value.filter(|v| !v.trim().is_empty()).unwrap_or_else(|| UNKNOWN.to_string())
+    }
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn pieces(template: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            return Err(format!("output_template has a }} without a matching {{: \"{}\"", template));
+        }
+        let close = rest[open..].find('}').map(|i| open + i)
+            .ok_or_else(|| format!("output_template has a {{ without a matching }}: \"{}\"", template))?;
+        let name = &rest[open + 1..close];
+        if !PLACEHOLDERS.contains(&name) {
+            let valid: Vec<String> = PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect();
+            return Err(format!("Unknown placeholder {{{}}} in output_template, expected one of {}", name, valid.join(", ")));
+        }
+        pieces.push(Piece::Text(&rest[..open]));
+        pieces.push(Piece::Placeholder(name));
+        rest = &rest[close + 1..];
+    }
+    pieces.push(Piece::Text(rest));
+    Ok(pieces)
+}
+
+// Run by validation so a bad template fails before anything renders
+pub fn check_template(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("output_template must not be empty".to_string());
+    }
+    pieces(template).map(|_| ())
+}
+
+// Makes `name` safe as a single file name on Windows, macOS and Linux; Windows' rules cover the others
+fn sanitize(name: &str) -> String {
+    let cleaned = match name.trim() {
+        "" => String::new(),
+        name => sanitize_filename(name, Platform::Windows, FilenameMode::Lenient).unwrap_or_default(),
+    };
+    let cleaned = cleaned.as_str();
+    let (stem, extension) = match cleaned.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() && extension.len() <= 4 => (stem, Some(extension)),
+        _ => (cleaned, None),
+    };
+    let mut stem: String = stem.chars().take(MAX_STEM_CHARS).collect::<String>().trim_end_matches(['.', ' ']).to_string();
+    if stem.is_empty() {
+        stem = "output".to_string();
+    }
+    match extension {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => format!("{}.mp4", stem),
+    }
+}
+
+// The sanitized file name; outputs without an extension in the template are .mp4
+pub fn expand(template: &str, context: &TemplateContext) -> Result<String, String> {
+    let name: String = pieces(template)?
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Text(text) => text.to_string(),
+            Piece::Placeholder(name) => context.value(name),
+        })
+        .collect();
+    Ok(sanitize(&name))
+}
+
+// The first free name in `dir`, counting {n} up from 1; without {n} an existing file is overwritten as usual
+fn resolve_in(dir: &Path, template: &str, context: &mut TemplateContext) -> Result<PathBuf, String> {
+    if !template.contains("{n}") {
+        return expand(template, context).map(|name| dir.join(name));
+    }
+    for n in 1..=MAX_COUNTER {
+        context.n = Some(n);
+        let path = dir.join(expand(template, context)?);
+        if !path.exists() {
+            return Ok(path);
+        }
+    }
+    Err(format!("Every name output_template gives up to {{n}} = {} is taken in {}", MAX_COUNTER, dir.display()))
+}
+
+// Replaces outputPath with the expanded output_template. The payload's template wins; the
+// settings' one names outputs the payload gave no outputPath. The file goes in outputPath's
+// directory, or next to the background video.
+pub fn apply_template(app: &AppHandle, mut data: Value) -> Result<Value, String> {
+    let output_path = data.get("outputPath").and_then(|v| v.as_str()).map(PathBuf::from);
+    let template = match data.get("output_template").and_then(|v| v.as_str()) {
+        Some(template) => template.to_string(),
+        None => match app.state::<SettingsState>().get().output_template {
+            Some(template) if output_path.is_none() => template,
+            _ => return Ok(data),
+        },
+    };
+    let dir = output_path
+        .as_deref()
+        .or_else(|| data.get("videoPath").and_then(|v| v.as_str()).map(Path::new))
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .ok_or("output_template needs an outputPath or videoPath to take the directory from")?;
+    let path = resolve_in(&dir, &template, &mut TemplateContext::from_payload(&data))?;
+    log::info!("Named the output {} from output_template \"{}\"", path.display(), template);
+    let object = data.as_object_mut().ok_or("Export data must be a JSON object")?;
+    object.insert("outputPath".to_string(), serde_json::json!(path_arg(&path)?));
+    // Named by boardcast, so the output retention may delete it
+    object.insert("managed_output".to_string(), Value::Bool(true));
+    Ok(data)
+}
+
+// The paths an export writes: outputPath and every outputs[].path, with the field each came from
+pub fn output_paths_mut(data: &mut Value) -> Vec<(String, &mut Value)> {
+    let mut paths = Vec::new();
+    let Some(object) = data.as_object_mut() else {
+        return paths;
+    };
+    let mut outputs = None;
+    for (key, value) in object.iter_mut() {
+        match key.as_str() {
+            "outputPath" if value.is_string() => paths.push(("outputPath".to_string(), value)),
+            "outputs" => outputs = value.as_array_mut(),
+            _ => {}
+        }
+    }
+    for (i, output) in outputs.into_iter().flatten().enumerate() {
+        if let Some(path) = output.get_mut("path").filter(|p| p.is_string()) {
+            paths.push((format!("outputs[{}].path", i), path));
+        }
+    }
+    paths
+}
+
+// An output name that the filename check changed
+#[derive(Debug, Clone, Serialize)]
+pub struct Renamed {
+    pub from: String,
+    pub to: String,
+    pub issues: Vec<Issue>,
+}
+
+// Checks the file names of outputPath and every outputs[].path against the rules of the
+// filesystem they go to. In lenient mode bad names are fixed and returned for the export to warn
+// about; in strict mode the first one fails the export.
+pub fn sanitize_output_paths(mut data: Value) -> Result<(Value, Vec<Renamed>), String> {
+    let mode = FilenameMode::from_value(&data)?;
+    let mut renamed = Vec::new();
+    for (field, value) in output_paths_mut(&mut data) {
+        let path = PathBuf::from(value.as_str().unwrap_or_default());
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| format!("{} must end in a file name, got \"{}\"", field, path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let platform = Platform::of(dir);
+        let fixed = sanitize_filename(name, platform, mode)
+            .map_err(|issues| format!("{} can't be used as a file name: {}", field, describe(&issues)))?;
+        if fixed != name {
+            let to = dir.join(&fixed).to_string_lossy().to_string();
+            let issues = check_filename(name, platform);
+            renamed.push(Renamed { from: path.display().to_string(), to: to.clone(), issues });
+            *value = Value::String(to);
+        }
+    }
+    Ok((data, renamed))
+}
+
+// Lets the UI show the name a template gives while it is being typed
+#[command]
+pub fn expand_output_template(template: String, context: Option<TemplateContext>) -> Result<String, String> {
+    check_template(&template)?;
+    expand(&template, &context.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    fn game() -> TemplateContext {
+        TemplateContext::from_payload(&json!({
+            "timestamps": [1.0, 2.5, 4.0],
+            "pgnHeaders": {"White": "Carlsen, Magnus", "Black": "Nepomniachtchi, Ian", "Event": "WCC 2021", "Date": "2021.12.03"},
+            "platform_preset": "youtube",
+        }))
+    }
+
+    #[test]
+    fn placeholders_are_filled_from_the_pgn_headers_and_the_payload() {
+        assert_eq!(
+            expand("{white}_vs_{black}_{date}_{moves}moves", &game()).unwrap(),
+            "Carlsen_vs_Nepomniachtchi_2021-12-03_3moves.mp4"
+        );
+        assert_eq!(expand("{event} {preset}.mkv", &game()).unwrap(), "WCC 2021 youtube.mkv");
+        // Missing values and an unset counter
+        assert_eq!(expand("{white}_{n}", &TemplateContext::default()).unwrap(), "unknown_1.mp4");
+    }
+
+    #[test]
+    fn the_expanded_name_is_one_safe_file_name() {
+        assert_eq!(expand("{event}/{white}: {black}?", &game()).unwrap(), "WCC 2021_Carlsen_ Nepomniachtchi_.mp4");
+        assert_eq!(expand("a\\b", &game()).unwrap(), "a_b.mp4");
+        assert_eq!(expand("CON", &game()).unwrap(), "_CON.mp4");
+        assert_eq!(expand("{white}. . .", &game()).unwrap(), "Carlsen.mp4");
+        assert_eq!(expand("{white}.mp4.", &game()).unwrap(), "Carlsen.mp4");
+        let long = expand(&"x".repeat(200), &game()).unwrap();
+        assert_eq!(long, format!("{}.mp4", "x".repeat(MAX_STEM_CHARS)));
+    }
+
+    #[test]
+    fn bad_templates_fail_validation() {
+        assert_eq!(
+            check_template("{white}_{elo}"),
+            Err("Unknown placeholder {elo} in output_template, expected one of {white}, {black}, {event}, {date}, {moves}, {preset}, {n}".to_string())
+        );
+        assert_eq!(check_template("{white"), Err("output_template has a { without a matching }: \"{white\"".to_string()));
+        assert_eq!(check_template("white}"), Err("output_template has a } without a matching {: \"white}\"".to_string()));
+        assert_eq!(check_template(" "), Err("output_template must not be empty".to_string()));
+        // The live preview checks the template the same way
+        assert!(expand_output_template("{white}_{elo}".to_string(), None).unwrap_err().starts_with("Unknown placeholder {elo}"));
+        assert_eq!(expand_output_template("{white}_{n}".to_string(), None).unwrap(), "unknown_1.mp4");
+    }
+
+    #[test]
+    fn the_counter_skips_names_that_are_taken() {
+        let dir = std::env::temp_dir().join(format!("boardcast-template-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for taken in ["Carlsen_1.mp4", "Carlsen_2.mp4"] {
+            fs::write(dir.join(taken), b"").unwrap();
+        }
+        assert_eq!(resolve_in(&dir, "{white}_{n}", &mut game()).unwrap(), dir.join("Carlsen_3.mp4"));
+        // Without {n} the name is used as it is
+        assert_eq!(resolve_in(&dir, "{white}_1", &mut game()).unwrap(), dir.join("Carlsen_1.mp4"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
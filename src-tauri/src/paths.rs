@@ -0,0 +1,201 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+use crate::settings::SettingsState;
+
+// Locates the Remotion project the pipeline renders from
+#[derive(Debug, Clone)]
+pub struct ProjectPaths {
+    pub root: PathBuf,
+    // The sample_dir setting, when first-run setup has put it elsewhere
+    pub sample_dir: Option<PathBuf>,
+}
+
+impl ProjectPaths {
+    // Tries the configured project root, then the dev layout (cwd is src-tauri), then the bundled resources
+    pub fn resolve(app: &AppHandle) -> Result<Self, String> {
+        let current_dir = env::current_dir().ok();
+        let settings = app.state::<SettingsState>().get();
+        let sample_dir = settings.sample_dir.map(PathBuf::from);
+        let candidates: Vec<PathBuf> = [
+            settings.project_root.map(PathBuf::from),
+            current_dir.as_ref().and_then(|d| d.parent()).map(Path::to_path_buf),
+            current_dir,
+            app.path().resource_dir().ok(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        candidates
+            .iter()
+            .find(|root| root.join("remotion").is_dir())
+            .map(|root| ProjectPaths { root: root.clone(), sample_dir })
+            .ok_or_else(|| {
+                let tried: Vec<String> = candidates.iter().map(|c| c.display().to_string()).collect();
+                format!("Could not find the remotion project, tried: {}", tried.join(", "))
+            })
+    }
+
+    pub fn remotion_dir(&self) -> PathBuf {
+        self.root.join("remotion")
+    }
+
+    // Imported by remotion/Root.tsx at bundle time
+    pub fn export_json(&self) -> PathBuf {
+        self.remotion_dir().join("export.json")
+    }
+
+    pub fn sample_exporting(&self) -> PathBuf {
+        self.sample_dir.clone().unwrap_or_else(|| self.root.join("sample_exporting"))
+    }
+}
+
+// Writes to a sibling temp file and renames it over the target, so readers never see a half-written file
+pub async fn write_atomic(path: &Path, content: &str) -> Result<(), String> {
+    let path = match env::current_dir() {
+        Ok(current_dir) if path.is_relative() => current_dir.join(path),
+        _ => path.to_path_buf(),
+    };
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let temp = path.with_file_name(format!("{}.tmp", file_name));
+    tokio::fs::write(&temp, content)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    tokio::fs::rename(&temp, &path)
+        .await
+        .map_err(|e| format!("Failed to move {} into place: {}", path.display(), e))
+}
+
+// A path as an argument for a child process. Argument lists are Strings all the way to the
+// process (the export registry, the filter graph and the command line shown all read them), so
+// a path that isn't valid Unicode is refused instead of being passed on with U+FFFD in it.
+pub fn path_arg(path: &Path) -> Result<String, String> {
+    path.to_str()
+        .map(String::from)
+        .ok_or_else(|| format!("{} can't be passed to ffmpeg because the path isn't valid Unicode", path.display()))
+}
+
+// Windows refuses paths longer than MAX_PATH (260) unless they are in extended-length form; this
+// leaves room for the files ffmpeg names after its arguments, like two-pass logs
+const LONG_PATH_CHARS: usize = 240;
+
+// The \\?\ form of an absolute Windows path this long; None for shorter paths and anything else
+pub fn extended_length(arg: &str) -> Option<String> {
+    if arg.chars().count() <= LONG_PATH_CHARS || arg.starts_with(r"\\?\") {
+        return None;
+    }
+    let bytes = arg.as_bytes();
+    let drive = bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && matches!(bytes[2], b'\\' | b'/');
+    let unc = arg.starts_with(r"\\") || arg.starts_with("//");
+    if !drive && !unc {
+        return None;
+    }
+    // The prefix turns Windows' own normalisation off, so separators, "." and ".." are resolved here
+    let root_parts = if drive { 1 } else { 2 };
+    let mut parts: Vec<&str> = Vec::new();
+    for part in arg.split(['\\', '/']).filter(|p| !p.is_empty() && *p != ".") {
+        if part == ".." {
+            if parts.len() > root_parts {
+                parts.pop();
+            }
+        } else {
+            parts.push(part);
+        }
+    }
+    Some(match drive {
+        true => format!(r"\\?\{}", parts.join(r"\")),
+        false => format!(r"\\?\UNC\{}", parts.join(r"\")),
+    })
+}
+
+// An argument for ffmpeg, with long Windows paths in the form it can open
+pub fn for_child_process(arg: &str) -> String {
+    match cfg!(windows) {
+        true => extended_length(arg).unwrap_or_else(|| arg.to_string()),
+        false => arg.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A OneDrive-style folder nested deep enough to pass MAX_PATH
+    fn deep_windows_dir(root: &str, separator: &str) -> String {
+        let folder = "Chess Exports - World Championship Match Game Recaps";
+        let mut dir = root.to_string();
+        for i in 0..5 {
+            dir.push_str(separator);
+            dir.push_str(&format!("{} {}", folder, i));
+        }
+        dir
+    }
+
+    #[test]
+    fn short_and_non_windows_paths_are_left_alone() {
+        assert_eq!(extended_length(r"C:\Users\me\Videos\output.mp4"), None);
+        let long_relative = deep_windows_dir("exports", r"\");
+        assert!(long_relative.len() > 260);
+        assert_eq!(extended_length(&long_relative), None);
+        assert_eq!(extended_length(&deep_windows_dir("/home/me", "/")), None);
+        let prefixed = format!(r"\\?\{}", deep_windows_dir("C:", r"\"));
+        assert_eq!(extended_length(&prefixed), None);
+    }
+
+    #[test]
+    fn long_drive_paths_get_the_extended_length_prefix() {
+        let dir = deep_windows_dir(r"C:\Users\me\OneDrive", r"\");
+        let output = format!(r"{}\Carlsen vs Nepomniachtchi - Game 6 - the longest game in World Championship history.mp4", dir);
+        assert!(output.len() > 260);
+        assert_eq!(extended_length(&output), Some(format!(r"\\?\{}", output)));
+    }
+
+    #[test]
+    fn the_prefixed_form_is_normalised_by_hand() {
+        let dir = deep_windows_dir("C:/Users/me/OneDrive", "/");
+        let messy = format!(r"{}/./skipped\..\game.mp4", dir);
+        let expected = format!(r"\\?\{}\game.mp4", dir.replace('/', r"\"));
+        assert_eq!(extended_length(&messy), Some(expected));
+        // ".." never climbs above the drive
+        let climbing = format!(r"C:\{}{}\game.mp4", r"..\".repeat(3), deep_windows_dir("Users", r"\"));
+        assert_eq!(extended_length(&climbing), Some(format!(r"\\?\C:\{}\game.mp4", deep_windows_dir("Users", r"\"))));
+    }
+
+    #[test]
+    fn long_network_paths_use_the_unc_prefix() {
+        let dir = deep_windows_dir(r"\\nas\media", r"\");
+        let output = format!(r"{}\game.mp4", dir);
+        assert!(output.len() > 260);
+        assert_eq!(extended_length(&output), Some(format!(r"\\?\UNC\{}", &output[2..])));
+        // The server and share stay put however many ".." follow them
+        let climbing = format!(r"\\nas\media\..\..{}\game.mp4", &dir[r"\\nas\media".len()..]);
+        assert_eq!(extended_length(&climbing), Some(format!(r"\\?\UNC\{}", &output[2..])));
+    }
+
+    #[tokio::test]
+    async fn files_past_max_path_can_be_written_through_the_helpers() {
+        let root = env::temp_dir().join(format!("boardcast-long-paths-{}", std::process::id()));
+        let mut dir = root.clone();
+        for i in 0..6 {
+            dir.push(format!("Chess Exports - World Championship Match Game Recaps {}", i));
+        }
+        let output = dir.join("export.json");
+        assert!(path_arg(&output).unwrap().chars().count() > 260);
+
+        // Windows only accepts the path once it is in extended-length form
+        let arg = for_child_process(&path_arg(&output).unwrap());
+        assert_eq!(arg.starts_with(r"\\?\"), cfg!(windows));
+        write_atomic(Path::new(&arg), "{}").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&arg).unwrap(), "{}");
+        assert!(!Path::new(&format!("{}.tmp", arg)).exists());
+        std::fs::remove_dir_all(for_child_process(&path_arg(&root).unwrap())).unwrap();
+    }
+}
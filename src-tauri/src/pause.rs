@@ -0,0 +1,318 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{command, AppHandle, Emitter, Manager, State};
+use tokio::sync::Notify;
+
+use crate::exports::ExportRegistry;
+use crate::process;
+
+#[derive(Debug, Default)]
+struct Pause {
+    // Set while the export is paused
+    since: Option<Instant>,
+    // The export's earlier pauses
+    total: Duration,
+    // Every process the export is running, as registered by run_streaming
+    pids: Vec<u32>,
+}
+
+impl Pause {
+    fn paused_for(&self) -> Duration {
+        self.total + self.since.map(|since| since.elapsed()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PauseEvent {
+    pub export_id: String,
+    pub paused: bool,
+}
+
+// Which exports are paused, and the processes to stop and continue for them
+#[derive(Default)]
+pub struct PauseState {
+    exports: Mutex<HashMap<String, Pause>>,
+    resumed: Notify,
+}
+
+// Unregisters a process from its export when the process is done with
+pub struct Tracked<'a> {
+    state: &'a PauseState,
+    export_id: String,
+    pid: u32,
+}
+
+impl Drop for Tracked<'_> {
+    fn drop(&mut self) {
+        if let Some(pause) = self.state.exports.lock().unwrap().get_mut(&self.export_id) {
+            pause.pids.retain(|pid| *pid != self.pid);
+        }
+    }
+}
+
+impl PauseState {
+    pub fn is_paused(&self, export_id: &str) -> bool {
+        self.exports.lock().unwrap().get(export_id).is_some_and(|p| p.since.is_some())
+    }
+
+    // The time the export has spent paused so far, the current pause included
+    pub fn paused_for(&self, export_id: &str) -> Duration {
+        self.exports.lock().unwrap().get(export_id).map(Pause::paused_for).unwrap_or_default()
+    }
+
+    // Returns once the export isn't paused, so its next stage doesn't start while it is
+    pub async fn wait_resumed(&self, export_id: &str) {
+        loop {
+            let resumed = self.resumed.notified();
+            if !self.is_paused(export_id) {
+                return;
+            }
+            resumed.await;
+        }
+    }
+
+    // A process the export started stops right away if the export was paused in the meantime
+    pub fn track(&self, export_id: &str, pid: u32) -> Tracked<'_> {
+        let mut exports = self.exports.lock().unwrap();
+        let pause = exports.entry(export_id.to_string()).or_default();
+        pause.pids.push(pid);
+        if pause.since.is_some() {
+            if let Err(e) = process::suspend_tree(pid) {
+                log::warn!("Failed to pause process {} of the paused export {}: {}", pid, export_id, e);
+            }
+        }
+        Tracked { state: self, export_id: export_id.to_string(), pid }
+    }
+
+    // The processes the export has running, for cancel_export to kill
+    pub fn processes(&self, export_id: &str) -> Vec<u32> {
+        self.exports.lock().unwrap().get(export_id).map(|p| p.pids.clone()).unwrap_or_default()
+    }
+
+    // tokio::time::timeout, except that the time the export spends paused doesn't count
+    pub async fn timeout<F: Future>(&self, export_id: Option<&str>, limit: Duration, future: F) -> Option<F::Output> {
+        let paused_for = || export_id.map(|id| self.paused_for(id)).unwrap_or_default();
+        tokio::pin!(future);
+        let mut deadline = Instant::now() + limit;
+        let mut credited = paused_for();
+        loop {
+            tokio::select! {
+                output = &mut future => return Some(output),
+                _ = tokio::time::sleep_until(deadline.into()) => {
+                    let paused = paused_for();
+                    if paused <= credited {
+                        return None;
+                    }
+                    deadline += paused - credited;
+                    credited = paused;
+                }
+            }
+        }
+    }
+
+    fn pause(&self, export_id: &str) -> Result<(), String> {
+        let pids = {
+            let mut exports = self.exports.lock().unwrap();
+            let pause = exports.entry(export_id.to_string()).or_default();
+            if pause.since.is_some() {
+                return Err(format!("Export {} is already paused", export_id));
+            }
+            pause.since = Some(Instant::now());
+            pause.pids.clone()
+        };
+        // Outside the lock: on Unix finding a process's children means running ps
+        for pid in pids {
+            if let Err(e) = process::suspend_tree(pid) {
+                // Continuing a process that wasn't stopped does nothing
+                self.resume(export_id)?;
+                return Err(format!("Failed to pause export {}: {}", export_id, e));
+            }
+        }
+        Ok(())
+    }
+
+    fn resume(&self, export_id: &str) -> Result<(), String> {
+        let pids = {
+            let mut exports = self.exports.lock().unwrap();
+            let pause = exports.get_mut(export_id).filter(|p| p.since.is_some());
+            let pause = pause.ok_or_else(|| format!("Export {} isn't paused", export_id))?;
+            pause.total = pause.paused_for();
+            pause.since = None;
+            pause.pids.clone()
+        };
+        for pid in pids {
+            if let Err(e) = process::resume_tree(pid) {
+                log::warn!("Failed to continue process {} of export {}: {}", pid, export_id, e);
+            }
+        }
+        self.resumed.notify_waiters();
+        Ok(())
+    }
+
+    // Called when the export ends
+    pub fn forget(&self, export_id: &str) {
+        self.exports.lock().unwrap().remove(export_id);
+    }
+}
+
+// Pauses or continues the export and tells the frontend
+pub fn set_paused(app: &AppHandle, export_id: &str, paused: bool) -> Result<(), String> {
+    let pauses = app.state::<PauseState>();
+    if paused {
+        pauses.pause(export_id)?;
+        log::info!("Paused export {}", export_id);
+    } else {
+        pauses.resume(export_id)?;
+        log::info!("Resumed export {}", export_id);
+    }
+    let _ = app.emit("export-paused", PauseEvent { export_id: export_id.to_string(), paused });
+    Ok(())
+}
+
+// Stops a running export's ffmpeg and Remotion processes until resume_export, to give the CPU
+// back without losing the progress. Paused between processes, the export waits before starting
+// the next one; timeouts and the ETA don't count the time spent paused.
+#[command]
+pub fn pause_export(app: AppHandle, registry: State<'_, ExportRegistry>, export_id: String) -> Result<(), String> {
+    if !registry.is_running(&export_id) {
+        return Err(format!("Export {} isn't running", export_id));
+    }
+    set_paused(&app, &export_id, true)
+}
+
+#[command]
+pub fn resume_export(app: AppHandle, export_id: String) -> Result<(), String> {
+    set_paused(&app, &export_id, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_export_is_paused_once_and_resumed_once() {
+        let pauses = PauseState::default();
+        assert!(pauses.resume("e1").unwrap_err().contains("isn't paused"));
+        pauses.pause("e1").unwrap();
+        assert!(pauses.is_paused("e1"));
+        assert!(pauses.pause("e1").unwrap_err().contains("already paused"));
+        pauses.resume("e1").unwrap();
+        assert!(!pauses.is_paused("e1"));
+        assert!(pauses.resume("e1").is_err());
+        assert!(!pauses.is_paused("e2"));
+    }
+
+    #[test]
+    fn the_time_spent_paused_adds_up_until_the_export_is_forgotten() {
+        let pauses = PauseState::default();
+        assert_eq!(pauses.paused_for("e1"), Duration::ZERO);
+        for _ in 0..2 {
+            pauses.pause("e1").unwrap();
+            std::thread::sleep(Duration::from_millis(30));
+            pauses.resume("e1").unwrap();
+        }
+        let paused = pauses.paused_for("e1");
+        assert!(paused >= Duration::from_millis(60), "{:?}", paused);
+        // Not counting while the export runs
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(pauses.paused_for("e1"), paused);
+
+        pauses.forget("e1");
+        assert_eq!(pauses.paused_for("e1"), Duration::ZERO);
+    }
+
+    #[test]
+    fn a_process_is_the_exports_until_it_is_done_with() {
+        let pauses = PauseState::default();
+        let first = pauses.track("e1", 101);
+        let second = pauses.track("e1", 102);
+        assert_eq!(pauses.processes("e1"), [101, 102]);
+        drop(first);
+        assert_eq!(pauses.processes("e1"), [102]);
+        drop(second);
+        assert!(pauses.processes("e1").is_empty());
+        assert!(pauses.processes("e2").is_empty());
+    }
+
+    // Whether the process is stopped, as SIGSTOP leaves it, once the signal has been delivered
+    #[cfg(unix)]
+    fn settles_stopped(pid: u32, stopped: bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+            let state = stat.rsplit(')').next().and_then(|rest| rest.trim_start().chars().next());
+            if (state == Some('T')) == stopped {
+                return true;
+            }
+            if Instant::now() > deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pausing_stops_the_exports_processes_and_any_it_starts_until_resumed() {
+        let sleep = || std::process::Command::new("sleep").arg("30").stdout(std::process::Stdio::null()).spawn().unwrap();
+        let pauses = PauseState::default();
+        let mut running = sleep();
+        let mut started = sleep();
+        let _tracked = pauses.track("e1", running.id());
+
+        pauses.pause("e1").unwrap();
+        let first_stopped = settles_stopped(running.id(), true);
+        // Started between stages of the paused export
+        let _tracked_too = pauses.track("e1", started.id());
+        let second_stopped = settles_stopped(started.id(), true);
+
+        pauses.resume("e1").unwrap();
+        let continued = settles_stopped(running.id(), false) && settles_stopped(started.id(), false);
+        for child in [&mut running, &mut started] {
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+        assert_eq!((first_stopped, second_stopped, continued), (true, true, true));
+    }
+
+    #[tokio::test]
+    async fn the_next_stage_waits_for_the_export_to_be_resumed() {
+        let pauses = PauseState::default();
+        pauses.wait_resumed("e1").await;
+
+        pauses.pause("e1").unwrap();
+        let started = Instant::now();
+        let resume = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            pauses.resume("e1").unwrap();
+        };
+        let waited = async {
+            pauses.wait_resumed("e1").await;
+            started.elapsed()
+        };
+        let (waited, ()) = tokio::join!(waited, resume);
+        assert!(waited >= Duration::from_millis(100), "{:?}", waited);
+        assert!(!pauses.is_paused("e1"));
+    }
+
+    #[tokio::test]
+    async fn a_timeout_leaves_out_the_time_spent_paused() {
+        let pauses = PauseState::default();
+        let work = || tokio::time::sleep(Duration::from_millis(400));
+        assert_eq!(pauses.timeout(Some("e1"), Duration::from_millis(250), work()).await, None);
+
+        // Paused for 300ms of it, the same work fits
+        let pause = async {
+            pauses.pause("e1").unwrap();
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            pauses.resume("e1").unwrap();
+        };
+        let (finished, ()) = tokio::join!(pauses.timeout(Some("e1"), Duration::from_millis(250), work()), pause);
+        assert_eq!(finished, Some(()));
+        // Without an export there is nothing to credit
+        assert_eq!(pauses.timeout(None, Duration::from_millis(10), work()).await, None);
+    }
+}
@@ -0,0 +1,391 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tauri::{command, AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+
+// Games returned with their full text by read_pgn_file; the rest are fetched with load_game
+const PREVIEW_GAMES: usize = 10;
+
+const RESULTS: &[&str] = &["1-0", "0-1", "1/2-1/2", "*"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PgnGame {
+    // Kept in file order; the seven-tag roster usually comes first
+    pub headers: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    pub result: Option<String>,
+}
+
+fn parse_header(line: &str, line_number: usize) -> Result<(String, String), String> {
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|l| l.strip_suffix(']'))
+        .ok_or_else(|| format!("Malformed header on line {}: {}", line_number, line))?;
+    let (key, rest) = inner
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| format!("Header on line {} has no value", line_number))?;
+
+    let quoted = rest.trim();
+    let value = quoted
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(|| format!("Header value on line {} must be quoted", line_number))?;
+
+    Ok((key.to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+fn is_san(token: &str) -> bool {
+    let token = token.trim_end_matches(['!', '?']);
+    if token.is_empty() {
+        return false;
+    }
+    if matches!(token.trim_end_matches(['+', '#']), "O-O" | "O-O-O" | "0-0" | "0-0-0") {
+        return true;
+    }
+    token.chars().all(|c| "abcdefghKQRBNx12345678+#=".contains(c))
+        && token.chars().any(|c| c.is_ascii_digit())
+}
+
+// Strips a leading move number ("12." or "12...") that may be glued to the move itself
+fn strip_move_number(token: &str) -> &str {
+    let digits = token.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && token[digits..].starts_with('.') {
+        token[digits..].trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+// Parses the first game in `text`: tag pairs followed by the mainline moves in SAN
+pub fn parse_game(text: &str) -> Result<PgnGame, String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let mut game = PgnGame {
+        headers: Vec::new(),
+        moves: Vec::new(),
+        result: None,
+    };
+
+    let mut movetext = String::new();
+    let mut movetext_start = 0;
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('%') {
+            continue;
+        }
+        if movetext.trim().is_empty() && trimmed.starts_with('[') {
+            game.headers.push(parse_header(trimmed, i + 1)?);
+            movetext_start = i + 1;
+        } else if trimmed.starts_with('[') && !movetext.trim().is_empty() {
+            // The next game's tags; only the first game is parsed
+            break;
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    let mut chars = movetext.char_indices().peekable();
+    let mut depth = 0usize;
+    let line_of = |offset: usize| movetext_start + 1 + movetext[..offset].matches('\n').count();
+
+    while let Some(&(offset, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '{' => {
+                if !chars.by_ref().any(|(_, c)| c == '}') {
+                    return Err(format!("Unterminated comment starting on line {}", line_of(offset)));
+                }
+            }
+            ';' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => {
+                depth += 1;
+                chars.next();
+            }
+            ')' => {
+                if depth == 0 {
+                    return Err(format!("Unbalanced ')' on line {}", line_of(offset)));
+                }
+                depth -= 1;
+                chars.next();
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if c.is_whitespace() || "{}();".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+
+                // Variations are skipped wholesale, only the mainline is kept
+                if depth > 0 || token.starts_with('$') {
+                    continue;
+                }
+                if RESULTS.contains(&token.as_str()) {
+                    game.result = Some(token);
+                    break;
+                }
+                let san = strip_move_number(&token);
+                if san.is_empty() {
+                    continue;
+                }
+                if !is_san(san) {
+                    return Err(format!("Unexpected token '{}' on line {}", token, line_of(offset)));
+                }
+                game.moves.push(san.trim_end_matches(['!', '?']).to_string());
+            }
+        }
+    }
+
+    if depth > 0 {
+        return Err("Unterminated variation at end of game".to_string());
+    }
+    if game.headers.is_empty() && game.moves.is_empty() {
+        return Err("No PGN game found".to_string());
+    }
+    Ok(game)
+}
+
+// Decodes PGN bytes, falling back to Latin-1 (common in older databases) when they aren't UTF-8
+pub fn decode_text(bytes: &[u8]) -> (String, bool) {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), false),
+        Err(_) => (bytes.iter().map(|&b| b as char).collect(), true),
+    }
+}
+
+// Tag pairs from the start of a game, skipping any that don't parse
+fn parse_headers(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with('[') || line.starts_with('%'))
+        .enumerate()
+        .filter(|(_, line)| line.starts_with('['))
+        .filter_map(|(i, line)| parse_header(line, i + 1).ok())
+        .collect()
+}
+
+fn trim_bytes(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+    let end = bytes.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |i| i + 1);
+    &bytes[start..end]
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GameSpan {
+    offset: u64,
+    length: u64,
+}
+
+// Walks a PGN database line by line, calling `on_game` with each game's span and bytes.
+// A game ends where a tag line follows movetext, outside of a brace comment.
+fn split_games<R: BufRead, F>(mut reader: R, mut on_game: F) -> std::io::Result<()>
+where
+    F: FnMut(GameSpan, &[u8]),
+{
+    let mut line = Vec::new();
+    let mut game = Vec::new();
+    let mut start = 0u64;
+    let mut offset = 0u64;
+    let mut in_movetext = false;
+    let mut in_comment = false;
+
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        let trimmed = trim_bytes(&line);
+        let trimmed = if offset == 0 { trimmed.strip_prefix(b"\xef\xbb\xbf").unwrap_or(trimmed) } else { trimmed };
+
+        if !in_comment && in_movetext && trimmed.starts_with(b"[") {
+            on_game(GameSpan { offset: start, length: game.len() as u64 }, &game);
+            game.clear();
+            in_movetext = false;
+        }
+        if game.is_empty() {
+            if trimmed.is_empty() {
+                offset += read as u64;
+                continue;
+            }
+            start = offset;
+        }
+        if !trimmed.is_empty() && !trimmed.starts_with(b"[") && !trimmed.starts_with(b"%") {
+            in_movetext = true;
+        }
+        if in_movetext {
+            for &b in trimmed {
+                match b {
+                    b'{' => in_comment = true,
+                    b'}' => in_comment = false,
+                    _ => {}
+                }
+            }
+        }
+
+        game.extend_from_slice(&line);
+        offset += read as u64;
+    }
+
+    if !trim_bytes(&game).is_empty() {
+        on_game(GameSpan { offset: start, length: game.len() as u64 }, &game);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct PgnGameSummary {
+    pub index: usize,
+    pub headers: Vec<(String, String)>,
+    // Set when the game wasn't valid UTF-8 and was decoded as Latin-1
+    pub latin1: bool,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PgnFile {
+    pub path: String,
+    pub games: Vec<PgnGameSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoadedGame {
+    pub index: usize,
+    pub text: String,
+    pub latin1: bool,
+    pub game: PgnGame,
+}
+
+// Game offsets from the last scan of each file, so load_game doesn't rescan a large database
+struct IndexedFile {
+    modified: SystemTime,
+    len: u64,
+    spans: Vec<GameSpan>,
+}
+
+#[derive(Default)]
+pub struct PgnIndex {
+    files: Mutex<HashMap<PathBuf, IndexedFile>>,
+}
+
+impl PgnIndex {
+    fn lookup(&self, path: &Path) -> Option<Vec<GameSpan>> {
+        let meta = fs::metadata(path).ok()?;
+        let files = self.files.lock().unwrap();
+        let indexed = files.get(path)?;
+        (meta.modified().ok()? == indexed.modified && meta.len() == indexed.len).then(|| indexed.spans.clone())
+    }
+
+    fn store(&self, path: &Path, spans: Vec<GameSpan>) {
+        let Ok(meta) = fs::metadata(path) else {
+            return;
+        };
+        if let Ok(modified) = meta.modified() {
+            let indexed = IndexedFile { modified, len: meta.len(), spans };
+            self.files.lock().unwrap().insert(path.to_path_buf(), indexed);
+        }
+    }
+}
+
+fn scan_file(path: &Path) -> Result<(Vec<PgnGameSummary>, Vec<GameSpan>), String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut games = Vec::new();
+    let mut spans = Vec::new();
+
+    split_games(BufReader::new(file), |span, bytes| {
+        let index = spans.len();
+        let (text, latin1) = decode_text(bytes);
+        games.push(PgnGameSummary {
+            index,
+            headers: parse_headers(&text),
+            latin1,
+            text: (index < PREVIEW_GAMES).then_some(text),
+        });
+        spans.push(span);
+    })
+    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    Ok((games, spans))
+}
+
+#[command]
+pub async fn read_pgn_file(
+    app: AppHandle,
+    pgn_index: State<'_, PgnIndex>,
+    path: Option<String>,
+) -> Result<Option<PgnFile>, String> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let picked = app.dialog().file().add_filter("PGN", &["pgn"]).blocking_pick_file();
+            match picked {
+                Some(file) => file.into_path().map_err(|e| e.to_string())?,
+                None => return Ok(None),
+            }
+        }
+    };
+
+    let scan_path = path.clone();
+    let (games, spans) = tauri::async_runtime::spawn_blocking(move || scan_file(&scan_path))
+        .await
+        .map_err(|e| e.to_string())??;
+    log::info!("Read {} games from {}", games.len(), path.display());
+
+    pgn_index.store(&path, spans);
+    Ok(Some(PgnFile {
+        path: path.display().to_string(),
+        games,
+    }))
+}
+
+#[command]
+pub async fn load_game(pgn_index: State<'_, PgnIndex>, path: String, index: usize) -> Result<LoadedGame, String> {
+    let path = PathBuf::from(path);
+    let spans = match pgn_index.lookup(&path) {
+        Some(spans) => spans,
+        None => {
+            let scan_path = path.clone();
+            let (_, spans) = tauri::async_runtime::spawn_blocking(move || scan_file(&scan_path))
+                .await
+                .map_err(|e| e.to_string())??;
+            pgn_index.store(&path, spans.clone());
+            spans
+        }
+    };
+
+    let span = *spans
+        .get(index)
+        .ok_or_else(|| format!("{} has {} games, no game at index {}", path.display(), spans.len(), index))?;
+
+    let mut file = File::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut bytes = vec![0; span.length as usize];
+    file.seek(SeekFrom::Start(span.offset))
+        .and_then(|_| file.read_exact(&mut bytes))
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let (text, latin1) = decode_text(&bytes);
+    let game = parse_game(&text).map_err(|e| format!("Game {} is malformed: {}", index, e))?;
+    Ok(LoadedGame {
+        index,
+        text,
+        latin1,
+        game,
+    })
+}
@@ -0,0 +1,1470 @@
+use tauri::{command, AppHandle, Manager};
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use serde_json::Value;
+
+use crate::benchmark::BenchmarkStore;
+use crate::cancel;
+use crate::chapters::{self, Chapter};
+use crate::drawtext::default_font_file;
+use crate::encoders;
+use crate::escape::render_command_line;
+use crate::export_data::{
+    background_audio, drop_zero_duration_moves, letterbox_fill, move_count, validate_export_data, AnimationKind, BackgroundBehavior, BackgroundTransform, BackgroundTreatment, BackgroundZoom, ClockOverlay, ColorGrade, CompositeStrategy, HwaccelDecode, EncodeSettings, BackgroundClip, ExtraLayer, GeneratedCanvas, HdrHandling, LayoutMode, MoveFlash, MoveRange,
+    OutputSpec, OutOfBounds, OverlayAnimation, OverlayCrop, ResourceLimits, SeekMode, SideBySide, SlideEdge,
+    SegmentTransition, TimePrecision,
+};
+use crate::exports::{ExportRegistry, ExportTracking};
+use crate::failure::{self, AtStage, ExportFailure, FailureStage};
+use crate::ffmpeglog;
+use crate::destination;
+use crate::filename::describe;
+use crate::finish;
+use crate::filtergraph;
+use crate::ffmpeg::{
+    probe_audio, probe_metadata_tag, probe_video, probe_video_size, VideoProbe,
+};
+use crate::history::{EncodeStats, ExportHistory, HistoryEntry};
+use crate::hook;
+use crate::hwdecode;
+use crate::instance::InstanceLock;
+use crate::jobstate::{find_crashed, hash_content, JobStage, JobState};
+use crate::metadata;
+use crate::migrate;
+use crate::outputname;
+use crate::preflight;
+use crate::presets;
+use crate::paths::{path_arg, write_atomic, ProjectPaths};
+use crate::scheduler::{Priority, Scheduler};
+use crate::segments;
+use crate::progress::{ProgressReporter, Stage};
+use crate::settings::SettingsState;
+use crate::sizetarget::{self, TwoPassJob};
+use crate::stitch;
+use crate::timecode;
+use crate::timeouts::{self, FrameBudget, JobTimeouts};
+use crate::timings::{self, Timings};
+use crate::warnings::{self, WarningCollector};
+use crate::hello::{
+    applied_rotation, canvas_plan, cfr_rate, check_offset_in_frame, check_placement, composition_frames, execute_ffmpeg_command,
+    flash_windows, get_multiple_overlay_command, hdr_path, letterbox_plan, move_cuts, move_filter_to_script, move_times,
+    overlay_plan, range_frames, render_chess_animation, rendered_frames, rotated_size, round_ms, segment_concat_args, segment_jobs,
+    segmented_unsupported, still_duration, validate_move_gaps, zoom_for_layout, BackgroundSpan, CanvasPlan, CompositeOptions,
+    HdrPath, Letterbox, OverlayPosition, Placement, RenderOptions, SourceTrim, SyntheticCanvas, TimeBase, TimingPlan,
+};
+use crate::webhook;
+use crate::workdir::{preview_dir, WorkDirs};
+
+// What every stage of one export runs inside: its FFmpeg log, its warnings and its timings
+struct ExportContext {
+    ffmpeg_log: Option<PathBuf>,
+    warnings: Arc<WarningCollector>,
+    timings: Arc<Timings>,
+    timeouts: Arc<JobTimeouts>,
+}
+
+impl ExportContext {
+    async fn run<F: Future>(&self, future: F) -> F::Output {
+        let future = timeouts::scope(self.timeouts.clone(), timings::scope(self.timings.clone(), future));
+        ffmpeglog::scope(self.ffmpeg_log.clone(), warnings::scope(self.warnings.clone(), future)).await
+    }
+
+    // Runs one step of the export, its error tagged with the stage it failed in
+    async fn stage<T, F: Future<Output = Result<T, String>>>(&self, stage: FailureStage, future: F) -> Result<T, ExportFailure> {
+        self.run(future).await.at(stage)
+    }
+}
+
+// The render's limit is known up front from the frame count; the ffmpeg one once the composite
+// has probed the background
+fn job_timeouts(app: &AppHandle, data: &Value, frames: u64) -> Arc<JobTimeouts> {
+    let settings = app.state::<SettingsState>().get();
+    let bounds = timeouts::Bounds { min_secs: settings.timeout_min_secs, max_secs: settings.timeout_max_secs };
+    let explicit = |field: &str| data.get(field).and_then(|v| v.as_u64());
+    let budget = FrameBudget::learned(app.state::<ExportHistory>().render_frame_secs(), app.state::<BenchmarkStore>().render_frame_secs());
+    let render = timeouts::render_timeout(explicit("render_timeout_secs"), frames, budget, bounds);
+    log::info!("Render timeout: {}s from {}", render.secs, render.basis);
+    JobTimeouts::new(render, explicit("ffmpeg_timeout_secs"), bounds)
+}
+
+#[command]
+pub async fn export(app: tauri::AppHandle, data: Value) -> Result<String, ExportFailure> {
+    export_with_priority(app, data, Priority::Interactive).await
+}
+
+// A preview always runs at Priority::Preview, whatever `priority` says
+pub async fn export_with_priority(app: tauri::AppHandle, data: Value, priority: Priority) -> Result<String, ExportFailure> {
+    let result = run_export(app.clone(), data, priority).await;
+    if let Err(failure) = &result {
+        failure::emit(&app, failure);
+    }
+    result
+}
+
+async fn run_export(app: tauri::AppHandle, data: Value, priority: Priority) -> Result<String, ExportFailure> {
+    let timings = Timings::new();
+    // Bad numbers would otherwise only surface as an ffmpeg error after the whole render
+    let validation = timings.span("validation");
+    let (data, migration) = migrate::migrate(data).map_err(|e| format!("Invalid export data: {}", e)).at(FailureStage::Validation)?;
+    let data = timecode::timecodes_to_seconds(&app, data).await.at(FailureStage::Validation)?;
+    let max_moves = app.state::<SettingsState>().get().max_moves;
+    validate_export_data(&data, max_moves).map_err(|e| format!("Invalid export data: {}", e)).at(FailureStage::Validation)?;
+    let mut data = timecode::resolve_background_fps(&app, data).await.at(FailureStage::Validation)?;
+    let skipped_moves = drop_zero_duration_moves(&mut data).map_err(|e| format!("Invalid export data: {}", e)).at(FailureStage::Validation)?;
+    validate_move_gaps(&data).map_err(|e| format!("Invalid export data: {}", e)).at(FailureStage::Validation)?;
+    let data = outputname::apply_template(&app, data).at(FailureStage::Validation)?;
+    let data = destination::normalize_output_paths(data).map_err(|e| format!("Invalid export data: {}", e)).at(FailureStage::Validation)?;
+    let (data, renamed_outputs) = outputname::sanitize_output_paths(data).map_err(|e| format!("Invalid export data: {}", e)).at(FailureStage::Validation)?;
+
+    // A preview is an ordinary export of the first moves, aimed at its own file
+    let preview_path = preview_dir(&app).at(FailureStage::WriteProps)?.join("preview.mp4");
+    let preview = preview_payload(&data, &preview_path).at(FailureStage::Validation)?;
+    let is_preview = preview.is_some();
+    let data = preview.unwrap_or(data);
+    let priority = if is_preview { Priority::Preview } else { priority };
+    let limits = ResourceLimits::from_value(&data).map_err(|e| format!("Invalid export data: {}", e)).at(FailureStage::Validation)?;
+
+    // The same checks as validate_export_paths, before anything slow starts; stitched clips check their own files
+    let background = data.get("videoPath").and_then(|v| v.as_str()).filter(|_| data.get("background_clips").is_none());
+    let output = data.get("outputPath").and_then(|v| v.as_str());
+    let paths = preflight::check_paths(&app, background, None, output).await;
+    if let Some(failure) = paths.failure() {
+        return Err(ExportFailure::new(FailureStage::Validation, "preflight_failed", format!("The export can't start: {}", failure)));
+    }
+    if let Some(action) = app.state::<SettingsState>().get().finish_action.filter(|_| !is_preview) {
+        action.check(&app).map_err(|e| format!("The export can't start: {}", e)).at_code(FailureStage::Validation, "preflight_failed")?;
+    }
+    app.state::<InstanceLock>().ensure(&app).map_err(|e| format!("The export can't start: {}", e)).at_code(FailureStage::Validation, "instance_locked")?;
+    drop(validation);
+
+    // Warned about once the export's warnings are collected
+    let mut notices: Vec<(&'static str, String, Option<Value>)> = Vec::new();
+    if migration.migrated() {
+        let message = format!("Migrated the export data from schema version {} to {}", migration.from, migration.to);
+        notices.push(("schema_migrated", message, serde_json::to_value(&migration).ok()));
+    }
+    if !skipped_moves.is_empty() {
+        let indices: Vec<String> = skipped_moves.iter().map(|i| i.to_string()).collect();
+        let message = format!("Skipped the moves at timestamps index {}, which repeated the previous timestamp", indices.join(", "));
+        notices.push(("zero_duration_skipped", message, Some(serde_json::json!({ "indices": skipped_moves }))));
+    }
+    for renamed in renamed_outputs {
+        let message = format!("Renamed the output {} to {}: {}", renamed.from, renamed.to, describe(&renamed.issues));
+        notices.push(("output_renamed", message, serde_json::to_value(&renamed).ok()));
+    }
+
+    let export_id = app.state::<ExportRegistry>().start_export();
+    let _tracking = ExportTracking::new(&app, &export_id);
+    log::info!("Starting export {}{}", export_id, if is_preview { " (preview)" } else { "" });
+    let export = run_started_export(&app, &export_id, data, priority, limits, timings, notices);
+    cancel::run_export(&app, &export_id, export).await
+}
+
+// The export from its turn in the queue on, which cancel_export can stop at any point
+async fn run_started_export(
+    app: &AppHandle,
+    export_id: &str,
+    data: Value,
+    priority: Priority,
+    limits: Option<ResourceLimits>,
+    timings: Arc<Timings>,
+    notices: Vec<(&'static str, String, Option<Value>)>,
+) -> Result<String, ExportFailure> {
+    let is_preview = data.get("preview_moves").is_some();
+    let _slot = app.state::<Scheduler>().admit(app, export_id, priority).await;
+    // Failures before the render are recorded here; the later ones go through finish_export
+    let failed = |failure: ExportFailure| {
+        let failure = failure.for_export(export_id);
+        app.state::<ExportRegistry>().record_finish(export_id, "failed", &Err(failure.clone()));
+        failure
+    };
+
+    let workdir = app.state::<WorkDirs>().allocate(app, export_id).at(FailureStage::WriteProps).map_err(failed)?;
+    log::info!("Working directory: {}", workdir.path().display());
+    let animation_path = workdir.file("chess-animation.mp4");
+
+    // First, write the JSON data to file
+    let content = serde_json::to_string_pretty(&data)
+        .map_err(|e| format!("Failed to serialize data: {}", e))
+        .at(FailureStage::WriteProps)
+        .map_err(failed)?;
+
+    let mut job = JobState::new(export_id, workdir.path(), &animation_path, &data, &content);
+    job.set_stage(JobStage::Started);
+
+    let moves = move_count(&data);
+    let progress = ProgressReporter::new(app, export_id, moves);
+    let context = ExportContext {
+        // Every ffmpeg run of this export, every pass included, is appended here
+        ffmpeg_log: ffmpeglog::log_path(app, export_id).ok(),
+        // Warnings from both stages end up in the composite's result
+        warnings: WarningCollector::new(app, export_id),
+        timings: timings.clone(),
+        timeouts: job_timeouts(app, &data, rendered_frames(&data)),
+    };
+    let ffmpeg_log = context.ffmpeg_log.clone();
+    for (code, message, detail) in notices {
+        context.run(async { warnings::warn_with(code, message, detail) }).await;
+    }
+    
+    // The render's props, kept next to the intermediates so the job can be inspected and resumed afterwards.
+    // Nothing of one export is written outside its working directory until the final output.
+    let export_json = timings.span("export_json");
+    let props_path = workdir.file("export.json");
+    if let Err(e) = write_atomic(&props_path, &content).await {
+        job.set_stage(JobStage::Failed);
+        return Err(failed(ExportFailure::new(FailureStage::WriteProps, "props_write_failed", e)));
+    }
+    log::info!("File written successfully to {}", props_path.display());
+    job.set_stage(JobStage::PropsWritten);
+    drop(export_json);
+
+    // House rules get the final payload, before anything renders
+    if !is_preview {
+        let pre_export_hook = timings.span("pre_export_hook");
+        let allowed = context.run(hook::before_export(app, export_id, &data, &props_path)).await;
+        drop(pre_export_hook);
+        if let Err(e) = allowed {
+            job.set_stage(JobStage::Failed);
+            let failure = ExportFailure::new(FailureStage::Validation, e.code(), e.to_string());
+            return finish_export(app, &context, export_id, &data, e.status(), timings.snapshot(), Err(failure)).await;
+        }
+    }
+    
+    // Now render the chess animation
+    log::info!("Starting chess animation rendering...");
+    job.set_stage(JobStage::Rendering);
+    let parallel = app
+        .state::<SettingsState>()
+        .get()
+        .parallel_render
+        .or_else(|| app.state::<BenchmarkStore>().recommended_parallel_render())
+        .unwrap_or(1);
+    let total_frames = composition_frames(&data);
+    let render_options = RenderOptions {
+        total_frames,
+        frame_range: range_frames(&data),
+        parallel,
+        scale: is_preview.then_some(PREVIEW_RENDER_SCALE),
+    };
+    let render = timings.span(Stage::Render.name());
+    let rendered = context.stage(FailureStage::Render, render_chess_animation(app, &props_path, &animation_path, render_options, limits, &progress)).await;
+    drop(render);
+    if let Err(failure) = rendered {
+        let failure = failure.map_message(|e| ffmpeglog::with_log_path(format!("Rendering failed: {}", e), ffmpeg_log.as_deref()));
+        job.set_stage(JobStage::Failed);
+        return finish_export(app, &context, export_id, &data, "failed", timings.snapshot(), Err(failure)).await;
+    }
+    log::info!("Chess animation rendered successfully!");
+
+    job.set_stage(JobStage::Rendered);
+
+    job.set_stage(JobStage::Compositing);
+    let composite = timings.span(Stage::Composite.name());
+    let result = context.run(composite_to_destinations(app, export_id, &data, &animation_path, &progress)).await
+        .map_err(|failure| failure.map_message(|e| ffmpeglog::with_log_path(e, ffmpeg_log.as_deref())));
+    drop(composite);
+    job.set_stage(if result.is_ok() { JobStage::Completed } else { JobStage::Failed });
+    let stage_timings = timings.snapshot();
+    let result = result.map(|r| with_timings(&r, &stage_timings, is_preview));
+    // Previews are never handed to the hook
+    let result = match result {
+        Ok(r) if !is_preview => Ok(context.run(hook::after_export(app, export_id, &data, r)).await),
+        other => other,
+    };
+    let status = if result.is_ok() { "completed" } else { "failed" };
+    finish_export(app, &context, export_id, &data, status, stage_timings, result).await
+}
+
+// Outputs on network shares are composited into the working directory and copied over afterwards,
+// then the finish_action setting copies or moves them on
+async fn composite_to_destinations(
+    app: &AppHandle,
+    export_id: &str,
+    data: &Value,
+    animation_path: &Path,
+    progress: &ProgressReporter,
+) -> Result<String, ExportFailure> {
+    let workdir = animation_path.parent().ok_or("Animation path has no parent directory".to_string()).at(FailureStage::Composite)?;
+    let (staged_data, staged) = destination::stage_outputs(data, workdir);
+    let mut result = composite_animation(app, export_id, &staged_data, animation_path, progress).await?;
+    if !staged.is_empty() {
+        let delivered = destination::deliver(&staged, progress).await.at_code(FailureStage::PostProcess, "delivery_failed")?;
+        result = destination::with_destinations(&result, &staged, &delivered);
+    }
+    // Previews stay in the preview folder
+    if data.get("preview_moves").is_some() {
+        return Ok(result);
+    }
+    Ok(finish::apply(app, result, progress).await)
+}
+
+// Previews render at this fraction of the composition's size and are encoded for speed, not quality
+const PREVIEW_RENDER_SCALE: f64 = 0.5;
+
+// The payload for a preview: the first `preview_moves` moves, written to the preview file only
+fn preview_payload(data: &Value, preview_path: &Path) -> Result<Option<Value>, String> {
+    let Some(moves) = data.get("preview_moves").and_then(|v| v.as_u64()) else {
+        return Ok(None);
+    };
+    let moves = (moves as usize).min(move_count(data));
+    let mut preview = data.clone();
+    let object = preview.as_object_mut().ok_or("Export data must be a JSON object")?;
+    object.insert("preview_moves".to_string(), serde_json::json!(moves));
+    object.insert("move_range".to_string(), serde_json::json!([1, moves]));
+    object.insert("outputPath".to_string(), serde_json::json!(path_arg(preview_path)?));
+    // Nothing that writes elsewhere or slows the encode down survives into a preview
+    for key in ["outputs", "target_size_mb", "platform_preset", "encoding", "videoEncoder", "post_export_hook"] {
+        object.remove(key);
+    }
+    Ok(Some(preview))
+}
+
+fn with_timings(result: &str, timings: &BTreeMap<String, f64>, preview: bool) -> String {
+    match serde_json::from_str::<Value>(result) {
+        Ok(mut value) => {
+            value["timings"] = serde_json::json!(timings);
+            if preview {
+                value["preview"] = Value::Bool(true);
+            }
+            value.to_string()
+        }
+        Err(_) => result.to_string(),
+    }
+}
+
+fn composition_fps(data: &Value) -> f64 {
+    let frame_per_move = data.get("framePerMove").and_then(|v| v.as_f64()).unwrap_or(5.0);
+    let time_per_move = data.get("timePerMove").and_then(|v| v.as_f64()).unwrap_or(0.2);
+    (frame_per_move / time_per_move).round()
+}
+
+// The overlay segments assume every rendered move lasts timePerMove. Remotion renders a different length
+// when the composition's fps or durationInFrames drift from that, and the segments then cut the wrong frames.
+async fn check_animation_duration(app: &AppHandle, data: &Value, animation_path: &Path) -> Result<Value, ExportFailure> {
+    let actual = probe_video(app, animation_path).await
+        .map_err(|e| format!("Failed to read the animation's duration: {}", e))
+        .at(FailureStage::Probe)?
+        .duration_secs;
+    compare_animation_duration(data, actual)
+}
+
+// The duration report of an animation lasting `actual` seconds, or the Verify failure when it's off
+fn compare_animation_duration(data: &Value, actual: Option<f64>) -> Result<Value, ExportFailure> {
+    let time_per_move = data.get("timePerMove").and_then(|v| v.as_f64()).unwrap_or(0.2);
+    let moves = match MoveRange::from_value(data).at(FailureStage::Validation)? {
+        Some(range) => range.last - range.first + 1,
+        None => move_count(data),
+    };
+    let expected = moves as f64 * time_per_move;
+    let tolerance = 1.0 / composition_fps(data);
+    let report = serde_json::json!({ "expected_secs": expected, "actual_secs": actual, "tolerance_secs": tolerance });
+    let Some(actual) = actual.filter(|actual| (actual - expected).abs() > tolerance) else {
+        return Ok(report);
+    };
+    let message = format!(
+        "The rendered animation is {:.3}s long but {} moves at timePerMove {} should take {:.3}s; check the fps and durationInFrames of the Remotion composition",
+        actual, moves, time_per_move, expected
+    );
+    if !data.get("ignore_duration_mismatch").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return Err(ExportFailure::new(FailureStage::Verify, "animation_duration_mismatch", message));
+    }
+    warnings::warn_with("animation_duration_mismatch", message, Some(report.clone()));
+    Ok(report)
+}
+
+// Where every export that got an id ends: it goes into the history and is reported to the webhook
+async fn finish_export(
+    app: &AppHandle,
+    context: &ExportContext,
+    export_id: &str,
+    data: &Value,
+    status: &str,
+    stage_durations: BTreeMap<String, f64>,
+    result: Result<String, ExportFailure>,
+) -> Result<String, ExportFailure> {
+    let result = result.map_err(|failure| failure.for_export(export_id));
+    record_history(app, export_id, data, status, stage_durations.clone(), result.as_deref().ok());
+    let result = context.run(webhook::notify(app, export_id, data, status, &stage_durations, result)).await;
+    app.state::<ExportRegistry>().record_finish(export_id, status, &result);
+    result
+}
+
+fn record_history(
+    app: &AppHandle,
+    export_id: &str,
+    data: &Value,
+    status: &str,
+    stage_durations: BTreeMap<String, f64>,
+    result: Option<&str>,
+) {
+    let result = result.and_then(|r| serde_json::from_str::<Value>(r).ok()).unwrap_or(Value::Null);
+    // The composite reports what it measured about the encode in its result
+    let encode = result.get("encode_stats").cloned().and_then(|stats| serde_json::from_value(stats).ok());
+    let finish_action = &result["finish_action"];
+    let finished_to: Vec<String> = finish_action["files"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|f| f["to"].as_str().map(String::from))
+        .collect();
+    // A moved output is in the user's folder now, out of the output retention's reach
+    let moved = finish_action["mode"] == "move" && !finished_to.is_empty();
+    // A resumed export only composites
+    let frames = stage_durations.contains_key(Stage::Render.name()).then(|| rendered_frames(data));
+    app.state::<ExportHistory>().record(HistoryEntry {
+        export_id: export_id.to_string(),
+        status: status.to_string(),
+        finished_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        moves: move_count(data),
+        video_path: data.get("videoPath").and_then(|v| v.as_str()).map(String::from),
+        output_path: result["output_path"].as_str().or_else(|| data.get("outputPath").and_then(|v| v.as_str())).map(String::from),
+        stage_durations,
+        encode,
+        preview: data.get("preview_moves").is_some(),
+        managed_output: !moved && data.get("managed_output").and_then(|v| v.as_bool()).unwrap_or(false),
+        pruned: false,
+        hooks_skipped: hook::skip_hooks(data),
+        finished_to,
+        rendered_frames: frames,
+    });
+}
+
+// Encoder and CRF as passed to ffmpeg; without -c:v the mp4 muxer picks libx264
+pub fn encoder_settings(args: &[String]) -> (String, Option<u32>) {
+    let value = |flag: &str| args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1));
+    let codec = value("-c:v").cloned().unwrap_or_else(|| "libx264".to_string());
+    let crf = value("-crf").and_then(|c| c.parse().ok());
+    (codec, crf)
+}
+
+// Probes the finished file so later size estimates can use real numbers
+async fn measure_encode(app: &AppHandle, args: &[String], output: &Path) -> Option<EncodeStats> {
+    let probe = probe_video(app, output).await.ok()?;
+    let size_bytes = fs::metadata(output).ok()?.len();
+    let (codec, crf) = encoder_settings(args);
+    Some(EncodeStats {
+        codec,
+        width: probe.width,
+        height: probe.height,
+        crf,
+        fps: probe.fps?,
+        duration_secs: probe.duration_secs?,
+        size_bytes,
+    })
+}
+
+// Intermediate encode for multiple outputs; the quality loss from transcoding it again is negligible
+pub const MEZZANINE_ARGS: &[&str] = &["-c:v", "libx264", "-preset", "veryfast", "-crf", "10"];
+
+// Resolves a requested encoder; "auto" falls back to libx264 when no hardware encoder passed verification
+async fn video_encoder(app: &AppHandle, requested: Option<&str>, codec: &str) -> Option<String> {
+    match requested? {
+        "auto" => {
+            let encoder = match encoders::auto_encoder(app, codec).await {
+                Some(encoder) => encoder,
+                None => {
+                    warnings::warn(
+                        "software_encoder_fallback",
+                        "No hardware encoder passed verification, so the video was encoded in software with libx264".to_string(),
+                    );
+                    "libx264".to_string()
+                }
+            };
+            log::info!("Using video encoder {}", encoder);
+            Some(encoder)
+        }
+        name => Some(name.to_string()),
+    }
+}
+
+// Encoders used when an output spec doesn't name a codec
+fn default_codecs(container: &str) -> (&'static str, &'static str) {
+    match container {
+        "webm" => ("libvpx-vp9", "libopus"),
+        _ => ("libx264", "aac"),
+    }
+}
+
+fn transcode_args(source: &str, spec: &OutputSpec, threads: Option<u32>) -> Vec<String> {
+    let container = spec.container();
+    let (default_video, audio_codec) = default_codecs(&container);
+    let video_codec = spec.codec.clone().unwrap_or_else(|| default_video.to_string());
+
+    let mut args: Vec<String> = ["-i", source, "-map", "0:v", "-map", "0:a?"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    if let Some((width, height)) = spec.dimensions() {
+        args.extend(["-vf".to_string(), format!("scale={}:{}", width, height)]);
+    }
+    args.extend(["-c:v".to_string(), video_codec.clone()]);
+    if let Some(quality) = spec.quality {
+        args.extend(["-crf".to_string(), quality.to_string()]);
+        // libvpx treats -crf as a cap on top of its default bitrate unless the bitrate is zeroed
+        if video_codec.contains("vpx") {
+            args.extend(["-b:v".to_string(), "0".to_string()]);
+        }
+    }
+    args.extend(["-c:a".to_string(), audio_codec.to_string()]);
+    if let Some(format) = &spec.format {
+        args.extend(["-f".to_string(), format.clone()]);
+    }
+    if let Some(threads) = threads.filter(|&t| t > 0) {
+        args.extend(["-threads".to_string(), threads.to_string()]);
+    }
+    args.extend(["-y".to_string(), spec.path.clone()]);
+    args
+}
+
+// Encodes every requested output from the mezzanine; a failed output is reported but doesn't stop the rest
+async fn transcode_outputs(
+    app: &AppHandle,
+    source: &str,
+    outputs: &[OutputSpec],
+    limits: Option<ResourceLimits>,
+    progress: &ProgressReporter,
+) -> Vec<Value> {
+    let mut results = Vec::with_capacity(outputs.len());
+    for (index, spec) in outputs.iter().enumerate() {
+        log::info!("Encoding output {} of {}: {}", index + 1, outputs.len(), spec.path);
+        let mut spec = spec.clone();
+        if spec.codec.as_deref() == Some("auto") {
+            // Only H.264 has hardware encoders we drive; other containers keep their software default
+            spec.codec = match default_codecs(&spec.container()).0 {
+                "libx264" => video_encoder(app, Some("auto"), "h264").await,
+                _ => None,
+            };
+        }
+        let args = transcode_args(source, &spec, limits.map(|l| l.ffmpeg_threads));
+        let report = |done: f64, total: f64| progress.report_output(index, &spec.path, done, total);
+        let low_priority = limits.map(|l| l.low_priority).unwrap_or(false);
+
+        let outcome = match execute_ffmpeg_command(app.clone(), &args, Some(&report), low_priority).await {
+            Ok(result) if result.success => Ok(()),
+            Ok(result) => Err(format!("FFmpeg command failed: {}\nReturn code: {:?}", result.error, result.return_code)),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &outcome {
+            log::error!("Output {} failed: {}", spec.path, e);
+        }
+        results.push(serde_json::json!({
+            "path": spec.path,
+            "format": spec.container(),
+            "status": if outcome.is_ok() { "success" } else { "failed" },
+            "error": outcome.err(),
+            "ffmpeg_command": render_command_line("ffmpeg", &args),
+        }));
+    }
+    results
+}
+
+// Chapter list for the output, empty when chapters are off or the container can't carry them
+pub fn move_chapters(data: &Value, plan: &TimingPlan, output_path: Option<&str>) -> Vec<Chapter> {
+    if !chapters::enabled(data) {
+        return Vec::new();
+    }
+    if let Some(output_path) = output_path.filter(|path| !chapters::supports_chapters(path)) {
+        warnings::warn("chapters_unsupported", format!("skipping chapters, {} does not support them", output_path));
+        return Vec::new();
+    }
+    let labels: Vec<Option<String>> = data.get("moves")
+        .and_then(|v| v.as_array())
+        .map(|moves| moves.iter().map(|m| m.as_str().map(String::from)).collect())
+        .unwrap_or_default();
+    chapters::build_chapters(&plan.windows, &plan.exported(&labels), plan.first_move_number())
+}
+
+// What a successful composite returns to the frontend
+#[derive(serde::Serialize)]
+struct CompositeResult<'a> {
+    status: &'static str,
+    export_id: &'a str,
+    overlay_segments: &'a [[f64; 2]],
+    background_segments: &'a [[f64; 2]],
+    background_behavior: BackgroundBehavior,
+    background_timeline: &'a [BackgroundSpan],
+    move_range: &'a Option<SourceTrim>,
+    output_duration: Option<f64>,
+    chapters: &'a [Chapter],
+    metadata: &'a BTreeMap<String, String>,
+    metadata_verified: Option<bool>,
+    encode_stats: &'a Option<EncodeStats>,
+    outputs: &'a [Value],
+    xy_offset: [f64; 2],
+    overlay_position: OverlayPosition,
+    overlay_crop: &'a Option<OverlayCrop>,
+    background_treatment: &'a Option<BackgroundTreatment>,
+    layout_mode: LayoutMode,
+    canvas: &'a Option<CanvasPlan>,
+    letterbox: &'a Option<Letterbox>,
+    placement: &'a Option<Placement>,
+    segment_positions: &'a [[f64; 2]],
+    segment_placements: &'a [Placement],
+    warnings: Vec<warnings::ExportWarning>,
+    background_zoom: &'a Option<BackgroundZoom>,
+    clock_overlay: &'a Option<ClockOverlay>,
+    move_flash: &'a Option<MoveFlash>,
+    overlay_animation: &'a Option<OverlayAnimation>,
+    segment_transition: &'a Option<SegmentTransition>,
+    color_grade: &'a Option<ColorGrade>,
+    deinterlace: &'a Value,
+    background_transform: &'a Option<BackgroundTransform>,
+    background_rotation: u32,
+    cfr_rate: Option<f64>,
+    merged_windows: usize,
+    animation_duration: &'a Value,
+    hdr: &'a Option<HdrPath>,
+    decode: &'a hwdecode::DecodeReport,
+    still_background: Option<f64>,
+    synthetic_canvas: &'a Option<SyntheticCanvas>,
+    background_clips: &'a Option<stitch::StitchedBackground>,
+    ffmpeg_log: Option<PathBuf>,
+    extra_layers: &'a [ExtraLayer],
+    platform_preset: &'a presets::ResolvedEncoding,
+    size_target: &'a Option<sizetarget::SizeTargetResult>,
+    video_path: Option<&'a str>,
+    output_path: Option<&'a str>,
+    ffmpeg_command: String,
+    filter_graph: &'a Option<filtergraph::FilterGraph>,
+    ffmpeg_output: &'a str,
+    ffmpeg_binary: &'a str,
+    seek_mode: SeekMode,
+    composite_strategy: CompositeStrategy,
+    segments: &'a Option<segments::SegmentReport>,
+    resource_limits: Option<ResourceLimits>,
+    timeouts: Option<timeouts::TimeoutReport>,
+    message: &'static str,
+}
+
+// Overlays an already rendered animation onto the background video
+pub async fn composite_animation(
+    app: &AppHandle,
+    export_id: &str,
+    data: &Value,
+    animation_path: &Path,
+    progress: &ProgressReporter,
+) -> Result<String, ExportFailure> {
+    log::info!("Processing overlay data...");
+    let (plan, position) = overlay_plan(data)?;
+    log::info!("Overlay data processed successfully!");
+    let animation_duration = check_animation_duration(app, data, animation_path).await?;
+    
+    // Extract videoPath and outputPath from the JSON data
+    let video_path = data.get("videoPath")
+        .and_then(|v| v.as_str());
+    let output_path = data.get("outputPath")
+        .and_then(|v| v.as_str());
+    
+    log::info!("Using paths from JSON:");
+    log::info!("  Video path (background): {:?}", video_path);
+    log::info!("  Output path: {:?}", output_path);
+
+    // With several outputs the overlay work is done once into a near-lossless mezzanine that each one is transcoded from
+    let outputs = OutputSpec::from_value(data).at(FailureStage::Validation)?;
+    let mezzanine = path_arg(&animation_path.with_file_name("composite.mkv")).at(FailureStage::Validation)?;
+    
+    let overlay_file = path_arg(animation_path).at(FailureStage::Validation)?;
+    let seek_mode = SeekMode::from_value(data).at(FailureStage::Validation)?;
+    let limits = ResourceLimits::from_value(data).at(FailureStage::Validation)?;
+    let background_behavior = BackgroundBehavior::from_value(data).at(FailureStage::Validation)?;
+
+    let crop = OverlayCrop::from_value(data).at(FailureStage::Validation)?;
+    let layout = LayoutMode::from_value(data).at(FailureStage::Validation)?;
+    let letterbox_fill = letterbox_fill(data).at(FailureStage::Validation)?;
+    let side_by_side = SideBySide::from_value(data).at(FailureStage::Validation)?;
+    let mut treatment = BackgroundTreatment::from_value(data).at(FailureStage::Validation)?;
+    if layout == LayoutMode::SideBySide && treatment.take().is_some() {
+        // Nothing covers the background side by side, so there is nothing to blur or dim behind
+        warnings::warn("option_ignored", "background_treatment is ignored in the side_by_side layout".to_string());
+    }
+    let zoom = BackgroundZoom::from_value(data).at(FailureStage::Validation)?;
+    let clock = ClockOverlay::from_value(data).at(FailureStage::Validation)?;
+    let flash = MoveFlash::from_value(data).at(FailureStage::Validation)?;
+    // Only an explicit true deinterlaces; otherwise a detected interlaced source just gets a warning
+    let deinterlace = data.get("deinterlace").and_then(|v| v.as_bool());
+    let transform = BackgroundTransform::from_value(data).at(FailureStage::Validation)?;
+    // Variable frame rates are normalised when detected; force_cfr true always does, false never
+    let force_cfr = data.get("force_cfr").and_then(|v| v.as_bool());
+    let transition = SegmentTransition::from_value(data).at(FailureStage::Validation)?;
+    let strategy = CompositeStrategy::from_value(data).at(FailureStage::Validation)?;
+    let color_grade = ColorGrade::from_value(data).at(FailureStage::Validation)?;
+    if let Some(lut) = color_grade.as_ref().and_then(|g| g.lut_file.as_ref()) {
+        if !Path::new(lut).is_file() {
+            return Err(ExportFailure::new(FailureStage::Validation, "input_not_found", format!("LUT file not found: {}", lut)));
+        }
+    }
+    let mut animation = OverlayAnimation::from_value(data).at(FailureStage::Validation)?;
+    if layout == LayoutMode::SideBySide && side_by_side.persistent && animation.take().is_some() {
+        warnings::warn("option_ignored", "overlay_animation is ignored because the side_by_side board is persistent".to_string());
+    }
+    // Picking the nearest edge needs both the board's and the frame's size
+    let nearest_edge = layout == LayoutMode::Overlay
+        && animation.is_some_and(|a| a.kind == AnimationKind::Slide && a.edge == SlideEdge::Nearest);
+    // videoEncoder predates the encoding options and counts as an explicit codec
+    let mut explicit_encoding = EncodeSettings::from_value(data).at(FailureStage::Validation)?;
+    if explicit_encoding.video_codec.is_none() {
+        explicit_encoding.video_codec = data.get("videoEncoder").and_then(|v| v.as_str()).map(String::from);
+    }
+    let preset_name = data.get("platform_preset").and_then(|v| v.as_str());
+    let target_size_mb = data.get("target_size_mb").and_then(|v| v.as_f64());
+    let preview = data.get("preview_moves").is_some();
+    let mut encoding = if preview {
+        presets::preview_encoding()
+    } else {
+        presets::resolve(preset_name, &explicit_encoding, target_size_mb).at(FailureStage::Validation)?
+    };
+    if encoding.target_size_mb.is_some() && !outputs.is_empty() {
+        let message = "target_size_mb applies to outputPath and can't be combined with outputs".to_string();
+        return Err(ExportFailure::new(FailureStage::Validation, "invalid_export_data", message));
+    }
+    // The extra outputs carry their own resolutions
+    let requested_size = encoding.settings.dimensions().filter(|_| outputs.is_empty());
+    if encoding.target_size_mb.is_some() && output_path.is_none() {
+        return Err(ExportFailure::new(FailureStage::Validation, "invalid_export_data", "target_size_mb needs an outputPath".to_string()));
+    }
+    // A size-targeted encode runs twice per attempt, so the overlay work goes into the mezzanine first
+    let via_mezzanine = !outputs.is_empty() || encoding.target_size_mb.is_some();
+    let composite_path = if via_mezzanine { Some(mezzanine.as_str()) } else { output_path };
+    if let Some(preset) = &encoding.preset {
+        log::info!("Using platform preset {} (overridden: {:?})", preset, encoding.overrides);
+    }
+    let mut layers = ExtraLayer::from_value(data).at(FailureStage::Validation)?;
+    for layer in &mut layers {
+        if !Path::new(&layer.file).is_file() {
+            return Err(ExportFailure::new(FailureStage::Validation, "input_not_found", format!("Extra layer not found: {}", layer.file)));
+        }
+        if layer.mix_audio && probe_audio(app, Path::new(&layer.file)).await.at(FailureStage::Probe)?.stream.is_none() {
+            warnings::warn("layer_without_audio", format!("{} has no audio to mix, using its video only", layer.file));
+            layer.mix_audio = false;
+        }
+    }
+    let base = TimeBase::from_value(data).at(FailureStage::Validation)?;
+    let move_times: Vec<f64> = move_times(data, base).into_iter().map(|t| base.seconds(t)).collect();
+
+    let out_of_bounds = OutOfBounds::from_value(data).at(FailureStage::Validation)?;
+    // Side by side the board is fitted into its own region, so it can't leave the frame
+    let check_bounds = out_of_bounds != OutOfBounds::Allow && layout == LayoutMode::Overlay;
+    // Every export probes the background: HDR, interlacing and variable frame rates are detected from it,
+    // and sizes, audio and duration feed the placement, the timeline and the encode limits
+    let background_clips = BackgroundClip::from_value(data).at(FailureStage::Validation)?;
+    let stitched_path = animation_path.with_file_name("stitched_background.mkv");
+    let stitched = match &background_clips {
+        Some(clips) => {
+            if video_path.is_some() {
+                warnings::warn("option_ignored", "videoPath is ignored because background_clips are given".to_string());
+            }
+            let loop_short = data.get("loop_background_clips").and_then(|v| v.as_bool()).unwrap_or(false);
+            let time_per_move = data.get("timePerMove").and_then(|v| v.as_f64()).unwrap_or(0.2);
+            let track = background_audio(data).at(FailureStage::Validation)?;
+            let _stitching = timings::span("stitch_background");
+            let stitched = stitch::stitch_background(
+                app, clips, &move_cuts(data).at(FailureStage::Validation)?, time_per_move, loop_short, track.as_deref(), &stitched_path,
+            ).await.at_code(FailureStage::Composite, "stitch_failed")?;
+            log::info!("Stitched {} clips into a {}s background", stitched.segments.len(), stitched.duration);
+            Some(stitched)
+        }
+        None => None,
+    };
+    let stitched_file = path_arg(&stitched_path).at(FailureStage::Validation)?;
+    let video_path = if stitched.is_some() { Some(stitched_file.as_str()) } else { video_path };
+    let mut synthetic_canvas = None;
+    let background = match video_path {
+        Some(video_path) => {
+            let probe = match probe_video(app, Path::new(video_path)).await {
+                Ok(probe) => probe,
+                Err(e) => {
+                    // No video stream but readable audio is a commentary track: the board goes over a
+                    // generated canvas. A file with neither is unreadable, which is still an error.
+                    let audio = probe_audio(app, Path::new(video_path)).await
+                        .ok()
+                        .filter(|a| a.stream.is_some())
+                        .ok_or_else(|| format!("Failed to read the background dimensions: {}", e))
+                        .at_code(FailureStage::Probe, "background_unreadable")?;
+                    let generated = GeneratedCanvas::from_value(data).at(FailureStage::Validation)?;
+                    let canvas = SyntheticCanvas {
+                        color: generated.color.trim().to_string(),
+                        width: generated.width,
+                        height: generated.height,
+                        fps: composition_fps(data),
+                        duration: audio.duration_secs.unwrap_or_else(|| still_duration(&plan)),
+                    };
+                    log::info!("{} has no video, generating a {}x{} {} canvas", video_path, canvas.width, canvas.height, canvas.color);
+                    let probe = VideoProbe {
+                        width: canvas.width,
+                        height: canvas.height,
+                        duration_secs: Some(canvas.duration),
+                        has_audio: true,
+                        fps: Some(canvas.fps),
+                        interlaced: None,
+                        rotation: 0,
+                        base_fps: None,
+                        variable_frame_rate: false,
+                        hdr_transfer: None,
+                        still_image: false,
+                    };
+                    synthetic_canvas = Some(canvas);
+                    probe
+                }
+            };
+            log::info!("Background dimensions: {}x{}", probe.width, probe.height);
+            Some(probe)
+        }
+        None => None,
+    };
+    let source_rotation = background.map(|b| b.rotation).unwrap_or(0);
+    // Positions and sizes refer to the frame as it appears after rotation
+    let frame_size = background.map(|b| rotated_size((b.width, b.height), applied_rotation(transform, b.rotation)));
+    // Side by side the canvas already has the requested size
+    let letterbox = match (requested_size, frame_size, layout) {
+        (Some(requested), Some(size), LayoutMode::Overlay) => letterbox_plan(size, requested, &letterbox_fill),
+        _ => None,
+    };
+    let interlaced = background.and_then(|b| b.interlaced);
+    let detection = match (interlaced, deinterlace) {
+        (Some(true), Some(true)) => Some("The background is interlaced and was deinterlaced".to_string()),
+        (Some(true), None) => Some(
+            "The background looks interlaced, which shows as combing over motion; set deinterlace to true to remove it".to_string()
+        ),
+        (Some(false), Some(true)) => Some(
+            "deinterlace was set but the background reports progressive frames; deinterlacing anyway may soften it".to_string()
+        ),
+        _ => None,
+    };
+    let cfr_rate = cfr_rate(background, force_cfr);
+    if let (Some(rate), Some(b)) = (cfr_rate, background) {
+        let warning = if b.variable_frame_rate {
+            format!(
+                "The background has a variable frame rate (averaging {} fps); it was normalised to {} fps",
+                b.fps.map(|f| f.to_string()).unwrap_or_else(|| "unknown".to_string()), rate
+            )
+        } else {
+            format!("The background was normalised to a constant {} fps as force_cfr asked", rate)
+        };
+        warnings::warn("frame_rate_normalised", warning);
+    }
+    // The plan is worked out in the payload's time base; the filter values are written in seconds
+    let mut plan = plan.in_seconds();
+    if TimePrecision::from_value(data).at(FailureStage::Validation)? == TimePrecision::Frame {
+        // The output keeps the background's frame rate, or the one it was normalised to
+        let fps = cfr_rate.or(background.and_then(|b| b.nominal_fps())).unwrap_or_else(|| composition_fps(data));
+        plan.snap_to_frames(fps);
+        log::info!("Snapped segment boundaries to {} fps: {:?}", fps, plan.windows);
+    }
+    let detected_hdr = background.and_then(|b| b.hdr_transfer);
+    let hdr = hdr_path(HdrHandling::from_value(data).at(FailureStage::Validation)?, detected_hdr);
+    let hdr_warning = match (hdr, detected_hdr) {
+        (Some(HdrPath::Tonemap(transfer)), Some(_)) => Some(format!(
+            "The background is HDR ({}); it was tonemapped to SDR BT.709", transfer.ffmpeg_name()
+        )),
+        (Some(HdrPath::Tonemap(_)), None) => Some(
+            "hdr_handling is tonemap but the background reports no HDR transfer; it was tonemapped as PQ".to_string()
+        ),
+        (Some(HdrPath::Passthrough(transfer)), _) => Some(format!(
+            "The background is HDR ({}) and was kept as HDR; the SDR board may look dim over it", transfer.ffmpeg_name()
+        )),
+        (None, _) => None,
+    };
+    if let Some(warning) = hdr_warning {
+        warnings::warn("hdr_background", warning);
+    }
+    let deinterlace_report = serde_json::json!({
+        "requested": deinterlace,
+        "interlaced": interlaced,
+        "applied": deinterlace == Some(true),
+    });
+    if let Some(warning) = detection {
+        warnings::warn("background_interlaced", warning);
+    }
+    if let Some(letterbox) = letterbox.as_ref().filter(|l| l.padded()) {
+        let warning = format!(
+            "The background's aspect ratio differs from {}x{}, so it was padded; the video occupies {:?} (x, y, width, height)",
+            letterbox.width, letterbox.height, letterbox.content
+        );
+        warnings::warn("background_letterboxed", warning);
+    }
+    let canvas = match (layout, frame_size) {
+        (LayoutMode::SideBySide, Some(size)) => Some(canvas_plan(side_by_side, requested_size.unwrap_or(size))),
+        (LayoutMode::SideBySide, None) => {
+            let message = "The side_by_side layout needs the background's dimensions".to_string();
+            return Err(ExportFailure::new(FailureStage::Validation, "invalid_export_data", message));
+        }
+        (LayoutMode::Overlay, _) => None,
+    };
+    let zoom = zoom_for_layout(zoom, canvas.as_ref(), letterbox.as_ref());
+
+    // A preview's animation is rendered small and scaled back up, so sizes are given at full scale
+    let overlay_scale = preview.then_some(1.0 / PREVIEW_RENDER_SCALE);
+    let animation_size = if crop.is_some() || treatment.is_some() || check_bounds || nearest_edge {
+        let (width, height) = probe_video_size(app, animation_path).await
+            .map_err(|e| format!("Failed to read the animation dimensions: {}", e))
+            .at(FailureStage::Probe)?;
+        let scale = overlay_scale.unwrap_or(1.0);
+        Some(((width as f64 * scale).round() as u32, (height as f64 * scale).round() as u32))
+    } else {
+        None
+    };
+    if let (Some(crop), Some(size)) = (crop, animation_size) {
+        crop.validate(size).at(FailureStage::Validation)?;
+    }
+    let overlay_size = crop.map(|c| (c.width, c.height)).or(animation_size);
+    if let (LayoutMode::Overlay, Some(frame)) = (layout, frame_size) {
+        let first_move = plan.first_move_number();
+        for (i, &move_position) in plan.positions.iter().enumerate() {
+            check_offset_in_frame(move_position, frame)
+                .map_err(|e| format!("Move {}: {}", first_move + i, e))
+                .at_code(FailureStage::Validation, "board_out_of_bounds")?;
+        }
+        if plan.positions.is_empty() {
+            check_offset_in_frame(position.to_pixels(frame_size).at(FailureStage::Validation)?, frame)
+                .at_code(FailureStage::Validation, "board_out_of_bounds")?;
+        }
+    }
+
+    let mut position = position;
+    let mut segment_positions = plan.positions.clone();
+    let mut segment_placements = Vec::new();
+    if let (true, Some(size), Some(frame)) = (check_bounds, overlay_size, frame_size) {
+        for (i, move_position) in segment_positions.iter_mut().enumerate() {
+            let move_number = plan.first_move_number() + i;
+            let placement = check_placement(out_of_bounds, *move_position, size, frame)
+                .map_err(|e| format!("Move {}: {}", move_number, e))
+                .at_code(FailureStage::Validation, "board_out_of_bounds")?;
+            if let Some(clamped) = placement.clamped_to {
+                let warning = format!(
+                    "The board for move {} extended past the frame and was moved to ({}, {})",
+                    move_number, clamped[0], clamped[1]
+                );
+                warnings::warn("board_clamped", warning);
+                *move_position = clamped;
+            }
+            segment_placements.push(placement);
+        }
+    }
+    if let Some(&[x, y]) = segment_positions.first() {
+        position = OverlayPosition::Pixels { x, y };
+    }
+    let placement = match (check_bounds && segment_positions.is_empty(), overlay_size, frame_size) {
+        (true, Some(size), Some(frame)) => {
+            let placement = check_placement(out_of_bounds, position.to_pixels(frame_size).at(FailureStage::Validation)?, size, frame).at_code(FailureStage::Validation, "board_out_of_bounds")?;
+            if let Some([x, y]) = placement.clamped_to {
+                let warning = format!(
+                    "The board extended past the frame and was moved to ({}, {})", x, y
+                );
+                warnings::warn("board_clamped", warning);
+                position = OverlayPosition::Pixels { x, y };
+            }
+            Some(placement)
+        }
+        _ => None,
+    };
+
+    if background.is_some_and(|b| !b.has_audio) && !layers.iter().any(|l| l.mix_audio) {
+        warnings::warn("silent_output", "The background has no audio, so the output is silent".to_string());
+    }
+    let still_background = background.filter(|b| b.still_image).map(|_| still_duration(&plan));
+    if let Some(duration) = still_background {
+        log::info!("The background is a still image, looping it for {}s", duration);
+    }
+    // Freezes and slowed moves lengthen the video
+    let output_duration = still_background
+        .or(background.and_then(|b| b.duration_secs))
+        .map(|duration| plan.output_duration(duration));
+    if let Some(duration) = output_duration {
+        encoding.check_duration(duration);
+        let output_size = requested_size.or(frame_size);
+        timeouts::fit_ffmpeg(duration, output_size.map(|(width, height)| u64::from(width) * u64::from(height)));
+    }
+
+    let hwaccel_decode = HwaccelDecode::from_value(data).at(FailureStage::Validation)?;
+    let decoded_file = video_path.filter(|_| still_background.is_none() && synthetic_canvas.is_none());
+    if hwaccel_decode != HwaccelDecode::Off && decoded_file.is_none() {
+        warnings::warn("option_ignored", "hwaccel_decode is ignored without a background video to decode".to_string());
+    }
+    let (hw_decode, mut decode_report) = hwdecode::choose(app, hwaccel_decode, decoded_file, detected_hdr.is_some()).await.at_code(FailureStage::Validation, "hwaccel_unsupported")?;
+
+    let options = CompositeOptions {
+        position,
+        frame_size,
+        seek_mode,
+        fps: composition_fps(data),
+        crop,
+        treatment,
+        overlay_size,
+        zoom,
+        background_duration: output_duration,
+        background_has_audio: background.map(|b| b.has_audio).unwrap_or(false),
+        layers: layers.clone(),
+        flash,
+        flash_windows: flash.map(|f| flash_windows(&plan, &move_times, f)).unwrap_or_default(),
+        clock: clock.clone(),
+        font_file: clock.as_ref().and_then(|_| default_font_file()),
+        // A requested resolution is already built into the canvas or the letterbox
+        output_size: preview.then(|| frame_size.map(|(width, height)| {
+            let scale = |side: u32| ((side as f64 * PREVIEW_RENDER_SCALE) as u32).max(2);
+            (scale(width), scale(height))
+        })).flatten(),
+        overlay_scale,
+        canvas,
+        letterbox: letterbox.clone(),
+        persistent_board: side_by_side.persistent,
+        segment_positions: segment_positions.clone(),
+        animation,
+        transition,
+        color_grade: color_grade.clone(),
+        deinterlace: deinterlace == Some(true),
+        transform,
+        source_rotation,
+        cfr_rate,
+        hdr,
+        hw_decode,
+        still_background,
+        synthetic_canvas: synthetic_canvas.clone(),
+        segment: None,
+    };
+    // Segments are cut on the output's frame grid, up to where the background ends
+    let segment_plan = match strategy {
+        CompositeStrategy::SinglePass => None,
+        CompositeStrategy::Segmented => {
+            let fps = cfr_rate.or(background.and_then(|b| b.nominal_fps()));
+            match (segmented_unsupported(&plan, &options), fps, output_duration) {
+                (Some(reason), _, _) => {
+                    warnings::warn("option_ignored", format!("composite_strategy segmented doesn't support {}, so the composite ran in a single pass", reason));
+                    None
+                }
+                (None, Some(fps), Some(duration)) => {
+                    let crossfade = transition.map(|t| round_ms(t.duration_ms as f64 / 1000.0));
+                    let persistent = options.canvas.is_some() && options.persistent_board;
+                    let segments = segments::plan_segments(&plan.group_ranges(crossfade, persistent), duration, fps);
+                    log::info!("Compositing in {} segments at {} fps", segments.segments.len(), fps);
+                    Some(segments)
+                }
+                _ => {
+                    warnings::warn("option_ignored", "composite_strategy segmented needs the background's frame rate and duration, so the composite ran in a single pass".to_string());
+                    None
+                }
+            }
+        }
+    };
+    let xy_offset = position.to_pixels(frame_size).unwrap_or_default().map(f64::round);
+    let xy_offset = letterbox.as_ref().map(|l| l.map(xy_offset)).unwrap_or(xy_offset);
+    let segment_xy: Vec<[f64; 2]> = segment_positions
+        .iter()
+        .map(|p| {
+            let p = p.map(f64::round);
+            letterbox.as_ref().map(|l| l.map(p)).unwrap_or(p)
+        })
+        .collect();
+
+    // Without paths in the payload the sample_exporting folder supplies the background and takes the output
+    let sample_file = |name: &str| {
+        ProjectPaths::resolve(app)
+            .and_then(|paths| path_arg(&paths.sample_exporting().join(name)))
+            .at(FailureStage::Validation)
+    };
+    let background_file = match video_path {
+        Some(path) => path.to_string(),
+        None => sample_file("background.mp4")?,
+    };
+    let composite_file = match composite_path {
+        Some(path) => path.to_string(),
+        None => sample_file("output.mp4")?,
+    };
+    let mut ffmpeg_args = get_multiple_overlay_command(
+        &plan,
+        &background_file,  // videoPath, or the sample background
+        &overlay_file,     // The animation rendered into this job's working directory
+        &composite_file,   // outputPath, or the mezzanine when there are several outputs
+        options.clone(),
+    )
+    .map_err(|e| format!("Failed to generate FFmpeg command: {}", e))
+    .at_code(FailureStage::Composite, "command_build_failed")?;
+    // Parsed before the graph can move into a script file
+    let filter_graph = filtergraph::from_args(&ffmpeg_args);
+    let graph_json = filter_graph.as_ref().and_then(|g| serde_json::to_string(g).ok()).unwrap_or_default();
+    if let Some(workdir) = animation_path.parent() {
+        move_filter_to_script(&mut ffmpeg_args, &workdir.join("filter_complex.txt")).at(FailureStage::Composite)?;
+    }
+
+    let encoder = if via_mezzanine {
+        None
+    } else {
+        video_encoder(app, encoding.settings.video_codec.as_deref(), "h264").await
+    };
+    let video_args = |output: &str| -> Vec<String> {
+        if via_mezzanine {
+            MEZZANINE_ARGS.iter().map(|a| a.to_string()).collect()
+        } else {
+            encoding.encoder_args(encoder.as_deref(), output)
+        }
+    };
+    let output_index = ffmpeg_args.len() - 1;
+    let encoder_args = video_args(&ffmpeg_args[output_index]);
+    ffmpeg_args.splice(output_index..output_index, encoder_args);
+
+    let chapters = move_chapters(data, &plan, composite_path);
+    let chapters_file = match animation_path.parent() {
+        Some(workdir) if !chapters.is_empty() => {
+            let path = workdir.join("chapters.ffmeta");
+            fs::write(&path, chapters::ffmetadata(&chapters))
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+                .at(FailureStage::Composite)?;
+            // Goes after the background, the animation and any extra layers
+            let input_count = ffmpeg_args.iter().filter(|a| *a == "-i").count();
+            let after_inputs = ffmpeg_args.iter().rposition(|a| a == "-i").map(|i| i + 2).unwrap_or(0);
+            ffmpeg_args.splice(after_inputs..after_inputs, ["-i".to_string(), path_arg(&path).at(FailureStage::Composite)?]);
+            let output_index = ffmpeg_args.len() - 1;
+            ffmpeg_args.splice(output_index..output_index, [
+                "-map_metadata".to_string(), input_count.to_string(),
+                "-map_chapters".to_string(), input_count.to_string(),
+            ]);
+            Some(path)
+        }
+        _ => None,
+    };
+    let container_tags = metadata::container_metadata(data);
+    let output_index = ffmpeg_args.len() - 1;
+    ffmpeg_args.splice(output_index..output_index, metadata::metadata_args(&container_tags));
+
+    // -threads is an output option, so it goes right before the output file
+    if let Some(threads) = limits.map(|l| l.ffmpeg_threads).filter(|&t| t > 0) {
+        let output_index = ffmpeg_args.len() - 1;
+        ffmpeg_args.splice(output_index..output_index, ["-threads".to_string(), threads.to_string()]);
+    }
+    log::info!("Generated FFmpeg arguments: {:?}", ffmpeg_args);
+    app.state::<ExportRegistry>().record_ffmpeg_args(export_id, &ffmpeg_args);
+    if let Some(graph) = &filter_graph {
+        app.state::<ExportRegistry>().record_filter_graph(export_id, graph);
+    }
+    
+    let low_priority = limits.map(|l| l.low_priority).unwrap_or(false);
+    progress.start(Stage::Composite);
+    let report = |done: f64, total: f64| progress.report(Stage::Composite, done, total);
+    let ffmpeg_span = timings::span("ffmpeg_composite");
+    let composite_started = Instant::now();
+    let (ffmpeg_result, segment_report) = match (&segment_plan, video_path, composite_path) {
+        (Some(segment_plan), Some(background_file), Some(output_file)) => {
+            let dir = animation_path.with_file_name("segments");
+            let mut segment_args = video_args("segment.mp4");
+            if let Some(threads) = limits.map(|l| l.ffmpeg_threads).filter(|&t| t > 0) {
+                segment_args.extend(["-threads".to_string(), threads.to_string()]);
+            }
+            let jobs = segment_jobs(&plan, background_file, &overlay_file, &options, segment_plan, &dir, &segment_args).at(FailureStage::Composite)?;
+            // Only what concerns the audio and the container; the video is copied
+            let mut output_args: Vec<String> = if via_mezzanine {
+                Vec::new()
+            } else {
+                encoding
+                    .encoder_args(None, output_file)
+                    .chunks(2)
+                    .filter(|pair| ["-c:a", "-b:a", "-movflags"].contains(&pair[0].as_str()))
+                    .flatten()
+                    .cloned()
+                    .collect()
+            };
+            output_args.extend(metadata::metadata_args(&container_tags));
+            let concat_args = segment_concat_args(
+                &plan, background_file, &options, &segments::list_path(&dir), chapters_file.as_deref(), output_args, output_file,
+            ).at(FailureStage::Composite)?;
+            log::info!("Segment join arguments: {:?}", concat_args);
+            match segments::composite(app, segment_plan, &jobs, &concat_args, Path::new(output_file), progress, low_priority).await {
+                Ok((result, segment_report)) => (Ok(result), Some(segment_report)),
+                Err(e) => (Err(e), None),
+            }
+        }
+        _ => (execute_ffmpeg_command(app.clone(), &ffmpeg_args, Some(&report), low_priority).await, None),
+    };
+    drop(ffmpeg_span);
+    decode_report.composite_secs = Some(composite_started.elapsed().as_secs_f64());
+    log::info!("Composited in {:.1}s decoding the background with {}", composite_started.elapsed().as_secs_f64(), decode_report.path);
+    if let Some(path) = chapters_file {
+        let _ = fs::remove_file(path);
+    }
+    let ffmpeg_result = ffmpeg_result
+        .map_err(|e| format!("Failed to execute FFmpeg command: {}\nFilter graph: {}", e, graph_json))
+        .at_code(FailureStage::Composite, "ffmpeg_not_started")?;
+    if !ffmpeg_result.success {
+        let error_msg = format!(
+            "FFmpeg command failed: {}\nReturn code: {:?}\nFilter graph: {}",
+            ffmpeg_result.error,
+            ffmpeg_result.return_code,
+            graph_json,
+        );
+        return Err(ExportFailure::new(FailureStage::Composite, "ffmpeg_failed", error_msg));
+    }
+    log::info!("FFmpeg command executed successfully!");
+    // Size fitting, extra outputs and the checks on the written file
+    let post_passes = timings::span("post_passes");
+
+    let size_target = match (encoding.target_size_mb, output_path) {
+        (Some(target_mb), Some(output_path)) => {
+            let job = TwoPassJob { source: Path::new(&mezzanine), output: output_path, encoding: &encoding, limits };
+            let duration = output_duration.unwrap_or(0.0);
+            let fitted = sizetarget::encode_to_size(app, &job, target_mb, duration, progress).await;
+            let _ = fs::remove_file(&mezzanine);
+            let fitted = fitted.at_code(FailureStage::PostProcess, "size_target_failed")?;
+            log::info!("Fitted {} into {} bytes at {} kb/s", output_path, fitted.size_bytes, fitted.video_bitrate_kbps);
+            Some(fitted)
+        }
+        _ => None,
+    };
+    // The mezzanine is gone by now, so the fitted file is checked instead
+    let final_path = if size_target.is_some() { output_path } else { composite_path };
+
+    let encode_stats = match (via_mezzanine, composite_path) {
+        (false, Some(composite_path)) => measure_encode(app, &ffmpeg_args, Path::new(composite_path)).await,
+        _ => None,
+    };
+
+    let metadata_verified = match (container_tags.get("title"), final_path) {
+        (Some(title), Some(final_path)) => {
+            let written = probe_metadata_tag(app, Path::new(final_path), "title").await;
+            let verified = matches!(&written, Ok(Some(t)) if t == title);
+            if !verified {
+                warnings::warn("metadata_not_written", format!("the title tag is missing from {} ({:?})", final_path, written));
+            }
+            Some(verified)
+        }
+        _ => None,
+    };
+    
+    let output_results = if outputs.is_empty() {
+        Vec::new()
+    } else {
+        let results = transcode_outputs(app, &mezzanine, &outputs, limits, progress).await;
+        let _ = fs::remove_file(&mezzanine);
+        if results.iter().all(|r| r["status"] != "success") {
+            let message = format!("All {} outputs failed: {}", results.len(), serde_json::to_string(&results).unwrap_or_default());
+            return Err(ExportFailure::new(FailureStage::PostProcess, "outputs_failed", message));
+        }
+        results
+    };
+
+    drop(post_passes);
+    let result = CompositeResult {
+        status: "success",
+        export_id,
+        overlay_segments: &plan.overlay_segs,
+        background_segments: &plan.windows,
+        background_behavior,
+        background_timeline: &plan.spans,
+        move_range: &plan.trim,
+        output_duration,
+        chapters: &chapters,
+        metadata: &container_tags,
+        metadata_verified,
+        encode_stats: &encode_stats,
+        outputs: &output_results,
+        xy_offset,
+        overlay_position: position,
+        overlay_crop: &crop,
+        background_treatment: &treatment,
+        layout_mode: layout,
+        canvas: &canvas,
+        letterbox: &letterbox,
+        placement: &placement,
+        segment_positions: &segment_xy,
+        segment_placements: &segment_placements,
+        warnings: warnings::collected(),
+        background_zoom: &zoom,
+        clock_overlay: &clock,
+        move_flash: &flash,
+        overlay_animation: &animation,
+        segment_transition: &transition,
+        color_grade: &color_grade,
+        deinterlace: &deinterlace_report,
+        background_transform: &transform,
+        background_rotation: applied_rotation(transform, source_rotation),
+        cfr_rate,
+        merged_windows: plan.windows.len() - plan.groups.len(),
+        animation_duration: &animation_duration,
+        hdr: &hdr,
+        decode: &decode_report,
+        still_background,
+        synthetic_canvas: &synthetic_canvas,
+        background_clips: &stitched,
+        ffmpeg_log: ffmpeglog::current(),
+        extra_layers: &layers,
+        platform_preset: &encoding,
+        size_target: &size_target,
+        video_path,
+        output_path,
+        ffmpeg_command: render_command_line("ffmpeg", &ffmpeg_args),
+        filter_graph: &filter_graph,
+        ffmpeg_output: &ffmpeg_result.output,
+        ffmpeg_binary: &ffmpeg_result.binary,
+        seek_mode,
+        composite_strategy: if segment_report.is_some() { strategy } else { CompositeStrategy::SinglePass },
+        segments: &segment_report,
+        resource_limits: limits,
+        timeouts: timeouts::report(),
+        message: "Chess animation rendered, overlay data processed, and FFmpeg command executed successfully",
+    };
+    
+    serde_json::to_string(&result).map_err(|e| e.to_string()).at(FailureStage::PostProcess)
+}
+
+#[command]
+pub async fn resume_crashed_export(app: tauri::AppHandle, export_id: String) -> Result<String, ExportFailure> {
+    let result = run_crashed_resume(app.clone(), export_id.clone()).await.map_err(|failure| failure.for_export(&export_id));
+    if let Err(failure) = &result {
+        failure::emit(&app, failure);
+    }
+    result
+}
+
+async fn run_crashed_resume(app: tauri::AppHandle, export_id: String) -> Result<String, ExportFailure> {
+    let not_resumable = |message: String| ExportFailure::new(FailureStage::Validation, "not_resumable", message);
+    let job = find_crashed(&app, &export_id).map_err(not_resumable)?;
+    app.state::<InstanceLock>().ensure(&app).map_err(|e| format!("The export can't resume: {}", e)).at_code(FailureStage::Validation, "instance_locked")?;
+    if !job.can_resume() {
+        return Err(not_resumable(format!("Export {} did not finish rendering and cannot be resumed", export_id)));
+    }
+
+    let workdir = app.state::<WorkDirs>().allocate(&app, &export_id).at(FailureStage::WriteProps)?;
+    let content = fs::read_to_string(workdir.file("export.json"))
+        .map_err(|e| format!("Failed to read the saved export data for {}: {}", export_id, e))
+        .map_err(not_resumable)?;
+    let data: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Saved export data for {} is invalid: {}", export_id, e))
+        .map_err(not_resumable)?;
+    if hash_content(&content) != job.data_hash {
+        return Err(not_resumable(format!("Saved export data for {} was modified since the export started", export_id)));
+    }
+    let max_moves = app.state::<SettingsState>().get().max_moves;
+    validate_export_data(&data, max_moves).map_err(|e| format!("Invalid export data: {}", e)).at(FailureStage::Validation)?;
+    if let Some(action) = app.state::<SettingsState>().get().finish_action.filter(|_| data.get("preview_moves").is_none()) {
+        action.check(&app).map_err(|e| format!("The export can't resume: {}", e)).at_code(FailureStage::Validation, "preflight_failed")?;
+    }
+
+    log::info!("Resuming export {} at the compositing stage", export_id);
+    app.state::<ExportRegistry>().resume_export(&export_id);
+    let _tracking = ExportTracking::new(&app, &export_id);
+    cancel::run_export(&app, &export_id, composite_resumed(&app, &export_id, &data, job)).await
+}
+
+// A resumed export from its turn in the queue on; cancel_export stops it like any other
+async fn composite_resumed(app: &AppHandle, export_id: &str, data: &Value, mut job: JobState) -> Result<String, ExportFailure> {
+    let _slot = app.state::<Scheduler>().admit(app, export_id, Priority::Interactive).await;
+    job.set_stage(JobStage::Compositing);
+    let progress = ProgressReporter::new(app, export_id, move_count(data));
+    let timings = Timings::new();
+    let context = ExportContext {
+        ffmpeg_log: ffmpeglog::log_path(app, export_id).ok(),
+        warnings: WarningCollector::new(app, export_id),
+        timings: timings.clone(),
+        // Nothing is rendered again
+        timeouts: job_timeouts(app, data, 0),
+    };
+    let composite = timings.span(Stage::Composite.name());
+    let result = context.run(composite_to_destinations(app, export_id, data, &job.animation_path, &progress)).await
+        .map_err(|failure| failure.map_message(|e| ffmpeglog::with_log_path(e, context.ffmpeg_log.as_deref())));
+    drop(composite);
+    job.set_stage(if result.is_ok() { JobStage::Completed } else { JobStage::Failed });
+
+    let stage_timings = timings.snapshot();
+    let result = result.map(|r| with_timings(&r, &stage_timings, false));
+    let result = match result {
+        Ok(r) => Ok(context.run(hook::after_export(app, export_id, data, r)).await),
+        other => other,
+    };
+    let status = if result.is_ok() { "completed" } else { "failed" };
+    finish_export(app, &context, export_id, data, status, stage_timings, result).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn three_moves() -> Value {
+        json!({"timestamps": [1.0, 2.5, 4.0], "timePerMove": 0.5, "x_offset": 100, "y_offset": 50})
+    }
+
+    fn failure_of<T: std::fmt::Debug>(result: Result<T, ExportFailure>) -> (FailureStage, &'static str) {
+        let failure = result.unwrap_err();
+        (failure.stage, failure.code)
+    }
+
+    #[test]
+    fn the_result_carries_the_stage_timings() {
+        let timings = BTreeMap::from([("composite".to_string(), 4.25), ("render".to_string(), 12.5), ("total".to_string(), 17.0)]);
+        let result: Value = serde_json::from_str(&with_timings(r#"{"outputPath":"/exports/game.mp4"}"#, &timings, false)).unwrap();
+        assert_eq!(
+            result,
+            json!({"outputPath": "/exports/game.mp4", "timings": {"composite": 4.25, "render": 12.5, "total": 17.0}})
+        );
+        let preview: Value = serde_json::from_str(&with_timings("{}", &timings, true)).unwrap();
+        assert_eq!(preview["preview"], true);
+        // Anything that isn't JSON is passed on untouched
+        assert_eq!(with_timings("done", &timings, false), "done");
+    }
+
+    fn output(spec: Value) -> OutputSpec {
+        serde_json::from_value(spec).unwrap()
+    }
+
+    #[test]
+    fn each_output_is_transcoded_from_the_mezzanine_with_its_own_settings() {
+        assert_eq!(
+            transcode_args("composite.mkv", &output(json!({"path": "/exports/game.mp4"})), None),
+            ["-i", "composite.mkv", "-map", "0:v", "-map", "0:a?", "-c:v", "libx264", "-c:a", "aac", "-y", "/exports/game.mp4"]
+        );
+        let webm = output(json!({"path": "/exports/game.webm", "quality": 32, "resolution": "1280x720"}));
+        assert_eq!(
+            transcode_args("composite.mkv", &webm, Some(4)),
+            [
+                "-i", "composite.mkv", "-map", "0:v", "-map", "0:a?", "-vf", "scale=1280:720", "-c:v", "libvpx-vp9",
+                "-crf", "32", "-b:v", "0", "-c:a", "libopus", "-threads", "4", "-y", "/exports/game.webm",
+            ]
+        );
+        let forced = output(json!({"path": "/exports/game.bin", "format": "mov", "codec": "libx265", "quality": 20}));
+        assert_eq!(
+            transcode_args("composite.mkv", &forced, Some(0)),
+            ["-i", "composite.mkv", "-map", "0:v", "-map", "0:a?", "-c:v", "libx265", "-crf", "20", "-c:a", "aac", "-f", "mov", "-y", "/exports/game.bin"]
+        );
+    }
+
+    #[test]
+    fn the_duration_report_keeps_both_lengths_and_a_mismatch_names_them() {
+        // Within a frame at 10 fps the animation passes, and the report still says how long it was
+        assert_eq!(
+            compare_animation_duration(&three_moves(), Some(1.58)).unwrap(),
+            json!({"expected_secs": 1.5, "actual_secs": 1.58, "tolerance_secs": 0.1})
+        );
+        assert_eq!(
+            compare_animation_duration(&three_moves(), Some(1.7)).unwrap_err().message,
+            "The rendered animation is 1.700s long but 3 moves at timePerMove 0.5 should take 1.500s; \
+             check the fps and durationInFrames of the Remotion composition"
+        );
+        // Ignored, the mismatch is reported rather than failed
+        let mut ignored = three_moves();
+        ignored["ignore_duration_mismatch"] = json!(true);
+        assert_eq!(compare_animation_duration(&ignored, Some(1.7)).unwrap()["actual_secs"], 1.7);
+    }
+
+    #[test]
+    fn an_animation_of_the_wrong_length_fails_verification() {
+        // Three moves at 0.5s and the default 5 frames a move make 1.5s at 10 fps
+        assert_eq!(compare_animation_duration(&three_moves(), Some(1.5)).unwrap()["expected_secs"], 1.5);
+        assert!(compare_animation_duration(&three_moves(), Some(1.55)).is_ok());
+        assert!(compare_animation_duration(&three_moves(), None).is_ok());
+        assert_eq!(failure_of(compare_animation_duration(&three_moves(), Some(2.0))), (FailureStage::Verify, "animation_duration_mismatch"));
+
+        let mut ignored = three_moves();
+        ignored["ignore_duration_mismatch"] = json!(true);
+        assert!(compare_animation_duration(&ignored, Some(2.0)).is_ok());
+
+        // A partial render is checked against its own moves, and a range that can't be is the payload's fault
+        let mut range = three_moves();
+        range["move_range"] = json!([2, 3]);
+        assert!(compare_animation_duration(&range, Some(1.0)).is_ok());
+        assert_eq!(failure_of(compare_animation_duration(&range, Some(1.5))), (FailureStage::Verify, "animation_duration_mismatch"));
+        range["move_range"] = json!([2, 9]);
+        assert_eq!(failure_of(compare_animation_duration(&range, Some(1.0))), (FailureStage::Validation, "invalid_export_data"));
+    }
+}
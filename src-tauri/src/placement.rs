@@ -0,0 +1,408 @@
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Manager};
+
+use crate::export_data::Corner;
+use crate::ffmpeg::{ffmpeg_command, probe_video, resolve_ffmpeg};
+use crate::paths::{for_child_process, path_arg};
+use crate::workdir::WorkDirs;
+
+const DEFAULT_SAMPLES: usize = 5;
+const MAX_SAMPLES: usize = 12;
+// Frames are analysed at this width, which keeps the heatmap cheap and ignores compression noise
+const ANALYSIS_WIDTH: u32 = 160;
+const PREVIEW_WIDTH: u32 = 320;
+// Distance from the picture's edges, as the extra layers and the clock keep
+const MARGIN: f64 = 20.0;
+// A brightness step between neighbouring analysis pixels large enough to count as an edge
+const EDGE_THRESHOLD: u8 = 24;
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(20);
+// Enough frames for cropdetect's default skip of 2 to still report one
+const CROPDETECT_FRAMES: &str = "3";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlacementCandidate {
+    // 1 is the quietest corner
+    pub rank: usize,
+    pub anchor: Corner,
+    // The board's top-left corner, ready for x_offset/y_offset or their _pct forms
+    pub x_offset: f64,
+    pub y_offset: f64,
+    pub x_offset_pct: f64,
+    pub y_offset_pct: f64,
+    // 0 for a flat area, towards 1 for detailed or high-contrast footage
+    pub busyness: f64,
+    // Share of the area's pixels on an edge, and its brightness spread (0 to 1), averaged over the samples
+    pub edge_density: f64,
+    pub contrast: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlacementSuggestion {
+    pub width: u32,
+    pub height: u32,
+    // [x, y, width, height] of the picture inside any letterbox or pillarbox bars
+    pub picture: [u32; 4],
+    pub sample_times: Vec<f64>,
+    // Downscaled JPEGs of the sampled frames, for the UI to draw the candidates on
+    pub sample_frames: Vec<String>,
+    pub candidates: Vec<PlacementCandidate>,
+}
+
+// One sampled frame in ANALYSIS_WIDTH gray pixels, with what cropdetect made of it
+struct Sample {
+    gray: Vec<u8>,
+    crop: Option<[u32; 4]>,
+}
+
+// Evenly spread over the video, each in the middle of its share, so even a clip shorter than the
+// spread gets distinct times inside it
+fn sample_times(duration: Option<f64>, count: usize) -> Vec<f64> {
+    match duration.filter(|d| *d > 0.0) {
+        Some(duration) => (0..count).map(|i| ((i as f64 + 0.5) / count as f64 * duration * 1000.0).round() / 1000.0).collect(),
+        None => vec![0.0],
+    }
+}
+
+// The last "crop=w:h:x:y" cropdetect printed
+fn parse_crop(stderr: &str) -> Option<[u32; 4]> {
+    let value = stderr.rsplit_once("crop=")?.1.split_whitespace().next()?;
+    let fields: Vec<i64> = value.split(':').map(|f| f.parse().ok()).collect::<Option<_>>()?;
+    match fields[..] {
+        [w, h, x, y] if w > 0 && h > 0 && x >= 0 && y >= 0 => Some([x as u32, y as u32, w as u32, h as u32]),
+        _ => None,
+    }
+}
+
+// The area every sample's picture falls in. cropdetect reports a near-empty picture for black or
+// single-colour frames, so those samples don't count; with only those, it's the whole frame.
+fn picture_area(crops: &[[u32; 4]], width: u32, height: u32) -> [u32; 4] {
+    let plausible: Vec<&[u32; 4]> = crops.iter().filter(|c| c[2] * 2 >= width && c[3] * 2 >= height).collect();
+    if plausible.is_empty() {
+        return [0, 0, width, height];
+    }
+    let x1 = plausible.iter().map(|c| c[0]).min().unwrap_or(0);
+    let y1 = plausible.iter().map(|c| c[1]).min().unwrap_or(0);
+    let x2 = plausible.iter().map(|c| c[0] + c[2]).max().unwrap_or(width).min(width);
+    let y2 = plausible.iter().map(|c| c[1] + c[3]).max().unwrap_or(height).min(height);
+    [x1, y1, x2 - x1, y2 - y1]
+}
+
+// The picture the board is kept inside; a board that doesn't fit between the bars goes over them
+fn board_area(crops: &[[u32; 4]], width: u32, height: u32, overlay_size: u32) -> [u32; 4] {
+    let picture = picture_area(crops, width, height);
+    if picture[2] < overlay_size || picture[3] < overlay_size {
+        return [0, 0, width, height];
+    }
+    picture
+}
+
+// ANALYSIS_WIDTH wide with the frame's aspect ratio, at an even height
+fn analysis_size(width: u32, height: u32) -> (u32, u32) {
+    (ANALYSIS_WIDTH, (((ANALYSIS_WIDTH as f64 * height as f64 / width as f64) / 2.0).round() as u32 * 2).max(2))
+}
+
+// Edge density and brightness spread of `rect` ([x, y, width, height] in analysis pixels)
+fn area_stats(gray: &[u8], width: usize, rect: [usize; 4]) -> (f64, f64) {
+    let [x0, y0, w, h] = rect;
+    let height = gray.len() / width.max(1);
+    let (x1, y1) = ((x0 + w).min(width), (y0 + h).min(height));
+    if x1 <= x0 || y1 <= y0 {
+        return (0.0, 0.0);
+    }
+    let (mut edges, mut sum, mut sum_sq, mut count) = (0usize, 0f64, 0f64, 0usize);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let value = gray[y * width + x];
+            let right = if x + 1 < x1 { gray[y * width + x + 1] } else { value };
+            let below = if y + 1 < y1 { gray[(y + 1) * width + x] } else { value };
+            if value.abs_diff(right).max(value.abs_diff(below)) >= EDGE_THRESHOLD {
+                edges += 1;
+            }
+            sum += value as f64;
+            sum_sq += (value as f64).powi(2);
+            count += 1;
+        }
+    }
+    let mean = sum / count as f64;
+    let deviation = (sum_sq / count as f64 - mean * mean).max(0.0).sqrt();
+    (edges as f64 / count as f64, (deviation / 128.0).min(1.0))
+}
+
+// The board's top-left corner for each anchor, inside the picture and clear of its edges
+fn corner_position(anchor: Corner, picture: [u32; 4], size: f64) -> [f64; 2] {
+    let [x, y, w, h] = picture.map(|v| v as f64);
+    let left = (x + MARGIN).min(x + w - size).max(0.0);
+    let right = (x + w - size - MARGIN).max(left);
+    let top = (y + MARGIN).min(y + h - size).max(0.0);
+    let bottom = (y + h - size - MARGIN).max(top);
+    match anchor {
+        Corner::TopLeft => [left, top],
+        Corner::TopRight => [right, top],
+        Corner::BottomLeft => [left, bottom],
+        Corner::BottomRight => [right, bottom],
+    }
+}
+
+// Ranks the four corners by how busy the background is under the board, quietest first; ties
+// keep Corner's default order
+fn rank_corners(samples: &[Vec<u8>], analysis: (u32, u32), frame: (u32, u32), picture: [u32; 4], size: f64) -> Vec<PlacementCandidate> {
+    let scale = analysis.0 as f64 / frame.0 as f64;
+    let mut candidates: Vec<PlacementCandidate> = [Corner::TopRight, Corner::TopLeft, Corner::BottomRight, Corner::BottomLeft]
+        .into_iter()
+        .map(|anchor| {
+            let [x, y] = corner_position(anchor, picture, size);
+            let rect = [x, y, size, size].map(|v| (v * scale).round() as usize);
+            let stats: Vec<(f64, f64)> = samples.iter().map(|gray| area_stats(gray, analysis.0 as usize, rect)).collect();
+            let count = stats.len().max(1) as f64;
+            let edge_density = stats.iter().map(|s| s.0).sum::<f64>() / count;
+            let contrast = stats.iter().map(|s| s.1).sum::<f64>() / count;
+            let round = |v: f64| (v * 1000.0).round() / 1000.0;
+            PlacementCandidate {
+                rank: 0,
+                anchor,
+                x_offset: x.round(),
+                y_offset: y.round(),
+                x_offset_pct: round(x / frame.0 as f64),
+                y_offset_pct: round(y / frame.1 as f64),
+                busyness: round((edge_density + contrast) / 2.0),
+                edge_density: round(edge_density),
+                contrast: round(contrast),
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.busyness.total_cmp(&b.busyness));
+    for (i, candidate) in candidates.iter_mut().enumerate() {
+        candidate.rank = i + 1;
+    }
+    candidates
+}
+
+// One decode per sample: a preview JPEG, the gray analysis frame on stdout and cropdetect on stderr
+fn sample_args(background: &str, time: f64, analysis: (u32, u32), preview: &Path) -> Result<Vec<String>, String> {
+    Ok(vec![
+        "-hide_banner".to_string(), "-ss".to_string(), time.to_string(), "-i".to_string(), for_child_process(background),
+        "-map".to_string(), "0:v:0".to_string(), "-frames:v".to_string(), "1".to_string(),
+        "-vf".to_string(), format!("scale={}:-2", PREVIEW_WIDTH), "-y".to_string(), for_child_process(&path_arg(preview)?),
+        "-map".to_string(), "0:v:0".to_string(), "-frames:v".to_string(), CROPDETECT_FRAMES.to_string(),
+        "-vf".to_string(), "cropdetect=limit=24:round=2:reset=0".to_string(), "-f".to_string(), "null".to_string(), "-".to_string(),
+        "-map".to_string(), "0:v:0".to_string(), "-frames:v".to_string(), "1".to_string(),
+        "-vf".to_string(), format!("scale={}:{},format=gray", analysis.0, analysis.1),
+        "-f".to_string(), "rawvideo".to_string(), "pipe:1".to_string(),
+    ])
+}
+
+// Seeking past a short video's last frame decodes nothing, which is None
+fn read_sample(stdout: &[u8], stderr: &str, analysis: (u32, u32)) -> Option<Sample> {
+    let size = (analysis.0 * analysis.1) as usize;
+    (stdout.len() >= size).then(|| Sample { gray: stdout[..size].to_vec(), crop: parse_crop(stderr) })
+}
+
+async fn sample_frame(app: &AppHandle, background: &str, time: f64, analysis: (u32, u32), preview: &Path) -> Result<Option<Sample>, String> {
+    let resolved = resolve_ffmpeg(app).await.map_err(|e| e.to_string())?;
+    let args = sample_args(background, time, analysis, preview)?;
+    let command = ffmpeg_command(app, &resolved)?.args(&args);
+    let output = tokio::time::timeout(SAMPLE_TIMEOUT, command.output())
+        .await
+        .map_err(|_| format!("Sampling the frame at {}s timed out after {} seconds", time, SAMPLE_TIMEOUT.as_secs()))?
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        let reason = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("ffmpeg failed").trim();
+        return Err(format!("Failed to sample the frame at {}s: {}", time, reason));
+    }
+    Ok(read_sample(&output.stdout, &stderr, analysis))
+}
+
+// Samples frames across the background and scores each corner by how busy the footage under a
+// board of `overlay_size` pixels would be. Only a suggestion: nothing is applied to any export.
+#[command]
+pub async fn suggest_overlay_position(
+    app: AppHandle,
+    background_path: String,
+    overlay_size: u32,
+    sample_count: Option<usize>,
+) -> Result<PlacementSuggestion, String> {
+    let probe = probe_video(&app, Path::new(&background_path)).await?;
+    // ffmpeg rotates the frames it decodes, and the offsets are in those frames' pixels
+    let (width, height) = if probe.rotation % 180 == 90 { (probe.height, probe.width) } else { (probe.width, probe.height) };
+    if overlay_size == 0 || overlay_size > width.min(height) {
+        return Err(format!("overlay_size must be between 1 and {} for a {}x{} background", width.min(height), width, height));
+    }
+    let analysis = analysis_size(width, height);
+    let count = sample_count.unwrap_or(DEFAULT_SAMPLES).clamp(1, MAX_SAMPLES);
+    let times = if probe.still_image { vec![0.0] } else { sample_times(probe.duration_secs, count) };
+
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let workdir = app.state::<WorkDirs>().allocate(&app, &format!("placement-{}", millis))?;
+    log::info!("Sampling {} frames of {} for overlay placement", times.len(), background_path);
+    let mut sampled_times = Vec::new();
+    let mut frames = Vec::new();
+    let mut samples = Vec::new();
+    for (i, &time) in times.iter().enumerate() {
+        let preview = workdir.file(&format!("sample-{:02}.jpg", i + 1));
+        if let Some(sample) = sample_frame(&app, &background_path, time, analysis, &preview).await? {
+            sampled_times.push(time);
+            frames.push(path_arg(&preview)?);
+            samples.push(sample);
+        }
+    }
+    // Nothing decoded at the spread's times, which a clip of a frame or two can do
+    if samples.is_empty() {
+        let preview = workdir.file("sample-01.jpg");
+        let sample = sample_frame(&app, &background_path, 0.0, analysis, &preview).await?
+            .ok_or_else(|| format!("No frame of {} could be decoded", background_path))?;
+        sampled_times.push(0.0);
+        frames.push(path_arg(&preview)?);
+        samples.push(sample);
+    }
+
+    let crops: Vec<[u32; 4]> = samples.iter().map(|s| s.crop.unwrap_or([0, 0, width, height])).collect();
+    let picture = board_area(&crops, width, height, overlay_size);
+    let grays: Vec<Vec<u8>> = samples.into_iter().map(|s| s.gray).collect();
+    let candidates = rank_corners(&grays, analysis, (width, height), picture, overlay_size as f64);
+    Ok(PlacementSuggestion {
+        width,
+        height,
+        picture,
+        sample_times: sampled_times,
+        sample_frames: frames,
+        candidates,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    const LETTERBOXED: &str = "[Parsed_cropdetect_0 @ 0x1] x1:0 x2:1919 y1:140 y2:939 w:1920 h:800 x:0 y:140 pts:0 t:0.000 crop=1920:800:0:140\n\
+        [Parsed_cropdetect_0 @ 0x1] x1:0 x2:1919 y1:138 y2:941 w:1920 h:804 x:0 y:138 pts:1 t:0.033 crop=1920:804:0:138\n";
+
+    #[test]
+    fn samples_spread_over_a_short_clip_stay_inside_it() {
+        assert_eq!(sample_times(Some(10.0), 5), [1.0, 3.0, 5.0, 7.0, 9.0]);
+        let short = sample_times(Some(0.2), MAX_SAMPLES);
+        assert_eq!(short.len(), MAX_SAMPLES);
+        assert!(short.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", short);
+        assert!(short.iter().all(|&t| t > 0.0 && t < 0.2), "{:?}", short);
+        // Without a duration, only the first frame is known to exist
+        assert_eq!(sample_times(None, 5), [0.0]);
+        assert_eq!(sample_times(Some(0.0), 5), [0.0]);
+    }
+
+    #[test]
+    fn the_last_crop_cropdetect_printed_is_used() {
+        assert_eq!(parse_crop(LETTERBOXED), Some([0, 138, 1920, 804]));
+        // What cropdetect prints for an all-black frame
+        assert_eq!(parse_crop("crop=-1904:-1072:1912:1080"), None);
+        assert_eq!(parse_crop("frame=    1 fps=0.0"), None);
+    }
+
+    #[test]
+    fn bars_are_left_out_unless_the_board_does_not_fit_between_them() {
+        let crops = [[0, 140, 1920, 800], [0, 138, 1920, 804]];
+        assert_eq!(board_area(&crops, 1920, 1080, 600), [0, 138, 1920, 804]);
+        assert_eq!(board_area(&crops, 1920, 1080, 900), [0, 0, 1920, 1080]);
+    }
+
+    #[test]
+    fn single_colour_samples_count_as_the_whole_frame() {
+        // cropdetect finds next to no picture in a flat frame
+        assert_eq!(picture_area(&[[1912, 1072, 8, 8]], 1920, 1080), [0, 0, 1920, 1080]);
+        assert_eq!(picture_area(&[[1912, 1072, 8, 8], [0, 140, 1920, 800]], 1920, 1080), [0, 140, 1920, 800]);
+        assert_eq!(picture_area(&[], 1920, 1080), [0, 0, 1920, 1080]);
+    }
+
+    #[test]
+    fn corners_keep_the_margin_until_the_board_fills_the_picture() {
+        let picture = [0, 0, 1920, 1080];
+        assert_eq!(corner_position(Corner::TopLeft, picture, 400.0), [20.0, 20.0]);
+        assert_eq!(corner_position(Corner::TopRight, picture, 400.0), [1500.0, 20.0]);
+        assert_eq!(corner_position(Corner::BottomLeft, picture, 400.0), [20.0, 660.0]);
+        assert_eq!(corner_position(Corner::BottomRight, picture, 400.0), [1500.0, 660.0]);
+        // Inside the bars, and on a picture too small for both margins every corner is the same
+        assert_eq!(corner_position(Corner::BottomRight, [0, 140, 1920, 800], 400.0), [1500.0, 520.0]);
+        for anchor in [Corner::TopLeft, Corner::TopRight, Corner::BottomLeft, Corner::BottomRight] {
+            assert_eq!(corner_position(anchor, [0, 0, 410, 410], 400.0), [10.0, 10.0]);
+        }
+    }
+
+    fn ranking(samples: &[Vec<u8>]) -> Vec<(Corner, f64)> {
+        rank_corners(samples, (320, 180), (320, 180), [0, 0, 320, 180], 60.0)
+            .into_iter()
+            .map(|c| (c.anchor, c.busyness))
+            .collect()
+    }
+
+    #[test]
+    fn the_busy_corner_ranks_last() {
+        // A flat grey frame with a checkerboard under the top-left board
+        let mut gray = vec![128u8; 320 * 180];
+        for y in 20..80 {
+            for x in 20..80 {
+                gray[y * 320 + x] = if (x + y) % 2 == 0 { 0 } else { 255 };
+            }
+        }
+        let ranked = ranking(&[gray]);
+        assert_eq!(ranked.iter().map(|r| r.0).collect::<Vec<_>>(), [Corner::TopRight, Corner::BottomRight, Corner::BottomLeft, Corner::TopLeft]);
+        assert!(ranked[3].1 > 0.5, "{:?}", ranked);
+        assert!(ranked[..3].iter().all(|r| r.1 == 0.0));
+    }
+
+    #[test]
+    fn a_single_colour_video_suggests_the_default_corner() {
+        let flat = vec![vec![90u8; 320 * 180]; 3];
+        let ranked = ranking(&flat);
+        assert_eq!(ranked[0], (Corner::default(), 0.0));
+        assert!(ranked.iter().all(|r| r.1 == 0.0));
+    }
+
+    #[test]
+    fn a_sample_past_the_end_is_none() {
+        let analysis = analysis_size(1920, 1080);
+        assert_eq!(analysis, (160, 90));
+        assert!(read_sample(&[], LETTERBOXED, analysis).is_none());
+        let sample = read_sample(&vec![7; 160 * 90 + 10], LETTERBOXED, analysis).unwrap();
+        assert_eq!((sample.gray.len(), sample.crop), (160 * 90, Some([0, 138, 1920, 804])));
+    }
+
+    // The real decode, on a clip shorter than the spread and a single-colour one. Needs ffmpeg on
+    // the PATH: cargo test sampling_short_and_single_colour_videos -- --ignored
+    #[test]
+    #[ignore]
+    fn sampling_short_and_single_colour_videos() {
+        let dir = env::temp_dir().join(format!("boardcast-placement-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let sample = |video: &str, time: f64| {
+            let analysis = analysis_size(320, 180);
+            let args = sample_args(&file(video), time, analysis, &dir.join("preview.jpg")).unwrap();
+            let output = std::process::Command::new("ffmpeg").args(&args).output().unwrap();
+            assert!(output.status.success(), "ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr));
+            read_sample(&output.stdout, &String::from_utf8_lossy(&output.stderr), analysis)
+        };
+        for (name, source) in [("short.mp4", "testsrc=s=320x180:r=30:d=0.4"), ("flat.mp4", "color=c=0x336699:s=320x180:r=30:d=2")] {
+            let status = std::process::Command::new("ffmpeg")
+                .args(["-v", "error", "-y", "-f", "lavfi", "-i", source, "-pix_fmt", "yuv420p", &file(name)])
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+
+        let short: Vec<Sample> = sample_times(Some(0.4), DEFAULT_SAMPLES).into_iter().map(|t| sample("short.mp4", t).unwrap()).collect();
+        assert_eq!(short.len(), DEFAULT_SAMPLES);
+        assert!(sample("short.mp4", 5.0).is_none());
+
+        let flat: Vec<Sample> = sample_times(Some(2.0), DEFAULT_SAMPLES).into_iter().map(|t| sample("flat.mp4", t).unwrap()).collect();
+        let crops: Vec<[u32; 4]> = flat.iter().map(|s| s.crop.unwrap_or([0, 0, 320, 180])).collect();
+        let picture = board_area(&crops, 320, 180, 60);
+        assert_eq!(picture, [0, 0, 320, 180]);
+        let grays: Vec<Vec<u8>> = flat.into_iter().map(|s| s.gray).collect();
+        let ranked = rank_corners(&grays, analysis_size(320, 180), (320, 180), picture, 60.0);
+        assert_eq!(ranked[0].anchor, Corner::default());
+        assert!(ranked.iter().all(|c| c.busyness == 0.0), "{:?}", ranked);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+use serde::Serialize;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle};
+
+use crate::destination::{classify, native_path, DestinationKind};
+use crate::ffmpeg::{probe_audio, probe_video};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathRole {
+    Background,
+    Overlay,
+    Output,
+}
+
+impl PathRole {
+    pub fn name(self) -> &'static str {
+        match self {
+            PathRole::Background => "background",
+            PathRole::Overlay => "overlay",
+            PathRole::Output => "output",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathCheck {
+    pub role: PathRole,
+    pub path: String,
+    pub ok: bool,
+    pub problems: Vec<String>,
+    // Fine, but worth telling the user, e.g. an audio-only background
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathReport {
+    pub ok: bool,
+    pub checks: Vec<PathCheck>,
+}
+
+impl PathReport {
+    // One line naming every failing path, for errors
+    pub fn failure(&self) -> Option<String> {
+        let failed: Vec<String> = self
+            .checks
+            .iter()
+            .filter(|c| !c.ok)
+            .map(|c| format!("{} {}: {}", c.role.name(), c.path, c.problems.join(", ")))
+            .collect();
+        (!failed.is_empty()).then(|| failed.join("; "))
+    }
+}
+
+fn check_readable(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err("does not exist".to_string());
+    }
+    if !path.is_file() {
+        return Err("is not a file".to_string());
+    }
+    File::open(path).map(|_| ()).map_err(|e| format!("can't be read: {}", e))
+}
+
+// Ok(Some(note)) when the file is usable without a video stream
+async fn check_media(app: &AppHandle, path: &Path, allow_audio_only: bool) -> Result<Option<String>, String> {
+    let error = match probe_video(app, path).await {
+        Ok(_) => return Ok(None),
+        Err(e) => e,
+    };
+    if allow_audio_only {
+        if let Ok(audio) = probe_audio(app, path).await {
+            if audio.stream.is_some() {
+                return Ok(Some("has no video stream, so the board will go over a generated canvas".to_string()));
+            }
+        }
+    }
+    Err(format!("isn't a playable video: {}", error))
+}
+
+// The output usually doesn't exist yet, so it is compared through its resolved parent
+fn resolved(path: &Path) -> Option<PathBuf> {
+    if let Ok(path) = fs::canonicalize(path) {
+        return Some(path);
+    }
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    Some(fs::canonicalize(parent).ok()?.join(path.file_name()?))
+}
+
+fn check_output(output: &Path, inputs: &[&Path]) -> Vec<String> {
+    let mut problems = Vec::new();
+    if output.is_dir() {
+        problems.push("is a directory, not a file".to_string());
+        return problems;
+    }
+    let parent = output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    if !parent.is_dir() {
+        problems.push(format!("its folder {} does not exist", parent.display()));
+        return problems;
+    }
+
+    // Permissions alone don't tell (read-only shares, ACLs), so a file is actually written
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let probe = parent.join(format!(".boardcast-write-test-{}-{}", std::process::id(), nanos));
+    match fs::write(&probe, b"") {
+        Ok(_) => {
+            if let Err(e) = fs::remove_file(&probe) {
+                log::warn!("Failed to remove {}: {}", probe.display(), e);
+            }
+        }
+        Err(e) => problems.push(format!("its folder {} isn't writable: {}", parent.display(), e)),
+    }
+
+    let target = resolved(output);
+    if inputs.iter().any(|input| target.is_some() && resolved(input) == target) {
+        problems.push("is the same file as an input".to_string());
+    }
+    problems
+}
+
+fn destination_note(output: &str) -> Option<String> {
+    match classify(output) {
+        DestinationKind::Local => None,
+        DestinationKind::Network => Some("is on a network share, so the video is encoded locally and copied there".to_string()),
+        DestinationKind::Wsl => Some("is on the other side of WSL, so the video is encoded locally and copied there".to_string()),
+    }
+}
+
+async fn check_input(app: &AppHandle, role: PathRole, path: &str) -> PathCheck {
+    let (problems, note) = match check_readable(Path::new(path)) {
+        Err(problem) => (vec![problem], None),
+        Ok(()) => match check_media(app, Path::new(path), role == PathRole::Background).await {
+            Ok(note) => (Vec::new(), note),
+            Err(problem) => (vec![problem], None),
+        },
+    };
+    PathCheck { role, path: path.to_string(), ok: problems.is_empty(), problems, note }
+}
+
+// Shared by export and validate_export_paths so the two agree on what can start
+pub async fn check_paths(app: &AppHandle, background: Option<&str>, overlay: Option<&str>, output: Option<&str>) -> PathReport {
+    let mut checks = Vec::new();
+    if let Some(background) = background {
+        checks.push(check_input(app, PathRole::Background, background).await);
+    }
+    if let Some(overlay) = overlay {
+        checks.push(check_input(app, PathRole::Overlay, overlay).await);
+    }
+    if let Some(output) = output {
+        let inputs: Vec<&Path> = background.into_iter().chain(overlay).map(Path::new).collect();
+        let (problems, note) = match native_path(output) {
+            Err(problem) => (vec![problem], None),
+            Ok(native) => (check_output(Path::new(&native), &inputs), destination_note(&native)),
+        };
+        checks.push(PathCheck { role: PathRole::Output, path: output.to_string(), ok: problems.is_empty(), problems, note });
+    }
+    PathReport { ok: checks.iter().all(|c| c.ok), checks }
+}
+
+#[command]
+pub async fn validate_export_paths(
+    app: AppHandle,
+    background: String,
+    overlay: Option<String>,
+    output: String,
+) -> Result<PathReport, String> {
+    Ok(check_paths(&app, Some(&background), overlay.as_deref(), Some(&output)).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory per test, removed when it goes out of scope
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Scratch {
+            let dir = std::env::temp_dir().join(format!("boardcast-preflight-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Scratch(dir)
+        }
+
+        fn fixture(&self, name: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::write(&path, b"not really a video").unwrap();
+            path
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn inputs_must_be_existing_files() {
+        let scratch = Scratch::new("inputs");
+        assert_eq!(check_readable(&scratch.fixture("background.mp4")), Ok(()));
+        assert_eq!(check_readable(&scratch.0.join("missing.mp4")), Err("does not exist".to_string()));
+        assert_eq!(check_readable(&scratch.0), Err("is not a file".to_string()));
+    }
+
+    #[test]
+    fn a_writable_folder_passes_and_is_left_clean() {
+        let scratch = Scratch::new("writable");
+        let background = scratch.fixture("background.mp4");
+        assert_eq!(check_output(&scratch.0.join("output.mp4"), &[&background]), Vec::<String>::new());
+        // Only the fixture is left; the write test was removed again
+        assert_eq!(fs::read_dir(&scratch.0).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn the_output_needs_an_existing_folder_and_a_file_name() {
+        let scratch = Scratch::new("folders");
+        let output = scratch.0.join("missing").join("output.mp4");
+        assert_eq!(check_output(&output, &[]), [format!("its folder {} does not exist", output.parent().unwrap().display())]);
+        assert_eq!(check_output(&scratch.0, &[]), ["is a directory, not a file"]);
+    }
+
+    #[test]
+    fn the_output_cannot_overwrite_an_input() {
+        let scratch = Scratch::new("same-file");
+        let background = scratch.fixture("background.mp4");
+        assert_eq!(check_output(&background, &[&background]), ["is the same file as an input"]);
+        // Spelled differently, it is still the same file
+        let roundabout = scratch.0.join("sub").join("..").join("background.mp4");
+        fs::create_dir(scratch.0.join("sub")).unwrap();
+        assert_eq!(check_output(&roundabout, &[&background]), ["is the same file as an input"]);
+        assert!(check_output(&scratch.0.join("other.mp4"), &[&background]).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_read_only_folder_is_reported() {
+        use std::os::unix::fs::PermissionsExt;
+        let scratch = Scratch::new("read-only");
+        let folder = scratch.0.join("locked");
+        fs::create_dir(&folder).unwrap();
+        fs::set_permissions(&folder, fs::Permissions::from_mode(0o555)).unwrap();
+        let problems = check_output(&folder.join("output.mp4"), &[]);
+        // Root can write anywhere, so there is nothing to report there
+        let locked = fs::write(folder.join("probe"), b"").is_err();
+        fs::set_permissions(&folder, fs::Permissions::from_mode(0o755)).unwrap();
+        if locked {
+            assert_eq!(problems.len(), 1);
+            assert!(problems[0].starts_with(&format!("its folder {} isn't writable: ", folder.display())), "{:?}", problems);
+        }
+    }
+
+    #[test]
+    fn the_failure_names_every_failing_path() {
+        let check = |role, path: &str, problems: &[&str]| PathCheck {
+            role,
+            path: path.to_string(),
+            ok: problems.is_empty(),
+            problems: problems.iter().map(|p| p.to_string()).collect(),
+            note: None,
+        };
+        let report = PathReport {
+            ok: false,
+            checks: vec![
+                check(PathRole::Background, "bg.mp4", &["does not exist"]),
+                check(PathRole::Overlay, "overlay.mp4", &[]),
+                check(PathRole::Output, "bg.mp4", &["is the same file as an input", "its folder . isn't writable: denied"]),
+            ],
+        };
+        assert_eq!(
+            report.failure().unwrap(),
+            "background bg.mp4: does not exist; output bg.mp4: is the same file as an input, its folder . isn't writable: denied"
+        );
+        assert_eq!(PathReport { ok: true, checks: vec![check(PathRole::Output, "out.mp4", &[])] }.failure(), None);
+    }
+}
@@ -0,0 +1,263 @@
+use serde::Serialize;
+use std::path::Path;
+
+use crate::export_data::EncodeSettings;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformPreset {
+    pub name: &'static str,
+    pub settings: EncodeSettings,
+    // Longest video the platform accepts
+    pub max_duration_secs: Option<f64>,
+    // Largest upload the platform accepts
+    pub max_size_mb: Option<f64>,
+    // Size the export is fitted to with a two-pass encode
+    pub target_size_mb: Option<f64>,
+}
+
+pub const PRESET_NAMES: &[&str] = &["youtube", "twitter", "discord"];
+
+fn settings(video_codec: &str, crf: u32, preset: &str, audio_kbps: u32, resolution: &str) -> EncodeSettings {
+    EncodeSettings {
+        video_codec: Some(video_codec.to_string()),
+        crf: Some(crf),
+        preset: Some(preset.to_string()),
+        audio_codec: Some("aac".to_string()),
+        audio_bitrate_kbps: Some(audio_kbps),
+        max_bitrate_kbps: None,
+        resolution: Some(resolution.to_string()),
+        faststart: Some(true),
+    }
+}
+
+pub fn platform_preset(name: &str) -> Option<PlatformPreset> {
+    match name.trim().to_lowercase().as_str() {
+        // YouTube re-encodes everything, so a high-quality upload is worth the extra size
+        "youtube" => Some(PlatformPreset {
+            name: "youtube",
+            settings: settings("libx264", 18, "slow", 192, "1920x1080"),
+            max_duration_secs: None,
+            max_size_mb: None,
+            target_size_mb: None,
+        }),
+        // 2:20 and 512 MB; the bitrate cap keeps the longest allowed video under the size limit
+        "twitter" | "x" => Some(PlatformPreset {
+            name: "twitter",
+            settings: EncodeSettings {
+                max_bitrate_kbps: Some(25_000),
+                ..settings("libx264", 21, "medium", 128, "1280x720")
+            },
+            max_duration_secs: Some(140.0),
+            max_size_mb: Some(512.0),
+            target_size_mb: None,
+        }),
+        // Uploads are capped by size rather than length, so the bitrate is fitted to the limit
+        "discord" => Some(PlatformPreset {
+            name: "discord",
+            settings: settings("libx264", 26, "medium", 96, "1280x720"),
+            max_duration_secs: None,
+            max_size_mb: Some(25.0),
+            target_size_mb: Some(25.0),
+        }),
+        _ => None,
+    }
+}
+
+// Fast, small encode for previews; the quality only has to show offsets and pacing
+pub fn preview_encoding() -> ResolvedEncoding {
+    ResolvedEncoding {
+        preset: Some("preview".to_string()),
+        // Scaled to half the background by the composite itself
+        settings: EncodeSettings {
+            resolution: None,
+            ..settings("libx264", 28, "ultrafast", 96, "")
+        },
+        ..Default::default()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResolvedEncoding {
+    pub preset: Option<String>,
+    pub settings: EncodeSettings,
+    // Preset fields replaced by explicit options
+    pub overrides: Vec<String>,
+    pub max_duration_secs: Option<f64>,
+    pub max_size_mb: Option<f64>,
+    pub target_size_mb: Option<f64>,
+    pub warnings: Vec<String>,
+}
+
+fn merge<T: Clone>(field: &str, value: &mut Option<T>, explicit: &Option<T>, overrides: &mut Vec<String>) {
+    if let Some(explicit) = explicit {
+        if value.is_some() {
+            overrides.push(field.to_string());
+        }
+        *value = Some(explicit.clone());
+    }
+}
+
+// Starts from the named preset, then applies every explicit option on top
+pub fn resolve(
+    preset_name: Option<&str>,
+    explicit: &EncodeSettings,
+    target_size_mb: Option<f64>,
+) -> Result<ResolvedEncoding, String> {
+    let preset = match preset_name {
+        Some(name) => Some(platform_preset(name).ok_or_else(|| {
+            format!("Unknown platform_preset '{}', expected one of {}", name, PRESET_NAMES.join(", "))
+        })?),
+        None => None,
+    };
+    let mut resolved = ResolvedEncoding {
+        preset: preset.as_ref().map(|p| p.name.to_string()),
+        settings: preset.as_ref().map(|p| p.settings.clone()).unwrap_or_default(),
+        max_duration_secs: preset.as_ref().and_then(|p| p.max_duration_secs),
+        max_size_mb: preset.as_ref().and_then(|p| p.max_size_mb),
+        target_size_mb: preset.as_ref().and_then(|p| p.target_size_mb),
+        ..Default::default()
+    };
+
+    let settings = &mut resolved.settings;
+    let overrides = &mut resolved.overrides;
+    merge("video_codec", &mut settings.video_codec, &explicit.video_codec, overrides);
+    merge("crf", &mut settings.crf, &explicit.crf, overrides);
+    merge("preset", &mut settings.preset, &explicit.preset, overrides);
+    merge("audio_codec", &mut settings.audio_codec, &explicit.audio_codec, overrides);
+    merge("audio_bitrate_kbps", &mut settings.audio_bitrate_kbps, &explicit.audio_bitrate_kbps, overrides);
+    merge("max_bitrate_kbps", &mut settings.max_bitrate_kbps, &explicit.max_bitrate_kbps, overrides);
+    merge("resolution", &mut settings.resolution, &explicit.resolution, overrides);
+    merge("faststart", &mut settings.faststart, &explicit.faststart, overrides);
+    merge("target_size_mb", &mut resolved.target_size_mb, &target_size_mb, overrides);
+    Ok(resolved)
+}
+
+impl ResolvedEncoding {
+    // Records a warning when the video runs past the platform's limit
+    pub fn check_duration(&mut self, duration_secs: f64) {
+        if let (Some(preset), Some(limit)) = (&self.preset, self.max_duration_secs) {
+            if duration_secs > limit {
+                let warning = format!(
+                    "The video is {:.1}s long, more than the {}s {} allows",
+                    duration_secs, limit, preset
+                );
+                log::warn!("{}", warning);
+                self.warnings.push(warning);
+            }
+        }
+    }
+
+    // Output options for the resolved settings; `video_codec` is the encoder after "auto" was resolved
+    pub fn encoder_args(&self, video_codec: Option<&str>, output_path: &str) -> Vec<String> {
+        let settings = &self.settings;
+        let mut args = Vec::new();
+        if let Some(codec) = video_codec {
+            args.extend(["-c:v".to_string(), codec.to_string()]);
+        }
+        // Hardware encoders have their own rate control and preset names
+        let software = video_codec.map(|c| c.starts_with("lib")).unwrap_or(true);
+        if software {
+            if let Some(preset) = settings.preset.as_ref().filter(|_| video_codec.is_some_and(|c| c.starts_with("libx26"))) {
+                args.extend(["-preset".to_string(), preset.clone()]);
+            }
+            if let Some(crf) = settings.crf {
+                args.extend(["-crf".to_string(), crf.to_string()]);
+                // libvpx treats -crf as a cap on top of its default bitrate unless the bitrate is zeroed
+                if video_codec.is_some_and(|c| c.contains("vpx")) && settings.max_bitrate_kbps.is_none() {
+                    args.extend(["-b:v".to_string(), "0".to_string()]);
+                }
+            }
+        }
+        if let Some(max_bitrate) = settings.max_bitrate_kbps {
+            args.extend([
+                "-maxrate".to_string(), format!("{}k", max_bitrate),
+                "-bufsize".to_string(), format!("{}k", max_bitrate * 2),
+            ]);
+        }
+        if let Some(audio_codec) = &settings.audio_codec {
+            args.extend(["-c:a".to_string(), audio_codec.clone()]);
+        }
+        if let Some(bitrate) = settings.audio_bitrate_kbps {
+            args.extend(["-b:a".to_string(), format!("{}k", bitrate)]);
+        }
+        let extension = Path::new(output_path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if settings.faststart == Some(true) && ["mp4", "mov", "m4v"].contains(&extension.as_str()) {
+            args.extend(["-movflags".to_string(), "+faststart".to_string()]);
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(encoding: &ResolvedEncoding, codec: Option<&str>, output: &str) -> String {
+        encoding.encoder_args(codec, output).join(" ")
+    }
+
+    #[test]
+    fn each_preset_encodes_for_its_platform() {
+        let youtube = resolve(Some("YouTube"), &EncodeSettings::default(), None).unwrap();
+        assert_eq!(
+            args(&youtube, Some("libx264"), "game.mp4"),
+            "-c:v libx264 -preset slow -crf 18 -c:a aac -b:a 192k -movflags +faststart"
+        );
+        assert_eq!(youtube.settings.resolution.as_deref(), Some("1920x1080"));
+
+        let twitter = resolve(Some("x"), &EncodeSettings::default(), None).unwrap();
+        assert_eq!(twitter.preset.as_deref(), Some("twitter"));
+        assert_eq!(
+            args(&twitter, Some("libx264"), "game.mp4"),
+            "-c:v libx264 -preset medium -crf 21 -maxrate 25000k -bufsize 50000k -c:a aac -b:a 128k -movflags +faststart"
+        );
+        assert_eq!((twitter.max_duration_secs, twitter.max_size_mb, twitter.target_size_mb), (Some(140.0), Some(512.0), None));
+
+        let discord = resolve(Some("discord"), &EncodeSettings::default(), None).unwrap();
+        assert_eq!(discord.target_size_mb, Some(25.0));
+    }
+
+    #[test]
+    fn explicit_options_win_and_are_reported_as_overrides() {
+        let explicit = EncodeSettings { crf: Some(20), max_bitrate_kbps: Some(8000), ..Default::default() };
+        let resolved = resolve(Some("youtube"), &explicit, Some(100.0)).unwrap();
+        assert_eq!(resolved.settings.crf, Some(20));
+        assert_eq!(resolved.settings.max_bitrate_kbps, Some(8000));
+        assert_eq!(resolved.target_size_mb, Some(100.0));
+        // The preset had no bitrate cap or size target, so only the CRF was replaced
+        assert_eq!(resolved.overrides, ["crf"]);
+
+        let plain = resolve(None, &explicit, None).unwrap();
+        assert_eq!((plain.preset, plain.overrides.len()), (None, 0));
+        assert_eq!(
+            resolve(Some("vimeo"), &explicit, None).unwrap_err(),
+            "Unknown platform_preset 'vimeo', expected one of youtube, twitter, discord"
+        );
+    }
+
+    #[test]
+    fn hardware_encoders_get_no_software_rate_control() {
+        let youtube = resolve(Some("youtube"), &EncodeSettings::default(), None).unwrap();
+        assert_eq!(args(&youtube, Some("h264_nvenc"), "game.mkv"), "-c:v h264_nvenc -c:a aac -b:a 192k");
+        let vp9 = resolve(None, &EncodeSettings { crf: Some(31), preset: Some("slow".to_string()), ..Default::default() }, None).unwrap();
+        assert_eq!(args(&vp9, Some("libvpx-vp9"), "game.webm"), "-c:v libvpx-vp9 -crf 31 -b:v 0");
+        // Left to the caller's own codec, only the rate control is added
+        assert_eq!(args(&vp9, None, "game.webm"), "-crf 31");
+    }
+
+    #[test]
+    fn a_video_past_the_platform_limit_is_warned_about() {
+        let mut twitter = resolve(Some("twitter"), &EncodeSettings::default(), None).unwrap();
+        twitter.check_duration(140.0);
+        assert!(twitter.warnings.is_empty());
+        twitter.check_duration(150.25);
+        assert_eq!(twitter.warnings, ["The video is 150.2s long, more than the 140s twitter allows"]);
+
+        let mut youtube = resolve(Some("youtube"), &EncodeSettings::default(), None).unwrap();
+        youtube.check_duration(36_000.0);
+        assert!(youtube.warnings.is_empty());
+    }
+}
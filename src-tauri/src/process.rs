@@ -0,0 +1,392 @@
+// Small cross-platform process helpers that std doesn't provide
+
+use crate::pause::PauseState;
+use crate::timeouts::Timeout;
+use crate::warnings;
+
+#[cfg(unix)]
+pub fn is_process_alive(pid: u32) -> bool {
+    // Signal 0 only performs the permission/existence check; EPERM still means the process exists
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+pub fn is_process_alive(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, STILL_ACTIVE};
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return false;
+        }
+        let mut exit_code = 0u32;
+        let ok = GetExitCodeProcess(handle, &mut exit_code) != 0;
+        CloseHandle(handle);
+        ok && exit_code == STILL_ACTIVE as u32
+    }
+}
+
+// Drops a running process to below-normal priority. The shell plugin can't pass creation flags or
+// wrap sidecars in `nice`, so this runs right after spawn; processes it starts later inherit it.
+#[cfg(unix)]
+pub fn lower_priority(pid: u32) -> Result<(), String> {
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS as _, pid as libc::id_t, 10) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+#[cfg(windows)]
+pub fn lower_priority(pid: u32) -> Result<(), String> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, SetPriorityClass, BELOW_NORMAL_PRIORITY_CLASS, PROCESS_SET_INFORMATION,
+    };
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_SET_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let ok = SetPriorityClass(handle, BELOW_NORMAL_PRIORITY_CLASS) != 0;
+        let error = std::io::Error::last_os_error();
+        CloseHandle(handle);
+        if ok {
+            Ok(())
+        } else {
+            Err(error.to_string())
+        }
+    }
+}
+
+// `cmd /C` with a command line from escape::cmd_script. Passed as separate arguments, the parts
+// would be quoted for the MSVC rules, which cmd.exe doesn't follow: a bare & in a path would end
+// the command there.
+#[cfg(windows)]
+pub fn cmd(script: &str) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("cmd");
+    command.arg("/C").raw_arg(script);
+    command
+}
+
+#[cfg(not(windows))]
+pub fn cmd(script: &str) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("cmd");
+    command.args(["/C", script]);
+    command
+}
+
+// Starts the child in its own process group so it can be signalled, along with its children, without us
+#[cfg(unix)]
+pub fn new_process_group(command: &mut tokio::process::Command) {
+    command.process_group(0);
+}
+
+#[cfg(windows)]
+pub fn new_process_group(command: &mut tokio::process::Command) {
+    use windows_sys::Win32::System::Threading::CREATE_NEW_PROCESS_GROUP;
+    command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+}
+
+// Asks a process group started by new_process_group to exit, giving it a chance to clean up
+#[cfg(unix)]
+pub fn interrupt_group(pid: u32) -> Result<(), String> {
+    let result = unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGTERM) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+#[cfg(windows)]
+pub fn interrupt_group(pid: u32) -> Result<(), String> {
+    use windows_sys::Win32::System::Console::{GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT};
+
+    // CTRL_C can't be sent to a process group, but CTRL_BREAK reaches every process in it
+    if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid) } != 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+// Kills the process and everything it started
+#[cfg(unix)]
+pub fn kill_tree(pid: u32) -> Result<(), String> {
+    let result = unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+#[cfg(windows)]
+pub fn kill_tree(pid: u32) -> Result<(), String> {
+    let output = std::process::Command::new("taskkill")
+        .args(["/PID", &pid.to_string(), "/T", "/F"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+// The process and its descendants, parents first
+fn descendants(pid: u32, parents: &[(u32, u32)]) -> Vec<u32> {
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i];
+        for (child, _) in parents.iter().filter(|(child, ppid)| *ppid == parent && *child != parent) {
+            // A reused pid can make a process look like its own ancestor
+            if !tree.contains(child) {
+                tree.push(*child);
+            }
+        }
+        i += 1;
+    }
+    tree
+}
+
+// The process and its descendants. Listed before anything is killed, children orphaned on the way are still found.
+#[cfg(unix)]
+pub fn process_tree(pid: u32) -> Result<Vec<u32>, String> {
+    let output = std::process::Command::new("ps")
+        .args(["-A", "-o", "pid=", "-o", "ppid="])
+        .output()
+        .map_err(|e| format!("Failed to list processes: {}", e))?;
+    let parents: Vec<(u32, u32)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().map(|f| f.parse::<u32>());
+            match (fields.next(), fields.next()) {
+                (Some(Ok(pid)), Some(Ok(ppid))) => Some((pid, ppid)),
+                _ => None,
+            }
+        })
+        .collect();
+    Ok(descendants(pid, &parents))
+}
+
+// Kills every process in `pids`, as listed by process_tree; unlike kill_tree it needs no process group
+#[cfg(unix)]
+pub fn kill_all(pids: &[u32]) -> Result<(), String> {
+    for &pid in pids {
+        // A process may have exited since it was listed
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) } != 0 && is_process_alive(pid) {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn kill_all(pids: &[u32]) -> Result<(), String> {
+    let alive: Vec<&u32> = pids.iter().filter(|pid| is_process_alive(**pid)).collect();
+    if alive.is_empty() {
+        return Ok(());
+    }
+    let mut command = std::process::Command::new("taskkill");
+    for pid in alive {
+        command.args(["/PID", &pid.to_string()]);
+    }
+    let output = command.arg("/F").output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+#[cfg(unix)]
+fn signal_tree(pid: u32, signal: libc::c_int) -> Result<(), String> {
+    for pid in process_tree(pid)? {
+        // A child may have exited since ps listed it
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } != 0 && is_process_alive(pid) {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+    }
+    Ok(())
+}
+
+// Stops the process and everything it started until resume_tree
+#[cfg(unix)]
+pub fn suspend_tree(pid: u32) -> Result<(), String> {
+    signal_tree(pid, libc::SIGSTOP)
+}
+
+#[cfg(unix)]
+pub fn resume_tree(pid: u32) -> Result<(), String> {
+    signal_tree(pid, libc::SIGCONT)
+}
+
+#[cfg(windows)]
+pub fn process_tree(pid: u32) -> Result<Vec<u32>, String> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+    };
+
+    let mut parents = Vec::new();
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let mut entry: PROCESSENTRY32W = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+        let mut more = Process32FirstW(snapshot, &mut entry) != 0;
+        while more {
+            parents.push((entry.th32ProcessID, entry.th32ParentProcessID));
+            more = Process32NextW(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+    }
+    Ok(descendants(pid, &parents))
+}
+
+// Windows has no SIGSTOP, so every thread of the tree is suspended on its own
+#[cfg(windows)]
+fn suspend_threads(pid: u32, suspend: bool) -> Result<(), String> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, SuspendThread, THREAD_SUSPEND_RESUME};
+
+    let tree = process_tree(pid)?;
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let mut entry: THREADENTRY32 = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+        let mut more = Thread32First(snapshot, &mut entry) != 0;
+        while more {
+            if tree.contains(&entry.th32OwnerProcessID) {
+                let thread = OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID);
+                if !thread.is_null() {
+                    if suspend {
+                        SuspendThread(thread);
+                    } else {
+                        ResumeThread(thread);
+                    }
+                    CloseHandle(thread);
+                }
+            }
+            more = Thread32Next(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn suspend_tree(pid: u32) -> Result<(), String> {
+    suspend_threads(pid, true)
+}
+
+#[cfg(windows)]
+pub fn resume_tree(pid: u32) -> Result<(), String> {
+    suspend_threads(pid, false)
+}
+
+pub struct StreamedOutput {
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+// Kills the child if the future driving it is dropped, e.g. when a sibling render chunk fails
+struct KillOnDrop(Option<tauri_plugin_shell::process::CommandChild>);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        if let Some(child) = self.0.take() {
+            let _ = child.kill();
+        }
+    }
+}
+
+// Runs a shell-plugin command to completion, handing every output line to `on_line` as it arrives.
+// Inside an export, the process is paused along with it and doesn't start while it is paused.
+pub async fn run_streaming<F>(
+    app: &tauri::AppHandle,
+    command: tauri_plugin_shell::process::Command,
+    limit: &Timeout,
+    low_priority: bool,
+    mut on_line: F,
+) -> Result<StreamedOutput, String>
+where
+    F: FnMut(&str, bool) -> bool,
+{
+    use tauri::Manager;
+    use tauri_plugin_shell::process::CommandEvent;
+
+    let pauses = app.state::<PauseState>();
+    let export_id = warnings::current_export_id();
+    if let Some(id) = &export_id {
+        pauses.wait_resumed(id).await;
+    }
+    let (mut events, child) = command.spawn().map_err(|e| format!("Failed to spawn: {}", e))?;
+    if low_priority {
+        if let Err(e) = lower_priority(child.pid()) {
+            log::warn!("Failed to lower the priority of process {}: {}", child.pid(), e);
+        }
+    }
+    let _tracked = export_id.as_deref().map(|id| pauses.track(id, child.pid()));
+    let mut child = KillOnDrop(Some(child));
+    let mut output = StreamedOutput {
+        code: None,
+        stdout: String::new(),
+        stderr: String::new(),
+    };
+
+    let collect = async {
+        while let Some(event) = events.recv().await {
+            match event {
+                CommandEvent::Stdout(line) | CommandEvent::Stderr(line) if line.is_empty() => {}
+                CommandEvent::Stdout(line) => {
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim_end();
+                    // The callback returns false for lines it consumed, like ffmpeg progress keys
+                    if on_line(line, false) {
+                        output.stdout.push_str(line);
+                        output.stdout.push('\n');
+                    }
+                }
+                CommandEvent::Stderr(line) => {
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim_end();
+                    if on_line(line, true) {
+                        output.stderr.push_str(line);
+                        output.stderr.push('\n');
+                    }
+                }
+                CommandEvent::Terminated(payload) => output.code = payload.code,
+                CommandEvent::Error(e) => log::warn!("Process output error: {}", e),
+                _ => {}
+            }
+        }
+    };
+
+    match pauses.timeout(export_id.as_deref(), limit.duration(), collect).await {
+        Some(()) => {
+            // Already exited
+            child.0 = None;
+            Ok(output)
+        }
+        None => Err(format!("timed out after {} seconds, {} timeout from {}", limit.secs, limit.source, limit.basis)),
+    }
+}
@@ -0,0 +1,242 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::exports::ExportRegistry;
+use crate::history::ExportHistory;
+use crate::pause::PauseState;
+use crate::segments::Segment;
+
+// Weight of the newest throughput sample; low enough that the ETA doesn't jump around
+const SMOOTHING: f64 = 0.3;
+
+// Share of the overall progress bar given to each stage
+const RENDER_WEIGHT: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Render,
+    Composite,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Render => "render",
+            Stage::Composite => "composite",
+        }
+    }
+
+    fn event(self) -> &'static str {
+        match self {
+            Stage::Render => "remotion-progress",
+            Stage::Composite => "ffmpeg-progress",
+        }
+    }
+}
+
+// Exponentially smoothed units-per-second estimate for one stage. `paused` is the export's time
+// spent paused so far, which its clock leaves out.
+pub struct EtaEstimator {
+    started: Instant,
+    paused_at_start: Duration,
+    last: Option<(Instant, Duration, f64)>,
+    rate: Option<f64>,
+    fallback_secs: Option<f64>,
+}
+
+impl EtaEstimator {
+    pub fn new(fallback_secs: Option<f64>, paused: Duration) -> Self {
+        EtaEstimator {
+            started: Instant::now(),
+            paused_at_start: paused,
+            last: None,
+            rate: None,
+            fallback_secs,
+        }
+    }
+
+    pub fn update(&mut self, done: f64, total: f64, paused: Duration) -> Option<f64> {
+        let now = Instant::now();
+        if let Some((last_time, last_paused, last_done)) = self.last {
+            let elapsed = now.duration_since(last_time).saturating_sub(paused.saturating_sub(last_paused)).as_secs_f64();
+            if elapsed > 0.0 && done > last_done {
+                let sample = (done - last_done) / elapsed;
+                self.rate = Some(match self.rate {
+                    Some(rate) => SMOOTHING * sample + (1.0 - SMOOTHING) * rate,
+                    None => sample,
+                });
+            }
+        }
+        if self.last.map(|(_, _, d)| done > d).unwrap_or(true) {
+            self.last = Some((now, paused, done));
+        }
+        self.eta(done, total, paused)
+    }
+
+    pub fn eta(&self, done: f64, total: f64, paused: Duration) -> Option<f64> {
+        match self.rate {
+            Some(rate) if rate > 0.0 => Some(((total - done).max(0.0) / rate).round()),
+            _ => self
+                .fallback_secs
+                .map(|avg| {
+                    let elapsed = self.started.elapsed().saturating_sub(paused.saturating_sub(self.paused_at_start));
+                    (avg - elapsed.as_secs_f64()).max(0.0).round()
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressEvent {
+    pub export_id: String,
+    pub stage: &'static str,
+    pub done: f64,
+    pub total: f64,
+    pub percent: f64,
+    pub eta_secs: Option<f64>,
+}
+
+// Progress of copying a finished output to a network destination
+#[derive(Debug, Clone, Serialize)]
+pub struct CopyProgressEvent {
+    pub export_id: String,
+    pub path: String,
+    pub done_bytes: u64,
+    pub total_bytes: u64,
+    pub percent: f64,
+}
+
+// Progress of one entry of `outputs`, transcoded from the shared composite
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputProgressEvent {
+    pub export_id: String,
+    pub output_index: usize,
+    pub path: String,
+    pub percent: f64,
+}
+
+// A segment of a segmented composite starting, finishing, failing or being retried or reused
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentProgressEvent {
+    pub export_id: String,
+    // 1-based, "segment 14/80"
+    pub segment: usize,
+    pub segments: usize,
+    pub start: f64,
+    pub end: f64,
+    pub status: &'static str,
+}
+
+pub struct ProgressReporter {
+    app: AppHandle,
+    export_id: String,
+    composite_average: Option<f64>,
+    render: Mutex<EtaEstimator>,
+    composite: Mutex<EtaEstimator>,
+}
+
+impl ProgressReporter {
+    pub fn new(app: &AppHandle, export_id: &str, moves: usize) -> Self {
+        let history = app.state::<ExportHistory>();
+        let render_average = history.average_stage_duration(Stage::Render.name(), moves);
+        let composite_average = history.average_stage_duration(Stage::Composite.name(), moves);
+
+        ProgressReporter {
+            app: app.clone(),
+            export_id: export_id.to_string(),
+            composite_average,
+            render: Mutex::new(EtaEstimator::new(render_average, Duration::ZERO)),
+            composite: Mutex::new(EtaEstimator::new(composite_average, Duration::ZERO)),
+        }
+    }
+
+    // Restarts a stage's clock so the historical fallback counts from when the stage actually began
+    pub fn start(&self, stage: Stage) {
+        let fallback = self.estimator(stage).lock().unwrap().fallback_secs;
+        *self.estimator(stage).lock().unwrap() = EtaEstimator::new(fallback, self.paused_for());
+    }
+
+    fn paused_for(&self) -> Duration {
+        self.app.state::<PauseState>().paused_for(&self.export_id)
+    }
+
+    fn estimator(&self, stage: Stage) -> &Mutex<EtaEstimator> {
+        match stage {
+            Stage::Render => &self.render,
+            Stage::Composite => &self.composite,
+        }
+    }
+
+    pub fn report(&self, stage: Stage, done: f64, total: f64) {
+        if total <= 0.0 {
+            return;
+        }
+        let fraction = (done / total).clamp(0.0, 1.0);
+        let eta_secs = self.estimator(stage).lock().unwrap().update(done, total, self.paused_for());
+
+        let stage_event = ProgressEvent {
+            export_id: self.export_id.clone(),
+            stage: stage.name(),
+            done,
+            total,
+            percent: (fraction * 100.0).round(),
+            eta_secs,
+        };
+
+        // The overall ETA needs the remaining stages too, which only history can provide mid-render
+        let (overall, overall_eta) = match stage {
+            Stage::Render => (
+                fraction * RENDER_WEIGHT,
+                eta_secs.zip(self.composite_average).map(|(eta, composite)| eta + composite.round()),
+            ),
+            Stage::Composite => (RENDER_WEIGHT + fraction * (1.0 - RENDER_WEIGHT), eta_secs),
+        };
+        let export_event = ProgressEvent {
+            percent: (overall * 100.0).round(),
+            eta_secs: overall_eta,
+            ..stage_event.clone()
+        };
+        self.app.state::<ExportRegistry>().record_progress(&self.export_id, stage.name(), export_event.percent, overall_eta);
+
+        let _ = self.app.emit(stage.event(), stage_event);
+        let _ = self.app.emit("export-progress", export_event);
+    }
+
+    pub fn report_copy(&self, path: &str, done: f64, total: f64) {
+        if total <= 0.0 {
+            return;
+        }
+        let _ = self.app.emit("copy-progress", CopyProgressEvent {
+            export_id: self.export_id.clone(),
+            path: path.to_string(),
+            done_bytes: done as u64,
+            total_bytes: total as u64,
+            percent: ((done / total).clamp(0.0, 1.0) * 100.0).round(),
+        });
+    }
+
+    pub fn report_output(&self, output_index: usize, path: &str, done: f64, total: f64) {
+        if total <= 0.0 {
+            return;
+        }
+        let _ = self.app.emit("output-progress", OutputProgressEvent {
+            export_id: self.export_id.clone(),
+            output_index,
+            path: path.to_string(),
+            percent: ((done / total).clamp(0.0, 1.0) * 100.0).round(),
+        });
+    }
+
+    pub fn report_segment(&self, segment: &Segment, segments: usize, status: &'static str) {
+        let _ = self.app.emit("segment-progress", SegmentProgressEvent {
+            export_id: self.export_id.clone(),
+            segment: segment.index + 1,
+            segments,
+            start: segment.start,
+            end: segment.end,
+            status,
+        });
+    }
+}
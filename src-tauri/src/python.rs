@@ -0,0 +1,1031 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Manager, State};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::Notify;
+
+use crate::escape::{cmd_script, quote_sh, render_command_line};
+use crate::filename::{sanitize_filename, FilenameMode, Platform};
+use crate::process;
+use crate::schema;
+use crate::wsl;
+use crate::settings::{PipenvOptions, SettingsState};
+
+// Older lines are dropped from the transcript past this; the end of a failing run matters most
+const MAX_TRANSCRIPT_LINES: usize = 2000;
+
+// export.py renders and composites a whole video, so the default has to be generous
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+// Time a stopped script gets to clean up before it is killed
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+// Printed to stderr by the WSL wrapper so the script can be signalled inside the distribution
+const WSL_PID_MARKER: &str = "__boardcast_pid__ ";
+
+// Cancel signals of the scripts started with a run_id
+#[derive(Default)]
+pub struct PythonRuns {
+    runs: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+impl PythonRuns {
+    fn register(&self, run_id: &str) -> Result<Arc<Notify>, String> {
+        let mut runs = self.runs.lock().unwrap();
+        if runs.contains_key(run_id) {
+            return Err(format!("A script with run_id {} is already running", run_id));
+        }
+        let cancel = Arc::new(Notify::new());
+        runs.insert(run_id.to_string(), cancel.clone());
+        Ok(cancel)
+    }
+
+    fn finish(&self, run_id: &str) {
+        self.runs.lock().unwrap().remove(run_id);
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+pub enum OsEnvironment {
+    #[default]
+    Windows,
+    Wsl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+// One line as it was read, with milliseconds since the script started
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptLine {
+    pub stream: Stream,
+    pub timestamp_ms: u64,
+    pub line: String,
+}
+
+// Windows-1252's 0x80..=0x9F; the rest of the code page matches Latin-1
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{FFFD}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{FFFD}', 'Ž', '\u{FFFD}',
+    '\u{FFFD}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{FFFD}', 'ž', 'Ÿ',
+];
+
+// Scripts are run with UTF-8 forced, but anything printed by a native library can still come
+// out in the console code page, so a line that isn't valid UTF-8 is read as cp1252
+fn decode_line(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(line) => line.to_string(),
+        Err(_) => bytes
+            .iter()
+            .map(|&b| match b {
+                0x80..=0x9F => CP1252_HIGH[(b - 0x80) as usize],
+                b => char::from(b),
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Default)]
+struct CapturedOutput {
+    code: Option<i32>,
+    stdout: String,
+    stderr: String,
+    // Both streams in the order their lines were read
+    transcript: VecDeque<TranscriptLine>,
+    transcript_truncated: bool,
+    termination: Option<Termination>,
+}
+
+impl CapturedOutput {
+    fn push(&mut self, stream: Stream, started: Instant, bytes: &[u8]) {
+        let line = decode_line(bytes).trim_end_matches(['\r', '\n']).to_string();
+        let text = match stream {
+            Stream::Stdout => &mut self.stdout,
+            Stream::Stderr => &mut self.stderr,
+        };
+        text.push_str(&line);
+        text.push('\n');
+        if self.transcript.len() == MAX_TRANSCRIPT_LINES {
+            self.transcript.pop_front();
+            self.transcript_truncated = true;
+        }
+        self.transcript.push_back(TranscriptLine {
+            stream,
+            timestamp_ms: started.elapsed().as_millis() as u64,
+            line,
+        });
+    }
+
+    fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+// What a structured call returns, whether or not the script succeeded
+#[derive(Debug, Serialize)]
+pub struct ScriptResult {
+    pub success: bool,
+    pub exit_code: Option<i32>,
+    // Parsed JSON with json_output, otherwise stdout as a string
+    pub output: Value,
+    pub stderr: String,
+    pub transcript: Vec<TranscriptLine>,
+    pub transcript_truncated: bool,
+    // Written only when the script fails
+    pub log_path: Option<String>,
+    // Set when the script was stopped by a timeout or cancel
+    pub termination: Option<Termination>,
+}
+
+// Interpreter inside a virtualenv, laid out the way the host's venv module does it
+fn venv_interpreter(venv: &Path) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        venv.join("Scripts").join("python.exe")
+    } else {
+        venv.join("bin").join("python")
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// A missing venv and a venv without an interpreter are different mistakes, so they get different errors
+async fn check_venv(os_env: OsEnvironment, venv: &str) -> Result<(), String> {
+    match os_env {
+        OsEnvironment::Windows => {
+            let interpreter = venv_interpreter(Path::new(venv));
+            if !Path::new(venv).is_dir() {
+                Err(format!("The virtualenv {} does not exist", venv))
+            } else if !is_executable(&interpreter) {
+                Err(format!("The virtualenv {} has no runnable interpreter at {}", venv, interpreter.display()))
+            } else {
+                Ok(())
+            }
+        }
+        OsEnvironment::Wsl => {
+            // The path is inside the distribution, so it is checked there and used exactly as given
+            let check = format!("test -d {0} || exit 2; test -x {0}/bin/python || exit 3", quote_sh(venv));
+            let status = Command::new("wsl")
+                .args(["bash", "-c", &check])
+                .status()
+                .await
+                .map_err(|e| format!("Failed to check the virtualenv in WSL: {}", e))?;
+            match status.code() {
+                Some(0) => Ok(()),
+                Some(2) => Err(format!("The virtualenv {} does not exist in WSL", venv)),
+                Some(3) => Err(format!("The virtualenv {} has no runnable interpreter at {}/bin/python", venv, venv)),
+                code => Err(format!("Failed to check the virtualenv {} in WSL (exit code {:?})", venv, code)),
+            }
+        }
+    }
+}
+
+// The settings' pipenv options with any per-call fields laid over them
+fn pipenv_options(settings: &PipenvOptions, overrides: Option<&Value>) -> Result<PipenvOptions, String> {
+    let Some(Value::Object(overrides)) = overrides else {
+        return match overrides {
+            None | Some(Value::Null) => Ok(settings.clone()),
+            Some(value) => Err(format!("pipenv_options must be an object, got {}", value)),
+        };
+    };
+    let mut merged = serde_json::to_value(settings).map_err(|e| e.to_string())?;
+    if let Value::Object(fields) = &mut merged {
+        fields.extend(overrides.clone());
+    }
+    serde_json::from_value(merged).map_err(|e| format!("Invalid pipenv_options: {}", e))
+}
+
+// Python otherwise writes in the console code page on Windows and fails on characters outside it
+fn utf8_env() -> Vec<(String, String)> {
+    vec![
+        ("PYTHONIOENCODING".to_string(), "utf-8".to_string()),
+        ("PYTHONUTF8".to_string(), "1".to_string()),
+    ]
+}
+
+fn pipenv_env(options: &PipenvOptions) -> Vec<(String, String)> {
+    let mut env = Vec::new();
+    let mut set = |key: &str, value: &str| env.push((key.to_string(), value.to_string()));
+    if options.ignore_virtualenvs {
+        set("PIPENV_IGNORE_VIRTUALENVS", "1");
+    }
+    if options.quiet {
+        set("PIPENV_VERBOSITY", "-1");
+    }
+    if options.no_spinner {
+        set("PIPENV_NOSPIN", "1");
+    }
+    if let Some(pipfile) = options.pipfile.as_deref().filter(|p| !p.trim().is_empty()) {
+        set("PIPENV_PIPFILE", pipfile);
+    }
+    env
+}
+
+// `venv` bypasses pipenv and runs the script with that environment's interpreter. WSL doesn't pass
+// the Windows environment through, so there `env` is set on the bash command line instead.
+fn script_command(
+    os_env: OsEnvironment,
+    dir: &str,
+    target: &[String],
+    cli_args: &[String],
+    venv: Option<&str>,
+    env: &[(String, String)],
+) -> Command {
+    match (os_env, venv) {
+        (OsEnvironment::Windows, Some(venv)) => {
+            let mut command = Command::new(venv_interpreter(Path::new(venv)));
+            command.current_dir(dir).args(target).args(cli_args);
+            command.envs(env.iter().cloned());
+            command
+        }
+        (OsEnvironment::Windows, None) => {
+            // For Windows, we'll use cmd to run the script
+            let mut line: Vec<&str> = vec!["pipenv", "run", "python"];
+            line.extend(target.iter().chain(cli_args).map(String::as_str));
+            let mut command = process::cmd(&cmd_script(&[&["cd", "/D", dir], &line]));
+            command.envs(env.iter().cloned());
+            command
+        }
+        (OsEnvironment::Wsl, venv) => {
+            // Escape and format CLI arguments for WSL
+            let args_str = target
+                .iter()
+                .chain(cli_args)
+                .map(|arg| quote_sh(arg))
+                .collect::<Vec<String>>()
+                .join(" ");
+            let python = match venv {
+                Some(venv) => format!("{}/bin/python", quote_sh(venv)),
+                None => "pipenv run python".to_string(),
+            };
+            let assignments: String = env.iter().map(|(key, value)| format!("{}={} ", key, quote_sh(value))).collect();
+            // exec keeps the pid printed here as the python process's (pipenv run execs python too)
+            let script_line = format!(
+                "echo \"{}$$\" >&2; cd {} && exec env {}{} {}",
+                WSL_PID_MARKER, quote_sh(dir), assignments, python, args_str
+            );
+            let mut command = Command::new("wsl");
+            command.args(["bash", "-c", &script_line]);
+            command
+        }
+    }
+}
+
+// Why a run was stopped before it finished
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    Timeout,
+    Cancelled,
+    // Nothing was printed on either stream for idle_timeout_secs
+    IdleTimeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Termination {
+    pub reason: StopReason,
+    // Exited within the grace period after the polite signal, rather than being killed
+    pub graceful: bool,
+    // How long the script had printed nothing when it was stopped
+    pub silent_ms: u64,
+}
+
+// Limits and the cancel signal for one run
+struct RunControl {
+    timeout: Duration,
+    idle_timeout: Option<Duration>,
+    grace: Duration,
+    cancel: Arc<Notify>,
+    // The child is wsl.exe, so the script itself has to be signalled inside the distribution
+    wsl: bool,
+}
+
+// Asks the script to exit: the python process inside WSL, or the process group the script heads
+fn request_stop(pid: Option<u32>, wsl_pid: Option<u32>) {
+    let result = match (wsl_pid, pid) {
+        (Some(wsl_pid), _) => std::process::Command::new("wsl")
+            .args(["-e", "kill", "-TERM", &wsl_pid.to_string()])
+            .status()
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        (None, Some(pid)) => process::interrupt_group(pid),
+        (None, None) => Ok(()),
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to ask the script to stop: {}", e);
+    }
+}
+
+fn force_kill(pid: Option<u32>, wsl_pid: Option<u32>) {
+    if let Some(wsl_pid) = wsl_pid {
+        let _ = std::process::Command::new("wsl").args(["-e", "kill", "-KILL", &wsl_pid.to_string()]).status();
+    }
+    if let Some(Err(e)) = pid.map(process::kill_tree) {
+        log::warn!("Failed to kill the script's process tree: {}", e);
+    }
+}
+
+// Lines quoted in the error of a script stopped for going silent
+const IDLE_TAIL_LINES: usize = 10;
+
+// Sends the polite signal and returns when the script gets killed unless it has exited
+fn begin_stop(
+    output: &mut CapturedOutput,
+    reason: StopReason,
+    silent: Duration,
+    (pid, wsl_pid): (Option<u32>, Option<u32>),
+    grace: Duration,
+) -> tokio::time::Instant {
+    log::info!("Stopping the script ({:?}), killing it in {}s unless it exits", reason, grace.as_secs());
+    output.termination = Some(Termination { reason, graceful: true, silent_ms: silent.as_millis() as u64 });
+    request_stop(pid, wsl_pid);
+    tokio::time::Instant::now() + grace
+}
+
+// Runs the script with both pipes read concurrently, so the transcript keeps the order lines arrived in.
+// A timeout or cancel first asks the script to exit and only kills it once the grace period is up.
+async fn run_captured(mut command: Command, control: RunControl) -> Result<CapturedOutput, String> {
+    command.stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+    process::new_process_group(&mut command);
+    let mut child = command.spawn().map_err(|e| e.to_string())?;
+    let pid = child.id();
+    let started = Instant::now();
+    let mut stdout = BufReader::new(child.stdout.take().ok_or("The script's stdout was not captured")?);
+    let mut stderr = BufReader::new(child.stderr.take().ok_or("The script's stderr was not captured")?);
+
+    let mut output = CapturedOutput::default();
+    let (mut stdout_line, mut stderr_line) = (Vec::new(), Vec::new());
+    let (mut stdout_open, mut stderr_open) = (true, true);
+    let mut wsl_pid: Option<u32> = None;
+    let deadline = tokio::time::Instant::now() + control.timeout;
+    let mut kill_at: Option<tokio::time::Instant> = None;
+    // Bytes of an unfinished line count as output too, so the buffers' lengths are compared as well
+    let mut last_output = tokio::time::Instant::now();
+    let mut partial = (0, 0);
+    while stdout_open || stderr_open {
+        let idle_at = last_output + control.idle_timeout.unwrap_or_default();
+        // A read cut short by the other branch keeps its bytes in the buffer and carries on next time
+        tokio::select! {
+            read = stdout.read_until(b'\n', &mut stdout_line), if stdout_open => match read {
+                Ok(0) | Err(_) => stdout_open = false,
+                Ok(_) => {
+                    output.push(Stream::Stdout, started, &stdout_line);
+                    stdout_line.clear();
+                    last_output = tokio::time::Instant::now();
+                }
+            },
+            read = stderr.read_until(b'\n', &mut stderr_line), if stderr_open => match read {
+                Ok(0) | Err(_) => stderr_open = false,
+                Ok(_) => {
+                    let marked = String::from_utf8_lossy(&stderr_line).trim().strip_prefix(WSL_PID_MARKER).map(|p| p.parse().ok());
+                    match marked {
+                        Some(parsed) if control.wsl && wsl_pid.is_none() => wsl_pid = parsed,
+                        _ => output.push(Stream::Stderr, started, &stderr_line),
+                    }
+                    stderr_line.clear();
+                    last_output = tokio::time::Instant::now();
+                }
+            },
+            reason = async {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(deadline) => StopReason::Timeout,
+                    _ = control.cancel.notified() => StopReason::Cancelled,
+                }
+            }, if kill_at.is_none() => {
+                kill_at = Some(begin_stop(&mut output, reason, last_output.elapsed(), (pid, wsl_pid), control.grace));
+            },
+            _ = tokio::time::sleep_until(idle_at), if control.idle_timeout.is_some() && kill_at.is_none() => {
+                let lengths = (stdout_line.len(), stderr_line.len());
+                if lengths != partial {
+                    partial = lengths;
+                    last_output = tokio::time::Instant::now();
+                } else {
+                    let silent = last_output.elapsed();
+                    kill_at = Some(begin_stop(&mut output, StopReason::IdleTimeout, silent, (pid, wsl_pid), control.grace));
+                }
+            },
+            _ = tokio::time::sleep_until(kill_at.unwrap_or(deadline)), if kill_at.is_some() => {
+                force_kill(pid, wsl_pid);
+                if let Some(termination) = &mut output.termination {
+                    termination.graceful = false;
+                }
+                // Whatever is still holding the pipes open is gone or orphaned; nothing more is read
+                break;
+            },
+        }
+    }
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    output.code = status.code();
+    Ok(output)
+}
+
+// Writes a failed run's transcript under the app log directory for later debugging
+fn write_invocation_log(app: &AppHandle, target: &[String], cli_args: &[String], output: &CapturedOutput) -> Option<PathBuf> {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let dir = app.path().app_log_dir().ok()?.join("python");
+    let name = target.last().map(String::as_str).unwrap_or("python");
+    let file_name = sanitize_filename(&format!("{}-{}.log", millis, name), Platform::of(&dir), FilenameMode::Lenient).ok()?;
+    let path = dir.join(file_name);
+
+    let mut content = format!(
+        "Command: {}\nExit code: {:?}\n\n",
+        render_command_line("python", &[target, cli_args].concat()),
+        output.code
+    );
+    if output.transcript_truncated {
+        content.push_str(&format!("(only the last {} lines were kept)\n", MAX_TRANSCRIPT_LINES));
+    }
+    for entry in &output.transcript {
+        let stream = match entry.stream {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        };
+        content.push_str(&format!("{:>8}ms {} | {}\n", entry.timestamp_ms, stream, entry.line));
+    }
+    let written = fs::create_dir_all(&dir).and_then(|_| fs::write(&path, content));
+    match written {
+        Ok(()) => Some(path),
+        Err(e) => {
+            log::warn!("Failed to write the python log {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn kwarg_value(key: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(text) => Ok(text.clone()),
+        Value::Number(number) => Ok(number.to_string()),
+        _ => Err(format!("kwargs {} must be a string, number, boolean or a list of strings and numbers, got {}", key, value)),
+    }
+}
+
+// Expands {"depth": 18, "verbose": true, "tag": ["a", "b"]} to --depth 18 --verbose --tag a --tag b,
+// in key order so the same map always gives the same argv
+fn expand_kwargs(kwargs: &Map<String, Value>) -> Result<Vec<String>, String> {
+    let mut keys: Vec<&String> = kwargs.keys().collect();
+    keys.sort();
+    let mut args = Vec::new();
+    for key in keys {
+        let safe = key.starts_with(|c: char| c.is_ascii_alphabetic())
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !safe {
+            return Err(format!("kwargs key '{}' must start with a letter and contain only letters, digits, _ and -", key));
+        }
+        let flag = format!("--{}", key);
+        match &kwargs[key] {
+            Value::Null => return Err(format!("kwargs {} is null; leave it out or give it a value", key)),
+            Value::Bool(true) => args.push(flag),
+            Value::Bool(false) => {}
+            Value::Array(items) => {
+                for item in items {
+                    args.extend([flag.clone(), kwarg_value(key, item)?]);
+                }
+            }
+            value => args.extend([flag, kwarg_value(key, value)?]),
+        }
+    }
+    Ok(args)
+}
+
+// What a dry run reports instead of running anything
+fn describe_command(command: &Command, env: &[(String, String)]) -> Value {
+    let command = command.as_std();
+    let args: Vec<String> = command.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+    let program = command.get_program().to_string_lossy().to_string();
+    serde_json::json!({
+        "program": program,
+        "args": args,
+        "cwd": command.get_current_dir().map(|d| d.display().to_string()),
+        "env": env.iter().cloned().collect::<std::collections::BTreeMap<_, _>>(),
+        "command_line": render_command_line(&program, &args),
+    })
+}
+
+fn parse_output(stdout: &str, json_output: bool, expected_schema: Option<&Value>) -> Result<Value, String> {
+    if !json_output {
+        // Return the raw string output wrapped in a JSON string value
+        return Ok(Value::String(stdout.to_string()));
+    }
+    let parsed: Value = serde_json::from_str(stdout).map_err(|e| format!("Failed to parse JSON output: {}", e))?;
+    if let Some(schema) = expected_schema {
+        let violations = schema::violations(schema, &parsed);
+        if !violations.is_empty() {
+            return Err(format!("The script's output doesn't match expected_schema:\n{}", violations.join("\n")));
+        }
+    }
+    Ok(parsed)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecMode {
+    // `script` is a .py file in the script directory
+    #[default]
+    Script,
+    // `script` is a dotted module name, run with python -m
+    Module,
+}
+
+// Dotted python identifiers, e.g. boardcast_tools.analyze
+fn is_module_name(name: &str) -> bool {
+    name.split('.').all(|part| {
+        let mut chars = part.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+// Everything past the original four arguments, so new knobs don't each need a parameter
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ScriptOptions {
+    // Returns a ScriptResult with the transcript, even when the script fails
+    pub structured: bool,
+    // Runs the script with this virtualenv's interpreter instead of pipenv
+    pub venv_path: Option<String>,
+    // Fields laid over the pipenv_options setting for this call
+    pub pipenv_options: Option<Value>,
+    // Describes the command, its directory and its environment without running it
+    pub dry_run: bool,
+    pub timeout_secs: Option<u64>,
+    // How long a timed-out or cancelled script gets to exit after being asked, default 5 s
+    pub grace_period_secs: Option<u64>,
+    // Stops the script once it has printed nothing on either stream for this long
+    pub idle_timeout_secs: Option<u64>,
+    // Lets cancel_python_script stop this run
+    pub run_id: Option<String>,
+    // Expanded to --key value flags after cli_args
+    pub kwargs: Option<Map<String, Value>>,
+    // Checked against the parsed output when json_output is on
+    pub expected_schema: Option<Value>,
+    pub exec_mode: ExecMode,
+}
+
+#[command]
+pub async fn run_python_script(
+    app: AppHandle,
+    script: String,
+    cli_args: Vec<String>,
+    os_env: Option<OsEnvironment>,
+    json_output: Option<bool>,
+    options: Option<ScriptOptions>,
+) -> Result<Value, String> {
+    let os_env = os_env.unwrap_or_default();
+    let json_output = json_output.unwrap_or(false);
+    let options = options.unwrap_or_default();
+    let settings = app.state::<SettingsState>().get();
+
+    let target = match options.exec_mode {
+        ExecMode::Script => {
+            // Validate script name
+            if !script.ends_with(".py") || script.contains('/') || script.contains('\\') {
+                return Err("Invalid script name.".to_string());
+            }
+            vec![script]
+        }
+        ExecMode::Module => {
+            if !is_module_name(&script) {
+                return Err(format!("Invalid module name: {}", script));
+            }
+            if !settings.python_modules.contains(&script) {
+                return Err(format!("The module {} isn't in the python_modules setting, so it can't be run", script));
+            }
+            vec!["-m".to_string(), script]
+        }
+    };
+
+    let mut cli_args = cli_args;
+    if let Some(kwargs) = &options.kwargs {
+        cli_args.extend(expand_kwargs(kwargs)?);
+    }
+
+    if matches!(os_env, OsEnvironment::Wsl) {
+        wsl::require_wsl(&app).await?;
+    }
+    let venv = options.venv_path.as_deref().map(str::trim).filter(|v| !v.is_empty());
+    if let Some(venv) = venv {
+        check_venv(os_env, venv).await?;
+    }
+
+    let mut env = utf8_env();
+    // Nothing of pipenv's applies when the venv's interpreter runs the script directly
+    if venv.is_none() {
+        env.extend(pipenv_env(&self::pipenv_options(&settings.pipenv_options, options.pipenv_options.as_ref())?));
+    }
+
+    let dir = match os_env {
+        OsEnvironment::Windows => settings.script_dir(),
+        OsEnvironment::Wsl => settings.wsl_script_dir(),
+    };
+    let command = script_command(os_env, &dir, &target, &cli_args, venv, &env);
+    if options.dry_run {
+        return Ok(describe_command(&command, &env));
+    }
+    let runs = app.state::<PythonRuns>();
+    let cancel = match &options.run_id {
+        Some(run_id) => runs.register(run_id)?,
+        None => Arc::new(Notify::new()),
+    };
+    let control = RunControl {
+        timeout: options.timeout_secs.map(Duration::from_secs).unwrap_or(DEFAULT_TIMEOUT),
+        idle_timeout: options.idle_timeout_secs.map(Duration::from_secs),
+        grace: options.grace_period_secs.map(Duration::from_secs).unwrap_or(DEFAULT_GRACE_PERIOD),
+        cancel,
+        wsl: matches!(os_env, OsEnvironment::Wsl),
+    };
+    let output = run_captured(command, control).await;
+    if let Some(run_id) = &options.run_id {
+        runs.finish(run_id);
+    }
+    let output = output?;
+    let log_path = (!output.success())
+        .then(|| write_invocation_log(&app, &target, &cli_args, &output))
+        .flatten();
+
+    if options.structured {
+        let result = ScriptResult {
+            success: output.success(),
+            exit_code: output.code,
+            output: if output.success() { parse_output(&output.stdout, json_output, options.expected_schema.as_ref())? } else { Value::String(output.stdout.clone()) },
+            stderr: output.stderr,
+            transcript: output.transcript.into(),
+            transcript_truncated: output.transcript_truncated,
+            log_path: log_path.map(|p| p.display().to_string()),
+            termination: output.termination,
+        };
+        return serde_json::to_value(result).map_err(|e| e.to_string());
+    }
+
+    if !output.success() {
+        let stopped = stop_message(&output);
+        return Err(match log_path {
+            Some(path) => format!("{}{}\nPython log: {}", stopped, output.stderr, path.display()),
+            None => format!("{}{}", stopped, output.stderr),
+        });
+    }
+    parse_output(&output.stdout, json_output, options.expected_schema.as_ref())
+}
+
+// Leads the error of a script that was stopped: why, and whether it exited or had to be killed
+fn stop_message(output: &CapturedOutput) -> String {
+    match output.termination {
+        Some(Termination { reason: StopReason::IdleTimeout, graceful, silent_ms }) => {
+            let skip = output.transcript.len().saturating_sub(IDLE_TAIL_LINES);
+            let tail: Vec<&str> = output.transcript.iter().skip(skip).map(|l| l.line.as_str()).collect();
+            format!(
+                "IdleTimeout: the script printed nothing for {:.1}s and {}\nLast lines:\n{}\n",
+                silent_ms as f64 / 1000.0,
+                if graceful { "exited when asked to stop" } else { "had to be killed" },
+                if tail.is_empty() { "(none)".to_string() } else { tail.join("\n") }
+            )
+        }
+        Some(Termination { reason, graceful, .. }) => format!(
+            "The script was stopped ({}) and {}\n",
+            if reason == StopReason::Timeout { "timed out" } else { "cancelled" },
+            if graceful { "exited on its own" } else { "had to be killed" }
+        ),
+        None => String::new(),
+    }
+}
+
+#[command]
+pub fn cancel_python_script(runs: State<'_, PythonRuns>, run_id: String) -> Result<(), String> {
+    let runs = runs.runs.lock().unwrap();
+    let cancel = runs.get(&run_id).ok_or_else(|| format!("No script is running with run_id {}", run_id))?;
+    cancel.notify_one();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control() -> RunControl {
+        RunControl {
+            timeout: Duration::from_secs(30),
+            idle_timeout: None,
+            grace: DEFAULT_GRACE_PERIOD,
+            cancel: Arc::new(Notify::new()),
+            wsl: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn the_transcript_keeps_lines_from_both_streams_in_order() {
+        // The pauses keep each line's read apart from the next one on the other pipe
+        let script = "import sys, time\n\
+            for i in range(3):\n    \
+                print(f'out {i}', flush=True)\n    \
+                time.sleep(0.05)\n    \
+                print(f'err {i}', file=sys.stderr, flush=True)\n    \
+                time.sleep(0.05)\n\
+            sys.exit(3)\n";
+        let mut command = Command::new(if cfg!(windows) { "python" } else { "python3" });
+        command.args(["-c", script]);
+        let output = run_captured(command, control()).await.unwrap();
+
+        assert_eq!(output.code, Some(3));
+        let lines: Vec<(Stream, &str)> = output.transcript.iter().map(|l| (l.stream, l.line.as_str())).collect();
+        assert_eq!(
+            lines,
+            [
+                (Stream::Stdout, "out 0"),
+                (Stream::Stderr, "err 0"),
+                (Stream::Stdout, "out 1"),
+                (Stream::Stderr, "err 1"),
+                (Stream::Stdout, "out 2"),
+                (Stream::Stderr, "err 2"),
+            ]
+        );
+        assert!(output.transcript.iter().zip(output.transcript.iter().skip(1)).all(|(a, b)| a.timestamp_ms <= b.timestamp_ms));
+        // Each stream's text is still kept on its own as well
+        assert_eq!(output.stdout, "out 0\nout 1\nout 2\n");
+        assert_eq!(output.stderr, "err 0\nerr 1\nerr 2\n");
+    }
+
+    fn encoding_check(args: &[&str]) -> Command {
+        let mut command = Command::new(if cfg!(windows) { "python" } else { "python3" });
+        command.arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("../py-util/encoding_check.py")).args(args);
+        command.envs(utf8_env());
+        command
+    }
+
+    const SAMPLE: &str = "Ding Lirén, Nepomniachtchi, Rapport Richárd, Duda Jan-Krzysztof, Çağrı ♛ ♞ 🏆 ✅";
+
+    #[tokio::test]
+    async fn accents_and_emoji_round_trip_through_a_script() {
+        let output = run_captured(encoding_check(&[]), control()).await.unwrap();
+        assert!(output.success(), "{}", output.stderr);
+        assert_eq!(parse_output(&output.stdout, false, None).unwrap(), format!("{}\n", SAMPLE));
+        assert_eq!(output.stderr, "encoding: utf-8\n");
+
+        let output = run_captured(encoding_check(&["--json"]), control()).await.unwrap();
+        let parsed = parse_output(&output.stdout, true, None).unwrap();
+        assert_eq!(parsed["sample"], SAMPLE);
+        // Python counts code points, so nothing was split or replaced on the way
+        assert_eq!(parsed["length"], SAMPLE.chars().count());
+    }
+
+    #[test]
+    fn lines_are_decoded_as_utf8_before_cp1252() {
+        assert_eq!(decode_line("Richárd 🏆".as_bytes()), "Richárd 🏆");
+        // The same name printed in cp1252, with a euro sign and curly quotes from 0x80..=0x9F
+        assert_eq!(decode_line(b"Rich\xe1rd \x80 \x93ok\x94"), "Richárd € “ok”");
+        // The code points cp1252 leaves undefined become the replacement character
+        assert_eq!(decode_line(b"\x81\xff"), "\u{FFFD}ÿ");
+    }
+
+    #[test]
+    fn the_transcript_drops_its_oldest_lines_past_the_cap() {
+        let mut output = CapturedOutput::default();
+        let started = Instant::now();
+        for i in 0..MAX_TRANSCRIPT_LINES + 2 {
+            output.push(if i % 2 == 0 { Stream::Stdout } else { Stream::Stderr }, started, format!("line {}\r\n", i).as_bytes());
+        }
+        assert!(output.transcript_truncated);
+        assert_eq!(output.transcript.len(), MAX_TRANSCRIPT_LINES);
+        assert_eq!(output.transcript.front().unwrap().line, "line 2");
+        assert_eq!(output.transcript.back().unwrap().line, format!("line {}", MAX_TRANSCRIPT_LINES + 1));
+        // The per-stream text isn't capped
+        assert!(output.stdout.starts_with("line 0\nline 2\n"));
+    }
+
+    fn argv(command: &Command) -> Vec<String> {
+        command.as_std().get_args().map(|a| a.to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn a_venv_runs_its_own_interpreter_without_pipenv() {
+        let (target, cli_args) = (["export.py".to_string()], ["--depth".to_string(), "18".to_string()]);
+        let command = script_command(OsEnvironment::Windows, "/scripts", &target, &cli_args, Some("/envs/torch"), &utf8_env());
+        assert_eq!(command.as_std().get_program(), venv_interpreter(Path::new("/envs/torch")).as_os_str());
+        assert_eq!(argv(&command), ["export.py", "--depth", "18"]);
+        assert_eq!(command.as_std().get_current_dir(), Some(Path::new("/scripts")));
+
+        // Inside WSL the path is the distribution's own and is used exactly as given
+        let command = script_command(OsEnvironment::Wsl, "/home/me/scripts", &target, &cli_args, Some("/home/me/.venvs/torch"), &[]);
+        assert_eq!(
+            argv(&command),
+            [
+                "bash",
+                "-c",
+                "echo \"__boardcast_pid__ $$\" >&2; cd '/home/me/scripts' && exec env '/home/me/.venvs/torch'/bin/python 'export.py' '--depth' '18'",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_missing_venv_and_a_venv_without_an_interpreter_are_told_apart() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let venv = std::env::temp_dir().join(format!("boardcast-venv-{}", std::process::id()));
+        let venv_str = venv.display().to_string();
+        assert_eq!(check_venv(OsEnvironment::Windows, &venv_str).await, Err(format!("The virtualenv {} does not exist", venv_str)));
+
+        let interpreter = venv_interpreter(&venv);
+        fs::create_dir_all(interpreter.parent().unwrap()).unwrap();
+        fs::write(&interpreter, "").unwrap();
+        assert_eq!(
+            check_venv(OsEnvironment::Windows, &venv_str).await,
+            Err(format!("The virtualenv {} has no runnable interpreter at {}", venv_str, interpreter.display()))
+        );
+        fs::set_permissions(&interpreter, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(check_venv(OsEnvironment::Windows, &venv_str).await, Ok(()));
+        fs::remove_dir_all(&venv).unwrap();
+    }
+
+    #[test]
+    fn per_call_pipenv_options_are_laid_over_the_settings() {
+        let settings = PipenvOptions::default();
+        assert_eq!(pipenv_options(&settings, None), Ok(settings.clone()));
+        assert_eq!(pipenv_options(&settings, Some(&Value::Null)), Ok(settings.clone()));
+        let merged = pipenv_options(&settings, Some(&serde_json::json!({"quiet": false, "pipfile": "/scripts/torch/Pipfile"}))).unwrap();
+        assert_eq!(
+            merged,
+            PipenvOptions { quiet: false, pipfile: Some("/scripts/torch/Pipfile".to_string()), ..settings.clone() }
+        );
+        assert_eq!(pipenv_options(&settings, Some(&serde_json::json!(true))), Err("pipenv_options must be an object, got true".to_string()));
+        assert!(pipenv_options(&settings, Some(&serde_json::json!({"quiet": "yes"}))).unwrap_err().starts_with("Invalid pipenv_options: "));
+    }
+
+    #[test]
+    fn the_dry_run_shows_the_pipenv_environment_that_was_applied() {
+        let env = pipenv_env(&PipenvOptions::default());
+        assert_eq!(
+            env,
+            [
+                ("PIPENV_IGNORE_VIRTUALENVS".to_string(), "1".to_string()),
+                ("PIPENV_VERBOSITY".to_string(), "-1".to_string()),
+                ("PIPENV_NOSPIN".to_string(), "1".to_string()),
+            ]
+        );
+        let everything_off = PipenvOptions { ignore_virtualenvs: false, quiet: false, no_spinner: false, pipfile: Some(" ".to_string()) };
+        assert_eq!(pipenv_env(&everything_off), []);
+
+        let env = pipenv_env(&PipenvOptions { pipfile: Some("/home/me/Pipfile".to_string()), ..PipenvOptions::default() });
+        let command = script_command(OsEnvironment::Wsl, "/home/me/scripts", &["export.py".to_string()], &[], None, &env);
+        let described = describe_command(&command, &env);
+        assert_eq!(
+            described["env"],
+            serde_json::json!({
+                "PIPENV_IGNORE_VIRTUALENVS": "1",
+                "PIPENV_NOSPIN": "1",
+                "PIPENV_PIPFILE": "/home/me/Pipfile",
+                "PIPENV_VERBOSITY": "-1",
+            })
+        );
+        // WSL doesn't inherit the environment, so the same variables are set on the bash line
+        assert_eq!(
+            described["args"][2],
+            "echo \"__boardcast_pid__ $$\" >&2; cd '/home/me/scripts' && exec env PIPENV_IGNORE_VIRTUALENVS='1' \
+             PIPENV_VERBOSITY='-1' PIPENV_NOSPIN='1' PIPENV_PIPFILE='/home/me/Pipfile' pipenv run python 'export.py'"
+        );
+    }
+
+    fn python(script: &str) -> Command {
+        let mut command = Command::new(if cfg!(windows) { "python" } else { "python3" });
+        command.args(["-c", script]);
+        command
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_stopped_script_that_exits_when_asked_isnt_killed() {
+        let script = "import signal, sys, time\n\
+            def stop(*_):\n    \
+                print('flushed partial results', flush=True)\n    \
+                sys.exit(0)\n\
+            signal.signal(signal.SIGTERM, stop)\n\
+            print('ready', flush=True)\n\
+            time.sleep(30)\n";
+        // Long enough for python to start and install its handler
+        let control = RunControl { timeout: Duration::from_secs(10), ..control() };
+        let cancel = control.cancel.clone();
+        let cancelling = async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            cancel.notify_one();
+        };
+        let (output, ()) = tokio::join!(run_captured(python(script), control), cancelling);
+        let output = output.unwrap();
+
+        assert_eq!(output.stdout, "ready\nflushed partial results\n");
+        let termination = output.termination.unwrap();
+        assert_eq!((termination.reason, termination.graceful), (StopReason::Cancelled, true));
+        assert_eq!(output.code, Some(0));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_script_ignoring_the_polite_signal_is_killed_after_the_grace_period() {
+        let script = "import signal, time\n\
+            signal.signal(signal.SIGTERM, signal.SIG_IGN)\n\
+            print('ready', flush=True)\n\
+            time.sleep(30)\n";
+        let control = RunControl { timeout: Duration::from_secs(1), grace: Duration::from_millis(300), ..control() };
+        let started = Instant::now();
+        let output = run_captured(python(script), control).await.unwrap();
+
+        let termination = output.termination.unwrap();
+        assert_eq!((termination.reason, termination.graceful), (StopReason::Timeout, false));
+        assert_eq!(output.code, None);
+        assert!(!output.success());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    fn expanded(kwargs: Value) -> Result<Vec<String>, String> {
+        expand_kwargs(kwargs.as_object().unwrap())
+    }
+
+    #[test]
+    fn kwargs_expand_to_flags_in_key_order() {
+        assert_eq!(
+            expanded(serde_json::json!({"pgn": "C:\\games\\round 1.pgn", "depth": 18, "verbose": true, "quiet": false, "tag": ["a", 2]})),
+            Ok(vec![
+                "--depth".to_string(), "18".to_string(),
+                "--pgn".to_string(), "C:\\games\\round 1.pgn".to_string(),
+                "--tag".to_string(), "a".to_string(), "--tag".to_string(), "2".to_string(),
+                "--verbose".to_string(),
+            ])
+        );
+        assert_eq!(expanded(serde_json::json!({})), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn kwargs_reject_nulls_nesting_and_unsafe_keys() {
+        assert_eq!(expanded(serde_json::json!({"depth": null})), Err("kwargs depth is null; leave it out or give it a value".to_string()));
+        assert_eq!(
+            expanded(serde_json::json!({"tag": [["a"]]})),
+            Err("kwargs tag must be a string, number, boolean or a list of strings and numbers, got [\"a\"]".to_string())
+        );
+        assert_eq!(
+            expanded(serde_json::json!({"engine": {"depth": 18}})),
+            Err("kwargs engine must be a string, number, boolean or a list of strings and numbers, got {\"depth\":18}".to_string())
+        );
+        for key in ["-depth", "1st", "pgn file", "a&calc", ""] {
+            assert_eq!(
+                expanded(serde_json::json!({ key: 1 })),
+                Err(format!("kwargs key '{}' must start with a letter and contain only letters, digits, _ and -", key))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn a_silent_script_is_stopped_with_the_last_lines_it_printed() {
+        let script = "import time\n\
+            for i in range(12):\n    \
+                print(f'depth {i}', flush=True)\n\
+            time.sleep(30)\n";
+        let control = RunControl { idle_timeout: Some(Duration::from_millis(500)), ..control() };
+        let output = run_captured(python(script), control).await.unwrap();
+
+        let termination = output.termination.unwrap();
+        assert_eq!((termination.reason, termination.graceful), (StopReason::IdleTimeout, true));
+        assert!(termination.silent_ms >= 500, "{}", termination.silent_ms);
+        let message = stop_message(&output);
+        let silent = format!("{:.1}", termination.silent_ms as f64 / 1000.0);
+        assert_eq!(
+            message,
+            format!(
+                "IdleTimeout: the script printed nothing for {}s and exited when asked to stop\nLast lines:\n{}\n",
+                silent,
+                (2..12).map(|i| format!("depth {}", i)).collect::<Vec<_>>().join("\n")
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn an_unfinished_line_still_counts_as_output() {
+        // A progress bar redrawn with \r never ends its line
+        let script = "import sys, time\n\
+            for i in range(8):\n    \
+                sys.stdout.write(f'\\r{i * 10}%')\n    \
+                sys.stdout.flush()\n    \
+                time.sleep(0.15)\n\
+            print()\n";
+        let control = RunControl { idle_timeout: Some(Duration::from_millis(500)), ..control() };
+        let output = run_captured(python(script), control).await.unwrap();
+        assert_eq!(output.termination, None);
+        assert_eq!(output.code, Some(0));
+        assert_eq!(stop_message(&output), "");
+    }
+}
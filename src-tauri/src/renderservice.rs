@@ -0,0 +1,685 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::process;
+use crate::timeouts::Timeout;
+
+// Shipped in the project root, next to remotion/
+const SCRIPT: &str = "render-service.mjs";
+// The service bundles the project and launches Chromium before it says it's ready
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(180);
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+// A render whose service crashed under it is sent to a fresh one this many times
+const CRASH_RETRIES: u32 = 1;
+// The end of stderr is kept for the error when the service dies
+const STDERR_TAIL_LINES: usize = 20;
+
+// One render, as render-service.mjs takes it. `frames` is inclusive, like --frames.
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderRequest {
+    pub composition: String,
+    // The export's props file
+    pub props: String,
+    pub output: String,
+    pub frames: Option<(u64, u64)>,
+    pub concurrency: Option<usize>,
+    pub scale: Option<f64>,
+}
+
+// A line to the service
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request<'a> {
+    Render {
+        id: u64,
+        #[serde(flatten)]
+        render: &'a RenderRequest,
+    },
+    Ping { id: u64 },
+    Cancel { id: u64 },
+    Shutdown,
+}
+
+// A line from the service
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    Ready,
+    Pong { id: u64 },
+    Progress { id: u64, rendered: f64, total: f64 },
+    Done { id: u64 },
+    // Without an id it's about the service itself, e.g. the bundle failing at startup
+    Error { id: Option<u64>, message: String },
+}
+
+impl Message {
+    fn id(&self) -> Option<u64> {
+        match self {
+            Message::Ready => None,
+            Message::Pong { id } | Message::Progress { id, .. } | Message::Done { id } => Some(*id),
+            Message::Error { id, .. } => *id,
+        }
+    }
+}
+
+// What a request waiting on the service hears
+enum Reply {
+    Message(Message),
+    // The service died, and why
+    Exited(String),
+}
+
+#[derive(Default)]
+struct Waiters {
+    replies: HashMap<u64, mpsc::UnboundedSender<Reply>>,
+    // Set once the service is gone, after which nothing more is sent to it
+    exited: Option<String>,
+}
+
+// One running `node render-service.mjs`
+struct Process {
+    root: PathBuf,
+    pid: Option<u32>,
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    waiters: Arc<Mutex<Waiters>>,
+    // Ends when the service does
+    watcher: Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+}
+
+impl Process {
+    fn has_exited(&self) -> bool {
+        self.waiters.lock().unwrap().exited.is_some()
+    }
+
+    // Requests are written whole under the lock, so concurrent renders never interleave their lines
+    async fn send(&self, request: &Request<'_>) -> Result<(), String> {
+        let mut line = serde_json::to_string(request).map_err(|e| e.to_string())?;
+        line.push('\n');
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(line.as_bytes()).await.map_err(|e| format!("Failed to write to the render service: {}", e))?;
+        stdin.flush().await.map_err(|e| format!("Failed to write to the render service: {}", e))
+    }
+
+    fn kill(&self) {
+        if let Some(pid) = self.pid {
+            // Chromium and its helpers are in the service's process group
+            if let Err(e) = process::kill_tree(pid) {
+                log::warn!("Failed to kill the render service ({}): {}", pid, e);
+            }
+        }
+    }
+}
+
+// Stops waiting for a request's replies when the future waiting for them is dropped, e.g. on a
+// timeout or when a sibling render chunk fails, and cancels the render if it's still running
+struct Waiting {
+    process: Arc<Process>,
+    id: u64,
+    cancel: bool,
+}
+
+impl Waiting {
+    fn start(process: &Arc<Process>, id: u64) -> Result<(Self, mpsc::UnboundedReceiver<Reply>), String> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut waiters = process.waiters.lock().unwrap();
+        if let Some(reason) = &waiters.exited {
+            return Err(reason.clone());
+        }
+        waiters.replies.insert(id, sender);
+        Ok((Waiting { process: process.clone(), id, cancel: false }, receiver))
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.process.waiters.lock().unwrap().replies.remove(&self.id);
+        if self.cancel && !self.process.has_exited() {
+            let (process, id) = (self.process.clone(), self.id);
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = process.send(&Request::Cancel { id }).await {
+                    log::warn!("Failed to cancel render {}: {}", id, e);
+                }
+            });
+        }
+    }
+}
+
+// How a render sent to the service ended, when it didn't succeed
+enum Failure {
+    // The service died under it, so a fresh one may manage it
+    Crashed(String),
+    Failed(String),
+}
+
+fn keep(tail: &mut VecDeque<String>, line: String) {
+    log::info!("Render service: {}", line);
+    if tail.len() == STDERR_TAIL_LINES {
+        tail.pop_front();
+    }
+    tail.push_back(line);
+}
+
+fn dispatch(line: &str, waiters: &Mutex<Waiters>, ready: &mut Option<oneshot::Sender<Result<(), String>>>) {
+    let message = match serde_json::from_str::<Message>(line) {
+        Ok(message) => message,
+        Err(_) => {
+            log::info!("Render service: {}", line);
+            return;
+        }
+    };
+    match (message.id(), message) {
+        (None, Message::Error { message, .. }) => {
+            log::warn!("Render service: {}", message);
+            if let Some(ready) = ready.take() {
+                let _ = ready.send(Err(message));
+            }
+        }
+        (None, _) => {
+            if let Some(ready) = ready.take() {
+                let _ = ready.send(Ok(()));
+            }
+        }
+        (Some(id), message) => {
+            // Replies for a request nobody waits for any more, e.g. progress after a cancel, are dropped
+            if let Some(sender) = waiters.lock().unwrap().replies.get(&id) {
+                let _ = sender.send(Reply::Message(message));
+            }
+        }
+    }
+}
+
+// Reads the service's output until it exits, then fails every request still waiting on it
+async fn watch(mut child: Child, waiters: Arc<Mutex<Waiters>>, ready: oneshot::Sender<Result<(), String>>) {
+    let mut ready = Some(ready);
+    let mut tail = VecDeque::new();
+    let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return;
+    };
+    let mut stdout = BufReader::new(stdout).lines();
+    let mut stderr = BufReader::new(stderr).lines();
+    let mut stderr_open = true;
+    loop {
+        tokio::select! {
+            line = stdout.next_line() => match line {
+                Ok(Some(line)) => dispatch(&line, &waiters, &mut ready),
+                _ => break,
+            },
+            line = stderr.next_line(), if stderr_open => match line {
+                Ok(Some(line)) => keep(&mut tail, line),
+                _ => stderr_open = false,
+            },
+        }
+    }
+    // What it printed last usually says why it stopped
+    if stderr_open {
+        let _ = tokio::time::timeout(Duration::from_secs(1), async {
+            while let Ok(Some(line)) = stderr.next_line().await {
+                keep(&mut tail, line);
+            }
+        })
+        .await;
+    }
+    let status = tokio::time::timeout(SHUTDOWN_TIMEOUT, child.wait()).await;
+    let mut reason = match status.ok().and_then(|s| s.ok()).and_then(|s| s.code()) {
+        Some(code) => format!("the render service exited with code {}", code),
+        None => "the render service stopped".to_string(),
+    };
+    if !tail.is_empty() {
+        reason.push_str(&format!(":\n{}", Vec::from(tail).join("\n")));
+    }
+    log::info!("{}", reason);
+
+    let mut waiters = waiters.lock().unwrap();
+    waiters.exited = Some(reason.clone());
+    for (_, sender) in waiters.replies.drain() {
+        let _ = sender.send(Reply::Exited(reason.clone()));
+    }
+    if let Some(ready) = ready.take() {
+        let _ = ready.send(Err(reason));
+    }
+}
+
+// The persistent renderer for the "service" render backend: one Node process, started by the first
+// render that needs it, that keeps the bundle and Chromium between exports. Requests are told apart
+// by id, so the chunks of a parallel render share it. A service that died or stopped answering is
+// replaced by the next render.
+#[derive(Default)]
+pub struct RemotionService {
+    process: tokio::sync::Mutex<Option<Arc<Process>>>,
+    next_id: AtomicU64,
+}
+
+impl RemotionService {
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    async fn start(&self, root: &Path) -> Result<Arc<Process>, String> {
+        let script = root.join(SCRIPT);
+        if !script.is_file() {
+            return Err(format!("The render service script {} is missing", script.display()));
+        }
+        log::info!("Starting the render service in {}", root.display());
+        let mut command = Command::new("node");
+        command
+            .arg(SCRIPT)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        process::new_process_group(&mut command);
+        let mut child = command.spawn().map_err(|e| format!("Failed to start the render service (node {}): {}", SCRIPT, e))?;
+        let stdin = child.stdin.take().ok_or("The render service's stdin was not captured")?;
+
+        let waiters = Arc::new(Mutex::new(Waiters::default()));
+        let (ready_sender, ready) = oneshot::channel();
+        let process = Arc::new(Process {
+            root: root.to_path_buf(),
+            pid: child.id(),
+            stdin: tokio::sync::Mutex::new(stdin),
+            waiters: waiters.clone(),
+            watcher: Mutex::new(None),
+        });
+        let watcher = tauri::async_runtime::spawn(watch(child, waiters, ready_sender));
+        *process.watcher.lock().unwrap() = Some(watcher);
+
+        match tokio::time::timeout(STARTUP_TIMEOUT, ready).await {
+            Ok(Ok(Ok(()))) => {
+                log::info!("The render service is ready");
+                Ok(process)
+            }
+            Ok(Ok(Err(e))) => {
+                process.kill();
+                Err(format!("The render service failed to start: {}", e))
+            }
+            Ok(Err(_)) => Err("The render service failed to start".to_string()),
+            Err(_) => {
+                process.kill();
+                Err(format!("The render service didn't start within {} seconds", STARTUP_TIMEOUT.as_secs()))
+            }
+        }
+    }
+
+    async fn ping(&self, process: &Arc<Process>) -> Result<(), String> {
+        let id = self.next_id();
+        let (_waiting, mut replies) = Waiting::start(process, id)?;
+        process.send(&Request::Ping { id }).await?;
+        match tokio::time::timeout(PING_TIMEOUT, replies.recv()).await {
+            Ok(Some(Reply::Message(Message::Pong { .. }))) => Ok(()),
+            Ok(Some(Reply::Exited(reason))) => Err(reason),
+            Ok(_) => Err("it answered a ping with something else".to_string()),
+            Err(_) => Err(format!("no answer to a ping within {} seconds", PING_TIMEOUT.as_secs())),
+        }
+    }
+
+    // The service for this project root, started or restarted if it isn't running and answering
+    async fn running(&self, root: &Path) -> Result<Arc<Process>, String> {
+        let mut current = self.process.lock().await;
+        if let Some(process) = current.as_ref() {
+            let why = if process.root != root {
+                "the project root changed".to_string()
+            } else if process.has_exited() {
+                "it exited".to_string()
+            } else {
+                match self.ping(process).await {
+                    Ok(()) => return Ok(process.clone()),
+                    Err(e) => format!("it failed a health check: {}", e),
+                }
+            };
+            log::warn!("Restarting the render service, as {}", why);
+            process.kill();
+        }
+        *current = None;
+        let process = self.start(root).await?;
+        *current = Some(process.clone());
+        Ok(process)
+    }
+
+    // Starts the service ahead of a render, so an export can use the CLI when it won't start
+    pub async fn ensure_running(&self, root: &Path) -> Result<(), String> {
+        self.running(root).await.map(|_| ())
+    }
+
+    async fn render_once<F>(&self, process: &Arc<Process>, request: &RenderRequest, on_progress: &mut F) -> Result<(), Failure>
+    where
+        F: FnMut(f64, f64),
+    {
+        let id = self.next_id();
+        let (mut waiting, mut replies) = Waiting::start(process, id).map_err(Failure::Crashed)?;
+        waiting.cancel = true;
+        process.send(&Request::Render { id, render: request }).await.map_err(Failure::Crashed)?;
+        while let Some(reply) = replies.recv().await {
+            match reply {
+                Reply::Message(Message::Progress { rendered, total, .. }) => on_progress(rendered, total),
+                Reply::Message(Message::Done { .. }) => {
+                    waiting.cancel = false;
+                    return Ok(());
+                }
+                Reply::Message(Message::Error { message, .. }) => {
+                    waiting.cancel = false;
+                    return Err(Failure::Failed(message));
+                }
+                Reply::Message(_) => {}
+                Reply::Exited(reason) => return Err(Failure::Crashed(reason)),
+            }
+        }
+        Err(Failure::Crashed("the render service stopped answering".to_string()))
+    }
+
+    // Renders through the service, restarting it when it isn't running. A render the service crashed
+    // during is tried again on a fresh one; one the service reported failed isn't. `limit` covers
+    // every attempt, and a render past it is cancelled in the service.
+    pub async fn render<F>(&self, root: &Path, request: &RenderRequest, limit: &Timeout, mut on_progress: F) -> Result<(), String>
+    where
+        F: FnMut(f64, f64),
+    {
+        let attempts = async {
+            let mut crashes = 0;
+            loop {
+                let process = self.running(root).await?;
+                match self.render_once(&process, request, &mut on_progress).await {
+                    Ok(()) => return Ok(()),
+                    Err(Failure::Crashed(reason)) if crashes < CRASH_RETRIES => {
+                        crashes += 1;
+                        log::warn!("The render service crashed while rendering {}, rendering it again: {}", request.output, reason);
+                    }
+                    Err(Failure::Crashed(reason)) => return Err(format!("Rendering failed, {}", reason)),
+                    Err(Failure::Failed(message)) => return Err(format!("Rendering failed in the render service: {}", message)),
+                }
+            }
+        };
+        match tokio::time::timeout(limit.duration(), attempts).await {
+            Ok(result) => result,
+            Err(_) => Err(format!(
+                "Rendering timed out after {} seconds, {} timeout from {}",
+                limit.secs, limit.source, limit.basis
+            )),
+        }
+    }
+
+    // Run on app exit, so the service closes Chromium instead of leaving it behind
+    pub async fn shutdown(&self) {
+        let Some(process) = self.process.lock().await.take() else {
+            return;
+        };
+        if process.has_exited() {
+            return;
+        }
+        log::info!("Stopping the render service");
+        let watcher = process.watcher.lock().unwrap().take();
+        let stopped = async {
+            process.send(&Request::Shutdown).await?;
+            if let Some(watcher) = watcher {
+                tokio::time::timeout(SHUTDOWN_TIMEOUT, watcher).await.map_err(|_| "it didn't exit in time".to_string())?.map_err(|e| e.to_string())?;
+            }
+            Ok::<(), String>(())
+        };
+        if let Err(e) = stopped.await {
+            log::warn!("Killing the render service, as {}", e);
+            process.kill();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Speaks the protocol of render-service.mjs. What a render does is picked by its props, and
+    // the stub leaves files behind in its folder for what the tests check afterwards.
+    const STUB: &str = r#"
+import fs from 'fs';
+import readline from 'readline';
+
+const send = (message) => process.stdout.write(JSON.stringify(message) + '\n');
+fs.appendFileSync('starts.log', `${process.pid}\n`);
+if (fs.existsSync('broken')) {
+  console.error('webpack exploded');
+  send({type: 'error', id: null, message: 'the bundle failed'});
+  process.exit(1);
+}
+
+function render({id, props, output, frames}) {
+  switch (props) {
+    case 'ok': {
+      // A log line that isn't JSON, and two messages in one write
+      process.stdout.write('Bundling 100%\n');
+      const total = frames[1] - frames[0] + 1;
+      process.stdout.write(JSON.stringify({type: 'progress', id, rendered: 1, total}) + '\n' + JSON.stringify({type: 'progress', id, rendered: total, total}) + '\n');
+      setTimeout(() => {
+        fs.writeFileSync(output, 'video');
+        send({type: 'done', id});
+      }, 50);
+      break;
+    }
+    case 'split': {
+      // Only safe with one render at a time, as the real service never splits a message
+      const done = JSON.stringify({type: 'done', id});
+      process.stdout.write(done.slice(0, 7));
+      setTimeout(() => process.stdout.write(done.slice(7) + '\n'), 50);
+      break;
+    }
+    case 'fail':
+      send({type: 'error', id, message: 'Could not find composition Chess'});
+      break;
+    case 'crash-once':
+      if (!fs.existsSync('crashed')) {
+        fs.writeFileSync('crashed', '');
+        console.error('Target closed');
+        process.exit(3);
+      }
+      send({type: 'done', id});
+      break;
+    case 'crash':
+      console.error('Target closed');
+      process.exit(3);
+    case 'hang':
+      break;
+  }
+}
+
+readline.createInterface({input: process.stdin}).on('line', (line) => {
+  const request = JSON.parse(line);
+  switch (request.type) {
+    case 'render':
+      render(request);
+      break;
+    case 'ping':
+      send({type: 'pong', id: request.id});
+      break;
+    case 'cancel':
+      fs.appendFileSync('cancels.log', `${request.id}\n`);
+      break;
+    case 'shutdown':
+      fs.writeFileSync('shut-down', '');
+      process.exit(0);
+  }
+});
+send({type: 'ready'});
+"#;
+
+    struct Stub(PathBuf);
+
+    impl Stub {
+        // None where there's no node to run it with
+        fn new(name: &str) -> Option<Self> {
+            if std::process::Command::new("node").arg("--version").output().is_err() {
+                return None;
+            }
+            let dir = std::env::temp_dir().join(format!("boardcast-renderservice-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(SCRIPT), STUB).unwrap();
+            Some(Stub(dir))
+        }
+
+        fn lines(&self, name: &str) -> Vec<String> {
+            fs::read_to_string(self.0.join(name)).unwrap_or_default().lines().map(str::to_string).collect()
+        }
+    }
+
+    impl Drop for Stub {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn request(stub: &Stub, props: &str) -> RenderRequest {
+        RenderRequest {
+            composition: "Chess".to_string(),
+            props: props.to_string(),
+            output: stub.0.join(format!("{}.mp4", props)).to_string_lossy().to_string(),
+            frames: Some((0, 59)),
+            concurrency: None,
+            scale: None,
+        }
+    }
+
+    fn limit(secs: u64) -> Timeout {
+        Timeout { secs, source: "explicit", basis: "render_timeout_secs in the export data".to_string() }
+    }
+
+    // On the runtime the app runs the service on, which its watcher and cancels are spawned onto
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tauri::async_runtime::block_on(future)
+    }
+
+    #[test]
+    fn renders_follow_their_own_progress_through_the_framing() {
+        let Some(stub) = Stub::new("framing") else {
+            return;
+        };
+        let service = RemotionService::default();
+        run(async {
+            let (mut first, mut second) = (Vec::new(), Vec::new());
+            let (one, limit) = (request(&stub, "ok"), limit(30));
+            let mut other = request(&stub, "ok");
+            other.output = stub.0.join("other.mp4").to_string_lossy().to_string();
+            other.frames = Some((60, 89));
+            // Two chunks of a parallel render share the service
+            let (a, b) = tokio::join!(
+                service.render(&stub.0, &one, &limit, |done, total| first.push((done, total))),
+                service.render(&stub.0, &other, &limit, |done, total| second.push((done, total))),
+            );
+            assert_eq!((a, b), (Ok(()), Ok(())));
+            assert_eq!(first, [(1.0, 60.0), (60.0, 60.0)]);
+            assert_eq!(second, [(1.0, 30.0), (30.0, 30.0)]);
+            assert!(stub.0.join("ok.mp4").is_file() && stub.0.join("other.mp4").is_file());
+            service.shutdown().await;
+        });
+        assert_eq!(stub.lines("starts.log").len(), 1);
+        assert!(stub.0.join("shut-down").is_file());
+    }
+
+    #[test]
+    fn a_render_the_service_reports_failed_is_not_retried() {
+        let Some(stub) = Stub::new("failed") else {
+            return;
+        };
+        let service = RemotionService::default();
+        let result = run(service.render(&stub.0, &request(&stub, "fail"), &limit(30), |_, _| {}));
+        assert_eq!(result, Err("Rendering failed in the render service: Could not find composition Chess".to_string()));
+        // The service is still up for the next export, even one whose reply comes in two pieces
+        assert_eq!(run(service.render(&stub.0, &request(&stub, "split"), &limit(30), |_, _| {})), Ok(()));
+        run(service.shutdown());
+        assert_eq!(stub.lines("starts.log").len(), 1);
+    }
+
+    #[test]
+    fn a_render_the_service_crashed_during_goes_to_a_fresh_service() {
+        let Some(stub) = Stub::new("crash-once") else {
+            return;
+        };
+        let service = RemotionService::default();
+        assert_eq!(run(service.render(&stub.0, &request(&stub, "crash-once"), &limit(30), |_, _| {})), Ok(()));
+        run(service.shutdown());
+        assert_eq!(stub.lines("starts.log").len(), 2);
+    }
+
+    #[test]
+    fn a_render_that_keeps_crashing_the_service_fails_with_its_last_words() {
+        let Some(stub) = Stub::new("crash") else {
+            return;
+        };
+        let service = RemotionService::default();
+        let result = run(service.render(&stub.0, &request(&stub, "crash"), &limit(30), |_, _| {}));
+        assert_eq!(result, Err("Rendering failed, the render service exited with code 3:\nTarget closed".to_string()));
+        assert_eq!(stub.lines("starts.log").len(), 1 + CRASH_RETRIES as usize);
+    }
+
+    #[test]
+    fn a_render_past_its_timeout_is_cancelled_in_the_service() {
+        let Some(stub) = Stub::new("timeout") else {
+            return;
+        };
+        let service = RemotionService::default();
+        let result = run(service.render(&stub.0, &request(&stub, "hang"), &limit(1), |_, _| {}));
+        assert_eq!(
+            result,
+            Err("Rendering timed out after 1 seconds, explicit timeout from render_timeout_secs in the export data".to_string())
+        );
+        // The cancel is sent in the background
+        run(async {
+            for _ in 0..50 {
+                if !stub.lines("cancels.log").is_empty() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+        assert_eq!(stub.lines("cancels.log"), ["1"]);
+        run(service.shutdown());
+    }
+
+    #[test]
+    fn a_service_that_died_between_renders_is_restarted() {
+        let Some(stub) = Stub::new("restart") else {
+            return;
+        };
+        let service = RemotionService::default();
+        run(async {
+            service.ensure_running(&stub.0).await.unwrap();
+            let process = service.process.lock().await.clone().unwrap();
+            process.kill();
+            for _ in 0..50 {
+                if process.has_exited() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            assert!(process.has_exited());
+            assert_eq!(service.render(&stub.0, &request(&stub, "ok"), &limit(30), |_, _| {}).await, Ok(()));
+            service.shutdown().await;
+        });
+        let starts = stub.lines("starts.log");
+        assert_eq!(starts.len(), 2);
+        assert_ne!(starts[0], starts[1]);
+    }
+
+    #[test]
+    fn a_service_that_fails_to_start_says_why() {
+        let Some(stub) = Stub::new("broken") else {
+            return;
+        };
+        fs::write(stub.0.join("broken"), "").unwrap();
+        let service = RemotionService::default();
+        assert_eq!(
+            run(service.ensure_running(&stub.0)),
+            Err("The render service failed to start: the bundle failed".to_string())
+        );
+        let missing = stub.0.join("elsewhere");
+        let error = run(service.ensure_running(&missing)).unwrap_err();
+        assert!(error.starts_with("The render service script") && error.ends_with("is missing"), "{}", error);
+    }
+}
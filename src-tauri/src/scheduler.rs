@@ -0,0 +1,520 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Manager, State};
+use tokio::sync::Notify;
+
+use crate::pause::{self, PauseState};
+use crate::settings::SettingsState;
+
+// A waiting export gains one priority level per this long, so a watch folder export waits at
+// most ten minutes behind a stream of previews
+const AGING_STEP: Duration = Duration::from_secs(300);
+const MAX_DECISIONS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    // Watch folder exports
+    Batch,
+    Interactive,
+    Preview,
+}
+
+// A waiting export, as the scheduling functions see it
+#[derive(Debug, Clone, Copy)]
+pub struct QueuedJob {
+    pub priority: Priority,
+    pub waited: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RunningJob {
+    pub priority: Priority,
+    pub running_for: Duration,
+    // Not already paused, by the user or for another preview
+    pub pausable: bool,
+}
+
+pub fn effective_priority(priority: Priority, waited: Duration) -> f64 {
+    priority as u8 as f64 + waited.as_secs_f64() / AGING_STEP.as_secs_f64()
+}
+
+// The order the waiting exports would start in: the highest priority once aged first, the
+// longest waiting among equals
+pub fn schedule_order(queue: &[QueuedJob]) -> Vec<usize> {
+    let rank = |job: &QueuedJob| effective_priority(job.priority, job.waited);
+    let mut order: Vec<usize> = (0..queue.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&queue[a], &queue[b]);
+        rank(b).total_cmp(&rank(a)).then(b.waited.cmp(&a.waited))
+    });
+    order
+}
+
+pub fn pick_next(queue: &[QueuedJob]) -> Option<usize> {
+    schedule_order(queue).first().copied()
+}
+
+// The running export a preview may pause for a slot: the least urgent one below it, the most
+// recently started among equals, as it has the least to lose
+pub fn preemption_target(running: &[RunningJob], arriving: Priority) -> Option<usize> {
+    if arriving != Priority::Preview {
+        return None;
+    }
+    running
+        .iter()
+        .enumerate()
+        .filter(|(_, job)| job.pausable && job.priority < arriving)
+        .min_by(|(_, a), (_, b)| a.priority.cmp(&b.priority).then(a.running_for.cmp(&b.running_for)))
+        .map(|(i, _)| i)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchedulingDecision {
+    pub at: u64,
+    pub export_id: String,
+    // "started", "preempted" or "resumed"
+    pub action: &'static str,
+    pub priority: Priority,
+    pub effective_priority: f64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedExport {
+    pub export_id: String,
+    // 1-based, in the order the exports would start if a slot freed now
+    pub position: usize,
+    pub priority: Priority,
+    pub effective_priority: f64,
+    pub waited_secs: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledExport {
+    pub export_id: String,
+    pub priority: Priority,
+    pub running_secs: f64,
+    // The preview this export is paused for
+    pub preempted_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportQueue {
+    pub max_parallel_exports: usize,
+    pub preemption: bool,
+    pub running: Vec<ScheduledExport>,
+    pub waiting: Vec<QueuedExport>,
+    // Newest first
+    pub decisions: Vec<SchedulingDecision>,
+}
+
+struct Waiting {
+    ticket: u64,
+    export_id: String,
+    priority: Priority,
+    since: Instant,
+}
+
+struct Running {
+    ticket: u64,
+    export_id: String,
+    priority: Priority,
+    started: Instant,
+    // The ticket of the preview it's paused for
+    preempted_by: Option<u64>,
+}
+
+#[derive(Default)]
+struct Queue {
+    next_ticket: u64,
+    waiting: Vec<Waiting>,
+    running: Vec<Running>,
+    decisions: VecDeque<SchedulingDecision>,
+}
+
+impl Queue {
+    fn queued_jobs(&self, now: Instant) -> Vec<QueuedJob> {
+        self.waiting.iter().map(|w| QueuedJob { priority: w.priority, waited: now - w.since }).collect()
+    }
+
+    // The ticket the export holds while it waits and runs
+    fn enqueue(&mut self, export_id: &str, priority: Priority, now: Instant) -> u64 {
+        self.next_ticket += 1;
+        let ticket = self.next_ticket;
+        self.waiting.push(Waiting { ticket, export_id: export_id.to_string(), priority, since: now });
+        ticket
+    }
+
+    // The index of the waiting export holding `ticket`, when it's the one to start next
+    fn next_in_line(&self, ticket: u64, now: Instant) -> Option<usize> {
+        pick_next(&self.queued_jobs(now)).filter(|&i| self.waiting[i].ticket == ticket)
+    }
+
+    fn decide(&mut self, export_id: &str, action: &'static str, priority: Priority, effective_priority: f64, reason: String) {
+        log::info!("Export {} {} ({:?} priority): {}", export_id, action, priority, reason);
+        self.decisions.push_back(SchedulingDecision {
+            at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            export_id: export_id.to_string(),
+            action,
+            priority,
+            effective_priority,
+            reason,
+        });
+        if self.decisions.len() > MAX_DECISIONS {
+            self.decisions.pop_front();
+        }
+    }
+
+    // Moves the waiting export at `index` to the running ones
+    fn start(&mut self, index: usize, now: Instant, reason: String) {
+        let job = self.waiting.remove(index);
+        let effective = effective_priority(job.priority, now - job.since);
+        self.decide(&job.export_id, "started", job.priority, effective, reason);
+        self.running.push(Running {
+            ticket: job.ticket,
+            export_id: job.export_id,
+            priority: job.priority,
+            started: now,
+            preempted_by: None,
+        });
+    }
+
+    fn active(&self) -> usize {
+        self.running.iter().filter(|r| r.preempted_by.is_none()).count()
+    }
+
+    // Frees the export's place; the exports that were paused for it are returned to be continued
+    fn release(&mut self, ticket: u64, now: Instant) -> Vec<String> {
+        self.waiting.retain(|w| w.ticket != ticket);
+        self.running.retain(|r| r.ticket != ticket);
+        let mut resumed = Vec::new();
+        for i in 0..self.running.len() {
+            if self.running[i].preempted_by == Some(ticket) {
+                self.running[i].preempted_by = None;
+                let (export_id, priority) = (self.running[i].export_id.clone(), self.running[i].priority);
+                let effective = effective_priority(priority, now - self.running[i].started);
+                self.decide(&export_id, "resumed", priority, effective, "the preview it was paused for finished".to_string());
+                resumed.push(export_id);
+            }
+        }
+        resumed
+    }
+}
+
+// Runs at most max_parallel_exports exports at once and decides which waiting one goes next
+#[derive(Default)]
+pub struct Scheduler {
+    queue: Mutex<Queue>,
+    changed: Notify,
+}
+
+// An export's place in the scheduler, waiting or running; dropping it frees the place
+pub struct Slot {
+    app: AppHandle,
+    ticket: u64,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.app.state::<Scheduler>().release(&self.app, self.ticket);
+    }
+}
+
+impl Scheduler {
+    // Waits until the export may run
+    pub async fn admit(&self, app: &AppHandle, export_id: &str, priority: Priority) -> Slot {
+        let ticket = self.queue.lock().unwrap().enqueue(export_id, priority, Instant::now());
+        let slot = Slot { app: app.clone(), ticket };
+        let mut logged = false;
+        loop {
+            let changed = self.changed.notified();
+            let settings = app.state::<SettingsState>().get();
+            if self.try_start(app, slot.ticket, settings.max_parallel_exports.max(1), settings.preemption) {
+                return slot;
+            }
+            if !logged {
+                log::info!("Export {} is waiting for one of the {} export slots", export_id, settings.max_parallel_exports.max(1));
+                logged = true;
+            }
+            changed.await;
+        }
+    }
+
+    fn try_start(&self, app: &AppHandle, ticket: u64, limit: usize, preemption: bool) -> bool {
+        let pauses = app.state::<PauseState>();
+        let (target, priority) = {
+            let mut queue = self.queue.lock().unwrap();
+            let now = Instant::now();
+            let Some(index) = queue.next_in_line(ticket, now) else {
+                return false;
+            };
+            let active = queue.active();
+            if active < limit {
+                let reason = format!("next in line with {} of {} slots busy", active, limit);
+                queue.start(index, now, reason);
+                return true;
+            }
+            let priority = queue.waiting[index].priority;
+            if !preemption {
+                return false;
+            }
+            let running: Vec<RunningJob> = queue
+                .running
+                .iter()
+                .map(|r| RunningJob {
+                    priority: r.priority,
+                    running_for: now - r.started,
+                    pausable: r.preempted_by.is_none() && !pauses.is_paused(&r.export_id),
+                })
+                .collect();
+            match preemption_target(&running, priority) {
+                Some(target) => (queue.running[target].export_id.clone(), priority),
+                None => return false,
+            }
+        };
+
+        // Outside the lock, as pausing lists the export's processes
+        if let Err(e) = pause::set_paused(app, &target, true) {
+            log::warn!("Failed to pause export {} for a preview: {}", target, e);
+            return false;
+        }
+        let mut queue = self.queue.lock().unwrap();
+        let now = Instant::now();
+        let index = queue.waiting.iter().position(|w| w.ticket == ticket);
+        let running = queue.running.iter().position(|r| r.export_id == target);
+        if let (Some(index), Some(running)) = (index, running) {
+            queue.running[running].preempted_by = Some(ticket);
+            let target_priority = queue.running[running].priority;
+            let effective = effective_priority(priority, now - queue.waiting[index].since);
+            let reason = format!("paused for the preview {}", queue.waiting[index].export_id);
+            queue.decide(&target, "preempted", target_priority, effective, reason);
+            queue.start(index, now, format!("all {} slots busy, paused export {}", limit, target));
+            return true;
+        }
+        // The export finished in the meantime
+        drop(queue);
+        let _ = pause::set_paused(app, &target, false);
+        false
+    }
+
+    fn release(&self, app: &AppHandle, ticket: u64) {
+        let resumed = self.queue.lock().unwrap().release(ticket, Instant::now());
+        for export_id in resumed {
+            if let Err(e) = pause::set_paused(app, &export_id, false) {
+                log::warn!("Failed to continue export {} after a preview: {}", export_id, e);
+            }
+        }
+        self.changed.notify_waiters();
+    }
+
+    // 1-based, while the export waits for a slot
+    pub fn queue_position(&self, export_id: &str) -> Option<usize> {
+        let queue = self.queue.lock().unwrap();
+        let order = schedule_order(&queue.queued_jobs(Instant::now()));
+        order.iter().position(|&i| queue.waiting[i].export_id == export_id).map(|p| p + 1)
+    }
+
+    fn snapshot(&self, max_parallel_exports: usize, preemption: bool) -> ExportQueue {
+        let queue = self.queue.lock().unwrap();
+        let now = Instant::now();
+        let jobs = queue.queued_jobs(now);
+        let ticket_id = |ticket: u64| {
+            let running = queue.running.iter().find(|r| r.ticket == ticket).map(|r| r.export_id.clone());
+            running.unwrap_or_else(|| ticket.to_string())
+        };
+        ExportQueue {
+            max_parallel_exports,
+            preemption,
+            running: queue
+                .running
+                .iter()
+                .map(|r| ScheduledExport {
+                    export_id: r.export_id.clone(),
+                    priority: r.priority,
+                    running_secs: (now - r.started).as_secs_f64(),
+                    preempted_by: r.preempted_by.map(ticket_id),
+                })
+                .collect(),
+            waiting: schedule_order(&jobs)
+                .into_iter()
+                .enumerate()
+                .map(|(position, i)| QueuedExport {
+                    export_id: queue.waiting[i].export_id.clone(),
+                    position: position + 1,
+                    priority: jobs[i].priority,
+                    effective_priority: effective_priority(jobs[i].priority, jobs[i].waited),
+                    waited_secs: jobs[i].waited.as_secs_f64(),
+                })
+                .collect(),
+            decisions: queue.decisions.iter().rev().cloned().collect(),
+        }
+    }
+}
+
+// The running and waiting exports with their priorities, and the scheduler's latest decisions
+#[command]
+pub fn get_export_queue(scheduler: State<'_, Scheduler>, settings: State<'_, SettingsState>) -> ExportQueue {
+    let settings = settings.get();
+    scheduler.snapshot(settings.max_parallel_exports.max(1), settings.preemption)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Priority::{Batch, Interactive, Preview};
+
+    fn queued(priority: Priority, waited_secs: u64) -> QueuedJob {
+        QueuedJob { priority, waited: Duration::from_secs(waited_secs) }
+    }
+
+    fn running(priority: Priority, running_secs: u64, pausable: bool) -> RunningJob {
+        RunningJob { priority, running_for: Duration::from_secs(running_secs), pausable }
+    }
+
+    #[test]
+    fn the_most_urgent_export_goes_first() {
+        assert_eq!(pick_next(&[]), None);
+        assert_eq!(pick_next(&[queued(Batch, 10), queued(Interactive, 5), queued(Preview, 0)]), Some(2));
+        assert_eq!(schedule_order(&[queued(Batch, 10), queued(Preview, 0), queued(Interactive, 5)]), [1, 2, 0]);
+    }
+
+    #[test]
+    fn the_longest_waiting_goes_first_among_equals() {
+        assert_eq!(schedule_order(&[queued(Interactive, 5), queued(Interactive, 50), queued(Interactive, 20)]), [1, 2, 0]);
+    }
+
+    #[test]
+    fn waiting_exports_age_one_level_per_step() {
+        assert_eq!(effective_priority(Batch, Duration::ZERO), 0.0);
+        assert_eq!(effective_priority(Preview, Duration::ZERO), 2.0);
+        assert_eq!(effective_priority(Batch, AGING_STEP / 2), 0.5);
+        assert_eq!(effective_priority(Interactive, AGING_STEP * 3), 4.0);
+
+        // Just short of two steps a batch export still waits behind a new preview, and goes ahead of it just after
+        assert_eq!(pick_next(&[queued(Batch, 599), queued(Preview, 0)]), Some(1));
+        assert_eq!(pick_next(&[queued(Batch, 601), queued(Preview, 0)]), Some(0));
+        assert_eq!(pick_next(&[queued(Batch, 301), queued(Interactive, 0)]), Some(0));
+    }
+
+    #[test]
+    fn a_stream_of_previews_does_not_starve_a_batch_export() {
+        // One slot; a new preview arrives every 30s and each takes 30s
+        let mut waiting = vec![(Batch, 0u64)];
+        let mut started = None;
+        for now in (0..3600).step_by(30) {
+            waiting.push((Preview, now));
+            let queue: Vec<QueuedJob> = waiting.iter().map(|&(p, since)| queued(p, now - since)).collect();
+            let next = pick_next(&queue).unwrap();
+            if waiting.remove(next).0 == Batch {
+                started = Some(now);
+                break;
+            }
+        }
+        let started = started.expect("the batch export never started");
+        assert!(started <= 2 * AGING_STEP.as_secs(), "started after {}s", started);
+    }
+
+    #[test]
+    fn only_a_preview_pauses_a_running_export() {
+        let jobs = [running(Batch, 100, true), running(Interactive, 10, true)];
+        assert_eq!(preemption_target(&jobs, Preview), Some(0));
+        assert_eq!(preemption_target(&jobs, Interactive), None);
+        assert_eq!(preemption_target(&jobs, Batch), None);
+    }
+
+    #[test]
+    fn the_least_urgent_and_most_recently_started_export_is_paused() {
+        let jobs = [running(Interactive, 5, true), running(Batch, 100, true), running(Batch, 20, true)];
+        assert_eq!(preemption_target(&jobs, Preview), Some(2));
+        let jobs = [running(Interactive, 50, true), running(Interactive, 5, true)];
+        assert_eq!(preemption_target(&jobs, Preview), Some(1));
+    }
+
+    #[test]
+    fn previews_and_paused_exports_are_never_paused_for_a_preview() {
+        assert_eq!(preemption_target(&[], Preview), None);
+        assert_eq!(preemption_target(&[running(Preview, 5, true)], Preview), None);
+        let jobs = [running(Batch, 20, false), running(Interactive, 30, true)];
+        assert_eq!(preemption_target(&jobs, Preview), Some(1));
+        assert_eq!(preemption_target(&[running(Batch, 20, false)], Preview), None);
+    }
+
+    #[test]
+    fn started_exports_leave_the_queue_and_paused_ones_free_their_slot() {
+        let mut queue = Queue::default();
+        let now = Instant::now();
+        for (ticket, priority) in [(1, Batch), (2, Preview)] {
+            let export_id = format!("export-{}", ticket);
+            queue.waiting.push(Waiting { ticket, export_id, priority, since: now });
+        }
+        queue.start(1, now, "next in line".to_string());
+        assert_eq!(queue.waiting.iter().map(|w| w.ticket).collect::<Vec<_>>(), [1]);
+        assert_eq!(queue.running.iter().map(|r| r.ticket).collect::<Vec<_>>(), [2]);
+        assert_eq!(queue.active(), 1);
+        queue.running[0].preempted_by = Some(3);
+        assert_eq!(queue.active(), 0);
+
+        let decision = &queue.decisions[0];
+        assert_eq!((decision.export_id.as_str(), decision.action, decision.priority), ("export-2", "started", Preview));
+        assert_eq!(decision.reason, "next in line");
+    }
+
+    // What admit does for each export on one slot, without preemption: it starts once it's next in
+    // line and the slot is free
+    fn start_waiting(queue: &mut Queue, tickets: &[u64], now: Instant) {
+        for &ticket in tickets {
+            if let Some(index) = queue.next_in_line(ticket, now).filter(|_| queue.active() < 1) {
+                queue.start(index, now, "next in line".to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn an_export_admitted_later_with_a_higher_priority_starts_first() {
+        let mut queue = Queue::default();
+        let now = Instant::now();
+        let first = queue.enqueue("batch-1", Batch, now);
+        start_waiting(&mut queue, &[first], now);
+        let second = queue.enqueue("batch-2", Batch, now + Duration::from_secs(1));
+        let preview = queue.enqueue("preview", Preview, now + Duration::from_secs(2));
+        let waiting = [second, preview];
+        start_waiting(&mut queue, &waiting, now + Duration::from_secs(3));
+        assert_eq!(queue.running.iter().map(|r| r.ticket).collect::<Vec<_>>(), [first]);
+        assert_eq!(queue.next_in_line(second, now + Duration::from_secs(3)), None);
+
+        for (done, later) in [(first, 4), (preview, 5)] {
+            assert!(queue.release(done, now + Duration::from_secs(later)).is_empty());
+            start_waiting(&mut queue, &waiting, now + Duration::from_secs(later));
+        }
+        let started: Vec<&str> = queue.decisions.iter().filter(|d| d.action == "started").map(|d| d.export_id.as_str()).collect();
+        assert_eq!(started, ["batch-1", "preview", "batch-2"]);
+        assert!(queue.waiting.is_empty());
+    }
+
+    #[test]
+    fn releasing_a_preview_continues_the_export_paused_for_it() {
+        let mut queue = Queue::default();
+        let now = Instant::now();
+        let batch = queue.enqueue("batch", Batch, now);
+        queue.start(0, now, "next in line".to_string());
+        let preview = queue.enqueue("preview", Preview, now);
+        queue.running[0].preempted_by = Some(preview);
+        queue.start(0, now, "paused export batch".to_string());
+        assert_eq!(queue.release(preview, now), ["batch"]);
+        assert_eq!((queue.active(), queue.running[0].ticket), (1, batch));
+        assert_eq!(queue.decisions.back().map(|d| d.action), Some("resumed"));
+    }
+
+    #[test]
+    fn only_the_latest_decisions_are_kept() {
+        let mut queue = Queue::default();
+        for i in 0..MAX_DECISIONS + 5 {
+            queue.decide(&i.to_string(), "started", Batch, 0.0, String::new());
+        }
+        assert_eq!(queue.decisions.len(), MAX_DECISIONS);
+        assert_eq!(queue.decisions.front().map(|d| d.export_id.as_str()), Some("5"));
+    }
+}
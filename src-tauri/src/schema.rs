@@ -0,0 +1,172 @@
+use serde_json::Value;
+
+// A small JSON Schema subset for checking script output: "type" (a name or a list of names),
+// "required", "properties" for objects and "items" for arrays. Anything else in a schema is ignored.
+const TYPE_NAMES: &[&str] = &["object", "array", "string", "number", "integer", "boolean", "null"];
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn matches_type(name: &str, value: &Value) -> bool {
+    match name {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        "number" => value.is_number(),
+        name => type_name(value) == name,
+    }
+}
+
+fn show(path: &str) -> &str {
+    if path.is_empty() { "(root)" } else { path }
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        errors.push(format!("{}: the schema must be an object", show(path)));
+        return;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let names: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(|n| n.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if let Some(unknown) = names.iter().find(|n| !TYPE_NAMES.contains(n)) {
+            errors.push(format!("{}: the schema has an unknown type '{}'", show(path), unknown));
+            return;
+        }
+        if names.is_empty() {
+            errors.push(format!("{}: the schema's type must be a type name or a list of them", show(path)));
+            return;
+        }
+        if !names.iter().any(|n| matches_type(n, value)) {
+            errors.push(format!("{}: expected {}, got {}", show(path), names.join(" or "), type_name(value)));
+            return;
+        }
+    }
+
+    match value {
+        Value::Object(fields) => {
+            let child = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+            for key in schema.get("required").and_then(|r| r.as_array()).into_iter().flatten().filter_map(|k| k.as_str()) {
+                if !fields.contains_key(key) {
+                    errors.push(format!("{}: missing required key", child(key)));
+                }
+            }
+            if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+                for (key, property) in properties {
+                    if let Some(field) = fields.get(key) {
+                        check(property, field, &child(key), errors);
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Every place `value` breaks `schema`, e.g. "timestamps[3]: expected number, got string"
+pub fn violations(schema: &Value, value: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check(schema, value, "", &mut errors);
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn export_schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["timestamps", "meta"],
+            "properties": {
+                "timestamps": {"type": "array", "items": {"type": "number"}},
+                "meta": {
+                    "type": "object",
+                    "required": ["fps"],
+                    "properties": {"fps": {"type": "integer"}, "title": {"type": ["string", "null"]}},
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn matching_output_has_no_violations() {
+        let output = json!({"timestamps": [1, 2.5], "meta": {"fps": 30, "title": null}, "extra": true});
+        assert_eq!(violations(&export_schema(), &output), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_violation_is_listed_with_its_path() {
+        let output = json!({"timestamps": [1, "2", 3, false], "meta": {"fps": 29.97, "title": 4}});
+        assert_eq!(
+            violations(&export_schema(), &output),
+            [
+                "meta.fps: expected integer, got number",
+                "meta.title: expected string or null, got number",
+                "timestamps[1]: expected number, got string",
+                "timestamps[3]: expected number, got boolean",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_keys_are_reported_at_every_depth() {
+        assert_eq!(
+            violations(&export_schema(), &json!({"meta": {}})),
+            ["timestamps: missing required key", "meta.fps: missing required key"]
+        );
+        assert_eq!(
+            violations(&json!({"items": {"required": ["x"]}}), &json!([{"x": 1}, {}])),
+            ["[1].x: missing required key"]
+        );
+    }
+
+    #[test]
+    fn a_wrong_type_stops_checking_below_it() {
+        assert_eq!(violations(&export_schema(), &json!([1, 2])), ["(root): expected object, got array"]);
+        assert_eq!(
+            violations(&export_schema(), &json!({"timestamps": {"0": "a"}, "meta": {"fps": 30}})),
+            ["timestamps: expected array, got object"]
+        );
+    }
+
+    #[test]
+    fn integers_are_also_numbers() {
+        assert!(violations(&json!({"type": "number"}), &json!(3)).is_empty());
+        assert!(violations(&json!({"type": "integer"}), &json!(u64::MAX)).is_empty());
+        assert!(violations(&json!({"type": "integer"}), &json!(-3)).is_empty());
+        assert_eq!(violations(&json!({"type": "integer"}), &json!(3.5)), ["(root): expected integer, got number"]);
+    }
+
+    #[test]
+    fn broken_schemas_are_reported() {
+        assert_eq!(violations(&json!("number"), &json!(1)), ["(root): the schema must be an object"]);
+        assert_eq!(violations(&json!({"type": "float"}), &json!(1)), ["(root): the schema has an unknown type 'float'"]);
+        assert_eq!(
+            violations(&json!({"type": 3}), &json!(1)),
+            ["(root): the schema's type must be a type name or a list of them"]
+        );
+        assert_eq!(
+            violations(&json!({"properties": {"a": {"items": 1}}}), &json!({"a": [0]})),
+            ["a[0]: the schema must be an object"]
+        );
+    }
+}
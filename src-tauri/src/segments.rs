@@ -0,0 +1,340 @@
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+use crate::escape::concat_entry;
+use crate::ffmpeg::probe_video;
+use crate::hello::{execute_ffmpeg_command, FFmpegResult};
+use crate::paths::path_arg;
+use crate::progress::{ProgressReporter, Stage};
+
+// A failed segment is run once more before the composite gives up; a resumed export reuses every
+// segment an earlier run finished
+const SEGMENT_ATTEMPTS: u32 = 2;
+
+// How far the joined output may be from the single-pass length, in frames
+const DURATION_TOLERANCE_FRAMES: f64 = 1.5;
+
+// One stretch of the output composited as its own ffmpeg job
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Segment {
+    pub index: usize,
+    // Output seconds on the output's frame grid; each segment ends where the next one starts
+    pub start: f64,
+    pub end: f64,
+    // The background is read from half a frame before `start`, so a frame lying exactly on a
+    // boundary goes to one segment only
+    pub lead: f64,
+    // The last segment reads the background to its end rather than for end - start
+    pub last: bool,
+    // The plan's overlay groups whose board is up at some point of the segment
+    pub groups: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SegmentPlan {
+    pub fps: f64,
+    pub duration: f64,
+    pub segments: Vec<Segment>,
+}
+
+// Cuts the output timeline of `duration` seconds at `fps` into segments. `ranges` are the output
+// times each overlay group is on screen; a segment opens where a board appears and where a run
+// of boards ends, so the stretches with no board are segments of their own.
+pub fn plan_segments(ranges: &[[f64; 2]], duration: f64, fps: f64) -> SegmentPlan {
+    let frame = 1.0 / fps;
+    // Starts round down and ends up, so a segment never misses a frame with a board on it
+    let floor = |t: f64| ((t * fps + 1e-6).floor() / fps).clamp(0.0, duration);
+    let ceil = |t: f64| ((t * fps - 1e-6).ceil() / fps).clamp(0.0, duration);
+
+    let mut sorted = ranges.to_vec();
+    sorted.sort_by(|a, b| a[0].total_cmp(&b[0]));
+    let mut cuts = vec![0.0, duration];
+    let mut covered_until = f64::NEG_INFINITY;
+    for (i, range) in sorted.iter().enumerate() {
+        cuts.push(floor(range[0]));
+        covered_until = covered_until.max(range[1]);
+        if sorted.get(i + 1).map_or(true, |next| next[0] > covered_until) {
+            cuts.push(ceil(covered_until));
+        }
+    }
+    cuts.sort_by(f64::total_cmp);
+    cuts.dedup_by(|later, earlier| *later - *earlier < frame / 2.0);
+    // Deduplication may have kept a cut just short of the end instead of the end itself
+    if let Some(end) = cuts.last_mut() {
+        *end = duration;
+    }
+
+    let count = cuts.len().saturating_sub(1);
+    let segments = cuts
+        .windows(2)
+        .enumerate()
+        .map(|(index, cut)| Segment {
+            index,
+            start: cut[0],
+            end: cut[1],
+            lead: if index == 0 { 0.0 } else { frame / 2.0 },
+            last: index + 1 == count,
+            groups: ranges
+                .iter()
+                .enumerate()
+                .filter(|(_, range)| range[0] < cut[1] && range[1] > cut[0])
+                .map(|(group, _)| group)
+                .collect(),
+        })
+        .collect();
+    SegmentPlan { fps, duration, segments }
+}
+
+// A segment's ffmpeg arguments, which write to `partial` until the job succeeds
+pub struct SegmentJob {
+    pub args: Vec<String>,
+    pub path: PathBuf,
+    pub partial: PathBuf,
+}
+
+impl SegmentJob {
+    // The name carries the segment's bounds, so a resumed export can only reuse a segment of the same plan
+    pub fn paths(dir: &Path, segment: &Segment) -> (PathBuf, PathBuf) {
+        let ms = |t: f64| (t * 1000.0).round() as u64;
+        let name = format!("segment-{:04}-{}-{}", segment.index + 1, ms(segment.start), ms(segment.end));
+        (dir.join(format!("{}.mp4", name)), dir.join(format!("{}.partial.mp4", name)))
+    }
+}
+
+pub fn list_path(dir: &Path) -> PathBuf {
+    dir.join("segments.txt")
+}
+
+// How a segmented composite went, for the export's result
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentReport {
+    pub segments: usize,
+    // 1-based; finished by an earlier run of the export and used as they were
+    pub reused: Vec<usize>,
+    // 1-based; failed once and succeeded on the next attempt
+    pub retried: Vec<usize>,
+    pub output_duration: Option<f64>,
+}
+
+fn last_line(text: &str) -> &str {
+    text.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("ffmpeg failed").trim()
+}
+
+// Runs every segment's job, then `concat`, which joins the segments listed in list_path(dir)
+// into `output`. A segment that still fails after its retry fails the composite with its ffmpeg
+// output; the finished segments stay for a resumed export.
+pub async fn composite(
+    app: &AppHandle,
+    plan: &SegmentPlan,
+    jobs: &[SegmentJob],
+    concat: &[String],
+    output: &Path,
+    progress: &ProgressReporter,
+    low_priority: bool,
+) -> Result<(FFmpegResult, SegmentReport), String> {
+    let count = plan.segments.len();
+    let mut report = SegmentReport { segments: count, reused: Vec::new(), retried: Vec::new(), output_duration: None };
+    for (segment, job) in plan.segments.iter().zip(jobs) {
+        let number = segment.index + 1;
+        if job.path.is_file() {
+            log::info!("Segment {}/{} was finished by an earlier run, reusing it", number, count);
+            report.reused.push(number);
+            progress.report_segment(segment, count, "reused");
+            progress.report(Stage::Composite, segment.end, plan.duration);
+            continue;
+        }
+        let length = segment.end - segment.start;
+        let report_segment = |done: f64, _: f64| progress.report(Stage::Composite, segment.start + done.min(length), plan.duration);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            log::info!("Compositing segment {}/{} ({}s to {}s)", number, count, segment.start, segment.end);
+            progress.report_segment(segment, count, if attempt == 1 { "running" } else { "retrying" });
+            let result = execute_ffmpeg_command(app.clone(), &job.args, Some(&report_segment), low_priority).await?;
+            if result.success {
+                fs::rename(&job.partial, &job.path)
+                    .map_err(|e| format!("Failed to move {} into place: {}", job.partial.display(), e))?;
+                if attempt > 1 {
+                    report.retried.push(number);
+                }
+                progress.report_segment(segment, count, "done");
+                break;
+            }
+            let _ = fs::remove_file(&job.partial);
+            if attempt < SEGMENT_ATTEMPTS {
+                log::warn!("Segment {}/{} failed, retrying it: {}", number, count, last_line(&result.error));
+                continue;
+            }
+            progress.report_segment(segment, count, "failed");
+            let error = format!(
+                "Segment {}/{} ({}s to {}s) failed after {} attempts: {}",
+                number, count, segment.start, segment.end, attempt, result.error
+            );
+            return Ok((FFmpegResult { error, ..result }, report));
+        }
+    }
+
+    let list: String = jobs.iter().map(|job| path_arg(&job.path).and_then(|p| concat_entry(&p))).collect::<Result<_, _>>()?;
+    let dir = jobs.first().and_then(|job| job.path.parent()).ok_or("There are no segments to join")?;
+    let list_file = list_path(dir);
+    fs::write(&list_file, list).map_err(|e| format!("Failed to write {}: {}", list_file.display(), e))?;
+    log::info!("Joining {} segments", count);
+    let joined = execute_ffmpeg_command(app.clone(), concat, None, low_priority).await?;
+    if !joined.success {
+        let error = format!("Failed to join the {} segments: {}", count, joined.error);
+        return Ok((FFmpegResult { error, ..joined }, report));
+    }
+
+    // Every segment holds exactly its own frames, so the joined file has to last as long as the single pass would
+    let duration = probe_video(app, output).await?.duration_secs;
+    report.output_duration = duration;
+    let tolerance = DURATION_TOLERANCE_FRAMES / plan.fps;
+    if let Some(duration) = duration.filter(|d| (d - plan.duration).abs() > tolerance) {
+        let error = format!(
+            "The joined segments last {}s but the single-pass composite would last {}s; set composite_strategy to single_pass",
+            duration, plan.duration
+        );
+        return Ok((FFmpegResult { success: false, error, ..joined }, report));
+    }
+    if let Err(e) = fs::remove_dir_all(dir) {
+        log::warn!("Failed to remove the segments in {}: {}", dir.display(), e);
+    }
+    Ok((joined, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(plan: &SegmentPlan) -> Vec<(f64, f64, Vec<usize>)> {
+        plan.segments.iter().map(|s| (s.start, s.end, s.groups.clone())).collect()
+    }
+
+    // The frames ffmpeg reads for a segment: from start - lead for end - start seconds, or to the
+    // end for the last one. A frame right at the end belongs to the next segment.
+    fn frames(segment: &Segment, plan: &SegmentPlan) -> std::ops::Range<u64> {
+        let frame = |t: f64| (t * plan.fps - 1e-6).ceil() as u64;
+        let end = if segment.last { plan.duration } else { segment.end };
+        frame(segment.start - segment.lead)..frame(end - segment.lead)
+    }
+
+    #[test]
+    fn boards_and_the_stretches_between_them_are_segments_of_their_own() {
+        let plan = plan_segments(&[[1.0, 1.5], [2.5, 3.0], [4.0, 4.5]], 7.0, 30.0);
+        assert_eq!(
+            bounds(&plan),
+            [
+                (0.0, 1.0, vec![]),
+                (1.0, 1.5, vec![0]),
+                (1.5, 2.5, vec![]),
+                (2.5, 3.0, vec![1]),
+                (3.0, 4.0, vec![]),
+                (4.0, 4.5, vec![2]),
+                (4.5, 7.0, vec![]),
+            ]
+        );
+        assert_eq!(plan.segments.iter().map(|s| s.index).collect::<Vec<_>>(), (0..7).collect::<Vec<_>>());
+        assert_eq!(plan.segments.iter().filter(|s| s.last).count(), 1);
+        assert!(plan.segments[6].last);
+        assert_eq!(plan.segments[0].lead, 0.0);
+        assert!(plan.segments[1..].iter().all(|s| s.lead == 1.0 / 60.0));
+    }
+
+    #[test]
+    fn overlapping_boards_share_a_segment() {
+        // A crossfade keeps the first board up after the second one appears
+        let plan = plan_segments(&[[1.0, 2.2], [2.0, 3.0], [5.0, 6.0]], 7.0, 30.0);
+        assert_eq!(
+            bounds(&plan),
+            [(0.0, 1.0, vec![]), (1.0, 2.0, vec![0]), (2.0, 3.0, vec![0, 1]), (3.0, 5.0, vec![]), (5.0, 6.0, vec![2]), (6.0, 7.0, vec![])]
+        );
+    }
+
+    #[test]
+    fn boards_off_the_frame_grid_widen_their_segment_to_whole_frames() {
+        let plan = plan_segments(&[[1.01, 1.49]], 3.0, 30.0);
+        let cuts: Vec<f64> = plan.segments.iter().map(|s| s.start).collect();
+        assert_eq!(cuts, [0.0, 1.0, 1.5]);
+        // At 25 fps the same board starts on frame 25 and ends after frame 37
+        let plan = plan_segments(&[[1.01, 1.49]], 3.0, 25.0);
+        assert_eq!(plan.segments[1].start, 1.0);
+        assert_eq!(plan.segments[1].end, 1.52);
+    }
+
+    #[test]
+    fn cuts_closer_than_half_a_frame_are_merged() {
+        // Back to back boards are one run, cut only where each appears
+        let plan = plan_segments(&[[0.01, 1.0], [1.0, 6.99]], 7.0, 30.0);
+        assert_eq!(bounds(&plan), [(0.0, 1.0, vec![0]), (1.0, 7.0, vec![1])]);
+        // A board ending a hundredth before a background that ends off the frame grid
+        let plan = plan_segments(&[[1.0, 7.0]], 7.01, 30.0);
+        assert_eq!(bounds(&plan), [(0.0, 1.0, vec![]), (1.0, 7.01, vec![0])]);
+    }
+
+    #[test]
+    fn a_board_up_to_the_end_or_with_no_boards_at_all_still_covers_the_timeline() {
+        assert_eq!(bounds(&plan_segments(&[], 7.0, 30.0)), [(0.0, 7.0, vec![])]);
+        // A persistent board stays up for good
+        let plan = plan_segments(&[[0.0, 2.0], [3.0, f64::INFINITY]], 7.0, 30.0);
+        assert_eq!(bounds(&plan), [(0.0, 2.0, vec![0]), (2.0, 3.0, vec![]), (3.0, 7.0, vec![1])]);
+    }
+
+    #[test]
+    fn segments_hold_every_frame_of_the_single_pass_exactly_once() {
+        let mut seed = 0x5eed_u64;
+        let mut random = move |low: f64, high: f64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            low + (seed >> 11) as f64 / (1u64 << 53) as f64 * (high - low)
+        };
+        for _ in 0..500 {
+            let fps = [23.976, 24.0, 25.0, 29.97, 30.0, 50.0, 59.94, 60.0][(random(0.0, 8.0) as usize).min(7)];
+            let duration = (random(1.0, 120.0) * fps).round() / fps;
+            let ranges: Vec<[f64; 2]> = (0..random(0.0, 12.0) as usize)
+                .map(|_| {
+                    let start = random(-0.5, duration);
+                    [start, start + random(0.0, 5.0)]
+                })
+                .collect();
+            let plan = plan_segments(&ranges, duration, fps);
+
+            assert_eq!(plan.segments.first().map(|s| s.start), Some(0.0));
+            assert_eq!(plan.segments.last().map(|s| s.end), Some(duration));
+            let mut next_frame = 0;
+            for pair in plan.segments.windows(2) {
+                assert_eq!(pair[0].end, pair[1].start);
+            }
+            for segment in &plan.segments {
+                assert!(segment.end > segment.start, "{:?}", segment);
+                let read = frames(segment, &plan);
+                assert_eq!(read.start, next_frame, "{:?} in {:?}", segment, ranges);
+                next_frame = read.end;
+                // Every board on screen during the segment is drawn in it
+                for (group, range) in ranges.iter().enumerate() {
+                    let shown = read.clone().any(|k| {
+                        let t = k as f64 / fps;
+                        t + 1e-9 >= range[0] && t < range[1]
+                    });
+                    if shown {
+                        assert!(segment.groups.contains(&group), "{:?} misses {:?} of {:?}", segment, range, ranges);
+                    }
+                }
+            }
+            // The joined segments last exactly as long as the single pass
+            assert_eq!(next_frame, (duration * fps).round() as u64, "{} at {} fps", duration, fps);
+            let total: f64 = plan.segments.iter().map(|s| s.end - s.start).sum();
+            assert!((total - duration).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn segment_files_are_named_after_their_bounds() {
+        let plan = plan_segments(&[[1.0, 1.5]], 7.0, 30.0);
+        let (path, partial) = SegmentJob::paths(Path::new("work"), &plan.segments[1]);
+        assert_eq!(path, Path::new("work").join("segment-0002-1000-1500.mp4"));
+        assert_eq!(partial, Path::new("work").join("segment-0002-1000-1500.partial.mp4"));
+        assert_eq!(last_line("frame=1\nConversion failed!\n\n"), "Conversion failed!");
+        assert_eq!(last_line(""), "ffmpeg failed");
+    }
+}
@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::cancel::CancelState;
+use crate::export_data::validate_export_data;
+use crate::ffmpeg::probe_video;
+use crate::hello::{execute_ffmpeg_command, render_chess_animation, RenderOptions};
+use crate::pipeline::composite_animation;
+use crate::pause::PauseState;
+use crate::paths::path_arg;
+use crate::progress::ProgressReporter;
+use crate::warnings::{self, WarningCollector};
+use crate::workdir::{WorkDir, WorkDirs};
+
+const STAGES: &[&str] = &["background", "render", "composite", "verify"];
+// The whole run; a stage still going when it's up fails
+const TIME_LIMIT: Duration = Duration::from_secs(60);
+// testsrc background, with a tone so the audio mix is exercised too
+const BACKGROUND_SECS: f64 = 3.0;
+const BACKGROUND_SIZE: (u32, u32) = (640, 360);
+const FRAME_PER_MOVE: u64 = 15;
+// Cancelled, and reported on, like an export with this id
+const SELF_TEST_ID: &str = "self-test";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfTestStage {
+    pub stage: String,
+    pub passed: bool,
+    pub secs: f64,
+    // Also set on the stages that didn't run because an earlier one failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub finished_at: u64,
+    pub total_secs: f64,
+    pub stages: Vec<SelfTestStage>,
+}
+
+// Two moves from the starting position over the testsrc background, encoded for speed
+fn self_test_payload(background: &Path, output: &Path) -> Result<serde_json::Value, String> {
+    Ok(serde_json::json!({
+        "videoPath": path_arg(background)?,
+        "outputPath": path_arg(output)?,
+        "boardSize": 240,
+        "framePerMove": FRAME_PER_MOVE,
+        "timePerMove": 0.5,
+        "positions": [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2"
+        ],
+        "moves": [null, "e4", "e5"],
+        "evaluations": [
+            { "bestMove": "e2e4", "evaluation": 0.3 },
+            { "bestMove": "e7e5", "evaluation": 0.3 },
+            { "bestMove": "g1f3", "evaluation": 0.3 }
+        ],
+        "timestamps": [0.0, 1.0, 2.0],
+        "x_offset": 0,
+        "y_offset": 0,
+        "encoding": { "video_codec": "libx264", "crf": 35, "preset": "ultrafast" }
+    }))
+}
+
+// Removes the self-test's working directory, also when it is cancelled
+struct Cleanup {
+    workdir: WorkDir,
+}
+
+impl Drop for Cleanup {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(self.workdir.path()) {
+            log::warn!("Failed to remove the self-test directory {}: {}", self.workdir.path().display(), e);
+        }
+    }
+}
+
+// Runs one stage against the run's deadline and records how it went; None when it failed
+async fn stage<T>(stages: &mut Vec<SelfTestStage>, name: &str, deadline: Instant, future: impl Future<Output = Result<T, String>>) -> Option<T> {
+    let started = Instant::now();
+    let outcome = match tokio::time::timeout_at(deadline.into(), future).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(format!("Didn't finish within the self-test's {}s", TIME_LIMIT.as_secs())),
+    };
+    let secs = started.elapsed().as_secs_f64();
+    log::info!("Self-test {}: {:.2}s {:?}", name, secs, outcome.as_ref().err());
+    stages.push(SelfTestStage {
+        stage: name.to_string(),
+        passed: outcome.is_ok(),
+        secs,
+        error: outcome.as_ref().err().cloned(),
+    });
+    outcome.ok()
+}
+
+async fn generate_background(app: &AppHandle, path: &Path) -> Result<(), String> {
+    let (width, height) = BACKGROUND_SIZE;
+    let args: Vec<String> = vec![
+        "-y".into(), "-f".into(), "lavfi".into(),
+        "-i".into(), format!("testsrc=size={}x{}:rate=30:duration={}", width, height, BACKGROUND_SECS),
+        "-f".into(), "lavfi".into(), "-i".into(), format!("sine=frequency=440:duration={}", BACKGROUND_SECS),
+        "-c:v".into(), "libx264".into(), "-preset".into(), "ultrafast".into(), "-pix_fmt".into(), "yuv420p".into(),
+        "-c:a".into(), "aac".into(), "-shortest".into(), path_arg(path)?,
+    ];
+    match execute_ffmpeg_command(app.clone(), &args, None, false).await {
+        Ok(r) if r.success => Ok(()),
+        Ok(r) => Err(r.error.lines().last().unwrap_or("testsrc failed").trim().to_string()),
+        Err(e) => Err(e),
+    }
+}
+
+async fn render(app: &AppHandle, workdir: &WorkDir, payload: &serde_json::Value, animation: &Path, progress: &ProgressReporter) -> Result<(), String> {
+    validate_export_data(payload, usize::MAX).map_err(|e| format!("Invalid self-test payload: {}", e))?;
+    let props_path = workdir.file("export.json");
+    let content = serde_json::to_string_pretty(payload).map_err(|e| e.to_string())?;
+    fs::write(&props_path, content).map_err(|e| format!("Failed to write {}: {}", props_path.display(), e))?;
+    let positions = payload["positions"].as_array().map(|p| p.len()).unwrap_or(0) as u64;
+    let options = RenderOptions { total_frames: positions * FRAME_PER_MOVE, frame_range: None, parallel: 1, scale: None };
+    render_chess_animation(app, &props_path, animation, options, None, progress).await.map(|_| ())
+}
+
+// The output has to be the background's size and length, with its audio
+async fn verify(app: &AppHandle, output: &Path) -> Result<(), String> {
+    let probe = probe_video(app, output).await?;
+    if (probe.width, probe.height) != BACKGROUND_SIZE {
+        return Err(format!("The output is {}x{}, expected {}x{}", probe.width, probe.height, BACKGROUND_SIZE.0, BACKGROUND_SIZE.1));
+    }
+    match probe.duration_secs {
+        Some(duration) if (duration - BACKGROUND_SECS).abs() < 0.5 => {}
+        duration => return Err(format!("The output lasts {:?}s, expected {}s", duration, BACKGROUND_SECS)),
+    }
+    if !probe.has_audio {
+        return Err("The output has no audio".to_string());
+    }
+    Ok(())
+}
+
+async fn self_test(app: &AppHandle) -> Result<SelfTestReport, String> {
+    let started = Instant::now();
+    let deadline = started + TIME_LIMIT;
+    let cleanup = Cleanup { workdir: app.state::<WorkDirs>().allocate(app, SELF_TEST_ID)? };
+    let background = cleanup.workdir.file("background.mp4");
+    let animation = cleanup.workdir.file("chess-animation.mp4");
+    let output = cleanup.workdir.file("output.mp4");
+    let payload = self_test_payload(&background, &output)?;
+    let progress = ProgressReporter::new(app, SELF_TEST_ID, 2);
+
+    let mut stages = Vec::new();
+    let _ = stage(&mut stages, "background", deadline, generate_background(app, &background)).await.is_some()
+        && stage(&mut stages, "render", deadline, render(app, &cleanup.workdir, &payload, &animation, &progress)).await.is_some()
+        && stage(&mut stages, "composite", deadline, async {
+            composite_animation(app, SELF_TEST_ID, &payload, &animation, &progress).await.map_err(|e| e.to_string())
+        }).await.is_some()
+        && stage(&mut stages, "verify", deadline, verify(app, &output)).await.is_some();
+    if let Some(failed) = stages.last().filter(|s| !s.passed).map(|s| s.stage.clone()) {
+        for name in &STAGES[stages.len()..] {
+            stages.push(SelfTestStage {
+                stage: name.to_string(),
+                passed: false,
+                secs: 0.0,
+                error: Some(format!("Not run because the {} stage failed", failed)),
+            });
+        }
+    }
+    drop(cleanup);
+    Ok(SelfTestReport {
+        passed: stages.iter().all(|s| s.passed),
+        finished_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        total_secs: started.elapsed().as_secs_f64(),
+        stages,
+    })
+}
+
+// Runs a tiny export through the whole render and composite chain in the cache directory and
+// reports each stage. Nothing it writes is kept.
+#[command]
+pub async fn run_self_test(app: AppHandle) -> Result<SelfTestReport, String> {
+    let cancels = app.state::<CancelState>();
+    let job = cancels.start(SELF_TEST_ID).ok_or("A self-test is already running")?;
+    log::info!("Starting the pipeline self-test...");
+    // Cancelled like an export: its processes are killed with everything they started, and
+    // dropping the self-test runs the cleanup
+    let self_test = warnings::scope(WarningCollector::new(&app, SELF_TEST_ID), self_test(&app));
+    job.run(self_test).await.unwrap_or_else(|| Err("The self-test was cancelled".to_string()))
+}
+
+#[command]
+pub fn cancel_self_test(cancels: State<'_, CancelState>, pauses: State<'_, PauseState>) -> Result<(), String> {
+    if !cancels.cancel(SELF_TEST_ID, &pauses) {
+        return Err("No self-test is running".to_string());
+    }
+    Ok(())
+}
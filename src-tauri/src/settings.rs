@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::ffmpeg::FfmpegResolver;
+use crate::finish::FinishAction;
+use crate::hook::ExportHook;
+use crate::migrate;
+use crate::outputname::check_template;
+use crate::webhook::WebhookConfig;
+use crate::{WINDOWS_SCRIPT_DIR, WSL_SCRIPT_DIR};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportPreset {
+    pub name: String,
+    #[serde(default)]
+    pub auto_export: bool,
+    // Where exports go; defaults to next to the source video
+    #[serde(default)]
+    pub output_dir: Option<String>,
+    // Export payload (positions, moves, offsets, ...) applied to every video using this preset
+    #[serde(default)]
+    pub data: Value,
+}
+
+impl ExportPreset {
+    // Upgrades the saved payload in memory; one from a newer build is left for the export to reject
+    fn migrate(&mut self) {
+        if !self.data.is_object() {
+            return;
+        }
+        match migrate::migrate(self.data.clone()) {
+            Ok((data, migration)) => {
+                if migration.migrated() {
+                    log::info!("Migrated preset {} from schema version {} to {}", self.name, migration.from, migration.to);
+                }
+                self.data = data;
+            }
+            Err(e) => log::warn!("Preset {}: {}", self.name, e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchFolderConfig {
+    pub path: String,
+    pub preset_name: String,
+}
+
+// Environment knobs for the pipenv that runs the python scripts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipenvOptions {
+    // Use the Pipfile's environment even when the user's shell has another virtualenv active
+    pub ignore_virtualenvs: bool,
+    // Keeps pipenv's own notices out of the script's output
+    pub quiet: bool,
+    pub no_spinner: bool,
+    // Pipfile to use instead of the one found from the script directory
+    pub pipfile: Option<String>,
+}
+
+impl Default for PipenvOptions {
+    fn default() -> Self {
+        PipenvOptions {
+            ignore_virtualenvs: true,
+            quiet: true,
+            no_spinner: true,
+            pipfile: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    // Used when the bundled sidecar is missing, before falling back to PATH
+    pub ffmpeg_path: Option<String>,
+    // Job directories in the cache older than this are removed at startup
+    pub cache_max_age_days: f64,
+    // Directory containing remotion/; found automatically in dev builds
+    pub project_root: Option<String>,
+    pub presets: Vec<ExportPreset>,
+    // Exports with more moves than this are rejected up front
+    pub max_moves: usize,
+    // Number of concurrent Remotion processes, each rendering a slice of the frames
+    pub parallel_render: Option<u32>,
+    pub render_backend: RenderBackend,
+    // Restarted on launch while set
+    pub watch_folder: Option<WatchFolderConfig>,
+    pub pipenv_options: PipenvOptions,
+    // Modules run_python_script may run with exec_mode "module"
+    pub python_modules: Vec<String>,
+    // The python scripts' directory on Windows and inside WSL, as chosen during first-run setup
+    pub script_dir: Option<String>,
+    pub wsl_script_dir: Option<String>,
+    // Folder for sample inputs and outputs in place of sample_exporting/ in the project root
+    pub sample_dir: Option<String>,
+    pub output_retention: OutputRetention,
+    // Names the output of exports that don't give an outputPath, e.g. "{white}_vs_{black}_{date}.mp4"
+    pub output_template: Option<String>,
+    // Exports run at the same time, up to this many; the rest wait in the scheduler's queue
+    pub max_parallel_exports: usize,
+    // A preview that would wait for a slot pauses a running export until it's done
+    pub preemption: bool,
+    // Bounds for the render and ffmpeg timeouts worked out from each export's size
+    pub timeout_min_secs: u64,
+    pub timeout_max_secs: u64,
+    // Run before and after every export that doesn't set its own; see hook.rs
+    pub pre_export_hook: Option<ExportHook>,
+    pub post_export_hook: Option<ExportHook>,
+    // Copies or moves every successful export into a folder
+    pub finish_action: Option<FinishAction>,
+    // Told about every export that finishes or fails
+    pub webhook: Option<WebhookConfig>,
+}
+
+// How the animation is rendered. The service keeps Remotion's bundle and browser between exports,
+// which short renders spend most of their time on with the CLI; see renderservice.rs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderBackend {
+    // `npx remotion render` per export
+    #[default]
+    Cli,
+    Service,
+}
+
+// How many of the outputs boardcast named itself (previews, watch folder exports) prune_outputs keeps.
+// Outputs at paths the user chose are never deleted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputRetention {
+    pub keep_last_n: Option<usize>,
+    pub max_total_gb: Option<f64>,
+}
+
+impl AppSettings {
+    pub fn preset(&self, name: &str) -> Option<&ExportPreset> {
+        self.presets.iter().find(|p| p.name == name)
+    }
+
+    pub fn script_dir(&self) -> String {
+        self.script_dir.clone().unwrap_or_else(|| WINDOWS_SCRIPT_DIR.to_string())
+    }
+
+    pub fn wsl_script_dir(&self) -> String {
+        self.wsl_script_dir.clone().unwrap_or_else(|| WSL_SCRIPT_DIR.to_string())
+    }
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            ffmpeg_path: None,
+            cache_max_age_days: 7.0,
+            project_root: None,
+            presets: Vec::new(),
+            max_moves: 500,
+            parallel_render: None,
+            render_backend: RenderBackend::default(),
+            watch_folder: None,
+            pipenv_options: PipenvOptions::default(),
+            python_modules: Vec::new(),
+            script_dir: None,
+            wsl_script_dir: None,
+            sample_dir: None,
+            output_retention: OutputRetention::default(),
+            output_template: None,
+            max_parallel_exports: 1,
+            preemption: false,
+            timeout_min_secs: 60,
+            timeout_max_secs: 4 * 60 * 60,
+            pre_export_hook: None,
+            post_export_hook: None,
+            finish_action: None,
+            webhook: None,
+        }
+    }
+}
+
+pub struct SettingsState {
+    path: Option<PathBuf>,
+    settings: Mutex<AppSettings>,
+}
+
+impl SettingsState {
+    pub fn load(app: &AppHandle) -> Self {
+        let path = app.path().app_config_dir().ok().map(|dir| dir.join("settings.json"));
+
+        let mut settings = match &path {
+            Some(path) if path.exists() => fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| serde_json::from_str(&content).map_err(|e| e.to_string()))
+                .unwrap_or_else(|e| {
+                    log::warn!("Failed to load settings from {}, using defaults: {}", path.display(), e);
+                    AppSettings::default()
+                }),
+            _ => AppSettings::default(),
+        };
+        for preset in &mut settings.presets {
+            preset.migrate();
+        }
+
+        SettingsState {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> AppSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn update<F: FnOnce(&mut AppSettings)>(&self, change: F) -> Result<(), String> {
+        let mut settings = self.get();
+        change(&mut settings);
+        self.save(settings)
+    }
+
+    fn save(&self, settings: AppSettings) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("Failed to resolve the app config directory")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        let content = serde_json::to_string_pretty(&settings)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        fs::write(path, content)
+            .map_err(|e| format!("Failed to write settings to {}: {}", path.display(), e))?;
+
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+}
+
+#[command]
+pub fn get_settings(state: State<'_, SettingsState>) -> AppSettings {
+    state.get()
+}
+
+#[command]
+pub fn update_settings(
+    state: State<'_, SettingsState>,
+    resolver: State<'_, FfmpegResolver>,
+    settings: AppSettings,
+) -> Result<AppSettings, String> {
+    if let Some(template) = &settings.output_template {
+        check_template(template)?;
+    }
+    if let Some(hook) = &settings.pre_export_hook {
+        hook.check("pre_export_hook")?;
+    }
+    if let Some(hook) = &settings.post_export_hook {
+        hook.check("post_export_hook")?;
+    }
+    if let Some(webhook) = &settings.webhook {
+        webhook.check()?;
+    }
+    if settings.timeout_min_secs == 0 || settings.timeout_max_secs < settings.timeout_min_secs {
+        return Err(format!(
+            "timeout_min_secs must be at least 1 and at most timeout_max_secs, got {} and {}",
+            settings.timeout_min_secs, settings.timeout_max_secs
+        ));
+    }
+    let ffmpeg_changed = state.get().ffmpeg_path != settings.ffmpeg_path;
+    state.save(settings)?;
+
+    if ffmpeg_changed {
+        resolver.invalidate();
+    }
+    Ok(state.get())
+}
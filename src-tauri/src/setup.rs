@@ -0,0 +1,338 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tauri::{command, AppHandle, Manager};
+use tokio::process::Command;
+
+use crate::diagnostics::{command_output, shell};
+use crate::paths::ProjectPaths;
+use crate::settings::SettingsState;
+use crate::WINDOWS_SCRIPT_DIR;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SetupStatus {
+    AlreadySetUp,
+    Created,
+    Done,
+    NeedsAttention,
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SetupItem {
+    pub name: String,
+    pub status: SetupStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SetupReport {
+    // Nothing needs the user's attention
+    pub ready: bool,
+    pub items: Vec<SetupItem>,
+}
+
+// Locations default to the saved settings, then to folders under the app data directory
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SetupChoices {
+    pub sample_dir: Option<String>,
+    pub script_dir: Option<String>,
+    pub project_root: Option<String>,
+    // Runs pipenv install in the script directory when its virtualenv doesn't exist yet
+    pub install_python_deps: bool,
+    // Runs npm install in the project root when the Remotion packages aren't there yet
+    pub prepare_remotion: bool,
+}
+
+fn item(name: &str, status: SetupStatus, detail: String) -> SetupItem {
+    SetupItem { name: name.to_string(), status, detail }
+}
+
+fn chosen(choice: &Option<String>) -> Option<String> {
+    choice.as_deref().map(str::trim).filter(|c| !c.is_empty()).map(String::from)
+}
+
+// Creates `dir` if needed; AlreadySetUp only when it exists and is the saved setting
+fn ensure_dir(name: &str, dir: &Path, saved: bool) -> SetupItem {
+    if dir.is_dir() {
+        let status = if saved { SetupStatus::AlreadySetUp } else { SetupStatus::Done };
+        return item(name, status, dir.display().to_string());
+    }
+    match fs::create_dir_all(dir) {
+        Ok(_) => item(name, SetupStatus::Created, dir.display().to_string()),
+        Err(e) => item(name, SetupStatus::NeedsAttention, format!("Failed to create {}: {}", dir.display(), e)),
+    }
+}
+
+fn default_dir(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(name))
+        .map_err(|e| format!("Failed to resolve the app data directory: {}", e))
+}
+
+// `run` runs a command to its output, command_output outside the tests
+async fn python_items<F, Fut>(script_dir: &Path, install: bool, run: F) -> Vec<SetupItem>
+where
+    F: Fn(Command) -> Fut,
+    Fut: Future<Output = Result<String, String>>,
+{
+    let mut pipenv = Command::new("pipenv");
+    pipenv.arg("--version");
+    let version = match run(pipenv).await {
+        Ok(version) => version,
+        Err(e) => {
+            return vec![item(
+                "pipenv",
+                SetupStatus::NeedsAttention,
+                format!("pipenv isn't available ({}); install it with `pip install pipenv`", e),
+            )];
+        }
+    };
+    let mut items = vec![item("pipenv", SetupStatus::AlreadySetUp, version)];
+
+    if !script_dir.join("Pipfile").is_file() {
+        items.push(item(
+            "python_dependencies",
+            SetupStatus::NeedsAttention,
+            format!("{} has no Pipfile; copy the py-util scripts there", script_dir.display()),
+        ));
+        return items;
+    }
+    let mut venv = Command::new("pipenv");
+    venv.arg("--venv").current_dir(script_dir);
+    if let Ok(path) = run(venv).await {
+        items.push(item("python_dependencies", SetupStatus::AlreadySetUp, path));
+        return items;
+    }
+    if !install {
+        items.push(item(
+            "python_dependencies",
+            SetupStatus::NeedsAttention,
+            "The virtualenv hasn't been created; run setup with installPythonDeps".to_string(),
+        ));
+        return items;
+    }
+    log::info!("Running pipenv install in {}...", script_dir.display());
+    let mut install = Command::new("pipenv");
+    install.arg("install").current_dir(script_dir);
+    items.push(match run(install).await {
+        Ok(_) => item("python_dependencies", SetupStatus::Done, "pipenv install finished".to_string()),
+        Err(e) => item("python_dependencies", SetupStatus::NeedsAttention, format!("pipenv install failed: {}", e)),
+    });
+    items
+}
+
+async fn render_items<F, Fut>(project: Option<&ProjectPaths>, prepare: bool, run: F) -> Vec<SetupItem>
+where
+    F: Fn(Command) -> Fut,
+    Fut: Future<Output = Result<String, String>>,
+{
+    let mut items = vec![match run(shell("node --version")).await {
+        Ok(version) => item("node", SetupStatus::AlreadySetUp, version),
+        Err(e) => item("node", SetupStatus::NeedsAttention, format!("Node.js isn't available ({}); install it from nodejs.org", e)),
+    }];
+
+    let Some(project) = project else {
+        items.push(item("remotion_dependencies", SetupStatus::Skipped, "No project root to install into".to_string()));
+        return items;
+    };
+    if project.root.join("node_modules").join("@remotion").is_dir() {
+        items.push(item("remotion_dependencies", SetupStatus::AlreadySetUp, project.root.display().to_string()));
+        return items;
+    }
+    if !prepare {
+        items.push(item(
+            "remotion_dependencies",
+            SetupStatus::NeedsAttention,
+            "The Remotion packages aren't installed; run setup with prepareRemotion".to_string(),
+        ));
+        return items;
+    }
+    log::info!("Running npm install in {}...", project.root.display());
+    let mut install = shell("npm install");
+    install.current_dir(&project.root);
+    items.push(match run(install).await {
+        Ok(_) => item("remotion_dependencies", SetupStatus::Done, "npm install finished".to_string()),
+        Err(e) => item("remotion_dependencies", SetupStatus::NeedsAttention, format!("npm install failed: {}", e)),
+    });
+    items
+}
+
+// Safe to run again: what is already in place is reported as already set up and left alone
+#[command]
+pub async fn run_first_time_setup(app: AppHandle, choices: Option<SetupChoices>) -> Result<SetupReport, String> {
+    let choices = choices.unwrap_or_default();
+    let state = app.state::<SettingsState>();
+    let settings = state.get();
+    let mut items = Vec::new();
+
+    let sample_dir = match chosen(&choices.sample_dir).or(settings.sample_dir.clone()) {
+        Some(dir) => PathBuf::from(dir),
+        None => default_dir(&app, "sample_exporting")?,
+    };
+    let sample_saved = settings.sample_dir.as_deref() == Some(&*sample_dir.to_string_lossy());
+    items.push(ensure_dir("sample_dir", &sample_dir, sample_saved));
+
+    // The built-in script directory only exists on the machine it was written for
+    let script_dir = match chosen(&choices.script_dir).or(settings.script_dir.clone()) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(target_os = "windows") && Path::new(WINDOWS_SCRIPT_DIR).is_dir() => PathBuf::from(WINDOWS_SCRIPT_DIR),
+        None => default_dir(&app, "py-util")?,
+    };
+    let script_saved = settings.script_dir.as_deref() == Some(&*script_dir.to_string_lossy());
+    items.push(ensure_dir("script_dir", &script_dir, script_saved));
+
+    // Only locations that exist are saved
+    let sample_ok = items[0].status != SetupStatus::NeedsAttention;
+    let script_ok = items[1].status != SetupStatus::NeedsAttention;
+    if (sample_ok && !sample_saved) || (script_ok && !script_saved) {
+        state.update(|s| {
+            if sample_ok {
+                s.sample_dir = Some(sample_dir.to_string_lossy().to_string());
+            }
+            if script_ok {
+                s.script_dir = Some(script_dir.to_string_lossy().to_string());
+            }
+        })?;
+    }
+
+    let mut project_item = None;
+    let mut project_changed = false;
+    if let Some(root) = chosen(&choices.project_root) {
+        if !Path::new(&root).join("remotion").is_dir() {
+            project_item = Some(item("project_root", SetupStatus::NeedsAttention, format!("{} has no remotion/ folder", root)));
+        } else if settings.project_root.as_deref() != Some(root.as_str()) {
+            state.update(|s| s.project_root = Some(root.clone()))?;
+            project_changed = true;
+        }
+    }
+    let project = ProjectPaths::resolve(&app);
+    items.push(project_item.unwrap_or_else(|| match &project {
+        Ok(project) => {
+            let status = if project_changed { SetupStatus::Done } else { SetupStatus::AlreadySetUp };
+            item("project_root", status, project.root.display().to_string())
+        }
+        Err(e) => item("project_root", SetupStatus::NeedsAttention, e.clone()),
+    }));
+
+    items.extend(python_items(&script_dir, choices.install_python_deps, command_output).await);
+    items.extend(render_items(project.as_ref().ok(), choices.prepare_remotion, command_output).await);
+
+    for entry in &items {
+        log::info!("Setup {}: {:?} {}", entry.name, entry.status, entry.detail);
+    }
+    Ok(SetupReport {
+        ready: items.iter().all(|i| i.status != SetupStatus::NeedsAttention),
+        items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("boardcast-setup-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // The command line as the fake machine sees it, e.g. "pipenv --venv" or "sh -c npm install"
+    fn command_line(command: &Command) -> String {
+        let command = command.as_std();
+        let mut line = vec![command.get_program().to_string_lossy().to_string()];
+        line.extend(command.get_args().map(|a| a.to_string_lossy().to_string()));
+        line.join(" ")
+    }
+
+    fn statuses(items: &[SetupItem]) -> Vec<(&str, SetupStatus)> {
+        items.iter().map(|i| (i.name.as_str(), i.status)).collect()
+    }
+
+    #[test]
+    fn a_directory_is_created_once_and_then_already_set_up() {
+        let root = scratch_dir("dirs");
+        let dir = root.join("sample_exporting");
+        assert_eq!(ensure_dir("sample_dir", &dir, false).status, SetupStatus::Created);
+        // There but not saved yet, then saved
+        assert_eq!(ensure_dir("sample_dir", &dir, false).status, SetupStatus::Done);
+        assert_eq!(ensure_dir("sample_dir", &dir, true).status, SetupStatus::AlreadySetUp);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_second_run_reports_the_installs_already_set_up_without_redoing_them() {
+        let root = scratch_dir("installs");
+        let script_dir = root.join("py-util");
+        fs::create_dir_all(&script_dir).unwrap();
+        fs::write(script_dir.join("Pipfile"), "[packages]\n").unwrap();
+        let project = ProjectPaths { root: root.join("project"), sample_dir: None };
+
+        let ran = RefCell::new(Vec::new());
+        let venv_created = Cell::new(false);
+        // A machine with pipenv and node, where each install leaves what it installed behind
+        let run = |command: Command| {
+            let line = command_line(&command);
+            ran.borrow_mut().push(line.clone());
+            let output = if line.ends_with("pipenv install") {
+                venv_created.set(true);
+                Ok(String::new())
+            } else if line.ends_with("npm install") {
+                fs::create_dir_all(project.root.join("node_modules").join("@remotion")).unwrap();
+                Ok(String::new())
+            } else if line.ends_with("--venv") {
+                if venv_created.get() { Ok("/venvs/py-util".to_string()) } else { Err("No virtualenv has been created".to_string()) }
+            } else {
+                Ok("1.0".to_string())
+            };
+            async move { output }
+        };
+
+        let first = python_items(&script_dir, true, &run).await;
+        assert_eq!(statuses(&first), [("pipenv", SetupStatus::AlreadySetUp), ("python_dependencies", SetupStatus::Done)]);
+        let first = render_items(Some(&project), true, &run).await;
+        assert_eq!(statuses(&first), [("node", SetupStatus::AlreadySetUp), ("remotion_dependencies", SetupStatus::Done)]);
+        let installs = |ran: &[String]| ran.iter().filter(|line| line.ends_with(" install")).count();
+        assert_eq!(installs(&ran.borrow()), 2);
+
+        let second = python_items(&script_dir, true, &run).await;
+        assert_eq!(statuses(&second), [("pipenv", SetupStatus::AlreadySetUp), ("python_dependencies", SetupStatus::AlreadySetUp)]);
+        assert_eq!(second[1].detail, "/venvs/py-util");
+        let second = render_items(Some(&project), true, &run).await;
+        assert_eq!(statuses(&second), [("node", SetupStatus::AlreadySetUp), ("remotion_dependencies", SetupStatus::AlreadySetUp)]);
+        assert_eq!(installs(&ran.borrow()), 2);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn nothing_is_installed_unless_asked_for() {
+        let root = scratch_dir("not-asked");
+        fs::write(root.join("Pipfile"), "").unwrap();
+        let ran = RefCell::new(Vec::new());
+        let run = |command: Command| {
+            let line = command_line(&command);
+            ran.borrow_mut().push(line.clone());
+            async move { if line.ends_with("--venv") { Err("No virtualenv".to_string()) } else { Ok("1.0".to_string()) } }
+        };
+        let project = ProjectPaths { root: root.clone(), sample_dir: None };
+        let mut items = python_items(&root, false, &run).await;
+        items.extend(render_items(Some(&project), false, &run).await);
+        assert_eq!(
+            statuses(&items),
+            [
+                ("pipenv", SetupStatus::AlreadySetUp),
+                ("python_dependencies", SetupStatus::NeedsAttention),
+                ("node", SetupStatus::AlreadySetUp),
+                ("remotion_dependencies", SetupStatus::NeedsAttention),
+            ]
+        );
+        assert!(!ran.borrow().iter().any(|line| line.ends_with(" install")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use tauri::AppHandle;
+
+use crate::chapters;
+use crate::estimate::CONTAINER_OVERHEAD;
+use crate::export_data::ResourceLimits;
+use crate::ffmpeg::probe_audio;
+use crate::hello::execute_ffmpeg_command;
+use crate::paths::path_arg;
+use crate::presets::ResolvedEncoding;
+use crate::progress::ProgressReporter;
+
+// Below this the board becomes unreadable mush; a smaller resolution is the better trade
+pub const MIN_VIDEO_KBPS: f64 = 200.0;
+
+// Nothing we export benefits from more than this, however generous the target
+const MAX_VIDEO_KBPS: f64 = 50_000.0;
+
+// Overshoot allowed before the encode is repeated at a lower bitrate
+const OVERSHOOT_TOLERANCE: f64 = 0.02;
+
+const DEFAULT_AUDIO_KBPS: u32 = 128;
+
+// Upload limits are counted in MiB
+fn target_bytes(target_mb: f64) -> f64 {
+    target_mb * 1024.0 * 1024.0
+}
+
+// Video bitrate that fills the target once audio and muxing overhead are taken out
+pub fn video_bitrate_kbps(target_mb: f64, duration_secs: f64, audio_kbps: u32) -> Result<f64, String> {
+    if duration_secs <= 0.0 {
+        return Err("The output duration is needed to target a file size".to_string());
+    }
+    let total_kbps = target_bytes(target_mb) * 8.0 / (1.0 + CONTAINER_OVERHEAD) / duration_secs / 1000.0;
+    let video_kbps = total_kbps - f64::from(audio_kbps);
+    if video_kbps < MIN_VIDEO_KBPS {
+        return Err(format!(
+            "{} MB for {:.1}s leaves {:.0} kb/s for video, below the {} kb/s minimum; lower the resolution or export a shorter range instead",
+            target_mb, duration_secs, video_kbps.max(0.0), MIN_VIDEO_KBPS
+        ));
+    }
+    Ok(video_kbps.min(MAX_VIDEO_KBPS).round())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SizeTargetResult {
+    pub target_mb: f64,
+    pub video_bitrate_kbps: f64,
+    pub audio_bitrate_kbps: u32,
+    pub size_bytes: u64,
+    // The encode was repeated because the first one overshot
+    pub reencoded: bool,
+    pub within_target: bool,
+}
+
+// Two-pass rate control only exists for the software encoders
+fn two_pass_codec(requested: Option<&str>, output: &str) -> &'static str {
+    let webm = output.to_lowercase().ends_with(".webm");
+    match requested {
+        _ if webm => "libvpx-vp9",
+        Some(codec) if codec.contains("vpx") || codec.contains("vp9") => "libvpx-vp9",
+        Some(codec) if codec != "libx264" => {
+            log::warn!("{} can't do a two-pass size-targeted encode, using libx264", codec);
+            "libx264"
+        }
+        _ => "libx264",
+    }
+}
+
+// One size-targeted encode of the composite; only the bitrate changes between attempts
+pub struct TwoPassJob<'a> {
+    pub source: &'a Path,
+    pub output: &'a str,
+    pub encoding: &'a ResolvedEncoding,
+    pub limits: Option<ResourceLimits>,
+}
+
+impl TwoPassJob<'_> {
+    fn pass_args(&self, codec: &str, video_kbps: f64, pass: u32, audio_kbps: Option<u32>, passlog: &Path) -> Result<Vec<String>, String> {
+        let mut args: Vec<String> = vec![
+            "-i".to_string(), path_arg(self.source)?, "-map".to_string(), "0:v".to_string(),
+        ];
+        if pass == 2 && audio_kbps.is_some() {
+            args.extend(["-map".to_string(), "0:a?".to_string()]);
+        }
+        args.extend(["-c:v".to_string(), codec.to_string(), "-b:v".to_string(), format!("{}k", video_kbps)]);
+        if let Some(preset) = self.encoding.settings.preset.as_ref().filter(|_| codec == "libx264") {
+            args.extend(["-preset".to_string(), preset.clone()]);
+        }
+        args.extend([
+            "-pass".to_string(), pass.to_string(),
+            "-passlogfile".to_string(), path_arg(passlog)?,
+        ]);
+        match (pass, audio_kbps) {
+            (2, Some(kbps)) => {
+                let audio_codec = if codec == "libvpx-vp9" { "libopus" } else { "aac" };
+                args.extend(["-c:a".to_string(), audio_codec.to_string(), "-b:a".to_string(), format!("{}k", kbps)]);
+            }
+            (2, None) => args.push("-an".to_string()),
+            // The first pass only gathers statistics
+            _ => {
+                args.extend(["-an".to_string(), "-f".to_string(), "null".to_string(), "-".to_string()]);
+                return Ok(args);
+            }
+        }
+        // The mezzanine always carries chapters, even when the final container can't
+        if !chapters::supports_chapters(self.output) {
+            args.extend(["-map_chapters".to_string(), "-1".to_string()]);
+        }
+        if self.encoding.settings.faststart == Some(true) && codec == "libx264" {
+            args.extend(["-movflags".to_string(), "+faststart".to_string()]);
+        }
+        args.extend(["-y".to_string(), self.output.to_string()]);
+        Ok(args)
+    }
+
+    async fn encode(
+        &self,
+        app: &AppHandle,
+        codec: &str,
+        video_kbps: f64,
+        audio_kbps: Option<u32>,
+        progress: &ProgressReporter,
+    ) -> Result<u64, String> {
+        let workdir = self.source.parent().ok_or("The composite has no parent directory")?;
+        let passlog = workdir.join("twopass");
+        let low_priority = self.limits.map(|l| l.low_priority).unwrap_or(false);
+        for pass in [1, 2] {
+            log::info!("Size-targeted encode, pass {} at {} kb/s", pass, video_kbps);
+            let args = self.pass_args(codec, video_kbps, pass, audio_kbps, &passlog)?;
+            // Each pass is half of the encode
+            let report = |done: f64, total: f64| {
+                progress.report_output(0, self.output, done + total * f64::from(pass - 1), total * 2.0)
+            };
+            let result = execute_ffmpeg_command(app.clone(), &args, Some(&report), low_priority).await?;
+            if !result.success {
+                return Err(format!("Pass {} failed: {}\nReturn code: {:?}", pass, result.error, result.return_code));
+            }
+        }
+        if let Ok(entries) = fs::read_dir(workdir) {
+            for entry in entries.flatten().filter(|e| e.file_name().to_string_lossy().starts_with("twopass")) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        fs::metadata(self.output)
+            .map(|m| m.len())
+            .map_err(|e| format!("Failed to read the size of {}: {}", self.output, e))
+    }
+}
+
+fn within_target(size: u64, target_mb: f64) -> bool {
+    size as f64 <= target_bytes(target_mb) * (1.0 + OVERSHOOT_TOLERANCE)
+}
+
+// The bitrate to encode again at after an overshoot, scaled down by how far it went over
+fn reduced_bitrate(video_kbps: f64, target_mb: f64, size: u64) -> Result<f64, String> {
+    let reduced = (video_kbps * target_bytes(target_mb) / size as f64).round();
+    if reduced < MIN_VIDEO_KBPS {
+        return Err(format!(
+            "Fitting {} MB needs under {} kb/s of video; lower the resolution instead",
+            target_mb, MIN_VIDEO_KBPS
+        ));
+    }
+    Ok(reduced)
+}
+
+// Encodes the composite to fit `target_mb`, repeating once at a proportionally lower bitrate if it overshoots
+pub async fn encode_to_size(
+    app: &AppHandle,
+    job: &TwoPassJob<'_>,
+    target_mb: f64,
+    duration_secs: f64,
+    progress: &ProgressReporter,
+) -> Result<SizeTargetResult, String> {
+    let has_audio = probe_audio(app, job.source).await?.stream.is_some();
+    let audio_kbps = has_audio.then(|| job.encoding.settings.audio_bitrate_kbps.unwrap_or(DEFAULT_AUDIO_KBPS));
+    let mut video_kbps = video_bitrate_kbps(target_mb, duration_secs, audio_kbps.unwrap_or(0))?;
+    let codec = two_pass_codec(job.encoding.settings.video_codec.as_deref(), job.output);
+
+    let mut size = job.encode(app, codec, video_kbps, audio_kbps, progress).await?;
+    let mut reencoded = false;
+    if !within_target(size, target_mb) {
+        let reduced = reduced_bitrate(video_kbps, target_mb, size)?;
+        log::info!("{} is {} bytes, over the {} MB target; re-encoding at {} kb/s", job.output, size, target_mb, reduced);
+        video_kbps = reduced;
+        size = job.encode(app, codec, video_kbps, audio_kbps, progress).await?;
+        reencoded = true;
+    }
+
+    let within_target = within_target(size, target_mb);
+    if !within_target {
+        log::warn!("{} is still {} bytes, over the {} MB target", job.output, size, target_mb);
+    }
+    Ok(SizeTargetResult {
+        target_mb,
+        video_bitrate_kbps: video_kbps,
+        audio_bitrate_kbps: audio_kbps.unwrap_or(0),
+        size_bytes: size,
+        reencoded,
+        within_target,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export_data::EncodeSettings;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn the_video_gets_what_audio_and_overhead_leave_of_the_target() {
+        // 25 MiB over 60s is 3427 kb/s once the 2% overhead is taken out, 96 of it audio
+        assert_eq!(video_bitrate_kbps(25.0, 60.0, 96), Ok(3331.0));
+        assert_eq!(video_bitrate_kbps(4000.0, 10.0, 128), Ok(MAX_VIDEO_KBPS));
+        assert_eq!(
+            video_bitrate_kbps(8.0, 600.0, 128).unwrap_err(),
+            "8 MB for 600.0s leaves 0 kb/s for video, below the 200 kb/s minimum; lower the resolution or export a shorter range instead"
+        );
+        assert!(video_bitrate_kbps(25.0, 0.0, 96).is_err());
+    }
+
+    #[test]
+    fn an_overshoot_is_encoded_again_in_proportion() {
+        assert!(within_target(25 * MIB, 25.0));
+        assert!(within_target(25 * MIB + MIB / 2, 25.0));
+        assert!(!within_target(26 * MIB, 25.0));
+        // 10% over the target takes the bitrate down by the same ratio
+        assert_eq!(reduced_bitrate(3300.0, 25.0, (27.5 * MIB as f64) as u64), Ok(3000.0));
+        assert!(reduced_bitrate(300.0, 25.0, 50 * MIB).is_err());
+    }
+
+    #[test]
+    fn only_software_encoders_do_two_passes() {
+        assert_eq!(two_pass_codec(None, "game.mp4"), "libx264");
+        assert_eq!(two_pass_codec(Some("h264_nvenc"), "game.mp4"), "libx264");
+        assert_eq!(two_pass_codec(Some("libvpx-vp9"), "game.mkv"), "libvpx-vp9");
+        assert_eq!(two_pass_codec(Some("libx264"), "game.WEBM"), "libvpx-vp9");
+    }
+
+    #[test]
+    fn the_first_pass_only_gathers_statistics() {
+        let encoding = ResolvedEncoding {
+            settings: EncodeSettings { preset: Some("slow".to_string()), faststart: Some(true), ..Default::default() },
+            ..Default::default()
+        };
+        let passlog = Path::new("/work/twopass");
+        let job = TwoPassJob { source: Path::new("/work/composite.mkv"), output: "/exports/game.mp4", encoding: &encoding, limits: None };
+        assert_eq!(
+            job.pass_args("libx264", 3331.0, 1, Some(96), passlog).unwrap().join(" "),
+            "-i /work/composite.mkv -map 0:v -c:v libx264 -b:v 3331k -preset slow -pass 1 -passlogfile /work/twopass -an -f null -"
+        );
+        assert_eq!(
+            job.pass_args("libx264", 3331.0, 2, Some(96), passlog).unwrap().join(" "),
+            "-i /work/composite.mkv -map 0:v -map 0:a? -c:v libx264 -b:v 3331k -preset slow -pass 2 -passlogfile /work/twopass \
+             -c:a aac -b:a 96k -movflags +faststart -y /exports/game.mp4"
+        );
+
+        let webm = TwoPassJob { output: "/exports/game.webm", ..job };
+        assert_eq!(
+            webm.pass_args("libvpx-vp9", 900.0, 2, None, passlog).unwrap().join(" "),
+            "-i /work/composite.mkv -map 0:v -c:v libvpx-vp9 -b:v 900k -pass 2 -passlogfile /work/twopass -an -map_chapters -1 -y /exports/game.webm"
+        );
+    }
+}
@@ -0,0 +1,347 @@
+use serde::Serialize;
+use std::path::Path;
+use tauri::AppHandle;
+
+use crate::export_data::BackgroundClip;
+use crate::ffmpeg::{probe_video, VideoProbe};
+use crate::hello::execute_ffmpeg_command;
+use crate::pipeline::MEZZANINE_ARGS;
+use crate::paths::path_arg;
+use crate::warnings;
+
+// Stretches shorter than this are dropped; concat can't join an empty segment
+const MIN_SEGMENT: f64 = 0.001;
+
+// A piece of the stitched background: `duration` seconds of `file` from `in_point`, placed at
+// `start` on the background timeline the moves are timed against
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClipSegment {
+    pub move_index: usize,
+    pub file: String,
+    pub in_point: f64,
+    pub start: f64,
+    pub duration: f64,
+    // The clip was shorter than its stretch and restarts from its beginning
+    pub looped: bool,
+    pub has_audio: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StitchedAudio {
+    // Each clip's own audio, with silence for clips that have none
+    Clips,
+    // The background_audio file
+    Track,
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StitchedBackground {
+    pub segments: Vec<ClipSegment>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    pub audio: StitchedAudio,
+    pub duration: f64,
+}
+
+fn round_ms(t: f64) -> f64 {
+    (t * 1000.0).round() / 1000.0
+}
+
+// Cuts every clip to its move's stretch of the background, from the move's cut to the next one's.
+// The first clip also covers the lead-in before the first move; the last plays on to its own end
+// but must last at least `min_last`. `cuts` are the moves' background start times.
+pub fn plan_segments(
+    clips: &[BackgroundClip],
+    probes: &[VideoProbe],
+    cuts: &[f64],
+    min_last: f64,
+    loop_short: bool,
+) -> Result<Vec<ClipSegment>, String> {
+    let mut starts: Vec<f64> = Vec::with_capacity(cuts.len());
+    for (i, &cut) in cuts.iter().enumerate() {
+        let previous = starts.last().copied().unwrap_or(0.0);
+        starts.push(if i == 0 { 0.0 } else { cut.max(previous) });
+    }
+
+    let mut segments = Vec::with_capacity(clips.len());
+    for (i, (clip, probe)) in clips.iter().zip(probes).enumerate() {
+        let last = i + 1 == clips.len();
+        let available = probe.duration_secs.map(|d| round_ms(d - clip.in_point));
+        if available.is_some_and(|a| a <= 0.0) {
+            return Err(format!(
+                "background_clips[{}] in_point {}s is past the end of {}", i, clip.in_point, clip.file
+            ));
+        }
+        let needed = if last { min_last } else { round_ms(starts[i + 1] - starts[i]) };
+        if needed < MIN_SEGMENT {
+            // Another move shares this cut, so this clip is never on screen
+            continue;
+        }
+        let short = available.is_some_and(|a| a < needed);
+        if short && !loop_short {
+            return Err(format!(
+                "background_clips[{}] has {}s of {} after its in_point but move {} needs {}s; use a longer clip or set loop_background_clips",
+                i, available.unwrap_or_default(), clip.file, i + 1, needed
+            ));
+        }
+        let duration = match available {
+            Some(available) if last && !short => available,
+            None if last => {
+                return Err(format!("Can't tell how long {} is, so it can't end the stitched background", clip.file));
+            }
+            _ => needed,
+        };
+        segments.push(ClipSegment {
+            move_index: i,
+            file: clip.file.clone(),
+            in_point: clip.in_point,
+            start: starts[i],
+            duration,
+            looped: short,
+            has_audio: probe.has_audio,
+        });
+    }
+    Ok(segments)
+}
+
+pub fn stitched_audio(segments: &[ClipSegment], track: Option<&str>) -> StitchedAudio {
+    match track {
+        Some(_) => StitchedAudio::Track,
+        None if segments.iter().any(|s| s.has_audio) => StitchedAudio::Clips,
+        None => StitchedAudio::None,
+    }
+}
+
+// Every segment is scaled and padded to one size, frame rate and pixel format so concat accepts them
+pub fn stitch_args(
+    segments: &[ClipSegment],
+    size: (u32, u32),
+    fps: f64,
+    audio: StitchedAudio,
+    track: Option<&str>,
+    output: &Path,
+) -> Result<Vec<String>, String> {
+    let (width, height) = size;
+    let mut args: Vec<String> = Vec::new();
+    for segment in segments {
+        if segment.looped {
+            // Loops restart at the start of the file rather than at the in_point
+            args.extend(["-stream_loop".to_string(), "-1".to_string()]);
+        }
+        args.extend([
+            "-ss".to_string(), segment.in_point.to_string(),
+            "-t".to_string(), segment.duration.to_string(),
+            "-i".to_string(), segment.file.clone(),
+        ]);
+    }
+    let total = round_ms(segments.iter().map(|s| s.duration).sum());
+    if let Some(track) = track {
+        args.extend(["-t".to_string(), total.to_string(), "-i".to_string(), track.to_string()]);
+    }
+
+    let mut parts = Vec::new();
+    let mut concat_inputs = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        parts.push(format!(
+            "[{i}:v]setpts=PTS-STARTPTS,scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={fps},format=yuv420p[clip_v{i}]",
+            i = i, w = width, h = height, fps = fps
+        ));
+        concat_inputs.push_str(&format!("[clip_v{}]", i));
+        if audio == StitchedAudio::Clips {
+            if segment.has_audio {
+                parts.push(format!(
+                    "[{}:a]asetpts=PTS-STARTPTS,aresample=48000,aformat=channel_layouts=stereo[clip_a{}]", i, i
+                ));
+            } else {
+                parts.push(format!("anullsrc=r=48000:cl=stereo,atrim=duration={}[clip_a{}]", segment.duration, i));
+            }
+            concat_inputs.push_str(&format!("[clip_a{}]", i));
+        }
+    }
+    let with_audio = audio == StitchedAudio::Clips;
+    let outputs = if with_audio { "[stitched_v][stitched_a]" } else { "[stitched_v]" };
+    parts.push(format!("{}concat=n={}:v=1:a={}{}", concat_inputs, segments.len(), u8::from(with_audio), outputs));
+
+    args.extend(["-filter_complex".to_string(), parts.join(";"), "-map".to_string(), "[stitched_v]".to_string()]);
+    match audio {
+        StitchedAudio::Clips => args.extend(["-map".to_string(), "[stitched_a]".to_string()]),
+        StitchedAudio::Track => args.extend(["-map".to_string(), format!("{}:a", segments.len())]),
+        StitchedAudio::None => {}
+    }
+    args.extend(MEZZANINE_ARGS.iter().map(|a| a.to_string()));
+    if audio != StitchedAudio::None {
+        args.extend(["-c:a".to_string(), "aac".to_string(), "-b:a".to_string(), "192k".to_string()]);
+    }
+    args.extend(["-y".to_string(), path_arg(output)?]);
+    Ok(args)
+}
+
+// Probes the clips, plans the segments and writes the stitched background to `output`
+pub async fn stitch_background(
+    app: &AppHandle,
+    clips: &[BackgroundClip],
+    cuts: &[f64],
+    min_last: f64,
+    loop_short: bool,
+    track: Option<&str>,
+    output: &Path,
+) -> Result<StitchedBackground, String> {
+    let mut probes = Vec::with_capacity(clips.len());
+    for (i, clip) in clips.iter().enumerate() {
+        if !Path::new(&clip.file).is_file() {
+            return Err(format!("background_clips[{}] not found: {}", i, clip.file));
+        }
+        let probe = probe_video(app, Path::new(&clip.file)).await
+            .map_err(|e| format!("Failed to read background_clips[{}]: {}", i, e))?;
+        probes.push(probe);
+    }
+    if let Some(track) = track.filter(|t| !Path::new(t).is_file()) {
+        return Err(format!("background_audio not found: {}", track));
+    }
+
+    let segments = plan_segments(clips, &probes, cuts, min_last, loop_short)?;
+    // The first clip that is shown sets the size and frame rate the others are fitted to
+    let first = segments.first().map(|s| &probes[s.move_index]).ok_or("No background clip is on screen")?;
+    let size = if first.rotation % 180 == 90 { (first.height, first.width) } else { (first.width, first.height) };
+    let fps = first.nominal_fps().unwrap_or(30.0);
+    let audio = stitched_audio(&segments, track);
+    for segment in segments.iter().filter(|s| s.looped) {
+        warnings::warn(
+            "background_clip_looped",
+            format!("{} is shorter than move {}'s {}s and was looped", segment.file, segment.move_index + 1, segment.duration),
+        );
+    }
+
+    let args = stitch_args(&segments, size, fps, audio, track, output)?;
+    log::info!("Stitching {} background clips: {:?}", segments.len(), args);
+    let result = execute_ffmpeg_command(app.clone(), &args, None, false).await?;
+    if !result.success {
+        return Err(format!("Failed to stitch the background clips: {}\nReturn code: {:?}", result.error, result.return_code));
+    }
+    Ok(StitchedBackground {
+        duration: round_ms(segments.iter().map(|s| s.duration).sum()),
+        segments,
+        width: size.0,
+        height: size.1,
+        fps,
+        audio,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(file: &str, in_point: f64) -> BackgroundClip {
+        BackgroundClip { file: file.to_string(), in_point }
+    }
+
+    fn probe(duration: Option<f64>, has_audio: bool) -> VideoProbe {
+        VideoProbe {
+            width: 1920,
+            height: 1080,
+            duration_secs: duration,
+            has_audio,
+            fps: Some(30.0),
+            interlaced: None,
+            rotation: 0,
+            base_fps: Some(30.0),
+            variable_frame_rate: false,
+            hdr_transfer: None,
+            still_image: false,
+        }
+    }
+
+    fn segment(move_index: usize, file: &str, in_point: f64, start: f64, duration: f64, looped: bool) -> ClipSegment {
+        ClipSegment { move_index, file: file.to_string(), in_point, start, duration, looped, has_audio: true }
+    }
+
+    #[test]
+    fn each_clip_covers_its_move_up_to_the_next_cut() {
+        let clips = [clip("a.mp4", 0.0), clip("b.mp4", 2.0), clip("c.mp4", 1.0)];
+        let probes = [probe(Some(10.0), true), probe(Some(10.0), true), probe(Some(10.0), true)];
+        let segments = plan_segments(&clips, &probes, &[1.0, 3.5, 6.0], 2.0, false).unwrap();
+        assert_eq!(
+            segments,
+            [
+                // The first clip also covers the lead-in before the first move
+                segment(0, "a.mp4", 0.0, 0.0, 3.5, false),
+                segment(1, "b.mp4", 2.0, 3.5, 2.5, false),
+                // The last plays to its own end
+                segment(2, "c.mp4", 1.0, 6.0, 9.0, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn short_clips_fail_unless_they_may_loop() {
+        let clips = [clip("a.mp4", 0.0), clip("b.mp4", 2.0), clip("c.mp4", 0.0)];
+        let probes = [probe(Some(10.0), true), probe(Some(3.0), true), probe(Some(1.0), true)];
+        let cuts = [1.0, 3.5, 6.0];
+        assert_eq!(
+            plan_segments(&clips, &probes, &cuts, 2.0, false).unwrap_err(),
+            "background_clips[1] has 1s of b.mp4 after its in_point but move 2 needs 2.5s; use a longer clip or set loop_background_clips"
+        );
+        let segments = plan_segments(&clips, &probes, &cuts, 2.0, true).unwrap();
+        assert_eq!(segments[1], segment(1, "b.mp4", 2.0, 3.5, 2.5, true));
+        // A looped last clip lasts as long as it has to rather than to its own end
+        assert_eq!(segments[2], segment(2, "c.mp4", 0.0, 6.0, 2.0, true));
+    }
+
+    #[test]
+    fn clips_that_never_show_are_dropped() {
+        let clips = [clip("a.mp4", 0.0), clip("b.mp4", 0.0), clip("c.mp4", 0.0)];
+        let probes = [probe(Some(10.0), true), probe(Some(10.0), true), probe(Some(10.0), true)];
+        // Move 2's cut comes after move 3's, so move 3 starts where move 2 does
+        let segments = plan_segments(&clips, &probes, &[1.0, 4.0, 3.0], 2.0, false).unwrap();
+        assert_eq!(segments.iter().map(|s| (s.move_index, s.start)).collect::<Vec<_>>(), [(0, 0.0), (2, 4.0)]);
+    }
+
+    #[test]
+    fn unusable_clips_are_reported() {
+        let clips = [clip("a.mp4", 12.0), clip("b.mp4", 0.0)];
+        let error = plan_segments(&clips, &[probe(Some(10.0), true), probe(Some(10.0), true)], &[1.0, 3.0], 2.0, false).unwrap_err();
+        assert_eq!(error, "background_clips[0] in_point 12s is past the end of a.mp4");
+
+        let clips = [clip("a.mp4", 0.0), clip("b.mp4", 0.0)];
+        let error = plan_segments(&clips, &[probe(Some(10.0), true), probe(None, true)], &[1.0, 3.0], 2.0, false).unwrap_err();
+        assert_eq!(error, "Can't tell how long b.mp4 is, so it can't end the stitched background");
+    }
+
+    #[test]
+    fn the_audio_comes_from_the_track_or_the_clips() {
+        let mut segments = vec![segment(0, "a.mp4", 0.0, 0.0, 3.5, false), segment(1, "b.mp4", 0.0, 3.5, 2.0, false)];
+        assert_eq!(stitched_audio(&segments, Some("commentary.m4a")), StitchedAudio::Track);
+        assert_eq!(stitched_audio(&segments, None), StitchedAudio::Clips);
+        segments.iter_mut().for_each(|s| s.has_audio = false);
+        assert_eq!(stitched_audio(&segments, None), StitchedAudio::None);
+    }
+
+    #[test]
+    fn the_stitch_normalises_every_clip_before_concat() {
+        let mut segments = vec![segment(0, "a.mp4", 0.0, 0.0, 3.5, false), segment(1, "b.mp4", 2.0, 3.5, 2.5, true)];
+        segments[1].has_audio = false;
+        let args = stitch_args(&segments, (1920, 1080), 30.0, StitchedAudio::Clips, None, Path::new("stitched.mkv")).unwrap();
+
+        assert_eq!(
+            args[..14],
+            ["-ss", "0", "-t", "3.5", "-i", "a.mp4", "-stream_loop", "-1", "-ss", "2", "-t", "2.5", "-i", "b.mp4"]
+        );
+        let graph = &args[args.iter().position(|a| a == "-filter_complex").unwrap() + 1];
+        let fit = "scale=1920:1080:force_original_aspect_ratio=decrease,pad=1920:1080:(ow-iw)/2:(oh-ih)/2,setsar=1,fps=30,format=yuv420p";
+        assert_eq!(
+            graph.split(';').collect::<Vec<_>>(),
+            [
+                format!("[0:v]setpts=PTS-STARTPTS,{}[clip_v0]", fit),
+                "[0:a]asetpts=PTS-STARTPTS,aresample=48000,aformat=channel_layouts=stereo[clip_a0]".to_string(),
+                format!("[1:v]setpts=PTS-STARTPTS,{}[clip_v1]", fit),
+                "anullsrc=r=48000:cl=stereo,atrim=duration=2.5[clip_a1]".to_string(),
+                "[clip_v0][clip_a0][clip_v1][clip_a1]concat=n=2:v=1:a=1[stitched_v][stitched_a]".to_string(),
+            ]
+        );
+        assert_eq!(args[args.len() - 2..], ["-y", "stitched.mkv"]);
+    }
+}
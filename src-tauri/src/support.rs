@@ -0,0 +1,435 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Manager, State};
+use tauri_plugin_dialog::DialogExt;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::activity::{LogFilter, RecentLogs};
+use crate::diagnostics::system_diagnostics;
+use crate::hello::execute_ffmpeg_command;
+use crate::paths::path_arg;
+use crate::workdir::{jobs_dir, WorkDirs};
+
+const EXPORT_LOGS: usize = 5;
+const PYTHON_LOGS: usize = 5;
+const PYTHON_TAIL_LINES: usize = 500;
+const JOBS: usize = 3;
+// App log files are cut to their end past this
+const MAX_LOG_BYTES: usize = 2 * 1024 * 1024;
+// Seconds of each background video copied into the bundle when media samples are included
+const SAMPLE_SECS: u32 = 5;
+const REDACTED: &str = "[redacted]";
+// Settings keys whose values are always redacted, wherever they appear. Hook extra_args are
+// where scripts usually take their tokens, so those go too.
+const SECRET_KEYS: &[&str] = &["secret", "token", "password", "api_key", "apikey", "extra_args"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SupportBundle {
+    pub path: String,
+    pub size_bytes: u64,
+    // The names inside the archive
+    pub files: Vec<String>,
+    // What couldn't be collected, and why
+    pub skipped: Vec<String>,
+}
+
+// What gets taken out of every text file in the bundle
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    secrets: Vec<String>,
+    username: Option<String>,
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SECRET_KEYS.iter().any(|secret| key.contains(secret))
+}
+
+fn collect_strings(value: &Value, found: &mut Vec<String>) {
+    match value {
+        Value::String(s) => found.push(s.clone()),
+        Value::Array(items) => items.iter().for_each(|v| collect_strings(v, found)),
+        Value::Object(map) => map.values().for_each(|v| collect_strings(v, found)),
+        _ => {}
+    }
+}
+
+impl Redactor {
+    // `username` is replaced in home directory paths; the secrets are those found in `settings`
+    pub fn new(settings: &Value, username: Option<String>) -> Self {
+        let mut secrets = Vec::new();
+        redact_json(&mut settings.clone(), &mut secrets);
+        // Short values would match too much unrelated text, and are no secret anyway
+        secrets.retain(|s| s.chars().count() >= 4);
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        secrets.dedup();
+        Redactor { secrets, username: username.filter(|u| !u.trim().is_empty()) }
+    }
+
+    pub fn text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in &self.secrets {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+        match &self.username {
+            Some(username) => redact_username(&text, username),
+            None => text,
+        }
+    }
+
+    // The settings file with the secret keys' values replaced, then as text
+    pub fn settings(&self, settings: &Value) -> String {
+        let mut settings = settings.clone();
+        redact_json(&mut settings, &mut Vec::new());
+        self.text(&serde_json::to_string_pretty(&settings).unwrap_or_default())
+    }
+}
+
+// Replaces the values of secret keys, collecting the strings replaced
+fn redact_json(value: &mut Value, secrets: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret_key(key) && !value.is_null() {
+                    collect_strings(value, secrets);
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value, secrets);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| redact_json(v, secrets)),
+        _ => {}
+    }
+}
+
+// `username` as the directory after Users or home, however the separators are escaped:
+// C:\Users\name, C:\\Users\\name in JSON, /home/name, /mnt/c/Users/name. Matched ignoring
+// ASCII case, as Windows does.
+fn redact_username(text: &str, username: &str) -> String {
+    let (lower, name) = (text.to_ascii_lowercase(), username.to_ascii_lowercase());
+    let mut redacted = String::with_capacity(text.len());
+    let mut start = 0;
+    while let Some(at) = lower[start..].find(&name).map(|i| start + i) {
+        let (before, after) = (&text[start..at], &text[at + name.len()..]);
+        let parent = before.trim_end_matches(['\\', '/']);
+        let separated = parent.len() < before.len();
+        let home = ["users", "home"].iter().any(|dir| {
+            let split = parent.len().saturating_sub(dir.len());
+            parent.get(split..).is_some_and(|tail| tail.eq_ignore_ascii_case(dir))
+                && !parent[..split].ends_with(|c: char| c.is_alphanumeric())
+        });
+        let ends = after.chars().next().map_or(true, |c| matches!(c, '\\' | '/' | '"' | '\'' | ' ' | '\n' | ':'));
+        redacted.push_str(before);
+        redacted.push_str(if separated && home && ends { "<user>" } else { &text[at..at + name.len()] });
+        start = at + name.len();
+    }
+    redacted.push_str(&text[start..]);
+    redacted
+}
+
+fn current_username() -> Option<String> {
+    std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .ok()
+        .or_else(|| std::env::var_os("HOME").and_then(|home| Path::new(&home).file_name().map(|n| n.to_string_lossy().to_string())))
+}
+
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path).and_then(|m| m.modified()).unwrap_or(UNIX_EPOCH)
+}
+
+// The entries of `dir` that `keep` accepts, newest first
+fn newest(dir: &Path, count: usize, keep: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| keep(p)).collect())
+        .unwrap_or_default();
+    found.sort_by_key(|p| std::cmp::Reverse(modified(p)));
+    found.truncate(count);
+    found
+}
+
+fn tail_lines(text: &str, lines: usize) -> String {
+    let all: Vec<&str> = text.lines().collect();
+    all[all.len().saturating_sub(lines)..].join("\n")
+}
+
+fn tail_bytes(text: &str, bytes: usize) -> &str {
+    let mut start = text.len().saturating_sub(bytes);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+struct Collected {
+    files: Vec<(String, Vec<u8>)>,
+    skipped: Vec<String>,
+}
+
+impl Collected {
+    fn text(&mut self, name: String, content: String, redactor: &Redactor) {
+        self.files.push((name, redactor.text(&content).into_bytes()));
+    }
+
+    fn read(&mut self, name: String, path: &Path, redactor: &Redactor, shorten: impl Fn(&str) -> String) {
+        match fs::read(path) {
+            Ok(bytes) => self.text(name, shorten(&String::from_utf8_lossy(&bytes)), redactor),
+            Err(e) => self.skipped.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+}
+
+// The first seconds of the job's background video, stream-copied so nothing is re-encoded
+async fn media_sample(app: &AppHandle, export_json: &Value, output: &Path) -> Result<(), String> {
+    let video = export_json.get("videoPath").and_then(|v| v.as_str()).ok_or("the export has no videoPath")?;
+    let args: Vec<String> = vec![
+        "-y".into(), "-t".into(), SAMPLE_SECS.to_string(), "-i".into(), video.to_string(),
+        "-map".into(), "0".into(), "-c".into(), "copy".into(), path_arg(output)?,
+    ];
+    let result = execute_ffmpeg_command(app.clone(), &args, None, true).await?;
+    if !result.success {
+        return Err(result.error.lines().last().unwrap_or("ffmpeg failed").trim().to_string());
+    }
+    Ok(())
+}
+
+async fn collect(app: &AppHandle, logs: &RecentLogs, include_media_samples: bool, redact_usernames: bool) -> Result<Collected, String> {
+    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    let log_dir = app.path().app_log_dir().map_err(|e| e.to_string())?;
+    let settings: Value = fs::read_to_string(config_dir.join("settings.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or(Value::Null);
+    let redactor = Redactor::new(&settings, redact_usernames.then(current_username).flatten());
+    let mut collected = Collected { files: Vec::new(), skipped: Vec::new() };
+
+    collected.text("settings.json".to_string(), redactor.settings(&settings), &redactor);
+    let report = system_diagnostics(app.clone(), None).await?;
+    collected.text("diagnostics.txt".to_string(), report.text, &redactor);
+    let recent: Vec<String> = logs
+        .query(&LogFilter::default(), usize::MAX, None)
+        .into_iter()
+        .rev()
+        .map(|e| format!("{} {:>5} {} {}", e.timestamp_ms, e.level, e.target, e.message))
+        .collect();
+    collected.text("recent-logs.txt".to_string(), recent.join("\n"), &redactor);
+
+    for path in newest(&log_dir, usize::MAX, |p| p.is_file() && p.extension().is_some_and(|e| e == "log")) {
+        let name = format!("logs/{}", path.file_name().unwrap_or_default().to_string_lossy());
+        collected.read(name, &path, &redactor, |text| tail_bytes(text, MAX_LOG_BYTES).to_string());
+    }
+    for dir in newest(&log_dir.join("exports"), EXPORT_LOGS, |p| p.join("ffmpeg.log").is_file()) {
+        let name = format!("exports/{}/ffmpeg.log", dir.file_name().unwrap_or_default().to_string_lossy());
+        collected.read(name, &dir.join("ffmpeg.log"), &redactor, |text| tail_bytes(text, MAX_LOG_BYTES).to_string());
+    }
+    for path in newest(&log_dir.join("python"), PYTHON_LOGS, Path::is_file) {
+        let name = format!("python/{}", path.file_name().unwrap_or_default().to_string_lossy());
+        collected.read(name, &path, &redactor, |text| tail_lines(text, PYTHON_TAIL_LINES));
+    }
+
+    let jobs = jobs_dir(app).map(|dir| newest(&dir, JOBS, |p| p.join("export.json").is_file())).unwrap_or_default();
+    let samples = if include_media_samples { Some(app.state::<WorkDirs>().allocate(app, "support-bundle")?) } else { None };
+    for job in jobs {
+        let id = job.file_name().unwrap_or_default().to_string_lossy().to_string();
+        for file in ["export.json", "job.json"].iter().filter(|f| job.join(f).is_file()) {
+            collected.read(format!("jobs/{}/{}", id, file), &job.join(file), &redactor, str::to_string);
+        }
+        let Some(samples) = &samples else {
+            continue;
+        };
+        let export_json = fs::read_to_string(job.join("export.json")).ok().and_then(|c| serde_json::from_str(&c).ok()).unwrap_or(Value::Null);
+        let sample = samples.file(&format!("{}.mkv", id));
+        match media_sample(app, &export_json, &sample).await.and_then(|_| fs::read(&sample).map_err(|e| e.to_string())) {
+            Ok(bytes) => collected.files.push((format!("jobs/{}/background-sample.mkv", id), bytes)),
+            Err(e) => collected.skipped.push(format!("media sample of {}: {}", id, e)),
+        }
+    }
+    if let Some(samples) = samples {
+        let _ = fs::remove_dir_all(samples.path());
+    }
+    if !collected.skipped.is_empty() {
+        let skipped = collected.skipped.join("\n");
+        collected.text("skipped.txt".to_string(), skipped, &redactor);
+    }
+    Ok(collected)
+}
+
+fn write_zip(path: &Path, files: &[(String, Vec<u8>)]) -> Result<u64, String> {
+    let file = fs::File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in files {
+        zip.start_file(name.as_str(), options)
+            .and_then(|_| zip.write_all(content).map_err(Into::into))
+            .map_err(|e| format!("Failed to add {} to {}: {}", name, path.display(), e))?;
+    }
+    zip.finish().map_err(|e| format!("Failed to finish {}: {}", path.display(), e))?;
+    fs::metadata(path).map(|m| m.len()).map_err(|e| e.to_string())
+}
+
+// Zips the logs, settings and recent jobs someone fixing a bug report needs, with secrets and,
+// unless turned off, the user name in paths taken out. Without `output_path` a save dialog asks
+// where; cancelling it returns None.
+#[command]
+pub async fn create_support_bundle(
+    app: AppHandle,
+    logs: State<'_, RecentLogs>,
+    include_media_samples: bool,
+    output_path: Option<String>,
+    redact_usernames: Option<bool>,
+) -> Result<Option<SupportBundle>, String> {
+    let path = match output_path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            let picked = app
+                .dialog()
+                .file()
+                .set_file_name(format!("boardcast-support-{}.zip", secs))
+                .add_filter("Zip archive", &["zip"])
+                .blocking_save_file();
+            match picked {
+                Some(file) => file.into_path().map_err(|e| e.to_string())?,
+                None => return Ok(None),
+            }
+        }
+    };
+    log::info!("Creating a support bundle at {}", path.display());
+    let collected = collect(&app, &logs, include_media_samples, redact_usernames.unwrap_or(true)).await?;
+    let files: Vec<String> = collected.files.iter().map(|(name, _)| name.clone()).collect();
+    let zip_path = path.clone();
+    let size_bytes = tauri::async_runtime::spawn_blocking(move || write_zip(&zip_path, &collected.files))
+        .await
+        .map_err(|e| e.to_string())??;
+    Ok(Some(SupportBundle { path: path_arg(&path)?, size_bytes, files, skipped: collected.skipped }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn settings() -> Value {
+        json!({
+            "theme": "dark",
+            "upload": {"api_key": "sk-live-0123456789", "Bucket_Token": "tok_abcdef", "host": "videos.example.com"},
+            "hooks": [{"command": "notify.sh", "extra_args": ["--webhook", "https://hooks.example.com/T0001/B0002"]}],
+            "password": null,
+            "smtpPassword": "hunter2!",
+            "pin_secret": "123",
+        })
+    }
+
+    #[test]
+    fn secret_keys_are_redacted_at_any_depth_and_in_any_case() {
+        let redactor = Redactor::new(&settings(), None);
+        let redacted: Value = serde_json::from_str(&redactor.settings(&settings())).unwrap();
+        assert_eq!(
+            redacted,
+            json!({
+                "theme": "dark",
+                "upload": {"api_key": REDACTED, "Bucket_Token": REDACTED, "host": "videos.example.com"},
+                "hooks": [{"command": "notify.sh", "extra_args": REDACTED}],
+                // Nothing to hide, and worth knowing it isn't set
+                "password": null,
+                "smtpPassword": REDACTED,
+                "pin_secret": REDACTED,
+            })
+        );
+    }
+
+    #[test]
+    fn secret_values_are_redacted_wherever_else_they_appear() {
+        let redactor = Redactor::new(&settings(), None);
+        let log = "uploading with key sk-live-0123456789\n\
+                   hook: notify.sh --webhook https://hooks.example.com/T0001/B0002 exited 1\n\
+                   auth=tok_abcdef;retry=hunter2!";
+        assert_eq!(
+            redactor.text(log),
+            "uploading with key [redacted]\nhook: notify.sh [redacted] [redacted] exited 1\nauth=[redacted];retry=[redacted]"
+        );
+        // Values too short to be told apart from other text are only redacted under their key
+        assert_eq!(redactor.text("frame 123 of 600"), "frame 123 of 600");
+    }
+
+    #[test]
+    fn a_secret_inside_a_longer_secret_does_not_leave_part_of_it_behind() {
+        let redactor = Redactor::new(&json!({"token": "abcd", "api_key": "xxabcdxx"}), None);
+        assert_eq!(redactor.text("key xxabcdxx, token abcd"), "key [redacted], token [redacted]");
+    }
+
+    #[test]
+    fn user_names_in_home_directory_paths_are_redacted() {
+        let redactor = Redactor::new(&Value::Null, Some("magnus".to_string()));
+        let cases = [
+            (r"C:\Users\magnus\Videos\game.mp4", r"C:\Users\<user>\Videos\game.mp4"),
+            (r#"{"videoPath": "C:\\Users\\Magnus\\Videos\\game.mp4"}"#, r#"{"videoPath": "C:\\Users\\<user>\\Videos\\game.mp4"}"#),
+            ("/home/magnus/videos/game.mp4", "/home/<user>/videos/game.mp4"),
+            ("/mnt/c/Users/MAGNUS/Videos", "/mnt/c/Users/<user>/Videos"),
+            ("C:/users/magnus", "C:/users/<user>"),
+            ("HOME=/home/magnus\nPATH=/home/magnus/bin:/usr/bin", "HOME=/home/<user>\nPATH=/home/<user>/bin:/usr/bin"),
+            ("cd '/home/magnus'", "cd '/home/<user>'"),
+            // Not in a home directory path, or a different user's
+            ("magnus won the game", "magnus won the game"),
+            ("/srv/magnus/videos", "/srv/magnus/videos"),
+            ("/home/magnus2/videos", "/home/magnus2/videos"),
+            ("/home/xmagnus/videos", "/home/xmagnus/videos"),
+            ("/myhome/magnus/videos", "/myhome/magnus/videos"),
+            (r"C:\Users\magnus.old\Videos", r"C:\Users\magnus.old\Videos"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(redactor.text(text), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn user_names_are_kept_when_their_redaction_is_off() {
+        let path = "/home/magnus/videos/game.mp4";
+        assert_eq!(Redactor::new(&Value::Null, None).text(path), path);
+        assert_eq!(Redactor::new(&Value::Null, Some("  ".to_string())).text(path), path);
+    }
+
+    #[test]
+    fn secrets_and_user_names_are_both_redacted_in_settings() {
+        let settings = json!({"outputDir": "/home/magnus/videos", "token": "/home/magnus/.token-file"});
+        let redacted = Redactor::new(&settings, Some("magnus".to_string())).settings(&settings);
+        assert!(redacted.contains("\"outputDir\": \"/home/<user>/videos\""), "{}", redacted);
+        assert!(redacted.contains("\"token\": \"[redacted]\""), "{}", redacted);
+        assert!(!redacted.contains("magnus"), "{}", redacted);
+    }
+
+    #[test]
+    fn logs_are_cut_to_their_end() {
+        assert_eq!(tail_lines("1\n2\n3\n4", 2), "3\n4");
+        assert_eq!(tail_lines("1\n2", 5), "1\n2");
+        // Never in the middle of a character
+        assert_eq!(tail_bytes("♜♞♝", 4), "♝");
+        assert_eq!(tail_bytes("♜♞♝", 6), "♞♝");
+        assert_eq!(tail_bytes("abc", 10), "abc");
+    }
+
+    #[test]
+    fn the_bundle_holds_every_file_by_name() {
+        if std::process::Command::new("unzip").arg("-v").output().is_err() {
+            return;
+        }
+        let path = std::env::temp_dir().join(format!("boardcast-support-test-{}.zip", std::process::id()));
+        let files = vec![
+            ("settings.json".to_string(), b"{}".to_vec()),
+            ("jobs/1700000000000-0/export.json".to_string(), "{\"title\": \"♛\"}".repeat(100).into_bytes()),
+        ];
+        let size = write_zip(&path, &files).unwrap();
+        assert_eq!(size, fs::metadata(&path).unwrap().len());
+        let listing = std::process::Command::new("unzip").arg("-Z1").arg(&path).output().unwrap();
+        let extracted = std::process::Command::new("unzip").arg("-p").arg(&path).arg("jobs/*").output().unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(String::from_utf8_lossy(&listing.stdout).lines().collect::<Vec<_>>(), ["settings.json", "jobs/1700000000000-0/export.json"]);
+        assert_eq!(extracted.stdout, files[1].1);
+    }
+}
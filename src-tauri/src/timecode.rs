@@ -0,0 +1,321 @@
+use serde_json::Value;
+use std::future::Future;
+use std::path::PathBuf;
+use tauri::{command, AppHandle};
+
+use crate::export_data::{timestamps_frames, FrameRate};
+use crate::ffmpeg::{probe_video, VideoProbe};
+
+// A time as an editor shows it: HH:MM:SS:FF counts frames, HH:MM:SS;FF counts drop-frame
+// NTSC frames, and HH:MM:SS.mmm is plain clock time
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Timecode {
+    Frames { hours: u64, minutes: u64, seconds: u64, frames: u64, drop_frame: bool },
+    Clock(f64),
+}
+
+impl Timecode {
+    fn parse(tc: &str) -> Result<Self, String> {
+        let invalid = || format!("\"{}\" is not a timecode like 00:01:23:12 or 00:01:23.500", tc);
+        let tc = tc.trim();
+        let number = |part: &str| part.parse::<u64>().ok().filter(|_| !part.is_empty() && part.len() <= 2);
+
+        if let Some((clock, millis)) = tc.split_once('.') {
+            let parts: Vec<&str> = clock.split(':').collect();
+            let [hours, minutes, seconds] = parts[..] else { return Err(invalid()) };
+            let (Some(hours), Some(minutes), Some(seconds)) = (number(hours), number(minutes), number(seconds)) else {
+                return Err(invalid());
+            };
+            if minutes >= 60 || seconds >= 60 || millis.is_empty() || !millis.chars().all(|c| c.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            let fraction: f64 = format!("0.{}", millis).parse().map_err(|_| invalid())?;
+            return Ok(Timecode::Clock((hours * 3600 + minutes * 60 + seconds) as f64 + fraction));
+        }
+
+        // Drop-frame is marked by a ; before the frames (some tools write ; throughout)
+        let drop_frame = tc.contains(';');
+        let parts: Vec<&str> = tc.split([':', ';']).collect();
+        let [hours, minutes, seconds, frames] = parts[..] else { return Err(invalid()) };
+        match (number(hours), number(minutes), number(seconds), number(frames)) {
+            (Some(hours), Some(minutes), Some(seconds), Some(frames)) if minutes < 60 && seconds < 60 => {
+                Ok(Timecode::Frames { hours, minutes, seconds, frames, drop_frame })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    fn seconds(self, rate: Option<FrameRate>) -> Result<f64, String> {
+        let (hours, minutes, seconds, frames, drop_frame) = match self {
+            Timecode::Clock(secs) => return Ok(secs),
+            Timecode::Frames { hours, minutes, seconds, frames, drop_frame } => (hours, minutes, seconds, frames, drop_frame),
+        };
+        let rate = rate.ok_or("a timecode with frames needs the frame rate")?;
+        // Timecode counts whole frames per second, so 29.97 is labelled as 30
+        let timebase = rate.fps().round() as u64;
+        if frames >= timebase {
+            return Err(format!("frame {} doesn't exist at {} fps", frames, timebase));
+        }
+        let total_minutes = hours * 60 + minutes;
+        let mut frame = (total_minutes * 60 + seconds) * timebase + frames;
+        if drop_frame {
+            if rate.den != 1001 || timebase % 30 != 0 {
+                return Err(format!("drop-frame timecode needs 29.97 or 59.94 fps, not {:.3}", rate.fps()));
+            }
+            // Frame numbers 0 and 1 (0-3 at 59.94) are skipped every minute except each tenth
+            let dropped = timebase / 15;
+            if seconds == 0 && frames < dropped && minutes % 10 != 0 {
+                return Err(format!("frame {} is skipped in drop-frame timecode at minute {}", frames, minutes));
+            }
+            frame -= dropped * (total_minutes - total_minutes / 10);
+        }
+        Ok(rate.seconds(frame))
+    }
+}
+
+// Seconds for `tc` at `rate`; numbers pass through unchanged
+fn time_value(value: &Value, rate: Option<FrameRate>) -> Result<Option<f64>, String> {
+    match value {
+        Value::String(tc) => Timecode::parse(tc)?
+            .seconds(rate)
+            .map(Some)
+            .map_err(|e| format!("\"{}\": {}", tc, e)),
+        _ => Ok(None),
+    }
+}
+
+fn needs_rate(value: &Value) -> bool {
+    value.as_str().is_some_and(|tc| matches!(Timecode::parse(tc), Ok(Timecode::Frames { .. })))
+}
+
+// background_fps when given, otherwise the frame rate probed from videoPath
+pub async fn background_rate(app: &AppHandle, data: &Value, needed_by: &str) -> Result<FrameRate, String> {
+    background_rate_with(data, needed_by, |path| async move { probe_video(app, &path).await }).await
+}
+
+async fn background_rate_with<F, Fut>(data: &Value, needed_by: &str, probe: F) -> Result<FrameRate, String>
+where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: Future<Output = Result<VideoProbe, String>>,
+{
+    if let Some(rate) = FrameRate::from_value(data)? {
+        return Ok(rate);
+    }
+    let video_path = data.get("videoPath")
+        .and_then(|v| v.as_str())
+        .filter(|_| data.get("background_clips").is_none())
+        .ok_or_else(|| format!("{} needs background_fps when there is no videoPath to probe", needed_by))?;
+    let probe = probe(PathBuf::from(video_path)).await
+        .map_err(|e| format!("Failed to probe the background frame rate for {}: {}", needed_by, e))?;
+    probe.nominal_fps()
+        .and_then(FrameRate::from_fps)
+        .ok_or_else(|| format!("The background reports no frame rate; give background_fps with {}", needed_by))
+}
+
+// Moves given as timestamps_frames stay frames; the rate they count at is written to background_fps
+// as an exact ratio, probed from videoPath when it isn't given, for the plan to convert with
+pub async fn resolve_background_fps(app: &AppHandle, data: Value) -> Result<Value, String> {
+    resolve_background_fps_with(data, |path| async move { probe_video(app, &path).await }).await
+}
+
+async fn resolve_background_fps_with<F, Fut>(mut data: Value, probe: F) -> Result<Value, String>
+where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: Future<Output = Result<VideoProbe, String>>,
+{
+    if timestamps_frames(&data)?.is_none() {
+        return Ok(data);
+    }
+    let rate = background_rate_with(&data, "timestamps_frames", probe).await?;
+    log::info!("Moves are given as frames at {}/{} fps", rate.num, rate.den);
+    let object = data.as_object_mut().ok_or("Export data must be a JSON object")?;
+    object.insert("background_fps".to_string(), serde_json::json!(format!("{}/{}", rate.num, rate.den)));
+    Ok(data)
+}
+
+// Replaces every timecode string among the payload's times with seconds. Each element of
+// timestamps is parsed on its own, so numbers and timecodes can be mixed.
+pub async fn timecodes_to_seconds(app: &AppHandle, mut data: Value) -> Result<Value, String> {
+    let object = data.as_object().ok_or("Export data must be a JSON object")?;
+    let timestamps = object.get("timestamps").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+    let clips = object.get("background_clips").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+    let fields = ["move_range_lead_in", "move_range_tail"];
+    let in_points: Vec<Value> = clips.iter().map(|c| c.get("in_point").cloned().unwrap_or(Value::Null)).collect();
+    let all = timestamps.iter().chain(&in_points).chain(fields.iter().filter_map(|f| object.get(*f)));
+    let any_timecode = all.clone().any(|v| v.is_string());
+    if !any_timecode {
+        return Ok(data);
+    }
+    let rate = match all.clone().any(needs_rate) {
+        true => Some(background_rate(app, &data, "a frame timecode").await?),
+        false => None,
+    };
+
+    let mut converted = 0;
+    let object = data.as_object_mut().ok_or("Export data must be a JSON object")?;
+    if let Some(Value::Array(timestamps)) = object.get_mut("timestamps") {
+        for (i, value) in timestamps.iter_mut().enumerate() {
+            if let Some(secs) = time_value(value, rate).map_err(|e| format!("timestamps[{}]: {}", i, e))? {
+                *value = serde_json::json!(secs);
+                converted += 1;
+            }
+        }
+    }
+    for field in fields {
+        if let Some(value) = object.get_mut(field) {
+            if let Some(secs) = time_value(value, rate).map_err(|e| format!("{}: {}", field, e))? {
+                *value = serde_json::json!(secs);
+                converted += 1;
+            }
+        }
+    }
+    if let Some(Value::Array(clips)) = object.get_mut("background_clips") {
+        for (i, clip) in clips.iter_mut().enumerate() {
+            if let Some(value) = clip.get_mut("in_point") {
+                if let Some(secs) = time_value(value, rate).map_err(|e| format!("background_clips[{}].in_point: {}", i, e))? {
+                    *value = serde_json::json!(secs);
+                    converted += 1;
+                }
+            }
+        }
+    }
+    log::info!("Converted {} timecodes to seconds", converted);
+    Ok(data)
+}
+
+// Lets the frontend check a field as it is typed; `fps` is a number or a ratio like "30000/1001"
+#[command]
+pub fn parse_timecode(tc: String, fps: Option<Value>) -> Result<f64, String> {
+    let rate = fps.filter(|v| !v.is_null()).map(|v| FrameRate::parse("fps", &v)).transpose()?;
+    Timecode::parse(&tc)?.seconds(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::cell::Cell;
+
+    fn probed(fps: f64) -> VideoProbe {
+        VideoProbe {
+            width: 1920,
+            height: 1080,
+            duration_secs: Some(60.0),
+            has_audio: true,
+            fps: Some(fps),
+            interlaced: Some(false),
+            rotation: 0,
+            base_fps: Some(fps),
+            variable_frame_rate: false,
+            hdr_transfer: None,
+            still_image: false,
+        }
+    }
+
+    const PAL: FrameRate = FrameRate { num: 25, den: 1 };
+    const NTSC: FrameRate = FrameRate { num: 30000, den: 1001 };
+    const NTSC_60: FrameRate = FrameRate { num: 60000, den: 1001 };
+
+    fn seconds(tc: &str, rate: FrameRate) -> Result<f64, String> {
+        Timecode::parse(tc)?.seconds(Some(rate))
+    }
+
+    #[test]
+    fn clock_time_needs_no_frame_rate() {
+        assert_eq!(Timecode::parse("00:01:23.500"), Ok(Timecode::Clock(83.5)));
+        assert_eq!(Timecode::parse("01:00:02.25").unwrap().seconds(None), Ok(3602.25));
+        for invalid in ["1:23.5", "00:60:00.000", "00:00:00.", "00:00:00.5x", "00:01:23"] {
+            assert!(Timecode::parse(invalid).is_err(), "{} was accepted", invalid);
+        }
+    }
+
+    #[test]
+    fn frames_count_at_the_whole_frame_rate() {
+        assert_eq!(seconds("00:00:10:12", PAL), Ok(PAL.seconds(262)));
+        assert_eq!(seconds("00:00:10:12", FrameRate { num: 30, den: 1 }), Ok(10.4));
+        assert_eq!(seconds("01:02:03:04", PAL), Ok(PAL.seconds(((60 + 2) * 60 + 3) * 25 + 4)));
+        // Non-drop timecode at 29.97 labels 30 frames a second
+        assert_eq!(seconds("00:00:01:00", NTSC), Ok(NTSC.seconds(30)));
+        assert_eq!(Timecode::parse("00:00:10:12").unwrap().seconds(None), Err("a timecode with frames needs the frame rate".to_string()));
+    }
+
+    #[test]
+    fn a_frame_past_the_timebase_is_rejected() {
+        assert_eq!(seconds("00:00:01:25", PAL), Err("frame 25 doesn't exist at 25 fps".to_string()));
+        assert_eq!(seconds("00:00:01;30", NTSC), Err("frame 30 doesn't exist at 30 fps".to_string()));
+        assert_eq!(seconds("00:00:01:24", PAL), Ok(PAL.seconds(49)));
+    }
+
+    #[test]
+    fn drop_frame_skips_two_frame_numbers_a_minute_except_every_tenth() {
+        // 00:01:00;02 is the first frame of minute 1, the 1800th of the clip
+        assert_eq!(seconds("00:01:00;02", NTSC), Ok(NTSC.seconds(1800)));
+        assert_eq!(seconds("00:00:59;29", NTSC), Ok(NTSC.seconds(1799)));
+        for (skipped, frame) in [("00:01:00;00", 0), ("00:01:00;01", 1)] {
+            assert_eq!(seconds(skipped, NTSC), Err(format!("frame {} is skipped in drop-frame timecode at minute 1", frame)));
+        }
+        // Every tenth minute keeps its first frames: ten minutes are 17982 frames
+        assert_eq!(seconds("00:10:00;00", NTSC), Ok(NTSC.seconds(17982)));
+        // Some tools write ; throughout
+        assert_eq!(seconds("00;01;00;02", NTSC), Ok(NTSC.seconds(1800)));
+    }
+
+    #[test]
+    fn drop_frame_at_59_94_skips_four_frame_numbers() {
+        assert_eq!(seconds("00:01:00;04", NTSC_60), Ok(NTSC_60.seconds(3600)));
+        assert_eq!(seconds("00:01:00;03", NTSC_60), Err("frame 3 is skipped in drop-frame timecode at minute 1".to_string()));
+        assert_eq!(seconds("00:10:00;00", NTSC_60), Ok(NTSC_60.seconds(35964)));
+    }
+
+    #[test]
+    fn drop_frame_needs_an_ntsc_rate() {
+        assert_eq!(seconds("00:00:01;02", FrameRate { num: 30, den: 1 }), Err("drop-frame timecode needs 29.97 or 59.94 fps, not 30.000".to_string()));
+        assert_eq!(seconds("00:00:01;02", PAL), Err("drop-frame timecode needs 29.97 or 59.94 fps, not 25.000".to_string()));
+        assert_eq!(seconds("00:00:01;02", FrameRate { num: 24000, den: 1001 }), Err("drop-frame timecode needs 29.97 or 59.94 fps, not 23.976".to_string()));
+    }
+
+    #[test]
+    fn the_command_takes_a_number_or_a_ratio() {
+        assert_eq!(parse_timecode("00:00:10:12".to_string(), Some(json!(25))), Ok(PAL.seconds(262)));
+        assert_eq!(parse_timecode("00:01:00;02".to_string(), Some(json!("30000/1001"))), Ok(NTSC.seconds(1800)));
+        assert_eq!(parse_timecode("00:00:01.250".to_string(), None), Ok(1.25));
+        assert_eq!(parse_timecode("00:00:01.250".to_string(), Some(Value::Null)), Ok(1.25));
+        assert!(parse_timecode("00:00:10:12".to_string(), None).is_err());
+        assert!(parse_timecode("10 seconds".to_string(), Some(json!(25))).unwrap_err().contains("is not a timecode"));
+    }
+
+    #[tokio::test]
+    async fn background_fps_is_probed_from_the_video_when_omitted() {
+        let probed_path = Cell::new(None);
+        let data = json!({"timestamps_frames": [100, 250], "videoPath": "/videos/game.mp4"});
+        let data = resolve_background_fps_with(data, |path| {
+            probed_path.set(Some(path));
+            async { Ok(probed(59.94)) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(probed_path.take(), Some(PathBuf::from("/videos/game.mp4")));
+        assert_eq!(data["background_fps"], "60000/1001");
+        // The moves stay frames
+        assert_eq!(data["timestamps_frames"], json!([100, 250]));
+        assert_eq!(data.get("timestamps"), None);
+    }
+
+    #[tokio::test]
+    async fn a_given_background_fps_is_kept_without_probing() {
+        let data = json!({"timestamps_frames": [100], "background_fps": 29.97, "videoPath": "/videos/game.mp4"});
+        let data = resolve_background_fps_with(data, |_| async { Err::<VideoProbe, _>("probed".to_string()) }).await.unwrap();
+        assert_eq!(data["background_fps"], "30000/1001");
+
+        let seconds = json!({"timestamps": [1.0], "videoPath": "/videos/game.mp4"});
+        let unchanged = resolve_background_fps_with(seconds.clone(), |_| async { Err::<VideoProbe, _>("probed".to_string()) }).await;
+        assert_eq!(unchanged, Ok(seconds));
+    }
+
+    #[tokio::test]
+    async fn frames_without_a_rate_or_a_video_to_probe_are_rejected() {
+        let data = json!({"timestamps_frames": [100]});
+        let result = resolve_background_fps_with(data, |_| async { Ok(probed(30.0)) }).await;
+        assert_eq!(result, Err("timestamps_frames needs background_fps when there is no videoPath to probe".to_string()));
+    }
+}
@@ -0,0 +1,233 @@
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Outside an export, and for the ffmpeg runs of an export before its output's length is known
+const DEFAULT_SECS: u64 = 300;
+// Remotion bundles the project before it renders the first frame
+const RENDER_STARTUP_SECS: f64 = 60.0;
+// When neither the history nor the benchmark has timed a render on this machine
+const DEFAULT_FRAME_SECS: f64 = 0.5;
+// A job may run this many times slower than measured before it counts as hung
+const SLACK: f64 = 3.0;
+// The slowest encode still counted as working, in seconds of 1080p output per second
+const ENCODE_SPEED_FLOOR: f64 = 0.1;
+const ENCODE_STARTUP_SECS: f64 = 30.0;
+const REFERENCE_PIXELS: f64 = 1920.0 * 1080.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Timeout {
+    pub secs: u64,
+    // "explicit", "computed" or "default"
+    pub source: &'static str,
+    // Where the number came from, for the result and the timeout error
+    pub basis: String,
+}
+
+impl Timeout {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.secs)
+    }
+
+    fn explicit(secs: u64, field: &str) -> Self {
+        Timeout { secs, source: "explicit", basis: format!("{} in the export data", field) }
+    }
+
+    fn default_limit(why: &str) -> Self {
+        Timeout { secs: DEFAULT_SECS, source: "default", basis: format!("the {}s default {}", DEFAULT_SECS, why) }
+    }
+}
+
+// The timeout_min_secs and timeout_max_secs settings; explicit timeouts aren't held to them
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub min_secs: u64,
+    pub max_secs: u64,
+}
+
+impl Bounds {
+    fn computed(self, secs: f64, working: String) -> Timeout {
+        let (min, max) = (self.min_secs, self.max_secs.max(self.min_secs));
+        let rounded = secs.ceil() as u64;
+        let (secs, basis) = if rounded < min {
+            (min, format!("{} = {}s, raised to the {}s minimum", working, rounded, min))
+        } else if rounded > max {
+            (max, format!("{} = {}s, capped at the {}s maximum", working, rounded, max))
+        } else {
+            (rounded, format!("{} = {}s", working, rounded))
+        };
+        Timeout { secs, source: "computed", basis }
+    }
+}
+
+// Seconds per rendered frame and what measured it
+#[derive(Debug, Clone, Copy)]
+pub struct FrameBudget {
+    pub secs: f64,
+    pub measured_by: &'static str,
+}
+
+impl FrameBudget {
+    pub fn learned(history: Option<f64>, benchmark: Option<f64>) -> Self {
+        match (history, benchmark) {
+            (Some(secs), _) => FrameBudget { secs, measured_by: "past exports" },
+            (None, Some(secs)) => FrameBudget { secs, measured_by: "the benchmark" },
+            (None, None) => FrameBudget { secs: DEFAULT_FRAME_SECS, measured_by: "no measurement yet" },
+        }
+    }
+}
+
+pub fn render_timeout(explicit: Option<u64>, frames: u64, budget: FrameBudget, bounds: Bounds) -> Timeout {
+    if let Some(secs) = explicit {
+        return Timeout::explicit(secs, "render_timeout_secs");
+    }
+    let secs = frames as f64 * budget.secs * SLACK + RENDER_STARTUP_SECS;
+    let working = format!(
+        "{} frames x {:.3}s per frame ({}) x {} + {}s startup",
+        frames, budget.secs, budget.measured_by, SLACK, RENDER_STARTUP_SECS
+    );
+    bounds.computed(secs, working)
+}
+
+// `pixels` is the output frame's size; anything larger than 1080p is allowed proportionally longer
+pub fn ffmpeg_timeout(explicit: Option<u64>, output_secs: f64, pixels: Option<u64>, bounds: Bounds) -> Timeout {
+    if let Some(secs) = explicit {
+        return Timeout::explicit(secs, "ffmpeg_timeout_secs");
+    }
+    let scale = pixels.map_or(1.0, |p| (p as f64 / REFERENCE_PIXELS).max(1.0));
+    let secs = output_secs * scale / ENCODE_SPEED_FLOOR + ENCODE_STARTUP_SECS;
+    let working = format!(
+        "{:.1}s of output x {:.2} for the frame size / {}x encode speed floor + {}s startup",
+        output_secs, scale, ENCODE_SPEED_FLOOR, ENCODE_STARTUP_SECS
+    );
+    bounds.computed(secs, working)
+}
+
+// One export's limits. The ffmpeg one is worked out once the composite knows the output's length.
+#[derive(Debug)]
+pub struct JobTimeouts {
+    bounds: Bounds,
+    explicit_ffmpeg: Option<u64>,
+    render: Timeout,
+    ffmpeg: Mutex<Timeout>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimeoutReport {
+    pub render: Timeout,
+    pub ffmpeg: Timeout,
+}
+
+impl JobTimeouts {
+    pub fn new(render: Timeout, explicit_ffmpeg: Option<u64>, bounds: Bounds) -> Arc<Self> {
+        let ffmpeg = match explicit_ffmpeg {
+            Some(secs) => Timeout::explicit(secs, "ffmpeg_timeout_secs"),
+            None => Timeout::default_limit("until the output's length is known"),
+        };
+        Arc::new(JobTimeouts { bounds, explicit_ffmpeg, render, ffmpeg: Mutex::new(ffmpeg) })
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: Arc<JobTimeouts>;
+}
+
+pub async fn scope<F: Future>(timeouts: Arc<JobTimeouts>, future: F) -> F::Output {
+    CURRENT.scope(timeouts, future).await
+}
+
+pub fn render() -> Timeout {
+    CURRENT.try_with(|t| t.render.clone()).unwrap_or_else(|_| Timeout::default_limit("outside an export"))
+}
+
+pub fn ffmpeg() -> Timeout {
+    CURRENT.try_with(|t| t.ffmpeg.lock().unwrap().clone()).unwrap_or_else(|_| Timeout::default_limit("outside an export"))
+}
+
+// Sizes the export's ffmpeg runs to its output once the composite has worked the length out
+pub fn fit_ffmpeg(output_secs: f64, pixels: Option<u64>) {
+    let _ = CURRENT.try_with(|t| {
+        let timeout = ffmpeg_timeout(t.explicit_ffmpeg, output_secs, pixels, t.bounds);
+        log::info!("ffmpeg timeout: {}s from {}", timeout.secs, timeout.basis);
+        *t.ffmpeg.lock().unwrap() = timeout;
+    });
+}
+
+// The current export's limits, for its result
+pub fn report() -> Option<TimeoutReport> {
+    CURRENT.try_with(|t| TimeoutReport { render: t.render.clone(), ffmpeg: t.ffmpeg.lock().unwrap().clone() }).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: Bounds = Bounds { min_secs: 120, max_secs: 7200 };
+
+    #[test]
+    fn the_render_timeout_scales_with_the_frames_and_their_measured_cost() {
+        let budget = FrameBudget::learned(Some(0.2), Some(0.4));
+        let timeout = render_timeout(None, 600, budget, BOUNDS);
+        assert_eq!((timeout.secs, timeout.source), (420, "computed"));
+        assert_eq!(timeout.basis, "600 frames x 0.200s per frame (past exports) x 3 + 60s startup = 420s");
+
+        let timeout = render_timeout(None, 1200, FrameBudget::learned(None, Some(0.4)), BOUNDS);
+        assert_eq!(timeout.basis, "1200 frames x 0.400s per frame (the benchmark) x 3 + 60s startup = 1500s");
+        assert_eq!(timeout.secs, 1500);
+    }
+
+    #[test]
+    fn computed_timeouts_are_clamped_but_explicit_ones_are_not() {
+        let unmeasured = FrameBudget::learned(None, None);
+        let short = render_timeout(None, 10, unmeasured, BOUNDS);
+        assert_eq!(short.secs, 120);
+        assert_eq!(short.basis, "10 frames x 0.500s per frame (no measurement yet) x 3 + 60s startup = 75s, raised to the 120s minimum");
+        let long = render_timeout(None, 100_000, unmeasured, BOUNDS);
+        assert_eq!(long.secs, 7200);
+        assert!(long.basis.ends_with(" = 150060s, capped at the 7200s maximum"), "{}", long.basis);
+        // A maximum below the minimum gives way to it
+        assert_eq!(render_timeout(None, 100_000, unmeasured, Bounds { min_secs: 600, max_secs: 60 }).secs, 600);
+
+        let explicit = render_timeout(Some(5), 100_000, unmeasured, BOUNDS);
+        assert_eq!((explicit.secs, explicit.source, explicit.basis.as_str()), (5, "explicit", "render_timeout_secs in the export data"));
+        assert_eq!(ffmpeg_timeout(Some(90_000), 60.0, None, BOUNDS).secs, 90_000);
+    }
+
+    #[test]
+    fn frames_past_1080p_give_ffmpeg_proportionally_longer() {
+        let hd = ffmpeg_timeout(None, 60.0, Some(1280 * 720), BOUNDS);
+        assert_eq!(hd.secs, 630);
+        assert_eq!(hd.basis, "60.0s of output x 1.00 for the frame size / 0.1x encode speed floor + 30s startup = 630s");
+        let uhd = ffmpeg_timeout(None, 60.0, Some(3840 * 2160), BOUNDS);
+        assert_eq!(uhd.basis, "60.0s of output x 4.00 for the frame size / 0.1x encode speed floor + 30s startup = 2430s");
+        assert_eq!(ffmpeg_timeout(None, 60.0, None, BOUNDS).secs, 630);
+    }
+
+    #[tokio::test]
+    async fn an_exports_ffmpeg_timeout_is_fitted_once_its_length_is_known() {
+        assert_eq!((render().secs, render().source), (300, "default"));
+        assert_eq!(ffmpeg().basis, "the 300s default outside an export");
+        assert!(report().is_none());
+
+        let computed = JobTimeouts::new(render_timeout(Some(900), 0, FrameBudget::learned(None, None), BOUNDS), None, BOUNDS);
+        scope(computed, async {
+            assert_eq!(render().secs, 900);
+            assert_eq!(ffmpeg().basis, "the 300s default until the output's length is known");
+            fit_ffmpeg(60.0, None);
+            let report = report().unwrap();
+            assert_eq!((report.render.secs, report.ffmpeg.secs, report.ffmpeg.source), (900, 630, "computed"));
+        })
+        .await;
+
+        let explicit = JobTimeouts::new(render_timeout(Some(900), 0, FrameBudget::learned(None, None), BOUNDS), Some(45), BOUNDS);
+        scope(explicit, async {
+            fit_ffmpeg(60.0, None);
+            assert_eq!((ffmpeg().secs, ffmpeg().source), (45, "explicit"));
+        })
+        .await;
+        // Nothing to fit outside an export
+        fit_ffmpeg(60.0, None);
+        assert_eq!(ffmpeg().secs, 300);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// Wall-clock seconds per stage of one export. Spans of the same name add up, and some nest
+// (probes run inside the render and composite), so "total" is measured rather than summed.
+pub struct Timings {
+    started: Instant,
+    stages: Mutex<BTreeMap<String, f64>>,
+}
+
+impl Timings {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Timings { started: Instant::now(), stages: Mutex::new(BTreeMap::new()) })
+    }
+
+    pub fn span(self: &Arc<Self>, name: &'static str) -> Span {
+        Span { timings: Some(self.clone()), name, started: Instant::now() }
+    }
+
+    // The stages finished so far plus the time since the export started
+    pub fn snapshot(&self) -> BTreeMap<String, f64> {
+        let mut stages = self.stages.lock().unwrap().clone();
+        stages.insert("total".to_string(), self.started.elapsed().as_secs_f64());
+        stages
+    }
+}
+
+// Recorded when dropped, so a stage cut short by `?` still shows up
+pub struct Span {
+    timings: Option<Arc<Timings>>,
+    name: &'static str,
+    started: Instant,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(timings) = &self.timings {
+            let elapsed = self.started.elapsed().as_secs_f64();
+            *timings.stages.lock().unwrap().entry(self.name.to_string()).or_default() += elapsed;
+        }
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: Arc<Timings>;
+}
+
+pub async fn scope<F: Future>(timings: Arc<Timings>, future: F) -> F::Output {
+    CURRENT.scope(timings, future).await
+}
+
+// A span of the export the current task belongs to; outside an export it records nothing
+pub fn span(name: &'static str) -> Span {
+    Span { timings: CURRENT.try_with(|t| t.clone()).ok(), name, started: Instant::now() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn failing_stage(name: &'static str, millis: u64) -> Result<(), String> {
+        let _span = span(name);
+        std::thread::sleep(Duration::from_millis(millis));
+        Err("failed part way".to_string())?;
+        std::thread::sleep(Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn spans_add_up_and_a_stage_cut_short_still_counts() {
+        let timings = Timings::new();
+        scope(timings.clone(), async {
+            assert!(failing_stage("probes", 20).is_err());
+            assert!(failing_stage("probes", 20).is_err());
+            let _render = timings.span("render");
+            std::thread::sleep(Duration::from_millis(30));
+        })
+        .await;
+
+        let snapshot = timings.snapshot();
+        assert_eq!(snapshot.keys().collect::<Vec<_>>(), ["probes", "render", "total"]);
+        assert!((0.04..0.5).contains(&snapshot["probes"]), "{}", snapshot["probes"]);
+        assert!((0.03..0.5).contains(&snapshot["render"]), "{}", snapshot["render"]);
+        // Probes ran before the render here, but "total" is measured, not summed
+        assert!(snapshot["total"] >= snapshot["probes"] + snapshot["render"]);
+    }
+
+    #[test]
+    fn spans_outside_an_export_record_nothing() {
+        let timings = Timings::new();
+        assert!(failing_stage("probes", 0).is_err());
+        assert_eq!(timings.snapshot().keys().collect::<Vec<_>>(), ["total"]);
+    }
+}
@@ -0,0 +1,153 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+
+// Something the user should know about that didn't stop the export. `code` is stable so the
+// frontend can key translations off it; `message` is the English text.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportWarning {
+    pub code: &'static str,
+    pub message: String,
+    pub detail: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExportWarningEvent<'a> {
+    export_id: &'a str,
+    #[serde(flatten)]
+    warning: &'a ExportWarning,
+}
+
+// The warnings of one export, shared by its render and composite stages
+pub struct WarningCollector {
+    app: AppHandle,
+    export_id: String,
+    warnings: Mutex<Vec<ExportWarning>>,
+}
+
+impl WarningCollector {
+    pub fn new(app: &AppHandle, export_id: &str) -> Arc<Self> {
+        Arc::new(WarningCollector {
+            app: app.clone(),
+            export_id: export_id.to_string(),
+            warnings: Mutex::new(Vec::new()),
+        })
+    }
+}
+
+tokio::task_local! {
+    static COLLECTOR: Arc<WarningCollector>;
+}
+
+// Runs `future` with its warnings going to `collector`
+pub async fn scope<F: Future>(collector: Arc<WarningCollector>, future: F) -> F::Output {
+    COLLECTOR.scope(collector, future).await
+}
+
+// Logs the warning and, inside an export, records it and emits it as export-warning right away
+pub fn warn_with(code: &'static str, message: String, detail: Option<Value>) {
+    log::warn!("{}", message);
+    let warning = ExportWarning { code, message, detail };
+    let _ = COLLECTOR.try_with(|collector| {
+        let event = ExportWarningEvent { export_id: &collector.export_id, warning: &warning };
+        let _ = collector.app.emit("export-warning", event);
+        collector.warnings.lock().unwrap().push(warning.clone());
+    });
+}
+
+pub fn warn(code: &'static str, message: String) {
+    warn_with(code, message, None);
+}
+
+// The export the current task belongs to
+pub fn current_export_id() -> Option<String> {
+    COLLECTOR.try_with(|collector| collector.export_id.clone()).ok()
+}
+
+// Every warning of the current export so far
+pub fn collected() -> Vec<ExportWarning> {
+    COLLECTOR.try_with(|collector| collector.warnings.lock().unwrap().clone()).unwrap_or_default()
+}
+
+// ffmpeg stderr lines worth passing on, matched case-sensitively as ffmpeg prints them
+const FFMPEG_PATTERNS: &[(&str, &str, &str)] = &[
+    ("non monotonically increasing dts", "ffmpeg_non_monotonic_dts", "The input has out-of-order timestamps, which can cause stutter or A/V drift"),
+    ("Past duration", "ffmpeg_past_duration", "Frames arrived later than their timestamps and some may have been dropped"),
+    ("deprecated pixel format used", "ffmpeg_deprecated_pixel_format", "The input uses a deprecated pixel format; colours may be slightly off"),
+    ("Queue input is backward in time", "ffmpeg_audio_backwards", "The audio went backwards in time and may have glitches"),
+    ("More than 1000 frames duplicated", "ffmpeg_frames_duplicated", "Many frames were duplicated to keep a constant frame rate"),
+    ("Invalid UTF-8 in decoded subtitles text", "ffmpeg_invalid_subtitles", "Some subtitle text wasn't valid UTF-8"),
+];
+
+// One warning per kind of known ffmpeg complaint in `stderr`, with how often it appeared
+pub fn scan_ffmpeg_stderr(stderr: &str) {
+    for warning in ffmpeg_warnings(stderr) {
+        warn_with(warning.code, warning.message, warning.detail);
+    }
+}
+
+fn ffmpeg_warnings(stderr: &str) -> Vec<ExportWarning> {
+    FFMPEG_PATTERNS
+        .iter()
+        .filter_map(|(pattern, code, message)| {
+            let matching: Vec<&str> = stderr.lines().filter(|line| line.contains(pattern)).collect();
+            matching.first().map(|first| ExportWarning {
+                code,
+                message: message.to_string(),
+                detail: Some(serde_json::json!({ "occurrences": matching.len(), "first_line": first.trim() })),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STDERR: &str = "\
+frame=  120 fps= 60 q=28.0 size=     256kB time=00:00:04.00 bitrate= 524.3kbits/s speed=2.00x
+[mp4 @ 0x5581] Application provided invalid, non monotonically increasing dts to muxer in stream 0: 512 >= 512
+[mp4 @ 0x5581] Application provided invalid, non monotonically increasing dts to muxer in stream 0: 1024 >= 1024
+[swscaler @ 0x7f12] deprecated pixel format used, make sure you did set range correctly
+";
+
+    #[test]
+    fn each_known_ffmpeg_complaint_is_one_warning_with_its_count() {
+        let warnings = ffmpeg_warnings(STDERR);
+        let codes: Vec<&str> = warnings.iter().map(|w| w.code).collect();
+        assert_eq!(codes, ["ffmpeg_non_monotonic_dts", "ffmpeg_deprecated_pixel_format"]);
+        assert_eq!(
+            warnings[0].detail,
+            Some(serde_json::json!({
+                "occurrences": 2,
+                "first_line": "[mp4 @ 0x5581] Application provided invalid, non monotonically increasing dts to muxer in stream 0: 512 >= 512",
+            }))
+        );
+        assert_eq!(warnings[1].message, "The input uses a deprecated pixel format; colours may be slightly off");
+        assert!(ffmpeg_warnings("frame=  120 fps= 60\n").is_empty());
+    }
+
+    #[test]
+    fn warnings_outside_an_export_are_only_logged() {
+        scan_ffmpeg_stderr(STDERR);
+        warn("silent_output", "The background has no audio, so the output is silent".to_string());
+        assert!(collected().is_empty());
+        assert_eq!(current_export_id(), None);
+    }
+
+    #[test]
+    fn a_warning_is_serialized_with_its_stable_code() {
+        let warning = ExportWarning { code: "move_duration_shrunk", message: "Move 3 was shortened by 0.4s to end before the next one".to_string(), detail: None };
+        assert_eq!(
+            serde_json::to_value(ExportWarningEvent { export_id: "1700000000000-0", warning: &warning }).unwrap(),
+            serde_json::json!({
+                "export_id": "1700000000000-0",
+                "code": "move_duration_shrunk",
+                "message": "Move 3 was shortened by 0.4s to end before the next one",
+                "detail": null,
+            })
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::async_runtime::JoinHandle;
+use tauri::{command, AppHandle, Emitter, Manager, State};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::filename::{sanitize_filename, FilenameMode, Platform};
+use crate::scheduler::Priority;
+use crate::settings::{ExportPreset, SettingsState, WatchFolderConfig};
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "mov", "webm", "avi", "flv"];
+// Files still being written by OBS, browsers or copy tools
+const PARTIAL_SUFFIXES: &[&str] = &[".part", ".tmp", ".crdownload", ".partial"];
+
+// A file counts as finished once its size hasn't changed for this long
+const STABLE_FOR: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// How often to try re-attaching when the folder (e.g. a network drive) is unavailable
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchFolderFile {
+    pub path: String,
+    pub preset_name: String,
+    pub auto_export: bool,
+}
+
+#[derive(Default)]
+pub struct WatchFolderState {
+    task: Mutex<Option<(WatchFolderConfig, JoinHandle<()>)>>,
+}
+
+fn is_candidate(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if name.starts_with('.') || name.starts_with('~') || PARTIAL_SUFFIXES.iter().any(|s| name.ends_with(s)) {
+        return false;
+    }
+    path.extension()
+        .map(|ext| VIDEO_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn list_videos(dir: &Path) -> Option<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir).ok()?;
+    Some(entries.flatten().map(|e| e.path()).filter(|p| p.is_file() && is_candidate(p)).collect())
+}
+
+// Fills the preset's export payload in for one recording
+fn export_payload(preset: &ExportPreset, video: &Path) -> Value {
+    let mut data = match &preset.data {
+        Value::Object(map) => Value::Object(map.clone()),
+        _ => json!({}),
+    };
+
+    let stem = video.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let output_dir = preset
+        .output_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(|| video.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+
+    data["videoPath"] = json!(video.display().to_string());
+    // Named here, so a name the output directory can't hold is fixed quietly rather than warned about
+    let name = sanitize_filename(&format!("{}_boardcast.mp4", stem), Platform::of(&output_dir), FilenameMode::Lenient).unwrap_or_default();
+    data["outputPath"] = json!(output_dir.join(name).display().to_string());
+    // The name was made up here, so the output retention may delete it
+    data["managed_output"] = json!(true);
+    data
+}
+
+struct Pending {
+    size: u64,
+    changed: Instant,
+}
+
+async fn watch_loop(app: AppHandle, config: WatchFolderConfig) {
+    let dir = PathBuf::from(&config.path);
+
+    // The scheduler runs them up to max_parallel_exports at once, behind previews and interactive
+    // exports; stopping the watcher lets queued ones finish
+    let (export_tx, mut export_rx) = mpsc::unbounded_channel::<Value>();
+    let export_app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut running = JoinSet::new();
+        while let Some(data) = export_rx.recv().await {
+            let app = export_app.clone();
+            running.spawn(async move {
+                if let Err(e) = crate::pipeline::export_with_priority(app, data, Priority::Batch).await {
+                    log::error!("Watch folder export failed at {:?} ({}): {}", e.stage, e.code, e);
+                }
+            });
+        }
+        while running.join_next().await.is_some() {}
+    });
+
+    // Recordings already in the folder when watching starts are left alone
+    let mut seen: HashSet<PathBuf> = list_videos(&dir).unwrap_or_default().into_iter().collect();
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    let mut watcher: Option<RecommendedWatcher> = None;
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel::<notify::Result<notify::Event>>();
+    let mut last_attempt: Option<Instant> = None;
+
+    loop {
+        if watcher.is_none() && last_attempt.map(|t| t.elapsed() >= RETRY_INTERVAL).unwrap_or(true) {
+            last_attempt = Some(Instant::now());
+            let tx = event_tx.clone();
+            let attached = notify::recommended_watcher(move |event| {
+                let _ = tx.send(event);
+            })
+            .and_then(|mut w| w.watch(&dir, RecursiveMode::NonRecursive).map(|_| w));
+
+            match attached {
+                Ok(w) => {
+                    log::info!("Watching {} for new recordings", dir.display());
+                    watcher = Some(w);
+                    // Catch anything that arrived while the folder was unavailable
+                    for path in list_videos(&dir).unwrap_or_default() {
+                        if !seen.contains(&path) {
+                            pending.entry(path).or_insert(Pending { size: 0, changed: Instant::now() });
+                        }
+                    }
+                }
+                Err(e) => log::warn!("Watch folder {} unavailable, retrying: {}", dir.display(), e),
+            }
+        }
+
+        while let Ok(event) = event_rx.try_recv() {
+            match event {
+                Ok(event) => {
+                    for path in event.paths.into_iter().filter(|p| is_candidate(p) && !seen.contains(p)) {
+                        pending.entry(path).or_insert(Pending { size: 0, changed: Instant::now() });
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Watch folder error, re-attaching: {}", e);
+                    watcher = None;
+                }
+            }
+        }
+        if watcher.is_some() && !dir.is_dir() {
+            log::info!("Watch folder {} disappeared", dir.display());
+            watcher = None;
+        }
+
+        let mut finished = Vec::new();
+        pending.retain(|path, state| match fs::metadata(path) {
+            Ok(meta) if meta.is_file() => {
+                if meta.len() != state.size {
+                    state.size = meta.len();
+                    state.changed = Instant::now();
+                } else if state.size > 0 && state.changed.elapsed() >= STABLE_FOR {
+                    finished.push(path.clone());
+                    return false;
+                }
+                true
+            }
+            // Renamed away (e.g. a .part file that was just the temp name) or not readable yet
+            _ => watcher.is_none(),
+        });
+
+        for path in finished {
+            seen.insert(path.clone());
+            let preset = app.state::<SettingsState>().get().preset(&config.preset_name).cloned();
+            let auto_export = preset.as_ref().map(|p| p.auto_export).unwrap_or(false);
+            log::info!("New recording in watch folder: {}", path.display());
+
+            let _ = app.emit(
+                "watch-folder-file",
+                WatchFolderFile {
+                    path: path.display().to_string(),
+                    preset_name: config.preset_name.clone(),
+                    auto_export,
+                },
+            );
+            if let Some(preset) = preset.filter(|p| p.auto_export) {
+                let _ = export_tx.send(export_payload(&preset, &path));
+            }
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+pub fn start(app: &AppHandle, config: WatchFolderConfig) {
+    let state = app.state::<WatchFolderState>();
+    let mut task = state.task.lock().unwrap();
+    if let Some((_, handle)) = task.take() {
+        handle.abort();
+    }
+    let handle = tauri::async_runtime::spawn(watch_loop(app.clone(), config.clone()));
+    *task = Some((config, handle));
+}
+
+#[command]
+pub fn start_watch_folder(
+    app: AppHandle,
+    settings: State<'_, SettingsState>,
+    path: String,
+    preset_name: String,
+) -> Result<WatchFolderConfig, String> {
+    if settings.get().preset(&preset_name).is_none() {
+        return Err(format!("No preset named '{}'", preset_name));
+    }
+    if !Path::new(&path).is_dir() {
+        return Err(format!("{} is not a folder", path));
+    }
+
+    let config = WatchFolderConfig { path, preset_name };
+    settings.update(|s| s.watch_folder = Some(config.clone()))?;
+    start(&app, config.clone());
+    Ok(config)
+}
+
+#[command]
+pub fn stop_watch_folder(
+    settings: State<'_, SettingsState>,
+    state: State<'_, WatchFolderState>,
+) -> Result<(), String> {
+    if let Some((config, handle)) = state.task.lock().unwrap().take() {
+        handle.abort();
+        log::info!("Stopped watching {}", config.path);
+    }
+    settings.update(|s| s.watch_folder = None)
+}
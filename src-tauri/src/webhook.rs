@@ -0,0 +1,301 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+use ureq::http::Uri;
+
+use crate::failure::ExportFailure;
+use crate::settings::SettingsState;
+use crate::warnings;
+
+// Hex HMAC-SHA256 of the body with the secret, as "sha256=<hex>"
+pub const SIGNATURE_HEADER: &str = "X-Boardcast-Signature";
+pub const EVENT_HEADER: &str = "X-Boardcast-Event";
+
+// Where finished exports are reported, with the secret their bodies are signed with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub secret: String,
+}
+
+impl WebhookConfig {
+    // https only, except for a receiver on this machine
+    pub fn check(&self) -> Result<(), String> {
+        let uri: Uri = self.url.parse().map_err(|e| format!("webhook url \"{}\" is not a URL: {}", self.url, e))?;
+        let host = uri.host().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+        let local = matches!(host, "localhost" | "127.0.0.1" | "::1");
+        match uri.scheme_str() {
+            Some("https") if !host.is_empty() => {}
+            Some("http") if local => {}
+            _ => return Err(format!("webhook url must be https, or http on localhost, got \"{}\"", self.url)),
+        }
+        if self.secret.is_empty() {
+            return Err("webhook secret must not be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+// How hard a delivery is tried; tests against a mock server can drop the backoff
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    // Doubled after each failed attempt
+    pub backoff: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { attempts: 3, backoff: Duration::from_secs(1), timeout: Duration::from_secs(5) }
+    }
+}
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    let hex: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+// What one export reports. Only what the export itself produced: no host name, user or binary paths.
+pub fn body(export_id: &str, status: &str, timings: &BTreeMap<String, f64>, result: &Result<String, ExportFailure>) -> Value {
+    let value = result.as_deref().ok().and_then(|r| serde_json::from_str::<Value>(r).ok()).unwrap_or(Value::Null);
+    let warnings: Vec<Value> = value
+        .get("warnings")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .map(|w| serde_json::json!({ "code": w["code"], "message": w["message"] }))
+        .collect();
+    serde_json::json!({
+        "event": if status == "completed" { "export.completed" } else { "export.failed" },
+        "export_id": export_id,
+        "status": status,
+        "output_path": value["output_path"],
+        "duration": value["output_duration"],
+        "timings": timings,
+        "warnings": warnings,
+        "error": result.as_ref().err().map(|f| &f.message),
+        "error_stage": result.as_ref().err().map(|f| f.stage),
+        "error_code": result.as_ref().err().map(|f| f.code),
+        "sent_at": SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+    })
+}
+
+// Statuses worth another attempt; the rest mean the receiver refused the request
+fn retryable(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::StatusCode(code) => *code == 408 || *code == 429 || *code >= 500,
+        _ => true,
+    }
+}
+
+// POSTs `body` with its signature, retrying with backoff. Blocking; returns the attempts made.
+pub fn deliver(config: &WebhookConfig, event: &str, body: &[u8], policy: RetryPolicy) -> Result<u32, String> {
+    config.check()?;
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(policy.timeout))
+        // A redirect could take the signed body somewhere the check never saw
+        .max_redirects(0)
+        .build()
+        .into();
+    let signature = sign(&config.secret, body);
+    let mut delay = policy.backoff;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let sent = agent
+            .post(&config.url)
+            .header("Content-Type", "application/json")
+            .header(EVENT_HEADER, event)
+            .header(SIGNATURE_HEADER, &signature)
+            .send(body);
+        match sent {
+            Ok(_) => return Ok(attempt),
+            Err(e) if attempt < policy.attempts && retryable(&e) => {
+                log::warn!("Webhook attempt {} failed, retrying in {:?}: {}", attempt, delay, e);
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) => return Err(format!("{} after {} attempt(s)", e, attempt)),
+        }
+    }
+}
+
+// Reports a finished export to the webhook setting's URL, if there is one. A delivery that fails
+// is a warning and leaves the export's result as it was, apart from its warnings. Previews aren't reported.
+pub async fn notify(
+    app: &AppHandle,
+    export_id: &str,
+    data: &Value,
+    status: &str,
+    timings: &BTreeMap<String, f64>,
+    result: Result<String, ExportFailure>,
+) -> Result<String, ExportFailure> {
+    let Some(config) = app.state::<SettingsState>().get().webhook else {
+        return result;
+    };
+    if data.get("preview_moves").is_some() {
+        return result;
+    }
+    report(config, export_id, status, timings, result, RetryPolicy::default()).await
+}
+
+// Delivers the report of a finished export and hands its result back, with a warning added if the delivery failed
+async fn report(
+    config: WebhookConfig,
+    export_id: &str,
+    status: &str,
+    timings: &BTreeMap<String, f64>,
+    result: Result<String, ExportFailure>,
+    policy: RetryPolicy,
+) -> Result<String, ExportFailure> {
+    let body = body(export_id, status, timings, &result);
+    let event = body["event"].as_str().unwrap_or_default().to_string();
+    let bytes = body.to_string().into_bytes();
+    let delivered = tokio::task::spawn_blocking(move || deliver(&config, &event, &bytes, policy))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|delivered| delivered);
+    match delivered {
+        Ok(attempts) => {
+            log::info!("Reported export {} to the webhook ({} attempt(s))", export_id, attempts);
+            result
+        }
+        Err(e) => {
+            warnings::warn("webhook_failed", format!("Failed to report the export to the webhook: {}", e));
+            result.map(|r| match serde_json::from_str::<Value>(&r) {
+                Ok(mut value) => {
+                    value["warnings"] = serde_json::json!(warnings::collected());
+                    value.to_string()
+                }
+                Err(_) => r,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::failure::FailureStage;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    // One request as the receiver saw it: lower-cased header names, then the raw body
+    struct Received {
+        headers: BTreeMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    // A receiver on a free local port answering one connection per status, in order
+    fn receiver(statuses: &[u16]) -> (WebhookConfig, mpsc::Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = WebhookConfig {
+            url: format!("http://127.0.0.1:{}/hook", listener.local_addr().unwrap().port()),
+            secret: "s3cret".to_string(),
+        };
+        let (sender, requests) = mpsc::channel();
+        let statuses = statuses.to_vec();
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = BTreeMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+                    }
+                }
+                let length = headers.get("content-length").map_or(0, |l| l.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let mut stream = stream;
+                write!(stream, "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+                let _ = sender.send(Received { headers, body });
+            }
+        });
+        (config, requests)
+    }
+
+    fn quick() -> RetryPolicy {
+        RetryPolicy { attempts: 3, backoff: Duration::from_millis(1), timeout: Duration::from_secs(5) }
+    }
+
+    #[test]
+    fn the_signature_covers_the_exact_body_sent() {
+        let (config, requests) = receiver(&[200]);
+        let body = br#"{"event":"export.completed","export_id":"e1"}"#;
+        assert_eq!(deliver(&config, "export.completed", body, quick()), Ok(1));
+        let request = requests.recv().unwrap();
+        assert_eq!(request.body, body);
+        assert_eq!(request.headers["x-boardcast-signature"], sign("s3cret", &request.body));
+        assert_eq!(request.headers["x-boardcast-event"], "export.completed");
+        assert_eq!(request.headers["content-type"], "application/json");
+    }
+
+    #[test]
+    fn a_server_error_is_retried_until_it_is_accepted() {
+        let (config, requests) = receiver(&[503, 500, 200]);
+        assert_eq!(deliver(&config, "export.completed", b"{}", quick()), Ok(3));
+        assert_eq!(requests.iter().count(), 3);
+    }
+
+    #[test]
+    fn a_server_error_gives_up_after_the_last_attempt() {
+        let (config, requests) = receiver(&[502, 502]);
+        let policy = RetryPolicy { attempts: 2, ..quick() };
+        let error = deliver(&config, "export.completed", b"{}", policy).unwrap_err();
+        assert!(error.ends_with("after 2 attempt(s)"), "{}", error);
+        assert_eq!(requests.iter().count(), 2);
+    }
+
+    #[test]
+    fn a_refused_request_is_not_retried() {
+        let (config, requests) = receiver(&[400, 200]);
+        let error = deliver(&config, "export.completed", b"{}", quick()).unwrap_err();
+        assert!(error.ends_with("after 1 attempt(s)"), "{}", error);
+        assert_eq!(requests.recv().unwrap().body, b"{}");
+        assert!(requests.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn an_insecure_or_unsigned_config_is_never_sent() {
+        let remote = WebhookConfig { url: "http://example.com/hook".to_string(), secret: "s".to_string() };
+        assert!(deliver(&remote, "export.completed", b"{}", quick()).is_err());
+        let unsigned = WebhookConfig { url: "https://example.com/hook".to_string(), secret: String::new() };
+        assert_eq!(unsigned.check(), Err("webhook secret must not be empty".to_string()));
+        let local = WebhookConfig { url: "http://[::1]:8080/hook".to_string(), secret: "s".to_string() };
+        assert_eq!(local.check(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn a_failed_delivery_never_fails_the_export() {
+        // Nothing listens on a port just given back
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = WebhookConfig { url: format!("http://127.0.0.1:{}/hook", port), secret: "s".to_string() };
+        let policy = RetryPolicy { attempts: 1, ..quick() };
+        let timings = BTreeMap::new();
+        let completed = r#"{"output_path":"/tmp/out.mp4","warnings":[]}"#.to_string();
+        let reported = report(config.clone(), "e1", "completed", &timings, Ok(completed), policy).await;
+        let value: Value = serde_json::from_str(&reported.unwrap()).unwrap();
+        assert_eq!(value["output_path"], "/tmp/out.mp4");
+
+        let failure = ExportFailure::new(FailureStage::Composite, "composite_failed", "ffmpeg exited with 1".to_string());
+        let reported = report(config, "e1", "failed", &timings, Err(failure), policy).await;
+        let failure = reported.unwrap_err();
+        assert_eq!((failure.stage, failure.message.as_str()), (FailureStage::Composite, "ffmpeg exited with 1"));
+    }
+}
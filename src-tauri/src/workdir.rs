@@ -0,0 +1,296 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::ffmpeglog;
+use crate::paths::path_arg;
+
+// Per-job directories for intermediates, kept under the app cache dir so packaged builds can write them
+pub struct WorkDir {
+    id: String,
+    path: PathBuf,
+    active: Arc<Mutex<HashSet<String>>>,
+}
+
+impl WorkDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        self.active.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[derive(Default)]
+pub struct WorkDirs {
+    active: Arc<Mutex<HashSet<String>>>,
+}
+
+impl WorkDirs {
+    pub fn allocate(&self, app: &AppHandle, job_id: &str) -> Result<WorkDir, String> {
+        self.allocate_in(&jobs_dir(app)?, job_id)
+    }
+
+    fn allocate_in(&self, root: &Path, job_id: &str) -> Result<WorkDir, String> {
+        let path = root.join(job_id);
+        // Everything an export writes goes in here and ends up in ffmpeg's arguments
+        path_arg(&path)?;
+        fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create working directory {}: {}", path.display(), e))?;
+
+        self.active.lock().unwrap().insert(job_id.to_string());
+        Ok(WorkDir {
+            id: job_id.to_string(),
+            path,
+            active: self.active.clone(),
+        })
+    }
+
+    pub fn is_active(&self, job_id: &str) -> bool {
+        self.active.lock().unwrap().contains(job_id)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheEntry {
+    pub id: String,
+    pub bytes: u64,
+    pub age_days: f64,
+    pub active: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheUsage {
+    pub path: String,
+    pub total_bytes: u64,
+    pub entries: Vec<CacheEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheCleanup {
+    pub removed: Vec<String>,
+    pub skipped_active: Vec<String>,
+    pub freed_bytes: u64,
+    // Export ids whose FFmpeg logs were removed along with the job directories
+    pub removed_logs: Vec<String>,
+}
+
+pub fn jobs_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_cache_dir()
+        .map(|dir| dir.join("jobs"))
+        .map_err(|e| format!("Failed to resolve the app cache directory: {}", e))
+}
+
+// Previews are written here and nowhere else, each one replacing the last
+pub fn preview_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_cache_dir()
+        .map(|dir| dir.join("preview"))
+        .map_err(|e| format!("Failed to resolve the app cache directory: {}", e))?;
+    path_arg(&dir)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+fn age(path: &Path) -> Duration {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .unwrap_or_default()
+}
+
+fn scan(app: &AppHandle, workdirs: &WorkDirs) -> Result<(PathBuf, Vec<(CacheEntry, PathBuf)>), String> {
+    let root = jobs_dir(app)?;
+    let mut entries = Vec::new();
+
+    if let Ok(dir) = fs::read_dir(&root) {
+        for entry in dir.flatten().filter(|e| e.path().is_dir()) {
+            let path = entry.path();
+            let id = entry.file_name().to_string_lossy().to_string();
+            entries.push((
+                CacheEntry {
+                    bytes: dir_size(&path),
+                    age_days: age(&path).as_secs_f64() / 86400.0,
+                    active: workdirs.is_active(&id),
+                    id,
+                },
+                path,
+            ));
+        }
+    }
+
+    entries.sort_by(|a, b| a.0.id.cmp(&b.0.id));
+    Ok((root, entries))
+}
+
+pub fn prune(app: &AppHandle, workdirs: &WorkDirs, older_than_days: f64) -> Result<CacheCleanup, String> {
+    let (_, entries) = scan(app, workdirs)?;
+    let mut cleanup = CacheCleanup {
+        removed: Vec::new(),
+        skipped_active: Vec::new(),
+        freed_bytes: 0,
+        removed_logs: ffmpeglog::prune(app, workdirs, older_than_days),
+    };
+
+    for (entry, path) in entries.into_iter().filter(|(e, _)| e.age_days >= older_than_days) {
+        if entry.active {
+            cleanup.skipped_active.push(entry.id);
+            continue;
+        }
+        match fs::remove_dir_all(&path) {
+            Ok(_) => {
+                cleanup.freed_bytes += entry.bytes;
+                cleanup.removed.push(entry.id);
+            }
+            Err(e) => log::warn!("Failed to remove {}: {}", path.display(), e),
+        }
+    }
+
+    log::info!(
+        "Cache cleanup removed {} job directories ({} bytes)",
+        cleanup.removed.len(), cleanup.freed_bytes
+    );
+    Ok(cleanup)
+}
+
+#[command]
+pub fn get_cache_usage(app: AppHandle, workdirs: State<'_, WorkDirs>) -> Result<CacheUsage, String> {
+    let (root, entries) = scan(&app, &workdirs)?;
+    let entries: Vec<CacheEntry> = entries.into_iter().map(|(entry, _)| entry).collect();
+
+    Ok(CacheUsage {
+        path: root.display().to_string(),
+        total_bytes: entries.iter().map(|e| e.bytes).sum(),
+        entries,
+    })
+}
+
+#[command]
+pub fn clear_cache(
+    app: AppHandle,
+    workdirs: State<'_, WorkDirs>,
+    older_than_days: Option<f64>,
+) -> Result<CacheCleanup, String> {
+    prune(&app, &workdirs, older_than_days.unwrap_or(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("boardcast-workdir-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dir_size_counts_nested_files() {
+        let dir = scratch("size");
+        fs::write(dir.join("overlay.mp4"), [0; 100]).unwrap();
+        fs::create_dir_all(dir.join("segments").join("deep")).unwrap();
+        fs::write(dir.join("segments").join("part1.mp4"), [0; 20]).unwrap();
+        fs::write(dir.join("segments").join("deep").join("part2.mp4"), [0; 3]).unwrap();
+        assert_eq!(dir_size(&dir), 123);
+        fs::remove_dir_all(&dir).unwrap();
+        // A job directory that is already gone just counts as empty
+        assert_eq!(dir_size(&dir), 0);
+    }
+
+    #[test]
+    fn a_new_directory_has_no_age() {
+        let dir = scratch("age");
+        assert!(age(&dir) < Duration::from_secs(60));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(age(&dir), Duration::ZERO);
+    }
+
+    #[test]
+    fn dropping_a_work_dir_releases_its_job_but_keeps_its_files() {
+        let dir = scratch("drop");
+        let workdirs = WorkDirs::default();
+        workdirs.active.lock().unwrap().insert("job-1".to_string());
+        let workdir = WorkDir { id: "job-1".to_string(), path: dir.clone(), active: workdirs.active.clone() };
+        fs::write(workdir.file("overlay.mp4"), b"").unwrap();
+        assert_eq!(workdir.file("overlay.mp4"), dir.join("overlay.mp4"));
+        assert!(workdirs.is_active("job-1"));
+
+        drop(workdir);
+        assert!(!workdirs.is_active("job-1"));
+        // The directory itself is left for a cache cleanup to remove
+        assert!(dir.join("overlay.mp4").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_exports_share_no_intermediate_path() {
+        let root = std::env::temp_dir().join(format!("boardcast-parallel-jobs-{}", std::process::id()));
+        let registry = crate::exports::ExportRegistry::default();
+        // Past the cap on kept records, which the ids must not depend on
+        for _ in 0..30 {
+            registry.start_export();
+        }
+        let workdirs = WorkDirs::default();
+        // Every file an export writes before its final output, as composite_animation names them
+        let intermediates = |workdir: &WorkDir| {
+            let animation_path = workdir.file("chess-animation.mp4");
+            vec![
+                workdir.file("export.json"),
+                animation_path.with_file_name("stitched_background.mkv"),
+                animation_path.with_file_name("composite.mkv"),
+                animation_path.with_file_name("segments"),
+                animation_path.with_file_name("twopass"),
+                animation_path,
+            ]
+        };
+        let planned: Vec<Vec<PathBuf>> = std::thread::scope(|scope| {
+            let jobs: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let workdir = workdirs.allocate_in(&root, &registry.start_export()).unwrap();
+                        let paths = intermediates(&workdir);
+                        assert!(paths.iter().all(|p| p.starts_with(workdir.path())), "{:?}", paths);
+                        // Held until every job has planned, as running exports hold theirs
+                        (workdir, paths)
+                    })
+                })
+                .collect();
+            let planned: Vec<(WorkDir, Vec<PathBuf>)> = jobs.into_iter().map(|job| job.join().unwrap()).collect();
+            planned.into_iter().map(|(_, paths)| paths).collect()
+        });
+        fs::remove_dir_all(&root).unwrap();
+
+        let all: Vec<&PathBuf> = planned.iter().flatten().collect();
+        let unique: std::collections::HashSet<&PathBuf> = all.iter().copied().collect();
+        assert_eq!(unique.len(), all.len());
+    }
+}
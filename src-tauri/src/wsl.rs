@@ -0,0 +1,138 @@
+use serde::Serialize;
+use std::fmt;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use tokio::process::Command;
+
+use crate::diagnostics::decode_output;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WslStatus {
+    NotInstalled { detail: String },
+    NoDistribution,
+    Available { default_distribution: Option<String> },
+}
+
+impl fmt::Display for WslStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WslStatus::NotInstalled { detail } => write!(
+                f,
+                "WSL is not installed. Install it by running `wsl --install` in an administrator terminal, \
+                 or run the script with the Windows environment instead ({})",
+                detail
+            ),
+            WslStatus::NoDistribution => write!(
+                f,
+                "WSL is installed but has no default distribution. Install one with `wsl --install -d Ubuntu`, then retry"
+            ),
+            WslStatus::Available { default_distribution } => write!(
+                f,
+                "available, default distribution: {}",
+                default_distribution.as_deref().unwrap_or("unknown")
+            ),
+        }
+    }
+}
+
+// Only a working WSL is remembered, so a retry after fixing the setup probes again
+#[derive(Default)]
+pub struct WslProbe {
+    cached: Mutex<Option<WslStatus>>,
+}
+
+async fn probe() -> WslStatus {
+    if !cfg!(target_os = "windows") {
+        return WslStatus::NotInstalled { detail: "WSL is only available on Windows".to_string() };
+    }
+    let output = match Command::new("wsl").arg("--status").kill_on_drop(true).output().await {
+        Ok(output) => output,
+        Err(e) => return WslStatus::NotInstalled { detail: format!("wsl.exe could not be started: {}", e) },
+    };
+    classify(output.status.code(), &output.stdout, &output.stderr)
+}
+
+// What `wsl --status` printed, in either encoding, and how it exited
+fn classify(code: Option<i32>, stdout: &[u8], stderr: &[u8]) -> WslStatus {
+    let text = format!("{}\n{}", decode_output(stdout), decode_output(stderr));
+    if text.to_lowercase().contains("no installed distributions") {
+        return WslStatus::NoDistribution;
+    }
+    if code != Some(0) {
+        let detail = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        return WslStatus::NotInstalled { detail: format!("wsl --status exited with {:?}: {}", code, detail) };
+    }
+    let default_distribution = text
+        .lines()
+        .find_map(|l| l.split_once("Default Distribution:").map(|(_, d)| d.trim().to_string()));
+    WslStatus::Available { default_distribution }
+}
+
+// `refresh` skips the cache, as diagnostics does
+pub async fn detect_wsl(app: &AppHandle, refresh: bool) -> WslStatus {
+    let state = app.state::<WslProbe>();
+    if !refresh {
+        if let Some(status) = state.cached.lock().unwrap().clone() {
+            return status;
+        }
+    }
+    let status = probe().await;
+    log::info!("WSL: {}", status);
+    state.remember(&status);
+    status
+}
+
+impl WslProbe {
+    fn remember(&self, status: &WslStatus) {
+        *self.cached.lock().unwrap() = matches!(status, WslStatus::Available { .. }).then(|| status.clone());
+    }
+}
+
+// Checked before anything is run through wsl.exe, so a missing WSL gets guidance rather than a spawn error
+pub async fn require_wsl(app: &AppHandle) -> Result<(), String> {
+    match detect_wsl(app, false).await {
+        WslStatus::Available { .. } => Ok(()),
+        status => Err(status.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn the_status_is_read_from_wsls_utf16_output() {
+        let status = utf16("Default Distribution: Ubuntu-22.04\r\nDefault Version: 2\r\n");
+        assert_eq!(classify(Some(0), &status, b""), WslStatus::Available { default_distribution: Some("Ubuntu-22.04".to_string()) });
+        assert_eq!(classify(Some(0), b"Default Version: 2\n", b""), WslStatus::Available { default_distribution: None });
+    }
+
+    #[test]
+    fn no_distribution_and_not_installed_are_told_apart() {
+        let empty = utf16("Windows Subsystem for Linux has no installed distributions.\r\n");
+        assert_eq!(classify(Some(-1), &empty, b""), WslStatus::NoDistribution);
+        assert_eq!(
+            classify(Some(1), b"", &utf16("The Windows Subsystem for Linux is not installed.\r\n")),
+            WslStatus::NotInstalled { detail: "wsl --status exited with Some(1): The Windows Subsystem for Linux is not installed.".to_string() }
+        );
+        assert!(WslStatus::NoDistribution.to_string().starts_with("WSL is installed but has no default distribution."));
+    }
+
+    #[test]
+    fn only_a_working_wsl_is_remembered() {
+        let probe = WslProbe::default();
+        probe.remember(&WslStatus::NoDistribution);
+        assert_eq!(*probe.cached.lock().unwrap(), None);
+        let available = WslStatus::Available { default_distribution: Some("Ubuntu".to_string()) };
+        probe.remember(&available);
+        assert_eq!(*probe.cached.lock().unwrap(), Some(available));
+        // A later failure, found by a refresh, isn't kept either
+        probe.remember(&WslStatus::NoDistribution);
+        assert_eq!(*probe.cached.lock().unwrap(), None);
+    }
+}